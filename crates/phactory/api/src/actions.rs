@@ -1,7 +1,13 @@
 pub const ACTION_GET_INFO: u8 = 2;
+pub const ACTION_GET_HEAP_PROFILE: u8 = 3;
+pub const ACTION_GET_LOG_FILTER: u8 = 4;
+pub const ACTION_GET_SIDEVM_INSTANCES: u8 = 5;
+pub const ACTION_GET_EGRESS_STATUS: u8 = 6;
 
 pub const BIN_ACTION_START: u8 = 128;
 pub const BIN_ACTION_SYNC_PARA_HEADER: u8 = BIN_ACTION_START + 0;
 pub const BIN_ACTION_DISPATCH_BLOCK: u8 = BIN_ACTION_START + 1;
 pub const BIN_ACTION_SYNC_HEADER: u8 = BIN_ACTION_START + 2;
 pub const BIN_ACTION_SYNC_COMBINED_HEADERS: u8 = BIN_ACTION_START + 3;
+pub const BIN_ACTION_SIGN_ENDPOINTS: u8 = BIN_ACTION_START + 4;
+pub const BIN_ACTION_SET_LOG_FILTER: u8 = BIN_ACTION_START + 5;
@@ -106,6 +106,15 @@ pub struct SyncCombinedHeadersReq {
     pub proof: StorageProof,
 }
 
+/// A snapshot of the parachain state, with the header it is taken at and the proof of the header
+/// in the relaychain state.
+#[derive(TypeInfo, Encode, Decode, Clone, Debug)]
+pub struct ImportStateSnapshotReq {
+    pub para_header: chain::Header,
+    pub proof: StorageProof,
+    pub state: StorageState,
+}
+
 #[derive(TypeInfo, Encode, Decode, Clone, Debug)]
 pub struct DispatchBlockReq {
     pub blocks: Vec<BlockHeaderWithChanges>,
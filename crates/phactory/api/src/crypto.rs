@@ -3,7 +3,7 @@ use alloc::vec::Vec;
 use parity_scale_codec::{Decode, Encode, Error as CodecError};
 
 use crate::prpc::{Signature, SignatureType};
-pub use phala_crypto::{aead, ecdh, CryptoError};
+pub use phala_crypto::{aead, ecdh, query, CryptoError};
 
 #[derive(Clone, Encode, Decode, Debug)]
 pub struct EncryptedData {
@@ -37,6 +37,26 @@ impl EncryptedData {
     }
 }
 
+impl From<query::EncryptedPayload> for EncryptedData {
+    fn from(payload: query::EncryptedPayload) -> Self {
+        Self {
+            iv: payload.iv,
+            pubkey: payload.pubkey,
+            data: payload.data,
+        }
+    }
+}
+
+impl From<EncryptedData> for query::EncryptedPayload {
+    fn from(data: EncryptedData) -> Self {
+        Self {
+            iv: data.iv,
+            pubkey: data.pubkey,
+            data: data.data,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum SignatureVerifyError {
     InvalidSignatureType,
@@ -95,6 +115,19 @@ impl Signature {
             None => return Err(SignatureVerifyError::CertificateMissing),
         }
     }
+
+    /// The last block the signature is valid at, the earliest TTL of its certificate chain.
+    pub fn ttl(&self) -> Result<u32, SignatureVerifyError> {
+        let cert = self
+            .signed_by
+            .as_ref()
+            .ok_or(SignatureVerifyError::CertificateMissing)?;
+        let ttl = cert.decode_body()?.ttl;
+        match &cert.signature {
+            Some(cert_sig) => Ok(ttl.min(cert_sig.ttl()?)),
+            None => Ok(ttl),
+        }
+    }
 }
 
 fn verify<T>(pubkey: &[u8], sig: &[u8], msg: &[u8]) -> bool
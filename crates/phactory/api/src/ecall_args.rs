@@ -34,6 +34,13 @@ pub struct InitArgs {
 
     /// Max number of checkpoint files kept
     pub max_checkpoint_files: u32,
+
+    /// Heap usage snapshot interval in seconds, 0 to disable
+    pub heap_snapshot_interval: u64,
+
+    /// The SS58 network prefix to display the accounts. pRuntime passes 30 (Phala) by default, the
+    /// generic Substrate one is used if not given.
+    pub ss58_prefix: Option<u16>,
 }
 
 pub fn git_revision() -> String {
@@ -35,13 +35,13 @@ impl RequestClient for RpcRequest {
         }
 
         let url = alloc::format!("{}/prpc/{}", self.base_url, path);
-        let res = reqwest::Client::new()
+        let mut req = reqwest::Client::new()
             .post(url)
-            .header("Connection", "close")
-            .body(body)
-            .send()
-            .await
-            .map_err(from_display)?;
+            .header("Connection", "close");
+        for (name, value) in phala_tracing::current_context_headers() {
+            req = req.header(name, value);
+        }
+        let res = req.body(body).send().await.map_err(from_display)?;
 
         info!("Response: {}", res.status());
         let status = res.status();
@@ -1,5 +1,6 @@
 use super::blocks::{
     AuthoritySetChange, BlockHeaderWithChanges, HeaderToSync, RuntimeHasher, StorageProof,
+    StorageState,
 };
 
 use alloc::collections::VecDeque;
@@ -48,6 +49,8 @@ pub enum Error {
     },
     /// Solo/Para mode mismatch
     ChainModeMismatch,
+    /// A state snapshot can only be imported before any parachain block is synced
+    AlreadySynced,
 }
 
 pub trait BlockValidator {
@@ -93,6 +96,17 @@ pub trait StorageSynchronizer {
         block: &BlockHeaderWithChanges,
         storage: &mut Storage,
     ) -> Result<()>;
+
+    /// Replace the storage with the parachain state at the given header, instead of replaying
+    /// all the blocks before it. Return the number of the header.
+    fn import_state_snapshot(
+        &mut self,
+        header: chain::Header,
+        proof: StorageProof,
+        storage_key: &[u8],
+        state: StorageState,
+        storage: &mut Storage,
+    ) -> Result<chain::BlockNumber>;
 }
 
 #[derive(Serialize, Deserialize)]
@@ -280,6 +294,17 @@ impl<Validator: BlockValidator> StorageSynchronizer for SolochainSynchronizer<Va
     ) -> Result<chain::BlockNumber> {
         Err(Error::ChainModeMismatch)
     }
+
+    fn import_state_snapshot(
+        &mut self,
+        _header: chain::Header,
+        _proof: StorageProof,
+        _storage_key: &[u8],
+        _state: StorageState,
+        _storage: &mut Storage,
+    ) -> Result<chain::BlockNumber> {
+        Err(Error::ChainModeMismatch)
+    }
 }
 
 
@@ -391,6 +416,56 @@ impl<Validator: BlockValidator> StorageSynchronizer for ParachainSynchronizer<Va
         self.sync_state
             .feed_block(block, &mut self.para_state_roots, storage)
     }
+
+    /// Given a parachain header proven by the last synced relaychain header, replace the storage
+    /// with the snapshot of the parachain state at it.
+    ///
+    /// The snapshot only contains the main trie, so the chain must not use child tries.
+    fn import_state_snapshot(
+        &mut self,
+        header: chain::Header,
+        proof: StorageProof,
+        storage_key: &[u8],
+        state: StorageState,
+        storage: &mut Storage,
+    ) -> Result<chain::BlockNumber> {
+        if self.para_header_number_next != 1 || self.sync_state.block_number_next != 1 {
+            return Err(Error::AlreadySynced);
+        }
+
+        let state_root = self
+            .last_relaychain_state_root
+            .as_ref()
+            .cloned()
+            .ok_or(Error::RelaychainHeaderNotSynced)?;
+
+        // 1. validate the header against the finalized relaychain state
+        self.sync_state.validator.validate_storage_proof(
+            state_root,
+            proof,
+            &[(storage_key, header.encode().encode().as_slice())],
+        )?;
+
+        // 2. validate the snapshot against the header
+        let mut snapshot = Storage::default();
+        snapshot.load(state.into_iter());
+        if snapshot.root() != &header.state_root {
+            return Err(Error::StateRootMismatch {
+                block: header.number,
+                expected: header.state_root,
+                actual: *snapshot.root(),
+            });
+        }
+
+        // All checks passed, continue to sync from the next block.
+        *storage = snapshot;
+        self.last_relaychain_state_root = None;
+        self.para_state_roots.clear();
+        self.para_header_number_next = header.number + 1;
+        self.sync_state.block_number_next = header.number + 1;
+
+        Ok(header.number)
+    }
 }
 
 // We create this new type to help serialize the original dyn StorageSynchronizer.
@@ -458,4 +533,16 @@ impl<Validator: BlockValidator> StorageSynchronizer for Synchronizer<Validator>
     ) -> Result<()> {
         self.as_dyn_mut().feed_block(block, storage)
     }
+
+    fn import_state_snapshot(
+        &mut self,
+        header: chain::Header,
+        proof: StorageProof,
+        storage_key: &[u8],
+        state: StorageState,
+        storage: &mut Storage,
+    ) -> Result<chain::BlockNumber> {
+        self.as_dyn_mut()
+            .import_state_snapshot(header, proof, storage_key, state, storage)
+    }
 }
@@ -26,8 +26,29 @@ pub struct MemoryUsage {
     pub rust_peak_used: usize,
 }
 
+/// The heap usage of the allocations made under a subsystem tag.
+pub struct TaggedMemoryUsage {
+    pub tag: u8,
+    pub used: usize,
+    pub peak_used: usize,
+    pub allocations: usize,
+}
+
 pub trait MemoryStats {
     fn memory_usage(&self) -> MemoryUsage;
+
+    /// The heap usage per subsystem tag. Empty if the platform does not account the tags.
+    fn memory_usage_by_tag(&self) -> Vec<TaggedMemoryUsage> {
+        Vec::new()
+    }
+}
+
+pub trait Logging {
+    type Error: ErrorType;
+    /// Replaces the log filter of the app, in the env_logger syntax, e.g. `info,sidevm=debug`.
+    fn set_log_filter(&self, filter: &str) -> Result<(), Self::Error>;
+    /// The current log filter of the app.
+    fn log_filter(&self) -> String;
 }
 
 pub trait Machine {
@@ -45,5 +66,11 @@ pub trait ProtectedFileSystem {
     fn create_protected_file(&self, path: impl AsRef<Path>, key: &[u8]) -> Result<Self::WriteFile, Self::IoError>;
 }
 
-pub trait Platform: Sealing + RA + Machine + MemoryStats + ProtectedFileSystem + Clone {}
-impl<T: Sealing + RA + Machine + MemoryStats + ProtectedFileSystem + Clone> Platform for T {}
+pub trait Platform:
+    Sealing + RA + Machine + MemoryStats + ProtectedFileSystem + Logging + Clone
+{
+}
+impl<T: Sealing + RA + Machine + MemoryStats + ProtectedFileSystem + Logging + Clone> Platform
+    for T
+{
+}
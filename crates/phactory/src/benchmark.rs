@@ -67,6 +67,7 @@ pub fn run() {
         if count % 100 == 0 {
             let score = est_score(since, start);
             debug!(
+                target: "mining",
                 "Benchmark counnter increased to {}, est score={}",
                 count, score,
             );
@@ -1,4 +1,5 @@
 use super::*;
+use phala_types::{WorkerEndpoint, WorkerEndpointPayload};
 
 // For bin_api
 impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform> {
@@ -27,6 +28,7 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
             "machine_id": machine_id,
             "version": info.version,
             "git_revision": info.git_revision,
+            "query_response_version": phala_crypto::query::RESPONSE_VERSION,
             "running_side_tasks": info.running_side_tasks,
             "memory_usage": {
                 "total_peak_used": meminfo.total_peak_used,
@@ -36,6 +38,91 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         }))
     }
 
+    fn get_heap_profile_json(&self) -> Result<Value, Value> {
+        Ok(self.heap_profiler.dump(&self.platform))
+    }
+
+    fn get_log_filter_json(&self) -> Result<Value, Value> {
+        Ok(json!({ "log_filter": self.platform.log_filter() }))
+    }
+
+    fn get_sidevm_instances_json(&self) -> Result<Value, Value> {
+        let system = self
+            .system
+            .as_ref()
+            .ok_or_else(|| error_msg("Runtime not initialized"))?;
+        let instances: Vec<_> = system
+            .sidevm_instances()
+            .into_iter()
+            .map(|(status, contract)| {
+                let state = match status.state {
+                    sidevm::service::VmState::Running => "running",
+                    sidevm::service::VmState::Stopped => "stopped",
+                    sidevm::service::VmState::Crashed => "crashed",
+                };
+                json!({
+                    "id": hex::encode(&status.id),
+                    "contract": contract.map(|_| hex::encode(&status.id)),
+                    "cluster": contract.map(|(cluster, _)| hex::encode(&cluster)),
+                    "code_hash": contract.map(|(_, code_hash)| hex::encode(&code_hash)),
+                    "state": state,
+                    "memory_size": status.memory_size,
+                    "max_memory_pages": status.max_memory_pages,
+                    "restart_count": status.restart_count,
+                })
+            })
+            .collect();
+        Ok(json!({ "instances": instances }))
+    }
+
+    /// Lists the pending egress messages of each sender, and whether they are accepted on chain.
+    fn get_egress_status_json(&self) -> Result<Value, Value> {
+        let state = self
+            .runtime_state
+            .as_ref()
+            .ok_or_else(|| error_msg("Runtime not initialized"))?;
+        let mut messages = state.send_mq.all_messages_grouped();
+        let senders: Vec<_> = state
+            .send_mq
+            .next_sequences()
+            .into_iter()
+            .map(|(sender, next_sequence)| {
+                let chain_next_sequence = state.chain_next_sequence(&sender);
+                let pending: Vec<_> = messages
+                    .remove(&sender)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|msg| {
+                        json!({
+                            "sequence": msg.sequence,
+                            "topic": format!("{:?}", msg.message.destination),
+                            "payload_hash": hex::encode(sp_core::blake2_256(&msg.message.payload)),
+                            "signed": !msg.signature.is_empty(),
+                            "acked": msg.sequence < chain_next_sequence,
+                        })
+                    })
+                    .collect();
+                json!({
+                    "sender": sender.to_string(),
+                    "next_sequence": next_sequence,
+                    "chain_next_sequence": chain_next_sequence,
+                    "messages": pending,
+                })
+            })
+            .collect();
+        Ok(json!({ "senders": senders }))
+    }
+
+    /// The input is the new filter as plain text, e.g. `info,sidevm=debug`.
+    fn bin_set_log_filter(&mut self, input: &[u8]) -> Result<Value, Value> {
+        let filter = str::from_utf8(input).map_err(|_| error_msg("Invalid utf8 filter"))?;
+        self.platform
+            .set_log_filter(filter.trim())
+            .map_err(|err| error_msg(&format!("{:?}", err)))?;
+        info!(target: "prpc", "Log filter changed to {:?}", filter.trim());
+        self.get_log_filter_json()
+    }
+
     fn bin_sync_header(&mut self, input: blocks::SyncHeaderReq) -> Result<Value, Value> {
         let resp =
             self.sync_header(input.headers, input.authority_set_change).map_err(display)?;
@@ -61,11 +148,38 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         }))
     }
 
+    fn bin_import_state_snapshot(
+        &mut self,
+        input: blocks::ImportStateSnapshotReq,
+    ) -> Result<Value, Value> {
+        let resp = self
+            .import_state_snapshot(input.para_header, input.proof, input.state)
+            .map_err(display)?;
+        Ok(json!({ "synced_to": resp.synced_to }))
+    }
+
     fn bin_dispatch_block(&mut self, input: blocks::DispatchBlockReq) -> Result<Value, Value> {
         let resp = self.dispatch_block(input.blocks).map_err(display)?;
         Ok(json!({ "dispatched_to": resp.synced_to }))
     }
 
+    fn bin_sign_endpoints(&mut self, endpoints: Vec<WorkerEndpoint>) -> Result<Value, Value> {
+        let system = self
+            .system
+            .as_ref()
+            .ok_or_else(|| error_msg("Runtime not initialized"))?;
+        let payload = WorkerEndpointPayload {
+            pubkey: system.identity_key.public(),
+            endpoints,
+            signing_time: system.now_ms / 1000,
+        };
+        let signature = system.identity_key.sign(&payload.signing_message()).0;
+        Ok(json!({
+            "endpoint_payload": hex::encode(payload.encode()),
+            "signature": hex::encode(&signature),
+        }))
+    }
+
     fn try_handle_scale_api(&mut self, action: u8, input: &[u8]) -> Result<Value, Value> {
         use phactory_api::actions::*;
 
@@ -75,16 +189,49 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
 
         match action {
             ACTION_GET_INFO => self.get_info_json(),
+            ACTION_GET_HEAP_PROFILE => self.get_heap_profile_json(),
+            ACTION_GET_LOG_FILTER => self.get_log_filter_json(),
+            ACTION_GET_SIDEVM_INSTANCES => self.get_sidevm_instances_json(),
+            ACTION_GET_EGRESS_STATUS => self.get_egress_status_json(),
             BIN_ACTION_SYNC_HEADER => self.bin_sync_header(load_scale(input)?),
             BIN_ACTION_SYNC_PARA_HEADER => self.bin_sync_para_header(load_scale(input)?),
             BIN_ACTION_SYNC_COMBINED_HEADERS => self.bin_sync_combined_headers(load_scale(input)?),
             BIN_ACTION_DISPATCH_BLOCK => self.bin_dispatch_block(load_scale(input)?),
+            BIN_ACTION_SIGN_ENDPOINTS => self.bin_sign_endpoints(load_scale(input)?),
+            BIN_ACTION_SET_LOG_FILTER => self.bin_set_log_filter(input),
             _ => Err(error_msg("Action not found")),
         }
     }
 
     pub fn handle_scale_api(&mut self, action: u8, input: &[u8]) -> Vec<u8> {
         let result = self.try_handle_scale_api(action, input);
+        self.sign_output(result)
+    }
+
+    /// Fails unless a state snapshot can be imported, to reject one before receiving it.
+    pub fn check_state_snapshot_importable(&self) -> Result<()> {
+        let state = self
+            .runtime_state
+            .as_ref()
+            .ok_or_else(|| anyhow!("Runtime not initialized"))?;
+        let counters = state.storage_synchronizer.counters();
+        if counters.next_para_header_number != 1 || counters.next_block_number != 1 {
+            return Err(anyhow!("The chain storage is already synced"));
+        }
+        Ok(())
+    }
+
+    /// Handles the admin bin_api `import_state_snapshot`, whose input is decoded by the caller
+    /// from the streamed request body.
+    pub fn handle_import_state_snapshot(
+        &mut self,
+        input: blocks::ImportStateSnapshotReq,
+    ) -> Vec<u8> {
+        let result = self.bin_import_state_snapshot(input);
+        self.sign_output(result)
+    }
+
+    fn sign_output(&self, result: Result<Value, Value>) -> Vec<u8> {
         let (status, payload) = match result {
             Ok(payload) => ("ok", payload),
             Err(payload) => ("error", payload),
@@ -102,7 +249,7 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
             "payload": str_payload,
             "signature": signature,
         });
-        info!("{}", output_json.to_string());
+        info!(target: "prpc", "{}", output_json.to_string());
         serde_json::to_vec(&output_json).unwrap()
     }
 }
@@ -0,0 +1,99 @@
+//! Display and parsing of the account ids in the formats shown by the wallets.
+
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chain::AccountId;
+use sp_core::crypto::{set_default_ss58_version, Ss58AddressFormat, Ss58Codec};
+use sp_core::ecdsa;
+
+/// Sets the SS58 network prefix used to display the accounts, which should be the `ss58Format`
+/// property of the chain spec.
+pub fn set_ss58_prefix(prefix: u16) {
+    set_default_ss58_version(Ss58AddressFormat::custom(prefix));
+}
+
+/// An account id displayed as an SS58 address with the configured network prefix.
+///
+/// It can be parsed from:
+/// - an SS58 address of any network, of either an sr25519/ed25519 account or an ecdsa public key
+/// - the hex of the 32 bytes account id
+/// - the hex of a 33 bytes compressed ecdsa public key
+///
+/// The ecdsa public keys are mapped to their accounts with `phala_crypto::ecdsa::account_id`, like
+/// the chain does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountIdWrapper(pub AccountId);
+
+impl AccountIdWrapper {
+    pub fn from_ecdsa(public: &ecdsa::Public) -> Self {
+        Self(phala_crypto::ecdsa::account_id(&public.0).into())
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self> {
+        let bytes = hex::decode(s.trim_start_matches("0x"))?;
+        if let Ok(account) = <[u8; 32]>::try_from(&bytes[..]) {
+            return Ok(Self(account.into()));
+        }
+        let public = ecdsa::Public::from_full(&bytes)
+            .map_err(|_| anyhow!("Expect 32 bytes account id or 33 bytes ecdsa public key"))?;
+        Ok(Self::from_ecdsa(&public))
+    }
+}
+
+impl fmt::Display for AccountIdWrapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.to_ss58check())
+    }
+}
+
+impl FromStr for AccountIdWrapper {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok((account, _)) = AccountId::from_ss58check_with_version(s) {
+            return Ok(Self(account));
+        }
+        if let Ok((public, _)) = ecdsa::Public::from_ss58check_with_version(s) {
+            return Ok(Self::from_ecdsa(&public));
+        }
+        Self::from_hex(s).map_err(|_| anyhow!("Invalid account: {}", s))
+    }
+}
+
+impl From<AccountId> for AccountIdWrapper {
+    fn from(account: AccountId) -> Self {
+        Self(account)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sp_core::Pair;
+
+    const ALICE_HEX: &str = "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
+
+    #[test]
+    fn parses_sr25519_accounts() {
+        let from_hex: AccountIdWrapper = ALICE_HEX.parse().unwrap();
+        let from_ss58 = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY".parse().unwrap();
+        assert_eq!(from_hex, from_ss58);
+        // The address of the same account on the Phala network
+        let phala = from_hex.0.to_ss58check_with_version(Ss58AddressFormat::custom(30));
+        assert_eq!(phala.parse::<AccountIdWrapper>().unwrap(), from_hex);
+    }
+
+    #[test]
+    fn parses_ecdsa_accounts() {
+        let public = ecdsa::Pair::from_seed(&[1; 32]).public();
+        let expected = AccountIdWrapper::from_ecdsa(&public);
+        let from_hex: AccountIdWrapper = hex::encode(public.as_ref()).parse().unwrap();
+        let from_ss58: AccountIdWrapper = public.to_ss58check().parse().unwrap();
+        assert_eq!(from_hex, expected);
+        assert_eq!(from_ss58, expected);
+        assert!("0x1234".parse::<AccountIdWrapper>().is_err());
+    }
+}
@@ -8,11 +8,18 @@ use std::string::ToString;
 
 use super::{NativeContext, TransactionError, TransactionResult};
 use crate::contracts;
-use crate::contracts::AccountId;
-use phala_types::messaging::{AssetCommand, AssetId};
+use crate::contracts::{AccountId, AccountIdWrapper};
+use phala_mq::traits::MessageChannel;
+use phala_types::messaging::{AssetCommand, AssetId, AssetUnwrap};
 
 type Command = AssetCommand<chain::AccountId, chain::Balance>;
 
+/// The name of the pallet locking the foreign assets on chain, the only one allowed to wrap them.
+const BRIDGE_PALLET: &[u8] = b"PhalaAssetsBridge";
+
+/// The symbols of the wrapped assets, reserved so that no issued asset can pass for one.
+const WRAPPED_SYMBOL_PREFIX: &str = "wrapped:";
+
 extern crate runtime as chain;
 
 #[derive(Encode, Decode, Debug, Clone)]
@@ -29,12 +36,38 @@ pub struct AssetMetadataBalance {
     balance: chain::Balance,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode)]
 pub struct Assets {
     next_id: u32,
     assets: BTreeMap<u32, BTreeMap<AccountId, chain::Balance>>,
     metadata: BTreeMap<u32, AssetMetadata>,
     history: BTreeMap<AccountId, Vec<AssetsTx>>,
+    /// The wrapped foreign assets, by their location on chain.
+    wrapped: BTreeMap<Vec<u8>, u32>,
+}
+
+impl Decode for Assets {
+    fn decode<I: parity_scale_codec::Input>(
+        input: &mut I,
+    ) -> Result<Self, parity_scale_codec::Error> {
+        let next_id = Decode::decode(input)?;
+        let assets = Decode::decode(input)?;
+        let metadata = Decode::decode(input)?;
+        let history = Decode::decode(input)?;
+        // The checkpoints taken before the foreign assets were supported end here. The contract
+        // is only decoded from a <&[u8] as Input>, which reports the correct remaining_len.
+        let wrapped = match input.remaining_len()? {
+            Some(0) => Default::default(),
+            _ => Decode::decode(input)?,
+        };
+        Ok(Assets {
+            next_id,
+            assets,
+            metadata,
+            history,
+            wrapped,
+        })
+    }
 }
 #[derive(Encode, Decode, Debug, Clone)]
 pub struct AssetsTx {
@@ -87,8 +120,17 @@ impl Assets {
             assets,
             metadata,
             history: Default::default(),
+            wrapped: Default::default(),
         }
     }
+
+    /// The location of a wrapped asset on chain.
+    fn wrapped_location(&self, id: u32) -> Option<&Vec<u8>> {
+        self.wrapped
+            .iter()
+            .find(|(_, wrapped_id)| **wrapped_id == id)
+            .map(|(location, _)| location)
+    }
 }
 
 impl contracts::NativeContract for Assets {
@@ -100,13 +142,22 @@ impl contracts::NativeContract for Assets {
         &mut self,
         origin: MessageOrigin,
         cmd: Self::Cmd,
-        _context: &mut NativeContext,
+        context: &mut NativeContext,
     ) -> TransactionResult {
         match cmd {
             Command::Issue { symbol, total } => {
                 let o = origin.account()?;
-                info!("Issue: [{}] -> [{}]: {}", hex::encode(&o), symbol, total);
+                info!(
+                    target: "contract",
+                    "Issue: [{}] -> [{}]: {}",
+                    AccountIdWrapper(o.clone()),
+                    symbol,
+                    total
+                );
 
+                if symbol.starts_with(WRAPPED_SYMBOL_PREFIX) {
+                    return Err(TransactionError::ReservedSymbol);
+                }
                 if !self
                     .metadata
                     .iter()
@@ -160,9 +211,10 @@ impl contracts::NativeContract for Assets {
                     let accounts = self.assets.get_mut(&metadatum.id).unwrap();
 
                     info!(
+                        target: "contract",
                         "Transfer: [{}] -> [{}]: {}",
-                        hex::encode(&o),
-                        hex::encode(&dest),
+                        AccountIdWrapper(o.clone()),
+                        AccountIdWrapper(dest.clone()),
                         value
                     );
                     if let Some(src_amount) = accounts.get_mut(&o) {
@@ -178,8 +230,18 @@ impl contracts::NativeContract for Assets {
                                 accounts.insert(dest.clone(), value);
                             }
 
-                            info!("   src: {:>20} -> {:>20}", src0, src0 - value);
-                            info!("  dest: {:>20} -> {:>20}", dest0, dest0 + value);
+                            info!(
+                                target: "contract",
+                                "   src: {:>20} -> {:>20}",
+                                src0,
+                                src0 - value
+                            );
+                            info!(
+                                target: "contract",
+                                "  dest: {:>20} -> {:>20}",
+                                dest0,
+                                dest0 + value
+                            );
 
                             let tx = AssetsTx {
                                 index,
@@ -191,12 +253,12 @@ impl contracts::NativeContract for Assets {
                             if is_tracked(&o) {
                                 let slot = self.history.entry(o).or_default();
                                 slot.push(tx.clone());
-                                info!(" pushed history (src)");
+                                info!(target: "contract", " pushed history (src)");
                             }
                             if is_tracked(&dest) {
                                 let slot = self.history.entry(dest).or_default();
                                 slot.push(tx);
-                                info!(" pushed history (dest)");
+                                info!(target: "contract", " pushed history (dest)");
                             }
 
                             Ok(Default::default())
@@ -210,6 +272,87 @@ impl contracts::NativeContract for Assets {
                     Err(TransactionError::AssetIdNotFound)
                 }
             }
+            Command::Wrap {
+                location,
+                who,
+                amount,
+            } => {
+                if origin != MessageOrigin::Pallet(BRIDGE_PALLET.to_vec()) {
+                    error!(
+                        target: "contract",
+                        "Received event from unexpected origin: {:?}",
+                        origin
+                    );
+                    return Err(TransactionError::BadOrigin);
+                }
+                let id = match self.wrapped.get(&location) {
+                    Some(id) => *id,
+                    None => {
+                        // The wrapped assets are owned by nobody, so they can not be destroyed.
+                        let id = self.next_id;
+                        let metadatum = AssetMetadata {
+                            owner: AccountId::from([0u8; 32]),
+                            total_supply: 0,
+                            symbol: format!("{}{}", WRAPPED_SYMBOL_PREFIX, hex::encode(&location)),
+                            id,
+                        };
+                        self.metadata.insert(id, metadatum);
+                        self.assets.insert(id, Default::default());
+                        self.wrapped.insert(location, id);
+                        self.next_id += 1;
+                        id
+                    }
+                };
+                info!(
+                    target: "contract",
+                    "Wrap: [{}] <- asset {}: {}",
+                    AccountIdWrapper(who.clone()),
+                    id,
+                    amount
+                );
+                let metadatum = self.metadata.get_mut(&id).unwrap();
+                let accounts = self.assets.get_mut(&id).unwrap();
+                let balance = accounts.get(&who).cloned().unwrap_or_default();
+                let total_supply = metadatum
+                    .total_supply
+                    .checked_add(amount)
+                    .ok_or(TransactionError::SupplyOverflow)?;
+                let balance = balance
+                    .checked_add(amount)
+                    .ok_or(TransactionError::SupplyOverflow)?;
+                metadatum.total_supply = total_supply;
+                accounts.insert(who, balance);
+                Ok(Default::default())
+            }
+            Command::Unwrap { id, dest, value } => {
+                let o = origin.account()?;
+                let location = self
+                    .wrapped_location(id)
+                    .ok_or(TransactionError::AssetIdNotFound)?
+                    .clone();
+                info!(
+                    target: "contract",
+                    "Unwrap: [{}] -> [{}]: asset {}: {}",
+                    AccountIdWrapper(o.clone()),
+                    AccountIdWrapper(dest.clone()),
+                    id,
+                    value
+                );
+                let accounts = self.assets.get_mut(&id).unwrap();
+                let src_amount = accounts.get_mut(&o).ok_or(TransactionError::NoBalance)?;
+                if *src_amount < value {
+                    return Err(TransactionError::InsufficientBalance);
+                }
+                *src_amount -= value;
+                self.metadata.get_mut(&id).unwrap().total_supply -= value;
+                let data = AssetUnwrap {
+                    location,
+                    dest,
+                    amount: value,
+                };
+                context.mq().push_message(&data);
+                Ok(Default::default())
+            }
         }
     }
 
@@ -302,3 +445,25 @@ impl contracts::NativeContract for Assets {
 fn is_tracked(_id: &AccountId) -> bool {
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_the_state_without_wrapped_assets() {
+        let mut assets = Assets::new();
+        assets.next_id = 2;
+        assets.wrapped.insert(vec![1, 2, 3], 1);
+        let encoded = assets.encode();
+        let decoded = Assets::decode(&mut &encoded[..]).unwrap();
+        assert_eq!(decoded.next_id, 2);
+        assert_eq!(decoded.wrapped, assets.wrapped);
+
+        // The layout before the foreign assets were supported
+        let legacy = (2u32, &assets.assets, &assets.metadata, &assets.history).encode();
+        let decoded = Assets::decode(&mut &legacy[..]).unwrap();
+        assert_eq!(decoded.next_id, 2);
+        assert!(decoded.wrapped.is_empty());
+    }
+}
@@ -10,7 +10,7 @@ use phala_mq::MessageOrigin;
 
 use super::{TransactionError, TransactionResult};
 use crate::contracts;
-use crate::contracts::{AccountId, NativeContext};
+use crate::contracts::{AccountId, AccountIdWrapper, NativeContext};
 extern crate runtime as chain;
 
 use phala_types::messaging::{BalancesCommand, BalancesTransfer};
@@ -75,9 +75,10 @@ impl contracts::NativeContract for Balances {
             Command::Transfer { dest, value } => {
                 let o = origin.account()?;
                 info!(
+                    target: "contract",
                     "Transfer: [{}] -> [{}]: {}",
-                    hex::encode(&o),
-                    hex::encode(&dest),
+                    AccountIdWrapper(o.clone()),
+                    AccountIdWrapper(dest.clone()),
                     value
                 );
                 if let Some(src_amount) = self.accounts.get_mut(&o) {
@@ -93,8 +94,8 @@ impl contracts::NativeContract for Balances {
                             self.accounts.insert(dest, value);
                         }
 
-                        info!("   src: {:>20} -> {:>20}", src0, src0 - value);
-                        info!("  dest: {:>20} -> {:>20}", dest0, dest0 + value);
+                        info!(target: "contract", "   src: {:>20} -> {:>20}", src0, src0 - value);
+                        info!(target: "contract", "  dest: {:>20} -> {:>20}", dest0, dest0 + value);
 
                         Ok(Default::default())
                     } else {
@@ -107,9 +108,10 @@ impl contracts::NativeContract for Balances {
             Command::TransferToChain { dest, value } => {
                 let o = origin.account()?;
                 info!(
+                    target: "contract",
                     "Transfer to chain: [{}] -> [{}]: {}",
-                    hex::encode(&o),
-                    hex::encode(&dest),
+                    AccountIdWrapper(o.clone()),
+                    AccountIdWrapper(dest.clone()),
                     value
                 );
                 if let Some(src_amount) = self.accounts.get_mut(&o) {
@@ -117,7 +119,7 @@ impl contracts::NativeContract for Balances {
                         let src0 = *src_amount;
                         *src_amount -= value;
                         self.total_issuance -= value;
-                        info!("   src: {:>20} -> {:>20}", src0, src0 - value);
+                        info!(target: "contract", "   src: {:>20} -> {:>20}", src0, src0 - value);
 
                         let data = BalancesTransfer {
                             dest,
@@ -134,19 +136,28 @@ impl contracts::NativeContract for Balances {
             }
             Command::TransferToTee { who, amount } => {
                 if !origin.is_pallet() {
-                    error!("Received event from unexpected origin: {:?}", origin);
+                    error!(
+                        target: "contract",
+                        "Received event from unexpected origin: {:?}",
+                        origin
+                    );
                     return Err(TransactionError::BadOrigin);
                 }
-                info!("TransferToTee from :{:?}, {:}", who, amount);
+                info!(target: "contract", "TransferToTee from :{:?}, {:}", who, amount);
                 let dest = who;
-                info!("   dest: {}", hex::encode(&dest));
+                info!(target: "contract", "   dest: {}", AccountIdWrapper(dest.clone()));
                 if let Some(dest_amount) = self.accounts.get_mut(&dest) {
                     let dest_amount0 = *dest_amount;
                     *dest_amount += amount;
-                    info!("   value: {:>20} -> {:>20}", dest_amount0, *dest_amount);
+                    info!(
+                        target: "contract",
+                        "   value: {:>20} -> {:>20}",
+                        dest_amount0,
+                        *dest_amount
+                    );
                 } else {
                     self.accounts.insert(dest, amount);
-                    info!("   value: {:>20} -> {:>20}", 0, amount);
+                    info!(target: "contract", "   value: {:>20} -> {:>20}", 0, amount);
                 }
                 self.total_issuance += amount;
                 Ok(Default::default())
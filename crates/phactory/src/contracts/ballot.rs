@@ -0,0 +1,240 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::string::String;
+
+use log::info;
+use parity_scale_codec::{Decode, Encode};
+use phala_mq::MessageOrigin;
+
+use super::{TransactionError, TransactionResult};
+use crate::contracts;
+use crate::contracts::{AccountId, AccountIdWrapper, NativeContext};
+extern crate runtime as chain;
+
+use phala_types::messaging::BallotCommand;
+
+pub type Command = BallotCommand<chain::AccountId, chain::Balance, chain::BlockNumber>;
+
+/// The max size of the title of a proposal.
+const MAX_TITLE_SIZE: usize = 256;
+/// The max number of options of a proposal.
+const MAX_OPTIONS: u32 = 16;
+/// The max number of proposals open for voting at the same time.
+const MAX_ONGOING_PROPOSALS: usize = 256;
+/// The max number of blocks a proposal is open for voting, about 30 days.
+const MAX_VOTING_PERIOD: chain::BlockNumber = 216_000;
+/// The name of the pallet feeding the locked balances, the only one allowed to update the weights.
+const WEIGHTS_PALLET: &[u8] = b"PhalaBallot";
+
+#[derive(Encode, Decode, Debug, Clone)]
+struct Proposal {
+    proposer: AccountId,
+    title: String,
+    options: u32,
+    deadline: chain::BlockNumber,
+    /// The option chosen by each voter. Never disclosed except to the voter.
+    votes: BTreeMap<AccountId, u32>,
+    /// The weighted votes of each option, counted at the end of the deadline block.
+    tally: Option<Vec<chain::Balance>>,
+}
+
+/// Secret ballot voting on proposals.
+///
+/// The votes are only known inside the enclave. Each vote is weighted by the balance the voter
+/// locks on chain, and only the tally is revealed after the deadline of the proposal.
+#[derive(Encode, Decode, Debug, Clone, Default)]
+pub struct Ballot {
+    weights: BTreeMap<AccountId, chain::Balance>,
+    proposals: Vec<Proposal>,
+    /// The proposals open for voting by their deadline, the only ones checked at the block end.
+    ongoing: BTreeSet<(chain::BlockNumber, u32)>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub enum Error {
+    OriginUnavailable,
+    NoProposal,
+    VotingNotEnded,
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub enum Request {
+    ProposalCount,
+    GetProposal { id: u32 },
+    /// The tally of a proposal, only available after its deadline.
+    GetTally { id: u32 },
+    /// The option the sender voted for.
+    GetMyVote { id: u32 },
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub enum Response {
+    ProposalCount { count: u32 },
+    GetProposal {
+        proposer: AccountId,
+        title: String,
+        options: u32,
+        deadline: chain::BlockNumber,
+        ended: bool,
+    },
+    GetTally { tally: Vec<chain::Balance> },
+    GetMyVote { option: Option<u32> },
+}
+
+impl Ballot {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn proposal(&self, id: u32) -> Result<&Proposal, Error> {
+        self.proposals.get(id as usize).ok_or(Error::NoProposal)
+    }
+
+    fn count_votes(weights: &BTreeMap<AccountId, chain::Balance>, proposal: &mut Proposal) {
+        let mut tally = vec![0; proposal.options as usize];
+        for (voter, option) in proposal.votes.iter() {
+            let weight = weights.get(voter).cloned().unwrap_or_default();
+            let count = &mut tally[*option as usize];
+            *count = count.saturating_add(weight);
+        }
+        proposal.tally = Some(tally);
+    }
+}
+
+impl contracts::NativeContract for Ballot {
+    type Cmd = Command;
+    type QReq = Request;
+    type QResp = Result<Response, Error>;
+
+    fn handle_command(
+        &mut self,
+        origin: MessageOrigin,
+        cmd: Command,
+        context: &mut NativeContext,
+    ) -> TransactionResult {
+        let now = context.block.block_number;
+        match cmd {
+            Command::Propose {
+                title,
+                options,
+                deadline,
+            } => {
+                let proposer = origin.account()?;
+                if title.len() > MAX_TITLE_SIZE
+                    || !(2..=MAX_OPTIONS).contains(&options)
+                    || deadline <= now
+                    || deadline - now > MAX_VOTING_PERIOD
+                {
+                    return Err(TransactionError::BadInput);
+                }
+                if self.ongoing.len() >= MAX_ONGOING_PROPOSALS {
+                    return Err(TransactionError::QuotaExceeded);
+                }
+                let id = self.proposals.len() as u32;
+                info!(
+                    target: "contract",
+                    "Ballot: [{}] proposed {}, deadline: {}",
+                    AccountIdWrapper(proposer.clone()),
+                    id,
+                    deadline
+                );
+                self.ongoing.insert((deadline, id));
+                self.proposals.push(Proposal {
+                    proposer,
+                    title,
+                    options,
+                    deadline,
+                    votes: Default::default(),
+                    tally: None,
+                });
+            }
+            Command::Vote { proposal, option } => {
+                let voter = origin.account()?;
+                if !self.weights.contains_key(&voter) {
+                    return Err(TransactionError::NoBalance);
+                }
+                let proposal = self
+                    .proposals
+                    .get_mut(proposal as usize)
+                    .ok_or(TransactionError::BadInput)?;
+                if proposal.tally.is_some()
+                    || now > proposal.deadline
+                    || option >= proposal.options
+                {
+                    return Err(TransactionError::BadInput);
+                }
+                proposal.votes.insert(voter, option);
+            }
+            Command::UpdateWeights { weights } => {
+                if origin != MessageOrigin::Pallet(WEIGHTS_PALLET.to_vec()) {
+                    error!(
+                        target: "contract",
+                        "Received event from unexpected origin: {:?}",
+                        origin
+                    );
+                    return Err(TransactionError::BadOrigin);
+                }
+                for (who, weight) in weights {
+                    if weight == 0 {
+                        self.weights.remove(&who);
+                    } else {
+                        self.weights.insert(who, weight);
+                    }
+                }
+            }
+        }
+        Ok(Default::default())
+    }
+
+    fn on_block_end(&mut self, context: &mut NativeContext) -> TransactionResult {
+        let now = context.block.block_number;
+        while let Some((deadline, id)) = self.ongoing.iter().next().cloned() {
+            if deadline > now {
+                break;
+            }
+            self.ongoing.remove(&(deadline, id));
+            let proposal = &mut self.proposals[id as usize];
+            Self::count_votes(&self.weights, proposal);
+            info!(target: "contract", "Ballot: proposal {} ended with {:?}", id, proposal.tally);
+        }
+        Ok(Default::default())
+    }
+
+    fn handle_query(
+        &self,
+        origin: Option<&chain::AccountId>,
+        req: Request,
+        _context: &mut contracts::QueryContext,
+    ) -> Result<Response, Error> {
+        match req {
+            Request::ProposalCount => Ok(Response::ProposalCount {
+                count: self.proposals.len() as u32,
+            }),
+            Request::GetProposal { id } => {
+                let proposal = self.proposal(id)?;
+                Ok(Response::GetProposal {
+                    proposer: proposal.proposer.clone(),
+                    title: proposal.title.clone(),
+                    options: proposal.options,
+                    deadline: proposal.deadline,
+                    ended: proposal.tally.is_some(),
+                })
+            }
+            Request::GetTally { id } => {
+                let tally = self.proposal(id)?.tally.clone();
+                Ok(Response::GetTally {
+                    tally: tally.ok_or(Error::VotingNotEnded)?,
+                })
+            }
+            Request::GetMyVote { id } => {
+                let voter = origin.ok_or(Error::OriginUnavailable)?;
+                Ok(Response::GetMyVote {
+                    option: self.proposal(id)?.votes.get(voter).cloned(),
+                })
+            }
+        }
+    }
+
+    fn snapshot(&self) -> Self {
+        self.clone()
+    }
+}
@@ -157,7 +157,7 @@ impl BtcLottery {
 
     fn check_secret_key(&self) -> bool {
         if self.seed.is_none() {
-            error!("Empty seed");
+            error!(target: "contract", "Empty seed");
             false
         } else {
             true
@@ -171,7 +171,7 @@ impl BtcLottery {
         total_count: u32,
         winner_count: u32,
     ) {
-        info!("new_round({}, {}, {})", round_id, total_count, winner_count);
+        info!(target: "contract", "new_round({}, {}, {})", round_id, total_count, winner_count);
         if !self.check_secret_key() {
             return;
         }
@@ -185,7 +185,7 @@ impl BtcLottery {
                 let token_id = format!("{:#x}", nft_id);
                 round_token.push(token_id);
             }
-            info!("new_round: n round_token: {}", round_token.len());
+            info!(target: "contract", "new_round: n round_token: {}", round_token.len());
             let mut lottery_token = BTreeMap::<String, PrivateKeyWrapper>::new();
             let raw_seed = blake2_256(&Encode::encode(&(seed, round_id)));
             let mut r: StdRng = SeedableRng::from_seed(raw_seed);
@@ -193,7 +193,7 @@ impl BtcLottery {
                 .iter()
                 .choose_multiple(&mut r, winner_count as usize);
 
-            info!("new_round: n sampled: {}", sample.len());
+            info!(target: "contract", "new_round: n sampled: {}", sample.len());
             let mut address_set = Vec::new();
             let mut salt = round_id * 10000;
             for winner_id in sample {
@@ -203,6 +203,7 @@ impl BtcLottery {
                     Ok(e) => e.private_key,
                     Err(_err) => {
                         error!(
+                            target: "contract",
                             "LotteryNewRound: cannot create a new secret key from the seed: {:?}",
                             &seed
                         );
@@ -222,7 +223,7 @@ impl BtcLottery {
 
             mq.push_message(&Lottery::BtcAddresses { address_set });
         } else {
-            error!("Round {} has already started", round_id);
+            error!(target: "contract", "Round {} has already started", round_id);
         }
     }
 
@@ -245,6 +246,7 @@ impl BtcLottery {
                 Ok(e) => e,
                 Err(_err) => {
                     error!(
+                        target: "contract",
                         "LotteryOpenBox: cannot convert btc_address to String: {:?}",
                         &btc_address
                     );
@@ -255,6 +257,7 @@ impl BtcLottery {
                 Ok(e) => e,
                 Err(_error) => {
                     error!(
+                        target: "contract",
                         "LotteryOpenBox: cannot convert btc_address to Address: {:?}",
                         &btc_address
                     );
@@ -315,6 +318,7 @@ impl BtcLottery {
                     Ok(e) => e.serialize_der(),
                     Err(err) => {
                         error!(
+                            target: "contract",
                             "LotteryOpenBox: the signing of the tx meets some problems:{}",
                             err
                         );
@@ -338,7 +342,7 @@ impl BtcLottery {
             };
             mq.push_message(&data);
         } else {
-            error!("Round {} has already started", round_id);
+            error!(target: "contract", "Round {} has already started", round_id);
         }
     }
 }
@@ -492,10 +496,10 @@ impl BtcLottery {
         ce: LotteryPalletCommand,
     ) -> TransactionResult {
         if !origin.is_pallet() {
-            error!("Received trasfer event from invalid origin: {:?}", origin);
+            error!(target: "contract", "Received trasfer event from invalid origin: {:?}", origin);
             return Err(TransactionError::BadOrigin);
         }
-        info!("Received trasfer event from {:?}", origin);
+        info!(target: "contract", "Received trasfer event from {:?}", origin);
         match ce {
             LotteryPalletCommand::NewRound {
                 round_id,
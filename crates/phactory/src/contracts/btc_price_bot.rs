@@ -125,7 +125,7 @@ impl contracts::NativeContract for BtcPriceBot {
         cmd: Command,
         context: &mut NativeContext,
     ) -> TransactionResult {
-        // info!("Command received: {:?}", &cmd);
+        // info!(target: "contract", "Command received: {:?}", &cmd);
 
         let alice = contracts::account_id_from_hex(ALICE)
             .expect("should not failed with valid address; qed.");
@@ -179,7 +179,7 @@ impl contracts::NativeContract for BtcPriceBot {
                     async move {
                         // Do network request in this block and return the result.
                         // Do NOT send mq message in this block.
-                        log::info!("Side task starts to get BTC price");
+                        log::info!(target: "contract", "Side task starts to get BTC price");
                         let mut resp = match surf::get(
                             "https://min-api.cryptocompare.com/data/price?fsym=BTC&tsyms=USD",
                         )
@@ -197,7 +197,7 @@ impl contracts::NativeContract for BtcPriceBot {
                                 format!("Network error: {:?}", err)
                             }
                         };
-                        log::info!("Side task got BTC price: {}", result);
+                        log::info!(target: "contract", "Side task got BTC price: {}", result);
 
                         let price: BtcPrice =
                             serde_json::from_str(result.as_str()).or(Err(Error::BadBtcPrice))?;
@@ -224,10 +224,10 @@ impl contracts::NativeContract for BtcPriceBot {
                                 format!("Network error: {:?}", err)
                             }
                         };
-                        log::info!("Side task sent BTC price: {}", result);
+                        log::info!(target: "contract", "Side task sent BTC price: {}", result);
 
                         let price = price.usd.to_string();
-                        log::info!("Side task reporting price: {:?}", &price);
+                        log::info!(target: "contract", "Side task reporting price: {:?}", &price);
                         let command = Command::UpdateBtcPrice { price };
                         let message = Payload::Plain(command);
 
@@ -240,6 +240,7 @@ impl contracts::NativeContract for BtcPriceBot {
             // Handle the price updating request from the side-task
             Command::UpdateBtcPrice { price } => {
                 log::info!(
+                    target: "contract",
                     "UpdateBtcPrice received, origin={}, price={}",
                     origin,
                     price
@@ -262,7 +263,7 @@ impl contracts::NativeContract for BtcPriceBot {
         req: Request,
         _context: &mut contracts::QueryContext,
     ) -> Result<Response, Error> {
-        info!("Query received: {:?}", &req);
+        info!(target: "contract", "Query received: {:?}", &req);
 
         let sender = origin.ok_or(Error::OriginUnavailable)?;
         let alice = contracts::account_id_from_hex(ALICE)
@@ -165,6 +165,7 @@ impl DataPlaza {
                                 // we only supports a single field right now
                                 let value = Self::read_field(0, &outbuf, &ends).to_vec();
                                 info!(
+                                    target: "contract",
                                     "inserting query target: {}",
                                     String::from_utf8(value.clone()).unwrap()
                                 );
@@ -218,13 +219,13 @@ impl DataPlaza {
                                     String::from_utf8(value.clone()).unwrap()
                                 );
                                 if targets.contains(&value) {
-                                    info!("found");
+                                    info!(target: "contract", "found");
                                     // should output the entire line!
                                     let mut full_line = (&bytes[..nin]).to_vec();
                                     out.append(&mut full_line);
                                     matched_rows += 1;
                                 } else {
-                                    info!("not found");
+                                    info!(target: "contract", "not found");
                                 }
                             }
                         }
@@ -254,7 +254,7 @@ impl Diem {
             match bcs::from_bytes(&transaction_with_proof.transaction_bytes) {
                 Ok(tx) => tx,
                 Err(_) => {
-                    error!("Decode transaction error");
+                    error!(target: "contract", "Decode transaction error");
 
                     return Err(anyhow::Error::msg(Error::Other(String::from(
                         "Decode transaction error",
@@ -285,7 +285,7 @@ impl Diem {
             }
 
             if !found {
-                error!("Bad receiver address");
+                error!(target: "contract", "Bad receiver address");
 
                 return Err(anyhow::Error::msg(Error::Other(String::from(
                     "Bad receiver address",
@@ -295,7 +295,7 @@ impl Diem {
             // Outgoing tx must be synced sequencely
             if let Some(seq) = self.seq_number.get(&account_address) {
                 if seq + 1 != raw_transaction.sequence_number() {
-                    error!("Bad sequence number");
+                    error!(target: "contract", "Bad sequence number");
 
                     return Err(anyhow::Error::msg(Error::Other(String::from(
                         "Bad sequence number",
@@ -336,7 +336,7 @@ impl Diem {
             if transaction_builder::get_transaction_name(script.code()).as_str()
                 != "peer_to_peer_with_metadata_transaction"
             {
-                info!("Not a peer to peer transaction");
+                info!(target: "contract", "Not a peer to peer transaction");
                 return Ok(());
             }
             use TransactionArgument::*;
@@ -371,8 +371,8 @@ impl Diem {
             }
         }
 
-        info!("pending_transactions:{:?}", self.pending_transactions);
-        info!("accounts:{:?}", self.accounts);
+        info!(target: "contract", "pending_transactions:{:?}", self.pending_transactions);
+        info!(target: "contract", "accounts:{:?}", self.accounts);
 
         Ok(())
     }
@@ -416,6 +416,7 @@ impl Diem {
                 latest_epoch_change_li,
             } => {
                 info!(
+                    target: "contract",
                     "verify_trusted_state: Verified epoch changed to {}",
                     latest_epoch_change_li
                         .ledger_info()
@@ -427,6 +428,7 @@ impl Diem {
             TrustedStateChange::Version { new_state } => {
                 if trusted_state.latest_version() < new_state.latest_version() {
                     info!(
+                        target: "contract",
                         "verify_trusted_state: Verified version change to: {}",
                         new_state.latest_version()
                     );
@@ -434,7 +436,7 @@ impl Diem {
                 self.trusted_state = Some(new_state);
             }
             TrustedStateChange::NoChange => {
-                info!("verify_trusted_state: NoChange");
+                info!(target: "contract", "verify_trusted_state: NoChange");
             }
         }
         Ok(())
@@ -482,7 +484,7 @@ impl Diem {
         ) {
             true
         } else {
-            error!("Failed to verify transaction");
+            error!(target: "contract", "Failed to verify transaction");
             false
         }
     }
@@ -527,13 +529,13 @@ impl contracts::NativeContract for Diem {
 
         match cmd {
             Command::AccountInfo { account_info_b64 } => {
-                info!("command account_info_b64:{:?}", account_info_b64);
+                info!(target: "contract", "command account_info_b64:{:?}", account_info_b64);
                 if let Ok(account_data) = base64::decode(&account_info_b64) {
                     let account_info: AccountInfo = match bcs::from_bytes(&account_data) {
                         Ok(result) => result,
                         Err(_) => return Err(TransactionError::BadAccountInfo),
                     };
-                    info!("account_info:{:?}", account_info);
+                    info!(target: "contract", "account_info:{:?}", account_info);
                     let exist = self
                         .account_info
                         .iter()
@@ -541,7 +543,7 @@ impl contracts::NativeContract for Diem {
                     if !exist {
                         self.account_info.push(account_info);
                     }
-                    info!("add account_ok");
+                    info!(target: "contract", "add account_ok");
                     Ok(())
                 } else {
                     Err(TransactionError::BadAccountInfo)
@@ -552,6 +554,7 @@ impl contracts::NativeContract for Diem {
                 chain_id,
             } => {
                 info!(
+                    target: "contract",
                     "trusted_state_b64: {:?}, chain_id: {:}",
                     trusted_state_b64, chain_id
                 );
@@ -562,7 +565,7 @@ impl contracts::NativeContract for Diem {
                 if self.chain_id == CHAIN_ID_UNINITIALIZED {
                     self.chain_id = chain_id;
                 } else if chain_id != self.chain_id {
-                    info!("Unexpected chain id, chain_id was not changed.")
+                    info!(target: "contract", "Unexpected chain id, chain_id was not changed.")
                 }
 
                 // Only initialize TrustedState once
@@ -573,7 +576,7 @@ impl contracts::NativeContract for Diem {
                     Ok(trusted_state) => {
                         self.init_trusted_state = Some(trusted_state.clone());
                         self.trusted_state = Some(trusted_state);
-                        info!("init trusted state OK");
+                        info!(target: "contract", "init trusted state OK");
                         Ok(())
                     }
                     Err(code) => code,
@@ -584,10 +587,11 @@ impl contracts::NativeContract for Diem {
                 epoch_change_proof_b64,
             } => {
                 info!(
+                    target: "contract",
                     "ledger_info_with_signatures_b64: {:?}",
                     ledger_info_with_signatures_b64
                 );
-                info!("epoch_change_proof_b64: {:?}", epoch_change_proof_b64);
+                info!(target: "contract", "epoch_change_proof_b64: {:?}", epoch_change_proof_b64);
                 let ledger_info_with_signatures_data =
                     base64::decode(ledger_info_with_signatures_b64)
                         .or(Err(Err(TransactionError::BadTrustedStateData)))
@@ -603,6 +607,7 @@ impl contracts::NativeContract for Diem {
                         .expect("Unable to parse epoch changed proof data");
 
                 info!(
+                    target: "contract",
                     "ledger_info_with_signatures: {:?}",
                     ledger_info_with_signatures
                 );
@@ -624,6 +629,7 @@ impl contracts::NativeContract for Diem {
                 transaction_with_proof_b64,
             } => {
                 info!(
+                    target: "contract",
                     "transaction_with_proof_b64: {:?}",
                     transaction_with_proof_b64
                 );
@@ -632,7 +638,7 @@ impl contracts::NativeContract for Diem {
                     AccountAddress::from_hex_literal(&("0x".to_string() + &account_address))
                 {
                     if !self.account_info.iter().any(|x| x.address == address) {
-                        error!("not a contract's account address");
+                        error!(target: "contract", "not a contract's account address");
 
                         return Err(TransactionError::InvalidAccount);
                     }
@@ -644,7 +650,11 @@ impl contracts::NativeContract for Diem {
                             Ok(result) => result,
                             Err(_) => return Err(TransactionError::BadTransactionWithProof),
                         };
-                    info!("transaction_with_proof:{:?}", transaction_with_proof);
+                    info!(
+                        target: "contract",
+                        "transaction_with_proof:{:?}",
+                        transaction_with_proof
+                    );
 
                     let transaction = match self.get_transaction(
                         transaction_with_proof.clone(),
@@ -670,7 +680,11 @@ impl contracts::NativeContract for Diem {
                     if self.verified.get(&tx_hash).is_some()
                         && self.verified.get(&tx_hash).unwrap() == &true
                     {
-                        info!("transaction has been verified:{:}", self.verified.len());
+                        info!(
+                            target: "contract",
+                            "transaction has been verified:{:}",
+                            self.verified.len()
+                        );
                         return Ok(());
                     }
 
@@ -680,7 +694,7 @@ impl contracts::NativeContract for Diem {
                             .transaction_hash()
                             .to_hex()
                     {
-                        error!("transaction hash doesn't match");
+                        error!(target: "contract", "transaction hash doesn't match");
                         return Err(TransactionError::FailedToVerify);
                     }
 
@@ -689,7 +703,11 @@ impl contracts::NativeContract for Diem {
                             self.verify_transaction_state_proof(transaction_with_proof, address);
                         self.verified.insert(tx_hash, verified);
                         if verified {
-                            info!("transaction was verified:{:}", self.verified.len());
+                            info!(
+                                target: "contract",
+                                "transaction was verified:{:}",
+                                self.verified.len()
+                            );
 
                             if let Ok(_) =
                                 self.maybe_update_balance(&transaction, account_address, address)
@@ -710,12 +728,17 @@ impl contracts::NativeContract for Diem {
             }
             Command::NewAccount { seq_number } => {
                 let o = origin.account()?;
-                info!("NewAccount {:}, seq_number:{:}", o.to_string(), seq_number);
+                info!(
+                    target: "contract",
+                    "NewAccount {:}, seq_number:{:}",
+                    o.to_string(),
+                    seq_number
+                );
 
                 let alice =
                     AccountId::from_hex(ALICE_PHALA).expect("Bad init master account");
                 if o == alice {
-                    error!("Alice can't execute NewAccount command");
+                    error!(target: "contract", "Alice can't execute NewAccount command");
                     return Err(TransactionError::InvalidAccount);
                 }
 
@@ -733,7 +756,7 @@ impl contracts::NativeContract for Diem {
                 let auth_key = AuthenticationKey::ed25519(&keypair.public_key).to_vec();
                 let receiver_address =
                     AuthenticationKey::ed25519(&keypair.public_key).derived_address();
-                info!("new child address:{:?}", receiver_address);
+                info!(target: "contract", "new child address:{:?}", receiver_address);
                 let receiver_auth_key_prefix = auth_key_prefix(auth_key);
 
                 let script = transaction_builder::encode_create_child_vasp_account_script(
@@ -756,7 +779,7 @@ impl contracts::NativeContract for Diem {
                     ChainId::new(self.chain_id),
                 )
                 .expect("User signed transaction");
-                info!("tx:{:?}", txn);
+                info!(target: "contract", "tx:{:?}", txn);
 
                 let transaction_data = TransactionData {
                     sequence: self.queue_seq,
@@ -786,6 +809,7 @@ impl contracts::NativeContract for Diem {
             Command::TransferXUS { to, amount } => {
                 let o = origin.account()?;
                 info!(
+                    target: "contract",
                     "TransferXUS from: {:}, to: {:}, amount: {:}",
                     o.to_string(),
                     to,
@@ -794,11 +818,11 @@ impl contracts::NativeContract for Diem {
 
                 if let Some(sender_account) = self.accounts.get_mut(&o) {
                     if !sender_account.is_child {
-                        error!("Not Allowed");
+                        error!(target: "contract", "Not Allowed");
                         return Err(TransactionError::TransferringNotAllowed);
                     }
                     if sender_account.free < amount {
-                        error!("InsufficientBalance");
+                        error!(target: "contract", "InsufficientBalance");
                         return Err(TransactionError::InsufficientBalance);
                     }
 
@@ -811,7 +835,7 @@ impl contracts::NativeContract for Diem {
                         if let Some(item) = pending_transactions.into_iter().find(|x| {
                             x.lock_time + TX_EXPIRATION as u64 * 1000000 < timestamp_usecs
                         }) {
-                            info!("tx timeout");
+                            info!(target: "contract", "tx timeout");
                             for pt in pending_transactions {
                                 if pt.sequence >= item.sequence {
                                     sender_account.free += pt.amount;
@@ -837,7 +861,7 @@ impl contracts::NativeContract for Diem {
                         AccountAddress::from_hex_literal(&("0x".to_string() + &to.to_string()))
                     {
                         if sender_account.address == receiver {
-                            error!("Can't fund yourself");
+                            error!(target: "contract", "Can't fund yourself");
                             return Err(TransactionError::InvalidAccount);
                         }
 
@@ -867,7 +891,7 @@ impl contracts::NativeContract for Diem {
                             ChainId::new(self.chain_id),
                         )
                         .expect("User signed transaction");
-                        info!("tx:{:?}", txn);
+                        info!(target: "contract", "tx:{:?}", txn);
 
                         let transaction_data = TransactionData {
                             sequence: self.queue_seq,
@@ -894,8 +918,12 @@ impl contracts::NativeContract for Diem {
                         sender_account.locked += amount;
                         sender_account.free -= amount;
 
-                        info!("pending_transactions:{:?}", self.pending_transactions);
-                        info!("accounts:{:?}", self.accounts);
+                        info!(
+                            target: "contract",
+                            "pending_transactions:{:?}",
+                            self.pending_transactions
+                        );
+                        info!(target: "contract", "accounts:{:?}", self.accounts);
 
                         Ok(())
                     } else {
@@ -916,7 +944,7 @@ impl contracts::NativeContract for Diem {
                     Ok(Response::VerifiedTransactions { hash })
                 }
                 Request::GetSignedTransactions { start } => {
-                    info!("GetSignedTransactions: {:}", start);
+                    info!(target: "contract", "GetSignedTransactions: {:}", start);
                     let queue: Vec<&TransactionData> = self
                         .tx_queue
                         .iter()
@@ -18,6 +18,10 @@ type Command = GeolocationCommand;
 
 const GEOCODING_EXPIRED_BLOCKNUM: u32 = 2400; // roughly 8 hours
 
+/// The min number of workers in a region to disclose any statistics of it, so that the reports of
+/// individual workers can not be singled out.
+const MIN_REGION_SIZE: usize = 5;
+
 #[derive(Encode, Decode, Debug, Clone)]
 pub struct GeocodingWithBlockInfo {
     data: Geocoding,
@@ -45,6 +49,7 @@ pub enum Request {
     GetAvailableRegionName,
     GetAccountsInRegion { region_name: String },
     GetAccountCountInRegion { region_name: String },
+    GetRegionStats,
 }
 
 #[derive(Encode, Decode, Debug, Clone)]
@@ -54,6 +59,13 @@ pub enum Response {
     GetAccountsInRegion { workers: Vec<AccountId> },
     GetAccountCountInRegion { count: u32 },
     Error(String),
+    GetRegionStats { regions: Vec<RegionStats> },
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct RegionStats {
+    pub region_name: String,
+    pub worker_count: u32,
 }
 
 impl Geolocation {
@@ -66,8 +78,8 @@ impl Geolocation {
 
     pub fn guard(&mut self, current_blocknum: &chain::BlockNumber) {
         // purging expired geo_data
-        self.geo_data
-            .retain(|_, v| v.created_at > current_blocknum - GEOCODING_EXPIRED_BLOCKNUM);
+        let expired_at = current_blocknum.saturating_sub(GEOCODING_EXPIRED_BLOCKNUM);
+        self.geo_data.retain(|_, v| v.created_at > expired_at);
 
         self.region_map.clear();
         // building region map
@@ -79,6 +91,54 @@ impl Geolocation {
             workers.push(k.clone());
         }
     }
+
+    /// The regions with enough workers to be disclosed.
+    fn disclosed_regions(&self) -> impl Iterator<Item = (&String, &Vec<AccountId>)> {
+        self.region_map
+            .iter()
+            .filter(|(_, workers)| workers.len() >= MIN_REGION_SIZE)
+    }
+
+    fn query(&self, req: Request) -> Result<Response, Error> {
+        match req {
+            // The individual reports are never disclosed.
+            Request::GetGeocoding { account: _ } => Err(Error::NotAuthorized),
+            Request::GetAvailableRegionName {} => {
+                let region_names: Vec<String> =
+                    self.disclosed_regions().map(|(name, _)| name.clone()).collect();
+                Ok(Response::GetAvailableRegionName { region_names })
+            }
+            Request::GetAccountsInRegion { region_name: _ } => {
+                // TODO(soptq): Authorization
+                Err(Error::Unimplemented)
+                // if let Some(workers) = self.city_distribution.get(&region_name) {
+                //     Ok(Response::GetCityDistribution { workers: workers.clone() })
+                // } else {
+                //     error!(target: "contract", "Unavailable city name provided");
+                //     Err(anyhow::Error::msg(Error::InvalidRequest))
+                // }
+            }
+            Request::GetAccountCountInRegion { region_name } => {
+                let workers = self
+                    .region_map
+                    .get(&region_name)
+                    .filter(|workers| workers.len() >= MIN_REGION_SIZE)
+                    .ok_or(Error::UnavailableCityName)?;
+                let count = u32::try_from(workers.len()).unwrap_or(u32::MAX);
+                Ok(Response::GetAccountCountInRegion { count })
+            }
+            Request::GetRegionStats => {
+                let regions = self
+                    .disclosed_regions()
+                    .map(|(name, workers)| RegionStats {
+                        region_name: name.clone(),
+                        worker_count: u32::try_from(workers.len()).unwrap_or(u32::MAX),
+                    })
+                    .collect();
+                Ok(Response::GetRegionStats { regions })
+            }
+        }
+    }
 }
 
 impl contracts::NativeContract for Geolocation {
@@ -127,42 +187,11 @@ impl contracts::NativeContract for Geolocation {
 
     fn handle_query(
         &self,
-        origin: Option<&chain::AccountId>,
+        _origin: Option<&chain::AccountId>,
         req: Request,
         _: &mut contracts::QueryContext,
     ) -> Result<Response, Error> {
-        match req {
-            Request::GetGeocoding { account } => {
-                if origin != Some(&account) {
-                    return Err(Error::NotAuthorized);
-                }
-                let geo_data = self.geo_data.get(&account).ok_or(Error::NoRecord)?;
-                let geocoding = geo_data.data.clone();
-                Ok(Response::GetGeocoding { geocoding })
-            }
-            Request::GetAvailableRegionName {} => {
-                let region_names: Vec<String> = self.region_map.keys().cloned().collect();
-                Ok(Response::GetAvailableRegionName { region_names })
-            }
-            Request::GetAccountsInRegion { region_name: _ } => {
-                // TODO(soptq): Authorization
-                Err(Error::Unimplemented)
-                // if let Some(workers) = self.city_distribution.get(&region_name) {
-                //     Ok(Response::GetCityDistribution { workers: workers.clone() })
-                // } else {
-                //     error!("Unavailable city name provided");
-                //     Err(anyhow::Error::msg(Error::InvalidRequest))
-                // }
-            }
-            Request::GetAccountCountInRegion { region_name } => {
-                let workers = self
-                    .region_map
-                    .get(&region_name)
-                    .ok_or(Error::UnavailableCityName)?;
-                let count = u32::try_from(workers.len()).unwrap_or(u32::MAX);
-                Ok(Response::GetAccountCountInRegion { count })
-            }
-        }
+        self.query(req)
     }
 
     fn on_block_end(&mut self, context: &mut NativeContext) -> TransactionResult {
@@ -175,3 +204,87 @@ impl contracts::NativeContract for Geolocation {
         self.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(geolocation: &mut Geolocation, worker: u8, region_name: &str, created_at: u32) {
+        let data = Geocoding {
+            latitude: 0,
+            longitude: 0,
+            region_name: region_name.into(),
+        };
+        let report = GeocodingWithBlockInfo { data, created_at };
+        geolocation.geo_data.insert(AccountId::from([worker; 32]), report);
+    }
+
+    fn count_in(geolocation: &Geolocation, region_name: &str) -> Option<u32> {
+        let req = Request::GetAccountCountInRegion {
+            region_name: region_name.into(),
+        };
+        match geolocation.query(req) {
+            Ok(Response::GetAccountCountInRegion { count }) => Some(count),
+            Err(Error::UnavailableCityName) => None,
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    fn stats(geolocation: &Geolocation) -> Vec<(String, u32)> {
+        match geolocation.query(Request::GetRegionStats) {
+            Ok(Response::GetRegionStats { regions }) => regions
+                .into_iter()
+                .map(|stats| (stats.region_name, stats.worker_count))
+                .collect(),
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn small_regions_are_not_disclosed() {
+        let mut geolocation = Geolocation::new();
+        for worker in 0..MIN_REGION_SIZE as u8 {
+            report(&mut geolocation, worker, "large", 1);
+        }
+        for worker in 0..MIN_REGION_SIZE as u8 - 1 {
+            report(&mut geolocation, 0x80 + worker, "small", 1);
+        }
+        geolocation.guard(&1);
+
+        assert_eq!(count_in(&geolocation, "large"), Some(MIN_REGION_SIZE as u32));
+        assert_eq!(count_in(&geolocation, "small"), None);
+        assert_eq!(count_in(&geolocation, "unknown"), None);
+        assert_eq!(stats(&geolocation), vec![("large".into(), MIN_REGION_SIZE as u32)]);
+        let region_names = match geolocation.query(Request::GetAvailableRegionName) {
+            Ok(Response::GetAvailableRegionName { region_names }) => region_names,
+            other => panic!("unexpected response: {:?}", other),
+        };
+        assert_eq!(region_names, vec!["large".to_string()]);
+        let req = Request::GetGeocoding {
+            account: AccountId::from([0; 32]),
+        };
+        assert!(matches!(geolocation.query(req), Err(Error::NotAuthorized)));
+
+        // One more worker discloses the region
+        report(&mut geolocation, 0xff, "small", 1);
+        geolocation.guard(&1);
+        assert_eq!(count_in(&geolocation, "small"), Some(MIN_REGION_SIZE as u32));
+    }
+
+    #[test]
+    fn expired_reports_are_not_counted() {
+        let mut geolocation = Geolocation::new();
+        for worker in 0..MIN_REGION_SIZE as u8 {
+            report(&mut geolocation, worker, "region", 1);
+        }
+        report(&mut geolocation, 0xff, "region", 100);
+        geolocation.guard(&GEOCODING_EXPIRED_BLOCKNUM);
+        assert_eq!(count_in(&geolocation, "region"), Some(MIN_REGION_SIZE as u32 + 1));
+
+        // All but the latest report expire, leaving too few workers in the region
+        geolocation.guard(&(GEOCODING_EXPIRED_BLOCKNUM + 1));
+        assert_eq!(geolocation.geo_data.len(), 1);
+        assert_eq!(count_in(&geolocation, "region"), None);
+        assert!(stats(&geolocation).is_empty());
+    }
+}
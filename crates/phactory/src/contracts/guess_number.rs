@@ -122,7 +122,7 @@ impl contracts::NativeContract for GuessNumber {
         cmd: Command,
         context: &mut NativeContext,
     ) -> TransactionResult {
-        info!("Command received: {:?}", &cmd);
+        info!(target: "contract", "Command received: {:?}", &cmd);
 
         // we want to limit the sender who can use the Commands to the pre-define root account
         let sender = match &origin {
@@ -162,7 +162,7 @@ impl contracts::NativeContract for GuessNumber {
         req: Request,
         _context: &mut contracts::QueryContext,
     ) -> Result<Response, Error> {
-        info!("Query received: {:?}", &req);
+        info!(target: "contract", "Query received: {:?}", &req);
         match req {
             Request::QueryOwner => Ok(Response::Owner(self.owner.clone())),
             Request::Guess { guess_number } => {
@@ -9,12 +9,16 @@ use chain::AccountId;
 use parity_scale_codec::{Decode, Encode};
 use phala_mq::{MessageOrigin, SignedMessageChannel};
 
+pub mod account;
 pub mod assets;
 pub mod balances;
+pub mod ballot;
 pub mod btc_lottery;
 // pub mod diem;
 pub mod geolocation;
 pub mod pink;
+pub mod price_oracle;
+pub mod vault;
 // pub mod substrate_kitties;
 
 // Disabled due to requiring &mut self in query
@@ -24,6 +28,7 @@ pub mod pink;
 pub mod btc_price_bot;
 pub mod guess_number;
 
+pub use account::AccountIdWrapper;
 pub use phala_types::contract::*;
 
 fn account_id_from_hex(s: &str) -> Result<AccountId> {
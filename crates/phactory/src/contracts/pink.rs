@@ -1,9 +1,15 @@
 use crate::contracts;
 use crate::system::{TransactionError, TransactionResult};
 use anyhow::{anyhow, Result};
+use core::convert::TryInto;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
 use parity_scale_codec::{Decode, Encode};
 use phala_mq::{ContractClusterId, MessageOrigin, ContractId};
 use pink::runtime::ExecSideEffects;
+use sidevm::service::StorageError;
 use runtime::{AccountId, BlockNumber, Hash};
 
 use super::contract_address_to_id;
@@ -81,6 +87,41 @@ impl Pink {
     pub fn set_on_block_end_selector(&mut self, selector: u32) {
         self.instance.set_on_block_end_selector(selector)
     }
+
+    /// Read-only access to the contract storage in a cluster storage snapshot, for the sidevm
+    /// instance of the contract. Any read sets `reader`, see `SidevmStorage`.
+    pub fn sidevm_storage(
+        &self,
+        snapshot: Option<Arc<Mutex<pink::Storage>>>,
+        reader: Arc<AtomicBool>,
+    ) -> SidevmStorage {
+        SidevmStorage {
+            instance: self.instance.clone(),
+            snapshot,
+            reader,
+        }
+    }
+}
+
+/// The contract storage read by a sidevm instance.
+///
+/// A snapshot is a deep copy of the cluster storage, so it is only taken for the instances which
+/// read the storage. An instance starts without snapshot, and its first read marks it as a reader
+/// to be handed a snapshot after each block from then on.
+pub struct SidevmStorage {
+    instance: pink::Contract,
+    snapshot: Option<Arc<Mutex<pink::Storage>>>,
+    reader: Arc<AtomicBool>,
+}
+
+impl sidevm::service::ContractStorage for SidevmStorage {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        self.reader.store(true, Ordering::Relaxed);
+        let key = key.try_into().or(Err(StorageError::InvalidKey))?;
+        let snapshot = self.snapshot.as_ref().ok_or(StorageError::NotAvailable)?;
+        let mut storage = snapshot.lock().unwrap();
+        Ok(self.instance.get_storage(&mut storage, key))
+    }
 }
 
 impl contracts::NativeContract for Pink {
@@ -100,17 +141,30 @@ impl contracts::NativeContract for Pink {
         match req {
             Query::InkMessage(input_data) => {
                 let storage = &mut context.storage;
-
-                let (ink_result, _effects) = self.instance.bare_call(
-                    storage,
-                    origin.clone(),
-                    input_data,
-                    true,
-                    context.block_number,
-                    context.now_ms,
-                );
+                let (block_number, now_ms) = (context.block_number, context.now_ms);
+
+                let sidevm_query = context.sidevm_query.clone();
+                let native_query = context.native_query.clone();
+                let (ink_result, _effects) =
+                    pink::runtime::using_sidevm_query_handler(sidevm_query, || {
+                        pink::runtime::using_native_query_handler(native_query, || {
+                            self.instance.bare_call(
+                                storage,
+                                origin.clone(),
+                                input_data,
+                                true,
+                                block_number,
+                                now_ms,
+                            )
+                        })
+                    });
                 if ink_result.result.is_err() {
-                    log::error!("Pink [{:?}] query exec error: {:?}", self.id(), ink_result);
+                    log::error!(
+                        target: "contract",
+                        "Pink [{:?}] query exec error: {:?}",
+                        self.id(),
+                        ink_result
+                    );
                 }
                 return Ok(Response::InkMessageReturn(ink_result.encode()));
             }
@@ -143,7 +197,12 @@ impl contracts::NativeContract for Pink {
                 );
 
                 let ret = pink::transpose_contract_result(&result).map_err(|err| {
-                    log::error!("Pink [{:?}] command exec error: {:?}", self.id(), err);
+                    log::error!(
+                        target: "contract",
+                        "Pink [{:?}] command exec error: {:?}",
+                        self.id(),
+                        err
+                    );
                     TransactionError::Other(format!("Call contract method failed: {:?}", err))
                 })?;
 
@@ -161,7 +220,12 @@ impl contracts::NativeContract for Pink {
             .instance
             .on_block_end(storage, context.block.block_number, context.block.now_ms)
             .map_err(|err| {
-                log::error!("Pink [{:?}] on_block_end exec error: {:?}", self.id(), err);
+                log::error!(
+                    target: "contract",
+                    "Pink [{:?}] on_block_end exec error: {:?}",
+                    self.id(),
+                    err
+                );
                 TransactionError::Other(format!("Call contract on_block_end failed: {:?}", err))
             })?;
         Ok(effects)
@@ -237,10 +301,26 @@ pub mod cluster {
             Some(&mut self.clusters.get_mut(cluster_id)?.storage)
         }
 
+        pub fn get_cluster(&self, cluster_id: &ContractClusterId) -> Option<&Cluster> {
+            self.clusters.get(cluster_id)
+        }
+
         pub fn get_cluster_mut(&mut self, cluster_id: &ContractClusterId) -> Option<&mut Cluster> {
             self.clusters.get_mut(cluster_id)
         }
 
+        pub fn remove_cluster(&mut self, cluster_id: &ContractClusterId) -> Option<Cluster> {
+            self.clusters.remove(cluster_id)
+        }
+
+        /// The storage roots of all the clusters
+        pub fn storage_roots(&self) -> Vec<(ContractClusterId, Hash)> {
+            self.clusters
+                .iter()
+                .map(|(id, cluster)| (*id, cluster.storage.root()))
+                .collect()
+        }
+
         pub fn get_cluster_or_default_mut(
             &mut self,
             cluster_id: &ContractClusterId,
@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::string::String;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::info;
+use parity_scale_codec::{Decode, Encode};
+use phala_mq::{ContractId, MessageOrigin};
+use sidevm::VmId;
+use sp_core::{sr25519, Pair as _};
+
+use super::{TransactionError, TransactionResult};
+use crate::contracts;
+use crate::contracts::{AccountId, NativeContext};
+extern crate runtime as chain;
+
+use phala_types::messaging::{PriceOracleCommand, PriceReport, U64F64Bits};
+
+type Command = PriceOracleCommand;
+
+/// The prices received earlier than this are not used.
+const MAX_PRICE_AGE_MS: u64 = 5 * 60 * 1000;
+/// The max number of (pair, source) prices kept for a feeder.
+const MAX_PRICES_PER_FEEDER: usize = 256;
+/// Prefixed to the signed attestations, so the worker identity key never signs a bare message
+/// chosen by the price feeds.
+pub const ATTESTATION_CONTEXT: &[u8] = b"phala/price_oracle/attestation:";
+
+#[derive(Debug, Clone, Copy)]
+struct ReceivedPrice {
+    price: U64F64Bits,
+    received_at_ms: u64,
+}
+
+type Feed = BTreeMap<(String, String), ReceivedPrice>;
+
+/// The latest prices reported by the sidevm instances, keyed by the (pair, source).
+///
+/// The reports only reach the local worker, so they are kept by the `System` out of the contract
+/// state, which must be the same on all the workers, and handed to the queries in the
+/// `QueryContext`.
+#[derive(Default)]
+pub struct PriceFeeds(Mutex<BTreeMap<VmId, Feed>>);
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl PriceFeeds {
+    /// Accepts a message emitted by a sidevm instance if it is a `PriceReport`.
+    pub(crate) fn accept_report(&self, vm_id: VmId, mut message: &[u8]) {
+        let report = match PriceReport::decode(&mut message) {
+            Ok(report) => report,
+            Err(_) => return,
+        };
+        let received_at_ms = now_ms();
+        let mut feeds = self.0.lock().unwrap();
+        let feed = feeds.entry(vm_id).or_default();
+        for item in report.prices {
+            let key = (item.pair, item.source);
+            if feed.len() >= MAX_PRICES_PER_FEEDER && !feed.contains_key(&key) {
+                continue;
+            }
+            let price = ReceivedPrice {
+                price: item.price,
+                received_at_ms,
+            };
+            feed.insert(key, price);
+        }
+    }
+
+    /// Drops the prices reported by a terminated sidevm instance.
+    pub(crate) fn remove_feeder(&self, vm_id: &VmId) {
+        self.0.lock().unwrap().remove(vm_id);
+    }
+
+    /// The prices of `pair` reported by `feeder`, received no earlier than `deadline`.
+    fn fresh_prices(&self, feeder: &VmId, pair: &str, deadline: u64) -> Vec<ReceivedPrice> {
+        let feeds = self.0.lock().unwrap();
+        feeds
+            .get(feeder)
+            .into_iter()
+            .flatten()
+            .filter(|((p, _), price)| p == pair && price.received_at_ms >= deadline)
+            .map(|(_, price)| *price)
+            .collect()
+    }
+}
+
+/// A price oracle medianizing the prices fetched from multiple exchanges.
+///
+/// The prices are fetched by the sidevm instance of the feeder pink contract set by the owner,
+/// and the queries are answered with the attestations signed by the worker.
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct PriceOracle {
+    owner: AccountId,
+    feeder: Option<ContractId>,
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct PriceAttestation {
+    /// The contract whose sidevm instance reported the prices.
+    pub feeder: ContractId,
+    pub pair: String,
+    /// The median of the fresh prices from all the sources.
+    pub price: U64F64Bits,
+    /// The number of sources with a fresh price.
+    pub sources: u32,
+    /// When the oldest price in use was received, in milliseconds since the UNIX epoch.
+    pub updated_at_ms: u64,
+    /// The latest block processed by the worker.
+    pub block_number: chain::BlockNumber,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub enum Error {
+    NoFeeder,
+    NoFreshPrice,
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub enum Request {
+    QueryOwner,
+    QueryFeeder,
+    GetPrice { pair: String },
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub enum Response {
+    Owner(AccountId),
+    Feeder(Option<ContractId>),
+    Price {
+        attestation: PriceAttestation,
+        /// The worker signing the attestation.
+        worker: sr25519::Public,
+        /// The signature of `ATTESTATION_CONTEXT` followed by the encoded attestation.
+        signature: sr25519::Signature,
+    },
+}
+
+impl PriceOracle {
+    pub fn new(owner: AccountId) -> Self {
+        PriceOracle {
+            owner,
+            feeder: None,
+        }
+    }
+
+    fn attest(
+        &self,
+        pair: String,
+        feeds: &PriceFeeds,
+        block_number: chain::BlockNumber,
+    ) -> Result<PriceAttestation, Error> {
+        let feeder = self.feeder.ok_or(Error::NoFeeder)?;
+        let deadline = now_ms().saturating_sub(MAX_PRICE_AGE_MS);
+        let mut prices = feeds.fresh_prices(&feeder.0, &pair, deadline);
+        if prices.is_empty() {
+            return Err(Error::NoFreshPrice);
+        }
+        prices.sort_by_key(|price| price.price);
+        let updated_at_ms = prices
+            .iter()
+            .map(|price| price.received_at_ms)
+            .min()
+            .unwrap_or_default();
+        Ok(PriceAttestation {
+            feeder,
+            pair,
+            // The lower median for an even number of sources.
+            price: prices[(prices.len() - 1) / 2].price,
+            sources: prices.len() as u32,
+            updated_at_ms,
+            block_number,
+        })
+    }
+}
+
+impl contracts::NativeContract for PriceOracle {
+    type Cmd = Command;
+    type QReq = Request;
+    type QResp = Result<Response, Error>;
+
+    fn handle_command(
+        &mut self,
+        origin: MessageOrigin,
+        cmd: Command,
+        _context: &mut NativeContext,
+    ) -> TransactionResult {
+        if origin.account()? != self.owner {
+            return Err(TransactionError::BadOrigin);
+        }
+        match cmd {
+            Command::SetOwner { owner } => {
+                self.owner = AccountId::from(*owner.as_fixed_bytes());
+            }
+            Command::SetFeeder { contract } => {
+                info!(target: "contract", "PriceOracle: set feeder to {:?}", contract);
+                self.feeder = Some(contract);
+            }
+        }
+        Ok(Default::default())
+    }
+
+    fn handle_query(
+        &self,
+        _origin: Option<&chain::AccountId>,
+        req: Request,
+        context: &mut contracts::QueryContext,
+    ) -> Result<Response, Error> {
+        match req {
+            Request::QueryOwner => Ok(Response::Owner(self.owner.clone())),
+            Request::QueryFeeder => Ok(Response::Feeder(self.feeder)),
+            Request::GetPrice { pair } => {
+                let attestation = self.attest(pair, &context.price_feeds, context.block_number)?;
+                let message = [ATTESTATION_CONTEXT, &attestation.encode()].concat();
+                let signature = context.identity_key.sign(&message);
+                Ok(Response::Price {
+                    attestation,
+                    worker: context.identity_key.public(),
+                    signature,
+                })
+            }
+        }
+    }
+
+    fn snapshot(&self) -> Self {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use phala_types::messaging::SourcePrice;
+
+    fn report(prices: &[(&str, &str, U64F64Bits)]) -> Vec<u8> {
+        let prices = prices
+            .iter()
+            .map(|(pair, source, price)| SourcePrice {
+                pair: pair.to_string(),
+                source: source.to_string(),
+                price: *price,
+            })
+            .collect();
+        PriceReport { prices }.encode()
+    }
+
+    #[test]
+    fn feeds_keep_the_reports_by_feeder() {
+        let feeds = PriceFeeds::default();
+        let (feeder, other) = ([1u8; 32], [2u8; 32]);
+        feeds.accept_report(feeder, &report(&[("BTC/USD", "a", 3), ("ETH/USD", "a", 2)]));
+        feeds.accept_report(feeder, &report(&[("BTC/USD", "b", 5)]));
+        feeds.accept_report(other, &report(&[("BTC/USD", "a", 7)]));
+        feeds.accept_report(feeder, b"not a report");
+
+        let mut prices: Vec<_> = feeds
+            .fresh_prices(&feeder, "BTC/USD", 0)
+            .into_iter()
+            .map(|price| price.price)
+            .collect();
+        prices.sort();
+        assert_eq!(prices, vec![3, 5]);
+        assert!(feeds.fresh_prices(&feeder, "BTC/USD", u64::MAX).is_empty());
+
+        feeds.remove_feeder(&feeder);
+        assert!(feeds.fresh_prices(&feeder, "BTC/USD", 0).is_empty());
+        assert_eq!(feeds.fresh_prices(&other, "BTC/USD", 0).len(), 1);
+    }
+}
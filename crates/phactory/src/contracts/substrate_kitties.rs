@@ -271,7 +271,11 @@ impl contracts::NativeContract for SubstrateKitties {
             }
             Command::Created(dest, kitty_id) => {
                 if !origin.is_pallet() {
-                    error!("Received event from unexpected origin: {:?}", origin);
+                    error!(
+                        target: "contract",
+                        "Received event from unexpected origin: {:?}",
+                        origin
+                    );
                     return Err(TransactionError::BadOrigin);
                 }
                 println!("Created Kitty {:?} by default owner: Kitty!!!", kitty_id);
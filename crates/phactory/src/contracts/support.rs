@@ -1,4 +1,8 @@
-use std::sync::{Arc, Mutex};
+use std::collections::BTreeMap;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
 
 use phala_crypto::ecdh::EcdhPublicKey;
 use phala_mq::traits::MessageChannel;
@@ -12,6 +16,10 @@ use crate::secret_channel::SecretReceiver;
 use crate::types::BlockInfo;
 use anyhow::{anyhow, bail};
 use phala_serde_more as more;
+use phala_types::contract::{messaging::ContractCommandFailure, ContractUsage};
+
+/// Counts the succeeded signed queries by their origins.
+pub type QueryCounter = Arc<Mutex<BTreeMap<AccountId, u64>>>;
 
 pub struct ExecuteEnv<'a, 'b> {
     pub block: &'a mut BlockInfo<'b>,
@@ -30,6 +38,15 @@ pub struct QueryContext {
     pub block_number: BlockNumber,
     pub now_ms: u64,
     pub storage: ::pink::Storage,
+    /// The identity key of the worker, to sign the query results.
+    pub identity_key: sp_core::sr25519::Pair,
+    /// Routes the `sidevm_query` calls of the pink contracts to their sidevm instances.
+    pub sidevm_query: Arc<dyn ::pink::runtime::SidevmQueryHandler>,
+    /// Routes the `native_query` calls of the pink contracts to the native contracts in their
+    /// cluster.
+    pub native_query: Arc<dyn ::pink::runtime::NativeQueryHandler>,
+    /// The prices reported by the sidevm instances, for the price oracles.
+    pub price_feeds: Arc<super::price_oracle::PriceFeeds>,
 }
 
 impl NativeContext<'_, '_> {
@@ -131,8 +148,16 @@ impl Default for SidevmHandle {
 struct SidevmInfo {
     code: Vec<u8>,
     memory_pages: u32,
+    #[serde(default)]
+    grpc_targets: Vec<String>,
+    /// The startup config given by the contract
+    #[serde(default)]
+    config: Vec<u8>,
     #[serde(skip, default)]
     handle: Arc<Mutex<SidevmHandle>>,
+    /// Set once the instance reads the contract storage, see `pink::SidevmStorage`
+    #[serde(skip, default)]
+    storage_reader: Arc<AtomicBool>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -146,6 +171,16 @@ pub struct FatContract {
     cluster_id: phala_mq::ContractClusterId,
     contract_id: phala_mq::ContractId,
     sidevm_info: Option<SidevmInfo>,
+    /// The usage since the last report
+    #[serde(default)]
+    usage: ContractUsage,
+    /// The succeeded signed queries since the last report, counted by the queries running outside
+    /// of the Phactory lock. Not checkpointed.
+    #[serde(skip, default)]
+    query_origins: QueryCounter,
+    /// The number of the native commands the contract has called
+    #[serde(default)]
+    native_command_nonce: u64,
 }
 
 impl FatContract {
@@ -165,6 +200,9 @@ impl FatContract {
             cluster_id,
             contract_id,
             sidevm_info: None,
+            usage: Default::default(),
+            query_origins: Default::default(),
+            native_command_nonce: 0,
         }
     }
 
@@ -176,6 +214,38 @@ impl FatContract {
         self.cluster_id
     }
 
+    pub(crate) fn is_pink(&self) -> bool {
+        matches!(self.contract, AnyContract::Pink(_))
+    }
+
+    /// The hash of the encoded contract state
+    pub(crate) fn state_digest(&self) -> sp_core::H256 {
+        sp_core::hashing::blake2_256(&self.contract.encode()).into()
+    }
+
+    /// Takes the id of the next native command called by the contract, which identifies its
+    /// failure report in place of the hash of the command, since the command is never on chain
+    /// and the hash of a guessable one could be brute-forced.
+    pub(crate) fn next_native_command_id(&mut self) -> sp_core::H256 {
+        let nonce = self.native_command_nonce;
+        self.native_command_nonce += 1;
+        sp_core::hashing::blake2_256(&(self.contract_id, nonce).encode()).into()
+    }
+
+    /// The counter a signed query should increase once it succeeds.
+    pub(crate) fn query_counter(&self) -> QueryCounter {
+        self.query_origins.clone()
+    }
+
+    /// Takes the usage since the last call, along with the number of the signed queries by their
+    /// origins.
+    pub(crate) fn take_usage(&mut self) -> (ContractUsage, BTreeMap<AccountId, u64>) {
+        let mut usage = std::mem::take(&mut self.usage);
+        let query_origins = std::mem::take(&mut *self.query_origins.lock().unwrap());
+        usage.queries += query_origins.values().sum::<u64>();
+        (usage, query_origins)
+    }
+
     pub(crate) fn snapshot_for_query(&self) -> Query {
         Query {
             contract: self.contract.snapshot(),
@@ -195,17 +265,81 @@ impl FatContract {
             self_id: self.id(),
         };
 
-        phala_mq::select! {
-            next_cmd = self.cmd_rcv_mq => match next_cmd {
-                Ok((_, cmd, origin)) => {
-                    info!(target: "contract", "Contract {:?} handling command", self.id());
-                    self.contract.handle_command(origin, cmd.0, &mut context)
-                }
-                Err(_e) => {
-                    Err(TransactionError::ChannelError)
+        let next_cmd = self.cmd_rcv_mq.try_next_with_payload().transpose()?;
+        let result = match next_cmd {
+            Ok((_, cmd, origin, payload)) => {
+                info!(target: "contract", "Contract {:?} handling command", self.id());
+                // Hash the payload as it was sent on chain, so the senders can recognize it.
+                let command_hash = sp_core::hashing::blake2_256(&payload).into();
+                let result = self.contract.handle_command(origin.clone(), cmd.0, &mut context);
+                if let Err(err) = &result {
+                    // Let the senders on chain learn about the failure. All the workers
+                    // running the contract push the same message, which is accepted once.
+                    if matches!(origin, MessageOrigin::Pallet(_) | MessageOrigin::AccountId(_)) {
+                        self.report_command_failure(origin, command_hash, err);
+                    }
                 }
-            },
+                result
+            }
+            Err(_e) => Err(TransactionError::ChannelError),
+        };
+        Some(result)
+    }
+
+    /// Handles a command called by a pink contract in the same cluster. Only native contracts can
+    /// be called this way.
+    ///
+    /// `command_id` is taken by the caller with `next_native_command_id`.
+    pub(crate) fn handle_native_command(
+        &mut self,
+        origin: MessageOrigin,
+        cmd: Vec<u8>,
+        command_id: sp_core::H256,
+        env: &mut ExecuteEnv,
+    ) -> TransactionResult {
+        let result = if self.is_pink() {
+            Err(TransactionError::BadContractId)
+        } else {
+            let secret_mq = SecretMessageChannel::new(&self.ecdh_key, &self.send_mq);
+            let mut context = NativeContext {
+                block: env.block,
+                mq: &self.send_mq,
+                secret_mq,
+                contract_clusters: &mut env.contract_clusters,
+                self_id: self.id(),
+            };
+            info!(target: "contract", "Contract {:?} handling native command", self.id());
+            self.contract.handle_command(origin.clone(), cmd, &mut context)
+        };
+        if let Err(err) = &result {
+            self.report_command_failure(origin, command_id, err);
         }
+        result
+    }
+
+    /// Lets the sender of a command learn about its failure on chain.
+    ///
+    /// The failure of a command sent by an account is published without the sender and the
+    /// error, since the command may be secret.
+    pub(crate) fn report_command_failure(
+        &self,
+        origin: MessageOrigin,
+        command_hash: sp_core::H256,
+        err: &TransactionError,
+    ) {
+        let failure = match origin {
+            MessageOrigin::AccountId(_) => ContractCommandFailure {
+                origin: None,
+                command_hash,
+                error_code: ContractCommandFailure::GENERIC_ERROR_CODE,
+            },
+            origin => ContractCommandFailure {
+                origin: Some(origin),
+                command_hash,
+                error_code: err.code(),
+            },
+        };
+        self.send_mq.push_message(&failure);
     }
 
     pub(crate) fn on_block_end(&mut self, env: &mut ExecuteEnv) -> TransactionResult {
@@ -224,7 +358,7 @@ impl FatContract {
         if let AnyContract::Pink(pink) = &mut self.contract {
             pink.set_on_block_end_selector(selector)
         } else {
-            log::error!("Can not set block_end_selector for native contract");
+            log::error!(target: "contract", "Can not set block_end_selector for native contract");
         }
     }
 
@@ -249,16 +383,29 @@ impl FatContract {
         spawner: &sidevm::service::Spawner,
         code: Vec<u8>,
         memory_pages: u32,
+        grpc_targets: Vec<String>,
+        config: Vec<u8>,
     ) -> Result<()> {
         if self.sidevm_info.is_some() {
             bail!("Sidevm can only be started once");
         }
-        let handle = do_start_sidevm(spawner, &code, memory_pages, self.contract_id.0)?;
+        let handle = do_start_sidevm(
+            spawner,
+            &code,
+            memory_pages,
+            self.contract_id.0,
+            grpc_targets.clone(),
+            config.clone(),
+        )?;
         self.sidevm_info = Some(SidevmInfo {
             code,
             memory_pages,
+            grpc_targets,
+            config,
             handle,
+            storage_reader: Default::default(),
         });
+        self.update_sidevm_storage(None);
         Ok(())
     }
 
@@ -272,16 +419,81 @@ impl FatContract {
                     spawner,
                     &sidevm_info.code,
                     sidevm_info.memory_pages,
-                    self.cluster_id.0,
+                    self.contract_id.0,
+                    sidevm_info.grpc_targets.clone(),
+                    sidevm_info.config.clone(),
                 )?;
                 sidevm_info.handle = handle;
+                self.update_sidevm_storage(None);
             }
         }
         Ok(())
     }
 
+    /// Returns true if the running sidevm instance of the contract reads the contract storage.
+    pub(crate) fn is_sidevm_storage_reader(&self) -> bool {
+        match &self.sidevm_info {
+            Some(info) => info.storage_reader.load(Ordering::Relaxed),
+            None => false,
+        }
+    }
+
+    /// Hands a snapshot of the cluster storage to the running sidevm instance of the contract, so
+    /// that it can read the contract storage at the latest block. Without snapshot, the reads of
+    /// the instance fail until it is handed one.
+    pub(crate) fn update_sidevm_storage(&self, snapshot: Option<&Arc<Mutex<pink::Storage>>>) {
+        let pink = match &self.contract {
+            AnyContract::Pink(pink) => pink,
+            _ => return,
+        };
+        let info = match &self.sidevm_info {
+            Some(info) => info,
+            None => return,
+        };
+        let tx = match &*info.handle.lock().unwrap() {
+            SidevmHandle::Running(tx) => tx.clone(),
+            SidevmHandle::Terminated => return,
+        };
+        let storage = Arc::new(pink.sidevm_storage(snapshot.cloned(), info.storage_reader.clone()));
+        // The instance keeps reading the previous snapshot if its command queue is full.
+        if tx
+            .try_send(sidevm::service::Command::UpdateContractStorage(storage))
+            .is_err()
+        {
+            warn!(target: "sidevm", "Update sidevm storage failed, the command queue is full");
+        }
+    }
+
+    /// Asks the running sidevm instance of the contract to stop.
+    pub(crate) fn stop_sidevm(&self) {
+        let handle = match &self.sidevm_info {
+            Some(info) => info.handle.lock().unwrap(),
+            None => return,
+        };
+        if let SidevmHandle::Running(tx) = &*handle {
+            if tx.try_send(sidevm::service::Command::Stop).is_err() {
+                warn!(target: "sidevm", "Stop sidevm failed, the command queue is full");
+            }
+        }
+    }
+
+    /// The hash of the sidevm code, if a sidevm instance was ever started for the contract.
+    pub(crate) fn sidevm_code_hash(&self) -> Option<sp_core::H256> {
+        self.sidevm_info
+            .as_ref()
+            .map(|info| sp_core::hashing::blake2_256(&info.code).into())
+    }
+
+    /// Returns true if the contract has a running sidevm instance.
+    pub(crate) fn has_running_sidevm(&self) -> bool {
+        match &self.sidevm_info {
+            Some(info) => !info.handle.lock().unwrap().is_terminated(),
+            None => false,
+        }
+    }
+
     pub(crate) fn push_message_to_sidevm(
-        &self,
+        &mut self,
         spawner: &sidevm::service::Spawner,
         message: Vec<u8>,
     ) -> Result<()> {
@@ -301,6 +513,7 @@ impl FatContract {
             }
             SidevmHandle::Running(tx) => tx.clone(),
         };
+        self.usage.sidevm_messages += 1;
         spawner.spawn(async move {
             let result = tx
                 .send(sidevm::service::Command::PushMessage(message))
@@ -318,8 +531,10 @@ fn do_start_sidevm(
     code: &[u8],
     memory_pages: u32,
     id: VmId,
+    grpc_targets: Vec<String>,
+    config: Vec<u8>,
 ) -> Result<Arc<Mutex<SidevmHandle>>> {
-    let (sender, join_handle) = spawner.start(code, memory_pages, id)?;
+    let (sender, join_handle) = spawner.start(code, memory_pages, id, grpc_targets, config)?;
     let handle = Arc::new(Mutex::new(SidevmHandle::Running(sender)));
     let cloned_handle = handle.clone();
 
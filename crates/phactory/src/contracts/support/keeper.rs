@@ -1,22 +1,34 @@
+use pink::storage::Snapshot as _;
 use serde::{Deserialize, Serialize};
 use sidevm::service::Spawner;
-use std::collections::BTreeMap;
+use std::collections::{btree_map::Entry, BTreeMap};
+use std::sync::{Arc, Mutex};
 
 use crate::{
     contracts::{
-        assets::Assets, balances::Balances, btc_lottery::BtcLottery, btc_price_bot::BtcPriceBot,
-        geolocation::Geolocation, guess_number::GuessNumber, pink::Pink, FatContract,
-        NativeContext, NativeContract as _, TransactionError, TransactionResult,
+        assets::Assets, balances::Balances, ballot::Ballot, btc_lottery::BtcLottery,
+        btc_price_bot::BtcPriceBot, geolocation::Geolocation, guess_number::GuessNumber,
+        pink::cluster::ClusterKeeper, pink::Pink, price_oracle::PriceOracle, vault::Vault,
+        FatContract, NativeContext, NativeContract as _, TransactionError, TransactionResult,
     },
     types::{deopaque_query, OpaqueError, OpaqueQuery, OpaqueReply},
 };
 use parity_scale_codec::{Decode, Encode};
 use phala_mq::{ContractId, MessageOrigin};
+use phala_types::contract::ContractUsage;
 
 use super::QueryContext;
 
 type ContractMap = BTreeMap<ContractId, FatContract>;
 
+/// The usage of the contracts in a cluster
+#[derive(Default)]
+pub struct ClusterUsage {
+    pub contracts: Vec<(ContractId, ContractUsage)>,
+    /// The number of the signed queries by their origins
+    pub query_origins: BTreeMap<runtime::AccountId, u64>,
+}
+
 macro_rules! define_any_native_contract {
     (pub enum $name:ident { $($contract:ident ($contract_type: tt),)* }) => {
         #[derive(Encode, Decode)]
@@ -89,6 +101,9 @@ define_any_native_contract!(
         Geolocation(Geolocation),
         GuessNumber(GuessNumber),
         BtcPriceBot(BtcPriceBot),
+        Vault(Vault),
+        Ballot(Ballot),
+        PriceOracle(PriceOracle),
     }
 );
 
@@ -112,6 +127,49 @@ impl ContractsKeeper {
         self.0.get(id)
     }
 
+    /// Snapshots of the native contracts in the cluster, for the pink contracts to query.
+    pub(crate) fn native_snapshots(
+        &self,
+        cluster_id: &phala_mq::ContractClusterId,
+    ) -> BTreeMap<ContractId, super::Query> {
+        self.0
+            .iter()
+            .filter(|(_, contract)| &contract.cluster_id() == cluster_id && !contract.is_pink())
+            .map(|(id, contract)| (*id, contract.snapshot_for_query()))
+            .collect()
+    }
+
+    /// The state digests of all the contracts
+    pub fn digests(&self) -> Vec<(ContractId, sp_core::H256)> {
+        self.0
+            .iter()
+            .map(|(id, contract)| (*id, contract.state_digest()))
+            .collect()
+    }
+
+    /// Removes all contracts of the given cluster.
+    pub fn remove_cluster_contracts(&mut self, cluster_id: &phala_mq::ContractClusterId) {
+        self.0
+            .retain(|_, contract| &contract.cluster_id() != cluster_id);
+    }
+
+    /// Takes the usage of the contracts since the last call, grouped by cluster.
+    pub fn take_usage(&mut self) -> BTreeMap<phala_mq::ContractClusterId, ClusterUsage> {
+        let mut usage: BTreeMap<_, ClusterUsage> = BTreeMap::new();
+        for (id, contract) in self.0.iter_mut() {
+            let (contract_usage, query_origins) = contract.take_usage();
+            if contract_usage.is_empty() {
+                continue;
+            }
+            let cluster_usage = usage.entry(contract.cluster_id()).or_default();
+            cluster_usage.contracts.push((*id, contract_usage));
+            for (origin, count) in query_origins {
+                *cluster_usage.query_origins.entry(origin).or_default() += count;
+            }
+        }
+        usage
+    }
+
     #[cfg(test)]
     pub fn values_mut(&mut self) -> impl Iterator<Item = &mut FatContract> {
         self.0.values_mut()
@@ -122,10 +180,37 @@ impl ContractsKeeper {
         self.0.len()
     }
 
+    /// Hands the sidevm instances reading the contract storage a snapshot of their cluster storage
+    /// at the current block. The snapshot of a cluster is shared by all of its instances, and is
+    /// only taken if any of them reads the storage.
+    pub fn update_sidevm_storages(&self, clusters: &mut ClusterKeeper) {
+        let mut snapshots = BTreeMap::new();
+        for contract in self.0.values() {
+            if !contract.has_running_sidevm() || !contract.is_sidevm_storage_reader() {
+                continue;
+            }
+            let cluster_id = contract.cluster_id();
+            let snapshot = match snapshots.entry(cluster_id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => match clusters.get_cluster_mut(&cluster_id) {
+                    Some(cluster) => entry.insert(Arc::new(Mutex::new(cluster.storage.snapshot()))),
+                    None => continue,
+                },
+            };
+            contract.update_sidevm_storage(Some(snapshot));
+        }
+    }
+
     pub fn try_restart_sidevms(&mut self, spawner: &Spawner) -> anyhow::Result<()> {
         for contract in self.0.values_mut() {
             contract.restart_sidevm_if_terminated(spawner)?;
         }
         Ok(())
     }
+
+    pub fn stop_sidevms(&self) {
+        for contract in self.0.values() {
+            contract.stop_sidevm();
+        }
+    }
 }
@@ -0,0 +1,267 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use log::info;
+use parity_scale_codec::{Decode, Encode};
+use phala_mq::MessageOrigin;
+
+use super::{TransactionError, TransactionResult};
+use crate::contracts;
+use crate::contracts::{AccountId, AccountIdWrapper, NativeContext};
+extern crate runtime as chain;
+
+use phala_types::messaging::VaultCommand;
+
+type Command = VaultCommand;
+
+/// The max size of a key.
+const MAX_KEY_SIZE: usize = 128;
+/// The max size of a secret.
+const MAX_VALUE_SIZE: usize = 4 * 1024;
+/// The max number of secrets an account can store.
+const MAX_ENTRIES_PER_ACCOUNT: usize = 256;
+/// The max number of accounts a secret can be shared with.
+const MAX_DELEGATES_PER_ENTRY: usize = 16;
+
+#[derive(Encode, Decode, Debug, Clone, Default)]
+struct Entry {
+    value: Vec<u8>,
+    delegates: BTreeSet<AccountId>,
+}
+
+/// A key-value store of secrets. The secrets only enter the enclave in encrypted commands, and can
+/// only be read by their owners and the accounts they delegated to.
+#[derive(Encode, Decode, Debug, Clone, Default)]
+pub struct Vault {
+    entries: BTreeMap<AccountId, BTreeMap<Vec<u8>, Entry>>,
+}
+
+#[derive(Encode, Decode, Debug)]
+pub enum Error {
+    OriginUnavailable,
+    NotFound,
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub enum Request {
+    /// Read a secret of `owner`. Only the owner and the delegates can read it.
+    Get { owner: AccountId, key: Vec<u8> },
+    /// List the keys of the sender's secrets.
+    ListKeys,
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub enum Response {
+    Value(Vec<u8>),
+    Keys(Vec<Vec<u8>>),
+}
+
+impl Vault {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn entry_mut(
+        &mut self,
+        owner: &AccountId,
+        key: &[u8],
+    ) -> Result<&mut Entry, TransactionError> {
+        self.entries
+            .get_mut(owner)
+            .and_then(|entries| entries.get_mut(key))
+            .ok_or(TransactionError::BadInput)
+    }
+
+    fn apply(&mut self, sender: AccountId, cmd: Command) -> Result<(), TransactionError> {
+        match cmd {
+            Command::Put { key, value } => {
+                if key.len() > MAX_KEY_SIZE || value.len() > MAX_VALUE_SIZE {
+                    return Err(TransactionError::BadInput);
+                }
+                let entries = self.entries.entry(sender.clone()).or_default();
+                if !entries.contains_key(&key) && entries.len() >= MAX_ENTRIES_PER_ACCOUNT {
+                    return Err(TransactionError::QuotaExceeded);
+                }
+                info!(target: "contract", "Vault: [{}] put a secret", AccountIdWrapper(sender));
+                entries.entry(key).or_default().value = value;
+            }
+            Command::Remove { key } => {
+                let entries = self
+                    .entries
+                    .get_mut(&sender)
+                    .ok_or(TransactionError::BadInput)?;
+                entries.remove(&key).ok_or(TransactionError::BadInput)?;
+                if entries.is_empty() {
+                    self.entries.remove(&sender);
+                }
+            }
+            Command::Grant { key, to } => {
+                let entry = self.entry_mut(&sender, &key)?;
+                if entry.delegates.len() >= MAX_DELEGATES_PER_ENTRY {
+                    return Err(TransactionError::QuotaExceeded);
+                }
+                entry.delegates.insert(AccountId::from(*to.as_fixed_bytes()));
+            }
+            Command::Revoke { key, from } => {
+                let entry = self.entry_mut(&sender, &key)?;
+                entry.delegates.remove(&AccountId::from(*from.as_fixed_bytes()));
+            }
+        }
+        Ok(())
+    }
+
+    fn query(&self, sender: &AccountId, req: Request) -> Result<Response, Error> {
+        match req {
+            Request::Get { owner, key } => {
+                // The others can not tell whether the key exists.
+                let entry = self
+                    .entries
+                    .get(&owner)
+                    .and_then(|entries| entries.get(&key))
+                    .filter(|entry| sender == &owner || entry.delegates.contains(sender))
+                    .ok_or(Error::NotFound)?;
+                Ok(Response::Value(entry.value.clone()))
+            }
+            Request::ListKeys => {
+                let keys = self
+                    .entries
+                    .get(sender)
+                    .map(|entries| entries.keys().cloned().collect())
+                    .unwrap_or_default();
+                Ok(Response::Keys(keys))
+            }
+        }
+    }
+}
+
+impl contracts::NativeContract for Vault {
+    type Cmd = Command;
+    type QReq = Request;
+    type QResp = Result<Response, Error>;
+
+    fn handle_command(
+        &mut self,
+        origin: MessageOrigin,
+        cmd: Command,
+        _context: &mut NativeContext,
+    ) -> TransactionResult {
+        let sender = origin.account()?;
+        self.apply(sender, cmd)?;
+        Ok(Default::default())
+    }
+
+    fn handle_query(
+        &self,
+        origin: Option<&chain::AccountId>,
+        req: Request,
+        _context: &mut contracts::QueryContext,
+    ) -> Result<Response, Error> {
+        let sender = origin.ok_or(Error::OriginUnavailable)?;
+        self.query(sender, req)
+    }
+
+    fn snapshot(&self) -> Self {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sp_core::H256;
+
+    fn account(n: u8) -> AccountId {
+        AccountId::from([n; 32])
+    }
+
+    fn put(key: &[u8], value: &[u8]) -> Command {
+        Command::Put {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        }
+    }
+
+    fn get(vault: &Vault, sender: u8, owner: u8, key: &[u8]) -> Option<Vec<u8>> {
+        let req = Request::Get {
+            owner: account(owner),
+            key: key.to_vec(),
+        };
+        match vault.query(&account(sender), req) {
+            Ok(Response::Value(value)) => Some(value),
+            Err(Error::NotFound) => None,
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn secrets_are_read_by_owners_and_delegates() {
+        let mut vault = Vault::new();
+        vault.apply(account(1), put(b"key", b"secret")).unwrap();
+        assert_eq!(get(&vault, 1, 1, b"key"), Some(b"secret".to_vec()));
+        assert_eq!(get(&vault, 2, 1, b"key"), None);
+        assert_eq!(get(&vault, 1, 1, b"missing"), None);
+
+        let grant = Command::Grant {
+            key: b"key".to_vec(),
+            to: H256::repeat_byte(2),
+        };
+        vault.apply(account(1), grant).unwrap();
+        assert_eq!(get(&vault, 2, 1, b"key"), Some(b"secret".to_vec()));
+        assert_eq!(get(&vault, 3, 1, b"key"), None);
+        // A delegate can not manage the secret
+        let revoke = Command::Revoke {
+            key: b"key".to_vec(),
+            from: H256::repeat_byte(2),
+        };
+        assert!(vault.apply(account(2), revoke.clone()).is_err());
+        vault.apply(account(1), revoke).unwrap();
+        assert_eq!(get(&vault, 2, 1, b"key"), None);
+
+        let keys = vault.query(&account(1), Request::ListKeys).unwrap();
+        assert!(matches!(keys, Response::Keys(keys) if keys == vec![b"key".to_vec()]));
+        let keys = vault.query(&account(2), Request::ListKeys).unwrap();
+        assert!(matches!(keys, Response::Keys(keys) if keys.is_empty()));
+
+        let remove = Command::Remove {
+            key: b"key".to_vec(),
+        };
+        assert!(vault.apply(account(2), remove.clone()).is_err());
+        vault.apply(account(1), remove).unwrap();
+        assert_eq!(get(&vault, 1, 1, b"key"), None);
+        assert!(vault.entries.is_empty());
+    }
+
+    #[test]
+    fn quotas_are_enforced() {
+        let mut vault = Vault::new();
+        let long_key = vec![0; MAX_KEY_SIZE + 1];
+        let result = vault.apply(account(1), put(&long_key, b""));
+        assert!(matches!(result, Err(TransactionError::BadInput)));
+        let large_value = vec![0; MAX_VALUE_SIZE + 1];
+        let result = vault.apply(account(1), put(b"key", &large_value));
+        assert!(matches!(result, Err(TransactionError::BadInput)));
+
+        for i in 0..MAX_ENTRIES_PER_ACCOUNT {
+            vault.apply(account(1), put(&i.to_be_bytes(), b"")).unwrap();
+        }
+        let result = vault.apply(account(1), put(b"one more", b""));
+        assert!(matches!(result, Err(TransactionError::QuotaExceeded)));
+        // Replacing a secret takes no more quota
+        vault.apply(account(1), put(&0usize.to_be_bytes(), b"new")).unwrap();
+        // The quota is per account
+        vault.apply(account(2), put(b"one more", b"")).unwrap();
+
+        for i in 0..MAX_DELEGATES_PER_ENTRY {
+            let grant = Command::Grant {
+                key: b"one more".to_vec(),
+                to: H256::repeat_byte(i as u8 + 3),
+            };
+            vault.apply(account(2), grant).unwrap();
+        }
+        let grant = Command::Grant {
+            key: b"one more".to_vec(),
+            to: H256::repeat_byte(0xff),
+        };
+        let result = vault.apply(account(2), grant);
+        assert!(matches!(result, Err(TransactionError::QuotaExceeded)));
+    }
+}
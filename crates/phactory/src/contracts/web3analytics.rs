@@ -897,7 +897,12 @@ impl contracts::NativeContract for Web3Analytics {
         let status = match cmd {
             Command::SetConfiguration { skip_stat } => {
                 let o = origin.account()?;
-                log::info!("SetConfiguration: [{}] -> {}", hex::encode(&o), skip_stat);
+                log::info!(
+                    target: "contract",
+                    "SetConfiguration: [{}] -> {}",
+                    hex::encode(&o),
+                    skip_stat
+                );
 
                 if skip_stat {
                     self.no_tracking.insert(o, skip_stat);
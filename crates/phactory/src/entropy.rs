@@ -0,0 +1,102 @@
+//! The entropy pool used by all key generation inside pRuntime.
+//!
+//! Mixes RDSEED, RDRAND and the system RNG (which is backed by the in-enclave RDRAND under
+//! Gramine, the equivalent of `sgx_read_rand`) together with a sealed boot counter.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{Context as _, Result};
+use parity_scale_codec::{Decode, Encode};
+use phala_crypto::entropy::{EntropyMixer, FnSource};
+use phala_crypto::CryptoError;
+use ring::rand::SecureRandom;
+
+const BOOT_COUNTER_FILE: &str = "entropy-counter.seal";
+
+lazy_static! {
+    static ref MIXER: Mutex<EntropyMixer> = Mutex::new(new_mixer(0));
+}
+
+fn new_mixer(boot_count: u64) -> EntropyMixer {
+    let mut mixer = EntropyMixer::with_hardware_sources(boot_count);
+    let rng = ring::rand::SystemRandom::new();
+    mixer.add_source(FnSource::new("system", move |buf: &mut [u8]| {
+        rng.fill(buf).or(Err(CryptoError::EntropySourceFailed))
+    }));
+    mixer
+}
+
+/// Loads and increases the sealed boot counter, then resets the pool with it.
+pub(crate) fn init(platform: &impl pal::Sealing, sealing_path: &str) -> Result<()> {
+    let filepath = PathBuf::from(sealing_path).join(BOOT_COUNTER_FILE);
+    let last_boot_count = match platform
+        .unseal_data(&filepath)
+        .map_err(Into::into)
+        .context("Failed to unseal entropy counter")?
+    {
+        Some(data) => u64::decode(&mut &data[..]).context("Failed to decode entropy counter")?,
+        None => 0,
+    };
+    let boot_count = last_boot_count + 1;
+    platform
+        .seal_data(&filepath, &boot_count.encode())
+        .map_err(Into::into)
+        .context("Failed to seal entropy counter")?;
+    let mixer = new_mixer(boot_count);
+    info!(
+        target: "phactory",
+        "Entropy pool initialized with {} sources, boot count {}",
+        mixer.num_sources(),
+        boot_count
+    );
+    *MIXER.lock().unwrap() = mixer;
+    Ok(())
+}
+
+/// Fills `buf` with mixed entropy for the key type given by `domain`.
+///
+/// Panics if any source fails, since continuing could produce a predictable key.
+pub(crate) fn fill(domain: &[u8], buf: &mut [u8]) {
+    MIXER
+        .lock()
+        .unwrap()
+        .fill(domain, buf)
+        .expect("Failed to gather entropy");
+}
+
+/// A `rand` RNG drawing from the pool, for the APIs that take an RNG to generate the keys.
+pub(crate) struct EntropyRng {
+    domain: &'static [u8],
+}
+
+impl EntropyRng {
+    pub(crate) fn new(domain: &'static [u8]) -> Self {
+        EntropyRng { domain }
+    }
+}
+
+impl rand::RngCore for EntropyRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut buf = [0_u8; 4];
+        self.fill_bytes(&mut buf);
+        u32::from_le_bytes(buf)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut buf = [0_u8; 8];
+        self.fill_bytes(&mut buf);
+        u64::from_le_bytes(buf)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        fill(self.domain, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl rand::CryptoRng for EntropyRng {}
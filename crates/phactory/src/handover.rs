@@ -0,0 +1,257 @@
+//! Worker key handover between two pRuntime instances on the same machine.
+//!
+//! When upgrading pRuntime, the new instance would otherwise generate a fresh identity key and
+//! have to be registered on chain again. Instead, the running (old) instance can hand its sealed
+//! identity key, its sync cursor and a fresh checkpoint over to the new instance:
+//!
+//! 1. The new instance asks the old one for a challenge (`handover_create_challenge`).
+//! 2. The new instance answers the challenge with an ephemeral ECDH pubkey, attested by a RA
+//!    report committing to the answer (`handover_accept_challenge`).
+//! 3. The old instance verifies the attestation against the on-chain pRuntime allowlist, then
+//!    encrypts its secrets to the ephemeral key (`handover_start`).
+//! 4. The new instance decrypts the secrets, seals the identity key and writes the checkpoint to
+//!    its own sealing path (`handover_receive`). It can then be restarted as the same worker.
+//!
+//! Once the secrets are handed over, the old instance stops processing blocks and seals a marker
+//! refusing to restart from its own key, so that the two instances never emit messages as the
+//! same worker. The marker can be removed by hand if the new instance failed to take over.
+
+use super::*;
+use crate::system::chain_state;
+use phala_crypto::ecdh;
+use phala_pallets::pallet_registry::{Attestation, AttestationValidator, IasValidator};
+use phactory_api::crypto::EncryptedData;
+
+/// The max age of a handover challenge in seconds.
+const MAX_CHALLENGE_AGE: u64 = 60;
+/// Sealed by the old instance after handing the worker key over.
+const HANDED_OVER_FILE: &str = "handed-over.seal";
+
+/// Whether the worker key sealed in `sealing_path` has been handed over to another instance.
+pub(crate) fn is_handed_over(platform: &impl pal::Sealing, sealing_path: &str) -> bool {
+    let filepath = PathBuf::from(sealing_path).join(HANDED_OVER_FILE);
+    !matches!(platform.unseal_data(filepath), Ok(None))
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct HandoverChallenge {
+    /// The block number the old instance has synced to when creating the challenge.
+    pub block_number: chain::BlockNumber,
+    /// The unix timestamp (in seconds) when the challenge is created.
+    pub now: u64,
+    /// Whether the old instance is running in dev mode (RA disabled).
+    pub dev_mode: bool,
+    pub nonce: [u8; 32],
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct ChallengeHandlerInfo {
+    pub challenge: HandoverChallenge,
+    /// The ephemeral key of the new instance to receive the secrets.
+    pub ecdh_pubkey: ecdh::EcdhPublicKey,
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct HandoverChallengeResponse {
+    pub handler: ChallengeHandlerInfo,
+    /// The RA report committing to `blake2_256(handler.encode())`. `None` in dev mode.
+    pub attestation: Option<Attestation>,
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct HandoverWorkerKey {
+    /// The encoded `HandoverSecret` encrypted to the ephemeral key of the new instance.
+    pub encrypted: EncryptedData,
+}
+
+#[derive(Encode, Decode)]
+struct HandoverSecret {
+    genesis_block_hash: H256,
+    sk: Sr25519SecretKey,
+    dev_mode: bool,
+    /// The block number the checkpoint is taken at.
+    synced_to: chain::BlockNumber,
+    /// The cbor encoded checkpoint.
+    checkpoint: Vec<u8>,
+}
+
+impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform> {
+    /// Called on the old instance. Creates a challenge the new instance should answer.
+    pub fn handover_create_challenge(&mut self) -> Result<HandoverChallenge> {
+        if self.handed_over {
+            anyhow::bail!("The worker key has been handed over");
+        }
+        let block_number = self
+            .runtime_state
+            .as_ref()
+            .ok_or_else(|| anyhow!("Runtime not initialized"))?
+            .storage_synchronizer
+            .counters()
+            .next_block_number
+            - 1;
+        let mut nonce = [0_u8; 32];
+        crate::entropy::fill(b"handover", &mut nonce);
+        let challenge = HandoverChallenge {
+            block_number,
+            now: unix_now(),
+            dev_mode: self.dev_mode,
+            nonce,
+        };
+        self.handover_last_challenge = Some(challenge.clone());
+        Ok(challenge)
+    }
+
+    /// Called on the old instance. Verifies the response and hands the secrets over.
+    pub fn handover_start(
+        &mut self,
+        response: HandoverChallengeResponse,
+    ) -> Result<HandoverWorkerKey> {
+        let challenge = self
+            .handover_last_challenge
+            .take()
+            .ok_or_else(|| anyhow!("No handover challenge"))?;
+        let handler = response.handler;
+        if handler.challenge != challenge {
+            anyhow::bail!("Handover challenge mismatch");
+        }
+        let now = unix_now();
+        if now > challenge.now + MAX_CHALLENGE_AGE {
+            anyhow::bail!("Handover challenge expired");
+        }
+
+        let state = self
+            .runtime_state
+            .as_ref()
+            .ok_or_else(|| anyhow!("Runtime not initialized"))?;
+        if !self.dev_mode {
+            let attestation = response
+                .attestation
+                .ok_or_else(|| anyhow!("Missing attestation"))?;
+            let allowlist = chain_state::pruntime_allowlist(&state.chain_storage);
+            let handler_hash = sp_core::hashing::blake2_256(&handler.encode());
+            IasValidator::validate(&attestation, &handler_hash, now, true, allowlist)
+                .map_err(|err| anyhow!("Invalid handover attestation: {:?}", err))?;
+        }
+
+        let synced_to = state.storage_synchronizer.counters().next_block_number - 1;
+        let genesis_block_hash = state.genesis_block_hash;
+        let system = self
+            .system
+            .as_ref()
+            .ok_or_else(|| anyhow!("Runtime not initialized"))?;
+        let sk = system.identity_key.dump_secret_key();
+        let ecdh_key = system.ecdh_key.clone();
+        let checkpoint =
+            serde_cbor::ser::to_vec(&PhactoryDumper(self)).context("Failed to dump checkpoint")?;
+
+        let secret = HandoverSecret {
+            genesis_block_hash,
+            sk,
+            dev_mode: self.dev_mode,
+            synced_to,
+            checkpoint,
+        };
+        let encrypted = EncryptedData::encrypt(
+            &ecdh_key,
+            &handler.ecdh_pubkey,
+            crate::generate_random_iv(),
+            &secret.encode(),
+        )
+        .map_err(|err| anyhow!("Failed to encrypt handover secret: {:?}", err))?;
+
+        let filepath = PathBuf::from(&self.args.sealing_path).join(HANDED_OVER_FILE);
+        self.platform
+            .seal_data(filepath, &synced_to.encode())
+            .map_err(Into::into)
+            .context("Failed to seal the handover marker")?;
+        self.handed_over = true;
+        if let Some(system) = &self.system {
+            system.stop_sidevms(SIDEVM_STOP_TIMEOUT);
+        }
+        info!(target: "handover", "Worker key handed over at block {}", synced_to);
+        Ok(HandoverWorkerKey { encrypted })
+    }
+
+    /// Called on the new instance. Answers the challenge from the old instance.
+    pub fn handover_accept_challenge(
+        &mut self,
+        challenge: HandoverChallenge,
+    ) -> Result<HandoverChallengeResponse> {
+        if self.system.is_some() {
+            anyhow::bail!("Runtime already initialized");
+        }
+        match Self::load_runtime_data(&self.platform, &self.args.sealing_path) {
+            Err(Error::PersistentRuntimeNotFound) => {}
+            Ok(_) => anyhow::bail!("Worker key already exists, refusing to receive a handover"),
+            Err(err) => anyhow::bail!("Failed to load persistent data: {}", err),
+        }
+        let mut seed = [0_u8; 32];
+        crate::entropy::fill(b"ecdh", &mut seed);
+        let ecdh_key =
+            EcdhKey::create(&seed).map_err(|err| anyhow!("Failed to create ecdh key: {:?}", err))?;
+        let handler = ChallengeHandlerInfo {
+            challenge,
+            ecdh_pubkey: ecdh_key.public(),
+        };
+        let attestation = if handler.challenge.dev_mode {
+            None
+        } else {
+            let handler_hash = sp_core::hashing::blake2_256(&handler.encode());
+            let (ra_report, signature, signing_cert) = self
+                .platform
+                .create_attestation_report(&handler_hash)
+                .map_err(Into::into)
+                .context("Failed to create attestation report")?;
+            Some(Attestation::SgxIas {
+                ra_report: ra_report.into_bytes(),
+                signature: base64::decode(signature).context("Bad report signature")?,
+                raw_signing_cert: base64::decode(signing_cert).context("Bad signing cert")?,
+            })
+        };
+        self.handover_ecdh_key = Some(ecdh_key);
+        Ok(HandoverChallengeResponse {
+            handler,
+            attestation,
+        })
+    }
+
+    /// Called on the new instance. Seals the received worker key and checkpoint.
+    pub fn handover_receive(&mut self, key: HandoverWorkerKey) -> Result<chain::BlockNumber> {
+        let ecdh_key = self
+            .handover_ecdh_key
+            .take()
+            .ok_or_else(|| anyhow!("Handover challenge not accepted"))?;
+        let secret = key
+            .encrypted
+            .decrypt(&ecdh_key)
+            .map_err(|err| anyhow!("Failed to decrypt handover secret: {:?}", err))?;
+        let secret = HandoverSecret::decode(&mut &secret[..])
+            .context("Failed to decode handover secret")?;
+
+        let identity_key = sr25519::Pair::restore_from_secret_key(&secret.sk);
+        self.save_runtime_data(secret.genesis_block_hash, identity_key, secret.dev_mode)?;
+
+        let checkpoint_file = checkpoint_filename_for(secret.synced_to, &self.args.sealing_path);
+        let mut file = self
+            .platform
+            .create_protected_file(&checkpoint_file, &secret.sk)
+            .map_err(|err| anyhow!("{:?}", err))
+            .context("Failed to create protected file")?;
+        file.write_all(&secret.checkpoint)
+            .context("Failed to write checkpoint")?;
+        info!(
+            target: "handover",
+            "Worker key received, checkpoint saved to {}",
+            checkpoint_file
+        );
+        Ok(secret.synced_to)
+    }
+}
+
+fn unix_now() -> u64 {
+    use std::time::SystemTime;
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("System time before unix epoch")
+        .as_secs()
+}
@@ -0,0 +1,164 @@
+//! Heap usage snapshots per subsystem.
+//!
+//! The subsystems tag their allocations with [`enter`]. The tags are only accounted when pRuntime
+//! is built with a tagging allocator (the `heap-profiling` feature of pruntime); otherwise the
+//! platform reports no tags and no snapshot is taken.
+
+use std::collections::VecDeque;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use pal::TaggedMemoryUsage;
+use phala_allocator::tags::{self, TagGuard};
+use serde_json::{json, Value};
+
+/// The number of snapshots kept in memory.
+const MAX_SNAPSHOTS: usize = 120;
+
+#[derive(Clone, Copy, Debug)]
+#[repr(u8)]
+pub(crate) enum Subsystem {
+    Other = 0,
+    Sync = 1,
+    Messages = 2,
+    Contracts = 3,
+    Query = 4,
+    Checkpoint = 5,
+    SideTasks = 6,
+}
+
+impl Subsystem {
+    const ALL: [Subsystem; 7] = [
+        Subsystem::Other,
+        Subsystem::Sync,
+        Subsystem::Messages,
+        Subsystem::Contracts,
+        Subsystem::Query,
+        Subsystem::Checkpoint,
+        Subsystem::SideTasks,
+    ];
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        Self::ALL.get(tag as usize).copied()
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Subsystem::Other => "other",
+            Subsystem::Sync => "sync",
+            Subsystem::Messages => "messages",
+            Subsystem::Contracts => "contracts",
+            Subsystem::Query => "query",
+            Subsystem::Checkpoint => "checkpoint",
+            Subsystem::SideTasks => "side_tasks",
+        }
+    }
+}
+
+/// Credits the allocations of the current thread to `subsystem` until the guard is dropped.
+pub(crate) fn enter(subsystem: Subsystem) -> TagGuard {
+    tags::enter(subsystem as u8)
+}
+
+fn tag_name(tag: u8) -> String {
+    match Subsystem::from_tag(tag) {
+        Some(subsystem) => subsystem.name().into(),
+        None => format!("tag{}", tag),
+    }
+}
+
+struct HeapSnapshot {
+    /// Unix time in seconds
+    time: u64,
+    block_number: chain::BlockNumber,
+    usage: Vec<TaggedMemoryUsage>,
+}
+
+fn usage_json(usage: &[TaggedMemoryUsage]) -> Vec<Value> {
+    usage
+        .iter()
+        .map(|usage| {
+            json!({
+                "subsystem": tag_name(usage.tag),
+                "used": usage.used,
+                "peak_used": usage.peak_used,
+                "allocations": usage.allocations,
+            })
+        })
+        .collect()
+}
+
+impl HeapSnapshot {
+    fn to_json(&self) -> Value {
+        json!({
+            "time": self.time,
+            "block_number": self.block_number,
+            "usage": usage_json(&self.usage),
+        })
+    }
+}
+
+/// Takes the periodic snapshots of the heap usage per subsystem.
+pub(crate) struct HeapProfiler {
+    snapshots: VecDeque<HeapSnapshot>,
+    last_snapshot: Option<Instant>,
+}
+
+impl Default for HeapProfiler {
+    fn default() -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(MAX_SNAPSHOTS),
+            last_snapshot: None,
+        }
+    }
+}
+
+impl HeapProfiler {
+    /// Takes a snapshot if `interval` seconds have passed since the last one. An interval of 0
+    /// disables the snapshots.
+    pub fn maybe_snapshot(
+        &mut self,
+        platform: &impl pal::MemoryStats,
+        interval: u64,
+        block_number: chain::BlockNumber,
+    ) {
+        if interval == 0 {
+            return;
+        }
+        if let Some(last) = self.last_snapshot {
+            if last.elapsed().as_secs() < interval {
+                return;
+            }
+        }
+        self.last_snapshot = Some(Instant::now());
+        let usage = platform.memory_usage_by_tag();
+        if usage.is_empty() {
+            return;
+        }
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.snapshots.push_back(HeapSnapshot {
+            time,
+            block_number,
+            usage,
+        });
+    }
+
+    /// The current usage per subsystem followed by the history of snapshots, oldest first.
+    pub fn dump(&self, platform: &impl pal::MemoryStats) -> Value {
+        let current = platform.memory_usage_by_tag();
+        if current.is_empty() {
+            return json!({ "enabled": false });
+        }
+        let snapshots: Vec<_> = self.snapshots.iter().map(HeapSnapshot::to_json).collect();
+        json!({
+            "enabled": true,
+            "current": usage_json(&current),
+            "snapshots": snapshots,
+        })
+    }
+}
@@ -12,7 +12,6 @@ extern crate phactory_pal as pal;
 extern crate runtime as chain;
 
 use glob::PatternError;
-use rand::*;
 use serde::{
     de::{self, DeserializeOwned, SeqAccess, Visitor},
     ser::SerializeSeq,
@@ -27,7 +26,6 @@ use std::{path::Path, str};
 use anyhow::{anyhow, Context as _, Result};
 use core::convert::TryInto;
 use parity_scale_codec::{Decode, Encode};
-use ring::rand::SecureRandom;
 use serde_json::{json, Value};
 use sp_core::{crypto::Pair, sr25519, H256};
 
@@ -48,13 +46,16 @@ use phala_mq::{BindTopic, MessageDispatcher, MessageSendQueue};
 use phala_pallets::pallet_mq;
 use phala_serde_more as more;
 use phala_types::WorkerRegistrationInfo;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use types::Error;
 
 pub use contracts::pink;
-pub use prpc_service::dispatch_prpc_request;
+pub use prpc_service::{
+    dispatch_prpc_request, start_subscription_poller, subscribe_contract_query, HealthInfo,
+};
 pub use side_task::SideTaskManager;
 pub use storage::{Storage, StorageExt};
+pub use subscription::Receiver as SubscriptionReceiver;
 pub use system::gk;
 pub use types::BlockInfo;
 
@@ -63,12 +64,16 @@ pub mod benchmark;
 mod bin_api_service;
 mod contracts;
 mod cryptography;
+mod entropy;
+pub mod handover;
+mod heap_profile;
 mod light_validation;
 mod prpc_service;
 mod rpc_types;
 mod secret_channel;
 mod side_task;
 mod storage;
+mod subscription;
 mod system;
 mod types;
 
@@ -96,22 +101,30 @@ struct RuntimeState {
 impl RuntimeState {
     fn purge_mq(&mut self) {
         self.send_mq.purge(|sender| {
-            use pallet_mq::StorageMapTrait as _;
-            type OffchainIngress = pallet_mq::OffchainIngress<chain::Runtime>;
-
-            let module_prefix = OffchainIngress::module_prefix();
-            let storage_prefix = OffchainIngress::storage_prefix();
-            let key = storage_map_prefix_twox_64_concat(module_prefix, storage_prefix, sender);
-            let sequence: u64 = self.chain_storage.get_decoded(&key).unwrap_or(0);
-            debug!("purging, sequence = {}", sequence);
+            let sequence = self.chain_next_sequence(sender);
+            debug!(target: "mq", "purging, sequence = {}", sequence);
             sequence
         })
     }
+
+    /// The sequence of the next message from the sender expected by the chain. The messages
+    /// below it are accepted on chain.
+    fn chain_next_sequence(&self, sender: &phala_mq::SenderId) -> u64 {
+        use pallet_mq::StorageMapTrait as _;
+        type OffchainIngress = pallet_mq::OffchainIngress<chain::Runtime>;
+
+        let module_prefix = OffchainIngress::module_prefix();
+        let storage_prefix = OffchainIngress::storage_prefix();
+        let key = storage_map_prefix_twox_64_concat(module_prefix, storage_prefix, sender);
+        self.chain_storage.get_decoded(&key).unwrap_or(0)
+    }
 }
 
 const RUNTIME_SEALED_DATA_FILE: &str = "runtime-data.seal";
 const CHECKPOINT_FILE: &str = "checkpoint.seal";
 const CHECKPOINT_VERSION: u32 = 2;
+/// How long to wait for the sidevm instances to stop when shutting down.
+const SIDEVM_STOP_TIMEOUT: Duration = Duration::from_secs(5);
 
 fn checkpoint_filename_for(block_number: chain::BlockNumber, basedir: &str) -> String {
     format!("{}/{}-{:0>9}", basedir, CHECKPOINT_FILE, block_number)
@@ -150,11 +163,11 @@ fn glob_checkpoint_files_sorted(
 
 fn maybe_remove_checkpoints(basedir: &str) {
     match glob_checkpoint_files(basedir) {
-        Err(err) => error!("Error globbing checkpoints: {:?}", err),
+        Err(err) => error!(target: "checkpoint", "Error globbing checkpoints: {:?}", err),
         Ok(iter) => {
             for filename in iter {
                 if let Err(e) = std::fs::remove_file(&filename) {
-                    error!("failed to remove {}: {}", filename.display(), e);
+                    error!(target: "checkpoint", "failed to remove {}: {}", filename.display(), e);
                 }
             }
         }
@@ -174,9 +187,14 @@ fn remove_outdated_checkpoints(
         kept += 1;
         if kept > max_kept {
             match std::fs::remove_file(&filename) {
-                Err(e) => error!("Failed to remove {}: {}", filename.display(), e),
+                Err(e) => error!(
+                    target: "checkpoint",
+                    "Failed to remove {}: {}",
+                    filename.display(),
+                    e
+                ),
                 Ok(_) => {
-                    info!("Removed {}", filename.display());
+                    info!(target: "checkpoint", "Removed {}", filename.display());
                 }
             }
         }
@@ -195,13 +213,13 @@ impl PersistentRuntimeData {
     pub fn decode_keys(&self) -> (sr25519::Pair, EcdhKey) {
         // load identity
         let identity_sk = sr25519::Pair::restore_from_secret_key(&self.sk);
-        info!("Identity pubkey: {:?}", hex::encode(&identity_sk.public()));
+        info!(target: "phactory", "Identity pubkey: {:?}", hex::encode(&identity_sk.public()));
 
         // derive ecdh key
         let ecdh_key = identity_sk
             .derive_ecdh_key()
             .expect("Unable to derive ecdh key");
-        info!("ECDH pubkey: {:?}", hex::encode(&ecdh_key.public()));
+        info!(target: "phactory", "ECDH pubkey: {:?}", hex::encode(&ecdh_key.public()));
         (identity_sk, ecdh_key)
     }
 }
@@ -211,6 +229,17 @@ enum RuntimeDataSeal {
     V1(PersistentRuntimeData),
 }
 
+/// A digest of the runtime state, used to compare the states of workers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateDigest {
+    /// The root of the chain storage
+    pub storage_root: H256,
+    /// The hash of the encoded state of each contract
+    pub contracts: Vec<(phala_mq::ContractId, H256)>,
+    /// The storage root of each contract cluster
+    pub clusters: Vec<(phala_mq::ContractClusterId, H256)>,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(bound(deserialize = "Platform: Deserialize<'de>"))]
 pub struct Phactory<Platform> {
@@ -229,6 +258,23 @@ pub struct Phactory<Platform> {
     #[serde(skip)]
     #[serde(default = "Instant::now")]
     last_checkpoint: Instant,
+
+    #[serde(skip)]
+    handover_last_challenge: Option<handover::HandoverChallenge>,
+
+    #[serde(skip)]
+    handover_ecdh_key: Option<EcdhKey>,
+
+    /// Set once the worker key is handed over to another instance. The runtime stops processing
+    /// blocks from then on.
+    #[serde(skip)]
+    handed_over: bool,
+
+    #[serde(skip)]
+    heap_profiler: heap_profile::HeapProfiler,
+
+    #[serde(skip)]
+    subscriptions: subscription::Subscriptions,
 }
 
 impl<Platform: pal::Platform> Phactory<Platform> {
@@ -245,6 +291,11 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             system: None,
             side_task_man: Default::default(),
             last_checkpoint: Instant::now(),
+            handover_last_challenge: None,
+            handover_ecdh_key: None,
+            handed_over: false,
+            heap_profiler: Default::default(),
+            subscriptions: Default::default(),
         }
     }
 
@@ -261,6 +312,14 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             benchmark::resume();
         }
 
+        if let Err(err) = entropy::init(&self.platform, &args.sealing_path) {
+            panic!("Failed to init entropy pool: {:?}", err);
+        }
+
+        if let Some(prefix) = args.ss58_prefix {
+            contracts::account::set_ss58_prefix(prefix);
+        }
+
         self.args = args;
     }
 
@@ -283,7 +342,10 @@ impl<Platform: pal::Platform> Phactory<Platform> {
             match Self::load_runtime_data(&self.platform, &self.args.sealing_path) {
                 Ok(data) => data,
                 Err(Error::PersistentRuntimeNotFound) => {
-                    warn!("Persistent data not found.");
+                    if handover::is_handed_over(&self.platform, &self.args.sealing_path) {
+                        anyhow::bail!("The worker key has been handed over to another instance");
+                    }
+                    warn!(target: "phactory", "Persistent data not found.");
                     let identity_sk = new_sr25519_key();
                     self.save_runtime_data(genesis_block_hash, identity_sk, false)?
                 }
@@ -298,8 +360,8 @@ impl<Platform: pal::Platform> Phactory<Platform> {
                 data.genesis_block_hash
             );
         }
-        info!("Machine id: {:?}", hex::encode(&self.machine_id));
-        info!("Init done.");
+        info!(target: "phactory", "Machine id: {:?}", hex::encode(&self.machine_id));
+        info!(target: "phactory", "Init done.");
         Ok(data)
     }
 
@@ -320,17 +382,23 @@ impl<Platform: pal::Platform> Phactory<Platform> {
         {
             let data = RuntimeDataSeal::V1(data.clone());
             let encoded_vec = data.encode();
-            info!("Length of encoded slice: {}", encoded_vec.len());
+            info!(target: "phactory", "Length of encoded slice: {}", encoded_vec.len());
             let filepath = PathBuf::from(&self.args.sealing_path).join(RUNTIME_SEALED_DATA_FILE);
             self.platform
                 .seal_data(filepath, &encoded_vec)
                 .map_err(Into::into)
                 .context("Failed to seal runtime data")?;
-            info!("Persistent Runtime Data saved");
+            info!(target: "phactory", "Persistent Runtime Data saved");
         }
         Ok(data)
     }
 
+    /// Returns the key of the checkpoint streams of the runtime sealed in `sealing_path`.
+    pub fn checkpoint_key(platform: &Platform, sealing_path: &str) -> Result<[u8; 16]> {
+        let runtime_data = Self::load_runtime_data(platform, sealing_path)?;
+        Ok(derive_key_for_checkpoint(&runtime_data.sk))
+    }
+
     fn load_runtime_data(
         platform: &Platform,
         sealing_path: &str,
@@ -355,6 +423,17 @@ impl<P> Phactory<P> {
         }
         Ok(())
     }
+
+    /// Returns the digest of the current state, or None if the runtime is not initialized.
+    pub fn state_digest(&self) -> Option<StateDigest> {
+        let state = self.runtime_state.as_ref()?;
+        let system = self.system.as_ref()?;
+        Some(StateDigest {
+            storage_root: *state.chain_storage.root(),
+            contracts: system.contracts.digests(),
+            clusters: system.cluster_storage_roots(),
+        })
+    }
 }
 
 impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform> {
@@ -365,7 +444,9 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
             return Err(anyhow!("Take checkpoint failed, runtime is not ready"));
         };
 
-        info!("Taking checkpoint...");
+        let _tag = heap_profile::enter(heap_profile::Subsystem::Checkpoint);
+        let _span = tracing::info_span!("take_checkpoint").entered();
+        info!(target: "checkpoint", "Taking checkpoint...");
         let checkpoint_file = checkpoint_filename_for(current_block, &self.args.sealing_path);
         {
             // Do serialization
@@ -378,7 +459,7 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
             serde_cbor::ser::to_writer(file, &PhactoryDumper(self))
                 .context("Failed to write checkpoint")?;
         }
-        info!("Checkpoint saved to {}", checkpoint_file);
+        info!(target: "checkpoint", "Checkpoint saved to {}", checkpoint_file);
         self.last_checkpoint = Instant::now();
         remove_outdated_checkpoints(
             &self.args.sealing_path,
@@ -388,6 +469,24 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         Ok(())
     }
 
+    /// Prepares for the process to exit: stops the sidevm instances and takes a final checkpoint,
+    /// so that the worker resumes from the latest synced block after restarting.
+    pub fn shutdown(&mut self) -> anyhow::Result<()> {
+        let system = match &self.system {
+            Some(system) => system,
+            None => return Ok(()),
+        };
+        system.stop_sidevms(SIDEVM_STOP_TIMEOUT);
+        if !self.args.enable_checkpoint || self.handed_over {
+            return Ok(());
+        }
+        let current_block = match &self.runtime_state {
+            Some(state) => state.storage_synchronizer.counters().next_block_number - 1,
+            None => return Ok(()),
+        };
+        self.take_checkpoint(current_block)
+    }
+
     pub fn take_checkpoint_to_writer<W: std::io::Write>(
         &mut self,
         writer: W,
@@ -398,7 +497,8 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
             return Err(anyhow!("Take checkpoint failed, runtime is not ready"));
         };
         let key128 = derive_key_for_checkpoint(&key);
-        let nonce = rand::thread_rng().gen();
+        let mut nonce = [0_u8; 7];
+        entropy::fill(b"checkpoint", &mut nonce);
         let mut enc_writer = aead::stream::new_aes128gcm_writer(key128, nonce, writer);
         serde_cbor::ser::to_writer(&mut enc_writer, &PhactoryDumper(self))
             .context("Failed to write checkpoint")?;
@@ -413,6 +513,9 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         sealing_path: &str,
         remove_corrupted_checkpoint: bool,
     ) -> anyhow::Result<Option<Self>> {
+        if handover::is_handed_over(platform, sealing_path) {
+            anyhow::bail!("The worker key has been handed over to another instance");
+        }
         let runtime_data = match Self::load_runtime_data(platform, sealing_path) {
             Err(Error::PersistentRuntimeNotFound) => return Ok(None),
             other => other.context("Failed to load persistent data")?,
@@ -432,11 +535,13 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
             }
             Err(err) => {
                 error!(
+                    target: "checkpoint",
                     "Failed to open checkpoint file {:?}: {:?}",
-                    ckpt_filename, err
+                    ckpt_filename,
+                    err
                 );
                 if remove_corrupted_checkpoint {
-                    error!("Removing {:?}", ckpt_filename);
+                    error!(target: "checkpoint", "Removing {:?}", ckpt_filename);
                     std::fs::remove_file(&ckpt_filename)
                         .context("Failed to remove corrupted checkpoint file")?;
                 }
@@ -451,16 +556,16 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         let loader: PhactoryLoader<_> = match serde_cbor::de::from_reader(file) {
             Ok(loader) => loader,
             Err(_err /*Don't leak it into the log*/) => {
-                error!("Failed to load checkpoint file {:?}", ckpt_filename);
+                error!(target: "checkpoint", "Failed to load checkpoint file {:?}", ckpt_filename);
                 if remove_corrupted_checkpoint {
-                    error!("Removing {:?}", ckpt_filename);
+                    error!(target: "checkpoint", "Removing {:?}", ckpt_filename);
                     std::fs::remove_file(&ckpt_filename)
                         .context("Failed to remove corrupted checkpoint file")?;
                 }
                 anyhow::bail!("Failed to load checkpoint file {:?}", ckpt_filename);
             }
         };
-        info!("Succeeded to load checkpoint file {:?}", ckpt_filename);
+        info!(target: "checkpoint", "Succeeded to load checkpoint file {:?}", ckpt_filename);
         return Ok(Some(loader.0));
     }
 
@@ -560,25 +665,22 @@ impl<'de, Platform: Serialize + DeserializeOwned> Deserialize<'de> for PhactoryL
 }
 
 fn new_sr25519_key() -> sr25519::Pair {
-    let mut rng = rand::thread_rng();
     let mut seed = [0_u8; SEED_BYTES];
-    rng.fill_bytes(&mut seed);
+    entropy::fill(b"sr25519", &mut seed);
     sr25519::Pair::from_seed(&seed)
 }
 
 // TODO.kevin: Move to phactory-api when the std ready.
 fn generate_random_iv() -> aead::IV {
     let mut nonce_vec = [0u8; aead::IV_BYTES];
-    let rand = ring::rand::SystemRandom::new();
-    rand.fill(&mut nonce_vec).unwrap();
+    entropy::fill(b"iv", &mut nonce_vec);
     nonce_vec
 }
 
 #[allow(dead_code)]
 fn generate_random_info() -> [u8; 32] {
     let mut nonce_vec = [0u8; 32];
-    let rand = ring::rand::SystemRandom::new();
-    rand.fill(&mut nonce_vec).unwrap();
+    entropy::fill(b"info", &mut nonce_vec);
     nonce_vec
 }
 
@@ -592,6 +694,7 @@ fn error_msg(msg: &str) -> Value {
     json!({ "message": msg })
 }
 
-fn derive_key_for_checkpoint(identity_key: &[u8]) -> [u8; 16] {
+/// Derives the key encrypting the checkpoint streams from the identity secret key.
+pub fn derive_key_for_checkpoint(identity_key: &[u8]) -> [u8; 16] {
     sp_core::blake2_128(&(identity_key, b"/checkpoint").encode())
 }
@@ -254,11 +254,11 @@ impl From<JustificationError> for Error {
     fn from(e: JustificationError) -> Self {
         match e {
             JustificationError::BadJustification(msg) => {
-                error!("InvalidFinalityProof(BadJustification({}))", msg);
+                error!(target: "chain", "InvalidFinalityProof(BadJustification({}))", msg);
                 Error::InvalidFinalityProof
             }
             JustificationError::JustificationDecode => {
-                error!("InvalidFinalityProof(JustificationDecode)");
+                error!(target: "chain", "InvalidFinalityProof(JustificationDecode)");
                 Error::InvalidFinalityProof
             }
         }
@@ -306,9 +306,10 @@ where
     H: Header<Hash = H256>,
 {
     {
-        info!("ancestor_hash: {}", ancestor_hash);
+        info!(target: "chain", "ancestor_hash: {}", ancestor_hash);
         for h in proof.iter() {
             info!(
+                target: "chain",
                 "block {:?} - hash: {} parent: {}",
                 h.number(),
                 h.hash(),
@@ -316,6 +317,7 @@ where
             );
         }
         info!(
+            target: "chain",
             "child block {:?} - hash: {} parent: {}",
             child.number(),
             child.hash(),
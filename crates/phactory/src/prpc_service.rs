@@ -7,11 +7,69 @@ use pb::{
     phactory_api_server::{PhactoryApi, PhactoryApiServer},
     server::Error as RpcError,
 };
-use phactory_api::{blocks, crypto, prpc as pb};
+use phactory_api::{blocks, prpc as pb};
+use phala_crypto::query;
 use phala_types::{contract, WorkerPublicKey};
 
 type RpcResult<T> = Result<T, RpcError>;
 
+/// The facts used to tell whether the worker is ready to serve.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HealthInfo {
+    pub initialized: bool,
+    pub registered: bool,
+    /// Whether the worker is a gatekeeper registered on chain.
+    pub gatekeeper: bool,
+    pub master_key_available: bool,
+    /// The timestamp of the latest dispatched block, in milliseconds since the UNIX epoch.
+    pub last_block_time_ms: u64,
+    /// The egress messages not yet accepted on chain.
+    pub pending_messages: usize,
+}
+
+/// A decrypted contract query.
+pub(crate) struct OpenedQuery {
+    pub origin: Option<chain::AccountId>,
+    /// The last block the signature of the query is valid at
+    pub ttl: chain::BlockNumber,
+    pub reply_to: query::worker::ReplyTo,
+    pub head: contract::ContractQueryHead,
+    /// The query following the head
+    pub payload: Vec<u8>,
+}
+
+impl OpenedQuery {
+    /// Encrypts the result, the encoded `ContractQueryResponse` is the nonce followed by it.
+    pub fn seal_response(
+        &self,
+        ecdh_key: &EcdhKey,
+        result: &[u8],
+    ) -> RpcResult<pb::ContractQueryResponse> {
+        let encrypted_resp = query::worker::seal_response(
+            &mut crate::entropy::EntropyRng::new(b"query_response"),
+            ecdh_key,
+            &self.reply_to,
+            &self.head.nonce,
+            result,
+        )
+        .map_err(from_debug)?;
+        Ok(pb::ContractQueryResponse::new(encrypted_resp.into()))
+    }
+}
+
+/// Converts the pubkey of a query signer to its account id.
+///
+/// sr25519 and ed25519 pubkeys are used as the account id directly, while a compressed ECDSA
+/// pubkey is hashed the same way as Substrate does for ECDSA signers.
+fn account_id_from_pubkey(pubkey: &[u8]) -> RpcResult<chain::AccountId> {
+    use core::convert::TryFrom;
+    use phala_crypto::ecdsa;
+    if let Ok(pubkey) = ecdsa::EcdsaPublicKey::try_from(pubkey) {
+        return Ok(ecdsa::account_id(&pubkey).into());
+    }
+    chain::AccountId::try_from(pubkey).map_err(|_| from_display("Bad account id"))
+}
+
 fn from_display(e: impl core::fmt::Display) -> RpcError {
     RpcError::AppError(e.to_string())
 }
@@ -119,16 +177,47 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         }
     }
 
+    pub fn health_info(&self) -> HealthInfo {
+        let pending_messages = self
+            .runtime_state
+            .as_ref()
+            .map(|state| state.send_mq.count_messages())
+            .unwrap_or_default();
+        let system = match &self.system {
+            Some(system) => system,
+            None => {
+                return HealthInfo {
+                    pending_messages,
+                    ..Default::default()
+                }
+            }
+        };
+        HealthInfo {
+            initialized: true,
+            registered: system.is_registered(),
+            gatekeeper: system
+                .gatekeeper
+                .as_ref()
+                .map_or(false, |gk| gk.registered_on_chain()),
+            master_key_available: system.has_master_key(),
+            last_block_time_ms: system.now_ms,
+            pending_messages,
+        }
+    }
+
     pub(crate) fn sync_header(
         &mut self,
         headers: Vec<blocks::HeaderToSync>,
         authority_set_change: Option<blocks::AuthoritySetChange>,
     ) -> RpcResult<pb::SyncedTo> {
         info!(
+            target: "prpc",
             "sync_header from={:?} to={:?}",
             headers.first().map(|h| h.header.number),
             headers.last().map(|h| h.header.number)
         );
+        let _tag = heap_profile::enter(heap_profile::Subsystem::Sync);
+        let _span = tracing::info_span!("sync_header").entered();
         let last_header = self
             .runtime_state()?
             .storage_synchronizer
@@ -146,11 +235,14 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         proof: blocks::StorageProof,
     ) -> RpcResult<pb::SyncedTo> {
         info!(
+            target: "prpc",
             "sync_para_header from={:?} to={:?}",
             headers.first().map(|h| h.number),
             headers.last().map(|h| h.number)
         );
 
+        let _tag = heap_profile::enter(heap_profile::Subsystem::Sync);
+        let _span = tracing::info_span!("sync_para_header").entered();
         let state = self.runtime_state()?;
 
         let para_id = state
@@ -172,6 +264,47 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         })
     }
 
+    /// Bootstrap the chain storage from a snapshot of the parachain state instead of replaying
+    /// the blocks before it.
+    ///
+    /// The header of the snapshot must be proven by the last synced relaychain header, which is
+    /// finalized by its justification. The messages in the skipped blocks are not dispatched, so
+    /// this is only meant for new workers, not for gatekeepers.
+    pub(crate) fn import_state_snapshot(
+        &mut self,
+        header: chain::Header,
+        proof: blocks::StorageProof,
+        snapshot: blocks::StorageState,
+    ) -> RpcResult<pb::SyncedTo> {
+        info!(
+            target: "prpc",
+            "import_state_snapshot at={} pairs={}",
+            header.number,
+            snapshot.len()
+        );
+
+        let _tag = heap_profile::enter(heap_profile::Subsystem::Sync);
+        let _span = tracing::info_span!("import_state_snapshot").entered();
+        let state = self.runtime_state()?;
+
+        let para_id = state
+            .chain_storage
+            .para_id()
+            .ok_or_else(|| from_display("No para_id"))?;
+
+        let storage_key = light_validation::utils::storage_map_prefix_twox_64_concat(
+            b"Paras", b"Heads", &para_id,
+        );
+
+        let synced_to = state
+            .storage_synchronizer
+            .import_state_snapshot(header, proof, &storage_key, snapshot, &mut state.chain_storage)
+            .map_err(from_display)?;
+        info!(target: "prpc", "State snapshot imported: {:?}", state.chain_storage.root());
+
+        Ok(pb::SyncedTo { synced_to })
+    }
+
     /// Sync a combined batch of relaychain & parachain headers
     /// NOTE:
     ///   - The two latest headers MUST be aligned with each other by the `Para.Heads` read from the relaychain storage.
@@ -207,30 +340,45 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         mut blocks: Vec<blocks::BlockHeaderWithChanges>,
     ) -> RpcResult<pb::SyncedTo> {
         info!(
+            target: "prpc",
             "dispatch_block from={:?} to={:?}",
             blocks.first().map(|h| h.block_header.number),
             blocks.last().map(|h| h.block_header.number)
         );
+        if self.handed_over {
+            return Err(from_display("The worker key has been handed over"));
+        }
         let counters = self.runtime_state()?.storage_synchronizer.counters();
         blocks.retain(|b| b.block_header.number >= counters.next_block_number);
 
         let mut last_block = counters.next_block_number - 1;
         for block in blocks.into_iter() {
-            info!("Dispatching block: {}", block.block_header.number);
+            info!(target: "prpc", "Dispatching block: {}", block.block_header.number);
+            let _span = tracing::info_span!("block", number = block.block_header.number).entered();
             let state = self.runtime_state()?;
-            state
-                .storage_synchronizer
-                .feed_block(&block, &mut state.chain_storage)
-                .map_err(from_display)?;
+            {
+                let _tag = heap_profile::enter(heap_profile::Subsystem::Sync);
+                let _span = tracing::info_span!("feed_block").entered();
+                state
+                    .storage_synchronizer
+                    .feed_block(&block, &mut state.chain_storage)
+                    .map_err(from_display)?;
+            }
 
             state.purge_mq();
             self.handle_inbound_messages(block.block_header.number)?;
             self.poll_side_tasks(block.block_header.number)?;
+            self.subscriptions.mark_outdated();
             last_block = block.block_header.number;
 
             if let Err(e) = self.maybe_take_checkpoint(last_block) {
-                error!("Failed to take checkpoint: {:?}", e);
+                error!(target: "prpc", "Failed to take checkpoint: {:?}", e);
             }
+            self.heap_profiler.maybe_snapshot(
+                &self.platform,
+                self.args.heap_snapshot_interval,
+                last_block,
+            );
         }
 
         Ok(pb::SyncedTo {
@@ -239,7 +387,7 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
     }
 
     fn maybe_take_checkpoint(&mut self, current_block: chain::BlockNumber) -> anyhow::Result<()> {
-        if !self.args.enable_checkpoint {
+        if !self.args.enable_checkpoint || self.handed_over {
             return Ok(());
         }
         if self.last_checkpoint.elapsed().as_secs() < self.args.checkpoint_interval {
@@ -294,20 +442,20 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
 
         let ecdsa_pk = identity_key.public();
         let ecdsa_hex_pk = hex::encode(&ecdsa_pk);
-        info!("Identity pubkey: {:?}", ecdsa_hex_pk);
+        info!(target: "prpc", "Identity pubkey: {:?}", ecdsa_hex_pk);
 
         // derive ecdh key
         let ecdh_pubkey = phala_types::EcdhPublicKey(ecdh_key.public());
         let ecdh_hex_pk = hex::encode(ecdh_pubkey.0.as_ref());
-        info!("ECDH pubkey: {:?}", ecdh_hex_pk);
+        info!(target: "prpc", "ECDH pubkey: {:?}", ecdh_hex_pk);
 
         // Measure machine score
         let cpu_core_num: u32 = self.platform.cpu_core_num();
-        info!("CPU cores: {}", cpu_core_num);
+        info!(target: "prpc", "CPU cores: {}", cpu_core_num);
 
         let cpu_feature_level: u32 = self.platform.cpu_feature_level();
 
-        info!("CPU feature level: {}", cpu_feature_level);
+        info!(target: "prpc", "CPU feature level: {}", cpu_feature_level);
 
         // Build WorkerRegistrationInfo
         let runtime_info = WorkerRegistrationInfo::<chain::AccountId> {
@@ -333,6 +481,34 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
             Synchronizer::new_solochain(light_client, main_bridge)
         };
 
+        self.install_runtime(
+            storage_synchronizer,
+            genesis_block_hash,
+            genesis_state,
+            identity_key,
+            ecdh_key,
+        );
+
+        let resp = pb::InitRuntimeResponse::new(
+            runtime_info,
+            genesis_block_hash,
+            ecdsa_pk,
+            ecdh_pubkey,
+            None,
+        );
+        self.skip_ra = skip_ra;
+        self.runtime_info = Some(resp.clone());
+        Ok(resp)
+    }
+
+    fn install_runtime(
+        &mut self,
+        storage_synchronizer: Synchronizer<LightValidation<chain::Runtime>>,
+        genesis_block_hash: H256,
+        genesis_state: blocks::StorageState,
+        identity_key: sr25519::Pair,
+        ecdh_key: EcdhKey,
+    ) {
         let send_mq = MessageSendQueue::default();
         let recv_mq = MessageDispatcher::default();
 
@@ -350,6 +526,7 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         runtime_state.chain_storage.load(genesis_state.into_iter());
 
         info!(
+            target: "prpc",
             "Genesis state loaded: {:?}",
             runtime_state.chain_storage.root()
         );
@@ -366,18 +543,67 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
             contracts,
         );
 
-        let resp = pb::InitRuntimeResponse::new(
-            runtime_info,
-            genesis_block_hash,
-            ecdsa_pk,
-            ecdh_pubkey,
-            None,
-        );
-        self.skip_ra = skip_ra;
-        self.runtime_info = Some(resp.clone());
         self.runtime_state = Some(runtime_state);
         self.system = Some(system);
-        Ok(resp)
+    }
+
+    /// Initializes the runtime to replay recorded blocks with [`Self::replay_block`].
+    ///
+    /// The state at `genesis_block_hash` is trusted as is and no light client is set up, so the
+    /// runtime must not be fed with headers or blocks from a node afterwards.
+    pub fn init_replay(
+        &mut self,
+        genesis_block_hash: H256,
+        genesis_state: blocks::StorageState,
+        identity_key: sr25519::Pair,
+    ) -> anyhow::Result<()> {
+        if self.system.is_some() {
+            return Err(anyhow!("Runtime already initialized"));
+        }
+        let rt_data = self.init_runtime_data(genesis_block_hash, Some(identity_key))?;
+        self.dev_mode = rt_data.dev_mode;
+        self.skip_ra = true;
+        let (identity_key, ecdh_key) = rt_data.decode_keys();
+        let storage_synchronizer = Synchronizer::new_solochain(LightValidation::new(), 0);
+        self.install_runtime(
+            storage_synchronizer,
+            genesis_block_hash,
+            genesis_state,
+            identity_key,
+            ecdh_key,
+        );
+        Ok(())
+    }
+
+    /// Applies a recorded block and dispatches its messages, bypassing the header validation.
+    ///
+    /// The storage changes are still checked against the state root in the block header.
+    pub fn replay_block(&mut self, block: &blocks::BlockHeaderWithChanges) -> anyhow::Result<()> {
+        let block_number = block.block_header.number;
+        let state = self
+            .runtime_state
+            .as_mut()
+            .ok_or_else(|| anyhow!("Runtime not initialized"))?;
+        let changes = &block.storage_changes;
+        let (state_root, transaction) = state.chain_storage.calc_root_if_changes(
+            &changes.main_storage_changes,
+            &changes.child_storage_changes,
+        );
+        if state_root != block.block_header.state_root {
+            return Err(anyhow!(
+                "State root mismatch at block {}: expected={:?} actual={:?}",
+                block_number,
+                block.block_header.state_root,
+                state_root
+            ));
+        }
+        state.chain_storage.apply_changes(state_root, transaction);
+        state.purge_mq();
+        self.handle_inbound_messages(block_number)
+            .map_err(|err| anyhow!("Failed to handle inbound messages: {:?}", err))?;
+        self.poll_side_tasks(block_number)
+            .map_err(|err| anyhow!("Failed to poll side tasks: {:?}", err))?;
+        Ok(())
     }
 
     fn get_runtime_info(
@@ -415,15 +641,15 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
                 // We hash the encoded bytes directly
                 let runtime_info_hash =
                     sp_core::hashing::blake2_256(&cached_resp.encoded_runtime_info);
-                info!("Encoded runtime info");
-                info!("{:?}", hex::encode(&cached_resp.encoded_runtime_info));
+                info!(target: "prpc", "Encoded runtime info");
+                info!(target: "prpc", "{:?}", hex::encode(&cached_resp.encoded_runtime_info));
 
                 let (attn_report, sig, cert) =
                     match self.platform.create_attestation_report(&runtime_info_hash) {
                         Ok(r) => r,
                         Err(e) => {
                             let message = format!("Failed to create attestation report: {:?}", e);
-                            error!("{}", message);
+                            error!(target: "prpc", "{}", message);
                             return Err(from_display(message));
                         }
                     };
@@ -454,17 +680,18 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         Ok(fit_size(messages, output_buf_len))
     }
 
-    fn contract_query(
-        &mut self,
-        request: pb::ContractQueryRequest,
-    ) -> RpcResult<impl FnOnce() -> RpcResult<pb::ContractQueryResponse>> {
+    /// Verifies the signature of a contract query and decrypts it.
+    fn open_contract_query(&mut self, request: pb::ContractQueryRequest) -> RpcResult<OpenedQuery> {
         // Validate signature
-        let origin = if let Some(sig) = &request.signature {
+        let (origin, ttl) = if let Some(sig) = &request.signature {
             let current_block = self.get_info().blocknum - 1;
             // At most two level cert chain supported
             match sig.verify(&request.encoded_encrypted_data, current_block, 2) {
                 Ok(key_chain) => match &key_chain[..] {
-                    [root_pubkey, ..] => Some(root_pubkey.clone()),
+                    [root_pubkey, ..] => {
+                        let ttl = sig.ttl().map_err(from_debug)?;
+                        (Some(root_pubkey.clone()), ttl)
+                    }
                     _ => {
                         return Err(from_display("BUG: verify ok but no key?"));
                     }
@@ -477,59 +704,94 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
                 }
             }
         } else {
-            info!("No query signature");
-            None
+            info!(target: "prpc", "No query signature");
+            (None, chain::BlockNumber::MAX)
         };
 
-        info!("Verifying signature passed! origin={:?}", origin);
+        info!(target: "prpc", "Verifying signature passed! origin={:?}", origin);
 
-        let ecdh_key = self.system()?.ecdh_key.clone();
+        let ecdh_key = &self.system()?.ecdh_key;
 
         // Decrypt data
         let encrypted_req = request.decode_encrypted_data()?;
-        let data = encrypted_req.decrypt(&ecdh_key).map_err(from_debug)?;
+        let (reply_to, data) = query::worker::open_query(ecdh_key, &encrypted_req.into())
+            .map_err(from_debug)?;
 
         // Decode head
         let mut data_cursor = &data[..];
         let head = contract::ContractQueryHead::decode(&mut data_cursor)?;
-        let rest = data_cursor.len();
+        let payload = data_cursor.to_vec();
 
         // Origin
-        let accid_origin = match origin {
-            Some(origin) => {
-                use core::convert::TryFrom;
-                let accid = chain::AccountId::try_from(origin.as_slice())
-                    .map_err(|_| from_display("Bad account id"))?;
-                Some(accid)
-            }
+        let origin = match origin {
+            Some(origin) => Some(account_id_from_pubkey(&origin)?),
             None => None,
         };
 
+        Ok(OpenedQuery {
+            origin,
+            ttl,
+            reply_to,
+            head,
+            payload,
+        })
+    }
+
+    fn contract_query(
+        &mut self,
+        request: pb::ContractQueryRequest,
+    ) -> RpcResult<impl FnOnce() -> RpcResult<pb::ContractQueryResponse>> {
+        let query = self.open_contract_query(request)?;
+        let ecdh_key = self.system()?.ecdh_key.clone();
+
         // Dispatch
-        let call = self.system()?.make_query(&head.id)?;
+        let call = self.system()?.make_query(&query.head.id)?;
 
         Ok(move || {
-            // Encode response
-            let response = contract::ContractQueryResponse {
-                nonce: head.nonce,
-                result: contract::Data(call(accid_origin.as_ref(), &data[data.len() - rest..])?),
-            };
-            let response_data = response.encode();
-
-            // Encrypt
-            let encrypted_resp = crypto::EncryptedData::encrypt(
-                &ecdh_key,
-                &encrypted_req.pubkey,
-                crate::generate_random_iv(),
-                &response_data,
-            )
-            .map_err(from_debug)?;
-
-            Ok(pb::ContractQueryResponse::new(encrypted_resp))
+            let _tag = heap_profile::enter(heap_profile::Subsystem::Query);
+            let result = call(query.origin.as_ref(), &query.payload)?;
+            query.seal_response(&ecdh_key, &result)
         })
     }
 
+    /// Subscribes to the result of a contract query, see [`subscription`]. Returns the poll to
+    /// push the current result.
+    fn subscribe_contract_query(
+        &mut self,
+        request: pb::ContractQueryRequest,
+    ) -> RpcResult<(subscription::Receiver, subscription::Poll)> {
+        if self.subscriptions.len() >= subscription::MAX_SUBSCRIPTIONS {
+            return Err(from_display("Too many subscriptions"));
+        }
+        let query = self.open_contract_query(request)?;
+        let origin = query
+            .origin
+            .as_ref()
+            .ok_or_else(|| from_display("Subscriptions must be signed"))?;
+        if self.subscriptions.count_of(origin) >= subscription::MAX_SUBSCRIPTIONS_PER_ORIGIN {
+            return Err(from_display("Too many subscriptions of the origin"));
+        }
+        let system = self
+            .system
+            .as_mut()
+            .ok_or_else(|| from_display("Runtime not initialized"))?;
+        Ok(self.subscriptions.subscribe(query, system)?)
+    }
+
+    /// Prepares the reruns of the subscribed queries if blocks have been dispatched since the
+    /// last poll.
+    fn prepare_subscription_polls(&mut self) -> Vec<subscription::Poll> {
+        let _tag = heap_profile::enter(heap_profile::Subsystem::Query);
+        let _span = tracing::info_span!("prepare_subscription_polls").entered();
+        match self.system.as_mut() {
+            Some(system) => self.subscriptions.prepare_polls(system),
+            None => vec![],
+        }
+    }
+
     fn handle_inbound_messages(&mut self, block_number: chain::BlockNumber) -> RpcResult<()> {
+        let _tag = heap_profile::enter(heap_profile::Subsystem::Messages);
+        let _span = tracing::info_span!("handle_inbound_messages").entered();
         let state = self
             .runtime_state
             .as_mut()
@@ -581,7 +843,7 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         let mut guard = scopeguard::guard(&mut state.recv_mq, |mq| {
             let n_unhandled = mq.clear();
             if n_unhandled > 0 {
-                warn!("There are {} unhandled messages dropped", n_unhandled);
+                warn!(target: "prpc", "There are {} unhandled messages dropped", n_unhandled);
             }
         });
 
@@ -597,6 +859,7 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
         // It need about 30 more seconds to sync up to date.
         let ready = block_time + 3600 > sys_time;
         debug!(
+            target: "prpc",
             "block_time={}, sys_time={}, ready={}",
             block_time, sys_time, ready
         );
@@ -619,6 +882,8 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> Phactory<Platform>
     }
 
     fn poll_side_tasks(&mut self, block_number: chain::BlockNumber) -> RpcResult<()> {
+        let _tag = heap_profile::enter(heap_profile::Subsystem::SideTasks);
+        let _span = tracing::info_span!("poll_side_tasks").entered();
         let state = self
             .runtime_state
             .as_ref()
@@ -642,16 +907,14 @@ pub fn dispatch_prpc_request<Platform>(
 where
     Platform: pal::Platform + Serialize + DeserializeOwned,
 {
-    use prpc::server::{Error, ProtoError};
-
     let path = match std::str::from_utf8(path) {
         Ok(path) => path,
         Err(e) => {
-            error!("prpc_request: invalid path: {}", e);
+            error!(target: "prpc", "prpc_request: invalid path: {}", e);
             return (400, b"Invalid path".to_vec());
         }
     };
-    info!("Dispatching request: {}", path);
+    info!(target: "prpc", "Dispatching request: {}", path);
 
     let mut server = PhactoryApiServer::new(RpcService {
         output_buf_len,
@@ -659,23 +922,75 @@ where
     });
     let (code, data) = match server.dispatch_request(path, data.to_vec()) {
         Ok(data) => (200, data),
-        Err(err) => {
-            error!("Rpc error: {:?}", err);
-            let (code, err) = match err {
-                Error::NotFound => (404, ProtoError::new("Method Not Found")),
-                Error::DecodeError(err) => {
-                    (400, ProtoError::new(format!("DecodeError({:?})", err)))
-                }
-                Error::AppError(msg) => (500, ProtoError::new(msg)),
-                Error::ContractQueryError(msg) => (500, ProtoError::new(msg)),
-            };
-            (code, prpc::codec::encode_message_to_vec(&err))
-        }
+        Err(err) => encode_rpc_error(err),
     };
 
     (code, data)
 }
 
+fn encode_rpc_error(err: RpcError) -> (u16, Vec<u8>) {
+    use prpc::server::{Error, ProtoError};
+
+    error!(target: "prpc", "Rpc error: {:?}", err);
+    let (code, err) = match err {
+        Error::NotFound => (404, ProtoError::new("Method Not Found")),
+        Error::DecodeError(err) => (400, ProtoError::new(format!("DecodeError({:?})", err))),
+        Error::AppError(msg) => (500, ProtoError::new(msg)),
+        Error::ContractQueryError(msg) => (500, ProtoError::new(msg)),
+    };
+    (code, prpc::codec::encode_message_to_vec(&err))
+}
+
+/// Subscribes to a contract query given the encoded `ContractQueryRequest`. Returns the receiver
+/// of the encoded `ContractQueryResponse`s, or the status code and the encoded error the same way
+/// as `dispatch_prpc_request`.
+pub fn subscribe_contract_query<Platform>(
+    data: &[u8],
+    phactory: &Mutex<Phactory<Platform>>,
+) -> Result<subscription::Receiver, (u16, Vec<u8>)>
+where
+    Platform: pal::Platform + Serialize + DeserializeOwned,
+{
+    use prpc::Message as _;
+
+    let request = pb::ContractQueryRequest::decode(data)
+        .map_err(|err| encode_rpc_error(RpcError::DecodeError(err)))?;
+    subscription::run(|| match phactory.lock().unwrap().subscribe_contract_query(request) {
+        Ok((receiver, poll)) => (Ok(receiver), vec![poll]),
+        Err(err) => (Err(encode_rpc_error(err)), vec![]),
+    })
+}
+
+/// Reruns the subscribed queries against the latest state, without holding the `Phactory` lock
+/// while the queries run.
+fn poll_subscriptions<Platform>(phactory: &Mutex<Phactory<Platform>>)
+where
+    Platform: pal::Platform + Serialize + DeserializeOwned,
+{
+    let _span = tracing::info_span!("poll_subscriptions").entered();
+    subscription::run(|| ((), phactory.lock().unwrap().prepare_subscription_polls()));
+}
+
+/// Starts the thread rerunning the subscribed queries after blocks are dispatched, so that the
+/// reruns do not delay the responses to `dispatch_blocks`.
+///
+/// To be called once the `Phactory` in the mutex is initialized or restored, since the thread
+/// waits on the signal of that instance.
+pub fn start_subscription_poller<Platform>(phactory: &'static Mutex<Phactory<Platform>>)
+where
+    Platform: pal::Platform + Serialize + DeserializeOwned,
+    Phactory<Platform>: Send,
+{
+    let signal = phactory.lock().unwrap().subscriptions.signal();
+    std::thread::Builder::new()
+        .name("subscriptions".into())
+        .spawn(move || loop {
+            signal.wait();
+            poll_subscriptions(phactory);
+        })
+        .expect("Failed to start the subscription poller");
+}
+
 pub struct RpcService<'a, Platform> {
     output_buf_len: usize,
     phactory: &'a Mutex<Phactory<Platform>>,
@@ -763,7 +1078,11 @@ impl<Platform: pal::Platform + Serialize + DeserializeOwned> PhactoryApi
         &mut self,
         request: pb::ContractQueryRequest,
     ) -> RpcResult<pb::ContractQueryResponse> {
-        let do_query = self.lock_phactory().contract_query(request)?;
+        let do_query = {
+            let _span = tracing::info_span!("open_query").entered();
+            self.lock_phactory().contract_query(request)?
+        };
+        let _span = tracing::info_span!("run_query").entered();
         do_query()
     }
 
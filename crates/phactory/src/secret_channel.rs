@@ -187,16 +187,26 @@ mod receiver {
         Wrp: Decode,
     {
         pub fn try_next(&mut self) -> Result<Option<(u64, Msg, MessageOrigin)>, anyhow::Error> {
+            let omsg = self.try_next_with_payload()?;
+            Ok(omsg.map(|(seq, msg, origin, _)| (seq, msg, origin)))
+        }
+
+        /// Same as `try_next`, but also returns the raw payload of the message as it was sent,
+        /// e.g. the encrypted one.
+        #[allow(clippy::type_complexity)]
+        pub fn try_next_with_payload(
+            &mut self,
+        ) -> Result<Option<(u64, Msg, MessageOrigin, Vec<u8>)>, anyhow::Error> {
             let omsg = self
                 .receiver
-                .try_next()
+                .try_next_with_payload()
                 .map_err(|e| anyhow::anyhow!("{}", e))?;
-            let (seq, msg, origin) = match omsg {
+            let (seq, msg, origin, payload) = match omsg {
                 Some(x) => x,
                 None => return Ok(None),
             };
             let msg = self.peeler.peel(msg)?;
-            Ok(Some((seq, msg, origin)))
+            Ok(Some((seq, msg, origin, payload)))
         }
 
         pub fn peek_ind(&self) -> Result<Option<u64>, ReceiveError> {
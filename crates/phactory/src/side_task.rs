@@ -62,6 +62,7 @@ impl SideTaskManager {
                 continue;
             }
             error!(
+                target: "side_task",
                 "BUG: side task end at past block, end_block={} current_block={}",
                 task.end_block, context.block_number
             );
@@ -144,7 +145,7 @@ pub mod async_side_task {
             let task = phala_async_executor::spawn(async move {
                 let result = future.await;
                 if let Err(err) = &result {
-                    log::error!("Async side task returns error: {:?}", err);
+                    log::error!(target: "side_task", "Async side task returns error: {:?}", err);
                 }
                 *set_result.lock().unwrap() = result.ok();
             });
@@ -52,7 +52,7 @@ mod storage_ext {
                 .map(|v| match Decode::decode(&mut &v[..]) {
                     Ok(decoded) => Ok(decoded),
                     Err(e) => {
-                        error!("Decode storage value failed: {}", e);
+                        error!(target: "chain", "Decode storage value failed: {}", e);
                         Err(e)
                     }
                 })
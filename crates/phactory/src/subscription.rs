@@ -0,0 +1,282 @@
+//! Contract query subscriptions.
+//!
+//! A subscription is a contract query, signed and encrypted the same way as a `ContractQuery`
+//! request, which is run again whenever the state it reads changes. The sealed response is pushed
+//! to the subscriber whenever the result changes, so the contract keeps deciding what each origin
+//! can read, and frontends do not have to poll the query.
+//!
+//! Only signed queries can subscribe, and each origin holds a limited number of subscriptions. A
+//! subscription ends once the certificate of its signature expires.
+//!
+//! The reruns are done by the poller thread, see `start_subscription_poller`, woken up after
+//! blocks are dispatched. A query is rerun only if the state of its contract or the storage of
+//! its cluster has changed since the last run, so a command, an event or a call from another
+//! contract triggers it, but the queries depending on the block time alone are not rerun. The
+//! queries are prepared against the latest state while holding the `Phactory` lock, but run
+//! after the lock is released, so that the subscriptions do not stall the block sync. The reruns
+//! are not billed to the clusters.
+//!
+//! All the responses of a subscription echo the nonce of the query. The subscriptions are not
+//! persisted in the checkpoints; the subscribers are expected to subscribe again after pRuntime
+//! restarts.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex};
+
+use log::{info, warn};
+use phala_crypto::ecdh::EcdhKey;
+use phala_mq::ContractId;
+use sp_core::H256;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::prpc_service::OpenedQuery;
+use crate::system::System;
+use crate::types::{OpaqueError, OpaqueQuery, OpaqueReply};
+
+/// The max number of active subscriptions of a worker.
+pub(crate) const MAX_SUBSCRIPTIONS: usize = 64;
+
+/// The max number of active subscriptions of an origin.
+pub(crate) const MAX_SUBSCRIPTIONS_PER_ORIGIN: usize = 8;
+
+/// The number of responses buffered for a subscriber. A subscriber falling behind is dropped.
+const QUEUE_SIZE: usize = 16;
+
+lazy_static! {
+    /// Serializes the polls, so that the responses of a subscription are pushed in order.
+    static ref POLLING: Mutex<()> = Mutex::new(());
+}
+
+/// Receives the encoded `ContractQueryResponse`s of a subscription.
+pub type Receiver = mpsc::Receiver<Vec<u8>>;
+
+type Call =
+    Box<dyn FnOnce(Option<&chain::AccountId>, OpaqueQuery) -> Result<OpaqueReply, OpaqueError>>;
+
+struct State {
+    last_result: Option<Vec<u8>>,
+    /// The `System::query_state_digest` of the contract at the last run
+    last_digest: Option<H256>,
+    /// Taken when the subscription is dropped, which ends the stream of the subscriber.
+    tx: Option<mpsc::Sender<Vec<u8>>>,
+}
+
+struct Subscription {
+    query: OpenedQuery,
+    state: Mutex<State>,
+}
+
+impl Subscription {
+    fn is_active(&self) -> bool {
+        match &self.state.lock().unwrap().tx {
+            Some(tx) => !tx.is_closed(),
+            None => false,
+        }
+    }
+
+    fn drop_with(&self, reason: impl core::fmt::Debug) {
+        warn!(target: "subscription", "Dropping subscription: {:?}", reason);
+        self.state.lock().unwrap().tx = None;
+    }
+
+    /// Prepares the query against the current state, unless the state it reads is unchanged.
+    fn prepare<P: pal::Platform>(
+        self: &Arc<Self>,
+        system: &mut System<P>,
+        digest: Option<H256>,
+    ) -> Option<Poll> {
+        if self.query.ttl < system.block_number() {
+            self.drop_with("the certificate expired");
+            return None;
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            if digest.is_some() && state.last_digest == digest {
+                return None;
+            }
+            state.last_digest = digest;
+        }
+        match system.make_unbilled_query(&self.query.head.id) {
+            Ok(call) => Some(Poll {
+                subscription: self.clone(),
+                call: Box::new(call),
+                ecdh_key: system.ecdh_key.clone(),
+            }),
+            Err(err) => {
+                self.drop_with(err);
+                None
+            }
+        }
+    }
+}
+
+/// A prepared query of a subscription, to be run without holding the `Phactory` lock.
+pub(crate) struct Poll {
+    subscription: Arc<Subscription>,
+    call: Call,
+    ecdh_key: EcdhKey,
+}
+
+impl Poll {
+    /// Runs the query and pushes the result if changed.
+    fn run(self) {
+        let subscription = &self.subscription;
+        if !subscription.is_active() {
+            return;
+        }
+        let query = &subscription.query;
+        let result = match (self.call)(query.origin.as_ref(), &query.payload) {
+            Ok(result) => result,
+            Err(err) => return subscription.drop_with(err),
+        };
+        let mut state = subscription.state.lock().unwrap();
+        if state.last_result.as_ref() == Some(&result) {
+            return;
+        }
+        let response = match query.seal_response(&self.ecdh_key, &result) {
+            Ok(response) => response,
+            Err(err) => {
+                drop(state);
+                return subscription.drop_with(err);
+            }
+        };
+        state.last_result = Some(result);
+        let tx = match &state.tx {
+            Some(tx) => tx,
+            None => return,
+        };
+        match tx.try_send(prpc::codec::encode_message_to_vec(&response)) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                drop(state);
+                subscription.drop_with("the subscriber is lagging");
+            }
+            Err(TrySendError::Closed(_)) => state.tx = None,
+        }
+    }
+}
+
+/// Runs the polls returned by `prepare`, in order with the polls of the other threads.
+///
+/// The `Phactory` lock must not be held by the caller, `prepare` takes it after `POLLING`.
+pub(crate) fn run<T>(prepare: impl FnOnce() -> (T, Vec<Poll>)) -> T {
+    let _guard = POLLING.lock().unwrap();
+    let (output, polls) = prepare();
+    for poll in polls {
+        poll.run();
+    }
+    output
+}
+
+/// Wakes up the poller thread once blocks are dispatched.
+#[derive(Default)]
+pub(crate) struct PollSignal {
+    outdated: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl PollSignal {
+    fn notify(&self) {
+        *self.outdated.lock().unwrap() = true;
+        self.condvar.notify_one();
+    }
+
+    /// Waits until blocks have been dispatched since the last call.
+    pub fn wait(&self) {
+        let mut outdated = self.outdated.lock().unwrap();
+        while !*outdated {
+            outdated = self.condvar.wait(outdated).unwrap();
+        }
+        *outdated = false;
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Subscriptions {
+    list: Vec<Arc<Subscription>>,
+    /// Whether blocks have been dispatched since the last poll.
+    outdated: bool,
+    signal: Arc<PollSignal>,
+}
+
+impl Subscriptions {
+    fn prune(&mut self) {
+        self.list.retain(|subscription| subscription.is_active());
+    }
+
+    pub fn len(&mut self) -> usize {
+        self.prune();
+        self.list.len()
+    }
+
+    /// The number of active subscriptions of the given origin.
+    pub fn count_of(&mut self, origin: &chain::AccountId) -> usize {
+        self.prune();
+        self.list
+            .iter()
+            .filter(|subscription| subscription.query.origin.as_ref() == Some(origin))
+            .count()
+    }
+
+    /// Adds a subscription. Returns the receiver of the responses, and the poll to push the
+    /// current result to it.
+    pub fn subscribe<P: pal::Platform>(
+        &mut self,
+        query: OpenedQuery,
+        system: &mut System<P>,
+    ) -> Result<(Receiver, Poll), OpaqueError> {
+        // Fail early if the contract does not exist.
+        let call = system.make_unbilled_query(&query.head.id)?;
+        let digest = system.query_state_digest(&query.head.id);
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        info!(target: "subscription", "New subscription to contract {:?}", query.head.id);
+        let subscription = Arc::new(Subscription {
+            query,
+            state: Mutex::new(State {
+                last_result: None,
+                last_digest: digest,
+                tx: Some(tx),
+            }),
+        });
+        self.list.push(subscription.clone());
+        let poll = Poll {
+            subscription,
+            call: Box::new(call),
+            ecdh_key: system.ecdh_key.clone(),
+        };
+        Ok((rx, poll))
+    }
+
+    /// The signal the poller thread waits on.
+    pub fn signal(&self) -> Arc<PollSignal> {
+        self.signal.clone()
+    }
+
+    /// Marks the subscribed results outdated and wakes up the poller, after a block is
+    /// dispatched.
+    pub fn mark_outdated(&mut self) {
+        self.outdated = !self.list.is_empty();
+        if self.outdated {
+            self.signal.notify();
+        }
+    }
+
+    /// Prepares the subscribed queries whose contract states have changed since the last poll.
+    pub fn prepare_polls<P: pal::Platform>(&mut self, system: &mut System<P>) -> Vec<Poll> {
+        if !std::mem::take(&mut self.outdated) {
+            return vec![];
+        }
+        self.prune();
+        let mut digests = BTreeMap::<ContractId, Option<H256>>::new();
+        self.list
+            .iter()
+            .filter_map(|subscription| {
+                let id = subscription.query.head.id;
+                let digest = *digests
+                    .entry(id)
+                    .or_insert_with(|| system.query_state_digest(&id));
+                subscription.prepare(system, digest)
+            })
+            .collect()
+    }
+}
@@ -13,7 +13,7 @@ use phala_types::{
         MiningInfoUpdateEvent, MiningReportEvent, RandomNumber, RandomNumberEvent, SettleInfo,
         SystemEvent, WorkerEvent, WorkerEventWithKey,
     },
-    EcdhPublicKey, WorkerPublicKey,
+    EcdhPublicKey, WorkerIdentity, WorkerPublicKey,
 };
 use serde::{Deserialize, Serialize};
 use sp_core::{hashing, sr25519, Pair};
@@ -156,14 +156,13 @@ where
     }
 
     pub fn register_on_chain(&mut self) {
-        info!("Gatekeeper: register on chain");
+        info!(target: "gk", "Gatekeeper: register on chain");
         self.egress.set_dummy(false);
         self.registered_on_chain = true;
     }
 
-    #[allow(unused)]
     pub fn unregister_on_chain(&mut self) {
-        info!("Gatekeeper: unregister on chain");
+        info!(target: "gk", "Gatekeeper: unregister on chain");
         self.egress.set_dummy(true);
         self.registered_on_chain = false;
     }
@@ -182,7 +181,7 @@ where
         ecdh_pubkey: &EcdhPublicKey,
         block_number: chain::BlockNumber,
     ) {
-        info!("Gatekeeper: try dispatch master key");
+        info!(target: "gk", "Gatekeeper: try dispatch master key");
         let master_key = self.master_key.dump_secret_key();
         let encrypted_key = self.encrypt_key_to(
             &[b"master_key_sharing"],
@@ -201,11 +200,14 @@ where
 
     pub fn process_messages(&mut self, block: &BlockInfo<'_>) {
         if !self.master_pubkey_on_chain {
-            info!("Gatekeeper: not handling the messages because Gatekeeper has not launched on chain");
+            info!(
+                target: "gk",
+                "Gatekeeper: not handling the messages because Gatekeeper has not launched on chain"
+            );
             return;
         }
 
-        debug!("Gatekeeper: processing block {}", block.block_number);
+        debug!(target: "gk", "Gatekeeper: processing block {}", block.block_number);
         loop {
             let ok = phala_mq::select_ignore_errors! {
                 (event, origin) = self.gatekeeper_events => {
@@ -214,6 +216,7 @@ where
                 (event, origin) = self.cluster_events => {
                     if let Err(err) = self.process_cluster_event(block, origin, event) {
                         error!(
+                            target: "gk",
                             "Failed to process cluster event: {:?}",
                             err
                         );
@@ -228,11 +231,11 @@ where
 
         self.mining_economics.process_messages(block);
 
-        debug!("Gatekeeper: processed block {}", block.block_number);
+        debug!(target: "gk", "Gatekeeper: processed block {}", block.block_number);
     }
 
     fn process_gatekeeper_event(&mut self, origin: MessageOrigin, event: GatekeeperEvent) {
-        debug!("Incoming gatekeeper event: {:?}", event);
+        debug!(target: "gk", "Incoming gatekeeper event: {:?}", event);
         match event {
             GatekeeperEvent::NewRandomNumber(random_number_event) => {
                 self.process_random_number_event(origin, random_number_event)
@@ -290,11 +293,11 @@ where
         origin: MessageOrigin,
         event: ClusterEvent,
     ) -> Result<(), TransactionError> {
-        info!("Incoming cluster event: {:?}", event);
+        info!(target: "gk", "Incoming cluster event: {:?}", event);
         match event {
             ClusterEvent::DeployCluster { cluster, workers } => {
                 if !origin.is_pallet() {
-                    error!("Attempt to deploy cluster from bad origin");
+                    error!(target: "gk", "Attempt to deploy cluster from bad origin");
                     return Err(TransactionError::BadOrigin);
                 }
 
@@ -308,35 +311,55 @@ where
                     });
                 // then distribute cluster key to all workers in one event
                 // the on-chain deployment state should be updated by assigned workers
-                // TODO.shelven: set up expiration
-                let secret_key = cluster_key.dump_secret_key();
-                let secret_keys: BTreeMap<_, _> = workers
-                    .into_iter()
-                    .map(|worker| {
-                        let encrypted_key = self.encrypt_key_to(
-                            &[b"cluster_key_sharing"],
-                            &worker.ecdh_pubkey,
-                            &secret_key,
-                            block.block_number,
-                        );
-                        (worker.pubkey, encrypted_key)
-                    })
-                    .collect();
-                self.egress
-                    .push_message(&ClusterKeyDistribution::batch_distribution(
-                        secret_keys,
-                        cluster,
-                        0,
-                    ));
+                self.distribute_cluster_key(block, cluster, workers);
+                Ok(())
+            }
+            ClusterEvent::AddWorkers { cluster, workers } => {
+                if !origin.is_pallet() {
+                    error!(target: "gk", "Attempt to add cluster workers from bad origin");
+                    return Err(TransactionError::BadOrigin);
+                }
+                self.distribute_cluster_key(block, cluster, workers);
                 Ok(())
             }
+            // Handled by the workers in the cluster
+            ClusterEvent::RemoveWorkers { .. } | ClusterEvent::DestroyCluster { .. } => Ok(()),
         }
     }
 
+    fn distribute_cluster_key(
+        &mut self,
+        block: &BlockInfo<'_>,
+        cluster: ContractClusterId,
+        workers: Vec<WorkerIdentity>,
+    ) {
+        // TODO.shelven: set up expiration
+        let cluster_key = get_cluster_key(&self.master_key, &cluster);
+        let secret_key = cluster_key.dump_secret_key();
+        let secret_keys: BTreeMap<_, _> = workers
+            .into_iter()
+            .map(|worker| {
+                let encrypted_key = self.encrypt_key_to(
+                    &[b"cluster_key_sharing"],
+                    &worker.ecdh_pubkey,
+                    &secret_key,
+                    block.block_number,
+                );
+                (worker.pubkey, encrypted_key)
+            })
+            .collect();
+        self.egress
+            .push_message(&ClusterKeyDistribution::batch_distribution(
+                secret_keys,
+                cluster,
+                0,
+            ));
+    }
+
     /// Verify on-chain random number
     fn process_random_number_event(&mut self, origin: MessageOrigin, event: RandomNumberEvent) {
         if !origin.is_gatekeeper() {
-            error!("Invalid origin {:?} sent a {:?}", origin, event);
+            error!(target: "gk", "Invalid origin {:?} sent a {:?}", origin, event);
             return;
         };
 
@@ -347,7 +370,7 @@ where
         );
         // instead of checking the origin, we directly verify the random to avoid access storage
         if expect_random != event.random_number {
-            error!("Fatal error: Expect random number {:?}", expect_random);
+            error!(target: "gk", "Fatal error: Expect random number {:?}", expect_random);
             #[cfg(not(feature = "shadow-gk"))]
             panic!("GK state poisoned");
         }
@@ -361,6 +384,7 @@ where
         let random_number =
             next_random_number(&self.master_key, block_number, self.last_random_number);
         info!(
+            target: "gk",
             "Gatekeeper: emit random number {} in block {}",
             hex::encode(&random_number),
             block_number
@@ -702,7 +726,7 @@ where
         let worker_pubkey = if let MessageOrigin::Worker(pubkey) = origin {
             pubkey
         } else {
-            error!("Invalid origin {:?} sent a {:?}", origin, event);
+            error!(target: "gk", "Invalid origin {:?} sent a {:?}", origin, event);
             return;
         };
         match event {
@@ -808,7 +832,7 @@ where
 
     fn process_system_event(&mut self, origin: MessageOrigin, event: SystemEvent) {
         if !origin.is_pallet() {
-            error!("Invalid origin {:?} sent a {:?}", origin, event);
+            error!(target: "gk", "Invalid origin {:?} sent a {:?}", origin, event);
             return;
         }
 
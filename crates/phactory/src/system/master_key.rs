@@ -37,7 +37,7 @@ pub fn seal(
 
     let data = MasterKeySeal::V1(PersistentMasterKey { secret, signature });
     let filepath = master_key_file_path(sealing_path);
-    info!("Seal master key to {}", filepath.as_path().display());
+    info!(target: "gk", "Seal master key to {}", filepath.as_path().display());
     sys.seal_data(filepath, &data.encode())
         .expect("Seal master key failed");
 }
@@ -51,14 +51,14 @@ pub fn try_unseal(
     sys: &impl Sealing,
 ) -> Option<sr25519::Pair> {
     let filepath = master_key_file_path(sealing_path);
-    info!("Unseal master key from {}", filepath.as_path().display());
+    info!(target: "gk", "Unseal master key from {}", filepath.as_path().display());
     let sealed_data = match sys
         .unseal_data(&filepath)
         .expect("Unseal master key failed")
     {
         Some(data) => data,
         None => {
-            warn!("No sealed master key");
+            warn!(target: "gk", "No sealed master key");
             return None;
         }
     };
@@ -1,10 +1,12 @@
 pub mod gk;
 mod master_key;
 mod side_tasks;
+mod sidevm_codes;
 
 use crate::{
     benchmark,
-    contracts::{pink::cluster::Cluster, AnyContract, ContractsKeeper, ExecuteEnv},
+    contracts::{price_oracle::PriceFeeds, AnyContract, ContractsKeeper, ExecuteEnv},
+    heap_profile,
     pink::{cluster::ClusterKeeper, Pink},
     secret_channel::{ecdh_serde, SecretReceiver},
     types::{BlockInfo, OpaqueError, OpaqueQuery, OpaqueReply},
@@ -32,21 +34,34 @@ use phala_mq::{
 };
 use phala_serde_more as more;
 use phala_types::{
-    contract::{self, messaging::ContractOperation, CodeIndex},
+    contract::{
+        self,
+        messaging::{ClusterEvent, ContractOperation, SidevmCodeEvent, WorkerUsageReport},
+        CodeIndex,
+    },
     messaging::{
         AeadIV, BatchDispatchClusterKeyEvent, ClusterKeyDistribution, DispatchMasterKeyEvent,
         GatekeeperChange, GatekeeperLaunch, HeartbeatChallenge, KeyDistribution, MiningReportEvent,
-        NewGatekeeperEvent, SystemEvent, WorkerClusterReport, WorkerContractReport, WorkerEvent,
+        NewGatekeeperEvent, RemoveGatekeeperEvent, SystemEvent, WorkerClusterReport,
+        WorkerContractReport, WorkerEvent,
     },
     EcdhPublicKey, WorkerPublicKey,
 };
 use serde::{Deserialize, Serialize};
 use side_tasks::geo_probe;
-use sidevm::service::Spawner;
+use sidevm_codes::SidevmCodeRegistry;
+use sidevm::service::{Spawner, VmState, VmStatus};
 use sp_core::{hashing::blake2_256, sr25519, Pair, U256};
+use std::convert::TryInto;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 pub type TransactionResult = Result<pink::runtime::ExecSideEffects, TransactionError>;
 
+/// The number of blocks between two contract usage reports
+const USAGE_REPORT_INTERVAL: chain::BlockNumber = 600;
+
 #[derive(Encode, Decode, Debug, Clone, thiserror::Error)]
 #[error("TransactionError: {:?}", self)]
 pub enum TransactionError {
@@ -84,6 +99,17 @@ pub enum TransactionError {
     // for contract
     CodeNotFound,
     DuplicatedClusterDeploy,
+    QuotaExceeded,
+    // for assets
+    SupplyOverflow,
+    ReservedSymbol,
+}
+
+impl TransactionError {
+    /// The variant index, reported to the chain in place of the error.
+    pub fn code(&self) -> u8 {
+        self.encode()[0]
+    }
 }
 
 impl From<BadOrigin> for TransactionError {
@@ -155,7 +181,7 @@ impl WorkerState {
                 use MiningState::*;
                 use WorkerEvent::*;
                 if log_on {
-                    info!("System::handle_event: {:?}", evt.event);
+                    info!(target: "mining", "System::handle_event: {:?}", evt.event);
                 }
                 match evt.event {
                     Registered(_) => {
@@ -172,7 +198,7 @@ impl WorkerState {
                     }
                     BenchScore(score) => {
                         if log_on {
-                            info!("My benchmark score is {}", score);
+                            info!(target: "mining", "My benchmark score is {}", score);
                         }
                     }
                     MiningStart { session_id, .. } => {
@@ -194,7 +220,7 @@ impl WorkerState {
                         if let Some(info) = &mut self.mining_state {
                             if let Mining = info.state {
                                 if log_on {
-                                    info!("Enter paused");
+                                    info!(target: "mining", "Enter paused");
                                 }
                                 info.state = Paused;
                                 return;
@@ -202,6 +228,7 @@ impl WorkerState {
                         }
                         if log_on {
                             error!(
+                                target: "mining",
                                 "Unexpected event received: {:?}, mining_state= {:?}",
                                 evt.event, self.mining_state
                             );
@@ -211,7 +238,7 @@ impl WorkerState {
                         if let Some(info) = &mut self.mining_state {
                             if let Paused = info.state {
                                 if log_on {
-                                    info!("Exit paused");
+                                    info!(target: "mining", "Exit paused");
                                 }
                                 info.state = Mining;
                                 return;
@@ -219,6 +246,7 @@ impl WorkerState {
                         }
                         if log_on {
                             error!(
+                                target: "mining",
                                 "Unexpected event received: {:?}, mining_state= {:?}",
                                 evt.event, self.mining_state
                             );
@@ -241,6 +269,7 @@ impl WorkerState {
     ) {
         if log_on {
             debug!(
+                target: "mining",
                 "System::handle_heartbeat_challenge({}, {:?}), registered={:?}, mining_state={:?}",
                 block.block_number, seed_info, self.registered, self.mining_state
             );
@@ -338,7 +367,7 @@ impl WorkerStateMachineCallback for WorkerSMDelegate<'_> {
             start_time,
             iterations,
         };
-        info!("Reporting benchmark: {:?}", report);
+        info!(target: "mining", "Reporting benchmark: {:?}", report);
         self.0.push_message(&report);
     }
     fn heartbeat(
@@ -354,7 +383,7 @@ impl WorkerStateMachineCallback for WorkerSMDelegate<'_> {
             challenge_time,
             iterations,
         };
-        info!("System: sending {:?}", event);
+        info!(target: "mining", "System: sending {:?}", event);
         self.0.push_message(&event);
     }
 }
@@ -405,6 +434,10 @@ pub struct System<Platform> {
     key_distribution_events: TypedReceiver<KeyDistribution>,
     cluster_key_distribution_events: TypedReceiver<ClusterKeyDistribution<chain::BlockNumber>>,
     contract_operation_events: TypedReceiver<ContractOperation<chain::Hash, chain::AccountId>>,
+    #[serde(default = "subscribe_cluster_events")]
+    cluster_events: TypedReceiver<ClusterEvent>,
+    #[serde(default = "subscribe_sidevm_code_events")]
+    sidevm_code_events: TypedReceiver<SidevmCodeEvent>,
     // Worker
     pub(crate) identity_key: WorkerIdentityKey,
     #[serde(with = "ecdh_serde")]
@@ -417,25 +450,131 @@ pub struct System<Platform> {
 
     pub(crate) contracts: ContractsKeeper,
     contract_clusters: ClusterKeeper,
+    #[serde(default)]
+    sidevm_codes: SidevmCodeRegistry,
     #[serde(skip)]
     #[serde(default = "create_sidevm_service")]
-    sidevm_spawner: Spawner,
+    sidevm: SidevmService,
 
     // Cached for query
     block_number: BlockNumber,
-    now_ms: u64,
+    pub(crate) now_ms: u64,
+}
+
+// Checkpoints taken before the worker handles cluster events don't have this receiver.
+fn subscribe_cluster_events() -> TypedReceiver<ClusterEvent> {
+    phala_mq::checkpoint_helper::subscribe_default(
+        <ClusterEvent as phala_mq::BindTopic>::topic(),
+    )
+    .into()
+}
+
+// Checkpoints taken before the worker keeps the sidevm code registry don't have this receiver.
+fn subscribe_sidevm_code_events() -> TypedReceiver<SidevmCodeEvent> {
+    phala_mq::checkpoint_helper::subscribe_default(
+        <SidevmCodeEvent as phala_mq::BindTopic>::topic(),
+    )
+    .into()
+}
+
+/// Routes the `sidevm_query` calls of the pink contracts to their sidevm instances.
+struct SidevmQueryRouter(Spawner);
+
+impl pink::runtime::SidevmQueryHandler for SidevmQueryRouter {
+    fn query(
+        &self,
+        contract: &[u8],
+        payload: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, pink::runtime::SidevmQueryError> {
+        use pink::runtime::SidevmQueryError;
+        use sidevm::service::QueryError;
+
+        let vm_id: sidevm::VmId = contract.try_into().or(Err(SidevmQueryError::NotRunning))?;
+        self.0
+            .query(&vm_id, payload, timeout)
+            .map_err(|err| match err {
+                QueryError::NotRunning => SidevmQueryError::NotRunning,
+                QueryError::Busy => SidevmQueryError::Busy,
+                QueryError::Timeout => SidevmQueryError::Timeout,
+            })
+    }
+}
+
+/// Routes the `native_query` calls of a pink contract to the snapshots of the native contracts in
+/// its cluster, taken along with the contract query.
+struct NativeQueryRouter(Mutex<NativeQueries>);
+
+struct NativeQueries {
+    contracts: BTreeMap<ContractId, contracts::Query>,
+    context: contracts::QueryContext,
+}
+
+impl pink::runtime::NativeQueryHandler for NativeQueryRouter {
+    fn query(
+        &self,
+        caller: &[u8],
+        contract: [u8; 32],
+        query: Vec<u8>,
+    ) -> Result<Vec<u8>, pink::runtime::NativeQueryError> {
+        use pink::runtime::NativeQueryError;
+
+        let caller: chain::AccountId =
+            caller.try_into().or(Err(NativeQueryError::ContractNotFound))?;
+        let mut queries = self.0.lock().unwrap();
+        let NativeQueries { contracts, context } = &mut *queries;
+        let target = contracts
+            .get(&ContractId::from(contract))
+            .ok_or(NativeQueryError::ContractNotFound)?;
+        target
+            .handle_query(Some(&caller), &query, context)
+            .or(Err(NativeQueryError::BadQuery))
+    }
+}
+
+/// Answers the `native_query` calls made outside of a pink contract query, which find no contract.
+struct NoNativeQuery;
+
+impl pink::runtime::NativeQueryHandler for NoNativeQuery {
+    fn query(
+        &self,
+        _caller: &[u8],
+        _contract: [u8; 32],
+        _query: Vec<u8>,
+    ) -> Result<Vec<u8>, pink::runtime::NativeQueryError> {
+        Err(pink::runtime::NativeQueryError::ContractNotFound)
+    }
+}
+
+/// The sidevm service, along with the prices reported by its instances.
+struct SidevmService {
+    spawner: Spawner,
+    price_feeds: Arc<PriceFeeds>,
 }
 
-fn create_sidevm_service() -> Spawner {
+fn create_sidevm_service() -> SidevmService {
     let (run, spawner) = sidevm::service::service();
+    let price_feeds = Arc::new(PriceFeeds::default());
+    let feeds = price_feeds.clone();
     std::thread::spawn(move || {
-        run.blocking_run(|report| {
-            let todo = "kevin: restart sidevm instance if it crashes";
-            let todo = "kevin: remove the log since it leak vm info";
-            info!("Sidevm report: {:?}", report);
+        run.blocking_run(|report| match report {
+            sidevm::service::Report::VmMessage { id, message } => {
+                feeds.accept_report(id, &message);
+            }
+            report => {
+                let todo = "kevin: restart sidevm instance if it crashes";
+                let todo = "kevin: remove the log since it leak vm info";
+                info!(target: "system", "Sidevm report: {:?}", report);
+                if let sidevm::service::Report::VmTerminated { id, .. } = report {
+                    feeds.remove_feeder(&id);
+                }
+            }
         })
     });
-    spawner
+    SidevmService {
+        spawner,
+        price_feeds,
+    }
 }
 
 impl<Platform: pal::Platform> System<Platform> {
@@ -467,6 +606,8 @@ impl<Platform: pal::Platform> System<Platform> {
             key_distribution_events: recv_mq.subscribe_bound(),
             cluster_key_distribution_events: recv_mq.subscribe_bound(),
             contract_operation_events: recv_mq.subscribe_bound(),
+            cluster_events: recv_mq.subscribe_bound(),
+            sidevm_code_events: recv_mq.subscribe_bound(),
             identity_key,
             ecdh_key,
             worker_state: WorkerState::new(pubkey),
@@ -474,18 +615,87 @@ impl<Platform: pal::Platform> System<Platform> {
             gatekeeper: None,
             contracts,
             contract_clusters: Default::default(),
+            sidevm_codes: Default::default(),
             block_number: 0,
             now_ms: 0,
-            sidevm_spawner: create_sidevm_service(),
+            sidevm: create_sidevm_service(),
         }
     }
 
+    /// The storage roots of the contract clusters
+    pub(crate) fn cluster_storage_roots(
+        &self,
+    ) -> Vec<(phala_mq::ContractClusterId, sp_core::H256)> {
+        self.contract_clusters.storage_roots()
+    }
+
+    /// The status of the sidevm instances, along with the owner contract and the hash of the code
+    /// each instance runs.
+    pub(crate) fn sidevm_instances(
+        &self,
+    ) -> Vec<(VmStatus, Option<(phala_mq::ContractClusterId, sp_core::H256)>)> {
+        self.sidevm.spawner
+            .instances()
+            .into_iter()
+            .map(|status| {
+                let contract = self
+                    .contracts
+                    .get(&ContractId::from(status.id))
+                    .and_then(|contract| {
+                        Some((contract.cluster_id(), contract.sidevm_code_hash()?))
+                    });
+                (status, contract)
+            })
+            .collect()
+    }
+
+    /// A digest of the states the queries to the contract read, the contract state and the
+    /// storage of its cluster. It changes whenever a command, an event or another contract in the
+    /// cluster changes what the queries could return.
+    pub fn query_state_digest(&self, contract_id: &ContractId) -> Option<sp_core::H256> {
+        let contract = self.contracts.get(contract_id)?;
+        let storage_root = self
+            .contract_clusters
+            .get_cluster(&contract.cluster_id())?
+            .storage
+            .root();
+        let digest = (contract.state_digest(), storage_root).encode();
+        Some(blake2_256(&digest).into())
+    }
+
+    /// The block the queries run at
+    pub fn block_number(&self) -> BlockNumber {
+        self.block_number
+    }
+
     pub fn make_query(
         &mut self,
         contract_id: &ContractId,
     ) -> Result<
         impl FnOnce(Option<&chain::AccountId>, OpaqueQuery) -> Result<OpaqueReply, OpaqueError>,
         OpaqueError,
+    > {
+        self.prepare_query(contract_id, true)
+    }
+
+    /// Like `make_query`, but the query is not billed, for the reruns of the subscriptions.
+    pub fn make_unbilled_query(
+        &mut self,
+        contract_id: &ContractId,
+    ) -> Result<
+        impl FnOnce(Option<&chain::AccountId>, OpaqueQuery) -> Result<OpaqueReply, OpaqueError>,
+        OpaqueError,
+    > {
+        self.prepare_query(contract_id, false)
+    }
+
+    fn prepare_query(
+        &mut self,
+        contract_id: &ContractId,
+        billed: bool,
+    ) -> Result<
+        impl FnOnce(Option<&chain::AccountId>, OpaqueQuery) -> Result<OpaqueReply, OpaqueError>,
+        OpaqueError,
     > {
         use pink::storage::Snapshot as _;
 
@@ -493,20 +703,50 @@ impl<Platform: pal::Platform> System<Platform> {
             .contracts
             .get_mut(contract_id)
             .ok_or(OpaqueError::ContractNotFound)?;
-        let storage = self
-            .contract_clusters
-            .get_cluster_mut(&contract.cluster_id())
-            .expect("BUG: contract cluster should always exists")
-            .storage
-            .snapshot();
+        // Only the succeeded signed queries are billed, to their origins
+        let queries = contract.query_counter();
+        let cluster_id = contract.cluster_id();
+        let is_pink = contract.is_pink();
         let contract = contract.snapshot_for_query();
+        let cluster = self
+            .contract_clusters
+            .get_cluster_mut(&cluster_id)
+            .expect("BUG: contract cluster should always exists");
+        let sidevm_query: Arc<dyn pink::runtime::SidevmQueryHandler> =
+            Arc::new(SidevmQueryRouter(self.sidevm.spawner.clone()));
+        // Only the pink contracts query the native contracts, which never query back.
+        let native_query: Arc<dyn pink::runtime::NativeQueryHandler> = if is_pink {
+            let context = contracts::QueryContext {
+                block_number: self.block_number,
+                now_ms: self.now_ms,
+                storage: cluster.storage.snapshot(),
+                identity_key: self.identity_key.0.clone(),
+                sidevm_query: sidevm_query.clone(),
+                native_query: Arc::new(NoNativeQuery),
+                price_feeds: self.sidevm.price_feeds.clone(),
+            };
+            Arc::new(NativeQueryRouter(Mutex::new(NativeQueries {
+                contracts: self.contracts.native_snapshots(&cluster_id),
+                context,
+            })))
+        } else {
+            Arc::new(NoNativeQuery)
+        };
         let mut context = contracts::QueryContext {
             block_number: self.block_number,
             now_ms: self.now_ms,
-            storage,
+            storage: cluster.storage.snapshot(),
+            identity_key: self.identity_key.0.clone(),
+            sidevm_query,
+            native_query,
+            price_feeds: self.sidevm.price_feeds.clone(),
         };
         Ok(move |origin: Option<&chain::AccountId>, req: OpaqueQuery| {
-            contract.handle_query(origin, req, &mut context)
+            let reply = contract.handle_query(origin, req, &mut context)?;
+            if let (true, Some(origin)) = (billed, origin) {
+                *queries.lock().unwrap().entry(origin.clone()).or_default() += 1;
+            }
+            Ok(reply)
         })
     }
 
@@ -533,6 +773,17 @@ impl<Platform: pal::Platform> System<Platform> {
             (event, origin) = self.contract_operation_events => {
                 self.process_contract_operation_event(block, origin, event)?
             },
+            (event, origin) = self.cluster_events => {
+                if let Err(err) = self.process_cluster_event(origin, event) {
+                    error!(target: "system", "Failed to process cluster event: {:?}", err);
+                }
+            },
+            (event, origin) = self.sidevm_code_events => {
+                if !origin.is_pallet() {
+                    anyhow::bail!("Invalid SidevmCodeEvent sender: {:?}", origin);
+                }
+                self.sidevm_codes.handle_event(event);
+            },
         };
         Ok(ok.is_none())
     }
@@ -553,7 +804,7 @@ impl<Platform: pal::Platform> System<Platform> {
         loop {
             match self.process_next_message(block) {
                 Err(err) => {
-                    error!("Error processing message: {:?}", err);
+                    error!(target: "system", "Error processing message: {:?}", err);
                 }
                 Ok(no_more) => {
                     if no_more {
@@ -575,6 +826,7 @@ impl<Platform: pal::Platform> System<Platform> {
         // Since the wasm contracts can instantiate new contracts, it means that it will mutate the `self.contracts`.
         // So we can not directly iterate over the self.contracts.values_mut() which would keep borrowing on `self.contracts`
         // in the scope of entire `for loop` body.
+        let _tag = heap_profile::enter(heap_profile::Subsystem::Contracts);
         let contract_ids: Vec<_> = self.contracts.keys().cloned().collect();
         'outer: for key in contract_ids {
             // Inner loop to handle commands. One command per iteration and apply the command side-effects to make it
@@ -600,7 +852,8 @@ impl<Platform: pal::Platform> System<Platform> {
                     &mut self.contract_clusters,
                     block,
                     &self.egress,
-                    &self.sidevm_spawner,
+                    &self.sidevm.spawner,
+                    &self.sidevm_codes,
                 );
             }
             let mut env = ExecuteEnv {
@@ -620,9 +873,27 @@ impl<Platform: pal::Platform> System<Platform> {
                 &mut self.contract_clusters,
                 block,
                 &self.egress,
-                &self.sidevm_spawner,
+                &self.sidevm.spawner,
+                &self.sidevm_codes,
             );
         }
+        self.contracts.update_sidevm_storages(&mut self.contract_clusters);
+
+        if block.block_number % USAGE_REPORT_INTERVAL == 0 {
+            self.report_contract_usage(block.block_number);
+        }
+    }
+
+    /// Reports the metered contract usage to the chain for billing.
+    fn report_contract_usage(&mut self, block_number: chain::BlockNumber) {
+        for (cluster, usage) in self.contracts.take_usage() {
+            self.egress.push_message(&WorkerUsageReport {
+                cluster,
+                block_number,
+                usage: usage.contracts,
+                query_origins: usage.query_origins.into_iter().collect(),
+            });
+        }
     }
 
     fn process_system_event(&mut self, block: &BlockInfo, event: &SystemEvent) {
@@ -684,13 +955,14 @@ impl<Platform: pal::Platform> System<Platform> {
         origin: MessageOrigin,
         event: GatekeeperLaunch,
     ) {
-        info!("Incoming gatekeeper launch event: {:?}", event);
+        info!(target: "system", "Incoming gatekeeper launch event: {:?}", event);
         match event {
             GatekeeperLaunch::FirstGatekeeper(new_gatekeeper_event) => {
                 self.process_first_gatekeeper_event(block, origin, new_gatekeeper_event)
             }
             GatekeeperLaunch::MasterPubkeyOnChain(_) => {
                 info!(
+                    target: "system",
                     "Gatekeeper launches on chain in block {}",
                     block.block_number
                 );
@@ -709,13 +981,14 @@ impl<Platform: pal::Platform> System<Platform> {
         event: NewGatekeeperEvent,
     ) {
         if !origin.is_pallet() {
-            error!("Invalid origin {:?} sent a {:?}", origin, event);
+            error!(target: "system", "Invalid origin {:?} sent a {:?}", origin, event);
             return;
         }
 
         // double check the first gatekeeper is valid on chain
         if !chain_state::is_gatekeeper(&event.pubkey, block.storage) {
             error!(
+                target: "system",
                 "Fatal error: Invalid first gatekeeper registration {:?}",
                 event
             );
@@ -726,13 +999,14 @@ impl<Platform: pal::Platform> System<Platform> {
         // if the first gatekeeper reboots, it will possess the master key,
         // and should not re-generate it
         if my_pubkey == event.pubkey && self.master_key.is_none() {
-            info!("Gatekeeper: generate master key as the first gatekeeper");
+            info!(target: "system", "Gatekeeper: generate master key as the first gatekeeper");
             // generate master key as the first gatekeeper
             // no need to restart
             let master_key = crate::new_sr25519_key();
             self.set_master_key(master_key.clone(), false);
             // upload the master key on chain via worker egress
             info!(
+                target: "system",
                 "Gatekeeper: upload master key {} on chain",
                 hex::encode(master_key.public())
             );
@@ -743,7 +1017,7 @@ impl<Platform: pal::Platform> System<Platform> {
         }
 
         if self.master_key.is_some() {
-            info!("Init gatekeeper in block {}", block.block_number);
+            info!(target: "system", "Init gatekeeper in block {}", block.block_number);
             self.init_gatekeeper(block);
         }
 
@@ -761,11 +1035,47 @@ impl<Platform: pal::Platform> System<Platform> {
         origin: MessageOrigin,
         event: GatekeeperChange,
     ) {
-        info!("Incoming gatekeeper change event: {:?}", event);
+        info!(target: "system", "Incoming gatekeeper change event: {:?}", event);
         match event {
             GatekeeperChange::GatekeeperRegistered(new_gatekeeper_event) => {
                 self.process_new_gatekeeper_event(block, origin, new_gatekeeper_event)
             }
+            GatekeeperChange::GatekeeperUnregistered(remove_gatekeeper_event) => {
+                self.process_remove_gatekeeper_event(block, origin, remove_gatekeeper_event)
+            }
+        }
+    }
+
+    /// Stop sending gatekeeper messages if this worker is rotated out
+    ///
+    /// The master key is kept, and the gatekeeper keeps processing the messages as a dummy one so
+    /// that it can be elected again.
+    fn process_remove_gatekeeper_event(
+        &mut self,
+        block: &mut BlockInfo,
+        origin: MessageOrigin,
+        event: RemoveGatekeeperEvent,
+    ) {
+        if !origin.is_pallet() {
+            error!(target: "system", "Invalid origin {:?} sent a {:?}", origin, event);
+            return;
+        }
+
+        // double check the gatekeeper is removed on chain
+        if chain_state::is_gatekeeper(&event.pubkey, block.storage) {
+            error!(
+                target: "system",
+                "Fatal error: Invalid gatekeeper unregistration {:?}",
+                event
+            );
+            panic!("System state poisoned");
+        }
+
+        let my_pubkey = self.identity_key.public();
+        if my_pubkey == event.pubkey {
+            if let Some(gatekeeper) = &mut self.gatekeeper {
+                gatekeeper.unregister_on_chain();
+            }
         }
     }
 
@@ -778,13 +1088,14 @@ impl<Platform: pal::Platform> System<Platform> {
         event: NewGatekeeperEvent,
     ) {
         if !origin.is_pallet() {
-            error!("Invalid origin {:?} sent a {:?}", origin, event);
+            error!(target: "system", "Invalid origin {:?} sent a {:?}", origin, event);
             return;
         }
 
         // double check the registered gatekeeper is valid on chain
         if !chain_state::is_gatekeeper(&event.pubkey, block.storage) {
             error!(
+                target: "system",
                 "Fatal error: Invalid first gatekeeper registration {:?}",
                 event
             );
@@ -812,7 +1123,11 @@ impl<Platform: pal::Platform> System<Platform> {
                 if let Err(err) =
                     self.process_master_key_distribution(origin, dispatch_master_key_event)
                 {
-                    error!("Failed to process master key distribution event: {:?}", err);
+                    error!(
+                        target: "system",
+                        "Failed to process master key distribution event: {:?}",
+                        err
+                    );
                 };
             }
         }
@@ -829,6 +1144,7 @@ impl<Platform: pal::Platform> System<Platform> {
                 let cluster = event.cluster;
                 if let Err(err) = self.process_cluster_key_distribution(block, origin, event) {
                     error!(
+                        target: "system",
                         "Failed to process cluster key distribution event: {:?}",
                         err
                     );
@@ -839,13 +1155,41 @@ impl<Platform: pal::Platform> System<Platform> {
         }
     }
 
+    fn process_cluster_event(
+        &mut self,
+        origin: MessageOrigin,
+        event: ClusterEvent,
+    ) -> Result<(), TransactionError> {
+        if !origin.is_pallet() {
+            error!(target: "system", "Invalid ClusterEvent sender: {:?}", origin);
+            return Err(TransactionError::BadOrigin);
+        }
+        let cluster = match event {
+            ClusterEvent::DestroyCluster { cluster } => cluster,
+            ClusterEvent::RemoveWorkers { cluster, workers } => {
+                if !workers.contains(&self.worker_state.pubkey) {
+                    return Ok(());
+                }
+                cluster
+            }
+            // Handled by the gatekeeper, which then distributes the cluster key to the workers
+            ClusterEvent::DeployCluster { .. } | ClusterEvent::AddWorkers { .. } => return Ok(()),
+        };
+        if self.contract_clusters.remove_cluster(&cluster).is_some() {
+            self.contracts.remove_cluster_contracts(&cluster);
+            pink::remove_cluster_cache(cluster.as_bytes());
+            info!(target: "system", "Cluster {:?} dropped", cluster);
+        }
+        Ok(())
+    }
+
     fn process_contract_operation_event(
         &mut self,
         block: &mut BlockInfo,
         sender: MessageOrigin,
         event: ContractOperation<chain::Hash, chain::AccountId>,
     ) -> anyhow::Result<()> {
-        info!("Incoming contract operation: {:?}", event);
+        info!(target: "system", "Incoming contract operation: {:?}", event);
         if !sender.is_pallet() {
             anyhow::bail!("Invalid origin {:?} for contract operation", sender);
         }
@@ -855,6 +1199,8 @@ impl<Platform: pal::Platform> System<Platform> {
                 code,
                 cluster_id,
             } => {
+                let code_hash = chain::Hash::from(blake2_256(&code));
+                ensure_code_allowed(&cluster_id, &code_hash, block)?;
                 let cluster = self
                     .contract_clusters
                     .get_cluster_mut(&cluster_id)
@@ -870,6 +1216,7 @@ impl<Platform: pal::Platform> System<Platform> {
                 };
                 self.egress.push_message(&message);
                 info!(
+                    target: "system",
                     "Uploaded code to cluster {}, code_hash={:?}",
                     cluster_id, hash
                 );
@@ -933,6 +1280,9 @@ impl<Platform: pal::Platform> System<Platform> {
                             (ASSETS => assets::Assets::new()),
                             (BTC_LOTTERY => btc_lottery::BtcLottery::new(Some(contract_key.to_raw_vec()))),
                             (GEOLOCATION => geolocation::Geolocation::new()),
+                            (VAULT => vault::Vault::new()),
+                            (BALLOT => ballot::Ballot::new()),
+                            (PRICE_ORACLE => price_oracle::PriceOracle::new(contract_info.deployer.clone())),
                             (GUESS_NUMBER => guess_number::GuessNumber::new()),
                             (BTC_PRICE_BOT => btc_price_bot::BtcPriceBot::new())
                         };
@@ -951,10 +1301,15 @@ impl<Platform: pal::Platform> System<Platform> {
                             deployer,
                             pubkey: contract_pubkey,
                         };
-                        info!("Native contract instantiate status: {:?}", message);
+                        info!(
+                            target: "system",
+                            "Native contract instantiate status: {:?}",
+                            message
+                        );
                         self.egress.push_message(&message);
                     }
                     CodeIndex::WasmCode(code_hash) => {
+                        ensure_code_allowed(&cluster_id, &code_hash, block)?;
                         let deployer = contract_info.deployer.clone();
                         let contract_id = contract_info.contract_id(blake2_256);
 
@@ -977,18 +1332,15 @@ impl<Platform: pal::Platform> System<Platform> {
                             )
                             .with_context(|| format!("Contract deployer: {:?}", deployer))?;
 
-                        let cluster = self
-                            .contract_clusters
-                            .get_cluster_mut(&cluster_id)
-                            .expect("Cluster must exist");
                         apply_pink_side_effects(
                             effects,
                             cluster_id,
                             &mut self.contracts,
-                            cluster,
+                            &mut self.contract_clusters,
                             block,
                             &self.egress,
-                            &self.sidevm_spawner,
+                            &self.sidevm.spawner,
+                            &self.sidevm_codes,
                         );
                     }
                 }
@@ -1023,7 +1375,7 @@ impl<Platform: pal::Platform> System<Platform> {
         event: DispatchMasterKeyEvent,
     ) -> Result<(), TransactionError> {
         if !origin.is_gatekeeper() {
-            error!("Invalid origin {:?} sent a {:?}", origin, event);
+            error!(target: "system", "Invalid origin {:?} sent a {:?}", origin, event);
             return Err(TransactionError::BadOrigin);
         }
 
@@ -1031,7 +1383,14 @@ impl<Platform: pal::Platform> System<Platform> {
         if my_pubkey == event.dest {
             let master_pair =
                 self.decrypt_key_from(&event.ecdh_pubkey, &event.encrypted_master_key, &event.iv);
-            info!("Gatekeeper: successfully decrypt received master key");
+            info!(target: "system", "Gatekeeper: successfully decrypt received master key");
+            if self.master_key.is_some() {
+                // pRuntime restarts on the first receipt and replays the blocks, so the
+                // confirmation is sent on the replay, after all the messages sent before.
+                self.egress.push_message(&RegistryEvent::MasterPubkey {
+                    master_pubkey: master_pair.public(),
+                });
+            }
             self.set_master_key(master_pair, true);
         }
         Ok(())
@@ -1044,7 +1403,7 @@ impl<Platform: pal::Platform> System<Platform> {
         event: BatchDispatchClusterKeyEvent<chain::BlockNumber>,
     ) -> anyhow::Result<()> {
         if !origin.is_gatekeeper() {
-            error!("Invalid origin {:?} sent a {:?}", origin, event);
+            error!(target: "system", "Invalid origin {:?} sent a {:?}", origin, event);
             return Err(TransactionError::BadOrigin.into());
         }
 
@@ -1056,12 +1415,12 @@ impl<Platform: pal::Platform> System<Platform> {
                 &encrypted_key.encrypted_key,
                 &encrypted_key.iv,
             );
-            info!("Worker: successfully decrypt received cluster key");
+            info!(target: "system", "Worker: successfully decrypt received cluster key");
 
             // TODO(shelven): forget cluster key after expiration time
             let cluster = self.contract_clusters.get_cluster_mut(&event.cluster);
             if cluster.is_some() {
-                error!("Cluster {:?} is already deployed", &event.cluster);
+                error!(target: "system", "Cluster {:?} is already deployed", &event.cluster);
                 return Err(TransactionError::DuplicatedClusterDeploy.into());
             }
             // register cluster
@@ -1080,6 +1439,10 @@ impl<Platform: pal::Platform> System<Platform> {
         self.worker_state.registered
     }
 
+    pub fn has_master_key(&self) -> bool {
+        self.master_key.is_some()
+    }
+
     pub fn gatekeeper_status(&self) -> GatekeeperStatus {
         let active = match &self.gatekeeper {
             Some(gk) => gk.registered_on_chain(),
@@ -1105,10 +1468,25 @@ impl<Platform: pal::Platform> System<Platform> {
 
 impl<P> System<P> {
     pub fn on_restored(&mut self) -> Result<()> {
-        self.contracts.try_restart_sidevms(&self.sidevm_spawner)
+        self.contracts.try_restart_sidevms(&self.sidevm.spawner)
+    }
+
+    /// Stops all the sidevm instances, and waits at most `timeout` for them to exit.
+    pub fn stop_sidevms(&self, timeout: Duration) {
+        self.contracts.stop_sidevms();
+        let deadline = Instant::now() + timeout;
+        let is_running = |vm: &VmStatus| vm.state == VmState::Running;
+        while self.sidevm.spawner.instances().iter().any(is_running) {
+            if Instant::now() >= deadline {
+                warn!(target: "system", "Timed out waiting for the sidevm instances to stop");
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn handle_contract_command_result(
     result: TransactionResult,
     cluster_id: phala_mq::ContractClusterId,
@@ -1117,38 +1495,49 @@ pub fn handle_contract_command_result(
     block: &mut BlockInfo,
     egress: &SignedMessageChannel,
     spawner: &Spawner,
+    sidevm_codes: &SidevmCodeRegistry,
 ) {
     let effects = match result {
         Err(err) => {
-            error!("Run contract command failed: {:?}", err);
+            error!(target: "system", "Run contract command failed: {:?}", err);
             return;
         }
         Ok(effects) => effects,
     };
-    let cluster = match clusters.get_cluster_mut(&cluster_id) {
-        None => {
-            error!(
-                "BUG: contract cluster not found, it should always exsists, cluster_id={:?}",
-                cluster_id
-            );
-            return;
-        }
-        Some(cluster) => cluster,
-    };
     apply_pink_side_effects(
-        effects, cluster_id, contracts, cluster, block, egress, spawner,
+        effects,
+        cluster_id,
+        contracts,
+        clusters,
+        block,
+        egress,
+        spawner,
+        sidevm_codes,
     );
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn apply_pink_side_effects(
     effects: ExecSideEffects,
     cluster_id: phala_mq::ContractClusterId,
     contracts: &mut ContractsKeeper,
-    cluster: &mut Cluster,
+    clusters: &mut ClusterKeeper,
     block: &mut BlockInfo,
     egress: &SignedMessageChannel,
     spawner: &Spawner,
+    sidevm_codes: &SidevmCodeRegistry,
 ) {
+    let cluster = match clusters.get_cluster_mut(&cluster_id) {
+        None => {
+            error!(
+                target: "system",
+                "BUG: contract cluster not found, it should always exsists, cluster_id={:?}",
+                cluster_id
+            );
+            return;
+        }
+        Some(cluster) => cluster,
+    };
     for (deployer, address) in effects.instantiated {
         let pink = Pink::from_address(address.clone(), cluster_id);
         let contract_id = ContractId::from(address.as_ref());
@@ -1168,10 +1557,10 @@ pub fn apply_pink_side_effects(
         );
 
         if let Err(err) = result {
-            error!("BUG: Install contract failed: {:?}", err);
-            error!(" address: {:?}", address);
-            error!(" cluster_id: {:?}", cluster_id);
-            error!(" deployer: {:?}", deployer);
+            error!(target: "system", "BUG: Install contract failed: {:?}", err);
+            error!(target: "system", " address: {:?}", address);
+            error!(target: "system", " cluster_id: {:?}", cluster_id);
+            error!(target: "system", " deployer: {:?}", deployer);
             continue;
         };
 
@@ -1184,12 +1573,14 @@ pub fn apply_pink_side_effects(
             pubkey: EcdhPublicKey(ecdh_key.public()),
         };
 
-        info!("pink instantiate status: {:?}", message);
+        info!(target: "system", "pink instantiate status: {:?}", message);
         egress.push_message(&message);
     }
 
     const MAX_SIDEVM_CODE_SIZE: usize = 1024 * 1024 * 2;
     let mut wasm_code = Vec::new();
+    let mut sidevm_config = Vec::new();
+    let mut native_commands = Vec::new();
 
     for (address, event) in effects.pink_events {
         let id = contracts::contract_address_to_id(&address);
@@ -1219,20 +1610,43 @@ pub fn apply_pink_side_effects(
             }
             PinkEvent::StartToTransferSidevmCode => {
                 wasm_code.clear();
+                sidevm_config.clear();
             }
             PinkEvent::SidevmCodeChunk(chunk) => {
                 if wasm_code.len() < MAX_SIDEVM_CODE_SIZE {
                     wasm_code.extend_from_slice(&chunk);
                 }
             }
+            PinkEvent::SidevmConfigChunk(chunk) => {
+                // An oversized config is rejected by the spawner.
+                if sidevm_config.len() <= sidevm::service::MAX_CONFIG_SIZE {
+                    sidevm_config.extend_from_slice(&chunk);
+                }
+            }
             PinkEvent::StartSidevm { memory_pages } => {
-                if wasm_code.len() < MAX_SIDEVM_CODE_SIZE {
-                    let wasm_code = std::mem::replace(&mut wasm_code, vec![]);
-                    if let Err(err) = contract.start_sidevm(&spawner, wasm_code, memory_pages) {
-                        error!(target: "sidevm", "Start sidevm failed: {:?}", err);
-                    }
-                } else {
+                if wasm_code.len() >= MAX_SIDEVM_CODE_SIZE {
                     error!(target: "sidevm", "Start sidevm failed: Code too large");
+                    continue;
+                }
+                let wasm_code = std::mem::replace(&mut wasm_code, vec![]);
+                let config = std::mem::take(&mut sidevm_config);
+                let code_hash = contract::SidevmCodeHash::from(blake2_256(&wasm_code));
+                let (memory_pages, grpc_targets) =
+                    match sidevm_codes.resources(&code_hash, memory_pages) {
+                        Some(resources) => resources,
+                        None => {
+                            error!(
+                                target: "sidevm",
+                                "Start sidevm failed: Code {:?} not registered",
+                                code_hash
+                            );
+                            continue;
+                        }
+                    };
+                if let Err(err) =
+                    contract.start_sidevm(&spawner, wasm_code, memory_pages, grpc_targets, config)
+                {
+                    error!(target: "sidevm", "Start sidevm failed: {:?}", err);
                 }
             }
             PinkEvent::SidevmMessage(payload) => {
@@ -1240,6 +1654,44 @@ pub fn apply_pink_side_effects(
                     error!(target: "sidevm", "Push message to sidevm failed: {:?}", err);
                 }
             }
+            PinkEvent::NativeCommand {
+                contract: target,
+                command,
+            } => {
+                let command_id = contract.next_native_command_id();
+                native_commands.push((id, ContractId::from(target), command, command_id));
+            }
+        }
+    }
+
+    // The native contracts see the calling contract as a regular account. Only the contracts in
+    // the same cluster can be called, since the others are not run by all its workers.
+    for (caller, target, command, command_id) in native_commands {
+        let origin = MessageOrigin::AccountId(caller);
+        match contracts.get_mut(&target) {
+            Some(contract) if contract.cluster_id() == cluster_id => {
+                let mut env = ExecuteEnv {
+                    block,
+                    contract_clusters: clusters,
+                };
+                if let Err(err) =
+                    contract.handle_native_command(origin, command, command_id, &mut env)
+                {
+                    error!(target: "system", "Native command from {:?} failed: {:?}", caller, err);
+                }
+            }
+            _ => {
+                error!(
+                    target: "system",
+                    "Native command to contract {:?} not in cluster {:?}",
+                    target, cluster_id
+                );
+                // Reported by the caller, as the target may not exist on the other workers.
+                if let Some(caller) = contracts.get(&caller) {
+                    let err = TransactionError::BadContractId;
+                    caller.report_command_failure(origin, command_id, &err);
+                }
+            }
         }
     }
 }
@@ -1295,10 +1747,24 @@ impl fmt::Display for Error {
     }
 }
 
+/// Double checks the code against the allowlist of the cluster on chain before running it
+fn ensure_code_allowed(
+    cluster: &phala_mq::ContractClusterId,
+    code_hash: &chain::Hash,
+    block: &BlockInfo,
+) -> anyhow::Result<()> {
+    match chain_state::cluster_code_allowlist(cluster, block.storage) {
+        Some(allowlist) if !allowlist.contains(code_hash) => {
+            anyhow::bail!("Code {:?} not allowed in cluster {:?}", code_hash, cluster)
+        }
+        _ => Ok(()),
+    }
+}
+
 pub mod chain_state {
     use super::*;
-    use crate::light_validation::utils::storage_prefix;
-    use crate::storage::Storage;
+    use crate::light_validation::utils::{storage_map_prefix_twox_64_concat, storage_prefix};
+    use crate::storage::{Storage, StorageExt as _};
     use parity_scale_codec::Decode;
 
     pub fn is_gatekeeper(pubkey: &WorkerPublicKey, chain_storage: &Storage) -> bool {
@@ -1313,6 +1779,30 @@ pub mod chain_state {
 
         gatekeepers.contains(pubkey)
     }
+
+    /// Returns the wasm code hashes allowed in a cluster, or `None` if any code is allowed.
+    pub fn cluster_code_allowlist(
+        cluster: &phala_mq::ContractClusterId,
+        chain_storage: &Storage,
+    ) -> Option<Vec<chain::Hash>> {
+        let key = storage_map_prefix_twox_64_concat(
+            b"PhalaFatContracts",
+            b"ClusterCodeAllowList",
+            cluster,
+        );
+        chain_storage.get_decoded(&key)
+    }
+
+    pub fn pruntime_allowlist(chain_storage: &Storage) -> Vec<Vec<u8>> {
+        let key = storage_prefix("PhalaRegistry", "PRuntimeAllowList");
+        chain_storage
+            .get(&key)
+            .map(|v| {
+                Vec::<Vec<u8>>::decode(&mut &v[..])
+                    .expect("Decode value of PRuntimeAllowList Failed. (This should not happen)")
+            })
+            .unwrap_or_default()
+    }
 }
 
 #[cfg(test)]
@@ -1344,23 +1834,23 @@ mod tests {
             .unwrap();
         insta::assert_debug_snapshot!(effects);
 
-        let cluster = keeper.get_cluster_mut(&cluster_id).unwrap();
         let mut builder = BlockInfo::builder().block_number(1).now_ms(1);
         let signer = sr25519::Pair::from_seed(&Default::default());
         let egress = builder
             .send_mq
             .channel(MessageOrigin::Gatekeeper, signer.into());
         let mut block_info = builder.build();
-        let spawner = create_sidevm_service();
+        let spawner = create_sidevm_service().spawner;
 
         apply_pink_side_effects(
             effects,
             cluster_id,
             &mut contracts,
-            cluster,
+            &mut keeper,
             &mut block_info,
             &egress,
             &spawner,
+            &Default::default(),
         );
 
         insta::assert_display_snapshot!(contracts.len());
@@ -1383,4 +1873,77 @@ mod tests {
             .collect();
         insta::assert_debug_snapshot!(messages);
     }
+
+    #[test]
+    fn native_command_to_other_cluster_is_rejected() {
+        use phala_mq::BindTopic as _;
+        use phala_types::contract::messaging::ContractCommandFailure;
+
+        let cluster_key = sp_core::Pair::from_seed(&Default::default());
+        let mut contracts = ContractsKeeper::default();
+        let mut keeper = ClusterKeeper::default();
+        let cluster_id = phala_mq::ContractClusterId([1; 32]);
+        let other_cluster_id = phala_mq::ContractClusterId([2; 32]);
+        keeper.get_cluster_or_default_mut(&cluster_id, &cluster_key);
+
+        let mut builder = BlockInfo::builder().block_number(1).now_ms(1);
+        let signer = sr25519::Pair::from_seed(&Default::default());
+        let egress = builder
+            .send_mq
+            .channel(MessageOrigin::Gatekeeper, signer.into());
+        let mut block_info = builder.build();
+        let spawner = create_sidevm_service().spawner;
+
+        let caller = ContractId::from([0xca; 32]);
+        let target = ContractId::from([0x7a; 32]);
+        for (id, cluster_id) in [(caller, cluster_id), (target, other_cluster_id)] {
+            let contract_key = get_contract_key(&cluster_key, &id);
+            let ecdh_key = contract_key.derive_ecdh_key().unwrap();
+            let balances = contracts::balances::Balances::new();
+            install_contract(
+                &mut contracts,
+                id,
+                balances,
+                contract_key,
+                ecdh_key,
+                &mut block_info,
+                cluster_id,
+            )
+            .unwrap();
+        }
+
+        let event = pink::runtime::PinkEvent::NativeCommand {
+            contract: target.0.into(),
+            command: vec![0u8],
+        };
+        let effects = ExecSideEffects {
+            pink_events: vec![(AccountId32::new(caller.0), event)],
+            instantiated: vec![],
+        };
+        apply_pink_side_effects(
+            effects,
+            cluster_id,
+            &mut contracts,
+            &mut keeper,
+            &mut block_info,
+            &egress,
+            &spawner,
+            &Default::default(),
+        );
+
+        let failures: Vec<_> = builder
+            .send_mq
+            .all_messages()
+            .into_iter()
+            .filter(|msg| *msg.message.destination.path() == ContractCommandFailure::topic())
+            .collect();
+        assert_eq!(failures.len(), 1);
+        let message = &failures[0].message;
+        assert_eq!(message.sender, MessageOrigin::Contract(caller));
+        let failure = ContractCommandFailure::decode(&mut &message.payload[..]).unwrap();
+        assert!(failure.origin.is_none());
+        // Identified by the nonce of the caller rather than the hash of the secret command
+        assert_eq!(failure.command_hash, blake2_256(&(caller, 0u64).encode()).into());
+        assert_eq!(failure.error_code, ContractCommandFailure::GENERIC_ERROR_CODE);
+    }
 }
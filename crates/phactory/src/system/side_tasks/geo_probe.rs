@@ -29,6 +29,9 @@ const PROBE_DURATION: BlockNumber = 5; // 1 min
 // 3. It is open sourced at https://github.com/rdegges/ipify-api.
 const IP_PROBE_URL: &str = "https://api.ipify.org"; // ipinfo is reported to be baned in China
 
+/// The coordinates are rounded to 0.1 degree (about 11km) before being reported.
+const COORDINATE_SCALE: f64 = 10f64;
+
 #[derive(Debug)]
 pub enum GeoProbeError {
     // geo_probe
@@ -90,6 +93,7 @@ pub fn process_block(
     ) % BLOCK_INTERVAL;
     if block_number % BLOCK_INTERVAL == worker_magic {
         log::info!(
+            target: "side_task",
             "start geolocation probing at block {}, worker magic {}",
             block_number,
             worker_magic
@@ -128,7 +132,7 @@ pub fn process_block(
                 .body_string()
                 .await
                 .or(Err(GeoProbeError::FailedToGetPublicIPAddress))?;
-            log::info!("public IP address: {}", pub_ip);
+            log::info!(target: "side_task", "public IP address: {}", pub_ip);
 
             // 3. Look up geolocation info in maxmind database.
             let reader =
@@ -147,14 +151,12 @@ pub fn process_block(
             let latitude = location.latitude.ok_or(GeoProbeError::NoRecord)?;
             let longitude = location.longitude.ok_or(GeoProbeError::NoRecord)?;
 
-            info!(
-                "look-up geolocation: {}, {}, {}",
-                latitude, longitude, region_name
-            );
+            info!(target: "side_task", "look-up geolocation: {}", region_name);
 
+            let coarse = |degree: f64| (degree * COORDINATE_SCALE).round() / COORDINATE_SCALE;
             let geocoding = Geocoding {
-                latitude: (latitude * 10000f64) as i32,
-                longitude: (longitude * 10000f64) as i32,
+                latitude: (coarse(latitude) * 10000f64) as i32,
+                longitude: (coarse(longitude) * 10000f64) as i32,
                 region_name: region_name.to_string(),
             };
 
@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+
+use log::{info, warn};
+use phala_types::contract::{messaging::SidevmCodeEvent, SidevmCodeHash, SidevmResourceProfile};
+use serde::{Deserialize, Serialize};
+
+/// The worker's copy of the on-chain sidevm code registry, kept up to date by the
+/// `SidevmCodeEvent`s from the PhalaSidevm pallet.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct SidevmCodeRegistry {
+    /// Whether the codes not in the registry are refused
+    enforced: bool,
+    codes: BTreeMap<SidevmCodeHash, SidevmResourceProfile>,
+}
+
+impl SidevmCodeRegistry {
+    pub fn handle_event(&mut self, event: SidevmCodeEvent) {
+        match event {
+            SidevmCodeEvent::CodeRegistered { hash, profile } => {
+                info!(target: "sidevm", "Sidevm code {:?} registered", hash);
+                self.codes.insert(hash, profile);
+            }
+            SidevmCodeEvent::ProfileUpdated { hash, profile } => {
+                info!(target: "sidevm", "Sidevm code {:?} profile updated", hash);
+                self.codes.insert(hash, profile);
+            }
+            SidevmCodeEvent::CodeUnregistered { hash } => {
+                info!(target: "sidevm", "Sidevm code {:?} unregistered", hash);
+                self.codes.remove(&hash);
+            }
+            SidevmCodeEvent::SetEnforced { enforced } => {
+                info!(target: "sidevm", "Sidevm code registry enforced: {}", enforced);
+                self.enforced = enforced;
+            }
+        }
+    }
+
+    /// Returns the memory pages and the gRPC targets to start the code with, or `None` if the
+    /// code is not allowed to start.
+    ///
+    /// A registered code runs with its registered profile. Before the registry is enforced, an
+    /// unregistered code still runs with the requested memory pages but no gRPC targets, the same
+    /// as before the registry was introduced.
+    pub fn resources(
+        &self,
+        hash: &SidevmCodeHash,
+        memory_pages: u32,
+    ) -> Option<(u32, Vec<String>)> {
+        match self.codes.get(hash) {
+            Some(profile) => {
+                let grpc_targets = profile
+                    .grpc_targets
+                    .iter()
+                    .filter_map(|target| String::from_utf8(target.clone()).ok())
+                    .collect();
+                Some((memory_pages.min(profile.memory_pages), grpc_targets))
+            }
+            None if self.enforced => None,
+            None => {
+                warn!(target: "sidevm", "Starting unregistered sidevm code {:?}", hash);
+                Some((memory_pages, vec![]))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registered(hash: SidevmCodeHash) -> SidevmCodeEvent {
+        SidevmCodeEvent::CodeRegistered {
+            hash,
+            profile: SidevmResourceProfile {
+                memory_pages: 16,
+                grpc_targets: vec![b"https://grpc.example.com:443".to_vec()],
+            },
+        }
+    }
+
+    #[test]
+    fn registered_codes_run_with_their_profile() {
+        let hash = SidevmCodeHash::repeat_byte(1);
+        let targets = vec!["https://grpc.example.com:443".to_string()];
+        let mut registry = SidevmCodeRegistry::default();
+        registry.handle_event(registered(hash));
+        assert_eq!(registry.resources(&hash, 32), Some((16, targets.clone())));
+        assert_eq!(registry.resources(&hash, 8), Some((8, targets)));
+    }
+
+    #[test]
+    fn unregistered_codes_are_refused_once_enforced() {
+        let hash = SidevmCodeHash::repeat_byte(1);
+        let mut registry = SidevmCodeRegistry::default();
+        assert_eq!(registry.resources(&hash, 32), Some((32, vec![])));
+
+        registry.handle_event(SidevmCodeEvent::SetEnforced { enforced: true });
+        assert_eq!(registry.resources(&hash, 32), None);
+        registry.handle_event(registered(hash));
+        assert!(registry.resources(&hash, 32).is_some());
+        registry.handle_event(SidevmCodeEvent::CodeUnregistered { hash });
+        assert_eq!(registry.resources(&hash, 32), None);
+    }
+}
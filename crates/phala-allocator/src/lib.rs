@@ -1,11 +1,13 @@
-#![no_std]
+#![cfg_attr(not(feature = "tags"), no_std)]
 use core::alloc::{GlobalAlloc, Layout};
 use core::sync::atomic::{AtomicUsize, Ordering};
 
+#[cfg(feature = "tags")]
+pub mod tags;
+
 pub struct StatSizeAllocator<T> {
     inner: T,
-    current_used: AtomicUsize,
-    peak_used: AtomicUsize,
+    counter: Counter,
 }
 
 #[derive(Debug)]
@@ -16,25 +18,28 @@ pub struct Stats {
     pub peak_used: usize,
 }
 
-impl<T> StatSizeAllocator<T> {
-    pub const fn new(inner: T) -> Self {
+/// Tracks the current and the peak size of a set of allocations.
+pub(crate) struct Counter {
+    current_used: AtomicUsize,
+    peak_used: AtomicUsize,
+}
+
+impl Counter {
+    pub(crate) const fn new() -> Self {
         Self {
-            inner,
             current_used: AtomicUsize::new(0),
             peak_used: AtomicUsize::new(0),
         }
     }
 
-    pub fn stats(&self) -> Stats {
+    pub(crate) fn stats(&self) -> Stats {
         Stats {
             current_used: self.current_used.load(Ordering::Relaxed),
             peak_used: self.peak_used.load(Ordering::Relaxed),
         }
     }
-}
 
-impl<T: GlobalAlloc> StatSizeAllocator<T> {
-    fn add_alloced_size(&self, size: usize) {
+    pub(crate) fn add(&self, size: usize) {
         let prev = self.current_used.fetch_add(size, Ordering::SeqCst);
         let total_size = prev + size;
         let mut peak = self.peak_used.load(Ordering::SeqCst);
@@ -58,32 +63,51 @@ impl<T: GlobalAlloc> StatSizeAllocator<T> {
             }
         }
     }
+
+    pub(crate) fn sub(&self, size: usize) {
+        self.current_used.fetch_sub(size, Ordering::SeqCst);
+    }
+
+    pub(crate) fn resize(&self, old_size: usize, new_size: usize) {
+        if new_size > old_size {
+            self.add(new_size - old_size);
+        } else if new_size < old_size {
+            self.sub(old_size - new_size);
+        }
+    }
+}
+
+impl<T> StatSizeAllocator<T> {
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            counter: Counter::new(),
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.counter.stats()
+    }
 }
 
 unsafe impl<T: GlobalAlloc> GlobalAlloc for StatSizeAllocator<T> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        self.add_alloced_size(layout.size());
+        self.counter.add(layout.size());
         self.inner.alloc(layout)
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        self.current_used.fetch_sub(layout.size(), Ordering::SeqCst);
+        self.counter.sub(layout.size());
         self.inner.dealloc(ptr, layout)
     }
 
     unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
-        self.add_alloced_size(layout.size());
+        self.counter.add(layout.size());
         self.inner.alloc_zeroed(layout)
     }
 
     unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
-        if new_size > layout.size() {
-            let difference = new_size - layout.size();
-            self.add_alloced_size(difference);
-        } else if new_size < layout.size() {
-            let difference = layout.size() - new_size;
-            self.current_used.fetch_sub(difference, Ordering::SeqCst);
-        }
+        self.counter.resize(layout.size(), new_size);
         self.inner.realloc(ptr, layout, new_size)
     }
 }
@@ -0,0 +1,240 @@
+//! Per subsystem accounting of the heap usage.
+//!
+//! Each thread has a current tag, set with [`enter`]. [`TaggedAllocator`] records the tag of the
+//! thread in a small header in front of each allocation, so that the memory is credited back to the
+//! same tag when it is freed, regardless of which thread or subsystem frees it. A reallocation
+//! keeps the tag of the original allocation.
+
+use super::{Counter, Stats};
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::Cell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of distinct tags. Tags out of range are accounted as tag 0.
+pub const MAX_TAGS: usize = 16;
+
+thread_local! {
+    static CURRENT_TAG: Cell<u8> = const { Cell::new(0) };
+}
+
+/// Restores the previous tag of the thread on drop.
+pub struct TagGuard {
+    prev: u8,
+}
+
+impl Drop for TagGuard {
+    fn drop(&mut self) {
+        set_current(self.prev);
+    }
+}
+
+/// Tags the allocations of the current thread with `tag` until the returned guard is dropped.
+pub fn enter(tag: u8) -> TagGuard {
+    let prev = current();
+    set_current(tag);
+    TagGuard { prev }
+}
+
+/// The current tag of the thread.
+pub fn current() -> u8 {
+    // The TLS might already be destroyed when the thread is exiting.
+    CURRENT_TAG.try_with(|tag| tag.get()).unwrap_or(0)
+}
+
+fn set_current(tag: u8) {
+    let tag = if (tag as usize) < MAX_TAGS { tag } else { 0 };
+    let _ = CURRENT_TAG.try_with(|current| current.set(tag));
+}
+
+#[derive(Debug)]
+pub struct TagStats {
+    pub tag: u8,
+    /// The current heap usage of the tag.
+    pub current_used: usize,
+    /// The peak heap usage of the tag.
+    pub peak_used: usize,
+    /// The number of allocations made under the tag since startup.
+    pub allocations: usize,
+}
+
+struct TagCounter {
+    size: Counter,
+    allocations: AtomicUsize,
+}
+
+impl TagCounter {
+    const fn new() -> Self {
+        Self {
+            size: Counter::new(),
+            allocations: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// A `StatSizeAllocator` which additionally accounts the usage per tag.
+///
+/// Each allocation carries a header of `layout.align()` bytes, which is not included in the stats.
+pub struct TaggedAllocator<T> {
+    inner: T,
+    total: Counter,
+    tags: [TagCounter; MAX_TAGS],
+}
+
+impl<T> TaggedAllocator<T> {
+    pub const fn new(inner: T) -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const COUNTER: TagCounter = TagCounter::new();
+        Self {
+            inner,
+            total: Counter::new(),
+            tags: [COUNTER; MAX_TAGS],
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.total.stats()
+    }
+
+    /// The stats of the tags that have ever been used.
+    pub fn tag_stats(&self) -> impl Iterator<Item = TagStats> + '_ {
+        self.tags
+            .iter()
+            .enumerate()
+            .filter(|(_, counter)| counter.allocations.load(Ordering::Relaxed) > 0)
+            .map(|(tag, counter)| {
+                let stats = counter.size.stats();
+                TagStats {
+                    tag: tag as u8,
+                    current_used: stats.current_used,
+                    peak_used: stats.peak_used,
+                    allocations: counter.allocations.load(Ordering::Relaxed),
+                }
+            })
+    }
+
+    fn on_alloc(&self, tag: u8, size: usize) {
+        let counter = &self.tags[tag as usize];
+        counter.size.add(size);
+        counter.allocations.fetch_add(1, Ordering::Relaxed);
+        self.total.add(size);
+    }
+
+    fn on_dealloc(&self, tag: u8, size: usize) {
+        self.tags[tag as usize].size.sub(size);
+        self.total.sub(size);
+    }
+}
+
+/// The layout including the header, which takes `layout.align()` bytes so that the returned pointer
+/// keeps the alignment.
+fn with_header(layout: Layout) -> Option<Layout> {
+    let size = layout.size().checked_add(layout.align())?;
+    Layout::from_size_align(size, layout.align()).ok()
+}
+
+/// The layout of an existing allocation, for which `with_header` has succeeded.
+unsafe fn with_header_unchecked(layout: Layout) -> Layout {
+    Layout::from_size_align_unchecked(layout.size() + layout.align(), layout.align())
+}
+
+impl<T: GlobalAlloc> TaggedAllocator<T> {
+    unsafe fn alloc_with(
+        &self,
+        layout: Layout,
+        alloc: impl FnOnce(&T, Layout) -> *mut u8,
+    ) -> *mut u8 {
+        let outer = match with_header(layout) {
+            Some(outer) => outer,
+            None => return core::ptr::null_mut(),
+        };
+        let base = alloc(&self.inner, outer);
+        if base.is_null() {
+            return base;
+        }
+        let tag = current();
+        let ptr = base.add(layout.align());
+        ptr.sub(1).write(tag);
+        self.on_alloc(tag, layout.size());
+        ptr
+    }
+}
+
+unsafe impl<T: GlobalAlloc> GlobalAlloc for TaggedAllocator<T> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.alloc_with(layout, |inner, layout| inner.alloc(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let tag = ptr.sub(1).read();
+        self.on_dealloc(tag, layout.size());
+        self.inner
+            .dealloc(ptr.sub(layout.align()), with_header_unchecked(layout))
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.alloc_with(layout, |inner, layout| inner.alloc_zeroed(layout))
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_outer_size = match new_size.checked_add(layout.align()) {
+            Some(size) => size,
+            None => return core::ptr::null_mut(),
+        };
+        let tag = ptr.sub(1).read();
+        let base = self.inner.realloc(
+            ptr.sub(layout.align()),
+            with_header_unchecked(layout),
+            new_outer_size,
+        );
+        if base.is_null() {
+            return base;
+        }
+        self.tags[tag as usize].size.resize(layout.size(), new_size);
+        self.total.resize(layout.size(), new_size);
+        base.add(layout.align())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::System;
+
+    #[test]
+    fn allocations_are_credited_to_their_tag() {
+        let allocator = TaggedAllocator::new(System);
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        unsafe {
+            let a = allocator.alloc(layout);
+            let b = {
+                let _guard = enter(3);
+                allocator.alloc_zeroed(layout)
+            };
+            assert_eq!(current(), 0);
+            assert_eq!(*b, 0);
+            let b = allocator.realloc(b, layout, 300);
+            let b_layout = Layout::from_size_align(300, 8).unwrap();
+
+            let stats: Vec<_> = allocator.tag_stats().collect();
+            assert_eq!(stats.len(), 2);
+            assert_eq!((stats[0].tag, stats[0].current_used), (0, 100));
+            assert_eq!((stats[1].tag, stats[1].current_used), (3, 300));
+            assert_eq!(allocator.stats().current_used, 400);
+
+            // Freed under another tag, still credited back to tag 3
+            allocator.dealloc(b, b_layout);
+            allocator.dealloc(a, layout);
+        }
+        let stats: Vec<_> = allocator.tag_stats().collect();
+        assert_eq!(stats[1].current_used, 0);
+        assert_eq!(stats[1].peak_used, 300);
+        assert_eq!(stats[1].allocations, 1);
+        assert_eq!(allocator.stats().current_used, 0);
+    }
+
+    #[test]
+    fn out_of_range_tags_fall_back_to_zero() {
+        let _guard = enter(MAX_TAGS as u8);
+        assert_eq!(current(), 0);
+    }
+}
@@ -0,0 +1,52 @@
+//! secp256k1 ECDSA keys, as used by Ethereum style wallets.
+
+use sp_core::{ecdsa, hashing::blake2_256, Pair};
+
+pub const PUBLIC_KEY_BYTES: usize = 33;
+pub const SIGNATURE_BYTES: usize = 65;
+pub const SEED_BYTES: usize = 32;
+
+pub type EcdsaPublicKey = [u8; PUBLIC_KEY_BYTES]; // compressed pubkey
+pub type EcdsaSignature = [u8; SIGNATURE_BYTES]; // r, s and the recovery id
+pub type Seed = [u8; SEED_BYTES];
+
+pub fn pair_from_seed(seed: &Seed) -> ecdsa::Pair {
+    ecdsa::Pair::from_seed(seed)
+}
+
+pub fn sign(pair: &ecdsa::Pair, data: &[u8]) -> EcdsaSignature {
+    pair.sign(data).0
+}
+
+pub fn verify(pubkey: &[u8], signature: &[u8], data: &[u8]) -> bool {
+    ecdsa::Pair::verify_weak(signature, data, pubkey)
+}
+
+/// Returns the 32 bytes account id of the given compressed pubkey.
+///
+/// This is the same as how Substrate converts an ECDSA `MultiSigner` into an `AccountId32`, so the
+/// account can be used on chain as well.
+pub fn account_id(pubkey: &EcdsaPublicKey) -> [u8; 32] {
+    blake2_256(pubkey)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::RngCore;
+
+    fn generate_pair() -> ecdsa::Pair {
+        let mut seed = Seed::default();
+        rand::thread_rng().fill_bytes(&mut seed);
+        pair_from_seed(&seed)
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let pair = generate_pair();
+        let sig = sign(&pair, b"hello");
+        let pubkey = pair.public();
+        assert!(verify(pubkey.as_ref(), &sig, b"hello"));
+        assert!(!verify(pubkey.as_ref(), &sig, b"world"));
+    }
+}
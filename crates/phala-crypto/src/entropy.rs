@@ -0,0 +1,344 @@
+//! Entropy mixing for key generation.
+//!
+//! Keys generated inside the enclave should not depend on a single source of randomness. The
+//! [`EntropyMixer`] draws from every registered [`EntropySource`] (e.g. RDSEED, RDRAND and the
+//! platform RNG), runs a health test on each output and hashes them together with a domain tag
+//! and a counter. As long as one of the sources is good, the output is unpredictable. A source
+//! failing the health test makes the whole mix fail rather than silently degrading.
+
+use crate::CryptoError;
+
+use alloc::{boxed::Box, vec::Vec};
+use sp_core::hashing::blake2_256;
+
+/// The number of bytes drawn from each source for one mix.
+pub const SOURCE_BYTES: usize = 32;
+
+const MIX_TAG: &[u8] = b"phala-entropy-mix-v1";
+
+pub trait EntropySource {
+    /// A short name of the source, used in error reporting.
+    fn name(&self) -> &'static str;
+
+    /// Fills `buf` with random bytes.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), CryptoError>;
+}
+
+/// Wraps a closure as an entropy source.
+pub struct FnSource<F> {
+    name: &'static str,
+    f: F,
+}
+
+impl<F: FnMut(&mut [u8]) -> Result<(), CryptoError>> FnSource<F> {
+    pub fn new(name: &'static str, f: F) -> Self {
+        Self { name, f }
+    }
+}
+
+impl<F: FnMut(&mut [u8]) -> Result<(), CryptoError>> EntropySource for FnSource<F> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), CryptoError> {
+        (self.f)(buf)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::*;
+    use core::arch::x86_64::{__cpuid, __cpuid_count, _rdrand64_step, _rdseed64_step};
+
+    /// RDRAND/RDSEED may transiently fail under contention, retry a few times before giving up.
+    const RETRIES: usize = 100;
+
+    fn fill_with(
+        buf: &mut [u8],
+        mut step: impl FnMut(&mut u64) -> i32,
+    ) -> Result<(), CryptoError> {
+        for chunk in buf.chunks_mut(8) {
+            let mut word = 0_u64;
+            let mut ok = false;
+            for _ in 0..RETRIES {
+                if step(&mut word) == 1 {
+                    ok = true;
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+            if !ok {
+                return Err(CryptoError::EntropySourceFailed);
+            }
+            chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+        }
+        Ok(())
+    }
+
+    /// The RDRAND instruction.
+    pub struct RdRand(());
+
+    impl RdRand {
+        /// Returns `None` if the CPU does not support RDRAND.
+        pub fn new() -> Option<Self> {
+            // CPUID.01H:ECX.RDRAND[bit 30]
+            let supported = unsafe { __cpuid(1) }.ecx & (1 << 30) != 0;
+            supported.then(|| Self(()))
+        }
+    }
+
+    impl EntropySource for RdRand {
+        fn name(&self) -> &'static str {
+            "rdrand"
+        }
+
+        fn fill(&mut self, buf: &mut [u8]) -> Result<(), CryptoError> {
+            // Safety: the CPU feature is checked in `new`.
+            fill_with(buf, |word| unsafe { _rdrand64_step(word) })
+        }
+    }
+
+    /// The RDSEED instruction.
+    pub struct RdSeed(());
+
+    impl RdSeed {
+        /// Returns `None` if the CPU does not support RDSEED.
+        pub fn new() -> Option<Self> {
+            // CPUID.(EAX=07H, ECX=0H):EBX.RDSEED[bit 18]
+            let max_leaf = unsafe { __cpuid(0) }.eax;
+            let supported = max_leaf >= 7 && unsafe { __cpuid_count(7, 0) }.ebx & (1 << 18) != 0;
+            supported.then(|| Self(()))
+        }
+    }
+
+    impl EntropySource for RdSeed {
+        fn name(&self) -> &'static str {
+            "rdseed"
+        }
+
+        fn fill(&mut self, buf: &mut [u8]) -> Result<(), CryptoError> {
+            // Safety: the CPU feature is checked in `new`.
+            fill_with(buf, |word| unsafe { _rdseed64_step(word) })
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub use x86::{RdRand, RdSeed};
+
+/// Per source health test state.
+///
+/// Implements simplified versions of the repetition count and adaptive proportion tests from
+/// NIST SP 800-90B. They only catch grossly broken sources (stuck output, constant bytes), which
+/// is what we are defending against here.
+struct HealthTest {
+    last: Option<[u8; SOURCE_BYTES]>,
+}
+
+impl HealthTest {
+    fn check(&mut self, output: &[u8; SOURCE_BYTES]) -> bool {
+        // Repetition: a good source never repeats a 256-bit output.
+        if self.last.as_ref() == Some(output) {
+            return false;
+        }
+        self.last = Some(*output);
+        // Adaptive proportion: no byte value should dominate a 32-byte sample.
+        let mut counts = [0_u8; 256];
+        for b in output.iter() {
+            counts[*b as usize] += 1;
+        }
+        counts.iter().all(|&c| c <= 8)
+    }
+}
+
+struct Source {
+    inner: Box<dyn EntropySource + Send>,
+    health: HealthTest,
+}
+
+/// Mixes multiple entropy sources into seeds for key generation.
+pub struct EntropyMixer {
+    sources: Vec<Source>,
+    boot_count: u64,
+    counter: u64,
+}
+
+impl EntropyMixer {
+    /// Creates an empty mixer. `boot_count` should be a value persisted across restarts which is
+    /// increased on each boot, so that outputs never repeat even if all sources are broken.
+    pub fn new(boot_count: u64) -> Self {
+        Self {
+            sources: Vec::new(),
+            boot_count,
+            counter: 0,
+        }
+    }
+
+    /// Creates a mixer with the hardware sources available on this CPU.
+    pub fn with_hardware_sources(boot_count: u64) -> Self {
+        #[allow(unused_mut)]
+        let mut mixer = Self::new(boot_count);
+        #[cfg(target_arch = "x86_64")]
+        {
+            if let Some(source) = RdSeed::new() {
+                mixer.add_source(source);
+            }
+            if let Some(source) = RdRand::new() {
+                mixer.add_source(source);
+            }
+        }
+        mixer
+    }
+
+    pub fn add_source(&mut self, source: impl EntropySource + Send + 'static) {
+        self.sources.push(Source {
+            inner: Box::new(source),
+            health: HealthTest { last: None },
+        });
+    }
+
+    pub fn num_sources(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn boot_count(&self) -> u64 {
+        self.boot_count
+    }
+
+    /// Draws from all sources and mixes them into a 32 bytes seed bound to `domain`.
+    pub fn mix(&mut self, domain: &[u8]) -> Result<[u8; 32], CryptoError> {
+        if self.sources.is_empty() {
+            return Err(CryptoError::NoEntropySource);
+        }
+        self.counter += 1;
+        let mut buffer = Vec::with_capacity(
+            MIX_TAG.len() + domain.len() + 24 + self.sources.len() * SOURCE_BYTES,
+        );
+        buffer.extend_from_slice(MIX_TAG);
+        buffer.extend_from_slice(&(domain.len() as u64).to_le_bytes());
+        buffer.extend_from_slice(domain);
+        buffer.extend_from_slice(&self.boot_count.to_le_bytes());
+        buffer.extend_from_slice(&self.counter.to_le_bytes());
+        for source in self.sources.iter_mut() {
+            let mut output = [0_u8; SOURCE_BYTES];
+            source.inner.fill(&mut output)?;
+            if !source.health.check(&output) {
+                return Err(CryptoError::EntropyHealthTestFailed(source.inner.name()));
+            }
+            buffer.extend_from_slice(&output);
+        }
+        let seed = blake2_256(&buffer);
+        buffer.iter_mut().for_each(|b| *b = 0);
+        Ok(seed)
+    }
+
+    /// Fills `buf` with mixed entropy bound to `domain`.
+    pub fn fill(&mut self, domain: &[u8], buf: &mut [u8]) -> Result<(), CryptoError> {
+        for chunk in buf.chunks_mut(32) {
+            let seed = self.mix(domain)?;
+            chunk.copy_from_slice(&seed[..chunk.len()]);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::RngCore;
+
+    fn good_source() -> impl EntropySource + Send {
+        FnSource::new("good", |buf: &mut [u8]| {
+            rand::thread_rng().fill_bytes(buf);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn it_requires_a_source() {
+        let mut mixer = EntropyMixer::new(0);
+        assert!(matches!(
+            mixer.mix(b"test"),
+            Err(CryptoError::NoEntropySource)
+        ));
+    }
+
+    #[test]
+    fn it_is_unique_with_a_predictable_source() {
+        let mut counter = 0_u8;
+        let mut mixer = EntropyMixer::new(0);
+        mixer.add_source(good_source());
+        // A source returning a counter passes the health test but is predictable. The good
+        // source still makes the output unique.
+        mixer.add_source(FnSource::new("counter", move |buf: &mut [u8]| {
+            counter = counter.wrapping_add(1);
+            for (i, b) in buf.iter_mut().enumerate() {
+                *b = counter.wrapping_add(i as u8);
+            }
+            Ok(())
+        }));
+        let a = mixer.mix(b"test").unwrap();
+        let b = mixer.mix(b"test").unwrap();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn it_rejects_repeated_output() {
+        let mut mixer = EntropyMixer::new(0);
+        mixer.add_source(good_source());
+        mixer.add_source(FnSource::new("stuck", |buf: &mut [u8]| {
+            for (i, b) in buf.iter_mut().enumerate() {
+                *b = i as u8;
+            }
+            Ok(())
+        }));
+        assert!(mixer.mix(b"test").is_ok());
+        assert!(matches!(
+            mixer.mix(b"test"),
+            Err(CryptoError::EntropyHealthTestFailed("stuck"))
+        ));
+    }
+
+    #[test]
+    fn it_rejects_constant_bytes() {
+        let mut mixer = EntropyMixer::new(0);
+        mixer.add_source(FnSource::new("zero", |buf: &mut [u8]| {
+            buf.iter_mut().for_each(|b| *b = 0);
+            Ok(())
+        }));
+        assert!(matches!(
+            mixer.mix(b"test"),
+            Err(CryptoError::EntropyHealthTestFailed("zero"))
+        ));
+    }
+
+    #[test]
+    fn it_separates_domains() {
+        let mut a = EntropyMixer::new(1);
+        let mut b = EntropyMixer::new(1);
+        let source = || {
+            let mut n = 0_u8;
+            FnSource::new("det", move |buf: &mut [u8]| {
+                n += 1;
+                for (i, b) in buf.iter_mut().enumerate() {
+                    *b = n.wrapping_mul(31).wrapping_add(i as u8);
+                }
+                Ok(())
+            })
+        };
+        a.add_source(source());
+        b.add_source(source());
+        assert_ne!(a.mix(b"sr25519").unwrap(), b.mix(b"ecdh").unwrap());
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn hardware_sources_work() {
+        let mut mixer = EntropyMixer::with_hardware_sources(0);
+        if mixer.num_sources() > 0 {
+            let mut buf = [0_u8; 50];
+            mixer.fill(b"test", &mut buf).unwrap();
+        }
+    }
+}
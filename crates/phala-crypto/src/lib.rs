@@ -7,7 +7,10 @@ extern crate alloc;
 extern crate std;
 
 pub mod ecdh;
+pub mod ecdsa;
 pub mod aead;
+pub mod entropy;
+pub mod query;
 pub mod sr25519;
 
 #[derive(Debug)]
@@ -20,4 +23,10 @@ pub enum CryptoError {
     AeadInvalidKey,
     AeadEncryptError,
     AeadDecryptError,
+    // Entropy errors
+    NoEntropySource,
+    EntropySourceFailed,
+    EntropyHealthTestFailed(&'static str),
+    // Query envelope errors
+    QueryNonceMismatch,
 }
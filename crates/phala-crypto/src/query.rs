@@ -0,0 +1,327 @@
+//! End-to-end encrypted query envelope.
+//!
+//! This is the scheme used by contract queries between a client and a worker:
+//!
+//! - The client generates an ephemeral sr25519 ECDH key for each query, agrees on a shared secret
+//!   with the worker's ECDH pubkey and encrypts the request with AES-256-GCM under a random IV.
+//!   The request plaintext carries a 32 bytes random nonce chosen by the client.
+//! - The worker decrypts the request and encrypts the response to the client's ephemeral pubkey
+//!   under a one-time ECDH key of its own, with a fresh random IV. The response plaintext starts
+//!   with the nonce from the request.
+//! - The response key is derived from both the one-time secret and the secret of the request, so
+//!   only the worker holding the key the query was sent to can produce it. The client checks the
+//!   response echoes its nonce before accepting it.
+//!
+//! Both sides drop their ephemeral keys once the response is sealed or opened, so a worker key
+//! captured later can still open the recorded queries but not their responses.
+//!
+//! The ephemeral keys, the IVs and the nonce are all generated here and the pending state is
+//! consumed when opening the response, so callers can not reuse any of them by accident.
+//!
+//! # Compatibility
+//!
+//! This is version 2 of the response envelope, see [`RESPONSE_VERSION`]. The layout of
+//! `EncryptedPayload` is unchanged, but in version 1 the response `pubkey` was the worker's ECDH
+//! pubkey and the response was encrypted under the secret of the query. Clients of version 1
+//! fail to decrypt the responses of version 2 and must switch to [`client`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! // Client
+//! let (pending, request) = client::seal_query(&mut rng, &worker_pubkey, |nonce| {
+//!     (contract_id, *nonce, query).encode()
+//! })?;
+//! // Worker
+//! let (reply_to, plaintext) = worker::open_query(&worker_key, &request)?;
+//! let response = worker::seal_response(&mut rng, &worker_key, &reply_to, &nonce, &result)?;
+//! // Client
+//! let result = pending.open_response(&response)?;
+//! ```
+
+use crate::{
+    aead::{self, IV},
+    ecdh::{self, EcdhKey, EcdhPublicKey},
+    CryptoError,
+};
+
+use alloc::vec::Vec;
+use rand_core::{CryptoRng, RngCore};
+use ring::hkdf;
+
+/// The version of the response envelope, reported by the worker in `get_info`.
+pub const RESPONSE_VERSION: u32 = 2;
+
+pub const NONCE_BYTES: usize = 32;
+pub type Nonce = [u8; NONCE_BYTES];
+
+const RESPONSE_KDF_SALT: &[u8] = b"phala-query-response";
+
+/// An encrypted message, either a query or a response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncryptedPayload {
+    pub iv: IV,
+    /// The ECDH pubkey of the sender.
+    pub pubkey: EcdhPublicKey,
+    /// The cipher with the 128 bits auth tag appended.
+    pub data: Vec<u8>,
+}
+
+fn random_iv(rng: &mut (impl RngCore + CryptoRng)) -> IV {
+    let mut iv = IV::default();
+    rng.fill_bytes(&mut iv);
+    iv
+}
+
+fn ephemeral_key(rng: &mut (impl RngCore + CryptoRng)) -> Result<EcdhKey, CryptoError> {
+    let mut seed = ecdh::Seed::default();
+    rng.fill_bytes(&mut seed);
+    EcdhKey::create(&seed)
+}
+
+/// Derives the key of a response from the secret of its one-time key and the secret of the query.
+fn response_secret(one_time: &[u8], query: &[u8]) -> Result<[u8; 32], CryptoError> {
+    let mut ikm = Vec::with_capacity(one_time.len() + query.len());
+    ikm.extend_from_slice(one_time);
+    ikm.extend_from_slice(query);
+    let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, RESPONSE_KDF_SALT).extract(&ikm);
+    let okm = prk
+        .expand(&[], hkdf::HKDF_SHA256)
+        .map_err(|_| CryptoError::HkdfExpandError)?;
+    let mut secret = [0u8; 32];
+    okm.fill(&mut secret)
+        .map_err(|_| CryptoError::HkdfExpandError)?;
+    Ok(secret)
+}
+
+fn encrypt(
+    iv: IV,
+    key: &EcdhKey,
+    remote_pubkey: &EcdhPublicKey,
+    mut data: Vec<u8>,
+) -> Result<EncryptedPayload, CryptoError> {
+    let secret = ecdh::agree(key, remote_pubkey)?;
+    aead::encrypt(&iv, &secret, &mut data)?;
+    Ok(EncryptedPayload {
+        iv,
+        pubkey: key.public(),
+        data,
+    })
+}
+
+fn decrypt(key: &EcdhKey, payload: &EncryptedPayload) -> Result<Vec<u8>, CryptoError> {
+    let secret = ecdh::agree(key, &payload.pubkey)?;
+    let mut data = payload.data.clone();
+    let msg = aead::decrypt(&payload.iv, &secret, &mut data)?;
+    Ok(msg.to_vec())
+}
+
+/// Helpers for the query sender.
+pub mod client {
+    use super::*;
+
+    /// A query waiting for its response.
+    pub struct PendingQuery {
+        key: EcdhKey,
+        remote_pubkey: EcdhPublicKey,
+        nonce: Nonce,
+    }
+
+    impl PendingQuery {
+        pub fn nonce(&self) -> &Nonce {
+            &self.nonce
+        }
+
+        /// Decrypts the response and returns the result following the nonce.
+        pub fn open_response(self, response: &EncryptedPayload) -> Result<Vec<u8>, CryptoError> {
+            self.open_subscribed_response(response)
+        }
+
+        /// Decrypts a response without consuming the query, for the subscriptions which receive
+        /// a response after each block to the same query.
+        pub fn open_subscribed_response(
+            &self,
+            response: &EncryptedPayload,
+        ) -> Result<Vec<u8>, CryptoError> {
+            let query_secret = ecdh::agree(&self.key, &self.remote_pubkey)?;
+            let one_time_secret = ecdh::agree(&self.key, &response.pubkey)?;
+            let secret = response_secret(&one_time_secret, &query_secret)?;
+            let mut data = response.data.clone();
+            let len = aead::decrypt(&response.iv, &secret, &mut data)?.len();
+            data.truncate(len);
+            if data.len() < NONCE_BYTES || data[..NONCE_BYTES] != self.nonce {
+                return Err(CryptoError::QueryNonceMismatch);
+            }
+            Ok(data.split_off(NONCE_BYTES))
+        }
+    }
+
+    /// Encrypts a query to `remote_pubkey`.
+    ///
+    /// `build` receives the freshly generated nonce and returns the plaintext which must include
+    /// it, so that the responder can bind the response to it.
+    pub fn seal_query(
+        rng: &mut (impl RngCore + CryptoRng),
+        remote_pubkey: &EcdhPublicKey,
+        build: impl FnOnce(&Nonce) -> Vec<u8>,
+    ) -> Result<(PendingQuery, EncryptedPayload), CryptoError> {
+        let key = ephemeral_key(rng)?;
+        let mut nonce = Nonce::default();
+        rng.fill_bytes(&mut nonce);
+        let payload = encrypt(random_iv(rng), &key, remote_pubkey, build(&nonce))?;
+        let pending = PendingQuery {
+            key,
+            remote_pubkey: *remote_pubkey,
+            nonce,
+        };
+        Ok((pending, payload))
+    }
+}
+
+/// Helpers for the query receiver (i.e. the enclave).
+pub mod worker {
+    use super::*;
+
+    /// Where the response should be sent to.
+    #[derive(Clone, Debug)]
+    pub struct ReplyTo {
+        pub pubkey: EcdhPublicKey,
+    }
+
+    /// Decrypts a query sent to `key`.
+    pub fn open_query(
+        key: &EcdhKey,
+        query: &EncryptedPayload,
+    ) -> Result<(ReplyTo, Vec<u8>), CryptoError> {
+        let data = decrypt(key, query)?;
+        Ok((
+            ReplyTo {
+                pubkey: query.pubkey,
+            },
+            data,
+        ))
+    }
+
+    /// Encrypts the response of a query, prefixed with the nonce from the query, under a one-time
+    /// key. `key` must be the key the query was sent to.
+    pub fn seal_response(
+        rng: &mut (impl RngCore + CryptoRng),
+        key: &EcdhKey,
+        reply_to: &ReplyTo,
+        nonce: &Nonce,
+        result: &[u8],
+    ) -> Result<EncryptedPayload, CryptoError> {
+        let mut data = Vec::with_capacity(NONCE_BYTES + result.len());
+        data.extend_from_slice(nonce);
+        data.extend_from_slice(result);
+        let one_time_key = ephemeral_key(rng)?;
+        let query_secret = ecdh::agree(key, &reply_to.pubkey)?;
+        let one_time_secret = ecdh::agree(&one_time_key, &reply_to.pubkey)?;
+        let secret = response_secret(&one_time_secret, &query_secret)?;
+        let iv = random_iv(rng);
+        aead::encrypt(&iv, &secret, &mut data)?;
+        Ok(EncryptedPayload {
+            iv,
+            pubkey: one_time_key.public(),
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn worker_key() -> EcdhKey {
+        let mut seed = ecdh::Seed::default();
+        rand::thread_rng().fill_bytes(&mut seed);
+        EcdhKey::create(&seed).unwrap()
+    }
+
+    fn roundtrip(
+        worker_key: &EcdhKey,
+        tamper: impl FnOnce(&mut EncryptedPayload),
+    ) -> Result<Vec<u8>, CryptoError> {
+        let mut rng = rand::thread_rng();
+        let (pending, query) =
+            client::seal_query(&mut rng, &worker_key.public(), |nonce| {
+                let mut data = nonce.to_vec();
+                data.extend_from_slice(b"ping");
+                data
+            })?;
+
+        let (reply_to, plaintext) = worker::open_query(worker_key, &query)?;
+        assert_eq!(&plaintext[NONCE_BYTES..], b"ping");
+        let mut nonce = Nonce::default();
+        nonce.copy_from_slice(&plaintext[..NONCE_BYTES]);
+        let mut response = worker::seal_response(&mut rng, worker_key, &reply_to, &nonce, b"pong")?;
+        tamper(&mut response);
+        pending.open_response(&response)
+    }
+
+    #[test]
+    fn query_roundtrip() {
+        let key = worker_key();
+        assert_eq!(roundtrip(&key, |_| ()).unwrap(), b"pong");
+    }
+
+    #[test]
+    fn tampered_response_is_rejected() {
+        let key = worker_key();
+        assert!(matches!(
+            roundtrip(&key, |resp| resp.data[0] ^= 1),
+            Err(CryptoError::AeadDecryptError)
+        ));
+    }
+
+    #[test]
+    fn response_from_other_worker_is_rejected() {
+        let mut rng = rand::thread_rng();
+        let key = worker_key();
+        let (pending, query) =
+            client::seal_query(&mut rng, &key.public(), |nonce| nonce.to_vec()).unwrap();
+        let (reply_to, _) = worker::open_query(&key, &query).unwrap();
+        // The client pubkey is public, anyone can seal a response to it.
+        let response =
+            worker::seal_response(&mut rng, &worker_key(), &reply_to, pending.nonce(), b"fake")
+                .unwrap();
+        assert!(matches!(
+            pending.open_response(&response),
+            Err(CryptoError::AeadDecryptError)
+        ));
+    }
+
+    #[test]
+    fn response_is_sealed_under_one_time_key() {
+        let mut rng = rand::thread_rng();
+        let key = worker_key();
+        let (_, query) =
+            client::seal_query(&mut rng, &key.public(), |nonce| nonce.to_vec()).unwrap();
+        let (reply_to, _) = worker::open_query(&key, &query).unwrap();
+        let nonce = [0; NONCE_BYTES];
+        let response = worker::seal_response(&mut rng, &key, &reply_to, &nonce, b"a").unwrap();
+        let another = worker::seal_response(&mut rng, &key, &reply_to, &nonce, b"a").unwrap();
+        assert_ne!(response.pubkey, key.public());
+        assert_ne!(response.pubkey, another.pubkey);
+        // The worker key alone, e.g. leaked later, can not open the recorded response.
+        let query_secret = ecdh::agree(&key, &query.pubkey).unwrap();
+        let mut data = response.data.clone();
+        assert!(aead::decrypt(&response.iv, &query_secret, &mut data).is_err());
+    }
+
+    #[test]
+    fn replayed_response_is_rejected() {
+        let mut rng = rand::thread_rng();
+        let key = worker_key();
+        let (pending, query) =
+            client::seal_query(&mut rng, &key.public(), |nonce| nonce.to_vec()).unwrap();
+        let (reply_to, _) = worker::open_query(&key, &query).unwrap();
+        // A response bound to another nonce
+        let response =
+            worker::seal_response(&mut rng, &key, &reply_to, &[0; NONCE_BYTES], b"old").unwrap();
+        assert!(matches!(
+            pending.open_response(&response),
+            Err(CryptoError::QueryNonceMismatch)
+        ));
+    }
+}
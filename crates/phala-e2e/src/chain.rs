@@ -0,0 +1,484 @@
+//! A minimal chain simulation running the Phala pallets natively.
+//!
+//! The runtime defined here only contains the pallets pRuntime reads from, under the same names
+//! and with the same primitive types as the node runtime, so that pRuntime can decode its storage
+//! as if it was syncing from a real node. Blocks are finalized instantly by a single GRANDPA
+//! authority owned by the [`Chain`].
+
+use std::collections::BTreeMap;
+
+use frame_support::{
+    dispatch::Dispatchable,
+    parameter_types,
+    traits::{ConstU32, Everything, GenesisBuild, OnFinalize, OnInitialize},
+};
+use parity_scale_codec::Encode;
+use phactory_api::blocks::{
+    AuthoritySet, BlockHeaderWithChanges, GenesisBlockInfo, HeaderToSync, StorageChanges,
+    StorageState,
+};
+use phala_pallets::{
+    pallet_fat, pallet_mq, pallet_registry, pallet_sidevm,
+    registry::{Attestation, AttestationError, AttestationValidator, IasFields},
+};
+use phala_types::messaging::{Message, MessageOrigin};
+use sp_core::{storage::StateVersion, Pair, H256};
+use sp_finality_grandpa::{AuthorityPair, GRANDPA_AUTHORITIES_KEY};
+use sp_runtime::{
+    generic,
+    traits::{Header as _, IdentityLookup},
+    DispatchError,
+};
+use sp_state_machine::Backend as _;
+
+pub use runtime::{AccountId, Balance, BlockNumber, Hash, Header, Index, Moment};
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Runtime>;
+type Block = generic::Block<Header, UncheckedExtrinsic>;
+
+frame_support::construct_runtime!(
+    pub enum Runtime where
+        Block = Block,
+        NodeBlock = Block,
+        UncheckedExtrinsic = UncheckedExtrinsic,
+    {
+        System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+        Timestamp: pallet_timestamp::{Pallet, Call, Storage, Inherent},
+        Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
+        PhalaMq: pallet_mq::{Pallet, Call, Storage},
+        PhalaRegistry: pallet_registry::{Pallet, Call, Event<T>, Storage, Config<T>},
+        PhalaFatContracts: pallet_fat::{Pallet, Call, Event<T>, Storage},
+        PhalaSidevm: pallet_sidevm::{Pallet, Call, Event<T>, Storage},
+    }
+);
+
+pub const DOLLARS: Balance = 1_000_000_000_000;
+/// The block time of the simulated chain
+pub const BLOCK_TIME_MS: Moment = 12_000;
+/// The number of accounts endowed in the genesis, see [`account`]
+pub const ENDOWED_ACCOUNTS: u8 = 10;
+
+const GENESIS_TIME_MS: Moment = 1_600_000_000_000;
+const GRANDPA_SET_ID: u64 = 0;
+
+parameter_types! {
+    pub const BlockHashCount: BlockNumber = 250;
+    pub const SS58Prefix: u16 = 30;
+    pub const ExistentialDeposit: Balance = 1;
+    pub const MinimumPeriod: Moment = 1;
+    pub const VerifyPRuntime: bool = false;
+    pub const VerifyRelaychainGenesisBlockHash: bool = false;
+    pub const EndpointLifetime: u64 = 3600;
+    pub const GatekeeperElectionPeriod: BlockNumber = 100;
+    pub const MaxGatekeepers: u32 = 3;
+    pub const GatekeeperConfidenceLevel: u8 = 3;
+    pub const IngressRetentionPeriod: BlockNumber = 100;
+    pub const ClusterDeposit: Balance = 1 * DOLLARS;
+    pub const MaxCodeAllowListLength: u32 = 16;
+    pub const MaxSidevmCodeSize: u32 = 2 * 1024 * 1024;
+    pub const MaxSidevmMemoryPages: u32 = 1024;
+    pub const MaxSidevmGrpcTargets: u32 = 16;
+    pub const MaxSidevmGrpcTargetLen: u32 = 256;
+}
+
+impl frame_system::Config for Runtime {
+    type BaseCallFilter = Everything;
+    type BlockWeights = ();
+    type BlockLength = ();
+    type Origin = Origin;
+    type Call = Call;
+    type Index = Index;
+    type BlockNumber = BlockNumber;
+    type Hash = Hash;
+    type Hashing = sp_runtime::traits::BlakeTwo256;
+    type AccountId = AccountId;
+    type Lookup = IdentityLookup<AccountId>;
+    type Header = Header;
+    type Event = Event;
+    type BlockHashCount = BlockHashCount;
+    type DbWeight = ();
+    // The default runtime version uses the state version 0, the same as pRuntime.
+    type Version = ();
+    type PalletInfo = PalletInfo;
+    type AccountData = pallet_balances::AccountData<Balance>;
+    type OnNewAccount = ();
+    type OnKilledAccount = ();
+    type SystemWeightInfo = ();
+    type SS58Prefix = SS58Prefix;
+    type OnSetCode = ();
+    type MaxConsumers = ConstU32<16>;
+}
+
+impl pallet_timestamp::Config for Runtime {
+    type Moment = Moment;
+    type OnTimestampSet = ();
+    type MinimumPeriod = MinimumPeriod;
+    type WeightInfo = ();
+}
+
+impl pallet_balances::Config for Runtime {
+    type Balance = Balance;
+    type DustRemoval = ();
+    type Event = Event;
+    type ExistentialDeposit = ExistentialDeposit;
+    type AccountStore = System;
+    type WeightInfo = ();
+    type MaxLocks = ();
+    type MaxReserves = ();
+    type ReserveIdentifier = [u8; 8];
+}
+
+impl pallet_registry::Config for Runtime {
+    type Event = Event;
+    type Currency = Balances;
+    type AttestationValidator = DevValidator;
+    type UnixTime = Timestamp;
+    type VerifyPRuntime = VerifyPRuntime;
+    type VerifyRelaychainGenesisBlockHash = VerifyRelaychainGenesisBlockHash;
+    type GovernanceOrigin = frame_system::EnsureRoot<AccountId>;
+    type EndpointLifetime = EndpointLifetime;
+    type GatekeeperElectionPeriod = GatekeeperElectionPeriod;
+    type MaxGatekeepers = MaxGatekeepers;
+    type GatekeeperConfidenceLevel = GatekeeperConfidenceLevel;
+}
+
+impl pallet_mq::Config for Runtime {
+    type QueueNotifyConfig = MessageRouteConfig;
+    type CallMatcher = MqCallMatcher;
+    type IngressRetentionPeriod = IngressRetentionPeriod;
+}
+
+impl pallet_fat::Config for Runtime {
+    type Event = Event;
+    type Currency = Balances;
+    type ClusterDeposit = ClusterDeposit;
+    type MaxCodeAllowListLength = MaxCodeAllowListLength;
+    type OnClusterDestroyed = ();
+}
+
+impl pallet_sidevm::Config for Runtime {
+    type Event = Event;
+    type MaxCodeSize = MaxSidevmCodeSize;
+    type MaxMemoryPages = MaxSidevmMemoryPages;
+    type MaxGrpcTargets = MaxSidevmGrpcTargets;
+    type MaxGrpcTargetLen = MaxSidevmGrpcTargetLen;
+}
+
+pub struct MqCallMatcher;
+impl pallet_mq::CallMatcher<Runtime> for MqCallMatcher {
+    fn match_call(call: &Call) -> Option<&pallet_mq::Call<Runtime>> {
+        match call {
+            Call::PhalaMq(mq_call) => Some(mq_call),
+            _ => None,
+        }
+    }
+}
+
+/// Routes the offchain messages to the pallets, the same as the node runtime does.
+pub struct MessageRouteConfig;
+impl pallet_mq::QueueNotifyConfig for MessageRouteConfig {
+    fn on_message_received(message: &Message) -> frame_support::dispatch::DispatchResult {
+        use phala_types::messaging::{BindTopic, DecodedMessage};
+
+        fn try_dispatch<Msg, Func>(func: Func, message: &Message) -> Result<(), DispatchError>
+        where
+            Msg: parity_scale_codec::Decode + BindTopic,
+            Func: Fn(DecodedMessage<Msg>) -> Result<(), DispatchError>,
+        {
+            if message.destination.path() == &Msg::topic() {
+                let msg: DecodedMessage<Msg> = message
+                    .decode()
+                    .ok_or(DispatchError::Other("MessageCodecError"))?;
+                return (func)(msg);
+            }
+            Ok(())
+        }
+
+        try_dispatch(PhalaRegistry::on_message_received, message)?;
+        try_dispatch(
+            PhalaFatContracts::on_worker_cluster_message_received,
+            message,
+        )?;
+        try_dispatch(
+            PhalaFatContracts::on_worker_contract_message_received,
+            message,
+        )?;
+        try_dispatch(PhalaFatContracts::on_cluster_message_received, message)?;
+        try_dispatch(PhalaFatContracts::on_contract_message_received, message)?;
+        try_dispatch(
+            PhalaFatContracts::on_contract_command_failure_received,
+            message,
+        )?;
+        Ok(())
+    }
+}
+
+/// Accepts any attestation, so that the non-SGX pRuntime can register itself.
+pub struct DevValidator;
+impl AttestationValidator for DevValidator {
+    fn validate(
+        _attestation: &Attestation,
+        user_data_hash: &[u8; 32],
+        _now: u64,
+        _verify_pruntime: bool,
+        _pruntime_allowlist: Vec<Vec<u8>>,
+    ) -> Result<IasFields, AttestationError> {
+        let mut report_data = [0u8; 64];
+        report_data[..32].copy_from_slice(user_data_hash);
+        Ok(IasFields {
+            mr_enclave: [0u8; 32],
+            mr_signer: [0u8; 32],
+            isv_prod_id: [0u8; 2],
+            isv_svn: [0u8; 2],
+            report_data,
+            confidence_level: 128u8,
+        })
+    }
+}
+
+/// Returns the `index`-th dev account. The first [`ENDOWED_ACCOUNTS`] accounts are endowed with
+/// 1000 dollars in the genesis.
+pub fn account(index: u8) -> AccountId {
+    AccountId::new([index; 32])
+}
+
+/// A produced block
+pub struct ChainBlock {
+    pub header: Header,
+    pub storage_changes: StorageChanges,
+    /// The dispatch results of the calls submitted to this block, in order
+    pub results: Vec<Result<(), DispatchError>>,
+}
+
+/// The in-memory chain.
+///
+/// Calls are queued by [`Chain::submit`] and dispatched in the next [`Chain::produce_block`].
+pub struct Chain {
+    ext: sp_io::TestExternalities,
+    /// The storage snapshot at the best block, to calculate the storage changes of a new block
+    state: BTreeMap<Vec<u8>, Vec<u8>>,
+    genesis: Header,
+    genesis_state: StorageState,
+    blocks: Vec<ChainBlock>,
+    pending: Vec<(AccountId, Call)>,
+    authority: AuthorityPair,
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chain {
+    /// Creates a chain with the [`ENDOWED_ACCOUNTS`] endowed and a benchmark duration of 1 block.
+    pub fn new() -> Self {
+        let authority = AuthorityPair::from_seed(&[0xaa; 32]);
+
+        let mut storage = frame_system::GenesisConfig::default()
+            .build_storage::<Runtime>()
+            .expect("Failed to build the system genesis");
+        pallet_balances::GenesisConfig::<Runtime> {
+            balances: (0..ENDOWED_ACCOUNTS)
+                .map(|i| (account(i), 1000 * DOLLARS))
+                .collect(),
+        }
+        .assimilate_storage(&mut storage)
+        .expect("Failed to build the balances genesis");
+        pallet_registry::GenesisConfig::<Runtime> {
+            workers: vec![],
+            gatekeepers: vec![],
+            benchmark_duration: 1,
+        }
+        .assimilate_storage(&mut storage)
+        .expect("Failed to build the registry genesis");
+        // The light client in pRuntime reads the authorities from the well-known key, prefixed by
+        // the version of the authority list.
+        let authorities = vec![(authority.public(), 1u64)];
+        storage.top.insert(
+            GRANDPA_AUTHORITIES_KEY.to_vec(),
+            (1u8, authorities).encode(),
+        );
+
+        let mut ext = sp_io::TestExternalities::new_with_state_version(storage, StateVersion::V0);
+        ext.commit_all().expect("Failed to commit the genesis");
+        let backend = ext.as_backend();
+        let genesis = Header::new(
+            0,
+            Default::default(),
+            *backend.root(),
+            Default::default(),
+            Default::default(),
+        );
+        let genesis_state = backend.pairs();
+        Self {
+            state: genesis_state.iter().cloned().collect(),
+            ext,
+            genesis,
+            genesis_state,
+            blocks: vec![],
+            pending: vec![],
+            authority,
+        }
+    }
+
+    /// The genesis block info to initialize pRuntime with
+    pub fn genesis_info(&self) -> GenesisBlockInfo {
+        let proof = sp_state_machine::prove_read(self.ext.as_backend(), &[GRANDPA_AUTHORITIES_KEY])
+            .expect("Failed to prove the authorities");
+        GenesisBlockInfo {
+            block_header: self.genesis.clone(),
+            authority_set: AuthoritySet {
+                list: vec![(self.authority.public(), 1)],
+                id: GRANDPA_SET_ID,
+            },
+            proof: proof.into_iter_nodes().collect(),
+        }
+    }
+
+    /// The genesis storage to initialize pRuntime with
+    pub fn genesis_state(&self) -> StorageState {
+        self.genesis_state.clone()
+    }
+
+    /// The number of the best block
+    pub fn best_number(&self) -> BlockNumber {
+        self.blocks.len() as _
+    }
+
+    /// The header of the block with the given number
+    pub fn header(&self, number: BlockNumber) -> Option<&Header> {
+        match number {
+            0 => Some(&self.genesis),
+            n => self.blocks.get(n as usize - 1).map(|b| &b.header),
+        }
+    }
+
+    /// The block with the given number
+    pub fn block(&self, number: BlockNumber) -> Option<&ChainBlock> {
+        self.blocks.get((number as usize).checked_sub(1)?)
+    }
+
+    /// Queues a call to be dispatched from `who` in the next block.
+    pub fn submit(&mut self, who: AccountId, call: impl Into<Call>) {
+        self.pending.push((who, call.into()));
+    }
+
+    /// Produces and finalizes a block with the queued calls.
+    pub fn produce_block(&mut self) -> &ChainBlock {
+        let number = self.best_number() + 1;
+        let parent_hash = self.header(number - 1).expect("Parent must exist").hash();
+        let now = GENESIS_TIME_MS + number as Moment * BLOCK_TIME_MS;
+        let calls = std::mem::take(&mut self.pending);
+
+        let (header, results) = self.ext.execute_with(|| {
+            System::initialize(&number, &parent_hash, &Default::default());
+            <AllPalletsWithSystem as OnInitialize<BlockNumber>>::on_initialize(number);
+            Timestamp::set(Origin::none(), now).expect("Failed to set the timestamp");
+            let results = calls
+                .into_iter()
+                .map(|(who, call)| {
+                    call.dispatch(Origin::signed(who))
+                        .map(|_| ())
+                        .map_err(|e| e.error)
+                })
+                .collect::<Vec<_>>();
+            <AllPalletsWithSystem as OnFinalize<BlockNumber>>::on_finalize(number);
+            (System::finalize(), results)
+        });
+        self.ext.commit_all().expect("Failed to commit the block");
+
+        let state: BTreeMap<_, _> = self.ext.as_backend().pairs().into_iter().collect();
+        let mut main_storage_changes: Vec<_> = state
+            .iter()
+            .filter(|(k, v)| self.state.get(*k) != Some(v))
+            .map(|(k, v)| (k.clone(), Some(v.clone())))
+            .collect();
+        main_storage_changes.extend(
+            self.state
+                .keys()
+                .filter(|k| !state.contains_key(*k))
+                .map(|k| (k.clone(), None)),
+        );
+        self.state = state;
+
+        self.blocks.push(ChainBlock {
+            header,
+            storage_changes: StorageChanges {
+                main_storage_changes,
+                child_storage_changes: vec![],
+            },
+            results,
+        });
+        self.blocks.last().expect("Just pushed; qed.")
+    }
+
+    /// Executes `f` in the context of the best block.
+    ///
+    /// Storage changes made in `f` are included in the next block.
+    pub fn execute_with<R>(&mut self, f: impl FnOnce() -> R) -> R {
+        self.ext.execute_with(f)
+    }
+
+    /// Returns the events emitted in the best block.
+    pub fn events(&mut self) -> Vec<Event> {
+        self.execute_with(|| System::events().into_iter().map(|r| r.event).collect())
+    }
+
+    /// Returns the ingress sequence expected by the chain from `sender`.
+    pub fn mq_next_sequence(&mut self, sender: &MessageOrigin) -> u64 {
+        self.execute_with(|| pallet_mq::OffchainIngress::<Runtime>::get(sender).unwrap_or(0))
+    }
+
+    /// Returns the headers in `from..=to` to sync, with the last one justified.
+    pub fn headers_to_sync(&self, from: BlockNumber, to: BlockNumber) -> Vec<HeaderToSync> {
+        (from..=to)
+            .map(|number| HeaderToSync {
+                header: self.header(number).expect("Header must exist").clone(),
+                justification: (number == to).then(|| self.justify(number)),
+            })
+            .collect()
+    }
+
+    /// Returns the blocks in `from..=to` with the storage changes.
+    pub fn blocks_to_dispatch(
+        &self,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Vec<BlockHeaderWithChanges> {
+        (from..=to)
+            .map(|number| {
+                let block = self.block(number).expect("Block must exist");
+                BlockHeaderWithChanges {
+                    block_header: block.header.clone(),
+                    storage_changes: block.storage_changes.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Creates a GRANDPA justification finalizing the given block
+    fn justify(&self, number: BlockNumber) -> Vec<u8> {
+        let header = self.header(number).expect("Header must exist");
+        let precommit = finality_grandpa::Precommit {
+            target_hash: header.hash(),
+            target_number: number,
+        };
+        let round = number as u64;
+        let message = finality_grandpa::Message::<H256, BlockNumber>::Precommit(precommit.clone());
+        let signature = self
+            .authority
+            .sign(&(&message, round, GRANDPA_SET_ID).encode());
+        let commit = finality_grandpa::Commit {
+            target_hash: precommit.target_hash,
+            target_number: number,
+            precommits: vec![finality_grandpa::SignedPrecommit {
+                precommit,
+                signature,
+                id: self.authority.public(),
+            }],
+        };
+        // Encoded as `GrandpaJustification { round, commit, votes_ancestries }`
+        (round, commit, Vec::<Header>::new()).encode()
+    }
+}
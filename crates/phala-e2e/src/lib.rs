@@ -0,0 +1,97 @@
+//! In-process end-to-end test harness.
+//!
+//! Runs a minimal in-memory chain, a non-SGX pRuntime and a relayer loop in a single process, so
+//! that the interaction between the pallets and pRuntime (mq, worker registration, contracts) can
+//! be covered by `cargo test` without docker.
+//!
+//! ```ignore
+//! let mut net = TestNet::new();
+//! net.register_worker()?;
+//! net.run_blocks(3)?;
+//! assert!(net.worker.info()?.registered);
+//! ```
+
+pub mod chain;
+mod platform;
+mod relayer;
+mod worker;
+
+pub use chain::{account, Chain, ChainBlock, Runtime};
+pub use phactory_api::prpc as pb;
+pub use platform::DevPlatform;
+pub use relayer::Relayer;
+pub use worker::{InProcessClient, Worker};
+
+use anyhow::Result;
+use chain::{AccountId, BlockNumber, Call};
+use phala_pallets::registry::Attestation;
+
+/// A chain with a single worker and the relayer between them.
+pub struct TestNet {
+    pub chain: Chain,
+    pub worker: Worker,
+    pub relayer: Relayer,
+}
+
+impl Default for TestNet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestNet {
+    /// Creates a chain and a worker operated by `account(1)`. The worker is initialized with the
+    /// chain genesis but not registered on chain yet.
+    pub fn new() -> Self {
+        let chain = Chain::new();
+        let mut worker = Worker::new(1);
+        worker
+            .init_runtime(&chain, Some(account(1)))
+            .expect("Failed to init the worker runtime");
+        Self {
+            chain,
+            worker,
+            relayer: Relayer::new(account(0)),
+        }
+    }
+
+    /// Queues a call to be dispatched from `who` in the next block.
+    pub fn submit(&mut self, who: AccountId, call: impl Into<Call>) {
+        self.chain.submit(who, call);
+    }
+
+    /// Queues the registration of the worker, submitted by its operator.
+    pub fn register_worker(&mut self) -> Result<()> {
+        let info = self.worker.registration_info()?;
+        let operator = info.operator.clone().unwrap_or_else(|| account(1));
+        self.submit(
+            operator,
+            phala_pallets::pallet_registry::Call::<Runtime>::register_worker {
+                pruntime_info: info,
+                attestation: Attestation::SgxIas {
+                    ra_report: vec![],
+                    signature: vec![],
+                    raw_signing_cert: vec![],
+                },
+            },
+        );
+        Ok(())
+    }
+
+    /// Produces a block, syncs it to the worker and queues the worker egress to the next block.
+    ///
+    /// Returns the number of the produced block.
+    pub fn step(&mut self) -> Result<BlockNumber> {
+        let number = self.chain.produce_block().header.number;
+        self.relayer.sync(&mut self.chain, &self.worker)?;
+        Ok(number)
+    }
+
+    /// Runs `n` steps
+    pub fn run_blocks(&mut self, n: u32) -> Result<()> {
+        for _ in 0..n {
+            self.step()?;
+        }
+        Ok(())
+    }
+}
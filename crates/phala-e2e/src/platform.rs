@@ -0,0 +1,105 @@
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::anyhow;
+use phactory_pal::{
+    Logging, Machine, MemoryStats, MemoryUsage, ProtectedFileSystem, Sealing, RA,
+};
+use serde::{Deserialize, Serialize};
+
+/// A platform without TEE. Data is sealed to plain files and remote attestation is unavailable.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DevPlatform;
+
+impl Sealing for DevPlatform {
+    type SealError = std::io::Error;
+    type UnsealError = std::io::Error;
+
+    fn seal_data(&self, path: impl AsRef<Path>, data: &[u8]) -> Result<(), Self::SealError> {
+        std::fs::write(path, data)
+    }
+
+    fn unseal_data(&self, path: impl AsRef<Path>) -> Result<Option<Vec<u8>>, Self::UnsealError> {
+        match std::fs::read(path) {
+            Err(err) if matches!(err.kind(), ErrorKind::NotFound) => Ok(None),
+            other => other.map(Some),
+        }
+    }
+}
+
+impl ProtectedFileSystem for DevPlatform {
+    type IoError = std::io::Error;
+    type ReadFile = File;
+    type WriteFile = File;
+
+    fn open_protected_file(
+        &self,
+        path: impl AsRef<Path>,
+        _key: &[u8],
+    ) -> Result<Option<Self::ReadFile>, Self::IoError> {
+        match File::open(path) {
+            Err(err) if matches!(err.kind(), ErrorKind::NotFound) => Ok(None),
+            other => other.map(Some),
+        }
+    }
+
+    fn create_protected_file(
+        &self,
+        path: impl AsRef<Path>,
+        _key: &[u8],
+    ) -> Result<Self::WriteFile, Self::IoError> {
+        File::create(path)
+    }
+}
+
+impl RA for DevPlatform {
+    type Error = anyhow::Error;
+
+    fn create_attestation_report(
+        &self,
+        _data: &[u8],
+    ) -> Result<(String, String, String), Self::Error> {
+        Err(anyhow!("RA is not available on the dev platform"))
+    }
+
+    fn quote_test(&self) -> Result<(), Self::Error> {
+        Err(anyhow!("RA is not available on the dev platform"))
+    }
+}
+
+impl Machine for DevPlatform {
+    fn machine_id(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    fn cpu_core_num(&self) -> u32 {
+        1
+    }
+
+    fn cpu_feature_level(&self) -> u32 {
+        1
+    }
+}
+
+impl MemoryStats for DevPlatform {
+    fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            total_peak_used: 0,
+            rust_used: 0,
+            rust_peak_used: 0,
+        }
+    }
+}
+
+impl Logging for DevPlatform {
+    type Error = anyhow::Error;
+
+    fn set_log_filter(&self, _filter: &str) -> Result<(), Self::Error> {
+        Err(anyhow!("The log filter is owned by the host app on the dev platform"))
+    }
+
+    fn log_filter(&self) -> String {
+        String::new()
+    }
+}
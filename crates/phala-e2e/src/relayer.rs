@@ -0,0 +1,63 @@
+//! A mock of pherry, relaying the chain to a worker and the worker egress back to the chain.
+
+use anyhow::Result;
+use futures::executor::block_on;
+use phactory_api::prpc as pb;
+use phala_pallets::pallet_mq;
+
+use crate::chain::{AccountId, BlockNumber, Chain, Runtime};
+use crate::worker::Worker;
+
+pub struct Relayer {
+    /// The account to submit the egress messages with
+    account: AccountId,
+    synced_to: BlockNumber,
+}
+
+impl Relayer {
+    pub fn new(account: AccountId) -> Self {
+        Self {
+            account,
+            synced_to: 0,
+        }
+    }
+
+    /// The last block synced to the worker
+    pub fn synced_to(&self) -> BlockNumber {
+        self.synced_to
+    }
+
+    /// Syncs the new blocks to the worker, then queues the egress messages of the worker to the
+    /// next block of the chain.
+    pub fn sync(&mut self, chain: &mut Chain, worker: &Worker) -> Result<()> {
+        let best = chain.best_number();
+        if best > self.synced_to {
+            let from = self.synced_to + 1;
+            let client = worker.client();
+            block_on(client.sync_header(pb::HeadersToSync::new(
+                chain.headers_to_sync(from, best),
+                None,
+            )))?;
+            block_on(client.dispatch_blocks(pb::Blocks::new(chain.blocks_to_dispatch(from, best))))?;
+            self.synced_to = best;
+        }
+
+        let messages = block_on(worker.client().get_egress_messages(()))?.decode_messages()?;
+        for (sender, messages) in messages {
+            let next_sequence = chain.mq_next_sequence(&sender);
+            for message in messages {
+                if message.sequence < next_sequence {
+                    continue;
+                }
+                log::debug!("Relaying message {} from {}", message.sequence, sender);
+                chain.submit(
+                    self.account.clone(),
+                    pallet_mq::Call::<Runtime>::sync_offchain_message {
+                        signed_message: message,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+//! A non-SGX pRuntime running in the same process, served through the same prpc dispatcher as the
+//! real pRuntime.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::{anyhow, Result};
+use futures::executor::block_on;
+use phactory::Phactory;
+use phactory_api::{
+    ecall_args::{git_revision, InitArgs},
+    prpc::{
+        self as pb,
+        client::{Error as ClientError, RequestClient},
+        phactory_api_client::PhactoryApiClient,
+        server::ProtoError as ServerError,
+        Message,
+    },
+};
+use phala_types::{WorkerPublicKey, WorkerRegistrationInfo};
+use tempdir::TempDir;
+
+use crate::chain::{AccountId, Chain};
+use crate::platform::DevPlatform;
+
+/// The size of the output buffer, the same as the one in the real pRuntime
+const OUTPUT_BUF_LEN: usize = 1024 * 1024 * 2;
+
+/// A prpc transport calling into the in-process pRuntime directly.
+pub struct InProcessClient {
+    phactory: Arc<Mutex<Phactory<DevPlatform>>>,
+}
+
+#[async_trait::async_trait]
+impl RequestClient for InProcessClient {
+    async fn request(&self, path: &str, body: Vec<u8>) -> Result<Vec<u8>, ClientError> {
+        let (code, data) = phactory::dispatch_prpc_request(
+            path.as_bytes(),
+            &body,
+            OUTPUT_BUF_LEN,
+            &self.phactory,
+        );
+        if code == 200 {
+            Ok(data)
+        } else {
+            let err: ServerError = Message::decode(&data[..])?;
+            Err(ClientError::ServerError(err))
+        }
+    }
+}
+
+pub struct Worker {
+    phactory: Arc<Mutex<Phactory<DevPlatform>>>,
+    client: PhactoryApiClient<InProcessClient>,
+    /// The seed of the identity key injected to the pRuntime
+    key_seed: [u8; 32],
+    runtime_info: Option<pb::InitRuntimeResponse>,
+    _sealing_dir: TempDir,
+}
+
+impl Worker {
+    /// Creates a pRuntime with its identity key derived from `seed`.
+    pub fn new(seed: u8) -> Self {
+        let sealing_dir = TempDir::new("phala-e2e").expect("Failed to create the sealing dir");
+        let mut phactory = Phactory::new(DevPlatform);
+        phactory.init(InitArgs {
+            sealing_path: sealing_dir.path().to_string_lossy().into(),
+            version: env!("CARGO_PKG_VERSION").into(),
+            git_revision: git_revision(),
+            ..Default::default()
+        });
+        let phactory = Arc::new(Mutex::new(phactory));
+        Self {
+            client: PhactoryApiClient::new(InProcessClient {
+                phactory: phactory.clone(),
+            }),
+            phactory,
+            key_seed: [seed; 32],
+            runtime_info: None,
+            _sealing_dir: sealing_dir,
+        }
+    }
+
+    /// The prpc client to call the pRuntime APIs
+    pub fn client(&self) -> &PhactoryApiClient<InProcessClient> {
+        &self.client
+    }
+
+    /// The directory the pRuntime seals its data to
+    pub fn sealing_path(&self) -> String {
+        self._sealing_dir.path().to_string_lossy().into()
+    }
+
+    /// Locks the pRuntime for direct access
+    pub fn phactory(&self) -> MutexGuard<'_, Phactory<DevPlatform>> {
+        self.phactory.lock().unwrap()
+    }
+
+    pub fn info(&self) -> Result<pb::PhactoryInfo> {
+        Ok(block_on(self.client.get_info(()))?)
+    }
+
+    /// Initializes the runtime with the genesis of `chain`, skipping RA.
+    pub fn init_runtime(&mut self, chain: &Chain, operator: Option<AccountId>) -> Result<()> {
+        let resp = block_on(self.client.init_runtime(pb::InitRuntimeRequest::new(
+            true,
+            chain.genesis_info(),
+            Some(self.key_seed.to_vec()),
+            chain.genesis_state(),
+            operator,
+            false,
+        )))?;
+        self.runtime_info = Some(resp);
+        Ok(())
+    }
+
+    /// The registration info of the initialized runtime
+    pub fn registration_info(&self) -> Result<WorkerRegistrationInfo<AccountId>> {
+        let resp = self
+            .runtime_info
+            .as_ref()
+            .ok_or_else(|| anyhow!("Runtime not initialized"))?;
+        resp.decode_runtime_info()
+            .map_err(|err| anyhow!("Failed to decode the runtime info: {:?}", err))
+    }
+
+    pub fn pubkey(&self) -> Result<WorkerPublicKey> {
+        Ok(self.registration_info()?.pubkey)
+    }
+}
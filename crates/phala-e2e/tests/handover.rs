@@ -0,0 +1,59 @@
+use phactory::Phactory;
+use phala_e2e::{DevPlatform, TestNet, Worker};
+
+#[test]
+fn worker_key_is_handed_over() {
+    let mut net = TestNet::new();
+    net.run_blocks(2).unwrap();
+    let pubkey = net.worker.info().unwrap().public_key;
+    let new = Worker::new(2);
+
+    let challenge = net.worker.phactory().handover_create_challenge().unwrap();
+    let response = new.phactory().handover_accept_challenge(challenge).unwrap();
+    let key = net.worker.phactory().handover_start(response).unwrap();
+    let synced_to = new.phactory().handover_receive(key).unwrap();
+    assert_eq!(synced_to, 2);
+
+    // The new instance restarts as the same worker.
+    let restored =
+        Phactory::<DevPlatform>::restore_from_checkpoint(&DevPlatform, &new.sealing_path(), false)
+            .unwrap()
+            .expect("The handed over checkpoint should be loaded");
+    assert_eq!(restored.get_info().public_key, pubkey);
+
+    // The old instance neither goes on nor restarts as the worker.
+    assert!(net.step().is_err());
+    assert!(net.worker.phactory().handover_create_challenge().is_err());
+    let old_path = net.worker.sealing_path();
+    let restored = Phactory::<DevPlatform>::restore_from_checkpoint(&DevPlatform, &old_path, false);
+    assert!(restored.is_err());
+}
+
+#[test]
+fn handover_rejects_a_stale_challenge() {
+    let mut net = TestNet::new();
+    net.run_blocks(1).unwrap();
+    let new = Worker::new(2);
+
+    let challenge = net.worker.phactory().handover_create_challenge().unwrap();
+    let response = new.phactory().handover_accept_challenge(challenge).unwrap();
+    // A later challenge replaces the answered one.
+    net.worker.phactory().handover_create_challenge().unwrap();
+    assert!(net.worker.phactory().handover_start(response).is_err());
+
+    // The old instance keeps running as the worker.
+    net.run_blocks(1).unwrap();
+}
+
+#[test]
+fn handover_receive_requires_an_accepted_challenge() {
+    let mut net = TestNet::new();
+    net.run_blocks(1).unwrap();
+    let new = Worker::new(2);
+    let other = Worker::new(3);
+
+    let challenge = net.worker.phactory().handover_create_challenge().unwrap();
+    let response = new.phactory().handover_accept_challenge(challenge).unwrap();
+    let key = net.worker.phactory().handover_start(response).unwrap();
+    assert!(other.phactory().handover_receive(key).is_err());
+}
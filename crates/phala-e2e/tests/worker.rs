@@ -0,0 +1,33 @@
+use phala_e2e::{chain::Runtime, TestNet};
+use phala_pallets::pallet_registry;
+
+#[test]
+fn worker_follows_the_chain_state() {
+    let mut net = TestNet::new();
+    net.run_blocks(3).unwrap();
+
+    let info = net.worker.info().unwrap();
+    assert_eq!(info.blocknum, 4);
+    let state_root = net.chain.header(3).unwrap().state_root;
+    assert_eq!(info.state_root, hex::encode(state_root));
+}
+
+#[test]
+fn worker_registers_and_reports_benchmark() {
+    let _ = env_logger::try_init();
+    let mut net = TestNet::new();
+    let pubkey = net.worker.pubkey().unwrap();
+
+    net.register_worker().unwrap();
+    let number = net.step().unwrap();
+    assert_eq!(net.chain.block(number).unwrap().results, vec![Ok(())]);
+    assert!(net.worker.info().unwrap().registered);
+
+    // The benchmark lasts for 1 block, then the report is relayed back to the chain.
+    net.run_blocks(2).unwrap();
+    let worker = net
+        .chain
+        .execute_with(|| pallet_registry::Workers::<Runtime>::get(&pubkey))
+        .unwrap();
+    assert!(worker.initial_score.is_some());
+}
@@ -0,0 +1,272 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use tokio::sync::watch;
+
+use crate::fixture::{parse_number, Fixture};
+
+/// Faults injected by the server, to exercise the error handling of the clients.
+#[derive(Default, Clone, Debug)]
+pub struct Faults {
+    /// The delay before each response
+    pub delay: Duration,
+    /// Answer every n-th request with an error
+    pub fail_every: Option<u64>,
+    /// Close each connection after it has served n requests
+    pub drop_after: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(-32602, message)
+    }
+}
+
+type RpcResult = Result<Value, RpcError>;
+
+/// The state of the mocked chain. Only the blocks up to the finalized one are visible to the
+/// clients, so that the tests can let the chain grow with [`MockChain::set_finalized`].
+pub struct MockChain {
+    fixture: Fixture,
+    first: u64,
+    by_hash: HashMap<String, usize>,
+    finalized: watch::Sender<u64>,
+    pub(crate) faults: Faults,
+    requests: AtomicU64,
+}
+
+impl MockChain {
+    /// Creates the chain with all the recorded blocks finalized.
+    pub fn new(fixture: Fixture, faults: Faults) -> Result<Self> {
+        let first = match fixture.blocks.first() {
+            Some(block) => block.number()?,
+            None => return Err(anyhow!("The fixture has no block")),
+        };
+        let mut by_hash = HashMap::new();
+        for (i, block) in fixture.blocks.iter().enumerate() {
+            if block.number()? != first + i as u64 {
+                return Err(anyhow!("The fixture blocks are not consecutive at {}", i));
+            }
+            by_hash.insert(block.hash.to_lowercase(), i);
+        }
+        let last = first + fixture.blocks.len() as u64 - 1;
+        Ok(Self {
+            fixture,
+            first,
+            by_hash,
+            finalized: watch::channel(last).0,
+            faults,
+            requests: AtomicU64::new(0),
+        })
+    }
+
+    pub fn first_block(&self) -> u64 {
+        self.first
+    }
+
+    pub fn last_block(&self) -> u64 {
+        self.first + self.fixture.blocks.len() as u64 - 1
+    }
+
+    pub fn finalized(&self) -> u64 {
+        *self.finalized.borrow()
+    }
+
+    /// Moves the finalized head, clamped to the recorded range. The subscribers are notified.
+    pub fn set_finalized(&self, number: u64) {
+        let number = number.clamp(self.first, self.last_block());
+        let _ = self.finalized.send(number);
+    }
+
+    pub fn subscribe_finalized(&self) -> watch::Receiver<u64> {
+        self.finalized.subscribe()
+    }
+
+    /// The number of requests served so far.
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// The index of a visible block.
+    fn index_of_number(&self, number: u64) -> Option<usize> {
+        if number < self.first || number > self.finalized() {
+            return None;
+        }
+        Some((number - self.first) as usize)
+    }
+
+    /// The index of the visible block with the given hash, or of the finalized block if the hash
+    /// is null.
+    fn index_of_hash(&self, hash: &Value) -> Result<Option<usize>, RpcError> {
+        match hash {
+            Value::Null => Ok(self.index_of_number(self.finalized())),
+            Value::String(hash) => Ok(self
+                .by_hash
+                .get(&hash.to_lowercase())
+                .copied()
+                .filter(|&i| self.first + i as u64 <= self.finalized())),
+            _ => Err(RpcError::invalid_params("Bad block hash")),
+        }
+    }
+
+    pub(crate) fn header_of(&self, number: u64) -> Value {
+        match self.index_of_number(number) {
+            Some(i) => self.fixture.blocks[i].header().clone(),
+            None => Value::Null,
+        }
+    }
+
+    fn canned(&self, method: &str, params: &Value) -> Option<Value> {
+        self.fixture
+            .calls
+            .iter()
+            .find(|call| call.method == method && (call.params.is_null() || call.params == *params))
+            .map(|call| call.result.clone())
+    }
+
+    /// The main storage at the block `index`, derived from the base storage and the changes.
+    fn storage_at(&self, index: usize) -> BTreeMap<String, String> {
+        let mut storage: BTreeMap<_, _> = self.fixture.base_storage.iter().cloned().collect();
+        for block in &self.fixture.blocks[..=index] {
+            let changes = match block.changes.as_ref() {
+                Some(changes) => &changes["mainStorageChanges"],
+                None => continue,
+            };
+            for change in changes.as_array().into_iter().flatten() {
+                let key = match change[0].as_str() {
+                    Some(key) => key.to_lowercase(),
+                    None => continue,
+                };
+                match change[1].as_str() {
+                    Some(value) => storage.insert(key, value.into()),
+                    None => storage.remove(&key),
+                };
+            }
+        }
+        storage
+    }
+
+    /// Serves a request, counting it.
+    pub(crate) fn call(&self, method: &str, params: &Value) -> RpcResult {
+        let count = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(n) = self.faults.fail_every {
+            if n > 0 && count % n == 0 {
+                return Err(RpcError::new(-32000, "Injected failure"));
+            }
+        }
+        self.handle(method, params)
+    }
+
+    fn handle(&self, method: &str, params: &Value) -> RpcResult {
+        if let Some(result) = self.canned(method, params) {
+            return Ok(result);
+        }
+        let param = |i: usize| params.get(i).cloned().unwrap_or(Value::Null);
+        let blocks = &self.fixture.blocks;
+        match method {
+            "chain_getBlockHash" => {
+                let number = match param(0) {
+                    Value::Null => self.finalized(),
+                    number => parse_number(&number)
+                        .ok_or_else(|| RpcError::invalid_params("Bad block number"))?,
+                };
+                if number == 0 {
+                    return Ok(json!(self.fixture.genesis_hash));
+                }
+                Ok(match self.index_of_number(number) {
+                    Some(i) => json!(blocks[i].hash),
+                    None => Value::Null,
+                })
+            }
+            "chain_getFinalizedHead" | "chain_getFinalisedHead" => {
+                let i = self.index_of_number(self.finalized());
+                Ok(json!(blocks[i.expect("The finalized block is visible")].hash))
+            }
+            "chain_getHeader" => Ok(match self.index_of_hash(&param(0))? {
+                Some(i) => blocks[i].header().clone(),
+                None => Value::Null,
+            }),
+            "chain_getBlock" => Ok(match self.index_of_hash(&param(0))? {
+                Some(i) => blocks[i].block.clone(),
+                None => Value::Null,
+            }),
+            "pha_getStorageChanges" => {
+                let from = self.index_of_hash(&param(0))?;
+                let to = self.index_of_hash(&param(1))?;
+                let (from, to) = match (from, to) {
+                    (Some(from), Some(to)) if from <= to => (from, to),
+                    _ => return Err(RpcError::invalid_params("Bad block range")),
+                };
+                let changes = blocks[from..=to]
+                    .iter()
+                    .map(|block| block.changes.clone())
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| RpcError::new(-32000, "Storage changes not recorded"))?;
+                Ok(Value::Array(changes))
+            }
+            "state_getStorage" => {
+                let key = param(0);
+                let key = key
+                    .as_str()
+                    .ok_or_else(|| RpcError::invalid_params("Bad storage key"))?;
+                let index = self
+                    .index_of_hash(&param(1))?
+                    .ok_or_else(|| RpcError::invalid_params("Unknown block"))?;
+                Ok(json!(self.storage_at(index).get(&key.to_lowercase())))
+            }
+            "state_getPairs" => {
+                let prefix = param(0);
+                let prefix = prefix
+                    .as_str()
+                    .ok_or_else(|| RpcError::invalid_params("Bad storage prefix"))?
+                    .to_lowercase();
+                let index = self
+                    .index_of_hash(&param(1))?
+                    .ok_or_else(|| RpcError::invalid_params("Unknown block"))?;
+                let pairs: Vec<_> = self
+                    .storage_at(index)
+                    .into_iter()
+                    .filter(|(key, _)| key.starts_with(&prefix))
+                    .collect();
+                Ok(json!(pairs))
+            }
+            "state_getMetadata" => self
+                .fixture
+                .metadata
+                .clone()
+                .ok_or_else(|| RpcError::new(-32000, "Metadata not recorded")),
+            "state_getRuntimeVersion" => self
+                .fixture
+                .runtime_version
+                .clone()
+                .ok_or_else(|| RpcError::new(-32000, "Runtime version not recorded")),
+            "system_health" => Ok(json!({
+                "peers": 1,
+                "isSyncing": false,
+                "shouldHavePeers": true,
+            })),
+            "system_syncState" => Ok(json!({
+                "startingBlock": self.first,
+                "currentBlock": self.finalized(),
+                "highestBlock": self.finalized(),
+            })),
+            _ => Err(RpcError::new(-32601, format!("Method not found: {}", method))),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// A recorded range of a chain. The values are kept in their JSON-RPC form, so a fixture can be
+/// filled with the raw responses of a node, e.g. the `result` of a `pha_getStorageChanges` call.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Fixture {
+    pub genesis_hash: String,
+    /// The result of `state_getMetadata`
+    #[serde(default)]
+    pub metadata: Option<Value>,
+    /// The result of `state_getRuntimeVersion`
+    #[serde(default)]
+    pub runtime_version: Option<Value>,
+    /// The storage pairs at the block before the first recorded block, as (key, value) hex strings
+    #[serde(default)]
+    pub base_storage: Vec<(String, String)>,
+    /// Consecutive blocks
+    pub blocks: Vec<FixtureBlock>,
+    /// Canned responses of the calls that are not derived from the blocks
+    #[serde(default)]
+    pub calls: Vec<CannedCall>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FixtureBlock {
+    pub hash: String,
+    /// The result of `chain_getBlock`, including the justifications
+    pub block: Value,
+    /// An item of the result of `pha_getStorageChanges`
+    #[serde(default)]
+    pub changes: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CannedCall {
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    pub result: Value,
+}
+
+impl FixtureBlock {
+    pub fn header(&self) -> &Value {
+        &self.block["block"]["header"]
+    }
+
+    pub fn number(&self) -> Result<u64> {
+        parse_number(&self.header()["number"]).ok_or_else(|| anyhow!("Bad block number"))
+    }
+}
+
+/// Parses a block number given either as a JSON number or as a hex string.
+pub fn parse_number(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => u64::from_str_radix(s.trim_start_matches("0x"), 16).ok(),
+        _ => None,
+    }
+}
+
+impl Fixture {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let file = std::fs::File::open(path).context("Failed to open the fixture")?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .context("Failed to parse the fixture")
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = std::fs::File::create(path).context("Failed to create the fixture")?;
+        serde_json::to_writer(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    /// Records the blocks `from..=to` of the node at the http `node_uri`. The base storage is
+    /// recorded too if `with_storage` is set, which can be large.
+    pub async fn record(node_uri: &str, from: u64, to: u64, with_storage: bool) -> Result<Self> {
+        let node = NodeClient::new(node_uri);
+        let genesis_hash = node.call("chain_getBlockHash", json!([0])).await?;
+        let mut fixture = Fixture {
+            genesis_hash: serde_json::from_value(genesis_hash)?,
+            metadata: Some(node.call("state_getMetadata", json!([])).await?),
+            runtime_version: Some(node.call("state_getRuntimeVersion", json!([])).await?),
+            ..Default::default()
+        };
+        if with_storage && from > 0 {
+            let base = node.call("chain_getBlockHash", json!([from - 1])).await?;
+            let pairs = node.call("state_getPairs", json!(["0x", base])).await?;
+            fixture.base_storage = serde_json::from_value(pairs)?;
+        }
+        for number in from..=to {
+            let hash = node.call("chain_getBlockHash", json!([number])).await?;
+            let block = node.call("chain_getBlock", json!([hash])).await?;
+            let changes = node
+                .call("pha_getStorageChanges", json!([hash, hash]))
+                .await?;
+            let changes = match changes {
+                Value::Array(mut items) if items.len() == 1 => items.pop(),
+                _ => return Err(anyhow!("Bad storage changes of block {}", number)),
+            };
+            log::info!("Recorded block {}", number);
+            fixture.blocks.push(FixtureBlock {
+                hash: serde_json::from_value(hash)?,
+                block,
+                changes,
+            });
+        }
+        Ok(fixture)
+    }
+}
+
+struct NodeClient {
+    uri: String,
+    client: reqwest::Client,
+}
+
+impl NodeClient {
+    fn new(uri: &str) -> Self {
+        Self {
+            uri: uri.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+        let mut response: Value = self
+            .client
+            .post(&self.uri)
+            .json(&request)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow!("{} failed: {}", method, error));
+        }
+        Ok(response["result"].take())
+    }
+}
@@ -0,0 +1,15 @@
+//! A mock Substrate node serving a recorded range of a chain over JSON-RPC/websocket, so that
+//! pherry's sync logic can be tested without a live node.
+//!
+//! The headers, blocks (with justifications), storage changes and storage reads are derived from
+//! a [`Fixture`], other calls are answered with its canned responses. The finalized head can be
+//! moved by the test to let the chain grow, and [`Faults`] can be injected to exercise the retry
+//! and failover paths.
+
+mod chain;
+mod fixture;
+mod server;
+
+pub use chain::{Faults, MockChain, RpcError};
+pub use fixture::{CannedCall, Fixture, FixtureBlock};
+pub use server::serve;
@@ -0,0 +1,98 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::{AppSettings, Parser, Subcommand};
+use phala_mock_chain::{serve, Faults, Fixture, MockChain};
+
+#[derive(Parser, Debug)]
+#[clap(about = "A mock Substrate node serving recorded chain fixtures.", version, author)]
+#[clap(global_setting(AppSettings::DeriveDisplayOrder))]
+struct Args {
+    #[clap(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand, Debug)]
+enum Action {
+    /// Record a range of blocks from a live node to a fixture file.
+    Record {
+        #[clap(long, default_value = "http://localhost:9933", help = "The node http rpc endpoint")]
+        node_uri: String,
+        #[clap(long)]
+        from: u64,
+        #[clap(long)]
+        to: u64,
+        #[clap(long, help = "Also record the storage before the first block.")]
+        with_storage: bool,
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+    /// Serve a fixture over websocket.
+    Serve {
+        fixture: PathBuf,
+        #[clap(long, default_value = "127.0.0.1:9944")]
+        addr: SocketAddr,
+        #[clap(long, help = "The initial finalized block, default to the last recorded one.")]
+        finalized: Option<u64>,
+        #[clap(long, help = "Finalize a new block every given milliseconds.")]
+        block_interval: Option<u64>,
+        #[clap(long, default_value = "0", help = "The delay in milliseconds of each response.")]
+        delay: u64,
+        #[clap(long, help = "Answer every n-th request with an error.")]
+        fail_every: Option<u64>,
+        #[clap(long, help = "Close each connection after n requests.")]
+        drop_after: Option<u64>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    match Args::parse().action {
+        Action::Record {
+            node_uri,
+            from,
+            to,
+            with_storage,
+            output,
+        } => {
+            let fixture = Fixture::record(&node_uri, from, to, with_storage).await?;
+            fixture.save(&output)?;
+            println!("{} blocks written to {}", fixture.blocks.len(), output.display());
+        }
+        Action::Serve {
+            fixture,
+            addr,
+            finalized,
+            block_interval,
+            delay,
+            fail_every,
+            drop_after,
+        } => {
+            let faults = Faults {
+                delay: Duration::from_millis(delay),
+                fail_every,
+                drop_after,
+            };
+            let chain = Arc::new(MockChain::new(Fixture::load(fixture)?, faults)?);
+            if let Some(finalized) = finalized {
+                chain.set_finalized(finalized);
+            }
+            let (_, server) = serve(chain.clone(), addr).await?;
+            if let Some(interval) = block_interval {
+                let mut ticker = tokio::time::interval(Duration::from_millis(interval));
+                while chain.finalized() < chain.last_block() {
+                    ticker.tick().await;
+                    chain.set_finalized(chain.finalized() + 1);
+                    log::info!("Finalized block {}", chain.finalized());
+                }
+            }
+            server.await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::{SinkExt as _, StreamExt as _};
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::chain::MockChain;
+
+/// Serves the JSON-RPC over websocket at `addr`. Returns the bound address, useful with port 0.
+pub async fn serve(
+    chain: Arc<MockChain>,
+    addr: SocketAddr,
+) -> Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = TcpListener::bind(addr).await?;
+    let addr = listener.local_addr()?;
+    info!("Mock chain listening at ws://{}", addr);
+    let handle = tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    warn!("Accept failed: {}", err);
+                    continue;
+                }
+            };
+            let chain = chain.clone();
+            tokio::spawn(async move {
+                if let Err(err) = serve_connection(chain, stream).await {
+                    debug!("Connection closed: {}", err);
+                }
+            });
+        }
+    });
+    Ok((addr, handle))
+}
+
+/// The subscription ids, unique across the connections.
+static NEXT_SUBSCRIPTION: AtomicU64 = AtomicU64::new(1);
+
+async fn serve_connection(chain: Arc<MockChain>, stream: TcpStream) -> Result<()> {
+    let ws = tokio_tungstenite::accept_async(stream).await?;
+    let (mut sink, mut source) = ws.split();
+    let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+    let writer = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            if sink.send(Message::Text(message.to_string())).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut served = 0;
+    let mut subscriptions = vec![];
+    let mut new_subscriptions = vec![];
+    while let Some(message) = source.next().await {
+        let text = match message? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        if !chain.faults.delay.is_zero() {
+            tokio::time::sleep(chain.faults.delay).await;
+        }
+        let response = match serde_json::from_str::<Value>(&text) {
+            Ok(Value::Array(batch)) => Value::Array(
+                batch
+                    .iter()
+                    .map(|request| handle_request(&chain, request, &mut new_subscriptions))
+                    .collect(),
+            ),
+            Ok(request) => handle_request(&chain, &request, &mut new_subscriptions),
+            Err(_) => error_response(Value::Null, -32700, "Parse error"),
+        };
+        let _ = tx.send(response);
+        // Started after the response, so that the client knows the id of the notifications.
+        for (notification, subscription) in new_subscriptions.drain(..) {
+            subscriptions.push(tokio::spawn(push_heads(
+                chain.clone(),
+                tx.clone(),
+                notification,
+                subscription,
+            )));
+        }
+        served += 1;
+        if matches!(chain.faults.drop_after, Some(n) if served >= n) {
+            info!("Dropping the connection after {} requests", served);
+            break;
+        }
+    }
+    for subscription in subscriptions {
+        subscription.abort();
+    }
+    drop(tx);
+    let _ = writer.await;
+    Ok(())
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+fn handle_request(
+    chain: &Arc<MockChain>,
+    request: &Value,
+    new_subscriptions: &mut Vec<(&'static str, u64)>,
+) -> Value {
+    let id = request["id"].clone();
+    let method = match request["method"].as_str() {
+        Some(method) => method,
+        None => return error_response(id, -32600, "Invalid request"),
+    };
+    debug!("{} {}", method, request["params"]);
+    let notification = match method {
+        "chain_subscribeFinalizedHeads" | "chain_subscribeFinalisedHeads" => "chain_finalizedHead",
+        "chain_subscribeNewHeads" | "chain_subscribeNewHead" => "chain_newHead",
+        "chain_subscribeAllHeads" => "chain_allHead",
+        _ if method.contains("_unsubscribe") => {
+            return json!({"jsonrpc": "2.0", "id": id, "result": true});
+        }
+        _ => {
+            return match chain.call(method, &request["params"]) {
+                Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                Err(err) => error_response(id, err.code, &err.message),
+            };
+        }
+    };
+    let subscription = NEXT_SUBSCRIPTION.fetch_add(1, Ordering::Relaxed);
+    new_subscriptions.push((notification, subscription));
+    json!({"jsonrpc": "2.0", "id": id, "result": subscription})
+}
+
+/// Pushes the finalized head, then every new one. All heads are final on the mock chain.
+async fn push_heads(
+    chain: Arc<MockChain>,
+    tx: mpsc::UnboundedSender<Value>,
+    notification: &'static str,
+    subscription: u64,
+) {
+    let mut finalized = chain.subscribe_finalized();
+    let mut next = *finalized.borrow();
+    loop {
+        let head = *finalized.borrow();
+        while next <= head {
+            let message = json!({
+                "jsonrpc": "2.0",
+                "method": notification,
+                "params": {"subscription": subscription, "result": chain.header_of(next)},
+            });
+            if tx.send(message).is_err() {
+                return;
+            }
+            next += 1;
+        }
+        if finalized.changed().await.is_err() {
+            return;
+        }
+    }
+}
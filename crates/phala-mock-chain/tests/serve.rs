@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use futures::{SinkExt as _, StreamExt as _};
+use phala_mock_chain::{serve, Faults, Fixture, FixtureBlock, MockChain};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+fn fixture() -> Fixture {
+    let blocks = (1..=3u64)
+        .map(|number| FixtureBlock {
+            hash: format!("0x{:064x}", number),
+            block: json!({
+                "block": {
+                    "header": {"number": format!("0x{:x}", number)},
+                    "extrinsics": [],
+                },
+                "justifications": null,
+            }),
+            changes: Some(json!({
+                "mainStorageChanges": [["0xaa", format!("0x{:02x}", number)]],
+                "childStorageChanges": [],
+            })),
+        })
+        .collect();
+    Fixture {
+        genesis_hash: format!("0x{:064x}", 0),
+        base_storage: vec![("0xbb".into(), "0x00".into())],
+        blocks,
+        ..Default::default()
+    }
+}
+
+async fn start(faults: Faults) -> (Arc<MockChain>, Client) {
+    let chain = Arc::new(MockChain::new(fixture(), faults).unwrap());
+    let (addr, _) = serve(chain.clone(), "127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let (client, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr))
+        .await
+        .unwrap();
+    (chain, client)
+}
+
+async fn receive(client: &mut Client) -> Value {
+    loop {
+        if let Message::Text(text) = client.next().await.unwrap().unwrap() {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+async fn call(client: &mut Client, method: &str, params: Value) -> Value {
+    let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+    client.send(Message::Text(request.to_string())).await.unwrap();
+    receive(client).await
+}
+
+#[tokio::test]
+async fn serves_the_visible_blocks() {
+    let (chain, mut client) = start(Faults::default()).await;
+    let hash_2 = format!("0x{:064x}", 2);
+
+    let resp = call(&mut client, "chain_getBlockHash", json!([2])).await;
+    assert_eq!(resp["result"], json!(hash_2));
+    let resp = call(&mut client, "chain_getHeader", json!([hash_2])).await;
+    assert_eq!(resp["result"]["number"], json!("0x2"));
+
+    chain.set_finalized(1);
+    let resp = call(&mut client, "chain_getBlockHash", json!([2])).await;
+    assert_eq!(resp["result"], Value::Null);
+    let resp = call(&mut client, "chain_getFinalizedHead", json!([])).await;
+    assert_eq!(resp["result"], json!(format!("0x{:064x}", 1)));
+}
+
+#[tokio::test]
+async fn derives_the_storage_from_the_changes() {
+    let (_, mut client) = start(Faults::default()).await;
+    let hash_1 = format!("0x{:064x}", 1);
+    let hash_3 = format!("0x{:064x}", 3);
+
+    let resp = call(&mut client, "pha_getStorageChanges", json!([hash_1, hash_3])).await;
+    assert_eq!(resp["result"].as_array().unwrap().len(), 3);
+
+    let resp = call(&mut client, "state_getStorage", json!(["0xaa", hash_1])).await;
+    assert_eq!(resp["result"], json!("0x01"));
+    let resp = call(&mut client, "state_getStorage", json!(["0xaa"])).await;
+    assert_eq!(resp["result"], json!("0x03"));
+    let resp = call(&mut client, "state_getStorage", json!(["0xbb", hash_3])).await;
+    assert_eq!(resp["result"], json!("0x00"));
+}
+
+#[tokio::test]
+async fn notifies_the_new_finalized_heads() {
+    let (chain, mut client) = start(Faults::default()).await;
+    chain.set_finalized(1);
+
+    let resp = call(&mut client, "chain_subscribeFinalizedHeads", json!([])).await;
+    let subscription = resp["result"].clone();
+    let notification = receive(&mut client).await;
+    assert_eq!(notification["params"]["subscription"], subscription);
+    assert_eq!(notification["params"]["result"]["number"], json!("0x1"));
+
+    chain.set_finalized(3);
+    for number in ["0x2", "0x3"] {
+        let notification = receive(&mut client).await;
+        assert_eq!(notification["params"]["result"]["number"], json!(number));
+    }
+}
+
+#[tokio::test]
+async fn injects_failures() {
+    let faults = Faults {
+        fail_every: Some(2),
+        ..Default::default()
+    };
+    let (chain, mut client) = start(faults).await;
+
+    let resp = call(&mut client, "system_health", json!([])).await;
+    assert!(resp.get("error").is_none());
+    let resp = call(&mut client, "system_health", json!([])).await;
+    assert_eq!(resp["error"]["message"], json!("Injected failure"));
+    assert_eq!(chain.requests(), 2);
+}
@@ -127,6 +127,15 @@ pub struct TypedReceiver<T> {
 
 impl<T: Decode> TypedReceiver<T> {
     pub fn try_next(&mut self) -> Result<Option<(u64, T, MessageOrigin)>, TypedReceiveError> {
+        let message = self.try_next_with_payload()?;
+        Ok(message.map(|(sn, typed, sender, _)| (sn, typed, sender)))
+    }
+
+    /// Same as `try_next`, but also returns the raw payload of the message as it was sent.
+    #[allow(clippy::type_complexity)]
+    pub fn try_next_with_payload(
+        &mut self,
+    ) -> Result<Option<(u64, T, MessageOrigin, Vec<u8>)>, TypedReceiveError> {
         let message = self.queue.try_next().map_err(|e| match e {
             ReceiveError::SenderGone => TypedReceiveError::SenderGone,
         })?;
@@ -135,7 +144,7 @@ impl<T: Decode> TypedReceiver<T> {
             Some(m) => m,
         };
         let typed = Decode::decode(&mut &msg.payload[..])?;
-        Ok(Some((sn, typed, msg.sender)))
+        Ok(Some((sn, typed, msg.sender, msg.payload)))
     }
 
     pub fn peek_ind(&self) -> Result<Option<u64>, ReceiveError> {
@@ -103,6 +103,12 @@ impl MessageSendQueue {
             .collect()
     }
 
+    /// The sequence of the next message of each sender.
+    pub fn next_sequences(&self) -> BTreeMap<SenderId, u64> {
+        let inner = self.inner.lock();
+        inner.iter().map(|(k, v)| (k.clone(), v.sequence)).collect()
+    }
+
     pub fn messages(&self, sender: &SenderId) -> Vec<SignedMessage> {
         let inner = self.inner.lock();
         inner
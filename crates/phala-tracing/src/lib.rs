@@ -0,0 +1,63 @@
+//! Distributed tracing across pherry, prpc and phactory.
+//!
+//! The spans are recorded with `tracing` and exported via OpenTelemetry. The trace context crosses
+//! the HTTP hops as the W3C `traceparent` and `tracestate` headers. Without [`init`], no span is
+//! exported and nothing is propagated.
+
+use std::collections::HashMap;
+
+use opentelemetry::global;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt as _;
+
+/// The headers carrying the trace context.
+pub const TRACE_HEADERS: [&str; 2] = ["traceparent", "tracestate"];
+
+/// Exports the spans of the app to the OTLP collector at `otlp_endpoint`, e.g.
+/// `http://localhost:4317`. Must be called inside a tokio runtime.
+#[cfg(feature = "exporter")]
+pub fn init(service_name: &'static str, otlp_endpoint: &str) -> anyhow::Result<()> {
+    use opentelemetry::{
+        sdk::{propagation::TraceContextPropagator, trace, Resource},
+        KeyValue,
+    };
+    use opentelemetry_otlp::WithExportConfig as _;
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(otlp_endpoint),
+        )
+        .with_trace_config(trace::config().with_resource(Resource::new(vec![
+            KeyValue::new("service.name", service_name),
+        ])))
+        .install_batch(opentelemetry::runtime::Tokio)?;
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::set_global_default(subscriber)?;
+    Ok(())
+}
+
+/// Flushes the pending spans.
+#[cfg(feature = "exporter")]
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// The trace context headers of the current span, to be sent along with an outgoing request.
+pub fn current_context_headers() -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    let cx = Span::current().context();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&cx, &mut headers));
+    headers
+}
+
+/// Makes `span` a child of the remote span in the headers of an incoming request.
+pub fn set_remote_parent(span: &Span, headers: &HashMap<String, String>) {
+    let cx = global::get_text_map_propagator(|propagator| propagator.extract(headers));
+    span.set_parent(cx);
+}
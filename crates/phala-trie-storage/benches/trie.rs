@@ -0,0 +1,276 @@
+//! Benchmarks of the trie backends on a recorded chain.
+//!
+//! The dataset is the genesis and the first blocks of a chain recorded in `tests/data`. Another
+//! recording with the same layout (`db-0.json`, `changes.json` and `state_roots.txt`) can be used
+//! by pointing `TRIE_BENCH_DATA` to its directory.
+//!
+//! ```text
+//! cargo bench -p phala-trie-storage --bench trie
+//! ```
+//!
+//! A new backend is compared with the others by implementing [`Backend`] for it and adding it to
+//! `bench_all`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use criterion::measurement::WallTime;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkGroup, Criterion, Throughput};
+use phala_trie_storage::{ChildStorageCollection, SharedTrieDB, StorageCollection, TrieStorage};
+use serde::Deserialize;
+use sp_core::{storage::ChildInfo, Hasher};
+use sp_state_machine::{prove_read_on_trie_backend, InMemoryBackend, TrieBackend, TrieBackendStorage};
+use sp_trie::MemoryDB;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct NativeBlakeTwo256;
+
+impl Hasher for NativeBlakeTwo256 {
+    type Out = sp_core::H256;
+    type StdHasher = hash256_std_hasher::Hash256StdHasher;
+    const LENGTH: usize = 32;
+
+    fn hash(s: &[u8]) -> Self::Out {
+        sp_core::hashing::blake2_256(s).into()
+    }
+}
+
+type Hash = sp_core::H256;
+
+const STATE_VERSION: sp_core::storage::StateVersion = sp_core::storage::StateVersion::V0;
+
+/// The number of keys read in a proof.
+const PROOF_KEYS: usize = 64;
+
+#[derive(Deserialize)]
+struct Bytes(#[serde(with = "impl_serde::serialize")] Vec<u8>);
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Vec<Changes>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Changes {
+    main_storage_changes: Vec<(Bytes, Option<Bytes>)>,
+    child_storage_changes: Vec<(Bytes, Vec<(Bytes, Option<Bytes>)>)>,
+}
+
+type ChangeSet = (StorageCollection, ChildStorageCollection);
+
+fn map_collection(collection: Vec<(Bytes, Option<Bytes>)>) -> StorageCollection {
+    collection
+        .into_iter()
+        .map(|(k, v)| (k.0, v.map(|v| v.0)))
+        .collect()
+}
+
+struct Dataset {
+    genesis: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The changes of the blocks following the genesis
+    blocks: Vec<ChangeSet>,
+    /// The state roots of the genesis and of each block
+    roots: Vec<String>,
+}
+
+impl Dataset {
+    fn load() -> Self {
+        let dir = std::env::var_os("TRIE_BENCH_DATA")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data"));
+        let read = |name: &str| {
+            std::fs::read_to_string(dir.join(name))
+                .unwrap_or_else(|err| panic!("Failed to read {}: {}", name, err))
+        };
+
+        let genesis: serde_json::Value = serde_json::from_str(&read("db-0.json")).unwrap();
+        let top: HashMap<String, String> =
+            serde_json::from_value(genesis["genesis"]["raw"]["top"].clone()).unwrap();
+        let mut genesis: Vec<_> = top
+            .iter()
+            .map(|(k, v)| (hex::decode(&k[2..]).unwrap(), hex::decode(&v[2..]).unwrap()))
+            .collect();
+        genesis.sort();
+
+        let response: RpcResponse = serde_json::from_str(&read("changes.json")).unwrap();
+        // The first item is the changes of the genesis itself, already in db-0.json.
+        let blocks = response
+            .result
+            .into_iter()
+            .skip(1)
+            .map(|changes| {
+                let children = changes
+                    .child_storage_changes
+                    .into_iter()
+                    .map(|(k, v)| (k.0, map_collection(v)))
+                    .collect();
+                (map_collection(changes.main_storage_changes), children)
+            })
+            .collect();
+
+        let roots = read("state_roots.txt")
+            .split_whitespace()
+            .map(Into::into)
+            .collect();
+        Self {
+            genesis,
+            blocks,
+            roots,
+        }
+    }
+
+    /// The keys to prove, spread over the whole key space.
+    fn proof_keys(&self) -> Vec<Vec<u8>> {
+        let step = (self.genesis.len() / PROOF_KEYS).max(1);
+        self.genesis
+            .iter()
+            .step_by(step)
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+}
+
+/// A trie backend under benchmark.
+trait Backend: Sized {
+    const NAME: &'static str;
+
+    /// The storage of the trie nodes.
+    type Storage: TrieBackendStorage<NativeBlakeTwo256>;
+
+    fn load(pairs: &[(Vec<u8>, Vec<u8>)]) -> Self;
+
+    fn apply(&mut self, changes: &ChangeSet);
+
+    fn root(&self) -> Hash;
+
+    /// The trie to generate the proofs from.
+    fn trie(&self) -> &TrieBackend<Self::Storage, NativeBlakeTwo256>;
+}
+
+/// The backend of pRuntime.
+impl Backend for TrieStorage<NativeBlakeTwo256> {
+    const NAME: &'static str = "TrieStorage";
+    type Storage = SharedTrieDB<NativeBlakeTwo256>;
+
+    fn load(pairs: &[(Vec<u8>, Vec<u8>)]) -> Self {
+        let mut storage = Self::default();
+        storage.load(pairs.iter());
+        storage
+    }
+
+    fn apply(&mut self, (main, children): &ChangeSet) {
+        let (root, transaction) = self.calc_root_if_changes(main, children);
+        self.apply_changes(root, transaction);
+    }
+
+    fn root(&self) -> Hash {
+        *TrieStorage::root(self)
+    }
+
+    fn trie(&self) -> &TrieBackend<Self::Storage, NativeBlakeTwo256> {
+        self.as_trie_backend()
+    }
+}
+
+/// The reference in-memory backend of sp-state-machine, as a baseline.
+impl Backend for InMemoryBackend<NativeBlakeTwo256> {
+    const NAME: &'static str = "InMemoryBackend";
+    type Storage = MemoryDB<NativeBlakeTwo256>;
+
+    fn load(pairs: &[(Vec<u8>, Vec<u8>)]) -> Self {
+        let mut backend = Self::default();
+        let changes = pairs
+            .iter()
+            .map(|(k, v)| (k.clone(), Some(v.clone())))
+            .collect();
+        backend.insert(std::iter::once((None, changes)), STATE_VERSION);
+        backend
+    }
+
+    fn apply(&mut self, (main, children): &ChangeSet) {
+        let changes = std::iter::once((None, main.clone())).chain(
+            children
+                .iter()
+                .map(|(name, changes)| (Some(ChildInfo::new_default(name)), changes.clone())),
+        );
+        self.insert(changes, STATE_VERSION);
+    }
+
+    fn root(&self) -> Hash {
+        *TrieBackend::root(self)
+    }
+
+    fn trie(&self) -> &TrieBackend<Self::Storage, NativeBlakeTwo256> {
+        self
+    }
+}
+
+/// Checks the backend against the recorded roots, so that a broken backend is not benchmarked.
+fn replay<B: Backend>(data: &Dataset) -> B {
+    let mut backend = B::load(&data.genesis);
+    assert_eq!(format!("{:?}", backend.root()), data.roots[0], "{}", B::NAME);
+    for (i, changes) in data.blocks.iter().enumerate() {
+        backend.apply(changes);
+        if let Some(root) = data.roots.get(i + 1) {
+            assert_eq!(&format!("{:?}", backend.root()), root, "{}", B::NAME);
+        }
+    }
+    backend
+}
+
+fn genesis_load<B: Backend>(group: &mut BenchmarkGroup<WallTime>, data: &Dataset) {
+    group.bench_function(B::NAME, |b| b.iter(|| B::load(&data.genesis)));
+}
+
+fn block_changes<B: Backend>(group: &mut BenchmarkGroup<WallTime>, data: &Dataset) {
+    group.bench_function(B::NAME, |b| {
+        b.iter_batched(
+            || B::load(&data.genesis),
+            |mut backend| {
+                for changes in data.blocks.iter() {
+                    backend.apply(changes);
+                }
+                backend
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn read_proof<B: Backend>(group: &mut BenchmarkGroup<WallTime>, data: &Dataset) {
+    let backend = replay::<B>(data);
+    let keys = data.proof_keys();
+    group.bench_function(B::NAME, |b| {
+        b.iter(|| prove_read_on_trie_backend(backend.trie(), &keys).unwrap())
+    });
+}
+
+fn bench_all(c: &mut Criterion) {
+    let data = Dataset::load();
+    type Phala = TrieStorage<NativeBlakeTwo256>;
+    type Reference = InMemoryBackend<NativeBlakeTwo256>;
+
+    let mut group = c.benchmark_group("genesis_load");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(data.genesis.len() as u64));
+    genesis_load::<Phala>(&mut group, &data);
+    genesis_load::<Reference>(&mut group, &data);
+    group.finish();
+
+    let mut group = c.benchmark_group("block_changes");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(data.blocks.len() as u64));
+    block_changes::<Phala>(&mut group, &data);
+    block_changes::<Reference>(&mut group, &data);
+    group.finish();
+
+    let mut group = c.benchmark_group("read_proof");
+    group.throughput(Throughput::Elements(data.proof_keys().len() as u64));
+    read_proof::<Phala>(&mut group, &data);
+    read_proof::<Reference>(&mut group, &data);
+    group.finish();
+}
+
+criterion_group!(benches, bench_all);
+criterion_main!(benches);
@@ -0,0 +1,265 @@
+//! Captures the golden test data of phala-trie-storage from a live node, in the layout read by
+//! `tests/test_state_root.rs`:
+//!
+//! - `db-0.json`: the genesis storage, as the `genesis.raw.top` of a raw chain spec
+//! - `changes.json`: the `pha_getStorageChanges` response from the genesis to the last block
+//! - `state_roots.txt`: the state root of each block, one per line
+//!
+//! The captured data is replayed on a `TrieStorage` and written only if all the roots match.
+//! The node must expose the unsafe RPC methods for `state_getPairs`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{AppSettings, Parser, Subcommand};
+use log::info;
+use phala_trie_storage::{StorageCollection, TrieStorage};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sp_core::Hasher;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct NativeBlakeTwo256;
+
+impl Hasher for NativeBlakeTwo256 {
+    type Out = sp_core::H256;
+    type StdHasher = hash256_std_hasher::Hash256StdHasher;
+    const LENGTH: usize = 32;
+
+    fn hash(s: &[u8]) -> Self::Out {
+        sp_core::hashing::blake2_256(s).into()
+    }
+}
+
+const DEFAULT_DATA_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/data");
+
+#[derive(Parser, Debug)]
+#[clap(about = "Trie state-root fixture generator.", version, author)]
+#[clap(global_setting(AppSettings::DeriveDisplayOrder))]
+struct Args {
+    #[clap(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand, Debug)]
+enum Action {
+    /// Capture the genesis and the first blocks of a live node, then validate and save them.
+    Capture {
+        #[clap(long, default_value = "http://localhost:9933", help = "The node http rpc endpoint")]
+        node_uri: String,
+        #[clap(long, default_value = "30", help = "The number of blocks after the genesis.")]
+        blocks: u64,
+        #[clap(short, long, default_value = DEFAULT_DATA_DIR)]
+        output: PathBuf,
+    },
+    /// Validate the fixture in a directory.
+    Check {
+        #[clap(default_value = DEFAULT_DATA_DIR)]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Deserialize)]
+struct Bytes(#[serde(with = "impl_serde::serialize")] Vec<u8>);
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Changes {
+    main_storage_changes: Vec<(Bytes, Option<Bytes>)>,
+    child_storage_changes: Vec<(Bytes, Vec<(Bytes, Option<Bytes>)>)>,
+}
+
+fn map_collection(collection: Vec<(Bytes, Option<Bytes>)>) -> StorageCollection {
+    collection
+        .into_iter()
+        .map(|(k, v)| (k.0, v.map(|v| v.0)))
+        .collect()
+}
+
+/// The fixture in its on-disk JSON form.
+struct Fixture {
+    /// The genesis storage, hex key to hex value
+    genesis: BTreeMap<String, String>,
+    /// The storage changes of the genesis and of each following block
+    changes: Vec<Value>,
+    /// The state root of the genesis and of each following block
+    roots: Vec<String>,
+}
+
+impl Fixture {
+    fn load(dir: &Path) -> Result<Self> {
+        let read = |name: &str| -> Result<String> {
+            std::fs::read_to_string(dir.join(name))
+                .with_context(|| format!("Failed to read {}", name))
+        };
+        let mut db: Value = serde_json::from_str(&read("db-0.json")?)?;
+        let genesis = serde_json::from_value(db["genesis"]["raw"]["top"].take())
+            .context("Bad genesis storage")?;
+        let mut response: Value = serde_json::from_str(&read("changes.json")?)?;
+        let changes =
+            serde_json::from_value(response["result"].take()).context("Bad storage changes")?;
+        let roots = read("state_roots.txt")?
+            .split_whitespace()
+            .map(Into::into)
+            .collect();
+        Ok(Self {
+            genesis,
+            changes,
+            roots,
+        })
+    }
+
+    fn save(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        let db = json!({"genesis": {"raw": {"top": self.genesis, "childrenDefault": {}}}});
+        std::fs::write(dir.join("db-0.json"), serde_json::to_string_pretty(&db)?)?;
+        let response = json!({"jsonrpc": "2.0", "result": self.changes, "id": 1});
+        std::fs::write(dir.join("changes.json"), response.to_string())?;
+        let mut roots = self.roots.join("\n");
+        roots.push('\n');
+        std::fs::write(dir.join("state_roots.txt"), roots)?;
+        Ok(())
+    }
+
+    /// Replays the changes on the genesis and checks the roots, the same way the tests do. Hand
+    /// captured fixtures may have a few more changes than roots, which are left unchecked.
+    fn validate(&self) -> Result<()> {
+        if self.roots.is_empty() {
+            return Err(anyhow!("No state root"));
+        }
+        let mut trie = TrieStorage::<NativeBlakeTwo256>::default();
+        let decode = |hex_str: &str| hex::decode(hex_str.trim_start_matches("0x"));
+        let pairs = self
+            .genesis
+            .iter()
+            .map(|(k, v)| Ok((decode(k)?, decode(v)?)))
+            .collect::<Result<Vec<_>, hex::FromHexError>>()
+            .context("Bad genesis storage")?;
+        trie.load(pairs.iter());
+        check_root(&trie, &self.roots[0], 0)?;
+
+        // The first changes are those of the genesis itself, already in the genesis storage.
+        let blocks = self.changes.iter().take(self.roots.len());
+        for (number, changes) in blocks.enumerate().skip(1) {
+            let changes: Changes = serde_json::from_value(changes.clone())
+                .with_context(|| format!("Bad storage changes of block {}", number))?;
+            let main = map_collection(changes.main_storage_changes);
+            let children: Vec<_> = changes
+                .child_storage_changes
+                .into_iter()
+                .map(|(k, v)| (k.0, map_collection(v)))
+                .collect();
+            let (root, transaction) = trie.calc_root_if_changes(&main, &children);
+            trie.apply_changes(root, transaction);
+            check_root(&trie, &self.roots[number], number)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_root(trie: &TrieStorage<NativeBlakeTwo256>, expected: &str, number: usize) -> Result<()> {
+    let root = format!("{:?}", trie.root());
+    if root != expected {
+        return Err(anyhow!(
+            "State root mismatch at block {}: expected {}, got {}",
+            number,
+            expected,
+            root
+        ));
+    }
+    Ok(())
+}
+
+struct NodeClient {
+    uri: String,
+    client: reqwest::Client,
+}
+
+impl NodeClient {
+    fn new(uri: &str) -> Self {
+        Self {
+            uri: uri.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+        let mut response: Value = self
+            .client
+            .post(&self.uri)
+            .json(&request)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow!("{} failed: {}", method, error));
+        }
+        Ok(response["result"].take())
+    }
+
+    async fn block_hash(&self, number: u64) -> Result<Value> {
+        match self.call("chain_getBlockHash", json!([number])).await? {
+            Value::Null => Err(anyhow!("The node has no block {}", number)),
+            hash => Ok(hash),
+        }
+    }
+}
+
+async fn capture(node_uri: &str, blocks: u64) -> Result<Fixture> {
+    let node = NodeClient::new(node_uri);
+    let genesis_hash = node.block_hash(0).await?;
+    let last_hash = node.block_hash(blocks).await?;
+
+    info!("Capturing the genesis storage");
+    let pairs: Vec<(String, String)> =
+        serde_json::from_value(node.call("state_getPairs", json!(["0x", genesis_hash])).await?)?;
+
+    info!("Capturing the storage changes of {} blocks", blocks);
+    let changes: Vec<Value> = serde_json::from_value(
+        node.call("pha_getStorageChanges", json!([genesis_hash, last_hash]))
+            .await?,
+    )?;
+
+    let mut roots = vec![];
+    for number in 0..=blocks {
+        let hash = node.block_hash(number).await?;
+        let header = node.call("chain_getHeader", json!([hash])).await?;
+        let root = header["stateRoot"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Bad header of block {}", number))?;
+        roots.push(root.to_lowercase());
+    }
+
+    Ok(Fixture {
+        genesis: pairs.into_iter().collect(),
+        changes,
+        roots,
+    })
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    match Args::parse().action {
+        Action::Capture {
+            node_uri,
+            blocks,
+            output,
+        } => {
+            let fixture = capture(&node_uri, blocks).await?;
+            fixture.validate().context("The captured data is inconsistent")?;
+            fixture.save(&output)?;
+            println!("{} blocks written to {}", blocks, output.display());
+        }
+        Action::Check { dir } => {
+            let fixture = Fixture::load(&dir)?;
+            fixture.validate()?;
+            println!("{} roots checked", fixture.roots.len());
+        }
+    }
+    Ok(())
+}
@@ -9,13 +9,15 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use core::iter::FromIterator;
 
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 use parity_scale_codec::Codec;
 use sp_core::storage::ChildInfo;
 use sp_core::Hasher;
-use sp_state_machine::{Backend, TrieBackend};
-use sp_trie::{trie_types::TrieDBMutV0 as TrieDBMut, MemoryDB, TrieMut};
+use sp_state_machine::{Backend, DefaultError, TrieBackend, TrieBackendStorage};
+use sp_trie::{trie_types::TrieDBMutV0 as TrieDBMut, MemoryDB, TrieMut, EMPTY_PREFIX};
+use spin::RwLock;
 
 use sp_trie::HashDBT as _;
 
@@ -31,17 +33,80 @@ pub type StorageCollection = Vec<(StorageKey, Option<StorageValue>)>;
 /// In memory arrays of storage values for multiple child tries.
 pub type ChildStorageCollection = Vec<(StorageKey, StorageCollection)>;
 
-pub struct TrieStorage<H: Hasher>(TrieBackend<MemoryDB<H>, H>);
+/// The trie DB shared by a `TrieStorage` and its read transactions.
+///
+/// The storage updates the current nodes in place. The nodes pruned while any transaction is
+/// alive are moved to a side table instead of being dropped, so that the transactions can still
+/// read them. The side table is cleared by the first change applied after the last transaction
+/// is dropped.
+pub struct SharedTrieDB<H: Hasher> {
+    current: Arc<RwLock<MemoryDB<H>>>,
+    pruned: Arc<RwLock<MemoryDB<H>>>,
+}
+
+impl<H: Hasher> SharedTrieDB<H> {
+    fn new(current: MemoryDB<H>) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(current)),
+            pruned: Default::default(),
+        }
+    }
+
+    /// Whether any read transaction shares the DB
+    fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.pruned) > 1
+    }
+}
+
+impl<H: Hasher> Clone for SharedTrieDB<H> {
+    fn clone(&self) -> Self {
+        Self {
+            current: self.current.clone(),
+            pruned: self.pruned.clone(),
+        }
+    }
+}
+
+impl<H: Hasher> TrieBackendStorage<H> for SharedTrieDB<H> {
+    type Overlay = MemoryDB<H>;
+
+    fn get(
+        &self,
+        key: &H::Out,
+        prefix: (&[u8], Option<u8>),
+    ) -> Result<Option<Vec<u8>>, DefaultError> {
+        // A node is moved to the side table before it is removed from the current nodes, so it
+        // is always found in either of them.
+        if let Some(value) = self.current.read().get(key, prefix) {
+            return Ok(Some(value));
+        }
+        Ok(self.pruned.read().get(key, prefix))
+    }
+}
+
+/// The trie DB is shared with the read transactions, see `SharedTrieDB`.
+pub struct TrieStorage<H: Hasher>(TrieBackend<SharedTrieDB<H>, H>);
 
 impl<H: Hasher> Default for TrieStorage<H>
 where
     H::Out: Codec,
 {
     fn default() -> Self {
-        Self(TrieBackend::new(Default::default(), Default::default()))
+        Self(TrieBackend::new(SharedTrieDB::new(Default::default()), Default::default()))
     }
 }
 
+/// A read-only snapshot of a `TrieStorage`, pinned to the root at the time it was taken.
+///
+/// It is not affected by the changes applied to the storage afterwards, so the reads made with it
+/// are consistent with each other.
+///
+/// # Performance
+///
+/// While any transaction is alive, the nodes pruned by `TrieStorage::apply_changes` are kept in
+/// memory, so the transactions should be dropped as soon as the reads are done.
+pub struct ReadTransaction<H: Hasher>(TrieBackend<SharedTrieDB<H>, H>);
+
 pub fn load_trie_backend<H: Hasher>(
     pairs: impl Iterator<Item = (impl AsRef<[u8]>, impl AsRef<[u8]>)>,
 ) -> TrieBackend<MemoryDB<H>, H>
@@ -70,14 +135,20 @@ where
     H::Out: Codec + Serialize,
     S: Serializer,
 {
-    let root = trie.root();
-    let kvs: Vec<_> = trie
-        .backend_storage()
-        .clone()
-        .drain()
-        .into_iter()
-        .map(|it| it.1)
-        .collect();
+    serialize_memory_db(trie.root(), trie.backend_storage(), serializer)
+}
+
+#[cfg(feature = "serde")]
+fn serialize_memory_db<H: Hasher, S>(
+    root: &H::Out,
+    mdb: &MemoryDB<H>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    H::Out: Codec + Serialize,
+    S: Serializer,
+{
+    let kvs: Vec<_> = mdb.clone().drain().into_iter().map(|it| it.1).collect();
     (root, kvs).serialize(serializer)
 }
 
@@ -127,10 +198,16 @@ impl<H: Hasher> TrieStorage<H>
 where
     H::Out: Codec + Ord,
 {
+    fn from_backend(backend: TrieBackend<MemoryDB<H>, H>) -> Self {
+        let root = *backend.root();
+        Self(TrieBackend::new(SharedTrieDB::new(backend.into_storage()), root))
+    }
+
     /// Overwrite all data in the trie DB with given key/value pairs.
+    ///
+    /// The read transactions alive keep reading the replaced DB.
     pub fn load(&mut self, pairs: impl Iterator<Item = (impl AsRef<[u8]>, impl AsRef<[u8]>)>) {
-        let trie = load_trie_backend(pairs);
-        let _ = core::mem::replace(&mut self.0, trie);
+        *self = Self::from_backend(load_trie_backend(pairs));
     }
 
     /// Calculate the new state root given storage changes. Returns the new root and a transaction to apply.
@@ -163,11 +240,45 @@ where
     }
 
     /// Apply storage changes calculated from `calc_root_if_changes`.
+    ///
+    /// The DB is updated in place. If any `ReadTransaction` is alive, the nodes pruned by the
+    /// changes are moved to the side table of the `SharedTrieDB`, so the cost grows with the size
+    /// of the changes, not of the state.
     pub fn apply_changes(&mut self, root: H::Out, transaction: MemoryDB<H>) {
-        let mut storage = core::mem::take(self).0.into_storage();
-        storage.consolidate(transaction);
-        storage.purge();
-        let _ = core::mem::replace(&mut self.0, TrieBackend::new(storage, root));
+        let db = core::mem::take(self).0.into_storage();
+        {
+            let mut current = db.current.write();
+            let mut pruned = db.pruned.write();
+            let shared = db.is_shared();
+            if !shared {
+                *pruned = Default::default();
+            }
+            // Only the nodes in the transaction can lose their last reference.
+            let keys = transaction.keys();
+            current.consolidate(transaction);
+            if shared {
+                for (key, _) in keys {
+                    if let Some((value, rc)) = current.raw(&key, EMPTY_PREFIX) {
+                        if rc <= 0 {
+                            pruned.emplace(key, EMPTY_PREFIX, value.clone());
+                        }
+                    }
+                }
+            }
+            current.purge();
+        }
+        self.0 = TrieBackend::new(db, root);
+    }
+
+    /// Begin a read transaction on the current state, which can be moved to another thread and
+    /// stays valid while changes are applied to the storage.
+    ///
+    /// Keeping the transaction alive keeps the nodes pruned by the following changes in memory.
+    pub fn begin_read(&self) -> ReadTransaction<H> {
+        ReadTransaction(TrieBackend::new(
+            self.0.backend_storage().clone(),
+            *self.0.root(),
+        ))
     }
 
     /// Return the state root hash
@@ -175,26 +286,71 @@ where
         self.0.root()
     }
 
+    /// Return the underlying trie backend, e.g. to generate storage proofs
+    pub fn as_trie_backend(&self) -> &TrieBackend<SharedTrieDB<H>, H> {
+        &self.0
+    }
+
     /// Given storage key return storage value
     pub fn get(&self, key: impl AsRef<[u8]>) -> Option<Vec<u8>> {
-        self.0.storage(key.as_ref()).ok().flatten()
+        get(&self.0, key)
     }
 
     /// Return storage pairs which start with given storage key prefix
     pub fn pairs(&self, prefix: impl AsRef<[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
-        self.pairs_into(prefix)
+        pairs_into(&self.0, prefix)
     }
+}
 
-    fn pairs_into<R: FromIterator<(Vec<u8>, Vec<u8>)>>(&self, prefix: impl AsRef<[u8]>) -> R {
-        self.0
-            .keys(prefix.as_ref())
-            .into_iter()
-            .map(|key| {
-                let value = self.get(&key).expect("Reflected key should exists");
-                (key, value)
-            })
-            .collect()
+impl<H: Hasher> ReadTransaction<H>
+where
+    H::Out: Codec + Ord,
+{
+    /// Return the state root hash the transaction is pinned to
+    pub fn root(&self) -> &H::Out {
+        self.0.root()
+    }
+
+    /// Return the underlying trie backend, e.g. to generate storage proofs
+    pub fn as_trie_backend(&self) -> &TrieBackend<SharedTrieDB<H>, H> {
+        &self.0
     }
+
+    /// Given storage key return storage value
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Option<Vec<u8>> {
+        get(&self.0, key)
+    }
+
+    /// Return storage pairs which start with given storage key prefix
+    pub fn pairs(&self, prefix: impl AsRef<[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        pairs_into(&self.0, prefix)
+    }
+}
+
+fn get<H: Hasher>(
+    trie: &TrieBackend<SharedTrieDB<H>, H>,
+    key: impl AsRef<[u8]>,
+) -> Option<Vec<u8>>
+where
+    H::Out: Codec + Ord,
+{
+    trie.storage(key.as_ref()).ok().flatten()
+}
+
+fn pairs_into<H: Hasher, R: FromIterator<(Vec<u8>, Vec<u8>)>>(
+    trie: &TrieBackend<SharedTrieDB<H>, H>,
+    prefix: impl AsRef<[u8]>,
+) -> R
+where
+    H::Out: Codec + Ord,
+{
+    trie.keys(prefix.as_ref())
+        .into_iter()
+        .map(|key| {
+            let value = get(trie, &key).expect("Reflected key should exists");
+            (key, value)
+        })
+        .collect()
 }
 
 #[cfg(feature = "serde")]
@@ -207,7 +363,8 @@ const _: () = {
         where
             S: Serializer,
         {
-            serialize_trie_backend(&self.0, serializer)
+            let current = self.0.backend_storage().current.read();
+            serialize_memory_db(self.0.root(), &current, serializer)
         }
     }
 
@@ -219,7 +376,7 @@ const _: () = {
         where
             D: Deserializer<'de>,
         {
-            Ok(Self(deserialize_trie_backend(deserializer)?))
+            Ok(Self::from_backend(deserialize_trie_backend(deserializer)?))
         }
     }
 };
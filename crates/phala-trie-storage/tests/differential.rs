@@ -0,0 +1,182 @@
+//! Differential tests of `TrieStorage` against the reference in-memory backend of
+//! sp-state-machine.
+//!
+//! Random change sets, including child trie changes, are applied to both backends and a plain
+//! model. The roots must be identical after every change set, and both backends must read back
+//! what the model holds.
+
+use std::collections::BTreeMap;
+
+use phala_trie_storage::{ChildStorageCollection, StorageCollection, TrieStorage};
+use proptest::collection::{btree_map, vec};
+use proptest::option;
+use proptest::prelude::*;
+use sp_core::{storage::ChildInfo, Hasher};
+use sp_state_machine::{Backend as _, InMemoryBackend};
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct NativeBlakeTwo256;
+
+impl Hasher for NativeBlakeTwo256 {
+    type Out = sp_core::H256;
+    type StdHasher = hash256_std_hasher::Hash256StdHasher;
+    const LENGTH: usize = 32;
+
+    fn hash(s: &[u8]) -> Self::Out {
+        sp_core::hashing::blake2_256(s).into()
+    }
+}
+
+const STATE_VERSION: sp_core::storage::StateVersion = sp_core::storage::StateVersion::V0;
+const CHILD_STORAGE_PREFIX: &[u8] = b":child_storage:";
+
+type ChangeSet = (StorageCollection, ChildStorageCollection);
+
+/// Keys from a tiny alphabet, so that the change sets keep hitting the same keys and branches.
+fn key() -> impl Strategy<Value = Vec<u8>> {
+    vec(0u8..4, 0..4)
+}
+
+/// An insertion or a removal. Values cross the 32 bytes boundary of inlined trie nodes. Empty
+/// values are left out since the trie can not tell them from a removal.
+fn value() -> impl Strategy<Value = Option<Vec<u8>>> {
+    option::of(vec(any::<u8>(), 1..64))
+}
+
+fn collection() -> impl Strategy<Value = StorageCollection> {
+    btree_map(key(), value(), 0..8).prop_map(|changes| changes.into_iter().collect())
+}
+
+fn change_set() -> impl Strategy<Value = ChangeSet> {
+    let children = btree_map(vec(0u8..3, 0..3), collection(), 0..3)
+        .prop_map(|changes| changes.into_iter().collect());
+    (collection(), children)
+}
+
+/// The expected content of the storage. Removed keys are kept as `None` to check the removals.
+#[derive(Default)]
+struct Model {
+    main: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    children: BTreeMap<Vec<u8>, BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+}
+
+impl Model {
+    fn apply(&mut self, (main, children): &ChangeSet) {
+        self.main.extend(main.iter().cloned());
+        for (name, changes) in children {
+            self.children
+                .entry(name.clone())
+                .or_default()
+                .extend(changes.iter().cloned());
+        }
+    }
+
+    fn main_pairs(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.main
+            .iter()
+            .filter_map(|(k, v)| Some((k.clone(), v.clone()?)))
+            .collect()
+    }
+}
+
+fn child_root_key(name: &[u8]) -> Vec<u8> {
+    ChildInfo::new_default(name).prefixed_storage_key().into_inner()
+}
+
+fn apply_to_reference(reference: &mut InMemoryBackend<NativeBlakeTwo256>, changes: &ChangeSet) {
+    let (main, children) = changes;
+    let changes = std::iter::once((None, main.clone())).chain(
+        children
+            .iter()
+            .map(|(name, changes)| (Some(ChildInfo::new_default(name)), changes.clone())),
+    );
+    reference.insert(changes, STATE_VERSION);
+}
+
+proptest! {
+    #[test]
+    fn roots_and_reads_match_reference(change_sets in vec(change_set(), 1..8)) {
+        let mut storage = TrieStorage::<NativeBlakeTwo256>::default();
+        let mut reference = InMemoryBackend::<NativeBlakeTwo256>::default();
+        let mut model = Model::default();
+        prop_assert_eq!(storage.root(), reference.root());
+
+        for changes in change_sets.iter() {
+            let (root, transaction) = storage.calc_root_if_changes(&changes.0, &changes.1);
+            storage.apply_changes(root, transaction);
+            apply_to_reference(&mut reference, changes);
+            model.apply(changes);
+
+            prop_assert_eq!(storage.root(), reference.root());
+
+            for (key, value) in model.main.iter() {
+                prop_assert_eq!(&storage.get(key), value);
+                prop_assert_eq!(&reference.storage(key).unwrap(), value);
+            }
+            let pairs: Vec<_> = storage
+                .pairs(&[])
+                .into_iter()
+                .filter(|(k, _)| !k.starts_with(CHILD_STORAGE_PREFIX))
+                .collect();
+            prop_assert_eq!(pairs, model.main_pairs());
+
+            for (name, child) in model.children.iter() {
+                let root_key = child_root_key(name);
+                prop_assert_eq!(storage.get(&root_key), reference.storage(&root_key).unwrap());
+                let child_info = ChildInfo::new_default(name);
+                for (key, value) in child.iter() {
+                    let read = storage.as_trie_backend().child_storage(&child_info, key);
+                    prop_assert_eq!(&read.unwrap(), value);
+                    prop_assert_eq!(&reference.child_storage(&child_info, key).unwrap(), value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn read_transaction_is_not_affected_by_changes(change_sets in vec(change_set(), 2..8)) {
+        let mut storage = TrieStorage::<NativeBlakeTwo256>::default();
+        let (first, rest) = change_sets.split_first().unwrap();
+        let (root, transaction) = storage.calc_root_if_changes(&first.0, &first.1);
+        storage.apply_changes(root, transaction);
+        let pinned_root = *storage.root();
+        let pinned_pairs = storage.pairs(&[]);
+
+        let read = storage.begin_read();
+        let mut unread = TrieStorage::<NativeBlakeTwo256>::default();
+        for changes in change_sets.iter() {
+            let (root, transaction) = unread.calc_root_if_changes(&changes.0, &changes.1);
+            unread.apply_changes(root, transaction);
+        }
+        for changes in rest {
+            let (root, transaction) = storage.calc_root_if_changes(&changes.0, &changes.1);
+            storage.apply_changes(root, transaction);
+        }
+        // Keeping the pruned nodes for the reader does not change the outcome of the changes.
+        prop_assert_eq!(storage.root(), unread.root());
+        prop_assert_eq!(storage.pairs(&[]), unread.pairs(&[]));
+        // The transaction can be read from another thread.
+        std::thread::spawn(move || {
+            assert_eq!(read.root(), &pinned_root);
+            assert_eq!(read.pairs(&[]), pinned_pairs);
+            for (key, value) in pinned_pairs {
+                assert_eq!(read.get(&key), Some(value));
+            }
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn loaded_root_matches_reference(pairs in btree_map(key(), vec(any::<u8>(), 1..64), 0..16)) {
+        let mut storage = TrieStorage::<NativeBlakeTwo256>::default();
+        storage.load(pairs.iter());
+
+        let mut reference = InMemoryBackend::<NativeBlakeTwo256>::default();
+        let changes = pairs.iter().map(|(k, v)| (k.clone(), Some(v.clone()))).collect();
+        reference.insert(std::iter::once((None, changes)), STATE_VERSION);
+
+        prop_assert_eq!(storage.root(), reference.root());
+        prop_assert_eq!(storage.pairs(&[]), pairs.into_iter().collect::<Vec<_>>());
+    }
+}
@@ -59,6 +59,7 @@ struct Changes {
     child_storage_changes: TestChildStorageCollection,
 }
 
+/// The fixture, refreshed with `cargo run -p trie-fixture-gen -- capture`.
 fn data_dir() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR"))
         .join("tests")
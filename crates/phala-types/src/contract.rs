@@ -3,6 +3,8 @@ use alloc::string::String;
 use alloc::vec::Vec;
 use codec::{Decode, Encode};
 use scale_info::TypeInfo;
+#[cfg(feature = "enable_serde")]
+use serde::{Deserialize, Serialize};
 
 use crate::WorkerPublicKey;
 pub use phala_mq::{ContractClusterId, ContractId};
@@ -17,6 +19,9 @@ pub const DIEM: ContractId32 = 5;
 pub const SUBSTRATE_KITTIES: ContractId32 = 6;
 pub const BTC_LOTTERY: ContractId32 = 7;
 pub const GEOLOCATION: ContractId32 = 8;
+pub const VAULT: ContractId32 = 9;
+pub const BALLOT: ContractId32 = 10;
+pub const PRICE_ORACLE: ContractId32 = 11;
 pub const GUESS_NUMBER: ContractId32 = 100;
 pub const BTC_PRICE_BOT: ContractId32 = 101;
 
@@ -39,18 +44,34 @@ pub mod messaging {
     use alloc::vec::Vec;
     use codec::{Decode, Encode};
 
-    use super::{ContractClusterId, ContractInfo};
-    use crate::WorkerIdentity;
-    use phala_mq::bind_topic;
+    use super::{
+        ContractClusterId, ContractId, ContractInfo, ContractUsage, SidevmCodeHash,
+        SidevmResourceProfile,
+    };
+    use crate::{WorkerIdentity, WorkerPublicKey};
+    use phala_mq::{bind_topic, MessageOrigin};
+    use sp_core::H256;
 
     bind_topic!(ClusterEvent, b"phala/cluster/event");
     #[derive(Encode, Decode, Debug)]
     pub enum ClusterEvent {
-        // TODO.shelven: enable add and remove workers
         DeployCluster {
             cluster: ContractClusterId,
             workers: Vec<WorkerIdentity>,
         },
+        /// New workers joining a deployed cluster without any code or contract yet. Handled by the
+        /// gatekeeper the same way as `DeployCluster`.
+        AddWorkers {
+            cluster: ContractClusterId,
+            workers: Vec<WorkerIdentity>,
+        },
+        /// Workers leaving a cluster should drop the cluster state.
+        RemoveWorkers {
+            cluster: ContractClusterId,
+            workers: Vec<WorkerPublicKey>,
+        },
+        /// All workers of the cluster should drop the cluster state.
+        DestroyCluster { cluster: ContractClusterId },
     }
 
     bind_topic!(ContractOperation<CodeHash, AccountId>, b"phala/contract/op");
@@ -71,12 +92,71 @@ pub mod messaging {
             ContractOperation::InstantiateCode { contract_info }
         }
     }
+
+    bind_topic!(SidevmCodeEvent, b"phala/sidevm/code");
+    /// The changes of the on-chain sidevm code registry. Workers keep a copy of the registry to
+    /// verify a sidevm code before spawning it.
+    #[derive(Encode, Decode, Debug)]
+    pub enum SidevmCodeEvent {
+        CodeRegistered {
+            hash: SidevmCodeHash,
+            profile: SidevmResourceProfile,
+        },
+        CodeUnregistered {
+            hash: SidevmCodeHash,
+        },
+        /// The resource profile of a registered code is changed
+        ProfileUpdated {
+            hash: SidevmCodeHash,
+            profile: SidevmResourceProfile,
+        },
+        /// Whether the workers refuse to start the sidevm codes not in the registry
+        SetEnforced { enforced: bool },
+    }
+
+    bind_topic!(ContractCommandFailure, b"phala/contract/command/failure");
+    /// Sent by a contract when it fails to handle a command, so that the command sender can learn
+    /// about the failure on chain.
+    ///
+    /// The commands sent by accounts may be secret, so their failures are reported with neither
+    /// the sender nor the error. The sender recognizes the command by its hash.
+    #[derive(Encode, Decode, Debug)]
+    pub struct ContractCommandFailure {
+        /// The sender of the failed command, `None` for an account
+        pub origin: Option<MessageOrigin>,
+        /// The blake2_256 hash of the command payload as it was sent on chain, e.g. the
+        /// encrypted one. For a native command called by a contract, which is not on chain, the
+        /// hash of the caller id and the number of the native commands it called before
+        pub command_hash: H256,
+        /// The index of the `TransactionError` variant in pRuntime, or `GENERIC_ERROR_CODE` for
+        /// an account
+        pub error_code: u8,
+    }
+
+    impl ContractCommandFailure {
+        /// The error code reported for the commands sent by accounts
+        pub const GENERIC_ERROR_CODE: u8 = u8::MAX;
+    }
+
+    bind_topic!(WorkerUsageReport<BlockNumber, AccountId>, b"phala/contract/worker/usage");
+    /// The contract usage served by a worker since its last report, to be billed.
+    #[derive(Encode, Decode, Debug)]
+    pub struct WorkerUsageReport<BlockNumber, AccountId> {
+        pub cluster: ContractClusterId,
+        /// The block number the report is made at
+        pub block_number: BlockNumber,
+        pub usage: Vec<(ContractId, ContractUsage)>,
+        /// The number of the signed queries by their origins, who pay for the queries
+        pub query_origins: Vec<(AccountId, u64)>,
+    }
 }
 
 #[derive(Encode, Decode, Clone, PartialEq, Eq, Debug, TypeInfo)]
 pub enum ClusterPermission<AccountId> {
     Public,
     OnlyOwner(AccountId),
+    /// The owner and the listed accounts
+    AllowList(Vec<AccountId>),
 }
 
 #[derive(Encode, Decode, Clone, PartialEq, Eq, Debug, TypeInfo)]
@@ -96,6 +176,43 @@ pub struct ContractInfo<CodeHash, AccountId> {
     pub instantiate_data: Vec<u8>,
 }
 
+/// The blake2_256 hash of a sidevm wasm code
+pub type SidevmCodeHash = sp_core::H256;
+
+/// The resources a sidevm instance running the code is allowed to use
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Debug, TypeInfo)]
+pub struct SidevmResourceProfile {
+    /// Max number of 64KB wasm memory pages
+    pub memory_pages: u32,
+    /// The gRPC targets the instance is allowed to call, in the form of `https://host:port`
+    pub grpc_targets: Vec<Vec<u8>>,
+}
+
+/// On-chain sidevm code registration info
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Debug, TypeInfo)]
+pub struct SidevmCodeInfo<AccountId, BlockNumber> {
+    pub owner: AccountId,
+    pub size: u32,
+    pub profile: SidevmResourceProfile,
+    pub registered_at: BlockNumber,
+}
+
+/// The metered usage of a contract on a worker
+#[cfg_attr(feature = "enable_serde", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, Debug, TypeInfo)]
+pub struct ContractUsage {
+    /// Number of the signed queries handled. The unsigned queries are free and not counted.
+    pub queries: u64,
+    /// Number of the messages pushed to the sidevm instance of the contract
+    pub sidevm_messages: u64,
+}
+
+impl ContractUsage {
+    pub fn is_empty(&self) -> bool {
+        self.queries == 0 && self.sidevm_messages == 0
+    }
+}
+
 /// Use blake2_256 on the preimage for the final contract id
 pub fn contract_id_preimage(
     deployer: &[u8],
@@ -148,10 +148,33 @@ pub mod messaging {
             value: Balance,
             index: u64,
         },
+        /// Credits a foreign asset locked on chain, e.g. bridged by XCM. Only accepted from the
+        /// PhalaAssetsBridge pallet.
+        Wrap {
+            /// The SCALE encoded location of the foreign asset.
+            location: Vec<u8>,
+            who: AccountId,
+            amount: Balance,
+        },
+        /// Burns a wrapped asset to release the foreign asset on chain.
+        Unwrap {
+            id: AssetId,
+            dest: AccountId,
+            value: Balance,
+        },
     }
 
     pub type AssetId = u32;
 
+    /// Asks the PhalaAssetsBridge pallet to release the foreign asset burnt in the contract.
+    bind_topic!(AssetUnwrap<AccountId, Balance>, b"^phala/assets/unwrap");
+    #[derive(Encode, Decode, TypeInfo)]
+    pub struct AssetUnwrap<AccountId, Balance> {
+        pub location: Vec<u8>,
+        pub dest: AccountId,
+        pub amount: Balance,
+    }
+
     // Messages for Web3Analytics
 
     #[derive(Encode, Decode, Debug, TypeInfo)]
@@ -254,9 +277,63 @@ pub mod messaging {
         UpdateBtcPrice { price: String },
     }
 
+    #[derive(Debug, Clone, Encode, Decode)]
+    pub enum VaultCommand {
+        /// Store a secret of the sender, replacing the old one under the same key
+        Put { key: Vec<u8>, value: Vec<u8> },
+        /// Remove a secret of the sender
+        Remove { key: Vec<u8> },
+        /// Allow another account to read a secret of the sender
+        Grant { key: Vec<u8>, to: AccountId },
+        /// Revoke a previous grant
+        Revoke { key: Vec<u8>, from: AccountId },
+    }
+
+    // Messages for Ballot
+
+    #[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+    pub enum BallotCommand<AccountId, Balance, BlockNumber> {
+        /// Create a proposal with the given number of options, open for voting until `deadline`
+        Propose {
+            title: String,
+            options: u32,
+            deadline: BlockNumber,
+        },
+        /// Vote for an option of a proposal, replacing the previous vote of the sender. The sender
+        /// must have a voting weight
+        Vote { proposal: u32, option: u32 },
+        /// Update the voting weights of the accounts to their locked balances. Only accepted from
+        /// the PhalaBallot pallet
+        UpdateWeights { weights: Vec<(AccountId, Balance)> },
+    }
+
     /// A fixed point number with 64 integer bits and 64 fractional bits.
     pub type U64F64Bits = u128;
 
+    #[derive(Debug, Clone, Encode, Decode)]
+    pub enum PriceOracleCommand {
+        /// Set the contract owner
+        SetOwner { owner: AccountId },
+        /// Accept the price reports from the sidevm instance of the given pink contract
+        SetFeeder { contract: H256 },
+    }
+
+    /// The prices fetched by the feeder sidevm of a price oracle, emitted to the host with the
+    /// ocall `emit_message`.
+    #[derive(Debug, Clone, Encode, Decode)]
+    pub struct PriceReport {
+        pub prices: Vec<SourcePrice>,
+    }
+
+    #[derive(Debug, Clone, Encode, Decode)]
+    pub struct SourcePrice {
+        /// The trading pair, e.g. `BTC/USD`
+        pub pair: String,
+        /// The exchange the price is fetched from
+        pub source: String,
+        pub price: U64F64Bits,
+    }
+
     // Messages: System
     #[derive(Encode, Decode, TypeInfo)]
     pub struct WorkerEventWithKey {
@@ -429,6 +506,13 @@ pub mod messaging {
     #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, TypeInfo)]
     pub enum GatekeeperChange {
         GatekeeperRegistered(NewGatekeeperEvent),
+        GatekeeperUnregistered(RemoveGatekeeperEvent),
+    }
+
+    #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, TypeInfo)]
+    pub struct RemoveGatekeeperEvent {
+        /// The public key of the rotated out gatekeeper
+        pub pubkey: WorkerPublicKey,
     }
 
     impl GatekeeperChange {
@@ -441,6 +525,10 @@ pub mod messaging {
                 ecdh_pubkey,
             })
         }
+
+        pub fn gatekeeper_unregistered(pubkey: WorkerPublicKey) -> GatekeeperChange {
+            GatekeeperChange::GatekeeperUnregistered(RemoveGatekeeperEvent { pubkey })
+        }
     }
 
     // Messages: Distribution of master key and contract keys
@@ -695,6 +783,43 @@ pub struct WorkerRegistrationInfo<AccountId> {
     pub operator: Option<AccountId>,
 }
 
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq, TypeInfo)]
+pub enum EndpointProtocol {
+    Http,
+    Https,
+}
+
+/// A public endpoint serving the worker's prpc API
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq, TypeInfo)]
+pub struct WorkerEndpoint {
+    pub protocol: EndpointProtocol,
+    pub url: Vec<u8>,
+    /// The version of the pRuntime serving the endpoint
+    pub version: u32,
+}
+
+/// The endpoints of a worker, signed by its identity key
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq, TypeInfo)]
+pub struct WorkerEndpointPayload {
+    pub pubkey: WorkerPublicKey,
+    pub endpoints: Vec<WorkerEndpoint>,
+    /// The unix timestamp (in seconds) when the payload is signed
+    pub signing_time: u64,
+}
+
+impl WorkerEndpointPayload {
+    /// Prefixed to the payload before signing, so that the signature can't be taken for any
+    /// other message signed by the identity key.
+    pub const SIGNING_CONTEXT: &'static [u8] = b"phala/worker/endpoint:";
+
+    /// The message to be signed by the worker's identity key
+    pub fn signing_message(&self) -> Vec<u8> {
+        let mut message = Self::SIGNING_CONTEXT.to_vec();
+        self.encode_to(&mut message);
+        message
+    }
+}
+
 #[derive(Encode, Decode, Debug, Default, TypeInfo)]
 pub struct RoundInfo<BlockNumber> {
     pub round: u32,
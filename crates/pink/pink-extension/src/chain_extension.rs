@@ -16,6 +16,28 @@ pub mod test;
 #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
 pub struct StorageQuotaExceeded;
 
+/// The error of `sidevm_query`.
+#[derive(scale::Encode, scale::Decode, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub enum SidevmQueryError {
+    /// The contract has no running sidevm instance.
+    NotRunning,
+    /// The sidevm instance has too many pending queries.
+    Busy,
+    /// The sidevm instance did not reply before the query deadline.
+    Timeout,
+}
+
+/// The error of `native_query`.
+#[derive(scale::Encode, scale::Decode, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub enum NativeQueryError {
+    /// There is no native contract with the id in the cluster of the contract.
+    ContractNotFound,
+    /// The native contract failed to decode the query.
+    BadQuery,
+}
+
 #[derive(scale::Encode, scale::Decode)]
 #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
 pub enum ErrorCode {}
@@ -80,6 +102,23 @@ pub trait PinkExt {
     /// command context.
     #[ink(extension = 0xff000009, handle_status = false, returns_result = false)]
     fn cache_remove(args: &[u8]) -> Option<Vec<u8>>;
+
+    /// Send a query to the sidevm instance of the contract and wait for its reply.
+    ///
+    /// The sidevm instance has to reply before the deadline of the contract query, which is shared
+    /// with the other blocking calls such as `http_request`.
+    ///
+    /// Only for query functions. The contract call fails if it is called from a command context.
+    #[ink(extension = 0xff00000a, handle_status = false, returns_result = false)]
+    fn sidevm_query(payload: &[u8]) -> Result<Vec<u8>, SidevmQueryError>;
+
+    /// Query a native contract in the same cluster, e.g. Balances, with the contract account as
+    /// the origin. The query and the reply are SCALE encoded as defined by the native contract.
+    ///
+    /// The native contracts are read as of the block the contract query is made at. Only for query
+    /// functions. The contract call fails if it is called from a command context.
+    #[ink(extension = 0xff00000b, handle_status = false, returns_result = false)]
+    fn native_query(contract: crate::Hash, query: &[u8]) -> Result<Vec<u8>, NativeQueryError>;
 }
 
 pub fn pink_extension_instance() -> <PinkExt as ChainExtensionInstance>::Instance {
@@ -12,6 +12,7 @@ pub use pink_extension_macro::contract;
 
 pub mod chain_extension;
 pub use chain_extension::pink_extension_instance;
+pub use chain_extension::pink_extension_instance as ext;
 
 const PINK_EVENT_TOPIC: &[u8] = b"phala.pink.event";
 
@@ -52,6 +53,15 @@ pub enum PinkEvent {
     },
     /// Push a message to the associated sidevm instance.
     SidevmMessage(Vec<u8>),
+    /// Call a command of a native contract on behalf of the contract account.
+    NativeCommand {
+        /// The id of the native contract.
+        contract: Hash,
+        /// The SCALE encoded command.
+        command: Vec<u8>,
+    },
+    /// One chunk of the startup config of the side VM, sent before `StartSidevm`.
+    SidevmConfigChunk(Cow<'static, [u8]>),
 }
 
 impl Topics for PinkEvent {
@@ -107,11 +117,20 @@ pub fn set_on_block_end_selector(selector: u32) {
 
 /// Start a side VM instance
 pub fn start_sidevm(wasm_code: &'static [u8], memory_pages: u32) {
+    start_sidevm_with_config(wasm_code, memory_pages, &[])
+}
+
+/// Start a side VM instance with a startup config of at most 64KB, which the side VM program reads
+/// with `sidevm::vm_config`.
+pub fn start_sidevm_with_config(wasm_code: &'static [u8], memory_pages: u32, config: &[u8]) {
     // `ink!` limit a single event size to 16KB. As a workaround, we chop the code in to 15KB chunks.
     emit_event::<PinkEnvironment, _>(PinkEvent::StartToTransferSidevmCode);
     for chunk in wasm_code.chunks(1024 * 15) {
         emit_event::<PinkEnvironment, _>(PinkEvent::SidevmCodeChunk(chunk.into()));
     }
+    for chunk in config.chunks(1024 * 15) {
+        emit_event::<PinkEnvironment, _>(PinkEvent::SidevmConfigChunk(chunk.to_vec().into()));
+    }
     emit_event::<PinkEnvironment, _>(PinkEvent::StartSidevm { memory_pages })
 }
 
@@ -120,6 +139,14 @@ pub fn push_sidevm_message(message: Vec<u8>) {
     emit_event::<PinkEnvironment, _>(PinkEvent::SidevmMessage(message))
 }
 
+/// Call a command of a native contract, e.g. Balances, with the contract account as the origin.
+///
+/// The command is executed after the current transaction finished, in the same block. Its result
+/// is not returned to the contract. Use `ext().native_query` in a query to read the outcome.
+pub fn call_native_contract(contract: Hash, command: Vec<u8>) {
+    emit_event::<PinkEnvironment, _>(PinkEvent::NativeCommand { contract, command })
+}
+
 /// Pink defined environment. Used this environment to access the fat contract runtime features.
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
@@ -0,0 +1,202 @@
+//! Bounded multi-producer, single-consumer channels between the guest tasks.
+//!
+//! A task blocked on a channel is woken up by marking it ready to the host, the same way the host
+//! wakes up the tasks waiting for the ocall resources.
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use super::*;
+
+struct Shared<T> {
+    queue: VecDeque<T>,
+    capacity: usize,
+    senders: usize,
+    receiver_alive: bool,
+    /// The task waiting in `recv`.
+    recv_task: Option<i32>,
+    /// The tasks waiting for the capacity in `send`.
+    send_tasks: Vec<i32>,
+}
+
+fn wake(task_id: i32) {
+    // The tasks are only woken up to poll again, so a failure here is not fatal.
+    let _ = ocall::mark_task_ready(task_id);
+}
+
+/// The sending half of a channel, which can be cloned to send from multiple tasks.
+pub struct Sender<T> {
+    shared: Rc<RefCell<Shared<T>>>,
+}
+
+/// The receiving half of a channel.
+pub struct Receiver<T> {
+    shared: Rc<RefCell<Shared<T>>>,
+}
+
+/// The error returned by `send` when the receiver is dropped. It carries the unsent value.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// The error returned by `try_send`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// The channel is full.
+    Full(T),
+    /// The receiver is dropped.
+    Closed(T),
+}
+
+/// Creates a channel buffering at most `capacity` values.
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "The capacity of a channel must be positive");
+    let shared = Rc::new(RefCell::new(Shared {
+        queue: VecDeque::with_capacity(capacity),
+        capacity,
+        senders: 1,
+        receiver_alive: true,
+        recv_task: None,
+        send_tasks: Vec::new(),
+    }));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+impl<T> Sender<T> {
+    /// Sends a value without waiting for the capacity.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut shared = self.shared.borrow_mut();
+        if !shared.receiver_alive {
+            return Err(TrySendError::Closed(value));
+        }
+        if shared.queue.len() >= shared.capacity {
+            return Err(TrySendError::Full(value));
+        }
+        shared.queue.push_back(value);
+        if let Some(task_id) = shared.recv_task.take() {
+            wake(task_id);
+        }
+        Ok(())
+    }
+
+    /// Sends a value, waiting until there is capacity in the channel.
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut value = Some(value);
+        combinators::poll_fn(|_cx| {
+            let item = value.take().expect("Polled after completion");
+            match self.try_send(item) {
+                Ok(()) => task::Poll::Ready(Ok(())),
+                Err(TrySendError::Closed(item)) => task::Poll::Ready(Err(SendError(item))),
+                Err(TrySendError::Full(item)) => {
+                    value = Some(item);
+                    let task_id = tasks::current_task();
+                    let mut shared = self.shared.borrow_mut();
+                    if !shared.send_tasks.contains(&task_id) {
+                        shared.send_tasks.push(task_id);
+                    }
+                    task::Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Returns true if the receiver is dropped.
+    pub fn is_closed(&self) -> bool {
+        !self.shared.borrow().receiver_alive
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.borrow_mut().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.borrow_mut();
+        shared.senders -= 1;
+        if shared.senders == 0 {
+            if let Some(task_id) = shared.recv_task.take() {
+                wake(task_id);
+            }
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Takes a value if there is any, without waiting.
+    ///
+    /// Returns `Ready(None)` if the channel is empty and all the senders are dropped.
+    pub fn try_recv(&self) -> task::Poll<Option<T>> {
+        let mut shared = self.shared.borrow_mut();
+        match shared.queue.pop_front() {
+            Some(value) => {
+                for task_id in shared.send_tasks.drain(..) {
+                    wake(task_id);
+                }
+                task::Poll::Ready(Some(value))
+            }
+            None if shared.senders == 0 => task::Poll::Ready(None),
+            None => task::Poll::Pending,
+        }
+    }
+
+    /// Receives the next value, or `None` if the channel is empty and all the senders are dropped.
+    pub async fn recv(&self) -> Option<T> {
+        combinators::poll_fn(|_cx| {
+            let poll = self.try_recv();
+            if poll.is_pending() {
+                self.shared.borrow_mut().recv_task = Some(tasks::current_task());
+            }
+            poll
+        })
+        .await
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.borrow_mut();
+        shared.receiver_alive = false;
+        for task_id in shared.send_tasks.drain(..) {
+            wake(task_id);
+        }
+    }
+}
+
+/// Bridges the host message inbox to a channel.
+///
+/// A task is spawned to forward the messages pushed by the host to the channel, and is stopped
+/// when the host closes the inbox. The returned `Sender` can be cloned to mix the messages
+/// produced by the guest tasks into the same stream.
+///
+/// The inbox can only be bridged once, since each message is only delivered to one consumer.
+pub fn message_inbox(capacity: usize) -> (Sender<Vec<u8>>, Receiver<Vec<u8>>) {
+    let (tx, rx) = bounded(capacity);
+    let forward_tx = tx.clone();
+    spawn(async move {
+        // The resource id 0 is the inbox of the messages pushed by the host.
+        let next_message = || {
+            combinators::poll_fn(|_cx| match ocall::poll(0) {
+                Ok(Poll::Ready(message)) => task::Poll::Ready(message),
+                Ok(Poll::Pending) => task::Poll::Pending,
+                Err(_) => task::Poll::Ready(None),
+            })
+        };
+        while let Some(message) = next_message().await {
+            if forward_tx.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+    (tx, rx)
+}
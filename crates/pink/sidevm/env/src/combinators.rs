@@ -0,0 +1,106 @@
+//! Future combinators working with the sidevm executor.
+//!
+//! The guest tasks are woken up by the host rather than by the `Waker` in the `Context`, so the
+//! combinators here poll all their inner futures each time the task is polled, and never touch the
+//! waker.
+
+use super::*;
+
+/// The output of `select`, telling which of the futures completed first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Either<A, B> {
+    /// The first future completed first.
+    Left(A),
+    /// The second future completed first.
+    Right(B),
+}
+
+/// A future wrapping a closure which is called on each poll.
+pub struct PollFn<F> {
+    f: F,
+}
+
+impl<F> Unpin for PollFn<F> {}
+
+/// Creates a future from a closure which is called on each poll.
+pub fn poll_fn<T, F>(f: F) -> PollFn<F>
+where
+    F: FnMut(&mut task::Context<'_>) -> task::Poll<T>,
+{
+    PollFn { f }
+}
+
+impl<T, F> Future for PollFn<F>
+where
+    F: FnMut(&mut task::Context<'_>) -> task::Poll<T>,
+{
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<T> {
+        (self.f)(cx)
+    }
+}
+
+/// Polls the future if it is not done yet, and stores the output.
+fn poll_once<F: Future>(
+    fut: Pin<&mut F>,
+    output: &mut Option<F::Output>,
+    cx: &mut task::Context<'_>,
+) {
+    if output.is_none() {
+        if let task::Poll::Ready(value) = fut.poll(cx) {
+            *output = Some(value);
+        }
+    }
+}
+
+/// Waits for both of the futures to complete.
+pub async fn join<A: Future, B: Future>(a: A, b: B) -> (A::Output, B::Output) {
+    let (mut a, mut b) = (Box::pin(a), Box::pin(b));
+    let (mut out_a, mut out_b) = (None, None);
+    poll_fn(move |cx| {
+        poll_once(a.as_mut(), &mut out_a, cx);
+        poll_once(b.as_mut(), &mut out_b, cx);
+        if out_a.is_some() && out_b.is_some() {
+            task::Poll::Ready((out_a.take().unwrap(), out_b.take().unwrap()))
+        } else {
+            task::Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Waits for all of the futures to complete. The outputs are in the same order as the futures.
+pub async fn join_all<F: Future>(futures: impl IntoIterator<Item = F>) -> Vec<F::Output> {
+    let mut futures: Vec<_> = futures.into_iter().map(|fut| (Box::pin(fut), None)).collect();
+    poll_fn(move |cx| {
+        let mut done = true;
+        for (fut, output) in futures.iter_mut() {
+            poll_once(fut.as_mut(), output, cx);
+            done &= output.is_some();
+        }
+        if done {
+            task::Poll::Ready(futures.drain(..).filter_map(|(_, output)| output).collect())
+        } else {
+            task::Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Waits for either of the futures to complete, and drops the other one.
+///
+/// The first future is polled first, so it wins if both are ready.
+pub async fn select<A: Future, B: Future>(a: A, b: B) -> Either<A::Output, B::Output> {
+    let (mut a, mut b) = (Box::pin(a), Box::pin(b));
+    poll_fn(move |cx| {
+        if let task::Poll::Ready(value) = a.as_mut().poll(cx) {
+            return task::Poll::Ready(Either::Left(value));
+        }
+        if let task::Poll::Ready(value) = b.as_mut().poll(cx) {
+            return task::Poll::Ready(Either::Right(value));
+        }
+        task::Poll::Pending
+    })
+    .await
+}
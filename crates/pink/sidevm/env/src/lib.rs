@@ -16,11 +16,16 @@ use scale::{Decode, Encode};
 use tinyvec::TinyVec;
 
 pub use args_stack::RetEncode;
+pub use combinators::{join, join_all, poll_fn, select, Either, PollFn};
 pub use ocall_def::*;
 pub use pink_sidevm_macro::main;
 pub use tasks::{spawn, TaskHandle};
 
+pub mod channel;
+pub mod time;
+
 mod args_stack;
+mod combinators;
 mod ocall_def;
 mod tasks;
 
@@ -49,8 +54,9 @@ pub enum OcallError {
     UnsupportedOperation = 8,
     IoError = 9,
     ResourceLimited = 10,
-    /// Reserved for future use
-    Reserved11 = 11,
+    /// The VM is not permitted to perform the operation, e.g. to call a gRPC target which is not in
+    /// its allowlist
+    PermissionDenied = 11,
     /// Reserved for future use
     Reserved12 = 12,
     /// Reserved for future use
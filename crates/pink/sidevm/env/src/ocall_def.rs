@@ -41,6 +41,26 @@ impl<T> From<Poll<T>> for std::task::Poll<T> {
     }
 }
 
+/// The response of a unary gRPC call made with the ocall `fn grpc_call`.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct GrpcResponse {
+    /// The `grpc-status` of the call, 0 for OK.
+    pub status: u32,
+    /// The `grpc-message` of a failed call.
+    pub message: String,
+    /// The protobuf encoded response message, empty if the call failed.
+    pub body: Vec<u8>,
+}
+
+/// A query from the paired pink contract, accepted with the ocall `fn query_accept`.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct QueryRequest {
+    /// The resource to send the reply to with the ocall `fn query_reply`.
+    pub reply_id: i32,
+    /// The payload passed to `sidevm_query` by the contract.
+    pub payload: Vec<u8>,
+}
+
 // Poll state for poll_read/poll_write.
 impl I32Convertible for Poll<u32> {
     fn to_i32(&self) -> i32 {
@@ -135,4 +155,53 @@ pub trait OcallFuncs {
     /// Print log message.
     #[ocall(id = 220, fast_input, fast_return)]
     fn log(level: log::Level, message: &str) -> Result<()>;
+
+    /// Start a unary gRPC call to the given target, e.g. `https://api.example.com:443`, which must
+    /// be in the allowlist of the VM. The path is the full method name, e.g.
+    /// `/package.Service/Method`, and the request is the protobuf encoded request message.
+    ///
+    /// The host performs the connection, the TLS handshake and the HTTP/2 framing. Poll the
+    /// returned resource_id to get the SCALE encoded `GrpcResponse`.
+    #[ocall(id = 230, fast_return)]
+    fn grpc_call(target: String, path: String, request: Vec<u8>) -> Result<i32>;
+
+    /// Read a raw storage item of the pink contract paired with the VM, or `None` if the item does
+    /// not exist.
+    ///
+    /// The reads are made on a snapshot of the contract storage at the latest processed block, so
+    /// the items read are consistent with each other. The key must be a 32 bytes storage key of
+    /// the contract, otherwise the read fails with `InvalidParameter`. Reading an item larger than
+    /// 64KB fails with `ResourceLimited`.
+    ///
+    /// The snapshots are only taken for the VMs reading the storage: the first read of a VM marks
+    /// it as a reader and fails with `NotFound`, and the snapshots are available from the next
+    /// processed block on.
+    #[ocall(id = 240)]
+    fn contract_storage_get(key: Vec<u8>) -> Result<Option<Vec<u8>>>;
+
+    /// Accept the next query made by the paired pink contract with `sidevm_query`, or `None` if
+    /// the host stopped serving the queries.
+    ///
+    /// The contract waits for the reply until its query deadline, so the guest should reply as
+    /// soon as possible with the ocall `fn query_reply`. Closing the `reply_id` without replying
+    /// fails the query.
+    #[ocall(id = 250)]
+    fn query_accept() -> Result<Poll<Option<QueryRequest>>>;
+
+    /// Send the reply of an accepted query. The reply is dropped if the contract query has already
+    /// timed out.
+    #[ocall(id = 251)]
+    fn query_reply(reply_id: i32, reply: Vec<u8>) -> Result<()>;
+
+    /// Emit a message to the host, which delivers it to the contract paired with the VM.
+    ///
+    /// A message can be at most 16KB. Fails with `ResourceLimited` if the host is too busy to take
+    /// more messages, in which case the guest can try again later.
+    #[ocall(id = 260)]
+    fn emit_message(message: Vec<u8>) -> Result<()>;
+
+    /// Get the startup config of the VM, an opaque blob given by the host when starting the VM.
+    /// It is empty if the host gives no config.
+    #[ocall(id = 270)]
+    fn vm_config() -> Result<Vec<u8>>;
 }
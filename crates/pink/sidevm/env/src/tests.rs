@@ -176,3 +176,56 @@ fn test_nargs_decode() {
     let _: StackedArgs<()> = stack;
     assert_eq!(c, 1);
 }
+
+/// Polls the future until it is ready, as the host would do after waking up the task.
+fn block_on<F: Future>(fut: F) -> F::Output {
+    fn raw_waker() -> task::RawWaker {
+        task::RawWaker::new(
+            &(),
+            &task::RawWakerVTable::new(|_| raw_waker(), |_| (), |_| (), |_| ()),
+        )
+    }
+    let waker = unsafe { task::Waker::from_raw(raw_waker()) };
+    let mut context = task::Context::from_waker(&waker);
+    let mut fut = Box::pin(fut);
+    for _ in 0..100 {
+        if let task::Poll::Ready(output) = fut.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+    panic!("The future is not ready after 100 polls");
+}
+
+#[test]
+fn test_join_and_select() {
+    let (tx, rx) = channel::bounded(4);
+    let ((), received) = block_on(join(
+        async {
+            tx.send(1).await.unwrap();
+            tx.send(2).await.unwrap();
+        },
+        async { (rx.recv().await, rx.recv().await) },
+    ));
+    assert_eq!(received, (Some(1), Some(2)));
+
+    let pending = poll_fn(|_| task::Poll::<()>::Pending);
+    assert_eq!(block_on(select(pending, async { 1 })), Either::Right(1));
+    assert_eq!(block_on(join_all((0..3).map(|i| async move { i }))), [0, 1, 2]);
+}
+
+#[test]
+fn test_bounded_channel() {
+    let (tx, rx) = channel::bounded(1);
+    assert_eq!(tx.try_send(1), Ok(()));
+    assert_eq!(tx.try_send(2), Err(channel::TrySendError::Full(2)));
+    let tx2 = tx.clone();
+    assert_eq!(block_on(join(tx2.send(2), rx.recv())), (Ok(()), Some(1)));
+    drop((tx, tx2));
+    assert_eq!(rx.try_recv(), task::Poll::Ready(Some(2)));
+    assert_eq!(rx.try_recv(), task::Poll::Ready(None));
+
+    let (tx, rx) = channel::bounded::<u8>(1);
+    drop(rx);
+    assert!(tx.is_closed());
+    assert_eq!(block_on(tx.send(1)), Err(channel::SendError(1)));
+}
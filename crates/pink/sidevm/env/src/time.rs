@@ -0,0 +1,50 @@
+//! Timers and timeouts.
+
+use std::time::Duration;
+
+use super::*;
+use crate::combinators::{select, Either};
+
+/// The future to sleep for a given duration, backed by a host timer.
+pub struct Sleep {
+    timer_id: i32,
+}
+
+/// Sleeps for the given duration, at most `i32::MAX` milliseconds.
+pub fn sleep(duration: Duration) -> Sleep {
+    let timeout = duration.as_millis().min(i32::MAX as u128) as i32;
+    let timer_id = ocall::create_timer(timeout).expect("Failed to create timer");
+    Sleep { timer_id }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut task::Context<'_>) -> task::Poll<()> {
+        match ocall::poll_read(self.timer_id, &mut []).expect("Poll timer failed") {
+            Poll::Ready(_) => task::Poll::Ready(()),
+            Poll::Pending => task::Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        let _ = ocall::close(self.timer_id);
+    }
+}
+
+/// The error returned by `timeout` when the duration elapsed first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display)]
+#[display(fmt = "Deadline has elapsed")]
+pub struct Elapsed;
+
+impl std::error::Error for Elapsed {}
+
+/// Requires the future to complete before the duration has elapsed.
+pub async fn timeout<F: Future>(duration: Duration, fut: F) -> Result<F::Output, Elapsed> {
+    match select(fut, sleep(duration)).await {
+        Either::Left(output) => Ok(output),
+        Either::Right(()) => Err(Elapsed),
+    }
+}
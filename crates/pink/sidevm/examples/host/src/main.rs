@@ -16,7 +16,9 @@ async fn main() -> anyhow::Result<()> {
 
     let wasm_bytes = std::fs::read(args().nth(1).unwrap()).unwrap();
     println!("VM running...");
-    let (_sender, handle) = spawner.start(&wasm_bytes, 100, Default::default()).unwrap();
+    let (_sender, handle) = spawner
+        .start(&wasm_bytes, 100, Default::default(), vec![], vec![])
+        .unwrap();
     handle.await?;
     tokio::time::sleep(std::time::Duration::from_millis(50)).await;
     println!("done");
@@ -1,6 +1,6 @@
 use std::{
     cell::Cell,
-    sync::{Arc, Mutex},
+    sync::{mpsc::SyncSender, Arc, Mutex},
     task::Poll::{Pending, Ready},
     time::Duration,
 };
@@ -11,40 +11,63 @@ use tokio::{
 };
 use wasmer::{imports, Function, ImportObject, Memory, Store, WasmerEnv};
 
-use env::{IntPtr, IntRet, OcallError, Poll, Result, RetEncode};
+use env::{IntPtr, IntRet, OcallError, Poll, QueryRequest, Result, RetEncode};
 use pink_sidevm_env as env;
 use thread_local::ThreadLocal;
 
 use crate::{
     async_context::{get_task_cx, set_task_env},
+    grpc,
     resource::{Resource, ResourceKeeper},
-    VmId,
+    service::{ContractStorage, Report, StorageError},
+    wasi, VmId,
 };
 
+/// The max size of a contract storage key the VM can read.
+const MAX_STORAGE_KEY_SIZE: usize = 128;
+/// The max size of a contract storage value the VM can read.
+const MAX_STORAGE_VALUE_SIZE: usize = 64 * 1024;
+/// The max number of contract queries waiting to be accepted by the VM.
+const QUERY_QUEUE_SIZE: usize = 16;
+/// The resource id of the contract queries.
+const QUERY_RESOURCE_ID: i32 = 1;
+/// The max size of a message emitted by the VM.
+const MAX_EMITTED_MESSAGE_SIZE: usize = 16 * 1024;
+
+/// A contract query waiting to be accepted by the VM.
+pub struct PendingQuery {
+    pub payload: Vec<u8>,
+    pub reply_tx: SyncSender<Vec<u8>>,
+}
+
 // Let the compiler check IntPtr is 32bit sized.
 fn _sizeof_i32_must_eq_to_intptr() {
     let _ = core::mem::transmute::<i32, IntPtr>;
 }
 
-pub fn create_env(id: VmId, store: &Store) -> (Env, ImportObject) {
-    let env = Env::new(id);
-    (
-        env.clone(),
-        imports! {
-            "env" => {
-                "sidevm_ocall" => Function::new_native_with_env(
-                    store,
-                    env.clone(),
-                    sidevm_ocall,
-                ),
-                "sidevm_ocall_fast_return" => Function::new_native_with_env(
-                    store,
-                    env,
-                    sidevm_ocall_fast_return,
-                ),
-            }
-        },
-    )
+pub fn create_env(
+    id: VmId,
+    store: &Store,
+    grpc_targets: Vec<String>,
+    config: Vec<u8>,
+) -> (Env, ImportObject) {
+    let env = Env::new(id, grpc_targets, config);
+    let mut import_object = imports! {
+        "env" => {
+            "sidevm_ocall" => Function::new_native_with_env(
+                store,
+                env.clone(),
+                sidevm_ocall,
+            ),
+            "sidevm_ocall_fast_return" => Function::new_native_with_env(
+                store,
+                env.clone(),
+                sidevm_ocall_fast_return,
+            ),
+        }
+    };
+    import_object.register("wasi_snapshot_preview1", wasi::exports(store, &env));
+    (env, import_object)
 }
 
 pub(crate) struct TaskSet {
@@ -80,8 +103,17 @@ struct State {
     temp_return_value: ThreadLocal<Cell<Option<Vec<u8>>>>,
     ocall_trace_enabled: bool,
     message_tx: Sender<Vec<u8>>,
+    query_tx: Sender<PendingQuery>,
     awake_tasks: Arc<TaskSet>,
     current_task: i32,
+    /// The gRPC targets the VM is allowed to call
+    grpc_targets: Vec<String>,
+    /// The latest snapshot of the paired contract storage
+    contract_storage: Option<Arc<dyn ContractStorage>>,
+    /// The channel to report the messages emitted by the VM to the host
+    report_tx: Option<Sender<Report>>,
+    /// The startup config of the VM
+    config: Vec<u8>,
 }
 
 impl State {
@@ -103,10 +135,12 @@ pub struct Env {
 }
 
 impl Env {
-    fn new(id: VmId) -> Self {
+    fn new(id: VmId, grpc_targets: Vec<String>, config: Vec<u8>) -> Self {
         let (message_tx, message_rx) = tokio::sync::mpsc::channel(100);
+        let (query_tx, query_rx) = tokio::sync::mpsc::channel(QUERY_QUEUE_SIZE);
         let mut resources = ResourceKeeper::default();
         let _ = resources.push(Resource::ChannelRx(message_rx));
+        let _ = resources.push(Resource::QueryRx(query_rx));
         Self {
             inner: Arc::new(Mutex::new(EnvInner {
                 memory: VmMemory(None),
@@ -116,8 +150,13 @@ impl Env {
                     temp_return_value: Default::default(),
                     ocall_trace_enabled: false,
                     message_tx,
+                    query_tx,
                     awake_tasks: Arc::new(TaskSet::with_task0()),
                     current_task: 0,
+                    grpc_targets,
+                    contract_storage: None,
+                    report_tx: None,
+                    config,
                 },
             })),
         }
@@ -127,6 +166,25 @@ impl Env {
         self.inner.lock().unwrap().memory.0 = Some(memory);
     }
 
+    /// The current size of the VM memory in bytes.
+    pub fn memory_size(&self) -> u64 {
+        match &self.inner.lock().unwrap().memory.0 {
+            Some(memory) => memory.size().bytes().0 as u64,
+            None => 0,
+        }
+    }
+
+    /// Runs `f` with the VM memory.
+    pub(crate) fn with_memory<T>(&self, f: impl FnOnce(&dyn env::VmMemory) -> T) -> T {
+        f(&self.inner.lock().unwrap().memory)
+    }
+
+    /// Writes a message to the log on behalf of the VM.
+    pub(crate) fn log(&self, level: log::Level, message: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        let _ = env::OcallFuncs::log(&mut inner.state, level, message);
+    }
+
     pub fn cleanup(&self) {
         // Cut up the reference cycle to avoid leaks.
         self.inner.lock().unwrap().memory.0 = None;
@@ -138,6 +196,21 @@ impl Env {
         tx.send(message).await
     }
 
+    /// The sender of the contract queries to the VM.
+    pub fn query_sender(&self) -> Sender<PendingQuery> {
+        self.inner.lock().unwrap().state.query_tx.clone()
+    }
+
+    /// Replace the snapshot of the paired contract storage readable by the VM.
+    pub fn set_contract_storage(&self, storage: Arc<dyn ContractStorage>) {
+        self.inner.lock().unwrap().state.contract_storage = Some(storage);
+    }
+
+    /// Set the channel to report the messages emitted by the VM to the host.
+    pub(crate) fn set_report_tx(&self, report_tx: Sender<Report>) {
+        self.inner.lock().unwrap().state.report_tx = Some(report_tx);
+    }
+
     /// The blocking version of `push_message`.
     #[allow(dead_code)]
     pub fn blocking_push_message(&self, message: Vec<u8>) -> Result<(), SendError<Vec<u8>>> {
@@ -275,6 +348,73 @@ impl env::OcallFuncs for State {
         log::log!(target: "sidevm", level, "[vm:{vm_id:<8}][{task:<3}] {message}");
         Ok(())
     }
+
+    fn grpc_call(&mut self, target: String, path: String, request: Vec<u8>) -> Result<i32> {
+        let call = grpc::start_call(&self.grpc_targets, &target, &path, request)?;
+        self.resources.push(Resource::GrpcCall(Some(call)))
+    }
+
+    fn contract_storage_get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        if key.len() > MAX_STORAGE_KEY_SIZE {
+            return Err(OcallError::InvalidParameter);
+        }
+        let storage = self.contract_storage.as_ref().ok_or(OcallError::NotFound)?;
+        let value = storage.get(&key).map_err(|err| match err {
+            StorageError::NotAvailable => OcallError::NotFound,
+            StorageError::InvalidKey => OcallError::InvalidParameter,
+        })?;
+        match value {
+            Some(value) if value.len() > MAX_STORAGE_VALUE_SIZE => Err(OcallError::ResourceLimited),
+            value => Ok(value),
+        }
+    }
+
+    fn query_accept(&mut self) -> Result<Poll<Option<QueryRequest>>> {
+        let query = {
+            let rx = match self.resources.get_mut(QUERY_RESOURCE_ID)? {
+                Resource::QueryRx(rx) => rx,
+                _ => return Err(OcallError::UnsupportedOperation),
+            };
+            match get_task_cx(|cx| rx.poll_recv(cx)) {
+                Pending => return Ok(Poll::Pending),
+                Ready(None) => return Ok(Poll::Ready(None)),
+                Ready(Some(query)) => query,
+            }
+        };
+        let reply_id = self.resources.push(Resource::QueryReply(query.reply_tx))?;
+        Ok(Poll::Ready(Some(QueryRequest {
+            reply_id,
+            payload: query.payload,
+        })))
+    }
+
+    fn query_reply(&mut self, reply_id: i32, reply: Vec<u8>) -> Result<()> {
+        match self.resources.get_mut(reply_id)? {
+            Resource::QueryReply(reply_tx) => {
+                // The contract query might have timed out.
+                let _ = reply_tx.try_send(reply);
+                Ok(())
+            }
+            _ => Err(OcallError::UnsupportedOperation),
+        }
+    }
+
+    fn emit_message(&mut self, message: Vec<u8>) -> Result<()> {
+        if message.len() > MAX_EMITTED_MESSAGE_SIZE {
+            return Err(OcallError::InvalidParameter);
+        }
+        let report_tx = self.report_tx.as_ref().ok_or(OcallError::UnsupportedOperation)?;
+        report_tx
+            .try_send(Report::VmMessage {
+                id: self.id,
+                message,
+            })
+            .or(Err(OcallError::ResourceLimited))
+    }
+
+    fn vm_config(&mut self) -> Result<Vec<u8>> {
+        Ok(self.config.clone())
+    }
 }
 
 fn sidevm_ocall_fast_return(
@@ -331,3 +471,24 @@ fn sidevm_ocall(
     }
     result.encode_ret()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use env::OcallEnv as _;
+    use wasmer::Universal;
+    use wasmer_compiler_singlepass::Singlepass;
+
+    #[test]
+    fn vm_config_returns_the_startup_config() {
+        let store = Store::new(&Universal::new(Singlepass::default()).engine());
+        let (env, _) = create_env([0; 32], &store, vec![], b"config".to_vec());
+        let mut inner = env.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        assert_eq!(env::ocall_id2name(270), "vm_config");
+        let len = env::dispatch_call(&mut inner.state, &inner.memory, 270, 0, 0, 0, 0).unwrap();
+        assert_eq!(len, 6);
+        assert_eq!(inner.state.take_return(), Some(b"config".to_vec()));
+    }
+}
@@ -0,0 +1,155 @@
+//! Unary gRPC calls made on behalf of the guests.
+//!
+//! The guest only deals with the protobuf encoded messages. The host opens the HTTP/2 connection,
+//! over TLS for the `https` targets or in prior knowledge mode for the `http` targets, and wraps
+//! the messages in the gRPC length-prefixed frames.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use hyper::client::HttpConnector;
+use hyper::{body::HttpBody as _, Body, Client, Request, Uri};
+use hyper_rustls::HttpsConnector;
+use once_cell::sync::Lazy;
+use pink_sidevm_env::{GrpcResponse, OcallError, Result};
+
+/// The max size of a response message.
+const MAX_RESPONSE_SIZE: usize = 1024 * 1024 * 4;
+
+/// A call is aborted if the response is not received in time.
+const CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub type GrpcCall = Pin<Box<dyn Future<Output = Result<GrpcResponse>> + Send>>;
+
+/// The connections are pooled and shared by all the VMs.
+static CLIENT: Lazy<Client<HttpsConnector<HttpConnector>>> = Lazy::new(|| {
+    Client::builder()
+        .http2_only(true)
+        .build(HttpsConnector::with_webpki_roots())
+});
+
+/// Starts a call to `target`, which must be in the allowlist.
+pub(crate) fn start_call(
+    allowlist: &[String],
+    target: &str,
+    path: &str,
+    request: Vec<u8>,
+) -> Result<GrpcCall> {
+    let target = target.trim_end_matches('/');
+    if !allowlist.iter().any(|allowed| allowed == target) {
+        return Err(OcallError::PermissionDenied);
+    }
+    if !path.starts_with('/') {
+        return Err(OcallError::InvalidParameter);
+    }
+    let uri: Uri = format!("{}{}", target, path)
+        .parse()
+        .or(Err(OcallError::InvalidParameter))?;
+    if !matches!(uri.scheme_str(), Some("http" | "https")) {
+        return Err(OcallError::InvalidParameter);
+    }
+    let request = Request::post(uri)
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(Body::from(encode_frame(&request)))
+        .or(Err(OcallError::InvalidParameter))?;
+    Ok(Box::pin(async move {
+        tokio::time::timeout(CALL_TIMEOUT, call(request))
+            .await
+            .or(Err(OcallError::IoError))?
+    }))
+}
+
+async fn call(request: Request<Body>) -> Result<GrpcResponse> {
+    let response = CLIENT.request(request).await.or(Err(OcallError::IoError))?;
+    if !response.status().is_success() {
+        return Err(OcallError::IoError);
+    }
+    // A failed call may have a trailers-only response, with the status in the headers.
+    let mut status = header_status(response.headers());
+    let mut body = response.into_body();
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.or(Err(OcallError::IoError))?;
+        if buf.len() + chunk.len() > MAX_RESPONSE_SIZE {
+            return Err(OcallError::ResourceLimited);
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    if let Some(trailers) = body.trailers().await.or(Err(OcallError::IoError))? {
+        status = status.or_else(|| header_status(&trailers));
+    }
+    let (status, message) = status.ok_or(OcallError::InvalidEncoding)?;
+    let body = if status == 0 {
+        decode_frame(&buf)?
+    } else {
+        Vec::new()
+    };
+    Ok(GrpcResponse {
+        status,
+        message,
+        body,
+    })
+}
+
+fn header_status(headers: &hyper::HeaderMap) -> Option<(u32, String)> {
+    let status = headers.get("grpc-status")?.to_str().ok()?.parse().ok()?;
+    let message = headers
+        .get("grpc-message")
+        .and_then(|message| message.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    Some((status, message))
+}
+
+/// Wraps a message in an uncompressed gRPC frame.
+fn encode_frame(message: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(message.len() + 5);
+    frame.push(0);
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+    frame
+}
+
+/// Unwraps the message of a unary response. Compressed messages are not supported since no
+/// `grpc-accept-encoding` is sent.
+fn decode_frame(frame: &[u8]) -> Result<Vec<u8>> {
+    if frame.len() < 5 || frame[0] != 0 {
+        return Err(OcallError::InvalidEncoding);
+    }
+    let len = u32::from_be_bytes([frame[1], frame[2], frame[3], frame[4]]) as usize;
+    if frame.len() != len + 5 {
+        return Err(OcallError::InvalidEncoding);
+    }
+    Ok(frame[5..].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip() {
+        let frame = encode_frame(b"hello");
+        assert_eq!(frame, b"\0\0\0\0\x05hello");
+        assert_eq!(decode_frame(&frame).unwrap(), b"hello");
+        assert!(decode_frame(&frame[..6]).is_err());
+        assert!(decode_frame(b"\x01\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn only_allowed_targets_can_be_called() {
+        let allowlist = vec!["https://grpc.example.com:443".to_string()];
+        let call = |target: &str, path: &str| start_call(&allowlist, target, path, vec![]).err();
+        assert!(matches!(
+            call("https://other.example.com:443", "/a.B/C"),
+            Some(OcallError::PermissionDenied)
+        ));
+        assert!(matches!(
+            call("https://grpc.example.com:443", "a.B/C"),
+            Some(OcallError::InvalidParameter)
+        ));
+        assert!(call("https://grpc.example.com:443/", "/a.B/C").is_none());
+    }
+}
@@ -1,9 +1,11 @@
 
 mod async_context;
 mod env;
+mod grpc;
 mod resource;
 mod run;
 pub mod service;
+mod wasi;
 
 pub type VmId = [u8; 32];
 pub use run::WasmRun;
@@ -1,9 +1,15 @@
 use pink_sidevm_env::{OcallError, Poll, Result};
-use std::{io::ErrorKind, net::SocketAddr, pin::Pin, task::Poll::*};
+use scale::Encode;
+use std::{
+    future::Future, io::ErrorKind, net::SocketAddr, pin::Pin, sync::mpsc::SyncSender,
+    task::Poll::*,
+};
 use tokio::{io::AsyncWrite as _, net, sync::mpsc::Receiver, time::Sleep};
 use Resource::*;
 
 use crate::async_context::get_task_cx;
+use crate::env::PendingQuery;
+use crate::grpc;
 
 pub enum Resource {
     Sleep(Pin<Box<Sleep>>),
@@ -13,6 +19,12 @@ pub enum Resource {
         stream: net::TcpStream,
         remote_addr: SocketAddr,
     },
+    /// A gRPC call in flight, or `None` once the response is taken.
+    GrpcCall(Option<grpc::GrpcCall>),
+    /// The queries from the paired pink contract.
+    QueryRx(Receiver<PendingQuery>),
+    /// The reply channel of an accepted query.
+    QueryReply(SyncSender<Vec<u8>>),
 }
 
 impl Resource {
@@ -29,6 +41,16 @@ impl Resource {
                 futures::pin_mut!(fut);
                 Ok(poll_in_task_cx(fut).into())
             }
+            GrpcCall(call) => {
+                let fut = call.as_mut().ok_or(OcallError::NotFound)?;
+                match get_task_cx(|cx| fut.as_mut().poll(cx)) {
+                    Ready(result) => {
+                        *call = None;
+                        Ok(Poll::Ready(Some(result?.encode())))
+                    }
+                    Pending => Ok(Poll::Pending),
+                }
+            }
             _ => Err(OcallError::UnsupportedOperation),
         }
     }
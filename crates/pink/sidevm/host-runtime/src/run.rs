@@ -20,7 +20,13 @@ impl Drop for WasmRun {
 }
 
 impl WasmRun {
-    pub fn run(code: &[u8], max_pages: u32, id: crate::VmId) -> Result<(WasmRun, env::Env)> {
+    pub fn run(
+        code: &[u8],
+        max_pages: u32,
+        id: crate::VmId,
+        grpc_targets: Vec<String>,
+        config: Vec<u8>,
+    ) -> Result<(WasmRun, env::Env)> {
         let compiler = Singlepass::default();
         let engine = Universal::new(compiler).engine();
         let base = BaseTunables {
@@ -31,13 +37,17 @@ impl WasmRun {
         let tunables = LimitingTunables::new(base, Pages(max_pages));
         let store = Store::new_with_tunables(&engine, tunables);
         let module = Module::new(&store, code)?;
-        let (env, import_object) = env::create_env(id, &store);
+        let (env, import_object) = env::create_env(id, &store, grpc_targets, config);
         let instance = Instance::new(&module, &import_object)?;
         let memory = instance
             .exports
             .get_memory("memory")
             .context("No memory exported")?;
         env.set_memory(memory.clone());
+        // The guests built as WASI reactors need to be initialized before calling any export.
+        if let Ok(initialize) = instance.exports.get_native_function::<(), ()>("_initialize") {
+            initialize.call()?;
+        }
         Ok((
             WasmRun {
                 env: env.clone(),
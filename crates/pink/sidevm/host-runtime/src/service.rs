@@ -1,18 +1,27 @@
+use crate::env::{Env, PendingQuery};
 use crate::run::WasmRun;
 use crate::VmId;
 use anyhow::{Context as _, Result};
 use log::{debug, error, info, warn};
+use std::collections::HashMap;
 use std::future::Future;
+use std::sync::{mpsc::RecvTimeoutError, Arc, Mutex};
+use std::time::Duration;
 use tokio::{
-    sync::mpsc::{channel, Receiver, Sender},
+    sync::mpsc::{channel, error::TrySendError, Receiver, Sender},
     task::JoinHandle,
 };
 
 pub type CommandSender = Sender<Command>;
 
+/// The max size of the startup config of a VM.
+pub const MAX_CONFIG_SIZE: usize = 64 * 1024;
+
 #[derive(Debug)]
 pub enum Report {
     VmTerminated { id: VmId, reason: ExitReason },
+    /// A message emitted by the VM with the ocall `emit_message`.
+    VmMessage { id: VmId, message: Vec<u8> },
 }
 
 #[derive(Debug)]
@@ -24,11 +33,79 @@ pub enum ExitReason {
     Cancelled,
 }
 
+/// The state of a VM instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmState {
+    Running,
+    /// Exited by itself or stopped by the host.
+    Stopped,
+    /// Trapped, or its task panicked or was cancelled.
+    Crashed,
+}
+
+impl From<&ExitReason> for VmState {
+    fn from(reason: &ExitReason) -> Self {
+        match reason {
+            ExitReason::Exited(_) | ExitReason::Stopped | ExitReason::InputClosed => {
+                VmState::Stopped
+            }
+            ExitReason::Panicked | ExitReason::Cancelled => VmState::Crashed,
+        }
+    }
+}
+
+/// The status of a VM instance kept by the service.
+#[derive(Debug, Clone)]
+pub struct VmStatus {
+    pub id: VmId,
+    pub state: VmState,
+    /// The max number of memory pages the instance can grow to.
+    pub max_memory_pages: u32,
+    /// The current size of the instance memory in bytes, 0 once it is terminated.
+    pub memory_size: u64,
+    /// The number of times an instance was started again with the same id.
+    pub restart_count: u32,
+}
+
+struct Instance {
+    /// The environment of the instance, dropped once it is terminated.
+    env: Option<Env>,
+    status: VmStatus,
+}
+
 pub enum Command {
     // Stop the side VM instance.
     Stop,
     // Send a sidevm message to the instance.
     PushMessage(Vec<u8>),
+    // Replace the snapshot of the paired contract storage readable by the instance.
+    UpdateContractStorage(Arc<dyn ContractStorage>),
+}
+
+/// Read-only access to a snapshot of the storage of the pink contract paired with a VM.
+pub trait ContractStorage: Send + Sync {
+    /// Reads a raw storage item of the contract.
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError>;
+}
+
+/// The error of `ContractStorage::get`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageError {
+    /// No snapshot of the storage is available yet.
+    NotAvailable,
+    /// The key is not a storage key of the contract.
+    InvalidKey,
+}
+
+/// The error of `Spawner::query`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryError {
+    /// No VM is running with the given id, or the VM dropped the query without replying.
+    NotRunning,
+    /// Too many queries are waiting to be accepted by the VM.
+    Busy,
+    /// The VM did not reply in time.
+    Timeout,
 }
 
 pub struct ServiceRun {
@@ -36,9 +113,12 @@ pub struct ServiceRun {
     report_rx: Receiver<Report>,
 }
 
+#[derive(Clone)]
 pub struct Spawner {
     runtime_handle: tokio::runtime::Handle,
     report_tx: Sender<Report>,
+    /// The bookkeeping of all the VMs ever started, including the terminated ones.
+    instances: Arc<Mutex<HashMap<VmId, Instance>>>,
 }
 
 pub fn service() -> (ServiceRun, Spawner) {
@@ -53,6 +133,7 @@ pub fn service() -> (ServiceRun, Spawner) {
     let spawner = Spawner {
         runtime_handle,
         report_tx,
+        instances: Default::default(),
     };
     (run, spawner)
 }
@@ -76,15 +157,47 @@ impl ServiceRun {
 }
 
 impl Spawner {
+    /// Starts a VM instance. The VM can only make gRPC calls to the `grpc_targets`, each in the
+    /// form of `https://host:port`.
+    ///
+    /// The `config` is an opaque blob of at most `MAX_CONFIG_SIZE` bytes, which the guest reads
+    /// with the ocall `vm_config`, e.g. to get the endpoints or the intervals it works with.
     pub fn start(
         &self,
         wasm_bytes: &[u8],
         memory_pages: u32,
         id: VmId,
+        grpc_targets: Vec<String>,
+        config: Vec<u8>,
     ) -> Result<(CommandSender, JoinHandle<()>)> {
+        if config.len() > MAX_CONFIG_SIZE {
+            anyhow::bail!("The sidevm config is too large: {} bytes", config.len());
+        }
         let (cmd_tx, mut cmd_rx) = channel(100);
-        let (mut wasm_run, env) = WasmRun::run(wasm_bytes, memory_pages, id)
+        let (mut wasm_run, env) = WasmRun::run(wasm_bytes, memory_pages, id, grpc_targets, config)
             .context("Failed to create sidevm instance")?;
+        env.set_report_tx(self.report_tx.clone());
+        {
+            let mut instances = self.instances.lock().unwrap();
+            let restart_count = match instances.get(&id) {
+                Some(instance) => instance.status.restart_count + 1,
+                None => 0,
+            };
+            let status = VmStatus {
+                id,
+                state: VmState::Running,
+                max_memory_pages: memory_pages,
+                memory_size: 0,
+                restart_count,
+            };
+            instances.insert(
+                id,
+                Instance {
+                    env: Some(env.clone()),
+                    status,
+                },
+            );
+        }
         let handle = self.runtime_handle.spawn(async move {
             loop {
                 tokio::select! {
@@ -98,6 +211,9 @@ impl Spawner {
                                 info!(target: "sidevm", "Received stop command. Exiting...");
                                 break ExitReason::Stopped;
                             }
+                            Some(Command::UpdateContractStorage(storage)) => {
+                                env.set_contract_storage(storage);
+                            }
                             Some(Command::PushMessage(msg)) => {
                                 debug!(target: "sidevm", "Sending message to sidevm.");
                                 match env.push_message(msg).await {
@@ -127,6 +243,7 @@ impl Spawner {
             }
         });
         let report_tx = self.report_tx.clone();
+        let instances = self.instances.clone();
         let handle = self.runtime_handle.spawn(async move {
             let reason = match handle.await {
                 Ok(r) => r,
@@ -139,6 +256,10 @@ impl Spawner {
                     }
                 }
             };
+            if let Some(instance) = instances.lock().unwrap().get_mut(&id) {
+                instance.env = None;
+                instance.status.state = VmState::from(&reason);
+            }
             if let Err(err) = report_tx.send(Report::VmTerminated { id, reason }).await {
                 warn!(target: "sidevm", "Failed to send report to sidevm service: {}", err);
             }
@@ -146,6 +267,51 @@ impl Spawner {
         Ok((cmd_tx, handle))
     }
 
+    /// Sends a query to the VM and waits for its reply until the timeout.
+    ///
+    /// This blocks the current thread, so it must not be called in the async context.
+    pub fn query(
+        &self,
+        id: &VmId,
+        payload: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, QueryError> {
+        let (reply_tx, reply_rx) = std::sync::mpsc::sync_channel(1);
+        let tx = self
+            .instances
+            .lock()
+            .unwrap()
+            .get(id)
+            .and_then(|instance| instance.env.as_ref())
+            .map(|env| env.query_sender())
+            .ok_or(QueryError::NotRunning)?;
+        match tx.try_send(PendingQuery { payload, reply_tx }) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => return Err(QueryError::Busy),
+            Err(TrySendError::Closed(_)) => return Err(QueryError::NotRunning),
+        }
+        reply_rx.recv_timeout(timeout).map_err(|err| match err {
+            RecvTimeoutError::Timeout => QueryError::Timeout,
+            RecvTimeoutError::Disconnected => QueryError::NotRunning,
+        })
+    }
+
+    /// Returns the status of all the VMs ever started by the service.
+    pub fn instances(&self) -> Vec<VmStatus> {
+        self.instances
+            .lock()
+            .unwrap()
+            .values()
+            .map(|instance| {
+                let mut status = instance.status.clone();
+                if let Some(env) = &instance.env {
+                    status.memory_size = env.memory_size();
+                }
+                status
+            })
+            .collect()
+    }
+
     pub fn spawn(&self, fut: impl Future<Output = ()> + Send + 'static) -> JoinHandle<()> {
         self.runtime_handle.spawn(fut)
     }
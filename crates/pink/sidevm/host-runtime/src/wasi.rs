@@ -0,0 +1,183 @@
+//! A curated subset of WASI preview1, so the crates written for the `wasm32-wasi` target can be
+//! used in the guests.
+//!
+//! Only the calls without access to the host resources are provided: the clocks, random numbers,
+//! empty args and environment variables, and the stdout/stderr which are written to the log. The
+//! other calls are not resolved, so a guest importing them fails to instantiate.
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use pink_sidevm_env::{IntPtr, OcallError};
+use rand::RngCore;
+use wasmer::{Exports, Function, RuntimeError, Store};
+
+use crate::env::Env;
+
+const ERRNO_SUCCESS: i32 = 0;
+const ERRNO_BADF: i32 = 8;
+const ERRNO_FAULT: i32 = 21;
+const ERRNO_INVAL: i32 = 28;
+
+const CLOCK_REALTIME: i32 = 0;
+const CLOCK_MONOTONIC: i32 = 1;
+
+const FD_STDOUT: i32 = 1;
+const FD_STDERR: i32 = 2;
+
+/// The size of a `ciovec`, a pair of u32 pointer and length.
+const CIOVEC_SIZE: i32 = 8;
+
+/// The reference point of the monotonic clock.
+static START_TIME: Lazy<Instant> = Lazy::new(Instant::now);
+
+pub(crate) fn exports(store: &Store, env: &Env) -> Exports {
+    let mut exports = Exports::new();
+    macro_rules! with_env {
+        ($f: expr) => {
+            Function::new_native_with_env(store, env.clone(), $f)
+        };
+    }
+    exports.insert("args_get", with_env!(args_get));
+    exports.insert("args_sizes_get", with_env!(args_sizes_get));
+    exports.insert("environ_get", with_env!(args_get));
+    exports.insert("environ_sizes_get", with_env!(args_sizes_get));
+    exports.insert("clock_res_get", with_env!(clock_res_get));
+    exports.insert("clock_time_get", with_env!(clock_time_get));
+    exports.insert("random_get", with_env!(random_get));
+    exports.insert("fd_write", with_env!(fd_write));
+    exports.insert("sched_yield", Function::new_native(store, sched_yield));
+    exports.insert("proc_exit", Function::new_native(store, proc_exit));
+    exports
+}
+
+fn errno(result: Result<(), OcallError>) -> i32 {
+    match result {
+        Ok(()) => ERRNO_SUCCESS,
+        Err(_) => ERRNO_FAULT,
+    }
+}
+
+/// Neither args nor environment variables are passed to the guests, so there is nothing to copy.
+fn args_get(_env: &Env, _ptrs: IntPtr, _buf: IntPtr) -> i32 {
+    ERRNO_SUCCESS
+}
+
+fn args_sizes_get(env: &Env, count: IntPtr, buf_size: IntPtr) -> i32 {
+    errno(env.with_memory(|memory| {
+        memory.copy_to_vm(&0u32.to_le_bytes(), count)?;
+        memory.copy_to_vm(&0u32.to_le_bytes(), buf_size)
+    }))
+}
+
+fn clock_res_get(env: &Env, clock_id: i32, resolution: IntPtr) -> i32 {
+    if !matches!(clock_id, CLOCK_REALTIME | CLOCK_MONOTONIC) {
+        return ERRNO_INVAL;
+    }
+    errno(env.with_memory(|memory| memory.copy_to_vm(&1u64.to_le_bytes(), resolution)))
+}
+
+fn clock_time_get(env: &Env, clock_id: i32, _precision: i64, time: IntPtr) -> i32 {
+    let now = match clock_id {
+        CLOCK_REALTIME => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default(),
+        CLOCK_MONOTONIC => START_TIME.elapsed(),
+        _ => return ERRNO_INVAL,
+    };
+    let nanos = now.as_nanos() as u64;
+    errno(env.with_memory(|memory| memory.copy_to_vm(&nanos.to_le_bytes(), time)))
+}
+
+fn random_get(env: &Env, buf: IntPtr, len: IntPtr) -> i32 {
+    errno(env.with_memory(|memory| {
+        rand::thread_rng().fill_bytes(memory.slice_from_vm_mut(buf, len)?);
+        Ok(())
+    }))
+}
+
+/// Writes the stdout of the guest to the log at the info level, and the stderr at the warn level.
+fn fd_write(env: &Env, fd: i32, iovs: IntPtr, iovs_len: IntPtr, written: IntPtr) -> i32 {
+    let level = match fd {
+        FD_STDOUT => log::Level::Info,
+        FD_STDERR => log::Level::Warn,
+        _ => return ERRNO_BADF,
+    };
+    let data = env.with_memory(|memory| {
+        let iovs_size = iovs_len
+            .checked_mul(CIOVEC_SIZE)
+            .ok_or(OcallError::InvalidAddress)?;
+        let mut data = Vec::new();
+        for iov in memory.slice_from_vm(iovs, iovs_size)?.chunks_exact(CIOVEC_SIZE as usize) {
+            let ptr = u32::from_le_bytes(iov[..4].try_into().expect("Slice of 4 bytes"));
+            let len = u32::from_le_bytes(iov[4..].try_into().expect("Slice of 4 bytes"));
+            data.extend_from_slice(memory.slice_from_vm(ptr as _, len as _)?);
+        }
+        memory.copy_to_vm(&(data.len() as u32).to_le_bytes(), written)?;
+        Ok(data)
+    });
+    match data {
+        Ok(data) => {
+            let message = String::from_utf8_lossy(&data);
+            let message = message.trim_end();
+            if !message.is_empty() {
+                env.log(level, message);
+            }
+            ERRNO_SUCCESS
+        }
+        Err(_) => ERRNO_FAULT,
+    }
+}
+
+fn sched_yield() -> i32 {
+    ERRNO_SUCCESS
+}
+
+/// Terminates the VM. The guests are not expected to exit by themselves, so it is a trap.
+fn proc_exit(code: i32) -> Result<(), RuntimeError> {
+    Err(RuntimeError::new(format!("Guest exited with code {}", code)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::create_env;
+    use wasmer::{Instance, Module, Universal};
+    use wasmer_compiler_singlepass::Singlepass;
+
+    const WAT: &str = r#"(module
+        (import "wasi_snapshot_preview1" "clock_time_get"
+            (func $clock_time_get (param i32 i64 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "random_get"
+            (func $random_get (param i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (func (export "now") (param $clock i32) (result i32)
+            (call $clock_time_get (local.get $clock) (i64.const 0) (i32.const 0)))
+        (func (export "random") (param $len i32) (result i32)
+            (call $random_get (i32.const 8) (local.get $len))))"#;
+
+    #[test]
+    fn clocks_and_random_work() {
+        let store = Store::new(&Universal::new(Singlepass::default()).engine());
+        let module = Module::new(&store, WAT).unwrap();
+        let (env, import_object) = create_env([0; 32], &store, vec![], vec![]);
+        let instance = Instance::new(&module, &import_object).unwrap();
+        env.set_memory(instance.exports.get_memory("memory").unwrap().clone());
+
+        let now = instance
+            .exports
+            .get_native_function::<i32, i32>("now")
+            .unwrap();
+        assert_eq!(now.call(CLOCK_REALTIME).unwrap(), ERRNO_SUCCESS);
+        let time = env.with_memory(|memory| memory.slice_from_vm(0, 8).unwrap().to_vec());
+        assert_ne!(time, [0; 8]);
+        assert_eq!(now.call(5).unwrap(), ERRNO_INVAL);
+
+        let random = instance
+            .exports
+            .get_native_function::<i32, i32>("random")
+            .unwrap();
+        assert_eq!(random.call(32).unwrap(), ERRNO_SUCCESS);
+        assert_eq!(random.call(0x10000).unwrap(), ERRNO_FAULT);
+    }
+}
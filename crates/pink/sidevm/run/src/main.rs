@@ -0,0 +1,234 @@
+//! Runs a sidevm program locally, the same way a worker runs it, so that the guest can be developed
+//! and debugged without deploying it to a worker.
+//!
+//! The messages to the program can be injected from stdin (one message per line) or with HTTP
+//! POST requests (one message per request body):
+//!
+//! ```text
+//! sidevm-run --stdin --http 127.0.0.1:8100 --block-interval 6000 program.wasm
+//! curl -d 'hello' http://127.0.0.1:8100/
+//! ```
+//!
+//! The guest logs are printed under the `sidevm` target.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{AppSettings, Parser};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use log::{error, info, warn};
+use parity_scale_codec::Encode;
+use pink_sidevm_host_runtime::service::{service, Command, CommandSender, ExitReason, Report};
+use pink_sidevm_host_runtime::VmId;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// The max code size accepted by the workers.
+const MAX_CODE_SIZE: usize = 1024 * 1024 * 2;
+
+#[derive(Parser, Debug)]
+#[clap(about = "Run a sidevm program locally.", version, author)]
+#[clap(global_setting(AppSettings::DeriveDisplayOrder))]
+struct Args {
+    /// The wasm file of the program
+    program: PathBuf,
+
+    #[clap(long, default_value = "256", help = "The memory limit in 64KB wasm pages.")]
+    memory_pages: u32,
+
+    #[clap(
+        long = "grpc-target",
+        help = "Allow the program to make gRPC calls to the target, e.g. https://host:443."
+    )]
+    grpc_targets: Vec<String>,
+
+    #[clap(long, help = "Pass the content of the file to the program as its startup config.")]
+    config: Option<PathBuf>,
+
+    #[clap(long, help = "The hex encoded 32 bytes id of the VM, default to all zeros.")]
+    vm_id: Option<String>,
+
+    #[clap(long, help = "Push each line read from stdin as a message.")]
+    stdin: bool,
+
+    #[clap(long, help = "Decode the stdin lines as hex before pushing them.")]
+    hex: bool,
+
+    #[clap(long, help = "Push the body of each HTTP POST request to this address as a message.")]
+    http: Option<SocketAddr>,
+
+    #[clap(
+        long,
+        help = "Push a fake block every given milliseconds, as the SCALE encoded \
+                (block_number: u32, timestamp_ms: u64)."
+    )]
+    block_interval: Option<u64>,
+
+    #[clap(long, default_value = "1", help = "The number of the first fake block.")]
+    start_block: u32,
+
+    #[clap(long, default_value = "info", help = "Log filter passed to env_logger.")]
+    log_filter: String,
+}
+
+fn vm_id(args: &Args) -> Result<VmId> {
+    let id = match &args.vm_id {
+        Some(id) => id,
+        None => return Ok(Default::default()),
+    };
+    hex::decode(id.trim_start_matches("0x"))
+        .context("Invalid hex VM id")?
+        .try_into()
+        .map_err(|_| anyhow!("The VM id must be 32 bytes"))
+}
+
+async fn push(tx: &CommandSender, message: Vec<u8>) -> bool {
+    if tx.send(Command::PushMessage(message)).await.is_err() {
+        warn!("Push message failed, the VM is stopped");
+        return false;
+    }
+    true
+}
+
+async fn inject_stdin(tx: CommandSender, hex: bool) -> Result<()> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        let message = if hex {
+            match hex::decode(line.trim().trim_start_matches("0x")) {
+                Ok(message) => message,
+                Err(err) => {
+                    error!("Invalid hex message: {}", err);
+                    continue;
+                }
+            }
+        } else {
+            line.into_bytes()
+        };
+        if !push(&tx, message).await {
+            break;
+        }
+    }
+    info!("Stdin closed");
+    Ok(())
+}
+
+async fn inject_http(tx: CommandSender, addr: SocketAddr) -> Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let tx = tx.clone();
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |request: Request<Body>| {
+                let tx = tx.clone();
+                async move {
+                    let body = hyper::body::to_bytes(request.into_body()).await?;
+                    let status = if push(&tx, body.to_vec()).await {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    };
+                    Ok::<_, hyper::Error>(
+                        Response::builder()
+                            .status(status)
+                            .body(Body::empty())
+                            .expect("Valid response"),
+                    )
+                }
+            }))
+        }
+    });
+    info!("Accepting messages at http://{}", addr);
+    Server::try_bind(&addr)?.serve(make_service).await?;
+    Ok(())
+}
+
+async fn block_clock(tx: CommandSender, interval: Duration, mut block_number: u32) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        if !push(&tx, (block_number, now_ms).encode()).await {
+            break;
+        }
+        block_number += 1;
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let env = env_logger::Env::default().default_filter_or(&args.log_filter);
+    env_logger::Builder::from_env(env).init();
+
+    let code = std::fs::read(&args.program).context("Failed to read the program")?;
+    if code.len() > MAX_CODE_SIZE {
+        warn!(
+            "The program is {} bytes, larger than the {} bytes accepted by the workers",
+            code.len(),
+            MAX_CODE_SIZE
+        );
+    }
+    let vm_id = vm_id(&args)?;
+    let config = match &args.config {
+        Some(path) => std::fs::read(path).context("Failed to read the config")?,
+        None => vec![],
+    };
+
+    let (run, spawner) = service();
+    std::thread::spawn(move || {
+        run.blocking_run(|report| match report {
+            Report::VmMessage { message, .. } => {
+                info!("The VM emitted a message: 0x{}", hex::encode(message));
+            }
+            Report::VmTerminated { reason, .. } => {
+                info!("The VM terminated: {:?}", reason);
+                std::process::exit(match reason {
+                    ExitReason::Exited(code) => code,
+                    _ => 1,
+                });
+            }
+        });
+    });
+
+    let (tx, _handle) = spawner.start(
+        &code,
+        args.memory_pages,
+        vm_id,
+        args.grpc_targets.clone(),
+        config,
+    )?;
+    info!("VM started");
+
+    if args.stdin {
+        let tx = tx.clone();
+        let hex = args.hex;
+        tokio::spawn(async move {
+            if let Err(err) = inject_stdin(tx, hex).await {
+                error!("Read stdin failed: {:?}", err);
+            }
+        });
+    }
+    if let Some(addr) = args.http {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            if let Err(err) = inject_http(tx, addr).await {
+                error!("HTTP server failed: {:?}", err);
+            }
+        });
+    }
+    if let Some(interval) = args.block_interval {
+        tokio::spawn(block_clock(
+            tx.clone(),
+            Duration::from_millis(interval),
+            args.start_block,
+        ));
+    }
+
+    // Keep the command channel open until the VM exits, which ends the process.
+    let _tx = tx;
+    std::future::pending::<()>().await;
+    Ok(())
+}
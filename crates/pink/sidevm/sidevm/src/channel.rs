@@ -1,5 +1,6 @@
 //! Multi-producer, single-consumer channel implementation.
-use super::{ocall, ResourceId};
+use super::{env, ocall, ResourceId};
+use crate::env::Result;
 use std::{
     future::Future,
     pin::Pin,
@@ -46,3 +47,47 @@ pub fn input_messages() -> &'static Receiver {
     static MSG_RX: Receiver = Receiver::new(ResourceId(0));
     &MSG_RX
 }
+
+/// A query made by the paired pink contract with `pink_extension::ext().sidevm_query(payload)`.
+///
+/// Dropping a query without replying fails the query in the contract.
+pub struct Query {
+    /// The payload passed to `sidevm_query` by the contract.
+    pub payload: Vec<u8>,
+    reply_id: ResourceId,
+}
+
+impl Query {
+    /// Reply to the contract. The reply is dropped if the contract query has already timed out.
+    pub fn reply(self, reply: &[u8]) -> Result<()> {
+        ocall::query_reply(self.reply_id.0, reply.to_vec())
+    }
+}
+
+/// The future to get the next query from the paired contract.
+pub struct QueryNext {
+    _private: (),
+}
+
+/// Accept the next query from the paired contract, or `None` if the host stopped serving the
+/// queries.
+///
+/// The contract blocks until the reply arrives or its query deadline is reached, so the queries
+/// should be answered promptly, e.g. by spawning a task for each of them.
+pub fn next_query() -> QueryNext {
+    QueryNext { _private: () }
+}
+
+impl Future for QueryNext {
+    type Output = Option<Query>;
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match ocall::query_accept().expect("Accept query failed") {
+            env::Poll::Ready(query) => Poll::Ready(query.map(|query| Query {
+                payload: query.payload,
+                reply_id: ResourceId(query.reply_id),
+            })),
+            env::Poll::Pending => Poll::Pending,
+        }
+    }
+}
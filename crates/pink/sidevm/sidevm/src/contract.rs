@@ -0,0 +1,14 @@
+//! Access to the pink contract paired with the sidevm instance.
+
+use crate::env::Result;
+use crate::ocall;
+
+/// Read a raw storage item of the paired contract, e.g. an ink! storage cell by its 32 bytes key.
+///
+/// The items are read from a snapshot of the contract storage taken at the latest block processed
+/// by the worker, so the items read between two blocks are consistent with each other. The worker
+/// only takes the snapshots once the VM reads the storage, so the first read fails with
+/// `NotFound`. Fails with `InvalidParameter` if the key is not 32 bytes.
+pub fn get_storage(key: &[u8]) -> Result<Option<Vec<u8>>> {
+    ocall::contract_storage_get(key.to_vec())
+}
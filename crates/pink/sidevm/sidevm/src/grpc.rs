@@ -0,0 +1,49 @@
+//! Unary gRPC client.
+//!
+//! The host makes the calls on behalf of the guest, so only the protobuf encoded messages cross
+//! the VM boundary. The targets must be in the allowlist the VM is started with.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::env::{self, GrpcResponse, OcallError, Result};
+use crate::{ocall, ResourceId};
+
+/// The future of a gRPC call.
+pub struct GrpcCall {
+    res_id: ResourceId,
+}
+
+/// Call the gRPC method `path` on the `target`, with a protobuf encoded request message.
+///
+/// # Example
+/// ```ignore
+/// use pink_sidevm::grpc;
+/// let response = grpc::call("https://grpc.example.com:443", "/price.Feed/Latest", &request)?
+///     .await?;
+/// if response.status == 0 {
+///     let reply = LatestReply::decode(&response.body[..])?;
+/// }
+/// ```
+pub fn call(target: &str, path: &str, request: &[u8]) -> Result<GrpcCall> {
+    let res_id = ocall::grpc_call(target.into(), path.into(), request.into())?;
+    Ok(GrpcCall {
+        res_id: ResourceId(res_id),
+    })
+}
+
+impl Future for GrpcCall {
+    type Output = Result<GrpcResponse>;
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match ocall::poll(self.res_id.0) {
+            Ok(env::Poll::Ready(Some(response))) => Poll::Ready(
+                scale::Decode::decode(&mut &response[..]).or(Err(OcallError::InvalidEncoding)),
+            ),
+            Ok(env::Poll::Ready(None)) => Poll::Ready(Err(OcallError::NotFound)),
+            Ok(env::Poll::Pending) => Poll::Pending,
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+}
@@ -11,7 +11,15 @@ pub use res_id::ResourceId;
 
 pub use env::spawn;
 
+/// Get the startup config of the VM, an opaque blob given by the contract starting it, e.g. with
+/// `pink_extension::start_sidevm_with_config`. It is empty if no config is given.
+pub fn vm_config() -> env::Result<Vec<u8>> {
+    ocall::vm_config()
+}
+
 pub mod channel;
+pub mod contract;
+pub mod grpc;
 pub mod time;
 pub mod net;
 
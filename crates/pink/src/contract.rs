@@ -240,6 +240,15 @@ impl Contract {
     pub fn set_on_block_end_selector(&mut self, selector: u32) {
         self.hooks.on_block_end = Some(selector)
     }
+
+    /// Read a raw storage item of the contract. Returns `None` if the item or the contract does
+    /// not exist.
+    pub fn get_storage(&self, storage: &mut Storage, key: [u8; 32]) -> Option<Vec<u8>> {
+        let addr = self.address.clone();
+        storage
+            .execute_with(true, move || Contracts::get_storage(addr, key).ok().flatten())
+            .0
+    }
 }
 
 pub fn transpose_contract_result(result: &ContractExecResult) -> Result<&[u8], ExecError> {
@@ -11,3 +11,4 @@ pub mod types;
 
 pub use contract::{Contract, ContractFile, Storage, transpose_contract_result};
 pub use export_fixtures::load_test_wasm;
+pub use local_cache::remove_cluster as remove_cluster_cache;
@@ -11,6 +11,11 @@ pub use pink_extension::chain_extension::StorageQuotaExceeded;
 
 pub static GLOBAL_CACHE: Lazy<RwLock<LocalCache>> = Lazy::new(Default::default);
 
+/// Drops the cached data of all the contracts in a cluster.
+pub fn remove_cluster(cluster_id: &[u8]) {
+    GLOBAL_CACHE.write().unwrap().remove_cluster(cluster_id);
+}
+
 #[derive(Default, Debug)]
 struct Storage {
     // Sum of the size of all the keys and values.
@@ -18,6 +23,16 @@ struct Storage {
     kvs: HashMap<Vec<u8>, StorageValue>,
 }
 
+/// The caches of the contracts in a cluster. The contracts of different clusters never share a
+/// namespace, and the size of all the caches in a cluster is limited as a whole, so the contracts
+/// in one cluster can not exhaust the cache of the others.
+#[derive(Default, Debug)]
+struct ClusterStorage {
+    // Sum of the size of all the contract storages.
+    size: usize,
+    storages: HashMap<Vec<u8>, Storage>,
+}
+
 #[derive(Debug)]
 struct StorageValue {
     // Expiration time in seconds since the first call to `now`.
@@ -34,7 +49,8 @@ pub struct LocalCache {
     // Default expiration time in seconds.
     default_value_lifetime: u64,
     max_cache_size_per_contract: usize,
-    storages: HashMap<Vec<u8>, Storage>,
+    max_cache_size_per_cluster: usize,
+    clusters: HashMap<Vec<u8>, ClusterStorage>,
 }
 
 impl Default for LocalCache {
@@ -44,7 +60,8 @@ impl Default for LocalCache {
             sets_since_last_gc: 0,
             default_value_lifetime: 3600 * 24 * 7, // 1 week
             max_cache_size_per_contract: 10 * 1024 * 1024, // 10MB
-            storages: Default::default(),
+            max_cache_size_per_cluster: 100 * 1024 * 1024, // 100MB
+            clusters: Default::default(),
         }
     }
 }
@@ -55,22 +72,32 @@ impl LocalCache {
         if self.sets_since_last_gc == self.gc_interval {
             self.sets_since_last_gc = 0;
             let now = now();
-            self.storages.values_mut().for_each(|storage| {
-                let storage_size = &mut storage.size;
-                storage.kvs.retain(|k, v| {
-                    if v.expire_at > now {
-                        true
-                    } else {
-                        *storage_size -= v.value.len() + k.len();
-                        false
-                    }
+            self.clusters.values_mut().for_each(|cluster| {
+                let cluster_size = &mut cluster.size;
+                cluster.storages.values_mut().for_each(|storage| {
+                    let storage_size = &mut storage.size;
+                    storage.kvs.retain(|k, v| {
+                        if v.expire_at > now {
+                            true
+                        } else {
+                            *storage_size -= v.value.len() + k.len();
+                            *cluster_size -= v.value.len() + k.len();
+                            false
+                        }
+                    });
                 });
             });
         }
     }
 
-    pub fn get(&self, id: &[u8], key: &[u8]) -> Option<Vec<u8>> {
-        let entry = self.storages.get(id)?.kvs.get(key)?;
+    pub fn get(&self, cluster_id: &[u8], id: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+        let entry = self
+            .clusters
+            .get(cluster_id)?
+            .storages
+            .get(id)?
+            .kvs
+            .get(key)?;
         if entry.expire_at <= now() {
             None
         } else {
@@ -79,35 +106,49 @@ impl LocalCache {
     }
 
     #[cfg(test)]
-    fn get_include_expired(&self, id: &[u8], key: &[u8]) -> Option<Vec<u8>> {
-        Some(self.storages.get(id)?.kvs.get(key)?.value.to_owned())
+    fn get_include_expired(&self, cluster_id: &[u8], id: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+        let storage = self.clusters.get(cluster_id)?.storages.get(id)?;
+        Some(storage.kvs.get(key)?.value.to_owned())
     }
 
     pub fn set(
         &mut self,
+        cluster_id: Cow<[u8]>,
         id: Cow<[u8]>,
         key: Cow<[u8]>,
         value: Cow<[u8]>,
     ) -> Result<(), StorageQuotaExceeded> {
         self.maybe_clear_expired();
-        let store = self
+        let cluster = self
+            .clusters
+            .entry(cluster_id.into_owned())
+            .or_insert_with(ClusterStorage::default);
+        let store = cluster
             .storages
             .entry(id.into_owned())
             .or_insert_with(Storage::default);
         let key_len = key.len();
         let value_len = value.len();
-        let prev_value = store.kvs.remove(key.as_ref());
-
-        let new_size = match prev_value {
-            Some(v) => store.size + value_len - v.value.len(),
-            None => store.size + key_len + value_len,
-        };
-
-        if new_size > self.max_cache_size_per_contract {
+        let prev_len = store.kvs.get(key.as_ref()).map(|v| key_len + v.value.len());
+        let new_len = key_len + value_len;
+
+        let new_size = store.size + new_len - prev_len.unwrap_or(0);
+        let new_cluster_size = cluster.size + new_len - prev_len.unwrap_or(0);
+
+        if new_size > self.max_cache_size_per_contract
+            || new_cluster_size > self.max_cache_size_per_cluster
+        {
+            // A failed set drops the previous value as well.
+            if let Some(prev_len) = prev_len {
+                store.kvs.remove(key.as_ref());
+                store.size -= prev_len;
+                cluster.size -= prev_len;
+            }
             return Err(StorageQuotaExceeded);
         }
 
         store.size = new_size;
+        cluster.size = new_cluster_size;
         store.kvs.insert(
             key.into_owned(),
             StorageValue {
@@ -118,31 +159,48 @@ impl LocalCache {
         Ok(())
     }
 
-    pub fn set_expire(&mut self, id: Cow<[u8]>, key: Cow<[u8]>, expire: u64) {
+    pub fn set_expire(
+        &mut self,
+        cluster_id: Cow<[u8]>,
+        id: Cow<[u8]>,
+        key: Cow<[u8]>,
+        expire: u64,
+    ) {
         self.maybe_clear_expired();
         if expire == 0 {
-            let _ = self.remove(id.as_ref(), key.as_ref());
+            let _ = self.remove(cluster_id.as_ref(), id.as_ref(), key.as_ref());
         } else {
-            self.storages
-                .get_mut(id.as_ref())
+            self.clusters
+                .get_mut(cluster_id.as_ref())
+                .and_then(|cluster| cluster.storages.get_mut(id.as_ref()))
                 .and_then(|storage| storage.kvs.get_mut(key.as_ref()))
                 .map(|v| v.expire_at = now().saturating_add(expire));
         }
     }
 
-    pub fn remove(&mut self, id: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+    pub fn remove(&mut self, cluster_id: &[u8], id: &[u8], key: &[u8]) -> Option<Vec<u8>> {
         self.maybe_clear_expired();
-        let store = self.storages.get_mut(id)?;
+        let cluster = self.clusters.get_mut(cluster_id)?;
+        let store = cluster.storages.get_mut(id)?;
         let v = store.kvs.remove(key).map(|v| v.value);
         if let Some(v) = &v {
             store.size -= v.len() + key.len();
+            cluster.size -= v.len() + key.len();
         }
         v
     }
 
     #[allow(dead_code)]
-    pub fn remove_storage(&mut self, id: &[u8]) {
-        let _ = self.storages.remove(id);
+    pub fn remove_storage(&mut self, cluster_id: &[u8], id: &[u8]) {
+        if let Some(cluster) = self.clusters.get_mut(cluster_id) {
+            if let Some(storage) = cluster.storages.remove(id) {
+                cluster.size -= storage.size;
+            }
+        }
+    }
+
+    pub fn remove_cluster(&mut self, cluster_id: &[u8]) {
+        let _ = self.clusters.remove(cluster_id);
     }
 }
 
@@ -160,7 +218,8 @@ mod test {
             sets_since_last_gc: 0,
             default_value_lifetime: 2,
             max_cache_size_per_contract: 1024,
-            storages: Default::default(),
+            max_cache_size_per_cluster: 4096,
+            clusters: Default::default(),
         }
     }
 
@@ -170,7 +229,7 @@ mod test {
 
     fn gc(cache: &mut LocalCache) {
         for _ in 0..cache.gc_interval + 1 {
-            let _ = cache.set(cow(b"_"), cow(b"_"), cow(b"_"));
+            let _ = cache.set(cow(b"_"), cow(b"_"), cow(b"_"), cow(b"_"));
         }
     }
 
@@ -179,59 +238,86 @@ mod test {
     }
 
     fn get_size(cache: &LocalCache, id: &[u8]) -> usize {
-        cache.storages.get(id).unwrap().size
+        cache.clusters.get(&b"cluster"[..]).unwrap().storages.get(id).unwrap().size
     }
 
     #[test]
     fn default_expire_should_work() {
         let mut cache = test_cache();
-        let _ = cache.set(cow(b"id"), cow(b"foo"), cow(b"value"));
-        assert_eq!(cache.get(b"id", b"foo"), Some(b"value".to_vec()));
+        let _ = cache.set(cow(b"cluster"), cow(b"id"), cow(b"foo"), cow(b"value"));
+        assert_eq!(cache.get(b"cluster", b"id", b"foo"), Some(b"value".to_vec()));
 
         sleep(cache.default_value_lifetime);
-        assert_eq!(cache.get(b"id", b"foo"), None);
-        assert!(cache.get_include_expired(b"id", b"foo").is_some());
+        assert_eq!(cache.get(b"cluster", b"id", b"foo"), None);
+        assert!(cache.get_include_expired(b"cluster", b"id", b"foo").is_some());
         gc(&mut cache);
-        assert_eq!(cache.get_include_expired(b"id", b"foo"), None);
+        assert_eq!(cache.get_include_expired(b"cluster", b"id", b"foo"), None);
         assert_eq!(get_size(&cache, b"id"), 0);
     }
 
     #[test]
     fn set_expire_should_work() {
         let mut cache = test_cache();
-        let _ = cache.set(cow(b"id"), cow(b"foo"), cow(b"value"));
-        assert_eq!(cache.get(b"id", b"foo"), Some(b"value".to_vec()));
-        cache.set_expire(cow(b"id"), cow(b"foo"), cache.default_value_lifetime + 2);
+        let _ = cache.set(cow(b"cluster"), cow(b"id"), cow(b"foo"), cow(b"value"));
+        assert_eq!(cache.get(b"cluster", b"id", b"foo"), Some(b"value".to_vec()));
+        cache.set_expire(
+            cow(b"cluster"),
+            cow(b"id"),
+            cow(b"foo"),
+            cache.default_value_lifetime + 2,
+        );
 
         sleep(cache.default_value_lifetime);
         gc(&mut cache);
 
-        assert_eq!(cache.get(b"id", b"foo"), Some(b"value".to_vec()));
+        assert_eq!(cache.get(b"cluster", b"id", b"foo"), Some(b"value".to_vec()));
 
         sleep(2);
         gc(&mut cache);
 
-        assert_eq!(cache.get_include_expired(b"id", b"foo"), None);
+        assert_eq!(cache.get_include_expired(b"cluster", b"id", b"foo"), None);
     }
 
     #[test]
     fn size_limit_should_work() {
         let mut cache = test_cache();
         cache.max_cache_size_per_contract = 10;
-        assert!(cache.set(cow(b"id"), cow(b"foo"), cow(b"value")).is_ok());
-        assert!(cache.set(cow(b"id"), cow(b"bar"), cow(b"value")).is_err());
+        assert!(cache.set(cow(b"cluster"), cow(b"id"), cow(b"foo"), cow(b"value")).is_ok());
+        assert!(cache.set(cow(b"cluster"), cow(b"id"), cow(b"bar"), cow(b"value")).is_err());
     }
 
     #[test]
     fn size_calc() {
         let mut cache = test_cache();
-        assert!(cache.set(cow(b"id"), cow(b"foo"), cow(b"bar")).is_ok());
+        assert!(cache.set(cow(b"cluster"), cow(b"id"), cow(b"foo"), cow(b"bar")).is_ok());
         assert_eq!(get_size(&cache, b"id"), 6);
-        assert!(cache.set(cow(b"id"), cow(b"foo"), cow(b"foobar")).is_ok());
+        assert!(cache.set(cow(b"cluster"), cow(b"id"), cow(b"foo"), cow(b"foobar")).is_ok());
         assert_eq!(get_size(&cache, b"id"), 9);
-        assert!(cache.set(cow(b"id"), cow(b"foo"), cow(b"foo")).is_ok());
+        assert!(cache.set(cow(b"cluster"), cow(b"id"), cow(b"foo"), cow(b"foo")).is_ok());
         assert_eq!(get_size(&cache, b"id"), 6);
-        assert!(cache.remove(b"id", b"foo").is_some());
+        assert!(cache.remove(b"cluster", b"id", b"foo").is_some());
         assert_eq!(get_size(&cache, b"id"), 0);
     }
+
+    #[test]
+    fn clusters_are_isolated() {
+        let mut cache = test_cache();
+        assert!(cache.set(cow(b"a"), cow(b"id"), cow(b"foo"), cow(b"bar")).is_ok());
+        assert_eq!(cache.get(b"a", b"id", b"foo"), Some(b"bar".to_vec()));
+        assert_eq!(cache.get(b"b", b"id", b"foo"), None);
+        assert_eq!(cache.remove(b"b", b"id", b"foo"), None);
+        cache.remove_cluster(b"a");
+        assert_eq!(cache.get(b"a", b"id", b"foo"), None);
+    }
+
+    #[test]
+    fn cluster_size_limit_should_work() {
+        let mut cache = test_cache();
+        cache.max_cache_size_per_cluster = 12;
+        assert!(cache.set(cow(b"a"), cow(b"id1"), cow(b"foo"), cow(b"value")).is_ok());
+        assert!(cache.set(cow(b"a"), cow(b"id2"), cow(b"foo"), cow(b"value")).is_err());
+        assert!(cache.set(cow(b"b"), cow(b"id2"), cow(b"foo"), cow(b"value")).is_ok());
+        assert!(cache.remove(b"a", b"id1", b"foo").is_some());
+        assert!(cache.set(cow(b"a"), cow(b"id2"), cow(b"foo"), cow(b"value")).is_ok());
+    }
 }
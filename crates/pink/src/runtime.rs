@@ -17,8 +17,14 @@ use sp_runtime::{
     Perbill,
 };
 
-pub use extension::{get_side_effects, ExecSideEffects};
-pub use pink_extension::{Message, OspMessage, PinkEvent};
+pub use extension::{
+    get_side_effects, using_native_query_handler, using_sidevm_query_handler, ExecSideEffects,
+    NativeQueryHandler, SidevmQueryHandler,
+};
+pub use pink_extension::{
+    chain_extension::{NativeQueryError, SidevmQueryError},
+    Message, OspMessage, PinkEvent,
+};
 
 type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<PinkRuntime>;
 type Block = frame_system::mocking::MockBlock<PinkRuntime>;
@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::sync::Arc;
 use std::{convert::TryFrom, time::Duration};
 
 use frame_support::log::error;
@@ -8,8 +9,8 @@ use pallet_contracts::chain_extension::{
 use phala_crypto::sr25519::{Persistence, KDF};
 use pink_extension::{
     chain_extension::{
-        HttpRequest, HttpResponse, PinkExtBackend, PublicKeyForArgs, SigType, SignArgs,
-        StorageQuotaExceeded, VerifyArgs,
+        HttpRequest, HttpResponse, NativeQueryError, PinkExtBackend, PublicKeyForArgs,
+        SidevmQueryError, SigType, SignArgs, StorageQuotaExceeded, VerifyArgs,
     },
     dispatch_ext_call, PinkEvent,
 };
@@ -24,6 +25,62 @@ use crate::{
 
 use crate::local_cache::GLOBAL_CACHE;
 
+/// The max time a contract query can spend on the blocking calls, e.g. http requests.
+const MAX_QUERY_TIME: Duration = Duration::from_secs(10);
+
+/// Routes the `sidevm_query` calls of the contracts to their sidevm instances.
+pub trait SidevmQueryHandler: Send + Sync {
+    /// Sends the payload to the sidevm instance of the contract, and waits for the reply until the
+    /// timeout.
+    fn query(
+        &self,
+        contract: &[u8],
+        payload: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, SidevmQueryError>;
+}
+
+environmental::environmental!(sidevm_query_handler: Arc<dyn SidevmQueryHandler>);
+
+/// Runs `f` with the handler of the `sidevm_query` calls made in it. The calls made outside of it
+/// fail with `NotRunning`.
+pub fn using_sidevm_query_handler<T>(
+    handler: Arc<dyn SidevmQueryHandler>,
+    f: impl FnOnce() -> T,
+) -> T {
+    let mut handler = handler;
+    sidevm_query_handler::using(&mut handler, f)
+}
+
+/// Routes the `native_query` calls of the contracts to the native contracts in their cluster.
+pub trait NativeQueryHandler: Send + Sync {
+    /// Queries the native contract on behalf of the `caller` contract.
+    fn query(
+        &self,
+        caller: &[u8],
+        contract: [u8; 32],
+        query: Vec<u8>,
+    ) -> Result<Vec<u8>, NativeQueryError>;
+}
+
+environmental::environmental!(native_query_handler: Arc<dyn NativeQueryHandler>);
+
+/// Runs `f` with the handler of the `native_query` calls made in it. The calls made outside of it
+/// fail with `ContractNotFound`.
+pub fn using_native_query_handler<T>(
+    handler: Arc<dyn NativeQueryHandler>,
+    f: impl FnOnce() -> T,
+) -> T {
+    let mut handler = handler;
+    native_query_handler::using(&mut handler, f)
+}
+
+/// The time left before the deadline of the current contract query.
+fn query_time_left() -> Result<Duration, DispatchError> {
+    let elapsed = get_call_elapsed().ok_or(DispatchError::Other("Invalid exec env"))?;
+    Ok(MAX_QUERY_TIME.saturating_sub(elapsed))
+}
+
 #[derive(Default, Debug)]
 pub struct ExecSideEffects {
     pub pink_events: Vec<(AccountId, PinkEvent)>,
@@ -76,6 +133,7 @@ impl ChainExtension<super::PinkRuntime> for PinkExtension {
     {
         let mut env = env.buf_in_buf_out();
         let call_in_query = CallInQuery {
+            cluster_id: super::Pink::cluster_id(),
             address: env.ext().address().clone(),
         };
         let result = if matches!(get_call_mode(), Some(CallMode::Command)) {
@@ -104,6 +162,8 @@ impl ChainExtension<super::PinkRuntime> for PinkExtension {
 }
 
 struct CallInQuery<AccountId> {
+    /// The cache of a contract is kept in the namespace of its cluster.
+    cluster_id: Vec<u8>,
     address: AccountId,
 }
 
@@ -136,12 +196,9 @@ where
         };
 
         // Hardcoded limitations for now
-        const MAX_QUERY_TIME: u64 = 10; // seconds
         const MAX_BODY_SIZE: usize = 1024 * 256; // 256KB
 
-        let elapsed = get_call_elapsed().ok_or(DispatchError::Other("Invalid exec env"))?;
-        let timeout = Duration::from_secs(MAX_QUERY_TIME) - elapsed;
-        req.timeout(Some(timeout));
+        req.timeout(Some(query_time_left()?));
 
         let mut body = Vec::new();
         let mut writer = LimitedWriter::new(&mut body, MAX_BODY_SIZE);
@@ -233,7 +290,7 @@ where
         let result = GLOBAL_CACHE
             .write()
             .unwrap()
-            .set(self.address.as_ref().into(), key, value);
+            .set((&self.cluster_id[..]).into(), self.address.as_ref().into(), key, value);
         Ok(result)
     }
 
@@ -241,7 +298,12 @@ where
         GLOBAL_CACHE
             .write()
             .unwrap()
-            .set_expire(self.address.as_ref().into(), key, expire);
+            .set_expire(
+                (&self.cluster_id[..]).into(),
+                self.address.as_ref().into(),
+                key,
+                expire,
+            );
         Ok(())
     }
 
@@ -249,7 +311,7 @@ where
         let value = GLOBAL_CACHE
             .read()
             .unwrap()
-            .get(self.address.as_ref(), key.as_ref());
+            .get(&self.cluster_id, self.address.as_ref(), key.as_ref());
         Ok(value)
     }
 
@@ -257,9 +319,31 @@ where
         let value = GLOBAL_CACHE
             .write()
             .unwrap()
-            .remove(self.address.as_ref(), key.as_ref());
+            .remove(&self.cluster_id, self.address.as_ref(), key.as_ref());
         Ok(value)
     }
+
+    fn sidevm_query(
+        &self,
+        payload: Cow<[u8]>,
+    ) -> Result<Result<Vec<u8>, SidevmQueryError>, Self::Error> {
+        let timeout = query_time_left()?;
+        Ok(sidevm_query_handler::with(|handler| {
+            handler.query(self.address.as_ref(), payload.into_owned(), timeout)
+        })
+        .unwrap_or(Err(SidevmQueryError::NotRunning)))
+    }
+
+    fn native_query(
+        &self,
+        contract: [u8; 32],
+        query: Cow<[u8]>,
+    ) -> Result<Result<Vec<u8>, NativeQueryError>, Self::Error> {
+        Ok(native_query_handler::with(|handler| {
+            handler.query(self.address.as_ref(), contract, query.into_owned())
+        })
+        .unwrap_or(Err(NativeQueryError::ContractNotFound)))
+    }
 }
 
 struct CallInCommand<AccountId> {
@@ -316,6 +400,21 @@ where
     fn cache_remove(&self, _args: Cow<[u8]>) -> Result<Option<Vec<u8>>, Self::Error> {
         Ok(None)
     }
+
+    fn sidevm_query(
+        &self,
+        _payload: Cow<[u8]>,
+    ) -> Result<Result<Vec<u8>, SidevmQueryError>, Self::Error> {
+        Err(DispatchError::Other("sidevm_query can only be called in query mode"))
+    }
+
+    fn native_query(
+        &self,
+        _contract: [u8; 32],
+        _query: Cow<[u8]>,
+    ) -> Result<Result<Vec<u8>, NativeQueryError>, Self::Error> {
+        Err(DispatchError::Other("native_query can only be called in query mode"))
+    }
 }
 
 struct LimitedWriter<W> {
@@ -55,6 +55,10 @@ pub mod pallet {
             <ClusterId<T>>::put(cluster_id.to_vec());
         }
 
+        pub fn cluster_id() -> Vec<u8> {
+            <ClusterId<T>>::get()
+        }
+
         pub fn set_key_seed(seed: Sr25519SecretKey) {
             <KeySeed<T>>::put(seed);
         }
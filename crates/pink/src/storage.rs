@@ -97,6 +97,15 @@ where
             .full_storage_root(delta, child_delta, sp_core::storage::StateVersion::V0)
     }
 
+    /// The current state root of the storage
+    pub fn root(&self) -> Hash {
+        *self
+            .backend
+            .as_trie_backend()
+            .expect("No trie backend?")
+            .root()
+    }
+
     pub fn commit_changes(&mut self, changes: OverlayedChanges) {
         let (root, transaction) = self.changes_transaction(changes);
         self.backend.commit_transaction(root, transaction)
@@ -237,7 +237,8 @@ const types = {
     "ClusterPermission": {
         "_enum": {
             "Public": null,
-            "OnlyOwner": "AccountId"
+            "OnlyOwner": "AccountId",
+            "AllowList": "Vec<AccountId>"
         }
     }
 };
@@ -0,0 +1,269 @@
+#!/usr/bin/env python3
+"""Generates dcap_attestation.json, the DCAP sample used by the tests in utils/dcap.rs.
+
+The quote and the collateral follow the layout of the Intel PCS, but every certificate is issued
+by a test root CA instead of the Intel SGX Root CA, since no one but Intel can sign them. The
+tests verify the sample against the test root.
+
+Requires the `cryptography` package.
+"""
+
+import datetime
+import hashlib
+import json
+import os
+import struct
+
+from cryptography import x509
+from cryptography.hazmat.primitives import hashes, serialization
+from cryptography.hazmat.primitives.asymmetric import ec
+from cryptography.hazmat.primitives.asymmetric.utils import decode_dss_signature
+from cryptography.x509.oid import NameOID
+
+NOT_BEFORE = datetime.datetime(2023, 1, 1, tzinfo=datetime.timezone.utc)
+NOT_AFTER = datetime.datetime(2049, 12, 31, tzinfo=datetime.timezone.utc)
+ISSUE_DATE = datetime.datetime(2023, 6, 1, tzinfo=datetime.timezone.utc)
+NEXT_UPDATE = datetime.datetime(2023, 7, 1, tzinfo=datetime.timezone.utc)
+TIMESTAMP = int(datetime.datetime(2023, 6, 15, tzinfo=datetime.timezone.utc).timestamp())
+
+SGX_EXTENSIONS_OID = "1.2.840.113741.1.13.1"
+FMSPC = bytes.fromhex("00906ea10000")
+CPU_SVN = bytes([2] * 16)
+PCE_SVN = 11
+QE_MR_SIGNER = bytes.fromhex("8c4f5775d796503e96137f77c68a829a0056ac8ded70140b081b094490c57bff")
+QE_ISV_SVN = 8
+
+
+def der(tag, value):
+    if len(value) < 0x80:
+        length = bytes([len(value)])
+    else:
+        encoded = len(value).to_bytes((len(value).bit_length() + 7) // 8, "big")
+        length = bytes([0x80 | len(encoded)]) + encoded
+    return bytes([tag]) + length + value
+
+
+def der_oid(oid):
+    parts = [int(p) for p in oid.split(".")]
+    body = bytes([parts[0] * 40 + parts[1]])
+    for part in parts[2:]:
+        chunk = [part & 0x7F]
+        part >>= 7
+        while part:
+            chunk.insert(0, 0x80 | (part & 0x7F))
+            part >>= 7
+        body += bytes(chunk)
+    return der(0x06, body)
+
+
+def der_uint(value):
+    return der(0x02, value.to_bytes(value.bit_length() // 8 + 1, "big"))
+
+
+def sgx_extension(suffix, value):
+    return der(0x30, der_oid(SGX_EXTENSIONS_OID + suffix) + value)
+
+
+def sgx_extensions():
+    tcb = b"".join(sgx_extension(".2.%d" % (i + 1), der_uint(svn)) for i, svn in enumerate(CPU_SVN))
+    tcb += sgx_extension(".2.17", der_uint(PCE_SVN))
+    tcb += sgx_extension(".2.18", der(0x04, CPU_SVN))
+    return der(
+        0x30,
+        sgx_extension(".1", der(0x04, bytes(16)))
+        + sgx_extension(".2", der(0x30, tcb))
+        + sgx_extension(".3", der(0x04, bytes(2)))
+        + sgx_extension(".4", der(0x04, FMSPC))
+        + sgx_extension(".5", der(0x0A, b"\x00")),
+    )
+
+
+def name(common_name):
+    return x509.Name(
+        [
+            x509.NameAttribute(NameOID.COMMON_NAME, common_name),
+            x509.NameAttribute(NameOID.ORGANIZATION_NAME, "Phala Network Test"),
+        ]
+    )
+
+
+def issue(subject, key, issuer, issuer_key, ca, extra=()):
+    builder = (
+        x509.CertificateBuilder()
+        .subject_name(subject)
+        .issuer_name(issuer)
+        .public_key(key.public_key())
+        .serial_number(x509.random_serial_number())
+        .not_valid_before(NOT_BEFORE)
+        .not_valid_after(NOT_AFTER)
+        .add_extension(x509.BasicConstraints(ca=ca, path_length=None), critical=True)
+    )
+    for extension in extra:
+        builder = builder.add_extension(extension, critical=False)
+    return builder.sign(issuer_key, hashes.SHA256())
+
+
+def crl(issuer, issuer_key, revoked):
+    builder = (
+        x509.CertificateRevocationListBuilder()
+        .issuer_name(issuer)
+        .last_update(ISSUE_DATE)
+        .next_update(NEXT_UPDATE)
+        .add_extension(x509.CRLNumber(1), critical=False)
+    )
+    for cert in revoked:
+        builder = builder.add_revoked_certificate(
+            x509.RevokedCertificateBuilder()
+            .serial_number(cert.serial_number)
+            .revocation_date(ISSUE_DATE)
+            .build()
+        )
+    return builder.sign(issuer_key, hashes.SHA256()).public_bytes(serialization.Encoding.DER)
+
+
+def raw_signature(key, message):
+    r, s = decode_dss_signature(key.sign(message, ec.ECDSA(hashes.SHA256())))
+    return r.to_bytes(32, "big") + s.to_bytes(32, "big")
+
+
+def raw_public_key(key):
+    point = key.public_key().public_bytes(
+        serialization.Encoding.X962, serialization.PublicFormat.UncompressedPoint
+    )
+    return point[1:]
+
+
+def pem(*certs):
+    return b"".join(cert.public_bytes(serialization.Encoding.PEM) for cert in certs)
+
+
+def report(mr_enclave, mr_signer, isv_prod_id, isv_svn, report_data, attributes=bytes(16)):
+    body = CPU_SVN + bytes(4) + bytes(28) + attributes + mr_enclave + bytes(32) + mr_signer
+    body += bytes(96) + struct.pack("<HH", isv_prod_id, isv_svn) + bytes(60) + report_data
+    assert len(body) == 384
+    return body
+
+
+def timestamp(time):
+    return time.strftime("%Y-%m-%dT%H:%M:%SZ")
+
+
+def tcb_level(svn, pce_svn, status, advisory_ids=None):
+    level = {
+        "tcb": {
+            "sgxtcbcomponents": [{"svn": svn} for _ in range(16)],
+            "pcesvn": pce_svn,
+        },
+        "tcbDate": timestamp(ISSUE_DATE),
+        "tcbStatus": status,
+    }
+    if advisory_ids is not None:
+        level["advisoryIDs"] = advisory_ids
+    return level
+
+
+def main():
+    new_key = lambda: ec.generate_private_key(ec.SECP256R1())
+    root_key, pck_ca_key, pck_key, tcb_key = new_key(), new_key(), new_key(), new_key()
+    other_key = new_key()
+    root_name = name("Test SGX Root CA")
+    root = issue(root_name, root_key, root_name, root_key, True)
+    pck_ca_name = name("Test SGX PCK Platform CA")
+    pck_ca = issue(pck_ca_name, pck_ca_key, root_name, root_key, True)
+    sgx_oid = x509.ObjectIdentifier(SGX_EXTENSIONS_OID)
+    sgx_ext = x509.UnrecognizedExtension(sgx_oid, sgx_extensions())
+    pck_name = name("Test SGX PCK Certificate")
+    pck = issue(pck_name, pck_key, pck_ca_name, pck_ca_key, False, [sgx_ext])
+    tcb_signer = issue(name("Intel SGX TCB Signing"), tcb_key, root_name, root_key, False)
+    other_signer = issue(name("Test SGX Other Signing"), other_key, root_name, root_key, False)
+
+    # The quote
+    attestation_key = new_key()
+    qe_auth_data = bytes(range(32))
+    header = struct.pack("<HHIHH", 3, 2, 0, QE_ISV_SVN, PCE_SVN) + bytes(16) + bytes(20)
+    mr_enclave = hashlib.sha256(b"pruntime").digest()
+    mr_signer = hashlib.sha256(b"phala").digest()
+    report_data = hashlib.sha256(b"runtime info").digest() + bytes(32)
+    enclave_report = report(mr_enclave, mr_signer, 0, 1, report_data)
+    key_hash = hashlib.sha256(raw_public_key(attestation_key) + qe_auth_data).digest()
+    qe_attributes = b"\x11" + bytes(15)
+    qe_report = report(bytes(32), QE_MR_SIGNER, 1, QE_ISV_SVN, key_hash + bytes(32), qe_attributes)
+    pck_chain = pem(pck, pck_ca, root)
+    signature_data = raw_signature(attestation_key, header + enclave_report)
+    signature_data += raw_public_key(attestation_key) + qe_report
+    signature_data += raw_signature(pck_key, qe_report)
+    signature_data += struct.pack("<H", len(qe_auth_data)) + qe_auth_data
+    signature_data += struct.pack("<HI", 5, len(pck_chain)) + pck_chain
+    quote = header + enclave_report + struct.pack("<I", len(signature_data)) + signature_data
+
+    # The collateral
+    tcb_info = json.dumps(
+        {
+            "id": "SGX",
+            "version": 3,
+            "issueDate": timestamp(ISSUE_DATE),
+            "nextUpdate": timestamp(NEXT_UPDATE),
+            "fmspc": FMSPC.hex(),
+            "pceId": "0000",
+            "tcbType": 0,
+            "tcbEvaluationDataNumber": 14,
+            "tcbLevels": [
+                tcb_level(3, 11, "UpToDate"),
+                tcb_level(2, 10, "SWHardeningNeeded", ["INTEL-SA-00334"]),
+                tcb_level(1, 5, "OutOfDate", ["INTEL-SA-00334", "INTEL-SA-00615"]),
+            ],
+        },
+        separators=(",", ":"),
+    ).encode()
+    qe_identity = json.dumps(
+        {
+            "id": "QE",
+            "version": 2,
+            "issueDate": timestamp(ISSUE_DATE),
+            "nextUpdate": timestamp(NEXT_UPDATE),
+            "tcbEvaluationDataNumber": 14,
+            "miscselect": "00000000",
+            "miscselectMask": "FFFFFFFF",
+            "attributes": "11000000000000000000000000000000",
+            "attributesMask": "FBFFFFFFFFFFFFFF0000000000000000",
+            "mrsigner": QE_MR_SIGNER.hex().upper(),
+            "isvprodid": 1,
+            "tcbLevels": [
+                {"tcb": {"isvsvn": 8}, "tcbDate": timestamp(ISSUE_DATE), "tcbStatus": "UpToDate"},
+                {"tcb": {"isvsvn": 0}, "tcbDate": timestamp(ISSUE_DATE), "tcbStatus": "OutOfDate"},
+            ],
+        },
+        separators=(",", ":"),
+    ).encode()
+
+    sample = {
+        "timestamp": TIMESTAMP,
+        "rootCa": root.public_bytes(serialization.Encoding.DER).hex(),
+        "quote": quote.hex(),
+        "mrEnclave": mr_enclave.hex(),
+        "reportData": report_data.hex(),
+        "tcbInfoIssuerChain": pem(tcb_signer, root).decode(),
+        "tcbInfo": tcb_info.decode(),
+        "tcbInfoSignature": raw_signature(tcb_key, tcb_info).hex(),
+        "qeIdentityIssuerChain": pem(tcb_signer, root).decode(),
+        "qeIdentity": qe_identity.decode(),
+        "qeIdentitySignature": raw_signature(tcb_key, qe_identity).hex(),
+        "rootCaCrl": crl(root_name, root_key, []).hex(),
+        "pckCrl": crl(pck_ca_name, pck_ca_key, []).hex(),
+        # Counterexamples
+        "pckCertChain": pck_chain.decode(),
+        "tcbInfoSignatureByPck": raw_signature(pck_key, tcb_info).hex(),
+        "otherSignerChain": pem(other_signer, root).decode(),
+        "tcbInfoSignatureByOtherSigner": raw_signature(other_key, tcb_info).hex(),
+        "pckCrlRevokingPck": crl(pck_ca_name, pck_ca_key, [pck]).hex(),
+        "rootCaCrlRevokingPckCa": crl(root_name, root_key, [pck_ca]).hex(),
+        "rootCaCrlRevokingTcbSigner": crl(root_name, root_key, [tcb_signer]).hex(),
+    }
+    path = os.path.join(os.path.dirname(os.path.abspath(__file__)), "dcap_attestation.json")
+    with open(path, "w") as f:
+        json.dump(sample, f, indent=2)
+        f.write("\n")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,432 @@
+//! # Assets Bridge Pallet
+//!
+//! Wraps the foreign assets, e.g. the ones bridged by XCM, into the Assets contract. A wrapped
+//! asset is locked on chain while it is held in the contract, and released to the destination
+//! account when the contract unwraps it. A release that fails is kept for the destination account
+//! to claim later.
+
+pub use self::pallet::*;
+
+#[frame_support::pallet]
+pub mod pallet {
+	use frame_support::{dispatch::DispatchResult, pallet_prelude::*, traits::StorageVersion};
+	use frame_system::pallet_prelude::*;
+	use sp_runtime::traits::{AtLeast32BitUnsigned, CheckedAdd, CheckedSub, Saturating, Zero};
+	use sp_std::prelude::*;
+
+	use crate::mq::MessageOriginInfo;
+
+	use phala_types::{
+		contract::{command_topic, ContractId},
+		messaging::{AssetCommand, AssetUnwrap, CommandPayload, DecodedMessage, MessageOrigin},
+	};
+
+	/// Moves the foreign assets between the accounts and the bridge
+	pub trait ForeignAssets<AccountId, Balance> {
+		/// Takes `amount` of the asset at `location` from `who` into the bridge.
+		fn lock(location: &[u8], who: &AccountId, amount: Balance) -> DispatchResult;
+		/// Gives `amount` of the asset at `location` from the bridge to `dest`.
+		fn release(location: &[u8], dest: &AccountId, amount: Balance) -> DispatchResult;
+	}
+
+	/// No foreign asset can be bridged.
+	impl<AccountId, Balance> ForeignAssets<AccountId, Balance> for () {
+		fn lock(_location: &[u8], _who: &AccountId, _amount: Balance) -> DispatchResult {
+			Err(DispatchError::Other("No foreign assets"))
+		}
+
+		fn release(_location: &[u8], _dest: &AccountId, _amount: Balance) -> DispatchResult {
+			Err(DispatchError::Other("No foreign assets"))
+		}
+	}
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config + crate::mq::Config {
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+		type Balance: Parameter + Member + AtLeast32BitUnsigned + Default + Copy;
+
+		type ForeignAssets: ForeignAssets<Self::AccountId, Self::Balance>;
+
+		/// The max length of the location of a foreign asset
+		#[pallet::constant]
+		type MaxLocationLength: Get<u32>;
+	}
+
+	const STORAGE_VERSION: StorageVersion = StorageVersion::new(0);
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	#[pallet::storage_version(STORAGE_VERSION)]
+	#[pallet::without_storage_info]
+	pub struct Pallet<T>(_);
+
+	/// The Assets contract holding the wrapped assets
+	#[pallet::storage]
+	pub type AssetsContract<T> = StorageValue<_, ContractId>;
+
+	/// The replaced Assets contracts, which can still unwrap the assets they hold
+	#[pallet::storage]
+	pub type RetiredContracts<T> = StorageMap<_, Twox64Concat, ContractId, ()>;
+
+	/// The amount of the foreign assets locked by the bridge, by their location
+	#[pallet::storage]
+	pub type Locked<T: Config> =
+		StorageMap<_, Blake2_128Concat, Vec<u8>, T::Balance, ValueQuery>;
+
+	/// The unwrapped assets failed to be released, by their location and destination account
+	#[pallet::storage]
+	pub type Claimable<T: Config> = StorageDoubleMap<
+		_,
+		Blake2_128Concat,
+		Vec<u8>,
+		Blake2_128Concat,
+		T::AccountId,
+		T::Balance,
+		ValueQuery,
+	>;
+
+	#[pallet::event]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
+		AssetsContractSet {
+			contract: ContractId,
+		},
+		/// A foreign asset is locked and credited to `who` in the Assets contract
+		Wrapped {
+			location: Vec<u8>,
+			who: T::AccountId,
+			amount: T::Balance,
+		},
+		/// A foreign asset unwrapped by the Assets contract is released to `dest`
+		Released {
+			location: Vec<u8>,
+			dest: T::AccountId,
+			amount: T::Balance,
+		},
+		/// A foreign asset unwrapped by the Assets contract failed to be released, and can be
+		/// claimed by `dest`
+		ReleaseFailed {
+			location: Vec<u8>,
+			dest: T::AccountId,
+			amount: T::Balance,
+			error: DispatchError,
+		},
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		/// No Assets contract is set to hold the wrapped assets
+		AssetsContractNotSet,
+		InvalidSender,
+		/// The locked amount of the asset would overflow
+		LockedOverflow,
+		/// The contract unwraps more than the locked amount of the asset
+		InsufficientLocked,
+		/// The location of the asset is longer than `MaxLocationLength`
+		LocationTooLong,
+		/// Nothing of the asset is claimable by the caller
+		NothingToClaim,
+	}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Sets the Assets contract to hold the wrapped assets
+		///
+		/// Can only be called by root.
+		#[pallet::weight(0)]
+		pub fn set_assets_contract(origin: OriginFor<T>, contract: ContractId) -> DispatchResult {
+			ensure_root(origin)?;
+			// The assets held in the replaced contract must still be unwrappable.
+			if let Some(replaced) = AssetsContract::<T>::get() {
+				if replaced != contract {
+					RetiredContracts::<T>::insert(replaced, ());
+				}
+			}
+			RetiredContracts::<T>::remove(contract);
+			AssetsContract::<T>::put(contract);
+			Self::deposit_event(Event::<T>::AssetsContractSet { contract });
+			Ok(())
+		}
+
+		/// Locks a foreign asset to hold it in the Assets contract
+		#[pallet::weight(0)]
+		pub fn wrap(origin: OriginFor<T>, location: Vec<u8>, amount: T::Balance) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(
+				location.len() <= T::MaxLocationLength::get() as usize,
+				Error::<T>::LocationTooLong
+			);
+			let contract = AssetsContract::<T>::get().ok_or(Error::<T>::AssetsContractNotSet)?;
+			let locked = Locked::<T>::get(&location)
+				.checked_add(&amount)
+				.ok_or(Error::<T>::LockedOverflow)?;
+			T::ForeignAssets::lock(&location, &who, amount)?;
+			Locked::<T>::insert(&location, locked);
+			let command = AssetCommand::Wrap {
+				location: location.clone(),
+				who: who.clone(),
+				amount,
+			};
+			Self::push_message_to(command_topic(contract), CommandPayload::Plain(command));
+			Self::deposit_event(Event::<T>::Wrapped {
+				location,
+				who,
+				amount,
+			});
+			Ok(())
+		}
+
+		/// Claims the asset at `location` failed to be released to the caller
+		#[pallet::weight(0)]
+		pub fn claim(origin: OriginFor<T>, location: Vec<u8>) -> DispatchResult {
+			let dest = ensure_signed(origin)?;
+			let amount = Claimable::<T>::get(&location, &dest);
+			ensure!(!amount.is_zero(), Error::<T>::NothingToClaim);
+			Self::release(&location, &dest, amount)?;
+			Claimable::<T>::remove(&location, &dest);
+			Self::deposit_event(Event::<T>::Released {
+				location,
+				dest,
+				amount,
+			});
+			Ok(())
+		}
+	}
+
+	impl<T: Config> Pallet<T> {
+		pub fn on_unwrap_message_received(
+			message: DecodedMessage<AssetUnwrap<T::AccountId, T::Balance>>,
+		) -> DispatchResult {
+			let contract = match message.sender {
+				MessageOrigin::Contract(contract) => contract,
+				_ => return Err(Error::<T>::InvalidSender.into()),
+			};
+			ensure!(
+				AssetsContract::<T>::get() == Some(contract)
+					|| RetiredContracts::<T>::contains_key(contract),
+				Error::<T>::InvalidSender
+			);
+			let AssetUnwrap {
+				location,
+				dest,
+				amount,
+			} = message.payload;
+			let locked = Locked::<T>::get(&location)
+				.checked_sub(&amount)
+				.ok_or(Error::<T>::InsufficientLocked)?;
+			Locked::<T>::insert(&location, locked);
+			match Self::release(&location, &dest, amount) {
+				Ok(()) => Self::deposit_event(Event::<T>::Released {
+					location,
+					dest,
+					amount,
+				}),
+				Err(error) => {
+					// The asset has left the contract, so it is kept here for `dest` to claim.
+					Claimable::<T>::mutate(&location, &dest, |claimable| {
+						*claimable = claimable.saturating_add(amount)
+					});
+					Self::deposit_event(Event::<T>::ReleaseFailed {
+						location,
+						dest,
+						amount,
+						error,
+					});
+				}
+			}
+			Ok(())
+		}
+
+		#[frame_support::transactional]
+		fn release(location: &[u8], dest: &T::AccountId, amount: T::Balance) -> DispatchResult {
+			T::ForeignAssets::release(location, dest, amount)
+		}
+	}
+
+	impl<T: Config> MessageOriginInfo for Pallet<T> {
+		type Config = T;
+	}
+
+	#[cfg(test)]
+	mod test {
+		use super::*;
+		use crate::mock::{
+			new_test_ext, set_block_1, take_events, take_messages, Event as TestEvent, Origin,
+			Test, BRIDGE_ACCOUNT, DOLLARS,
+		};
+		// Pallets
+		use crate::mock::{Balances, PhalaAssetsBridge};
+		use frame_support::{assert_noop, assert_ok};
+		use phala_types::messaging::{BindTopic, Topic};
+
+		/// The location of the native token in the mock foreign assets
+		const LOCATION: [u8; 1] = [0];
+
+		fn contract() -> ContractId {
+			ContractId::repeat_byte(1)
+		}
+
+		fn unwrap_message(
+			sender: MessageOrigin,
+			dest: u64,
+			amount: u128,
+		) -> DecodedMessage<AssetUnwrap<u64, u128>> {
+			DecodedMessage {
+				sender,
+				destination: Topic::new(AssetUnwrap::<u64, u128>::topic()),
+				payload: AssetUnwrap {
+					location: LOCATION.to_vec(),
+					dest,
+					amount,
+				},
+			}
+		}
+
+		#[test]
+		fn wrap_and_release() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				assert_noop!(
+					PhalaAssetsBridge::wrap(Origin::signed(1), LOCATION.to_vec(), DOLLARS),
+					Error::<Test>::AssetsContractNotSet
+				);
+				assert_ok!(PhalaAssetsBridge::set_assets_contract(Origin::root(), contract()));
+				take_messages();
+
+				let free = Balances::free_balance(1);
+				assert_ok!(PhalaAssetsBridge::wrap(
+					Origin::signed(1),
+					LOCATION.to_vec(),
+					10 * DOLLARS
+				));
+				assert_eq!(Balances::free_balance(1), free - 10 * DOLLARS);
+				assert_eq!(Balances::free_balance(BRIDGE_ACCOUNT), 10 * DOLLARS);
+				assert_eq!(Locked::<Test>::get(LOCATION.to_vec()), 10 * DOLLARS);
+				let messages = take_messages();
+				assert_eq!(messages.len(), 1);
+				assert_eq!(messages[0].sender, PhalaAssetsBridge::message_origin());
+				assert_eq!(messages[0].destination.path(), &command_topic(contract()));
+				let command = CommandPayload::Plain(AssetCommand::<u64, u128>::Wrap {
+					location: LOCATION.to_vec(),
+					who: 1,
+					amount: 10 * DOLLARS,
+				});
+				assert_eq!(messages[0].payload, command.encode());
+
+				// Only the Assets contract can unwrap
+				let other = MessageOrigin::Contract(ContractId::repeat_byte(2));
+				assert_noop!(
+					PhalaAssetsBridge::on_unwrap_message_received(unwrap_message(other, 2, DOLLARS)),
+					Error::<Test>::InvalidSender
+				);
+				let sender = MessageOrigin::Contract(contract());
+				assert_noop!(
+					PhalaAssetsBridge::on_unwrap_message_received(unwrap_message(
+						sender.clone(),
+						2,
+						11 * DOLLARS
+					)),
+					Error::<Test>::InsufficientLocked
+				);
+				let free = Balances::free_balance(2);
+				take_events();
+				assert_ok!(PhalaAssetsBridge::on_unwrap_message_received(unwrap_message(
+					sender,
+					2,
+					4 * DOLLARS
+				)));
+				assert_eq!(Balances::free_balance(2), free + 4 * DOLLARS);
+				assert_eq!(Locked::<Test>::get(LOCATION.to_vec()), 6 * DOLLARS);
+				assert!(take_events().contains(&TestEvent::PhalaAssetsBridge(Event::Released {
+					location: LOCATION.to_vec(),
+					dest: 2,
+					amount: 4 * DOLLARS,
+				})));
+			});
+		}
+
+		#[test]
+		fn unknown_assets_are_not_locked() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				assert_ok!(PhalaAssetsBridge::set_assets_contract(Origin::root(), contract()));
+				take_messages();
+				assert!(PhalaAssetsBridge::wrap(Origin::signed(1), vec![1], DOLLARS).is_err());
+				assert_eq!(Locked::<Test>::get(vec![1]), 0);
+				assert_noop!(
+					PhalaAssetsBridge::wrap(Origin::signed(1), vec![0; 5], DOLLARS),
+					Error::<Test>::LocationTooLong
+				);
+				assert!(take_messages().is_empty());
+			});
+		}
+
+		#[test]
+		fn failed_releases_are_claimable() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				assert_ok!(PhalaAssetsBridge::set_assets_contract(Origin::root(), contract()));
+				assert_ok!(PhalaAssetsBridge::wrap(
+					Origin::signed(1),
+					LOCATION.to_vec(),
+					10 * DOLLARS
+				));
+				take_events();
+
+				// Below the existential deposit, the new account 50 can not receive the asset
+				let sender = MessageOrigin::Contract(contract());
+				assert_ok!(PhalaAssetsBridge::on_unwrap_message_received(unwrap_message(
+					sender, 50, 1
+				)));
+				assert_eq!(Balances::free_balance(50), 0);
+				assert_eq!(Locked::<Test>::get(LOCATION.to_vec()), 10 * DOLLARS - 1);
+				assert_eq!(Claimable::<Test>::get(LOCATION.to_vec(), 50), 1);
+				assert!(take_events().iter().any(|event| matches!(
+					event,
+					TestEvent::PhalaAssetsBridge(Event::ReleaseFailed { dest: 50, amount: 1, .. })
+				)));
+
+				assert_noop!(
+					PhalaAssetsBridge::claim(Origin::signed(2), LOCATION.to_vec()),
+					Error::<Test>::NothingToClaim
+				);
+				assert!(PhalaAssetsBridge::claim(Origin::signed(50), LOCATION.to_vec()).is_err());
+				assert_ok!(Balances::transfer(Origin::signed(1), 50, DOLLARS));
+				assert_ok!(PhalaAssetsBridge::claim(Origin::signed(50), LOCATION.to_vec()));
+				assert_eq!(Balances::free_balance(50), DOLLARS + 1);
+				assert_eq!(Claimable::<Test>::get(LOCATION.to_vec(), 50), 0);
+			});
+		}
+
+		#[test]
+		fn replaced_contract_can_unwrap() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				assert_ok!(PhalaAssetsBridge::set_assets_contract(Origin::root(), contract()));
+				assert_ok!(PhalaAssetsBridge::wrap(
+					Origin::signed(1),
+					LOCATION.to_vec(),
+					10 * DOLLARS
+				));
+				let replacement = ContractId::repeat_byte(2);
+				assert_ok!(PhalaAssetsBridge::set_assets_contract(Origin::root(), replacement));
+				assert!(RetiredContracts::<Test>::contains_key(contract()));
+
+				let free = Balances::free_balance(2);
+				let sender = MessageOrigin::Contract(contract());
+				assert_ok!(PhalaAssetsBridge::on_unwrap_message_received(unwrap_message(
+					sender,
+					2,
+					4 * DOLLARS
+				)));
+				assert_eq!(Balances::free_balance(2), free + 4 * DOLLARS);
+
+				// Setting the replaced contract back makes it the current one again
+				assert_ok!(PhalaAssetsBridge::set_assets_contract(Origin::root(), contract()));
+				assert!(!RetiredContracts::<Test>::contains_key(contract()));
+				assert!(RetiredContracts::<Test>::contains_key(replacement));
+			});
+		}
+	}
+}
@@ -0,0 +1,184 @@
+//! # Ballot Pallet
+//!
+//! Feeds the voting weights to the Ballot contract. An account sets its weight by locking that
+//! amount of its balance, and the new weight is sent to the contract in the same call. As the
+//! locked tokens can't be moved, the same tokens are never counted for two voters.
+
+pub use self::pallet::*;
+
+use frame_support::traits::Currency;
+
+type BalanceOf<T> =
+	<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+
+#[frame_support::pallet]
+pub mod pallet {
+	use super::BalanceOf;
+	use frame_support::{
+		dispatch::DispatchResult,
+		pallet_prelude::*,
+		traits::{Currency, LockIdentifier, LockableCurrency, StorageVersion, WithdrawReasons},
+	};
+	use frame_system::pallet_prelude::*;
+	use sp_runtime::traits::Zero;
+	use sp_std::prelude::*;
+
+	use crate::mq::MessageOriginInfo;
+
+	use phala_types::{
+		contract::{command_topic, ContractId},
+		messaging::{BallotCommand, CommandPayload},
+	};
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config + crate::mq::Config {
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+		type Currency: LockableCurrency<Self::AccountId>;
+	}
+
+	const STORAGE_VERSION: StorageVersion = StorageVersion::new(0);
+	const BALLOT_ID: LockIdentifier = *b"phala/bl";
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	#[pallet::storage_version(STORAGE_VERSION)]
+	#[pallet::without_storage_info]
+	pub struct Pallet<T>(_);
+
+	/// The Ballot contract receiving the voting weights
+	#[pallet::storage]
+	pub type BallotContract<T> = StorageValue<_, ContractId>;
+
+	#[pallet::event]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
+		BallotContractSet { contract: ContractId },
+		/// The voting weight of an account is locked and sent to the Ballot contract
+		WeightSet {
+			who: T::AccountId,
+			amount: BalanceOf<T>,
+		},
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		/// No Ballot contract is set to receive the weights
+		BallotContractNotSet,
+		/// The weight is more than the free balance
+		InsufficientBalance,
+	}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Sets the Ballot contract to receive the voting weights
+		///
+		/// Can only be called by root.
+		#[pallet::weight(0)]
+		pub fn set_ballot_contract(origin: OriginFor<T>, contract: ContractId) -> DispatchResult {
+			ensure_root(origin)?;
+			BallotContract::<T>::put(contract);
+			Self::deposit_event(Event::<T>::BallotContractSet { contract });
+			Ok(())
+		}
+
+		/// Locks `amount` of the free balance of the sender as its voting weight, replacing the
+		/// previous lock. A zero amount unlocks the balance and withdraws the votes of the sender.
+		///
+		/// The votes are counted with the weights at the deadline of a proposal.
+		#[pallet::weight(0)]
+		pub fn set_voting_weight(origin: OriginFor<T>, amount: BalanceOf<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let contract = BallotContract::<T>::get().ok_or(Error::<T>::BallotContractNotSet)?;
+			ensure!(
+				amount <= T::Currency::free_balance(&who),
+				Error::<T>::InsufficientBalance
+			);
+			if amount.is_zero() {
+				T::Currency::remove_lock(BALLOT_ID, &who);
+			} else {
+				T::Currency::set_lock(BALLOT_ID, &who, amount, WithdrawReasons::all());
+			}
+			let command = BallotCommand::<_, _, T::BlockNumber>::UpdateWeights {
+				weights: vec![(who.clone(), amount)],
+			};
+			Self::push_message_to(command_topic(contract), CommandPayload::Plain(command));
+			Self::deposit_event(Event::<T>::WeightSet { who, amount });
+			Ok(())
+		}
+	}
+
+	impl<T: Config> MessageOriginInfo for Pallet<T> {
+		type Config = T;
+	}
+
+	#[cfg(test)]
+	mod test {
+		use super::*;
+		use crate::mock::{new_test_ext, set_block_1, take_messages, Origin, Test, DOLLARS};
+		// Pallets
+		use crate::mock::{Balances, PhalaBallot};
+		use frame_support::{assert_noop, assert_ok, traits::ExistenceRequirement::AllowDeath};
+
+		fn contract() -> ContractId {
+			ContractId::repeat_byte(1)
+		}
+
+		fn weight_command(who: u64, amount: u128) -> Vec<u8> {
+			CommandPayload::Plain(BallotCommand::<u64, u128, u64>::UpdateWeights {
+				weights: vec![(who, amount)],
+			})
+			.encode()
+		}
+
+		#[test]
+		fn weights_are_locked_balances() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				assert_noop!(
+					PhalaBallot::set_voting_weight(Origin::signed(1), 100 * DOLLARS),
+					Error::<Test>::BallotContractNotSet
+				);
+				assert_noop!(
+					PhalaBallot::set_ballot_contract(Origin::signed(1), contract()),
+					DispatchError::BadOrigin
+				);
+				assert_ok!(PhalaBallot::set_ballot_contract(Origin::root(), contract()));
+				assert_noop!(
+					PhalaBallot::set_voting_weight(Origin::signed(1), 1001 * DOLLARS),
+					Error::<Test>::InsufficientBalance
+				);
+
+				take_messages();
+				assert_ok!(PhalaBallot::set_voting_weight(Origin::signed(1), 600 * DOLLARS));
+				let messages = take_messages();
+				assert_eq!(messages.len(), 1);
+				assert_eq!(messages[0].sender, PhalaBallot::message_origin());
+				assert_eq!(messages[0].destination.path(), &command_topic(contract()));
+				assert_eq!(messages[0].payload, weight_command(1, 600 * DOLLARS));
+
+				// The locked weight can't be moved to another voter
+				assert!(
+					<Balances as Currency<u64>>::transfer(&1, &2, 500 * DOLLARS, AllowDeath)
+						.is_err()
+				);
+				assert_ok!(<Balances as Currency<u64>>::transfer(
+					&1,
+					&2,
+					400 * DOLLARS,
+					AllowDeath
+				));
+
+				// Unlocked by setting the weight to zero
+				assert_ok!(PhalaBallot::set_voting_weight(Origin::signed(1), 0));
+				assert_eq!(take_messages()[0].payload, weight_command(1, 0));
+				assert_ok!(<Balances as Currency<u64>>::transfer(
+					&1,
+					&2,
+					500 * DOLLARS,
+					AllowDeath
+				));
+			});
+		}
+	}
+}
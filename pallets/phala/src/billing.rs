@@ -0,0 +1,443 @@
+//! # Contract Billing Pallet
+//!
+//! Settles the contract usage fees. The workers in a cluster periodically report the metered
+//! usage of the contracts they served, and the fees are paid to the operator of the worker:
+//!
+//! - The signed queries are paid by their origins. The unsigned queries are free, so that an
+//!   operator can't make up paid queries with its own worker.
+//! - The messages pushed to the sidevm instances are paid from the balance of the cluster.
+//!   Cluster owners (or anyone) deposit to the balance, and the remaining balance is refunded to
+//!   the owner when the cluster is destroyed.
+//!
+//! The fees paid for a report are capped by `MaxFeePerReport`.
+
+pub use self::pallet::*;
+
+#[frame_support::pallet]
+pub mod pallet {
+	use frame_support::{
+		dispatch::DispatchResult,
+		pallet_prelude::*,
+		traits::{
+			Currency,
+			ExistenceRequirement::{AllowDeath, KeepAlive},
+			StorageVersion,
+		},
+		PalletId,
+	};
+	use frame_system::pallet_prelude::*;
+	use sp_runtime::{
+		traits::{AccountIdConversion, Saturating, Zero},
+		SaturatedConversion,
+	};
+	use sp_std::prelude::*;
+
+	use crate::{fat, registry};
+
+	use phala_types::{
+		contract::{messaging::WorkerUsageReport, ContractClusterId},
+		messaging::{DecodedMessage, MessageOrigin},
+		WorkerPublicKey,
+	};
+
+	const BILLING_PALLETID: PalletId = PalletId(*b"phala/bl");
+
+	type BalanceOf<T> =
+		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+
+	/// Provides the cluster information from the contract registry
+	pub trait ClusterInfoProvider<AccountId> {
+		fn cluster_owner(cluster: &ContractClusterId) -> Option<AccountId>;
+		fn is_cluster_worker(cluster: &ContractClusterId, worker: &WorkerPublicKey) -> bool;
+	}
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config + registry::Config {
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+		type Currency: Currency<Self::AccountId>;
+
+		type Clusters: ClusterInfoProvider<Self::AccountId>;
+
+		/// The fee of a contract query
+		#[pallet::constant]
+		type QueryPrice: Get<BalanceOf<Self>>;
+
+		/// The fee of a message pushed to a sidevm instance
+		#[pallet::constant]
+		type SidevmMessagePrice: Get<BalanceOf<Self>>;
+
+		/// The max fees paid for a usage report
+		#[pallet::constant]
+		type MaxFeePerReport: Get<BalanceOf<Self>>;
+	}
+
+	const STORAGE_VERSION: StorageVersion = StorageVersion::new(0);
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	#[pallet::storage_version(STORAGE_VERSION)]
+	#[pallet::without_storage_info]
+	pub struct Pallet<T>(_);
+
+	/// The balance of the clusters to pay the usage fees
+	#[pallet::storage]
+	pub type ClusterBalances<T: Config> =
+		StorageMap<_, Twox64Concat, ContractClusterId, BalanceOf<T>, ValueQuery>;
+
+	#[pallet::event]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
+		/// Some balance is deposited to a cluster
+		ClusterDeposited {
+			cluster: ContractClusterId,
+			who: T::AccountId,
+			amount: BalanceOf<T>,
+		},
+		/// Some balance is withdrawn from a cluster by its owner
+		ClusterWithdrawn {
+			cluster: ContractClusterId,
+			amount: BalanceOf<T>,
+		},
+		/// The remaining balance of a destroyed cluster is refunded to its owner
+		ClusterRefunded {
+			cluster: ContractClusterId,
+			owner: T::AccountId,
+			amount: BalanceOf<T>,
+		},
+		/// The usage reported by a worker is paid to its operator
+		///
+		/// `fee` can be less than the metered usage if the query origins or the cluster balance
+		/// run out, or if it exceeds `MaxFeePerReport`.
+		UsageSettled {
+			cluster: ContractClusterId,
+			worker: WorkerPublicKey,
+			queries: u64,
+			sidevm_messages: u64,
+			fee: BalanceOf<T>,
+		},
+		/// The usage is not paid because the worker has no operator
+		UsageDismissed {
+			cluster: ContractClusterId,
+			worker: WorkerPublicKey,
+		},
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		ClusterNotFound,
+		NotClusterOwner,
+		InsufficientClusterBalance,
+		InvalidSender,
+		/// The reporting worker is not in the cluster
+		WorkerNotInCluster,
+	}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Deposits to the balance of a cluster
+		#[pallet::weight(0)]
+		pub fn deposit_to_cluster(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			amount: BalanceOf<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(
+				T::Clusters::cluster_owner(&cluster).is_some(),
+				Error::<T>::ClusterNotFound
+			);
+			<T as Config>::Currency::transfer(&who, &Self::account_id(), amount, KeepAlive)?;
+			ClusterBalances::<T>::mutate(&cluster, |balance| balance.saturating_accrue(amount));
+			Self::deposit_event(Event::<T>::ClusterDeposited {
+				cluster,
+				who,
+				amount,
+			});
+			Ok(())
+		}
+
+		/// Withdraws from the balance of a cluster
+		///
+		/// Can only be called by the cluster owner.
+		#[pallet::weight(0)]
+		pub fn withdraw_from_cluster(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			amount: BalanceOf<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let owner = T::Clusters::cluster_owner(&cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(owner == who, Error::<T>::NotClusterOwner);
+			let balance = ClusterBalances::<T>::get(&cluster);
+			ensure!(balance >= amount, Error::<T>::InsufficientClusterBalance);
+			<T as Config>::Currency::transfer(&Self::account_id(), &who, amount, AllowDeath)?;
+			ClusterBalances::<T>::insert(&cluster, balance - amount);
+			Self::deposit_event(Event::<T>::ClusterWithdrawn { cluster, amount });
+			Ok(())
+		}
+	}
+
+	impl<T: Config> Pallet<T> {
+		pub fn account_id() -> T::AccountId {
+			BILLING_PALLETID.into_account()
+		}
+
+		pub fn on_usage_report_received(
+			message: DecodedMessage<WorkerUsageReport<T::BlockNumber, T::AccountId>>,
+		) -> DispatchResult {
+			let worker = match message.sender {
+				MessageOrigin::Worker(worker) => worker,
+				_ => return Err(Error::<T>::InvalidSender.into()),
+			};
+			let report = message.payload;
+			let cluster = report.cluster;
+			ensure!(
+				T::Clusters::is_cluster_worker(&cluster, &worker),
+				Error::<T>::WorkerNotInCluster
+			);
+
+			let (queries, sidevm_messages) =
+				report
+					.usage
+					.iter()
+					.fold((0u64, 0u64), |(queries, messages), (_, usage)| {
+						(
+							queries.saturating_add(usage.queries),
+							messages.saturating_add(usage.sidevm_messages),
+						)
+					});
+			let operator = match registry::Workers::<T>::get(&worker).and_then(|w| w.operator) {
+				Some(operator) => operator,
+				None => {
+					Self::deposit_event(Event::<T>::UsageDismissed { cluster, worker });
+					return Ok(());
+				}
+			};
+
+			let max_fee = T::MaxFeePerReport::get();
+			let mut fee = BalanceOf::<T>::zero();
+			for (origin, count) in report.query_origins.iter() {
+				let spendable = <T as Config>::Currency::free_balance(origin)
+					.saturating_sub(<T as Config>::Currency::minimum_balance());
+				let query_fee = T::QueryPrice::get()
+					.saturating_mul((*count).saturated_into())
+					.min(spendable)
+					.min(max_fee - fee);
+				// The queries an origin can't afford are left unpaid without failing the report.
+				let paid =
+					<T as Config>::Currency::transfer(origin, &operator, query_fee, KeepAlive);
+				if paid.is_ok() {
+					fee += query_fee;
+				}
+			}
+			let balance = ClusterBalances::<T>::get(&cluster);
+			let sidevm_fee = T::SidevmMessagePrice::get()
+				.saturating_mul(sidevm_messages.saturated_into())
+				.min(balance)
+				.min(max_fee - fee);
+			<T as Config>::Currency::transfer(
+				&Self::account_id(),
+				&operator,
+				sidevm_fee,
+				AllowDeath,
+			)?;
+			ClusterBalances::<T>::insert(&cluster, balance - sidevm_fee);
+			fee += sidevm_fee;
+			Self::deposit_event(Event::<T>::UsageSettled {
+				cluster,
+				worker,
+				queries,
+				sidevm_messages,
+				fee,
+			});
+			Ok(())
+		}
+	}
+
+	impl<T: Config> fat::OnClusterDestroyed<T::AccountId> for Pallet<T> {
+		fn on_cluster_destroyed(
+			cluster: &ContractClusterId,
+			owner: &T::AccountId,
+		) -> DispatchResult {
+			let balance = ClusterBalances::<T>::get(cluster);
+			if balance.is_zero() {
+				return Ok(());
+			}
+			<T as Config>::Currency::transfer(&Self::account_id(), owner, balance, AllowDeath)?;
+			ClusterBalances::<T>::remove(cluster);
+			Self::deposit_event(Event::<T>::ClusterRefunded {
+				cluster: *cluster,
+				owner: owner.clone(),
+				amount: balance,
+			});
+			Ok(())
+		}
+	}
+
+	#[cfg(test)]
+	mod test {
+		use super::*;
+		use crate::mock::{
+			new_test_ext, set_block_1, setup_workers, take_events, worker_pubkey, ClusterDeposit,
+			Event as TestEvent, Origin, Test, CENTS, DOLLARS,
+		};
+		// Pallets
+		use crate::mock::{Balances, PhalaBilling, PhalaFatContracts};
+		use frame_support::{assert_noop, assert_ok};
+		use phala_types::{
+			contract::{ClusterPermission, ContractId, ContractUsage},
+			messaging::Topic,
+		};
+
+		fn cluster() -> ContractClusterId {
+			ContractClusterId::from_low_u64_be(0)
+		}
+
+		/// A report of `queries` signed by account 3, and `sidevm_messages`
+		fn report(
+			worker: u8,
+			queries: u64,
+			sidevm_messages: u64,
+		) -> DecodedMessage<WorkerUsageReport<u64, u64>> {
+			DecodedMessage {
+				sender: MessageOrigin::Worker(worker_pubkey(worker)),
+				destination: Topic::new(*b"phala/contract/worker/usage"),
+				payload: WorkerUsageReport {
+					cluster: cluster(),
+					block_number: 1,
+					usage: vec![(
+						ContractId::repeat_byte(1),
+						ContractUsage {
+							queries,
+							sidevm_messages,
+						},
+					)],
+					query_origins: vec![(3, queries)],
+				},
+			}
+		}
+
+		#[test]
+		fn usage_is_settled_from_cluster_balance() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(2);
+				assert_noop!(
+					PhalaBilling::deposit_to_cluster(
+						Origin::signed(1),
+						ContractClusterId::from_low_u64_be(1),
+						DOLLARS
+					),
+					Error::<Test>::ClusterNotFound
+				);
+				assert_ok!(PhalaBilling::deposit_to_cluster(
+					Origin::signed(2),
+					cluster(),
+					DOLLARS
+				));
+				assert_eq!(ClusterBalances::<Test>::get(cluster()), DOLLARS);
+
+				// Worker 2 is not in the cluster
+				assert_noop!(
+					PhalaBilling::on_usage_report_received(report(2, 1, 0)),
+					Error::<Test>::WorkerNotInCluster
+				);
+
+				// The queries are paid by their origin (account 3), and the sidevm messages by
+				// the cluster, to the operator (account 1)
+				let free = Balances::free_balance(1);
+				let origin_free = Balances::free_balance(3);
+				take_events();
+				assert_ok!(PhalaBilling::on_usage_report_received(report(1, 10, 5)));
+				let query_fee = 10 * <Test as Config>::QueryPrice::get();
+				let sidevm_fee = 5 * <Test as Config>::SidevmMessagePrice::get();
+				let fee = query_fee + sidevm_fee;
+				assert_eq!(Balances::free_balance(1), free + fee);
+				assert_eq!(Balances::free_balance(3), origin_free - query_fee);
+				assert_eq!(ClusterBalances::<Test>::get(cluster()), DOLLARS - sidevm_fee);
+				assert!(take_events().contains(&TestEvent::PhalaBilling(Event::UsageSettled {
+					cluster: cluster(),
+					worker: worker_pubkey(1),
+					queries: 10,
+					sidevm_messages: 5,
+					fee,
+				})));
+
+				// Only the owner can withdraw
+				assert_noop!(
+					PhalaBilling::withdraw_from_cluster(Origin::signed(2), cluster(), 1),
+					Error::<Test>::NotClusterOwner
+				);
+				assert_ok!(PhalaBilling::withdraw_from_cluster(
+					Origin::signed(1),
+					cluster(),
+					DOLLARS - sidevm_fee - CENTS
+				));
+				// The sidevm fee is capped by the cluster balance
+				let free = Balances::free_balance(1);
+				assert_ok!(PhalaBilling::on_usage_report_received(report(1, 0, 10)));
+				assert_eq!(Balances::free_balance(1), free + CENTS);
+				assert_eq!(ClusterBalances::<Test>::get(cluster()), 0);
+			});
+		}
+
+		#[test]
+		fn fees_are_capped_per_report() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				assert_ok!(PhalaBilling::deposit_to_cluster(Origin::signed(2), cluster(), DOLLARS));
+				let max_fee = <Test as Config>::MaxFeePerReport::get();
+				let queries = (max_fee / <Test as Config>::QueryPrice::get()) as u64;
+
+				let free = Balances::free_balance(1);
+				assert_ok!(PhalaBilling::on_usage_report_received(report(1, queries * 2, 10)));
+				assert_eq!(Balances::free_balance(1), free + max_fee);
+				// Nothing is left for the sidevm messages
+				assert_eq!(ClusterBalances::<Test>::get(cluster()), DOLLARS);
+			});
+		}
+
+		#[test]
+		fn unsigned_queries_are_free() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				assert_ok!(PhalaBilling::deposit_to_cluster(Origin::signed(2), cluster(), DOLLARS));
+				// An operator can't make up paid queries without the signatures of the origins
+				let mut message = report(1, 1000, 0);
+				message.payload.query_origins.clear();
+				let free = Balances::free_balance(1);
+				assert_ok!(PhalaBilling::on_usage_report_received(message));
+				assert_eq!(Balances::free_balance(1), free);
+				assert_eq!(ClusterBalances::<Test>::get(cluster()), DOLLARS);
+			});
+		}
+
+		#[test]
+		fn remaining_balance_is_refunded_on_destroy() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				assert_ok!(PhalaFatContracts::add_cluster(
+					Origin::signed(1),
+					ClusterPermission::Public,
+					vec![worker_pubkey(1)]
+				));
+				assert_ok!(PhalaBilling::deposit_to_cluster(Origin::signed(2), cluster(), DOLLARS));
+				let free = Balances::free_balance(1);
+				take_events();
+				assert_ok!(PhalaFatContracts::destroy_cluster(Origin::signed(1), cluster()));
+				assert_eq!(ClusterBalances::<Test>::get(cluster()), 0);
+				// The remaining balance plus the unreserved cluster deposit
+				assert_eq!(Balances::free_balance(1), free + DOLLARS + ClusterDeposit::get());
+				assert!(take_events().contains(&TestEvent::PhalaBilling(Event::ClusterRefunded {
+					cluster: cluster(),
+					owner: 1,
+					amount: DOLLARS,
+				})));
+			});
+		}
+	}
+}
@@ -5,9 +5,14 @@ pub use self::pallet::*;
 #[frame_support::pallet]
 pub mod pallet {
 	use codec::Encode;
-	use frame_support::{dispatch::DispatchResult, pallet_prelude::*, traits::StorageVersion};
+	use frame_support::{
+		dispatch::DispatchResult,
+		pallet_prelude::*,
+		traits::{Currency, ReservableCurrency, StorageVersion},
+	};
 	use frame_system::pallet_prelude::*;
 	use sp_core::H256;
+	use sp_runtime::traits::Hash;
 	use sp_std::prelude::*;
 
 	use crate::{mq::MessageOriginInfo, registry};
@@ -15,9 +20,10 @@ pub mod pallet {
 	pub use crate::attestation::{Attestation, IasValidator};
 
 	use phala_types::{
-		contract::messaging::{ClusterEvent, ContractOperation},
+		contract::messaging::{ClusterEvent, ContractCommandFailure, ContractOperation},
 		contract::{
-			ClusterInfo, ClusterPermission, CodeIndex, ContractClusterId, ContractId, ContractInfo,
+			contract_id_preimage, ClusterInfo, ClusterPermission, CodeIndex, ContractClusterId,
+			ContractId, ContractInfo,
 		},
 		messaging::{
 			bind_topic, DecodedMessage, MessageOrigin, WorkerClusterReport, WorkerContractReport,
@@ -43,9 +49,34 @@ pub mod pallet {
 		},
 	}
 
+	/// Called before a cluster is destroyed, to release the funds bound to it
+	pub trait OnClusterDestroyed<AccountId> {
+		/// The cluster is kept if an error is returned.
+		fn on_cluster_destroyed(
+			_cluster: &ContractClusterId,
+			_owner: &AccountId,
+		) -> DispatchResult {
+			Ok(())
+		}
+	}
+
+	impl<AccountId> OnClusterDestroyed<AccountId> for () {}
+
 	#[pallet::config]
 	pub trait Config: frame_system::Config {
 		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+		type Currency: ReservableCurrency<Self::AccountId>;
+
+		/// The amount reserved from the owner when creating a cluster, returned on destruction
+		#[pallet::constant]
+		type ClusterDeposit: Get<BalanceOf<Self>>;
+
+		/// The max number of code hashes in the code allowlist of a cluster
+		#[pallet::constant]
+		type MaxCodeAllowListLength: Get<u32>;
+
+		type OnClusterDestroyed: OnClusterDestroyed<Self::AccountId>;
 	}
 
 	const STORAGE_VERSION: StorageVersion = StorageVersion::new(5);
@@ -72,16 +103,51 @@ pub mod pallet {
 	pub type ClusterContracts<T: Config> =
 		StorageMap<_, Twox64Concat, ContractClusterId, Vec<ContractId>, ValueQuery>;
 
+	/// The clusters that have received any code or contract. The workers added to them later
+	/// would miss the cluster state, so no worker can be added.
+	#[pallet::storage]
+	pub type ClusterInUse<T> = StorageMap<_, Twox64Concat, ContractClusterId, bool, ValueQuery>;
+
 	#[pallet::storage]
 	pub type ClusterWorkers<T> =
 		StorageMap<_, Twox64Concat, ContractClusterId, Vec<WorkerPublicKey>, ValueQuery>;
 
+	/// The deposit reserved from the cluster owner
+	#[pallet::storage]
+	pub type ClusterDeposits<T: Config> =
+		StorageMap<_, Twox64Concat, ContractClusterId, BalanceOf<T>, ValueQuery>;
+
+	/// The wasm code hashes allowed to be uploaded to and instantiated in a cluster
+	///
+	/// A cluster without an entry accepts any code. The workers check the list again before
+	/// instantiating a contract.
+	#[pallet::storage]
+	pub type ClusterCodeAllowList<T: Config> =
+		StorageMap<_, Twox64Concat, ContractClusterId, Vec<CodeHash<T>>>;
+
 	#[pallet::event]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
 		ClusterCreated {
 			cluster: ContractClusterId,
 		},
+		ClusterDestroyed {
+			cluster: ContractClusterId,
+		},
+		ClusterWorkersAdded {
+			cluster: ContractClusterId,
+			workers: Vec<WorkerPublicKey>,
+		},
+		ClusterWorkersRemoved {
+			cluster: ContractClusterId,
+			workers: Vec<WorkerPublicKey>,
+		},
+		ClusterPermissionUpdated {
+			cluster: ContractClusterId,
+		},
+		ClusterCodeAllowListUpdated {
+			cluster: ContractClusterId,
+		},
 		ClusterPubkeyAvailable {
 			cluster: ContractClusterId,
 			pubkey: ClusterPublicKey,
@@ -120,6 +186,17 @@ pub mod pallet {
 			cluster: ContractClusterId,
 			deployer: H256,
 		},
+		/// A contract failed to handle a command
+		///
+		/// `error_code` is the index of the `TransactionError` variant in pRuntime. The origin and
+		/// the error of the commands sent by accounts are not disclosed, see
+		/// `ContractCommandFailure`.
+		CommandFailed {
+			contract: ContractId,
+			origin: Option<MessageOrigin>,
+			command_hash: H256,
+			error_code: u8,
+		},
 	}
 
 	#[pallet::error]
@@ -133,9 +210,16 @@ pub mod pallet {
 		NoWorkerSpecified,
 		InvalidSender,
 		WorkerNotFound,
+		NotClusterOwner,
+		CodeNotAllowed,
+		CodeAllowListTooLong,
+		/// Workers can only be added to a cluster without any code or contract
+		ClusterNotEmpty,
 	}
 
 	type CodeHash<T> = <T as frame_system::Config>::Hash;
+	type BalanceOf<T> =
+		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
 
 	fn check_cluster_permission<T: Config>(
 		deployer: &T::AccountId,
@@ -144,14 +228,39 @@ pub mod pallet {
 		match &cluster.permission {
 			ClusterPermission::Public => true,
 			ClusterPermission::OnlyOwner(owner) => deployer == owner,
+			ClusterPermission::AllowList(accounts) => {
+				deployer == &cluster.owner || accounts.contains(deployer)
+			}
 		}
 	}
 
+	fn check_code_allowed<T: Config>(cluster: &ContractClusterId, code_hash: &CodeHash<T>) -> bool {
+		match ClusterCodeAllowList::<T>::get(cluster) {
+			Some(allowlist) => allowlist.contains(code_hash),
+			None => true,
+		}
+	}
+
+	fn worker_identities<T: Config + registry::Config>(
+		workers: &[WorkerPublicKey],
+	) -> Result<Vec<WorkerIdentity>, Error<T>> {
+		workers
+			.iter()
+			.map(|worker| {
+				let worker_info =
+					registry::Workers::<T>::get(worker).ok_or(Error::<T>::WorkerNotFound)?;
+				Ok(WorkerIdentity {
+					pubkey: worker_info.pubkey,
+					ecdh_pubkey: worker_info.ecdh_pubkey,
+				})
+			})
+			.collect()
+	}
+
 	#[pallet::call]
 	impl<T: Config> Pallet<T>
 	where
 		T: crate::mq::Config + crate::registry::Config,
-		T::AccountId: AsRef<[u8]>,
 	{
 		#[pallet::weight(0)]
 		pub fn add_cluster(
@@ -163,17 +272,9 @@ pub mod pallet {
 			let origin: T::AccountId = ensure_signed(origin)?;
 
 			ensure!(deploy_workers.len() > 0, Error::<T>::NoWorkerSpecified);
-			let workers = deploy_workers
-				.iter()
-				.map(|worker| {
-					let worker_info =
-						registry::Workers::<T>::get(worker).ok_or(Error::<T>::WorkerNotFound)?;
-					Ok(WorkerIdentity {
-						pubkey: worker_info.pubkey,
-						ecdh_pubkey: worker_info.ecdh_pubkey,
-					})
-				})
-				.collect::<Result<Vec<WorkerIdentity>, Error<T>>>()?;
+			let workers = worker_identities::<T>(&deploy_workers)?;
+			let deposit = T::ClusterDeposit::get();
+			T::Currency::reserve(&origin, deposit)?;
 
 			let cluster_info = ClusterInfo {
 				owner: origin,
@@ -189,11 +290,156 @@ pub mod pallet {
 			let cluster = ContractClusterId::from_low_u64_be(cluster_id);
 
 			Clusters::<T>::insert(&cluster, &cluster_info);
+			ClusterDeposits::<T>::insert(&cluster, deposit);
 			Self::deposit_event(Event::ClusterCreated { cluster });
 			Self::push_message(ClusterEvent::DeployCluster { cluster, workers });
 			Ok(())
 		}
 
+		/// Destroys a cluster and returns the deposit to the owner.
+		///
+		/// The workers drop the cluster state, including all the contracts in it.
+		#[pallet::weight(0)]
+		pub fn destroy_cluster(origin: OriginFor<T>, cluster: ContractClusterId) -> DispatchResult {
+			let origin: T::AccountId = ensure_signed(origin)?;
+			let cluster_info = Clusters::<T>::get(cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(cluster_info.owner == origin, Error::<T>::NotClusterOwner);
+			T::OnClusterDestroyed::on_cluster_destroyed(&cluster, &origin)?;
+
+			for contract in ClusterContracts::<T>::take(&cluster) {
+				Contracts::<T>::remove(&contract);
+				registry::ContractKeys::<T>::remove(&contract);
+			}
+			// The cluster and its contracts can no longer send messages
+			registry::ClusterKeys::<T>::remove(&cluster);
+			ClusterWorkers::<T>::remove(&cluster);
+			ClusterInUse::<T>::remove(&cluster);
+			ClusterCodeAllowList::<T>::remove(&cluster);
+			Clusters::<T>::remove(&cluster);
+			let deposit = ClusterDeposits::<T>::take(&cluster);
+			T::Currency::unreserve(&origin, deposit);
+
+			Self::deposit_event(Event::ClusterDestroyed { cluster });
+			Self::push_message(ClusterEvent::DestroyCluster { cluster });
+			Ok(())
+		}
+
+		/// Adds workers to a cluster. The gatekeeper will share the cluster key with them.
+		///
+		/// The new workers only get the cluster key but not the cluster state, so the workers can
+		/// only be added before any code is uploaded to or any contract is instantiated in the
+		/// cluster.
+		#[pallet::weight(0)]
+		pub fn add_workers_to_cluster(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			workers: Vec<WorkerPublicKey>,
+		) -> DispatchResult {
+			let origin: T::AccountId = ensure_signed(origin)?;
+			let mut cluster_info =
+				Clusters::<T>::get(cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(cluster_info.owner == origin, Error::<T>::NotClusterOwner);
+			ensure!(!ClusterInUse::<T>::get(&cluster), Error::<T>::ClusterNotEmpty);
+
+			let new_workers: Vec<_> = workers
+				.into_iter()
+				.filter(|worker| !cluster_info.workers.contains(worker))
+				.collect();
+			ensure!(new_workers.len() > 0, Error::<T>::NoWorkerSpecified);
+			let identities = worker_identities::<T>(&new_workers)?;
+			cluster_info.workers.extend(new_workers.iter().cloned());
+			Clusters::<T>::insert(&cluster, &cluster_info);
+
+			Self::deposit_event(Event::ClusterWorkersAdded {
+				cluster,
+				workers: new_workers,
+			});
+			Self::push_message(ClusterEvent::AddWorkers {
+				cluster,
+				workers: identities,
+			});
+			Ok(())
+		}
+
+		/// Removes workers from a cluster. The removed workers drop the cluster state.
+		#[pallet::weight(0)]
+		pub fn remove_workers_from_cluster(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			workers: Vec<WorkerPublicKey>,
+		) -> DispatchResult {
+			let origin: T::AccountId = ensure_signed(origin)?;
+			let mut cluster_info =
+				Clusters::<T>::get(cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(cluster_info.owner == origin, Error::<T>::NotClusterOwner);
+			ensure!(workers.len() > 0, Error::<T>::NoWorkerSpecified);
+
+			cluster_info
+				.workers
+				.retain(|worker| !workers.contains(worker));
+			ensure!(
+				cluster_info.workers.len() > 0,
+				Error::<T>::NoWorkerSpecified
+			);
+			Clusters::<T>::insert(&cluster, &cluster_info);
+			ClusterWorkers::<T>::mutate(&cluster, |deployed| {
+				deployed.retain(|worker| !workers.contains(worker))
+			});
+
+			Self::deposit_event(Event::ClusterWorkersRemoved {
+				cluster,
+				workers: workers.clone(),
+			});
+			Self::push_message(ClusterEvent::RemoveWorkers { cluster, workers });
+			Ok(())
+		}
+
+		/// Updates the policy of who may upload code to and deploy contracts in the cluster.
+		#[pallet::weight(0)]
+		pub fn set_cluster_permission(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			permission: ClusterPermission<T::AccountId>,
+		) -> DispatchResult {
+			let origin: T::AccountId = ensure_signed(origin)?;
+			let mut cluster_info =
+				Clusters::<T>::get(cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(cluster_info.owner == origin, Error::<T>::NotClusterOwner);
+
+			cluster_info.permission = permission;
+			Clusters::<T>::insert(&cluster, &cluster_info);
+			Self::deposit_event(Event::ClusterPermissionUpdated { cluster });
+			Ok(())
+		}
+
+		/// Restricts the wasm code allowed in the cluster, or lifts the restriction with `None`.
+		///
+		/// Native contracts are only restricted by the cluster permission.
+		#[pallet::weight(
+			10_000
+				+ T::DbWeight::get().reads_writes(1, 1)
+				+ 1_000 * allowlist.as_ref().map_or(0, |list| list.len() as u64)
+		)]
+		pub fn set_cluster_code_allowlist(
+			origin: OriginFor<T>,
+			cluster: ContractClusterId,
+			allowlist: Option<Vec<CodeHash<T>>>,
+		) -> DispatchResult {
+			let origin: T::AccountId = ensure_signed(origin)?;
+			if let Some(list) = &allowlist {
+				ensure!(
+					list.len() <= T::MaxCodeAllowListLength::get() as usize,
+					Error::<T>::CodeAllowListTooLong
+				);
+			}
+			let cluster_info = Clusters::<T>::get(cluster).ok_or(Error::<T>::ClusterNotFound)?;
+			ensure!(cluster_info.owner == origin, Error::<T>::NotClusterOwner);
+
+			ClusterCodeAllowList::<T>::set(&cluster, allowlist);
+			Self::deposit_event(Event::ClusterCodeAllowListUpdated { cluster });
+			Ok(())
+		}
+
 		#[pallet::weight(0)]
 		pub fn upload_code_to_cluster(
 			origin: OriginFor<T>,
@@ -206,6 +452,11 @@ pub mod pallet {
 				check_cluster_permission::<T>(&origin, &cluster_info),
 				Error::<T>::ClusterPermissionDenied
 			);
+			ensure!(
+				check_code_allowed::<T>(&cluster_id, &T::Hashing::hash(&code)),
+				Error::<T>::CodeNotAllowed
+			);
+			ClusterInUse::<T>::insert(&cluster_id, true);
 			Self::push_message(
 				ContractOperation::<CodeHash<T>, T::AccountId>::UploadCodeToCluster {
 					origin,
@@ -230,6 +481,12 @@ pub mod pallet {
 				check_cluster_permission::<T>(&deployer, &cluster_info),
 				Error::<T>::ClusterPermissionDenied
 			);
+			if let CodeIndex::WasmCode(code_hash) = &code_index {
+				ensure!(
+					check_code_allowed::<T>(&cluster_id, code_hash),
+					Error::<T>::CodeNotAllowed
+				);
+			}
 
 			let contract_info = ContractInfo {
 				deployer,
@@ -238,12 +495,19 @@ pub mod pallet {
 				cluster_id,
 				instantiate_data: data,
 			};
-			let contract_id = contract_info.contract_id(crate::hashing::blake2_256);
+			// Same as `ContractInfo::contract_id`, since an `AccountId32` encodes to its raw bytes
+			let contract_id = ContractId::from(crate::hashing::blake2_256(&contract_id_preimage(
+				&contract_info.deployer.encode(),
+				&contract_info.code_index.code_hash(),
+				contract_info.cluster_id.as_ref(),
+				&contract_info.salt,
+			)));
 			ensure!(
 				!Contracts::<T>::contains_key(contract_id),
 				Error::<T>::DuplicatedContract
 			);
 			Contracts::<T>::insert(&contract_id, &contract_info);
+			ClusterInUse::<T>::insert(&contract_info.cluster_id, true);
 
 			Self::push_message(ContractOperation::instantiate_code(contract_info.clone()));
 			Self::deposit_event(Event::Instantiating {
@@ -297,6 +561,23 @@ pub mod pallet {
 			Ok(())
 		}
 
+		pub fn on_contract_command_failure_received(
+			message: DecodedMessage<ContractCommandFailure>,
+		) -> DispatchResult {
+			let contract = match message.sender {
+				MessageOrigin::Contract(contract) => contract,
+				_ => return Err(Error::<T>::InvalidSender.into()),
+			};
+			let failure = message.payload;
+			Self::deposit_event(Event::CommandFailed {
+				contract,
+				origin: failure.origin,
+				command_hash: failure.command_hash,
+				error_code: failure.error_code,
+			});
+			Ok(())
+		}
+
 		pub fn on_worker_cluster_message_received(
 			message: DecodedMessage<WorkerClusterReport>,
 		) -> DispatchResult {
@@ -379,4 +660,395 @@ pub mod pallet {
 	impl<T: Config + crate::mq::Config> MessageOriginInfo for Pallet<T> {
 		type Config = T;
 	}
+
+	impl<T: Config> crate::billing::ClusterInfoProvider<T::AccountId> for Pallet<T> {
+		fn cluster_owner(cluster: &ContractClusterId) -> Option<T::AccountId> {
+			Clusters::<T>::get(cluster).map(|info| info.owner)
+		}
+
+		fn is_cluster_worker(cluster: &ContractClusterId, worker: &WorkerPublicKey) -> bool {
+			ClusterWorkers::<T>::get(cluster).contains(worker)
+		}
+	}
+
+	#[cfg(test)]
+	mod test {
+		use super::*;
+		use crate::mock::{
+			new_test_ext, set_block_1, setup_workers, take_events, take_messages, worker_pubkey,
+			Balances, Event as TestEvent, Origin, Test, DOLLARS,
+		};
+		// Pallets
+		use crate::mock::PhalaFatContracts;
+		use frame_support::{assert_noop, assert_ok};
+		use phala_types::messaging::{BindTopic, Topic};
+
+		fn cluster_events() -> Vec<ClusterEvent> {
+			take_messages()
+				.iter()
+				.filter(|m| m.destination.path() == &ClusterEvent::topic())
+				.filter_map(|m| m.decode_payload())
+				.collect()
+		}
+
+		fn cluster(id: u64) -> ContractClusterId {
+			ContractClusterId::from_low_u64_be(id)
+		}
+
+		/// Creates cluster 0 owned by account1 with `worker_pubkey(1)` in it.
+		fn setup_cluster(permission: ClusterPermission<u64>) {
+			setup_workers(3);
+			assert_ok!(PhalaFatContracts::add_cluster(
+				Origin::signed(1),
+				permission,
+				vec![worker_pubkey(1)]
+			));
+			take_events();
+			take_messages();
+		}
+
+		#[test]
+		fn add_and_destroy_cluster() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers(1);
+				assert_noop!(
+					PhalaFatContracts::add_cluster(
+						Origin::signed(1),
+						ClusterPermission::Public,
+						vec![]
+					),
+					Error::<Test>::NoWorkerSpecified
+				);
+				assert_noop!(
+					PhalaFatContracts::add_cluster(
+						Origin::signed(1),
+						ClusterPermission::Public,
+						vec![worker_pubkey(2)]
+					),
+					Error::<Test>::WorkerNotFound
+				);
+				assert_ok!(PhalaFatContracts::add_cluster(
+					Origin::signed(1),
+					ClusterPermission::Public,
+					vec![worker_pubkey(1)]
+				));
+				assert_eq!(ClusterCounter::<Test>::get(), 1);
+				assert_eq!(Balances::reserved_balance(1), 10 * DOLLARS);
+				assert_eq!(ClusterDeposits::<Test>::get(cluster(0)), 10 * DOLLARS);
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaFatContracts(Event::ClusterCreated {
+						cluster: cluster(0)
+					})]
+				);
+				assert!(matches!(
+					&cluster_events()[..],
+					[ClusterEvent::DeployCluster { cluster: c, workers }]
+						if *c == cluster(0) && workers.len() == 1
+							&& workers[0].pubkey == worker_pubkey(1)
+				));
+
+				// Only the owner can destroy the cluster
+				assert_noop!(
+					PhalaFatContracts::destroy_cluster(Origin::signed(2), cluster(0)),
+					Error::<Test>::NotClusterOwner
+				);
+				assert_ok!(PhalaFatContracts::destroy_cluster(Origin::signed(1), cluster(0)));
+				assert_eq!(Balances::reserved_balance(1), 0);
+				assert!(Clusters::<Test>::get(cluster(0)).is_none());
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaFatContracts(Event::ClusterDestroyed {
+						cluster: cluster(0)
+					})]
+				);
+				assert!(matches!(
+					&cluster_events()[..],
+					[ClusterEvent::DestroyCluster { cluster: c }] if *c == cluster(0)
+				));
+				assert_noop!(
+					PhalaFatContracts::destroy_cluster(Origin::signed(1), cluster(0)),
+					Error::<Test>::ClusterNotFound
+				);
+			});
+		}
+
+		#[test]
+		fn add_and_remove_workers() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_cluster(ClusterPermission::Public);
+				assert_noop!(
+					PhalaFatContracts::add_workers_to_cluster(
+						Origin::signed(2),
+						cluster(0),
+						vec![worker_pubkey(2)]
+					),
+					Error::<Test>::NotClusterOwner
+				);
+				// Workers already in the cluster are skipped
+				assert_noop!(
+					PhalaFatContracts::add_workers_to_cluster(
+						Origin::signed(1),
+						cluster(0),
+						vec![worker_pubkey(1)]
+					),
+					Error::<Test>::NoWorkerSpecified
+				);
+				assert_ok!(PhalaFatContracts::add_workers_to_cluster(
+					Origin::signed(1),
+					cluster(0),
+					vec![worker_pubkey(1), worker_pubkey(2), worker_pubkey(3)]
+				));
+				assert_eq!(
+					Clusters::<Test>::get(cluster(0)).unwrap().workers,
+					vec![worker_pubkey(1), worker_pubkey(2), worker_pubkey(3)]
+				);
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaFatContracts(Event::ClusterWorkersAdded {
+						cluster: cluster(0),
+						workers: vec![worker_pubkey(2), worker_pubkey(3)],
+					})]
+				);
+				assert!(matches!(
+					&cluster_events()[..],
+					[ClusterEvent::AddWorkers { cluster: c, workers }]
+						if *c == cluster(0) && workers.len() == 2
+				));
+
+				assert_noop!(
+					PhalaFatContracts::remove_workers_from_cluster(
+						Origin::signed(2),
+						cluster(0),
+						vec![worker_pubkey(2)]
+					),
+					Error::<Test>::NotClusterOwner
+				);
+				assert_noop!(
+					PhalaFatContracts::remove_workers_from_cluster(
+						Origin::signed(1),
+						cluster(0),
+						vec![]
+					),
+					Error::<Test>::NoWorkerSpecified
+				);
+				// A cluster can't be left without workers
+				assert_noop!(
+					PhalaFatContracts::remove_workers_from_cluster(
+						Origin::signed(1),
+						cluster(0),
+						vec![worker_pubkey(1), worker_pubkey(2), worker_pubkey(3)]
+					),
+					Error::<Test>::NoWorkerSpecified
+				);
+				assert_ok!(PhalaFatContracts::remove_workers_from_cluster(
+					Origin::signed(1),
+					cluster(0),
+					vec![worker_pubkey(2)]
+				));
+				assert_eq!(
+					Clusters::<Test>::get(cluster(0)).unwrap().workers,
+					vec![worker_pubkey(1), worker_pubkey(3)]
+				);
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaFatContracts(Event::ClusterWorkersRemoved {
+						cluster: cluster(0),
+						workers: vec![worker_pubkey(2)],
+					})]
+				);
+				assert!(matches!(
+					&cluster_events()[..],
+					[ClusterEvent::RemoveWorkers { cluster: c, workers }]
+						if *c == cluster(0) && *workers == vec![worker_pubkey(2)]
+				));
+
+				// The workers added later would miss the uploaded code
+				assert_ok!(PhalaFatContracts::upload_code_to_cluster(
+					Origin::signed(1),
+					vec![0u8; 4],
+					cluster(0)
+				));
+				assert_noop!(
+					PhalaFatContracts::add_workers_to_cluster(
+						Origin::signed(1),
+						cluster(0),
+						vec![worker_pubkey(2)]
+					),
+					Error::<Test>::ClusterNotEmpty
+				);
+			});
+		}
+
+		#[test]
+		fn cluster_permission_is_enforced() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_cluster(ClusterPermission::OnlyOwner(1));
+				assert_noop!(
+					PhalaFatContracts::upload_code_to_cluster(
+						Origin::signed(2),
+						vec![0u8; 4],
+						cluster(0)
+					),
+					Error::<Test>::ClusterPermissionDenied
+				);
+				assert_ok!(PhalaFatContracts::upload_code_to_cluster(
+					Origin::signed(1),
+					vec![0u8; 4],
+					cluster(0)
+				));
+
+				assert_noop!(
+					PhalaFatContracts::set_cluster_permission(
+						Origin::signed(2),
+						cluster(0),
+						ClusterPermission::Public
+					),
+					Error::<Test>::NotClusterOwner
+				);
+				assert_ok!(PhalaFatContracts::set_cluster_permission(
+					Origin::signed(1),
+					cluster(0),
+					ClusterPermission::Public
+				));
+				assert_eq!(
+					Clusters::<Test>::get(cluster(0)).unwrap().permission,
+					ClusterPermission::Public
+				);
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaFatContracts(Event::ClusterPermissionUpdated {
+						cluster: cluster(0)
+					})]
+				);
+				assert_ok!(PhalaFatContracts::upload_code_to_cluster(
+					Origin::signed(2),
+					vec![0u8; 4],
+					cluster(0)
+				));
+			});
+		}
+
+		#[test]
+		fn code_allowlist_is_enforced() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_cluster(ClusterPermission::Public);
+				let code = vec![0u8; 4];
+				let code_hash = <Test as frame_system::Config>::Hashing::hash(&code);
+				let other = H256::repeat_byte(1);
+				let instantiate = |code_index| {
+					PhalaFatContracts::instantiate_contract(
+						Origin::signed(2),
+						code_index,
+						vec![],
+						vec![],
+						cluster(0),
+					)
+				};
+
+				assert_noop!(
+					PhalaFatContracts::set_cluster_code_allowlist(
+						Origin::signed(2),
+						cluster(0),
+						Some(vec![other])
+					),
+					Error::<Test>::NotClusterOwner
+				);
+				assert_noop!(
+					PhalaFatContracts::set_cluster_code_allowlist(
+						Origin::signed(1),
+						cluster(0),
+						Some(vec![other; 3])
+					),
+					Error::<Test>::CodeAllowListTooLong
+				);
+				assert_ok!(PhalaFatContracts::set_cluster_code_allowlist(
+					Origin::signed(1),
+					cluster(0),
+					Some(vec![other])
+				));
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaFatContracts(Event::ClusterCodeAllowListUpdated {
+						cluster: cluster(0)
+					})]
+				);
+				assert_noop!(
+					PhalaFatContracts::upload_code_to_cluster(
+						Origin::signed(2),
+						code.clone(),
+						cluster(0)
+					),
+					Error::<Test>::CodeNotAllowed
+				);
+				assert_noop!(
+					instantiate(CodeIndex::WasmCode(code_hash)),
+					Error::<Test>::CodeNotAllowed
+				);
+				// Native contracts are only restricted by the cluster permission
+				assert_ok!(instantiate(CodeIndex::NativeCode(1)));
+
+				assert_ok!(PhalaFatContracts::set_cluster_code_allowlist(
+					Origin::signed(1),
+					cluster(0),
+					Some(vec![other, code_hash])
+				));
+				assert_ok!(PhalaFatContracts::upload_code_to_cluster(
+					Origin::signed(2),
+					code.clone(),
+					cluster(0)
+				));
+				assert_ok!(instantiate(CodeIndex::WasmCode(code_hash)));
+
+				// Lift the restriction
+				assert_ok!(PhalaFatContracts::set_cluster_code_allowlist(
+					Origin::signed(1),
+					cluster(0),
+					None
+				));
+				assert_eq!(ClusterCodeAllowList::<Test>::get(cluster(0)), None);
+			});
+		}
+
+		#[test]
+		fn command_failure_is_reported() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let contract = ContractId::repeat_byte(1);
+				let failure = |sender| DecodedMessage {
+					sender,
+					destination: Topic::new(ContractCommandFailure::topic()),
+					payload: ContractCommandFailure {
+						origin: Some(MessageOrigin::Pallet(b"PhalaBallot".to_vec())),
+						command_hash: H256::repeat_byte(3),
+						error_code: 4,
+					},
+				};
+				// Only the contracts can report their failures
+				assert_noop!(
+					PhalaFatContracts::on_contract_command_failure_received(failure(
+						MessageOrigin::Pallet(b"PhalaFatContracts".to_vec())
+					)),
+					Error::<Test>::InvalidSender
+				);
+				take_events();
+				assert_ok!(PhalaFatContracts::on_contract_command_failure_received(failure(
+					MessageOrigin::Contract(contract)
+				)));
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaFatContracts(Event::CommandFailed {
+						contract,
+						origin: Some(MessageOrigin::Pallet(b"PhalaBallot".to_vec())),
+						command_hash: H256::repeat_byte(3),
+						error_code: 4,
+					})]
+				);
+			});
+		}
+	}
 }
@@ -7,6 +7,9 @@
 #[cfg(target_arch = "wasm32")]
 extern crate webpki_wasm as webpki;
 
+#[cfg(target_arch = "wasm32")]
+extern crate ring_wasm as ring;
+
 #[cfg(not(feature = "std"))]
 extern crate alloc;
 
@@ -16,20 +19,28 @@ use utils::{accumulator, attestation, balance_convert, constants, fixed_point};
 pub mod migrations;
 pub mod utils;
 
+pub mod assets_bridge;
+pub mod ballot;
+pub mod billing;
 pub mod fat;
 pub mod mining;
 pub mod mq;
 pub mod ott;
 pub mod puppets;
 pub mod registry;
+pub mod sidevm;
 pub mod stakepool;
 
 // Alias
+pub use assets_bridge as pallet_assets_bridge;
+pub use ballot as pallet_ballot;
+pub use billing as pallet_billing;
 pub use fat as pallet_fat;
 pub use mining as pallet_mining;
 pub use mq as pallet_mq;
 pub use ott as pallet_ott;
 pub use registry as pallet_registry;
+pub use sidevm as pallet_sidevm;
 pub use stakepool as pallet_stakepool;
 
 #[cfg(feature = "native")]
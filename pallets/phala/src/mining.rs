@@ -29,7 +29,7 @@ pub mod pallet {
 	use scale_info::TypeInfo;
 	use sp_core::U256;
 	use sp_runtime::{
-		traits::{AccountIdConversion, One, Zero},
+		traits::{AccountIdConversion, One, Saturating, Zero},
 		SaturatedConversion,
 	};
 	use sp_std::cmp;
@@ -175,6 +175,18 @@ pub mod pallet {
 		fn on_stopped(worker: &WorkerPublicKey, orig_stake: Balance, slashed: Balance) {}
 	}
 
+	pub trait OnUnresponsive {
+		/// Called when a miner enters the unresponsive state.
+		fn on_unresponsive(worker: &WorkerPublicKey) {}
+		/// Called when a miner is reported to be unresponsive for longer than the grace period.
+		///
+		/// The slash of the V is done by the Gatekeepers. This is the hook to apply additional
+		/// penalties or to notify the owner of the worker.
+		fn on_grace_period_expired(worker: &WorkerPublicKey) {}
+	}
+
+	impl OnUnresponsive for () {}
+
 	/// The stats of a mining session
 	#[derive(Encode, Decode, TypeInfo, Clone, PartialEq, Eq, Default, RuntimeDebug)]
 	pub struct MinerStats {
@@ -199,9 +211,14 @@ pub mod pallet {
 		type Randomness: Randomness<Self::Hash, Self::BlockNumber>;
 		type OnReward: OnReward;
 		type OnUnbound: OnUnbound;
+		// Let the StakePool to take over the slash events.
 		type OnStopped: OnStopped<BalanceOf<Self>>;
 		type OnTreasurySettled: OnUnbalanced<NegativeImbalanceOf<Self>>;
-		// Let the StakePool to take over the slash events.
+		type OnUnresponsive: OnUnresponsive;
+
+		/// The number of blocks a miner can stay unresponsive before it can be reported
+		#[pallet::constant]
+		type UnresponsiveGracePeriod: Get<Self::BlockNumber>;
 
 		/// The origin to update tokenomic.
 		type UpdateTokenomicOrigin: EnsureOrigin<Self::Origin>;
@@ -230,6 +247,13 @@ pub mod pallet {
 	#[pallet::getter(fn online_miners)]
 	pub type OnlineMiners<T> = StorageValue<_, u32, ValueQuery>;
 
+	/// The block number when a miner entered the unresponsive state.
+	///
+	/// Removed when the miner recovers, stops mining, or is reported.
+	#[pallet::storage]
+	pub type UnresponsiveSince<T: Config> =
+		StorageMap<_, Twox64Concat, T::AccountId, T::BlockNumber>;
+
 	/// The expected heartbeat count at every block (default: 20)
 	#[pallet::storage]
 	pub type ExpectedHeartbeatCount<T> = StorageValue<_, u32>;
@@ -325,6 +349,14 @@ pub mod pallet {
 		/// Affected states:
 		/// - the miner info at [`Miners`] is updated from `MiningIdle` to `MiningUnresponsive`
 		MinerEnterUnresponsive { miner: T::AccountId },
+		/// Miner has been unresponsive for longer than the grace period and got reported.
+		///
+		/// Affected states:
+		/// - the miner entry at [`UnresponsiveSince`] is removed
+		MinerUnresponsiveReported {
+			miner: T::AccountId,
+			worker: WorkerPublicKey,
+		},
 		/// Miner returns to responsive state.
 		///
 		/// Affected states:
@@ -397,12 +429,18 @@ pub mod pallet {
 		TooMuchStake,
 		/// Internal error. The tokenomic parameter is not set.
 		InternalErrorBadTokenomicParameters,
+		/// The new tokenomic parameters are out of the valid range.
+		InvalidTokenomicParameters,
 		/// Not permitted because the worker is already bound with another miner account.
 		DuplicateBoundWorker,
 		/// Indicating the initial benchmark score is too low to start mining.
 		BenchmarkTooLow,
 		/// Internal error. A miner should never start with existing stake in the storage.
 		InternalErrorCannotStartWithExistingStake,
+		/// Miner is not in `MiningUnresponsive` state.
+		MinerNotUnresponsive,
+		/// The miner is still within the unresponsive grace period.
+		UnresponsiveGracePeriodNotExpired,
 	}
 
 	type BalanceOf<T> =
@@ -482,15 +520,48 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Reports a miner which has been unresponsive for longer than the grace period
+		///
+		/// Can be called by anyone. A miner is reported at most once per unresponsive period.
+		#[pallet::weight(0)]
+		pub fn report_unresponsive_miner(
+			origin: OriginFor<T>,
+			miner: T::AccountId,
+		) -> DispatchResult {
+			ensure_signed(origin)?;
+			let worker = MinerBindings::<T>::get(&miner).ok_or(Error::<T>::MinerNotBound)?;
+			let miner_info = Miners::<T>::get(&miner).ok_or(Error::<T>::MinerNotFound)?;
+			ensure!(
+				miner_info.state == MinerState::MiningUnresponsive,
+				Error::<T>::MinerNotUnresponsive
+			);
+			let since =
+				UnresponsiveSince::<T>::get(&miner).ok_or(Error::<T>::MinerNotUnresponsive)?;
+			let now = frame_system::Pallet::<T>::block_number();
+			ensure!(
+				now >= since.saturating_add(T::UnresponsiveGracePeriod::get()),
+				Error::<T>::UnresponsiveGracePeriodNotExpired
+			);
+			UnresponsiveSince::<T>::remove(&miner);
+			T::OnUnresponsive::on_grace_period_expired(&worker);
+			Self::deposit_event(Event::<T>::MinerUnresponsiveReported { miner, worker });
+			Ok(())
+		}
+
 		/// Updates the tokenomic parameters at the end of this block.
 		///
-		/// Can only be called by the tokenomic admin.
+		/// The new parameters are broadcasted to the Gatekeepers, so the economics can be tuned
+		/// without a pRuntime release. Can only be called by the tokenomic admin.
 		#[pallet::weight(1)]
 		pub fn update_tokenomic(
 			origin: OriginFor<T>,
 			new_params: TokenomicParams,
 		) -> DispatchResult {
 			T::UpdateTokenomicOrigin::ensure_origin(origin)?;
+			ensure!(
+				Self::validate_tokenomic_parameters(&new_params),
+				Error::<T>::InvalidTokenomicParameters
+			);
 			ScheduledTokenomicUpdate::<T>::put(new_params);
 			Ok(())
 		}
@@ -618,6 +689,11 @@ pub mod pallet {
 						}
 						miner_info.state = MinerState::MiningUnresponsive;
 						Miners::<T>::insert(&account, &miner_info);
+						UnresponsiveSince::<T>::insert(
+							&account,
+							frame_system::Pallet::<T>::block_number(),
+						);
+						T::OnUnresponsive::on_unresponsive(&worker);
 						Self::deposit_event(Event::<T>::MinerEnterUnresponsive { miner: account });
 						Self::push_message(SystemEvent::new_worker_event(
 							worker,
@@ -639,6 +715,7 @@ pub mod pallet {
 						}
 						miner_info.state = MinerState::MiningIdle;
 						Miners::<T>::insert(&account, &miner_info);
+						UnresponsiveSince::<T>::remove(&account);
 						Self::deposit_event(Event::<T>::MinerExitUnresponsive { miner: account });
 						Self::push_message(SystemEvent::new_worker_event(
 							worker,
@@ -908,6 +985,7 @@ pub mod pallet {
 			miner_info.state = MinerState::MiningCoolingDown;
 			miner_info.cool_down_start = now;
 			Miners::<T>::insert(&miner, &miner_info);
+			UnresponsiveSince::<T>::remove(&miner);
 			OnlineMiners::<T>::mutate(|v| *v -= 1); // v cannot be 0
 
 			// Calculate remaining stake (assume there's no more slash after calling `stop_mining`)
@@ -934,6 +1012,17 @@ pub mod pallet {
 			MinerBindings::<T>::get(&miner).ok_or(Error::<T>::MinerNotBound)
 		}
 
+		/// Rejects the parameters that would stall or break the Gatekeepers' V computation.
+		fn validate_tokenomic_parameters(params: &TokenomicParams) -> bool {
+			let one = FixedPoint::from_num(1);
+			let ratio = |bits| FixedPoint::from_bits(bits) <= one;
+			params.heartbeat_window > 0
+				&& FixedPoint::from_bits(params.rho) >= one
+				&& ratio(params.slash_rate)
+				&& ratio(params.treasury_ratio)
+				&& FixedPoint::from_bits(params.v_max) > FixedPoint::from_num(0)
+		}
+
 		fn update_tokenomic_parameters(params: TokenomicParams) {
 			TokenomicParameters::<T>::put(params.clone());
 			Self::push_message(GatekeeperEvent::TokenomicParametersChanged(params));
@@ -1220,7 +1309,8 @@ pub mod pallet {
 		use super::*;
 		use crate::mock::{
 			elapse_seconds, new_test_ext, set_block_1, setup_workers, take_events, take_messages,
-			worker_pubkey, BlockNumber, Event as TestEvent, Origin, Test, DOLLARS,
+			worker_pubkey, BlockNumber, Event as TestEvent, Origin, Test, UnresponsiveGracePeriod,
+			DOLLARS,
 		};
 		// Pallets
 		use crate::mock::{PhalaMining, PhalaRegistry, System};
@@ -1447,6 +1537,35 @@ pub mod pallet {
 			});
 		}
 
+		#[test]
+		fn invalid_tokenomic_update_is_rejected() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let tokenomic = TokenomicParameters::<Test>::get().unwrap();
+				let mut params = tokenomic.clone();
+				params.heartbeat_window = 0;
+				assert_noop!(
+					PhalaMining::update_tokenomic(Origin::root(), params),
+					Error::<Test>::InvalidTokenomicParameters
+				);
+				let mut params = tokenomic.clone();
+				params.treasury_ratio = fp!(1.5).to_bits();
+				assert_noop!(
+					PhalaMining::update_tokenomic(Origin::root(), params),
+					Error::<Test>::InvalidTokenomicParameters
+				);
+				let mut params = tokenomic;
+				params.budget_per_block = fp!(50).to_bits();
+				params.heartbeat_window = 20;
+				assert_ok!(PhalaMining::update_tokenomic(Origin::root(), params.clone()));
+				PhalaMining::on_finalize(1);
+				assert_eq!(TokenomicParameters::<Test>::get(), Some(params.clone()));
+				let messages = take_messages();
+				assert!(messages.iter().any(|m| m.decode_payload::<GatekeeperEvent>()
+					== Some(GatekeeperEvent::TokenomicParametersChanged(params.clone()))));
+			});
+		}
+
 		#[test]
 		fn khala_tokenomics() {
 			new_test_ext().execute_with(|| {
@@ -1629,6 +1748,58 @@ pub mod pallet {
 			});
 		}
 
+		#[test]
+		fn report_unresponsive_miner() {
+			new_test_ext().execute_with(|| {
+				use phala_types::messaging::Topic;
+
+				set_block_1();
+				setup_workers(1);
+				PhalaRegistry::internal_set_benchmark(&worker_pubkey(1), Some(600));
+				assert_ok!(PhalaMining::bind(1, worker_pubkey(1)));
+				elapse_seconds(100);
+				assert_ok!(PhalaMining::start_mining(1, 3000 * DOLLARS));
+				assert_noop!(
+					PhalaMining::report_unresponsive_miner(Origin::signed(2), 1),
+					Error::<Test>::MinerNotUnresponsive
+				);
+				assert_ok!(PhalaMining::on_gk_message_received(DecodedMessage::<
+					MiningInfoUpdateEvent<BlockNumber>,
+				> {
+					sender: MessageOrigin::Gatekeeper,
+					destination: Topic::new(*b"^phala/mining/update"),
+					payload: MiningInfoUpdateEvent::<BlockNumber> {
+						block_number: 1,
+						timestamp_ms: 0,
+						offline: vec![worker_pubkey(1)],
+						recovered_to_online: vec![],
+						settle: vec![],
+					},
+				}));
+				assert_eq!(UnresponsiveSince::<Test>::get(1), Some(1));
+				assert_noop!(
+					PhalaMining::report_unresponsive_miner(Origin::signed(2), 1),
+					Error::<Test>::UnresponsiveGracePeriodNotExpired
+				);
+
+				System::set_block_number(1 + UnresponsiveGracePeriod::get());
+				take_events();
+				assert_ok!(PhalaMining::report_unresponsive_miner(Origin::signed(2), 1));
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaMining(Event::MinerUnresponsiveReported {
+						miner: 1,
+						worker: worker_pubkey(1),
+					})]
+				);
+				// Only reported once
+				assert_noop!(
+					PhalaMining::report_unresponsive_miner(Origin::signed(2), 1),
+					Error::<Test>::MinerNotUnresponsive
+				);
+			});
+		}
+
 		#[test]
 		fn phala_params_migration_not_crash() {
 			new_test_ext().execute_with(|| {
@@ -1,12 +1,14 @@
 use crate::{
 	attestation::{Attestation, AttestationValidator, Error as AttestationError, IasFields},
-	mining, mq, ott, registry, stakepool,
+	assets_bridge, ballot, billing, fat, mining, mq, ott, registry, sidevm, stakepool,
 };
 
 use frame_support::{
+	dispatch::{DispatchError, DispatchResult},
+	ensure,
 	pallet_prelude::ConstU32,
 	parameter_types,
-	traits::{GenesisBuild, OnFinalize, OnInitialize},
+	traits::{Currency, ExistenceRequirement::AllowDeath, GenesisBuild, OnFinalize, OnInitialize},
 };
 use frame_support_test::TestRandomness;
 use frame_system as system;
@@ -39,6 +41,11 @@ frame_support::construct_runtime!(
 		PhalaMining: mining::{Pallet, Event<T>, Storage, Config},
 		PhalaStakePool: stakepool::{Pallet, Event<T>},
 		PhalaOneshotTransfer: ott::{Pallet, Event<T>},
+		PhalaSidevm: sidevm::{Pallet, Event<T>, Storage},
+		PhalaBilling: billing::{Pallet, Event<T>, Storage},
+		PhalaFatContracts: fat::{Pallet, Event<T>, Storage},
+		PhalaAssetsBridge: assets_bridge::{Pallet, Event<T>, Storage},
+		PhalaBallot: ballot::{Pallet, Event<T>, Storage},
 	}
 );
 
@@ -56,6 +63,22 @@ parameter_types! {
 	pub const MaxPoolWorkers: u32 = 10;
 	pub const VerifyPRuntime: bool = false;
 	pub const VerifyRelaychainGenesisBlockHash: bool = true;
+	pub const MaxSidevmCodeSize: u32 = 1024;
+	pub const MaxSidevmMemoryPages: u32 = 256;
+	pub const MaxSidevmGrpcTargets: u32 = 4;
+	pub const MaxSidevmGrpcTargetLen: u32 = 64;
+	pub const EndpointLifetime: u64 = 3600;
+	pub const GatekeeperElectionPeriod: u64 = 10;
+	pub const MaxGatekeepers: u32 = 2;
+	pub const GatekeeperConfidenceLevel: u8 = 3;
+	pub const IngressRetentionPeriod: u64 = 100;
+	pub const UnresponsiveGracePeriod: u64 = 50;
+	pub const QueryPrice: Balance = 1 * CENTS;
+	pub const SidevmMessagePrice: Balance = 1 * CENTS;
+	pub const MaxUsageFeePerReport: Balance = 1 * DOLLARS;
+	pub const ClusterDeposit: Balance = 10 * DOLLARS;
+	pub const MaxCodeAllowListLength: u32 = 2;
+	pub const MaxAssetLocationLength: u32 = 4;
 }
 impl system::Config for Test {
 	type BaseCallFilter = frame_support::traits::Everything;
@@ -109,6 +132,7 @@ pub const CENTS: Balance = DOLLARS / 100;
 impl mq::Config for Test {
 	type QueueNotifyConfig = ();
 	type CallMatcher = MqCallMatcher;
+	type IngressRetentionPeriod = IngressRetentionPeriod;
 }
 
 pub struct MqCallMatcher;
@@ -129,6 +153,10 @@ impl registry::Config for Test {
 	type VerifyPRuntime = VerifyPRuntime;
 	type VerifyRelaychainGenesisBlockHash = VerifyRelaychainGenesisBlockHash;
 	type GovernanceOrigin = frame_system::EnsureRoot<Self::AccountId>;
+	type EndpointLifetime = EndpointLifetime;
+	type GatekeeperElectionPeriod = GatekeeperElectionPeriod;
+	type MaxGatekeepers = MaxGatekeepers;
+	type GatekeeperConfidenceLevel = GatekeeperConfidenceLevel;
 }
 
 impl mining::Config for Test {
@@ -140,6 +168,8 @@ impl mining::Config for Test {
 	type OnReward = PhalaStakePool;
 	type OnUnbound = PhalaStakePool;
 	type OnStopped = PhalaStakePool;
+	type OnUnresponsive = PhalaStakePool;
+	type UnresponsiveGracePeriod = UnresponsiveGracePeriod;
 	type OnTreasurySettled = ();
 	type UpdateTokenomicOrigin = frame_system::EnsureRoot<Self::AccountId>;
 }
@@ -161,6 +191,71 @@ impl ott::Config for Test {
 	type Currency = Balances;
 }
 
+impl sidevm::Config for Test {
+	type Event = Event;
+	type MaxCodeSize = MaxSidevmCodeSize;
+	type MaxMemoryPages = MaxSidevmMemoryPages;
+	type MaxGrpcTargets = MaxSidevmGrpcTargets;
+	type MaxGrpcTargetLen = MaxSidevmGrpcTargetLen;
+}
+
+/// A single cluster `0` owned by account1, with `worker_pubkey(1)` in it.
+pub struct MockClusters;
+impl billing::ClusterInfoProvider<u64> for MockClusters {
+	fn cluster_owner(cluster: &ContractClusterId) -> Option<u64> {
+		(*cluster == ContractClusterId::from_low_u64_be(0)).then(|| 1)
+	}
+	fn is_cluster_worker(cluster: &ContractClusterId, worker: &WorkerPublicKey) -> bool {
+		*cluster == ContractClusterId::from_low_u64_be(0) && *worker == worker_pubkey(1)
+	}
+}
+
+impl billing::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+	type Clusters = MockClusters;
+	type QueryPrice = QueryPrice;
+	type SidevmMessagePrice = SidevmMessagePrice;
+	type MaxFeePerReport = MaxUsageFeePerReport;
+}
+
+impl fat::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+	type ClusterDeposit = ClusterDeposit;
+	type MaxCodeAllowListLength = MaxCodeAllowListLength;
+	type OnClusterDestroyed = PhalaBilling;
+}
+
+/// The account holding the bridged assets
+pub const BRIDGE_ACCOUNT: u64 = 100;
+
+/// The native token at location `[0]` is the only foreign asset.
+pub struct MockForeignAssets;
+impl assets_bridge::ForeignAssets<u64, Balance> for MockForeignAssets {
+	fn lock(location: &[u8], who: &u64, amount: Balance) -> DispatchResult {
+		ensure!(location == [0], DispatchError::Other("Unknown asset"));
+		<Balances as Currency<u64>>::transfer(who, &BRIDGE_ACCOUNT, amount, AllowDeath)
+	}
+
+	fn release(location: &[u8], dest: &u64, amount: Balance) -> DispatchResult {
+		ensure!(location == [0], DispatchError::Other("Unknown asset"));
+		<Balances as Currency<u64>>::transfer(&BRIDGE_ACCOUNT, dest, amount, AllowDeath)
+	}
+}
+
+impl assets_bridge::Config for Test {
+	type Event = Event;
+	type Balance = Balance;
+	type ForeignAssets = MockForeignAssets;
+	type MaxLocationLength = MaxAssetLocationLength;
+}
+
+impl ballot::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+}
+
 pub struct MockValidator;
 impl AttestationValidator for MockValidator {
 	fn validate(
@@ -234,7 +329,7 @@ pub fn take_messages() -> Vec<Message> {
 	messages
 }
 
-use phala_types::{EcdhPublicKey, WorkerPublicKey};
+use phala_types::{contract::ContractClusterId, EcdhPublicKey, WorkerPublicKey};
 
 pub fn worker_pubkey(i: u8) -> WorkerPublicKey {
 	let mut raw = [0u8; 32];
@@ -16,12 +16,21 @@ pub mod pallet {
 		BindTopic, CommandPayload, ContractCommand, Message, MessageOrigin, Path, SignedMessage,
 	};
 	use primitive_types::H256;
+	use sp_runtime::traits::Saturating;
 	use sp_std::vec::Vec;
 
+	/// The offchain indexing key prefix of the archived ingress sequences
+	pub const PRUNED_INGRESS_PREFIX: &[u8] = b"phala/mq/pruned_ingress/";
+
 	#[pallet::config]
 	pub trait Config: frame_system::Config + crate::registry::Config {
 		type QueueNotifyConfig: QueueNotifyConfig;
 		type CallMatcher: CallMatcher<Self>;
+
+		/// The number of blocks to keep the ingress sequence of a retired sender after its last
+		/// message
+		#[pallet::constant]
+		type IngressRetentionPeriod: Get<Self::BlockNumber>;
 	}
 
 	const STORAGE_VERSION: StorageVersion = StorageVersion::new(5);
@@ -36,6 +45,11 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type OffchainIngress<T> = StorageMap<_, Twox64Concat, MessageOrigin, u64>;
 
+	/// The block number of the last ingress message coming from a certain sender (origin)
+	#[pallet::storage]
+	pub type OffchainIngressLastActive<T: Config> =
+		StorageMap<_, Twox64Concat, MessageOrigin, T::BlockNumber>;
+
 	#[pallet::storage]
 	pub type QueuedOutboundMessage<T> = StorageValue<_, Vec<Message>>;
 
@@ -51,6 +65,12 @@ pub mod pallet {
 		BadSender,
 		BadSequence,
 		BadDestination,
+		/// The sender can still send messages, so its ingress sequence can not be pruned
+		SenderNotRetired,
+		/// The sender has been active within the retention period
+		IngressStillRetained,
+		/// No ingress sequence is recorded for the sender
+		IngressNotFound,
 	}
 
 	#[pallet::call]
@@ -87,11 +107,44 @@ pub mod pallet {
 			crate::registry::Pallet::<T>::check_message(&signed_message)?;
 			// Update ingress
 			OffchainIngress::<T>::insert(sender.clone(), expected_seq + 1);
+			OffchainIngressLastActive::<T>::insert(
+				sender.clone(),
+				frame_system::Pallet::<T>::block_number(),
+			);
 			// Call dispatch_message
 			Self::dispatch_message(signed_message.message);
 			Ok(())
 		}
 
+		/// Prunes the ingress sequence of a retired sender
+		///
+		/// A sender is retired once its pubkey is removed from the registry, so no more message
+		/// from it (including a replayed one) can pass the signature check. Its ingress sequence is
+		/// removed after `IngressRetentionPeriod` blocks since its last message, and archived to
+		/// the offchain index if enabled on the node. Can be called by anyone.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(2, 2))]
+		pub fn prune_offchain_ingress(origin: OriginFor<T>, sender: MessageOrigin) -> DispatchResult {
+			ensure_signed(origin)?;
+			ensure!(
+				!crate::registry::Pallet::<T>::is_known_sender(&sender),
+				Error::<T>::SenderNotRetired
+			);
+			let sequence = OffchainIngress::<T>::get(&sender).ok_or(Error::<T>::IngressNotFound)?;
+			if let Some(last_active) = OffchainIngressLastActive::<T>::get(&sender) {
+				let now = frame_system::Pallet::<T>::block_number();
+				ensure!(
+					now >= last_active.saturating_add(T::IngressRetentionPeriod::get()),
+					Error::<T>::IngressStillRetained
+				);
+			}
+			OffchainIngress::<T>::remove(&sender);
+			OffchainIngressLastActive::<T>::remove(&sender);
+
+			let key = [PRUNED_INGRESS_PREFIX, &sender.encode()].concat();
+			sp_io::offchain_index::set(&key, &sequence.encode());
+			Ok(())
+		}
+
 		// Messaging API for end user.
 		// TODO.kevin: confirm the weight
 		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
@@ -249,6 +302,52 @@ pub mod pallet {
 			Pallet::<Self::Config>::queue_bound_message(Self::message_origin(), payload);
 		}
 	}
+
+	#[cfg(test)]
+	mod test {
+		use super::*;
+		use crate::mock::{new_test_ext, set_block_1, IngressRetentionPeriod, Origin, System, Test};
+		// Pallets
+		use crate::mock::PhalaMq;
+		use frame_support::{assert_noop, assert_ok};
+		use phala_types::messaging::ContractId;
+
+		#[test]
+		fn prune_retired_ingress() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let contract = ContractId::repeat_byte(1);
+				let sender = MessageOrigin::Contract(contract);
+				assert_noop!(
+					PhalaMq::prune_offchain_ingress(Origin::signed(1), sender.clone()),
+					Error::<Test>::IngressNotFound
+				);
+				OffchainIngress::<Test>::insert(&sender, 10);
+				OffchainIngressLastActive::<Test>::insert(&sender, 1);
+
+				// The contract pubkey is still registered
+				let pubkey = sp_core::sr25519::Public::from_raw([1; 32]);
+				crate::registry::ContractKeys::<Test>::insert(&contract, pubkey);
+				assert_noop!(
+					PhalaMq::prune_offchain_ingress(Origin::signed(1), sender.clone()),
+					Error::<Test>::SenderNotRetired
+				);
+				crate::registry::ContractKeys::<Test>::remove(&contract);
+				assert_noop!(
+					PhalaMq::prune_offchain_ingress(Origin::signed(1), sender.clone()),
+					Error::<Test>::IngressStillRetained
+				);
+
+				System::set_block_number(1 + IngressRetentionPeriod::get());
+				assert_ok!(PhalaMq::prune_offchain_ingress(
+					Origin::signed(1),
+					sender.clone()
+				));
+				assert_eq!(OffchainIngress::<Test>::get(&sender), None);
+				assert_eq!(OffchainIngressLastActive::<Test>::get(&sender), None);
+			});
+		}
+	}
 }
 
 /// Provides `SignedExtension` to check message sequence.
@@ -13,22 +13,24 @@ pub mod pallet {
 	use frame_system::pallet_prelude::*;
 	use scale_info::TypeInfo;
 	use sp_core::{sr25519, H256};
-	use sp_runtime::SaturatedConversion;
+	use sp_runtime::{traits::Zero, SaturatedConversion};
 	use sp_std::prelude::*;
 	use sp_std::{convert::TryFrom, vec};
 
-	use crate::attestation::{AttestationValidator, Error as AttestationError};
 	use crate::mq::MessageOriginInfo;
 	// Re-export
-	pub use crate::attestation::{Attestation, IasValidator};
+	pub use crate::attestation::{
+		Attestation, AttestationValidator, DcapCollateral, Error as AttestationError, IasFields,
+		IasValidator,
+	};
 
 	use phala_types::{
 		messaging::{
 			self, bind_topic, ContractClusterId, ContractId, DecodedMessage, GatekeeperChange,
 			GatekeeperLaunch, MessageOrigin, SignedMessage, SystemEvent, WorkerEvent,
 		},
-		ClusterPublicKey, ContractPublicKey, EcdhPublicKey, MasterPublicKey, WorkerPublicKey,
-		WorkerRegistrationInfo,
+		ClusterPublicKey, ContractPublicKey, EcdhPublicKey, MasterPublicKey, WorkerEndpointPayload,
+		WorkerPublicKey, WorkerRegistrationInfo,
 	};
 
 	bind_topic!(RegistryEvent, b"^phala/registry/event");
@@ -62,10 +64,30 @@ pub mod pallet {
 
 		/// Origin used to govern the pallet
 		type GovernanceOrigin: EnsureOrigin<Self::Origin>;
+
+		/// The number of seconds a signed worker endpoint record stays valid
+		#[pallet::constant]
+		type EndpointLifetime: Get<u64>;
+
+		/// The number of blocks between two gatekeeper elections, or zero to disable the elections
+		#[pallet::constant]
+		type GatekeeperElectionPeriod: Get<Self::BlockNumber>;
+
+		/// The max number of gatekeepers to elect
+		#[pallet::constant]
+		type MaxGatekeepers: Get<u32>;
+
+		/// The largest confidence level a gatekeeper candidate can have
+		#[pallet::constant]
+		type GatekeeperConfidenceLevel: Get<u8>;
 	}
 
 	const STORAGE_VERSION: StorageVersion = StorageVersion::new(5);
 
+	/// How many seconds the signing time of an endpoint record can be ahead of the chain time, to
+	/// tolerate the clock drift of the workers
+	const MAX_ENDPOINT_CLOCK_DRIFT: u64 = 300;
+
 	#[pallet::pallet]
 	#[pallet::generate_store(pub(super) trait Store)]
 	#[pallet::storage_version(STORAGE_VERSION)]
@@ -80,6 +102,17 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type GatekeeperMasterPubkey<T: Config> = StorageValue<_, MasterPublicKey>;
 
+	/// The workers running for the gatekeeper in the next elections
+	#[pallet::storage]
+	pub type GatekeeperCandidates<T: Config> = StorageValue<_, Vec<WorkerPublicKey>, ValueQuery>;
+
+	/// The gatekeepers in office which haven't confirmed to have received the master key
+	///
+	/// No gatekeeper is rotated out by the elections while any elected one is in this list.
+	#[pallet::storage]
+	pub type UnconfirmedGatekeepers<T: Config> =
+		StorageValue<_, Vec<WorkerPublicKey>, ValueQuery>;
+
 	/// Mapping from worker pubkey to WorkerInfo
 	#[pallet::storage]
 	pub type Workers<T: Config> =
@@ -115,10 +148,26 @@ pub mod pallet {
 	pub type RelaychainGenesisBlockHashAllowList<T: Config> =
 		StorageValue<_, Vec<H256>, ValueQuery>;
 
+	/// The public endpoints published by the workers
+	///
+	/// A record expires `EndpointLifetime` seconds after it was signed.
+	#[pallet::storage]
+	pub type Endpoints<T: Config> =
+		StorageMap<_, Twox64Concat, WorkerPublicKey, WorkerEndpointPayload>;
+
 	#[pallet::event]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
 		/// A new Gatekeeper is enabled on the blockchain
 		GatekeeperAdded(WorkerPublicKey),
+		/// A Gatekeeper is rotated out by an election
+		GatekeeperRemoved(WorkerPublicKey),
+		/// A worker starts running for the gatekeeper
+		GatekeeperCandidateAdded(WorkerPublicKey),
+		/// A worker stops running for the gatekeeper
+		GatekeeperCandidateRemoved(WorkerPublicKey),
+		/// A Gatekeeper confirms to have received the master key
+		GatekeeperKeyConfirmed(WorkerPublicKey),
 	}
 
 	#[pallet::error]
@@ -156,6 +205,25 @@ pub mod pallet {
 		PRuntimeRejected,
 		PRuntimeAlreadyExists,
 		PRuntimeNotFound,
+		// Endpoint related
+		EndpointExpired,
+		OutdatedEndpoint,
+		EndpointNotFound,
+		EndpointNotExpired,
+		EndpointSignedInFuture,
+		// Gatekeeper election related
+		NotWorkerOperator,
+		UnqualifiedCandidate,
+		DuplicatedCandidate,
+		CandidateNotFound,
+		// DCAP related
+		InvalidQuote,
+		InvalidPckCertChain,
+		InvalidQuoteSignature,
+		InvalidCollateral,
+		OutdatedCollateral,
+		InvalidQeIdentity,
+		CertificateRevoked,
 		// Additional
 		UnknownCluster,
 		NotImplemented,
@@ -256,7 +324,9 @@ pub mod pallet {
 						gatekeeper,
 						worker_info.ecdh_pubkey,
 					));
+					UnconfirmedGatekeepers::<T>::append(gatekeeper);
 				}
+				Self::deposit_event(Event::<T>::GatekeeperAdded(gatekeeper));
 			}
 			Ok(())
 		}
@@ -272,6 +342,61 @@ pub mod pallet {
 			Err(Error::<T>::NotImplemented.into())
 		}
 
+		/// Runs for the gatekeeper in the following elections
+		///
+		/// Can only be called by the operator of the worker. The worker must be benchmarked and
+		/// have a confidence level no larger than `GatekeeperConfidenceLevel`.
+		#[pallet::weight(0)]
+		pub fn add_gatekeeper_candidate(
+			origin: OriginFor<T>,
+			worker: WorkerPublicKey,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let worker_info = Workers::<T>::get(&worker).ok_or(Error::<T>::WorkerNotFound)?;
+			ensure!(
+				worker_info.operator.as_ref() == Some(&who),
+				Error::<T>::NotWorkerOperator
+			);
+			ensure!(
+				Self::is_qualified_gatekeeper(&worker_info),
+				Error::<T>::UnqualifiedCandidate
+			);
+			let mut candidates = GatekeeperCandidates::<T>::get();
+			ensure!(
+				!candidates.contains(&worker),
+				Error::<T>::DuplicatedCandidate
+			);
+			candidates.push(worker);
+			GatekeeperCandidates::<T>::put(candidates);
+			Self::deposit_event(Event::<T>::GatekeeperCandidateAdded(worker));
+			Ok(())
+		}
+
+		/// Stops running for the gatekeeper
+		///
+		/// An elected gatekeeper stays in office until it's rotated out by a later election.
+		#[pallet::weight(0)]
+		pub fn remove_gatekeeper_candidate(
+			origin: OriginFor<T>,
+			worker: WorkerPublicKey,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let worker_info = Workers::<T>::get(&worker).ok_or(Error::<T>::WorkerNotFound)?;
+			ensure!(
+				worker_info.operator.as_ref() == Some(&who),
+				Error::<T>::NotWorkerOperator
+			);
+			let mut candidates = GatekeeperCandidates::<T>::get();
+			let pos = candidates
+				.iter()
+				.position(|c| c == &worker)
+				.ok_or(Error::<T>::CandidateNotFound)?;
+			candidates.remove(pos);
+			GatekeeperCandidates::<T>::put(candidates);
+			Self::deposit_event(Event::<T>::GatekeeperCandidateRemoved(worker));
+			Ok(())
+		}
+
 		/// Registers a worker on the blockchain
 		///
 		/// Usually called by a bridging relayer program (`pherry` and `prb`). Can be called by
@@ -349,6 +474,69 @@ pub mod pallet {
 			Ok(())
 		}
 
+		/// Publishes the public endpoints of a worker
+		///
+		/// The payload must be signed by the worker's identity key, and be newer than the existing
+		/// record. Can be called by anyone on behalf of a worker.
+		#[pallet::weight(0)]
+		pub fn update_worker_endpoint(
+			origin: OriginFor<T>,
+			endpoint_payload: WorkerEndpointPayload,
+			signature: Vec<u8>,
+		) -> DispatchResult {
+			ensure_signed(origin)?;
+			let pubkey = endpoint_payload.pubkey;
+			ensure!(
+				Workers::<T>::contains_key(&pubkey),
+				Error::<T>::WorkerNotFound
+			);
+			ensure!(signature.len() == 64, Error::<T>::InvalidSignatureLength);
+			let sig = sr25519::Signature::try_from(signature.as_slice())
+				.or(Err(Error::<T>::MalformedSignature))?;
+			ensure!(
+				sp_io::crypto::sr25519_verify(&sig, &endpoint_payload.signing_message(), &pubkey),
+				Error::<T>::InvalidSignature
+			);
+
+			let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
+			ensure!(
+				endpoint_payload.signing_time.saturating_add(T::EndpointLifetime::get()) > now,
+				Error::<T>::EndpointExpired
+			);
+			// A record signed far in the future would never expire, and could not be replaced.
+			ensure!(
+				endpoint_payload.signing_time <= now.saturating_add(MAX_ENDPOINT_CLOCK_DRIFT),
+				Error::<T>::EndpointSignedInFuture
+			);
+			if let Some(record) = Endpoints::<T>::get(&pubkey) {
+				ensure!(
+					endpoint_payload.signing_time > record.signing_time,
+					Error::<T>::OutdatedEndpoint
+				);
+			}
+			Endpoints::<T>::insert(&pubkey, endpoint_payload);
+			Ok(())
+		}
+
+		/// Removes an expired endpoint record
+		///
+		/// Can be called by anyone.
+		#[pallet::weight(0)]
+		pub fn remove_expired_endpoint(
+			origin: OriginFor<T>,
+			pubkey: WorkerPublicKey,
+		) -> DispatchResult {
+			ensure_signed(origin)?;
+			let record = Endpoints::<T>::get(&pubkey).ok_or(Error::<T>::EndpointNotFound)?;
+			let now = T::UnixTime::now().as_secs().saturated_into::<u64>();
+			ensure!(
+				record.signing_time.saturating_add(T::EndpointLifetime::get()) <= now,
+				Error::<T>::EndpointNotExpired
+			);
+			Endpoints::<T>::remove(&pubkey);
+			Ok(())
+		}
+
 		/// Registers a pruntime binary to [`PRuntimeAllowList`]
 		///
 		/// Can only be called by `GovernanceOrigin`.
@@ -438,11 +626,102 @@ pub mod pallet {
 		}
 	}
 
+	#[pallet::hooks]
+	impl<T: Config> Hooks<T::BlockNumber> for Pallet<T>
+	where
+		T: crate::mq::Config,
+	{
+		fn on_finalize(n: T::BlockNumber) {
+			let period = T::GatekeeperElectionPeriod::get();
+			if !period.is_zero() && (n % period).is_zero() {
+				Self::elect_gatekeepers();
+			}
+		}
+	}
+
 	// TODO.kevin: Move it to mq
 	impl<T: Config> Pallet<T>
 	where
 		T: crate::mq::Config,
 	{
+		fn is_qualified_gatekeeper(worker_info: &WorkerInfo<T::AccountId>) -> bool {
+			worker_info.initial_score.is_some()
+				&& worker_info.confidence_level <= T::GatekeeperConfidenceLevel::get()
+		}
+
+		/// Elects the gatekeepers from the candidates and the gatekeepers in office
+		///
+		/// The qualified workers with the highest benchmark scores are elected. The gatekeepers
+		/// without a benchmark score were registered by the governance, and always keep their
+		/// seats.
+		///
+		/// The newly elected gatekeepers receive the master key from the ones in office over mq,
+		/// and confirm it with a `RegistryEvent::MasterPubkey`. The gatekeepers voted out stay in
+		/// office until all the elected ones have confirmed, so the key is never left with too
+		/// few gatekeepers. The rotated out gatekeepers keep the master key but stop sending
+		/// messages.
+		pub(crate) fn elect_gatekeepers() {
+			let gatekeepers = Gatekeeper::<T>::get();
+			// Wait for the master key to be generated by the first gatekeeper
+			if gatekeepers.is_empty() || !GatekeeperMasterPubkey::<T>::exists() {
+				return;
+			}
+			let mut elected: Vec<WorkerPublicKey> = gatekeepers
+				.iter()
+				.filter(|pubkey| {
+					Workers::<T>::get(pubkey).map_or(false, |w| w.initial_score.is_none())
+				})
+				.cloned()
+				.collect();
+			let seats = (T::MaxGatekeepers::get() as usize).saturating_sub(elected.len());
+			let candidates = GatekeeperCandidates::<T>::get();
+			let mut ranked: Vec<(u32, WorkerPublicKey)> = gatekeepers
+				.iter()
+				.chain(candidates.iter().filter(|c| !gatekeepers.contains(c)))
+				.filter_map(|pubkey| {
+					let worker_info = Workers::<T>::get(pubkey)?;
+					if !Self::is_qualified_gatekeeper(&worker_info) {
+						return None;
+					}
+					Some((worker_info.initial_score.unwrap_or_default(), *pubkey))
+				})
+				.collect();
+			// Highest score first, ties broken by the pubkey to be deterministic
+			ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+			elected.extend(ranked.iter().take(seats).map(|(_, pubkey)| *pubkey));
+
+			let mut unconfirmed = UnconfirmedGatekeepers::<T>::get();
+			for pubkey in elected.iter().filter(|p| !gatekeepers.contains(p)) {
+				let ecdh_pubkey = match Workers::<T>::get(pubkey) {
+					Some(worker_info) => worker_info.ecdh_pubkey,
+					None => continue,
+				};
+				Self::push_message(GatekeeperChange::gatekeeper_registered(
+					*pubkey,
+					ecdh_pubkey,
+				));
+				Self::deposit_event(Event::<T>::GatekeeperAdded(*pubkey));
+				unconfirmed.push(*pubkey);
+			}
+			let confirmed = !elected.iter().any(|p| unconfirmed.contains(p));
+			let voted_out: Vec<WorkerPublicKey> = gatekeepers
+				.into_iter()
+				.filter(|p| !elected.contains(p))
+				.collect();
+			let mut in_office = elected;
+			for pubkey in voted_out {
+				if confirmed {
+					Self::push_message(GatekeeperChange::gatekeeper_unregistered(pubkey));
+					Self::deposit_event(Event::<T>::GatekeeperRemoved(pubkey));
+				} else {
+					in_office.push(pubkey);
+				}
+			}
+			unconfirmed.retain(|p| in_office.contains(p));
+			UnconfirmedGatekeepers::<T>::put(unconfirmed);
+			Gatekeeper::<T>::put(in_office);
+		}
+
 		pub fn check_message(message: &SignedMessage) -> DispatchResult {
 			let pubkey_copy: sr25519::Public;
 			let pubkey = match &message.message.sender {
@@ -466,6 +745,17 @@ pub mod pallet {
 			Self::verify_signature(pubkey, message)
 		}
 
+		/// Whether the sender has a registered pubkey, i.e. its messages can pass `check_message`
+		pub fn is_known_sender(sender: &MessageOrigin) -> bool {
+			match sender {
+				MessageOrigin::Worker(pubkey) => Workers::<T>::contains_key(pubkey),
+				MessageOrigin::Cluster(id) => ClusterKeys::<T>::contains_key(id),
+				MessageOrigin::Contract(id) => ContractKeys::<T>::contains_key(id),
+				MessageOrigin::Gatekeeper => GatekeeperMasterPubkey::<T>::exists(),
+				_ => false,
+			}
+		}
+
 		fn verify_signature(pubkey: &WorkerPublicKey, message: &SignedMessage) -> DispatchResult {
 			let raw_sig = &message.signature;
 			ensure!(raw_sig.len() == 64, Error::<T>::InvalidSignatureLength);
@@ -533,6 +823,12 @@ pub mod pallet {
 							));
 						}
 					}
+					let mut unconfirmed = UnconfirmedGatekeepers::<T>::get();
+					if unconfirmed.contains(worker_pubkey) {
+						unconfirmed.retain(|p| p != worker_pubkey);
+						UnconfirmedGatekeepers::<T>::put(unconfirmed);
+						Self::deposit_event(Event::<T>::GatekeeperKeyConfirmed(*worker_pubkey));
+					}
 				}
 			}
 			Ok(())
@@ -676,6 +972,13 @@ pub mod pallet {
 				AttestationError::OutdatedIASReport => Self::OutdatedIASReport,
 				AttestationError::UnknownQuoteBodyFormat => Self::UnknownQuoteBodyFormat,
 				AttestationError::InvalidUserDataHash => Self::InvalidRuntimeInfoHash,
+				AttestationError::InvalidQuote => Self::InvalidQuote,
+				AttestationError::InvalidPckCertChain => Self::InvalidPckCertChain,
+				AttestationError::InvalidQuoteSignature => Self::InvalidQuoteSignature,
+				AttestationError::InvalidCollateral => Self::InvalidCollateral,
+				AttestationError::OutdatedCollateral => Self::OutdatedCollateral,
+				AttestationError::InvalidQeIdentity => Self::InvalidQeIdentity,
+				AttestationError::CertificateRevoked => Self::CertificateRevoked,
 			}
 		}
 	}
@@ -687,7 +990,8 @@ pub mod pallet {
 		use super::*;
 		use crate::mock::{
 			ecdh_pubkey, elapse_seconds, new_test_ext, set_block_1,
-			setup_relaychain_genesis_allowlist, worker_pubkey, Origin, Test,
+			setup_relaychain_genesis_allowlist, setup_workers_linked_operators, take_messages,
+			worker_pubkey, EndpointLifetime, Origin, Test,
 		};
 		// Pallets
 		use crate::mock::PhalaRegistry;
@@ -823,5 +1127,281 @@ pub mod pallet {
 				assert_eq!(RelaychainGenesisBlockHashAllowList::<Test>::get().len(), 0);
 			});
 		}
+
+		#[test]
+		fn test_update_worker_endpoint() {
+			use phala_types::{EndpointProtocol, WorkerEndpoint};
+			use sp_core::Pair;
+
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let worker = sr25519::Pair::from_seed(&[1u8; 32]);
+				let pubkey = worker.public();
+				let sign = |payload: &WorkerEndpointPayload| {
+					worker.sign(&payload.signing_message()).0.to_vec()
+				};
+
+				elapse_seconds(100);
+				let record = WorkerEndpointPayload {
+					pubkey,
+					endpoints: vec![WorkerEndpoint {
+						protocol: EndpointProtocol::Https,
+						url: b"https://worker.example.com:8000".to_vec(),
+						version: 1,
+					}],
+					signing_time: 100,
+				};
+				assert_noop!(
+					PhalaRegistry::update_worker_endpoint(
+						Origin::signed(1),
+						record.clone(),
+						sign(&record)
+					),
+					Error::<Test>::WorkerNotFound
+				);
+				assert_ok!(PhalaRegistry::force_register_worker(
+					Origin::root(),
+					pubkey,
+					ecdh_pubkey(1),
+					Some(1)
+				));
+				// Bad signature
+				let mut forged = record.clone();
+				forged.signing_time = 101;
+				assert_noop!(
+					PhalaRegistry::update_worker_endpoint(Origin::signed(1), forged, sign(&record)),
+					Error::<Test>::InvalidSignature
+				);
+				// Signed without the context tag
+				let untagged = worker.sign(&record.encode()).0.to_vec();
+				assert_noop!(
+					PhalaRegistry::update_worker_endpoint(
+						Origin::signed(1),
+						record.clone(),
+						untagged
+					),
+					Error::<Test>::InvalidSignature
+				);
+				// Records signed in the future would lock the endpoint forever
+				let mut far_future = record.clone();
+				far_future.signing_time = u64::MAX;
+				assert_noop!(
+					PhalaRegistry::update_worker_endpoint(
+						Origin::signed(1),
+						far_future.clone(),
+						sign(&far_future)
+					),
+					Error::<Test>::EndpointSignedInFuture
+				);
+				// Only a small clock drift is tolerated
+				far_future.signing_time = 100 + 300 + 1;
+				assert_noop!(
+					PhalaRegistry::update_worker_endpoint(
+						Origin::signed(1),
+						far_future.clone(),
+						sign(&far_future)
+					),
+					Error::<Test>::EndpointSignedInFuture
+				);
+				assert_ok!(PhalaRegistry::update_worker_endpoint(
+					Origin::signed(1),
+					record.clone(),
+					sign(&record)
+				));
+				assert_eq!(Endpoints::<Test>::get(&pubkey), Some(record.clone()));
+				// Replay of the same record
+				assert_noop!(
+					PhalaRegistry::update_worker_endpoint(
+						Origin::signed(1),
+						record.clone(),
+						sign(&record)
+					),
+					Error::<Test>::OutdatedEndpoint
+				);
+				assert_noop!(
+					PhalaRegistry::remove_expired_endpoint(Origin::signed(1), pubkey),
+					Error::<Test>::EndpointNotExpired
+				);
+				// Expire the record
+				elapse_seconds(EndpointLifetime::get());
+				assert_noop!(
+					PhalaRegistry::update_worker_endpoint(
+						Origin::signed(1),
+						record.clone(),
+						sign(&record)
+					),
+					Error::<Test>::EndpointExpired
+				);
+				assert_ok!(PhalaRegistry::remove_expired_endpoint(
+					Origin::signed(1),
+					pubkey
+				));
+				assert_eq!(Endpoints::<Test>::get(&pubkey), None);
+			});
+		}
+
+		fn gatekeeper_changes() -> Vec<GatekeeperChange> {
+			take_messages()
+				.iter()
+				.filter_map(|m| m.decode_payload::<GatekeeperChange>())
+				.collect()
+		}
+
+		fn confirm_key(worker: u8, master_key: u8) -> DecodedMessage<RegistryEvent> {
+			DecodedMessage {
+				sender: MessageOrigin::Worker(worker_pubkey(worker)),
+				destination: messaging::Topic::new(*b"^phala/registry/event"),
+				payload: RegistryEvent::MasterPubkey {
+					master_pubkey: worker_pubkey(master_key),
+				},
+			}
+		}
+
+		fn set_worker(i: u8, score: Option<u32>, confidence_level: u8) {
+			Workers::<Test>::mutate(worker_pubkey(i), |w| {
+				let w = w.as_mut().unwrap();
+				w.initial_score = score;
+				w.confidence_level = confidence_level;
+			});
+		}
+
+		#[test]
+		fn test_gatekeeper_election() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers_linked_operators(4);
+				for i in 1..=3 {
+					set_worker(i, Some(i as u32 * 100), 1);
+				}
+				set_worker(4, Some(1000), 128);
+				Gatekeeper::<Test>::put(vec![worker_pubkey(1)]);
+				GatekeeperMasterPubkey::<Test>::put(worker_pubkey(99));
+
+				assert_noop!(
+					PhalaRegistry::add_gatekeeper_candidate(Origin::signed(1), worker_pubkey(2)),
+					Error::<Test>::NotWorkerOperator
+				);
+				assert_noop!(
+					PhalaRegistry::add_gatekeeper_candidate(Origin::signed(4), worker_pubkey(4)),
+					Error::<Test>::UnqualifiedCandidate
+				);
+				assert_ok!(PhalaRegistry::add_gatekeeper_candidate(
+					Origin::signed(2),
+					worker_pubkey(2)
+				));
+				assert_noop!(
+					PhalaRegistry::add_gatekeeper_candidate(Origin::signed(2), worker_pubkey(2)),
+					Error::<Test>::DuplicatedCandidate
+				);
+				assert_ok!(PhalaRegistry::add_gatekeeper_candidate(
+					Origin::signed(3),
+					worker_pubkey(3)
+				));
+				take_messages();
+
+				// No election before the end of the period
+				PhalaRegistry::on_finalize(5);
+				assert_eq!(Gatekeeper::<Test>::get(), vec![worker_pubkey(1)]);
+
+				// Worker 3 and 2 have the highest scores, but worker 1 is kept until they confirm
+				// to have received the key
+				PhalaRegistry::on_finalize(10);
+				assert_eq!(
+					Gatekeeper::<Test>::get(),
+					vec![worker_pubkey(3), worker_pubkey(2), worker_pubkey(1)]
+				);
+				assert_eq!(
+					gatekeeper_changes(),
+					vec![
+						GatekeeperChange::gatekeeper_registered(worker_pubkey(3), ecdh_pubkey(1)),
+						GatekeeperChange::gatekeeper_registered(worker_pubkey(2), ecdh_pubkey(1)),
+					]
+				);
+
+				// Only the gatekeepers in office can confirm, with the right master pubkey
+				assert_noop!(
+					PhalaRegistry::on_message_received(confirm_key(4, 99)),
+					Error::<Test>::InvalidGatekeeper
+				);
+				assert_noop!(
+					PhalaRegistry::on_message_received(confirm_key(3, 98)),
+					Error::<Test>::MasterKeyMismatch
+				);
+				assert_ok!(PhalaRegistry::on_message_received(confirm_key(3, 99)));
+				assert_eq!(
+					UnconfirmedGatekeepers::<Test>::get(),
+					vec![worker_pubkey(2)]
+				);
+				PhalaRegistry::on_finalize(20);
+				assert_eq!(
+					Gatekeeper::<Test>::get(),
+					vec![worker_pubkey(3), worker_pubkey(2), worker_pubkey(1)]
+				);
+				assert_eq!(gatekeeper_changes(), vec![]);
+
+				// Worker 1 is rotated out once both hold the key
+				assert_ok!(PhalaRegistry::on_message_received(confirm_key(2, 99)));
+				PhalaRegistry::on_finalize(30);
+				assert_eq!(
+					Gatekeeper::<Test>::get(),
+					vec![worker_pubkey(3), worker_pubkey(2)]
+				);
+				assert_eq!(
+					gatekeeper_changes(),
+					vec![GatekeeperChange::gatekeeper_unregistered(worker_pubkey(1))]
+				);
+
+				assert_ok!(PhalaRegistry::remove_gatekeeper_candidate(
+					Origin::signed(2),
+					worker_pubkey(2)
+				));
+				assert_noop!(
+					PhalaRegistry::remove_gatekeeper_candidate(Origin::signed(2), worker_pubkey(2)),
+					Error::<Test>::CandidateNotFound
+				);
+			});
+		}
+
+		#[test]
+		fn test_governance_gatekeeper_keeps_its_seat() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				setup_workers_linked_operators(3);
+				// Registered by the governance without a benchmark
+				set_worker(1, None, 1);
+				set_worker(2, Some(200), 1);
+				set_worker(3, Some(300), 1);
+				Gatekeeper::<Test>::put(vec![worker_pubkey(1)]);
+				GatekeeperMasterPubkey::<Test>::put(worker_pubkey(99));
+				for i in 2..=3 {
+					assert_ok!(PhalaRegistry::add_gatekeeper_candidate(
+						Origin::signed(i as _),
+						worker_pubkey(i)
+					));
+				}
+				take_messages();
+
+				// Worker 1 takes one of the two seats
+				PhalaRegistry::on_finalize(10);
+				assert_eq!(
+					Gatekeeper::<Test>::get(),
+					vec![worker_pubkey(1), worker_pubkey(3)]
+				);
+				assert_eq!(
+					gatekeeper_changes(),
+					vec![GatekeeperChange::gatekeeper_registered(
+						worker_pubkey(3),
+						ecdh_pubkey(1)
+					)]
+				);
+				assert_ok!(PhalaRegistry::on_message_received(confirm_key(3, 99)));
+				PhalaRegistry::on_finalize(20);
+				assert_eq!(
+					Gatekeeper::<Test>::get(),
+					vec![worker_pubkey(1), worker_pubkey(3)]
+				);
+				assert_eq!(gatekeeper_changes(), vec![]);
+			});
+		}
 	}
 }
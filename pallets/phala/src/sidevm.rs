@@ -0,0 +1,366 @@
+//! # Sidevm Code Registry Pallet
+//!
+//! The canonical source of truth of which sidevm code the workers are allowed to run. Each code is
+//! registered by its blake2_256 hash, together with the owner and the resource profile it runs
+//! with. The owner picks the memory pages of the profile, while the gRPC targets a code can call
+//! are only set by root, since the owner would allow any target for its own code.
+//!
+//! The changes are announced to the workers by [`SidevmCodeEvent`] messages, and the workers
+//! verify a sidevm code against their copy of the registry before spawning it. So the changes take
+//! effect on the instances started afterwards.
+//!
+//! The registry is only enforced after root turns it on with `set_registry_enforced`, so that the
+//! deployed contracts starting unregistered codes keep working until their codes are registered.
+
+pub use self::pallet::*;
+
+#[frame_support::pallet]
+pub mod pallet {
+	use frame_support::{dispatch::DispatchResult, pallet_prelude::*, traits::StorageVersion};
+	use frame_system::pallet_prelude::*;
+	use sp_std::prelude::*;
+
+	use crate::mq::MessageOriginInfo;
+
+	use phala_types::contract::{
+		messaging::SidevmCodeEvent, SidevmCodeHash, SidevmCodeInfo, SidevmResourceProfile,
+	};
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config + crate::mq::Config {
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+		/// The max size of a sidevm code in bytes
+		#[pallet::constant]
+		type MaxCodeSize: Get<u32>;
+
+		/// The max number of memory pages a sidevm code can request
+		#[pallet::constant]
+		type MaxMemoryPages: Get<u32>;
+
+		/// The max number of gRPC targets a sidevm code can be allowed to call
+		#[pallet::constant]
+		type MaxGrpcTargets: Get<u32>;
+
+		/// The max length of a gRPC target in bytes
+		#[pallet::constant]
+		type MaxGrpcTargetLen: Get<u32>;
+	}
+
+	const STORAGE_VERSION: StorageVersion = StorageVersion::new(0);
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	#[pallet::storage_version(STORAGE_VERSION)]
+	#[pallet::without_storage_info]
+	pub struct Pallet<T>(_);
+
+	/// The registered sidevm codes
+	#[pallet::storage]
+	pub type Codes<T: Config> = StorageMap<
+		_,
+		Twox64Concat,
+		SidevmCodeHash,
+		SidevmCodeInfo<T::AccountId, T::BlockNumber>,
+	>;
+
+	/// Whether the workers refuse to start the sidevm codes not in the registry
+	#[pallet::storage]
+	pub type RegistryEnforced<T> = StorageValue<_, bool, ValueQuery>;
+
+	#[pallet::event]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
+		CodeRegistered {
+			hash: SidevmCodeHash,
+			owner: T::AccountId,
+		},
+		CodeUnregistered {
+			hash: SidevmCodeHash,
+		},
+		RegistryEnforcedSet {
+			enforced: bool,
+		},
+		GrpcTargetsSet {
+			hash: SidevmCodeHash,
+		},
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		/// The code is already registered
+		DuplicatedCode,
+		/// The code is not registered
+		CodeNotFound,
+		/// The code is larger than `MaxCodeSize`
+		CodeTooLarge,
+		/// The resource profile requests more memory than `MaxMemoryPages`
+		TooManyMemoryPages,
+		/// More gRPC targets than `MaxGrpcTargets` are given
+		TooManyGrpcTargets,
+		/// A gRPC target is longer than `MaxGrpcTargetLen`
+		GrpcTargetTooLong,
+		/// Only the owner can operate the code
+		NotCodeOwner,
+	}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Registers a sidevm code running with at most `memory_pages` of memory. The code is not
+		/// allowed to call any gRPC target until root sets them with `set_grpc_targets`.
+		#[pallet::weight(0)]
+		pub fn register_code(
+			origin: OriginFor<T>,
+			code: Vec<u8>,
+			memory_pages: u32,
+		) -> DispatchResult {
+			let owner = ensure_signed(origin)?;
+			ensure!(
+				code.len() <= T::MaxCodeSize::get() as usize,
+				Error::<T>::CodeTooLarge
+			);
+			ensure!(
+				memory_pages <= T::MaxMemoryPages::get(),
+				Error::<T>::TooManyMemoryPages
+			);
+			let profile = SidevmResourceProfile {
+				memory_pages,
+				grpc_targets: Vec::new(),
+			};
+			let hash = SidevmCodeHash::from(crate::hashing::blake2_256(&code));
+			ensure!(
+				!Codes::<T>::contains_key(&hash),
+				Error::<T>::DuplicatedCode
+			);
+
+			let info = SidevmCodeInfo {
+				owner: owner.clone(),
+				size: code.len() as u32,
+				profile: profile.clone(),
+				registered_at: frame_system::Pallet::<T>::block_number(),
+			};
+			Codes::<T>::insert(&hash, &info);
+			Self::push_message(SidevmCodeEvent::CodeRegistered { hash, profile });
+			Self::deposit_event(Event::<T>::CodeRegistered { hash, owner });
+			Ok(())
+		}
+
+		/// Removes a sidevm code from the registry. The running instances are not affected, but
+		/// no new instance can be started with the code.
+		#[pallet::weight(0)]
+		pub fn unregister_code(origin: OriginFor<T>, hash: SidevmCodeHash) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let info = Codes::<T>::get(&hash).ok_or(Error::<T>::CodeNotFound)?;
+			ensure!(info.owner == who, Error::<T>::NotCodeOwner);
+
+			Codes::<T>::remove(&hash);
+			Self::push_message(SidevmCodeEvent::CodeUnregistered { hash });
+			Self::deposit_event(Event::<T>::CodeUnregistered { hash });
+			Ok(())
+		}
+
+		/// Sets the gRPC targets a sidevm code is allowed to call, each in the form of
+		/// `https://host:port`
+		///
+		/// Can only be called by root.
+		#[pallet::weight(0)]
+		pub fn set_grpc_targets(
+			origin: OriginFor<T>,
+			hash: SidevmCodeHash,
+			targets: Vec<Vec<u8>>,
+		) -> DispatchResult {
+			ensure_root(origin)?;
+			ensure!(
+				targets.len() <= T::MaxGrpcTargets::get() as usize,
+				Error::<T>::TooManyGrpcTargets
+			);
+			ensure!(
+				targets
+					.iter()
+					.all(|target| target.len() <= T::MaxGrpcTargetLen::get() as usize),
+				Error::<T>::GrpcTargetTooLong
+			);
+			let profile = Codes::<T>::try_mutate(&hash, |info| -> Result<_, DispatchError> {
+				let info = info.as_mut().ok_or(Error::<T>::CodeNotFound)?;
+				info.profile.grpc_targets = targets;
+				Ok(info.profile.clone())
+			})?;
+			Self::push_message(SidevmCodeEvent::ProfileUpdated { hash, profile });
+			Self::deposit_event(Event::<T>::GrpcTargetsSet { hash });
+			Ok(())
+		}
+
+		/// Turns on or off the enforcement of the registry on the workers
+		///
+		/// Can only be called by root.
+		#[pallet::weight(0)]
+		pub fn set_registry_enforced(origin: OriginFor<T>, enforced: bool) -> DispatchResult {
+			ensure_root(origin)?;
+			RegistryEnforced::<T>::put(enforced);
+			Self::push_message(SidevmCodeEvent::SetEnforced { enforced });
+			Self::deposit_event(Event::<T>::RegistryEnforcedSet { enforced });
+			Ok(())
+		}
+	}
+
+	impl<T: Config> MessageOriginInfo for Pallet<T> {
+		type Config = T;
+	}
+
+	#[cfg(test)]
+	mod test {
+		use super::*;
+		use crate::mock::{
+			new_test_ext, set_block_1, take_events, take_messages, Event as TestEvent, Origin,
+			Test,
+		};
+		// Pallets
+		use crate::mock::PhalaSidevm;
+		use frame_support::{assert_noop, assert_ok};
+
+		const TARGET: &[u8] = b"https://grpc.example.com:443";
+
+		fn profile(memory_pages: u32) -> SidevmResourceProfile {
+			SidevmResourceProfile {
+				memory_pages,
+				grpc_targets: vec![],
+			}
+		}
+
+		#[test]
+		fn register_and_unregister_code() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let code = vec![0u8, 1, 2, 3];
+				let hash = SidevmCodeHash::from(crate::hashing::blake2_256(&code));
+				assert_ok!(PhalaSidevm::register_code(Origin::signed(1), code.clone(), 16));
+				assert_eq!(
+					Codes::<Test>::get(&hash),
+					Some(SidevmCodeInfo {
+						owner: 1,
+						size: 4,
+						profile: profile(16),
+						registered_at: 1,
+					})
+				);
+				assert_eq!(
+					take_events(),
+					vec![TestEvent::PhalaSidevm(Event::CodeRegistered {
+						hash,
+						owner: 1
+					})]
+				);
+				let messages = take_messages();
+				assert_eq!(messages.len(), 1);
+				assert_eq!(messages[0].sender, PhalaSidevm::message_origin());
+				let event = SidevmCodeEvent::CodeRegistered {
+					hash,
+					profile: profile(16),
+				};
+				assert_eq!(messages[0].payload, event.encode());
+				assert_noop!(
+					PhalaSidevm::register_code(Origin::signed(2), code, 16),
+					Error::<Test>::DuplicatedCode
+				);
+
+				assert_noop!(
+					PhalaSidevm::unregister_code(Origin::signed(2), hash),
+					Error::<Test>::NotCodeOwner
+				);
+				assert_ok!(PhalaSidevm::unregister_code(Origin::signed(1), hash));
+				assert_eq!(Codes::<Test>::get(&hash), None);
+				let messages = take_messages();
+				assert_eq!(messages.len(), 1);
+				assert_eq!(
+					messages[0].payload,
+					SidevmCodeEvent::CodeUnregistered { hash }.encode()
+				);
+				assert_noop!(
+					PhalaSidevm::unregister_code(Origin::signed(1), hash),
+					Error::<Test>::CodeNotFound
+				);
+			});
+		}
+
+		#[test]
+		fn registry_enforcement_is_set_by_root() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				assert!(!RegistryEnforced::<Test>::get());
+				assert_noop!(
+					PhalaSidevm::set_registry_enforced(Origin::signed(1), true),
+					DispatchError::BadOrigin
+				);
+				assert_ok!(PhalaSidevm::set_registry_enforced(Origin::root(), true));
+				assert!(RegistryEnforced::<Test>::get());
+				let messages = take_messages();
+				assert_eq!(messages.len(), 1);
+				assert_eq!(
+					messages[0].payload,
+					SidevmCodeEvent::SetEnforced { enforced: true }.encode()
+				);
+			});
+		}
+
+		#[test]
+		fn resource_limits_are_enforced() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				assert_noop!(
+					PhalaSidevm::register_code(Origin::signed(1), vec![0u8; 1025], 1),
+					Error::<Test>::CodeTooLarge
+				);
+				assert_noop!(
+					PhalaSidevm::register_code(Origin::signed(1), vec![0u8; 8], 257),
+					Error::<Test>::TooManyMemoryPages
+				);
+			});
+		}
+
+		#[test]
+		fn grpc_targets_are_set_by_root() {
+			new_test_ext().execute_with(|| {
+				set_block_1();
+				let code = vec![0u8, 1, 2, 3];
+				let hash = SidevmCodeHash::from(crate::hashing::blake2_256(&code));
+				let targets = vec![TARGET.to_vec()];
+				assert_noop!(
+					PhalaSidevm::set_grpc_targets(Origin::root(), hash, targets.clone()),
+					Error::<Test>::CodeNotFound
+				);
+				assert_ok!(PhalaSidevm::register_code(Origin::signed(1), code, 16));
+				take_messages();
+				// Not even the owner can set the targets of its code
+				assert_noop!(
+					PhalaSidevm::set_grpc_targets(Origin::signed(1), hash, targets.clone()),
+					DispatchError::BadOrigin
+				);
+				assert_noop!(
+					PhalaSidevm::set_grpc_targets(Origin::root(), hash, vec![TARGET.to_vec(); 5]),
+					Error::<Test>::TooManyGrpcTargets
+				);
+				assert_noop!(
+					PhalaSidevm::set_grpc_targets(Origin::root(), hash, vec![vec![b'a'; 65]]),
+					Error::<Test>::GrpcTargetTooLong
+				);
+
+				assert_ok!(PhalaSidevm::set_grpc_targets(Origin::root(), hash, targets.clone()));
+				let expected = SidevmResourceProfile {
+					memory_pages: 16,
+					grpc_targets: targets,
+				};
+				assert_eq!(Codes::<Test>::get(&hash).unwrap().profile, expected);
+				let messages = take_messages();
+				assert_eq!(messages.len(), 1);
+				assert_eq!(
+					messages[0].payload,
+					SidevmCodeEvent::ProfileUpdated {
+						hash,
+						profile: expected
+					}
+					.encode()
+				);
+			});
+		}
+	}
+}
@@ -280,6 +280,16 @@ pub mod pallet {
 			user: T::AccountId,
 			shares: BalanceOf<T>,
 		},
+		/// A worker in the pool stopped responding to the heartbeat challenges
+		///
+		/// There's no affected state.
+		PoolWorkerUnresponsive { pid: u64, worker: WorkerPublicKey },
+		/// A worker in the pool has been unresponsive for longer than the grace period
+		///
+		/// The pool owner should fix or stop the worker, otherwise the stake keeps being slashed.
+		///
+		/// There's no affected state.
+		PoolWorkerUnresponsiveReported { pid: u64, worker: WorkerPublicKey },
 	}
 
 	#[pallet::error]
@@ -1268,6 +1278,30 @@ pub mod pallet {
 		}
 	}
 
+	impl<T: Config> mining::OnUnresponsive for Pallet<T>
+	where
+		T: mining::Config<Currency = <T as Config>::Currency>,
+		BalanceOf<T>: FixedPointConvert + Display,
+	{
+		fn on_unresponsive(worker: &WorkerPublicKey) {
+			if let Some(pid) = WorkerAssignments::<T>::get(worker) {
+				Self::deposit_event(Event::<T>::PoolWorkerUnresponsive {
+					pid,
+					worker: *worker,
+				});
+			}
+		}
+
+		fn on_grace_period_expired(worker: &WorkerPublicKey) {
+			if let Some(pid) = WorkerAssignments::<T>::get(worker) {
+				Self::deposit_event(Event::<T>::PoolWorkerUnresponsiveReported {
+					pid,
+					worker: *worker,
+				});
+			}
+		}
+	}
+
 	impl<T: Config> Ledger<T::AccountId, BalanceOf<T>> for Pallet<T>
 	where
 		T: mining::Config<Currency = <T as Config>::Currency>,
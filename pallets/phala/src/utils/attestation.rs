@@ -1,4 +1,5 @@
 use crate::constants::*;
+pub use crate::utils::dcap::DcapCollateral;
 
 use codec::{Decode, Encode};
 use scale_info::TypeInfo;
@@ -15,6 +16,10 @@ pub enum Attestation {
 		signature: Vec<u8>,
 		raw_signing_cert: Vec<u8>,
 	},
+	SgxDcap {
+		quote: Vec<u8>,
+		collateral: DcapCollateral,
+	},
 }
 
 pub trait AttestationValidator {
@@ -38,6 +43,14 @@ pub enum Error {
 	OutdatedIASReport,
 	UnknownQuoteBodyFormat,
 	InvalidUserDataHash,
+	// DCAP related
+	InvalidQuote,
+	InvalidPckCertChain,
+	InvalidQuoteSignature,
+	InvalidCollateral,
+	OutdatedCollateral,
+	InvalidQeIdentity,
+	CertificateRevoked,
 }
 
 #[derive(Encode, Decode, TypeInfo, Debug, Clone, PartialEq, Eq)]
@@ -50,7 +63,7 @@ pub struct IasFields {
 	pub confidence_level: u8,
 }
 
-/// Attestation validator implementation for IAS
+/// Attestation validator implementation for IAS and DCAP
 pub struct IasValidator;
 impl AttestationValidator for IasValidator {
 	fn validate(
@@ -73,6 +86,13 @@ impl AttestationValidator for IasValidator {
 				verify_pruntime,
 				pruntime_allowlist,
 			),
+			Attestation::SgxDcap { quote, collateral } => crate::utils::dcap::validate_dcap_quote(
+				quote,
+				collateral,
+				now,
+				verify_pruntime,
+				pruntime_allowlist,
+			),
 		}?;
 		let commit = &fields.report_data[..32];
 		if commit != user_data_hash {
@@ -83,7 +103,7 @@ impl AttestationValidator for IasValidator {
 	}
 }
 
-fn extend_mrenclave(
+pub(crate) fn extend_mrenclave(
 	mr_enclave: &[u8],
 	mr_signer: &[u8],
 	isv_prod_id: &[u8],
@@ -14,6 +14,14 @@ pub const IAS_QUOTE_ADVISORY_ID_WHITELIST: &[&str] = &[
 	"INTEL-SA-00381",
 	"INTEL-SA-00389",
 ];
+// DCAP TCB status, graded the same as the IAS quote status
+pub const DCAP_TCB_STATUS_LEVEL_1: &[&str] = &["UpToDate"];
+pub const DCAP_TCB_STATUS_LEVEL_2: &[&str] = &["SWHardeningNeeded"];
+pub const DCAP_TCB_STATUS_LEVEL_3: &[&str] = &[
+	"ConfigurationNeeded",
+	"ConfigurationAndSWHardeningNeeded",
+];
+pub const DCAP_TCB_STATUS_LEVEL_5: &[&str] = &["OutOfDate", "OutOfDateConfigurationNeeded"];
 pub type SignatureAlgorithms = &'static [&'static webpki::SignatureAlgorithm];
 pub static SUPPORTED_SIG_ALGS: SignatureAlgorithms = &[
 	// &webpki::ECDSA_P256_SHA256,
@@ -66,3 +74,31 @@ pub static IAS_SERVER_ROOTS: webpki::TlsServerTrustAnchors = webpki::TlsServerTr
         name_constraints: None
     },
 ]);
+
+pub static DCAP_SUPPORTED_SIG_ALGS: SignatureAlgorithms = &[&webpki::ECDSA_P256_SHA256];
+
+pub static DCAP_SERVER_ROOTS: webpki::TlsServerTrustAnchors = webpki::TlsServerTrustAnchors(&[
+    /*
+     * -----BEGIN CERTIFICATE-----
+     * MIICjzCCAjSgAwIBAgIUImUM1lqdNInzg7SVUr9QGzknBqwwCgYIKoZIzj0EAwIw
+     * aDEaMBgGA1UEAwwRSW50ZWwgU0dYIFJvb3QgQ0ExGjAYBgNVBAoMEUludGVsIENv
+     * cnBvcmF0aW9uMRQwEgYDVQQHDAtTYW50YSBDbGFyYTELMAkGA1UECAwCQ0ExCzAJ
+     * BgNVBAYTAlVTMB4XDTE4MDUyMTEwNDUxMFoXDTQ5MTIzMTIzNTk1OVowaDEaMBgG
+     * A1UEAwwRSW50ZWwgU0dYIFJvb3QgQ0ExGjAYBgNVBAoMEUludGVsIENvcnBvcmF0
+     * aW9uMRQwEgYDVQQHDAtTYW50YSBDbGFyYTELMAkGA1UECAwCQ0ExCzAJBgNVBAYT
+     * AlVTMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEC6nEwMDIYZOj/iPWsCzaEKi7
+     * 1OiOSLRFhWGjbnBVJfVnkY4u3IjkDYYL0MxO4mqsyYjlBalTVYxFP2sJBK5zlKOB
+     * uzCBuDAfBgNVHSMEGDAWgBQiZQzWWp00ifODtJVSv1AbOScGrDBSBgNVHR8ESzBJ
+     * MEegRaBDhkFodHRwczovL2NlcnRpZmljYXRlcy50cnVzdGVkc2VydmljZXMuaW50
+     * ZWwuY29tL0ludGVsU0dYUm9vdENBLmRlcjAdBgNVHQ4EFgQUImUM1lqdNInzg7SV
+     * Ur9QGzknBqwwDgYDVR0PAQH/BAQDAgEGMBIGA1UdEwEB/wQIMAYBAf8CAQEwCgYI
+     * KoZIzj0EAwIDSQAwRgIhAOW/5QkR+S9CiSDcNoowLuPRLsWGf/Yi7GSX94BgwTwg
+     * AiEA4J0lrHoMs+Xo5o/sX6O9QWxHRAvZUGOdRQ7cvqRXaqI=
+     * -----END CERTIFICATE-----
+     */
+    webpki::TrustAnchor {
+        subject: b"1\x1a0\x18\x06\x03U\x04\x03\x0c\x11Intel SGX Root CA1\x1a0\x18\x06\x03U\x04\x0a\x0c\x11Intel Corporation1\x140\x12\x06\x03U\x04\x07\x0c\x0bSanta Clara1\x0b0\x09\x06\x03U\x04\x08\x0c\x02CA1\x0b0\x09\x06\x03U\x04\x06\x13\x02US",
+        spki: b"0\x13\x06\x07*\x86H\xce=\x02\x01\x06\x08*\x86H\xce=\x03\x01\x07\x03B\x00\x04\x0b\xa9\xc4\xc0\xc0\xc8a\x93\xa3\xfe#\xd6\xb0,\xda\x10\xa8\xbb\xd4\xe8\x8eH\xb4E\x85a\xa3npU%\xf5g\x91\x8e.\xdc\x88\xe4\x0d\x86\x0b\xd0\xccN\xe2j\xac\xc9\x88\xe5\x05\xa9SU\x8cE?k\x09\x04\xaes\x94",
+        name_constraints: None
+    },
+]);
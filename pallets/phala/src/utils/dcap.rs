@@ -0,0 +1,1029 @@
+//! Verification of SGX DCAP (ECDSA) quotes.
+//!
+//! A quote is verified against the Intel SGX Root CA embedded in [`DCAP_SERVER_ROOTS`] and the
+//! collateral fetched from the Intel PCS by the submitter:
+//!
+//! 1. The PCK certificate chain carried by the quote is chained up to the root CA.
+//! 2. The QE report is signed by the PCK key and commits to the attestation key.
+//! 3. The enclave report is signed by the attestation key.
+//! 4. The TCB info and the QE identity are signed by the Intel SGX TCB Signing certificate issued
+//!    by the root CA, and are not expired.
+//! 5. The PCK certificate, the PCK CA and the TCB Signing certificate are not revoked by the CRLs
+//!    of the root CA and the PCK CA.
+//! 6. The QE matches the QE identity, and the TCB level of the platform is looked up in the TCB
+//!    info to grade the confidence level the same way as the IAS quote status.
+
+use crate::attestation::{extend_mrenclave, Error, IasFields};
+use crate::constants::*;
+
+use codec::{Decode, Encode};
+use scale_info::TypeInfo;
+use sp_std::{
+	convert::{TryFrom, TryInto},
+	vec::Vec,
+};
+
+/// The collateral to verify a DCAP quote with, as served by the Intel PCS
+#[derive(Encode, Decode, TypeInfo, Debug, Clone, PartialEq, Eq)]
+pub struct DcapCollateral {
+	/// PEM encoded certificate chain of the key signing `tcb_info`
+	pub tcb_info_issuer_chain: Vec<u8>,
+	/// The raw JSON of the `tcbInfo` field
+	pub tcb_info: Vec<u8>,
+	/// The raw ECDSA signature (r || s) of `tcb_info`
+	pub tcb_info_signature: Vec<u8>,
+	/// PEM encoded certificate chain of the key signing `qe_identity`
+	pub qe_identity_issuer_chain: Vec<u8>,
+	/// The raw JSON of the `enclaveIdentity` field
+	pub qe_identity: Vec<u8>,
+	/// The raw ECDSA signature (r || s) of `qe_identity`
+	pub qe_identity_signature: Vec<u8>,
+	/// DER encoded CRL of the Intel SGX Root CA
+	pub root_ca_crl: Vec<u8>,
+	/// DER encoded CRL of the PCK CA issuing the PCK certificate of the quote
+	pub pck_crl: Vec<u8>,
+}
+
+const QUOTE_VERSION: u16 = 3;
+const ATTESTATION_KEY_TYPE_ECDSA_P256: u16 = 2;
+const TEE_TYPE_SGX: u32 = 0;
+const CERT_DATA_TYPE_PCK_CHAIN: u16 = 5;
+
+const HEADER_BYTES: usize = 48;
+const REPORT_BYTES: usize = 384;
+const SIGNATURE_BYTES: usize = 64;
+const PUBKEY_BYTES: usize = 64;
+
+/// OID 1.2.840.113741.1.13.1, the SGX extensions of a PCK certificate
+const SGX_EXTENSIONS_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf8, 0x4d, 0x01, 0x0d, 0x01];
+const SGX_EXTENSION_TCB: u8 = 2;
+const SGX_EXTENSION_FMSPC: u8 = 4;
+const TCB_PCESVN: u8 = 17;
+
+/// OID 1.2.840.10045.4.3.2, ecdsa-with-SHA256
+const ECDSA_WITH_SHA256_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+/// OID 2.5.4.3, the common name in a distinguished name
+const COMMON_NAME_OID: &[u8] = &[0x55, 0x04, 0x03];
+const TCB_SIGNING_COMMON_NAME: &[u8] = b"Intel SGX TCB Signing";
+
+const DER_INTEGER: u8 = 0x02;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OID: u8 = 0x06;
+const DER_SEQUENCE: u8 = 0x30;
+const DER_UTC_TIME: u8 = 0x17;
+const DER_GENERALIZED_TIME: u8 = 0x18;
+const DER_EXPLICIT_0: u8 = 0xa0;
+
+const TCB_COMPONENT_KEYS: [&str; 16] = [
+	"sgxtcbcomp01svn",
+	"sgxtcbcomp02svn",
+	"sgxtcbcomp03svn",
+	"sgxtcbcomp04svn",
+	"sgxtcbcomp05svn",
+	"sgxtcbcomp06svn",
+	"sgxtcbcomp07svn",
+	"sgxtcbcomp08svn",
+	"sgxtcbcomp09svn",
+	"sgxtcbcomp10svn",
+	"sgxtcbcomp11svn",
+	"sgxtcbcomp12svn",
+	"sgxtcbcomp13svn",
+	"sgxtcbcomp14svn",
+	"sgxtcbcomp15svn",
+	"sgxtcbcomp16svn",
+];
+
+struct Reader<'a> {
+	data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+	fn new(data: &'a [u8]) -> Self {
+		Self { data }
+	}
+
+	fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+		if self.data.len() < len {
+			return Err(Error::InvalidQuote);
+		}
+		let (head, tail) = self.data.split_at(len);
+		self.data = tail;
+		Ok(head)
+	}
+
+	fn u16(&mut self) -> Result<u16, Error> {
+		Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+	}
+
+	fn u32(&mut self) -> Result<u32, Error> {
+		Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+}
+
+/// A v3 ECDSA quote, borrowing from the raw bytes
+struct Quote<'a> {
+	header: &'a [u8],
+	report: &'a [u8],
+	signature: &'a [u8],
+	attestation_key: &'a [u8],
+	qe_report: &'a [u8],
+	qe_report_signature: &'a [u8],
+	qe_auth_data: &'a [u8],
+	pck_cert_chain: &'a [u8],
+}
+
+fn parse_quote(raw: &[u8]) -> Result<Quote<'_>, Error> {
+	let mut reader = Reader::new(raw);
+	let header = reader.take(HEADER_BYTES)?;
+	let mut header_reader = Reader::new(header);
+	let version = header_reader.u16()?;
+	let key_type = header_reader.u16()?;
+	let tee_type = header_reader.u32()?;
+	if version != QUOTE_VERSION
+		|| key_type != ATTESTATION_KEY_TYPE_ECDSA_P256
+		|| tee_type != TEE_TYPE_SGX
+	{
+		return Err(Error::InvalidQuote);
+	}
+	let report = reader.take(REPORT_BYTES)?;
+	let signature_len = reader.u32()? as usize;
+	let mut sig_reader = Reader::new(reader.take(signature_len)?);
+	let signature = sig_reader.take(SIGNATURE_BYTES)?;
+	let attestation_key = sig_reader.take(PUBKEY_BYTES)?;
+	let qe_report = sig_reader.take(REPORT_BYTES)?;
+	let qe_report_signature = sig_reader.take(SIGNATURE_BYTES)?;
+	let qe_auth_data_len = sig_reader.u16()? as usize;
+	let qe_auth_data = sig_reader.take(qe_auth_data_len)?;
+	let cert_data_type = sig_reader.u16()?;
+	let cert_data_len = sig_reader.u32()? as usize;
+	let pck_cert_chain = sig_reader.take(cert_data_len)?;
+	if cert_data_type != CERT_DATA_TYPE_PCK_CHAIN {
+		return Err(Error::InvalidQuote);
+	}
+	Ok(Quote {
+		header,
+		report,
+		signature,
+		attestation_key,
+		qe_report,
+		qe_report_signature,
+		qe_auth_data,
+		pck_cert_chain,
+	})
+}
+
+/// Decodes the certificates in a PEM bundle, leaf first
+fn decode_pem_chain(pem: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+	const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+	const END: &str = "-----END CERTIFICATE-----";
+	let mut rest = sp_std::str::from_utf8(pem).or(Err(Error::InvalidPckCertChain))?;
+	let mut certs = Vec::new();
+	while let Some(begin) = rest.find(BEGIN) {
+		rest = &rest[begin + BEGIN.len()..];
+		let end = rest.find(END).ok_or(Error::InvalidPckCertChain)?;
+		let body: Vec<u8> = rest[..end]
+			.bytes()
+			.filter(|b| !b.is_ascii_whitespace())
+			.collect();
+		certs.push(base64::decode(&body).or(Err(Error::InvalidPckCertChain))?);
+		rest = &rest[end + END.len()..];
+	}
+	if certs.is_empty() {
+		return Err(Error::InvalidPckCertChain);
+	}
+	Ok(certs)
+}
+
+/// Verifies the chain up to the root CA and returns the leaf certificate
+fn verify_cert_chain<'a>(
+	chain: &'a [Vec<u8>],
+	root: &webpki::TrustAnchor,
+	now: u64,
+) -> Result<webpki::EndEntityCert<'a>, Error> {
+	let (leaf, intermediates) = chain.split_first().ok_or(Error::InvalidPckCertChain)?;
+	let cert =
+		webpki::EndEntityCert::try_from(leaf.as_slice()).or(Err(Error::InvalidPckCertChain))?;
+	let intermediates: Vec<&[u8]> = intermediates.iter().map(|cert| cert.as_slice()).collect();
+	let time_now = webpki::Time::from_seconds_since_unix_epoch(now);
+	cert.verify_is_valid_tls_server_cert(
+		DCAP_SUPPORTED_SIG_ALGS,
+		&webpki::TlsServerTrustAnchors(sp_std::slice::from_ref(root)),
+		&intermediates,
+		time_now,
+	)
+	.or(Err(Error::InvalidPckCertChain))?;
+	Ok(cert)
+}
+
+/// Converts a raw P-256 ECDSA signature (r || s) to the ASN.1 DER form webpki expects
+fn encode_der_signature(raw: &[u8]) -> Result<Vec<u8>, Error> {
+	fn push_integer(out: &mut Vec<u8>, bytes: &[u8]) {
+		let start = bytes
+			.iter()
+			.position(|b| *b != 0)
+			.unwrap_or(bytes.len() - 1);
+		let bytes = &bytes[start..];
+		let padded = bytes[0] & 0x80 != 0;
+		out.push(0x02);
+		out.push((bytes.len() + padded as usize) as u8);
+		if padded {
+			out.push(0);
+		}
+		out.extend_from_slice(bytes);
+	}
+	if raw.len() != SIGNATURE_BYTES {
+		return Err(Error::InvalidQuoteSignature);
+	}
+	let mut body = Vec::with_capacity(SIGNATURE_BYTES + 6);
+	push_integer(&mut body, &raw[..32]);
+	push_integer(&mut body, &raw[32..]);
+	let mut der = Vec::with_capacity(body.len() + 2);
+	der.push(0x30);
+	der.push(body.len() as u8);
+	der.extend_from_slice(&body);
+	Ok(der)
+}
+
+fn verify_raw_key_signature(pubkey: &[u8], message: &[u8], signature: &[u8]) -> bool {
+	let mut key = Vec::with_capacity(PUBKEY_BYTES + 1);
+	key.push(0x04);
+	key.extend_from_slice(pubkey);
+	ring::signature::UnparsedPublicKey::new(&ring::signature::ECDSA_P256_SHA256_FIXED, key)
+		.verify(message, signature)
+		.is_ok()
+}
+
+/// A DER element
+struct Der<'a> {
+	tag: u8,
+	value: &'a [u8],
+	/// The whole encoding of the element, including the tag and the length
+	raw: &'a [u8],
+}
+
+/// Reads the DER element at the front of `data` and returns it with the remaining bytes
+fn read_der(data: &[u8]) -> Option<(Der<'_>, &[u8])> {
+	let (&tag, rest) = data.split_first()?;
+	let (&len, mut rest) = rest.split_first()?;
+	let len = if len & 0x80 == 0 {
+		len as usize
+	} else {
+		let num_bytes = (len & 0x7f) as usize;
+		if num_bytes == 0 || num_bytes > 3 || rest.len() < num_bytes {
+			return None;
+		}
+		let (len_bytes, tail) = rest.split_at(num_bytes);
+		rest = tail;
+		len_bytes
+			.iter()
+			.fold(0usize, |acc, b| (acc << 8) | *b as usize)
+	};
+	if rest.len() < len {
+		return None;
+	}
+	let header_len = data.len() - rest.len();
+	let (value, rest) = rest.split_at(len);
+	let element = Der {
+		tag,
+		value,
+		raw: &data[..header_len + len],
+	};
+	Some((element, rest))
+}
+
+/// Reads the elements of a DER sequence one after another
+struct DerReader<'a> {
+	data: &'a [u8],
+}
+
+impl<'a> DerReader<'a> {
+	fn new(data: &'a [u8]) -> Self {
+		Self { data }
+	}
+
+	fn is_empty(&self) -> bool {
+		self.data.is_empty()
+	}
+
+	fn read_any(&mut self) -> Option<Der<'a>> {
+		let (element, rest) = read_der(self.data)?;
+		self.data = rest;
+		Some(element)
+	}
+
+	fn read(&mut self, tag: u8) -> Option<Der<'a>> {
+		self.read_any().filter(|element| element.tag == tag)
+	}
+
+	/// Reads the next element only if it has the given tag
+	fn read_optional(&mut self, tag: u8) -> Option<Der<'a>> {
+		if self.data.first() == Some(&tag) {
+			self.read_any()
+		} else {
+			None
+		}
+	}
+}
+
+/// Finds the value of the DER element following the given OID
+fn find_der_value_after_oid<'a>(der: &'a [u8], oid: &[u8]) -> Option<&'a [u8]> {
+	let mut pattern = Vec::with_capacity(oid.len() + 2);
+	pattern.push(DER_OID);
+	pattern.push(oid.len() as u8);
+	pattern.extend_from_slice(oid);
+	let pos = der
+		.windows(pattern.len())
+		.position(|window| window == &pattern[..])?;
+	read_der(&der[pos + pattern.len()..]).map(|(element, _)| element.value)
+}
+
+/// Decodes a UTCTime or a GeneralizedTime to the seconds since the unix epoch
+fn decode_der_time(time: &Der) -> Option<i64> {
+	let text = sp_std::str::from_utf8(time.value).ok()?.strip_suffix('Z')?;
+	let (year, text) = match time.tag {
+		DER_UTC_TIME => {
+			let year: i32 = text.get(..2)?.parse().ok()?;
+			let year = if year < 50 { 2000 + year } else { 1900 + year };
+			(year, text.get(2..)?)
+		}
+		DER_GENERALIZED_TIME => (text.get(..4)?.parse().ok()?, text.get(4..)?),
+		_ => return None,
+	};
+	// MMDDHHMMSS
+	if text.len() != 10 || !text.bytes().all(|b| b.is_ascii_digit()) {
+		return None;
+	}
+	let field = |i: usize| text[i..i + 2].parse::<u32>().ok();
+	let date = chrono::NaiveDate::from_ymd_opt(year, field(0)?, field(2)?)?;
+	Some(date.and_hms_opt(field(4)?, field(6)?, field(8)?)?.timestamp())
+}
+
+/// The signed part and the signature of a certificate or a CRL
+struct SignedDer<'a> {
+	tbs: Der<'a>,
+	signature: &'a [u8],
+}
+
+impl SignedDer<'_> {
+	fn parse(der: &[u8]) -> Option<SignedDer<'_>> {
+		let (outer, _) = read_der(der).filter(|(outer, _)| outer.tag == DER_SEQUENCE)?;
+		let mut reader = DerReader::new(outer.value);
+		let tbs = reader.read(DER_SEQUENCE)?;
+		let algorithm = reader.read(DER_SEQUENCE)?;
+		let signature = reader.read(DER_BIT_STRING)?;
+		let algorithm = DerReader::new(algorithm.value).read(DER_OID)?;
+		if algorithm.value != ECDSA_WITH_SHA256_OID {
+			return None;
+		}
+		// The number of unused bits in the bit string must be 0
+		match signature.value.split_first()? {
+			(0, signature) => Some(SignedDer { tbs, signature }),
+			_ => None,
+		}
+	}
+
+	/// Checks the signature with the P-256 key in the given subject public key info
+	fn is_signed_by(&self, spki: &[u8]) -> bool {
+		let mut reader = DerReader::new(spki);
+		let key = reader
+			.read(DER_SEQUENCE)
+			.and_then(|_| reader.read(DER_BIT_STRING));
+		match key.as_ref().and_then(|key| key.value.split_first()) {
+			Some((0, key)) => ring::signature::UnparsedPublicKey::new(
+				&ring::signature::ECDSA_P256_SHA256_ASN1,
+				key,
+			)
+			.verify(self.tbs.raw, self.signature)
+			.is_ok(),
+			_ => false,
+		}
+	}
+}
+
+/// The fields of a certificate to check its issuer and its revocation with
+struct Certificate<'a> {
+	signed: SignedDer<'a>,
+	serial: &'a [u8],
+	issuer: &'a [u8],
+	subject: &'a [u8],
+	spki: &'a [u8],
+}
+
+impl Certificate<'_> {
+	fn parse(der: &[u8]) -> Option<Certificate<'_>> {
+		let signed = SignedDer::parse(der)?;
+		let mut reader = DerReader::new(signed.tbs.value);
+		reader.read_optional(DER_EXPLICIT_0);
+		let serial = reader.read(DER_INTEGER)?.value;
+		reader.read(DER_SEQUENCE)?;
+		let issuer = reader.read(DER_SEQUENCE)?.value;
+		reader.read(DER_SEQUENCE)?;
+		let subject = reader.read(DER_SEQUENCE)?.value;
+		let spki = reader.read(DER_SEQUENCE)?.value;
+		Some(Certificate {
+			signed,
+			serial,
+			issuer,
+			subject,
+			spki,
+		})
+	}
+
+	/// Whether this certificate is issued and signed by the given issuer
+	fn is_issued_by(&self, issuer: &[u8], issuer_spki: &[u8]) -> bool {
+		self.issuer == issuer && self.signed.is_signed_by(issuer_spki)
+	}
+}
+
+/// The serial numbers revoked by a CRL
+struct RevocationList<'a> {
+	revoked: Vec<&'a [u8]>,
+}
+
+impl RevocationList<'_> {
+	fn revokes(&self, cert: &Certificate) -> bool {
+		self.revoked.contains(&cert.serial)
+	}
+}
+
+/// Parses a CRL checking it is issued and signed by the given issuer and not expired
+fn verify_crl<'a>(
+	der: &'a [u8],
+	issuer: &[u8],
+	issuer_spki: &[u8],
+	now: u64,
+) -> Result<RevocationList<'a>, Error> {
+	let signed = SignedDer::parse(der).ok_or(Error::InvalidCollateral)?;
+	if !signed.is_signed_by(issuer_spki) {
+		return Err(Error::InvalidCollateral);
+	}
+	let parse = || -> Option<(i64, Vec<&'a [u8]>)> {
+		let mut reader = DerReader::new(signed.tbs.value);
+		reader.read_optional(DER_INTEGER);
+		reader.read(DER_SEQUENCE)?;
+		if reader.read(DER_SEQUENCE)?.value != issuer {
+			return None;
+		}
+		reader.read_any()?;
+		let next_update = decode_der_time(&reader.read_any()?)?;
+		let mut revoked = Vec::new();
+		if let Some(entries) = reader.read_optional(DER_SEQUENCE) {
+			let mut entries = DerReader::new(entries.value);
+			while !entries.is_empty() {
+				let entry = entries.read(DER_SEQUENCE)?;
+				revoked.push(DerReader::new(entry.value).read(DER_INTEGER)?.value);
+			}
+		}
+		Some((next_update, revoked))
+	};
+	let (next_update, revoked) = parse().ok_or(Error::InvalidCollateral)?;
+	if now as i64 > next_update {
+		return Err(Error::OutdatedCollateral);
+	}
+	Ok(RevocationList { revoked })
+}
+
+fn decode_der_uint(value: &[u8]) -> Option<u16> {
+	if value.is_empty() || value.len() > 3 {
+		return None;
+	}
+	let value = value.iter().fold(0u32, |acc, b| (acc << 8) | *b as u32);
+	value.try_into().ok()
+}
+
+/// The platform info in the SGX extensions of a PCK certificate
+struct PckInfo {
+	fmspc: [u8; 6],
+	cpu_svn: [u8; 16],
+	pce_svn: u16,
+}
+
+fn parse_pck_extensions(cert: &[u8]) -> Result<PckInfo, Error> {
+	let oid = |suffix: &[u8]| -> Vec<u8> {
+		let mut oid = SGX_EXTENSIONS_OID.to_vec();
+		oid.extend_from_slice(suffix);
+		oid
+	};
+	let fmspc = find_der_value_after_oid(cert, &oid(&[SGX_EXTENSION_FMSPC]))
+		.and_then(|v| v.try_into().ok())
+		.ok_or(Error::InvalidPckCertChain)?;
+	let mut cpu_svn = [0u8; 16];
+	for (i, svn) in cpu_svn.iter_mut().enumerate() {
+		*svn = find_der_value_after_oid(cert, &oid(&[SGX_EXTENSION_TCB, i as u8 + 1]))
+			.and_then(decode_der_uint)
+			.and_then(|v| v.try_into().ok())
+			.ok_or(Error::InvalidPckCertChain)?;
+	}
+	let pce_svn = find_der_value_after_oid(cert, &oid(&[SGX_EXTENSION_TCB, TCB_PCESVN]))
+		.and_then(decode_der_uint)
+		.ok_or(Error::InvalidPckCertChain)?;
+	Ok(PckInfo {
+		fmspc,
+		cpu_svn,
+		pce_svn,
+	})
+}
+
+/// Verifies a piece of collateral signed by the TCB Signing certificate and returns the parsed
+/// JSON
+fn verify_collateral(
+	issuer_chain: &[u8],
+	body: &[u8],
+	signature: &[u8],
+	root: &webpki::TrustAnchor,
+	root_crl: &RevocationList,
+	now: u64,
+) -> Result<serde_json::Value, Error> {
+	let chain = decode_pem_chain(issuer_chain).or(Err(Error::InvalidCollateral))?;
+	let signer = verify_cert_chain(&chain, root, now).or(Err(Error::InvalidCollateral))?;
+	// Any certificate chained up to the root CA passes the check above, including the PCK ones
+	let signer_cert = Certificate::parse(&chain[0]).ok_or(Error::InvalidCollateral)?;
+	if !signer_cert.is_issued_by(root.subject, root.spki)
+		|| find_der_value_after_oid(signer_cert.subject, COMMON_NAME_OID)
+			!= Some(TCB_SIGNING_COMMON_NAME)
+	{
+		return Err(Error::InvalidCollateral);
+	}
+	if root_crl.revokes(&signer_cert) {
+		return Err(Error::CertificateRevoked);
+	}
+	let signature = encode_der_signature(signature).or(Err(Error::InvalidCollateral))?;
+	signer
+		.verify_signature(&webpki::ECDSA_P256_SHA256, body, &signature)
+		.or(Err(Error::InvalidCollateral))?;
+	let parsed: serde_json::Value =
+		serde_json::from_slice(body).or(Err(Error::InvalidCollateral))?;
+	let next_update = parsed["nextUpdate"]
+		.as_str()
+		.ok_or(Error::InvalidCollateral)?;
+	let next_update = chrono::DateTime::parse_from_rfc3339(next_update)
+		.or(Err(Error::InvalidCollateral))?
+		.timestamp();
+	if now as i64 > next_update {
+		return Err(Error::OutdatedCollateral);
+	}
+	Ok(parsed)
+}
+
+fn hex_field<const N: usize>(value: &serde_json::Value) -> Result<[u8; N], Error> {
+	let mut bytes = [0u8; N];
+	let text = value.as_str().ok_or(Error::InvalidCollateral)?;
+	hex::decode_to_slice(text, &mut bytes).or(Err(Error::InvalidCollateral))?;
+	Ok(bytes)
+}
+
+/// Grades a TCB status, `None` for `Revoked` or an unknown status
+fn tcb_status_level(status: &str) -> Option<u8> {
+	if DCAP_TCB_STATUS_LEVEL_1.contains(&status) {
+		Some(1)
+	} else if DCAP_TCB_STATUS_LEVEL_2.contains(&status) {
+		Some(2)
+	} else if DCAP_TCB_STATUS_LEVEL_3.contains(&status) {
+		Some(3)
+	} else if DCAP_TCB_STATUS_LEVEL_5.contains(&status) {
+		Some(5)
+	} else {
+		None
+	}
+}
+
+/// Looks up the TCB level of the platform and returns the graded confidence level
+fn platform_confidence_level(tcb_info: &serde_json::Value, pck: &PckInfo) -> Result<u8, Error> {
+	if hex_field::<6>(&tcb_info["fmspc"])? != pck.fmspc {
+		return Err(Error::InvalidCollateral);
+	}
+	let levels = tcb_info["tcbLevels"]
+		.as_array()
+		.ok_or(Error::InvalidCollateral)?;
+	for level in levels {
+		let tcb = &level["tcb"];
+		let mut matched = true;
+		for (i, key) in TCB_COMPONENT_KEYS.iter().enumerate() {
+			// TCB info v3 lists the components in an array, while v2 names them one by one
+			let svn = match tcb["sgxtcbcomponents"].as_array() {
+				Some(components) => components.get(i).and_then(|c| c["svn"].as_u64()),
+				None => tcb[*key].as_u64(),
+			}
+			.ok_or(Error::InvalidCollateral)?;
+			if (pck.cpu_svn[i] as u64) < svn {
+				matched = false;
+				break;
+			}
+		}
+		let pce_svn = tcb["pcesvn"].as_u64().ok_or(Error::InvalidCollateral)?;
+		if !matched || (pck.pce_svn as u64) < pce_svn {
+			continue;
+		}
+		let status = level["tcbStatus"]
+			.as_str()
+			.ok_or(Error::InvalidCollateral)?;
+		let mut confidence_level = tcb_status_level(status).ok_or(Error::InvalidQuoteStatus)?;
+		if confidence_level < 5 {
+			// `advisoryIDs` is optional
+			if let Some(advisory_ids) = level["advisoryIDs"].as_array() {
+				for advisory_id in advisory_ids {
+					let advisory_id = advisory_id.as_str().ok_or(Error::InvalidCollateral)?;
+					if !IAS_QUOTE_ADVISORY_ID_WHITELIST.contains(&advisory_id) {
+						confidence_level = 4;
+					}
+				}
+			}
+		}
+		return Ok(confidence_level);
+	}
+	Err(Error::InvalidQuoteStatus)
+}
+
+/// Checks the QE against its identity and returns the graded confidence level
+fn qe_confidence_level(qe_identity: &serde_json::Value, qe_report: &[u8]) -> Result<u8, Error> {
+	let miscselect: [u8; 4] = hex_field(&qe_identity["miscselect"])?;
+	let miscselect_mask: [u8; 4] = hex_field(&qe_identity["miscselectMask"])?;
+	let attributes: [u8; 16] = hex_field(&qe_identity["attributes"])?;
+	let attributes_mask: [u8; 16] = hex_field(&qe_identity["attributesMask"])?;
+	let mr_signer: [u8; 32] = hex_field(&qe_identity["mrsigner"])?;
+	let isv_prod_id = qe_identity["isvprodid"]
+		.as_u64()
+		.ok_or(Error::InvalidCollateral)?;
+
+	let masked_eq = |actual: &[u8], mask: &[u8], expected: &[u8]| {
+		actual
+			.iter()
+			.zip(mask)
+			.zip(expected)
+			.all(|((a, m), e)| a & m == *e)
+	};
+	// miscselect is little endian in the report but big endian in the identity
+	let mut report_miscselect = [0u8; 4];
+	report_miscselect.copy_from_slice(&qe_report[16..20]);
+	report_miscselect.reverse();
+	let report_isv_prod_id = u16::from_le_bytes(qe_report[256..258].try_into().unwrap());
+	let report_isv_svn = u16::from_le_bytes(qe_report[258..260].try_into().unwrap());
+	if !masked_eq(&report_miscselect, &miscselect_mask, &miscselect)
+		|| !masked_eq(&qe_report[48..64], &attributes_mask, &attributes)
+		|| qe_report[128..160] != mr_signer
+		|| report_isv_prod_id as u64 != isv_prod_id
+	{
+		return Err(Error::InvalidQeIdentity);
+	}
+
+	let levels = qe_identity["tcbLevels"]
+		.as_array()
+		.ok_or(Error::InvalidCollateral)?;
+	for level in levels {
+		let isv_svn = level["tcb"]["isvsvn"]
+			.as_u64()
+			.ok_or(Error::InvalidCollateral)?;
+		if (report_isv_svn as u64) < isv_svn {
+			continue;
+		}
+		let status = level["tcbStatus"]
+			.as_str()
+			.ok_or(Error::InvalidCollateral)?;
+		return tcb_status_level(status).ok_or(Error::InvalidQeIdentity);
+	}
+	Err(Error::InvalidQeIdentity)
+}
+
+pub fn validate_dcap_quote(
+	raw_quote: &[u8],
+	collateral: &DcapCollateral,
+	now: u64,
+	verify_pruntime: bool,
+	pruntime_allowlist: Vec<Vec<u8>>,
+) -> Result<IasFields, Error> {
+	validate_dcap_quote_with_root(
+		raw_quote,
+		collateral,
+		now,
+		verify_pruntime,
+		pruntime_allowlist,
+		&DCAP_SERVER_ROOTS.0[0],
+	)
+}
+
+fn validate_dcap_quote_with_root(
+	raw_quote: &[u8],
+	collateral: &DcapCollateral,
+	now: u64,
+	verify_pruntime: bool,
+	pruntime_allowlist: Vec<Vec<u8>>,
+	root: &webpki::TrustAnchor,
+) -> Result<IasFields, Error> {
+	let quote = parse_quote(raw_quote)?;
+
+	// Validate the PCK certificate and the QE report it signs
+	let pck_chain = decode_pem_chain(quote.pck_cert_chain)?;
+	let pck_cert = verify_cert_chain(&pck_chain, root, now)?;
+	let qe_report_signature = encode_der_signature(quote.qe_report_signature)?;
+	pck_cert
+		.verify_signature(
+			&webpki::ECDSA_P256_SHA256,
+			quote.qe_report,
+			&qe_report_signature,
+		)
+		.or(Err(Error::InvalidQuoteSignature))?;
+	let mut key_commit = quote.attestation_key.to_vec();
+	key_commit.extend_from_slice(quote.qe_auth_data);
+	let key_hash = crate::hashing::sha2_256(&key_commit);
+	let qe_report_data = &quote.qe_report[320..384];
+	if qe_report_data[..32] != key_hash || qe_report_data[32..].iter().any(|b| *b != 0) {
+		return Err(Error::InvalidQuoteSignature);
+	}
+
+	// Validate the enclave report signed by the attestation key
+	let mut signed = quote.header.to_vec();
+	signed.extend_from_slice(quote.report);
+	if !verify_raw_key_signature(quote.attestation_key, &signed, quote.signature) {
+		return Err(Error::InvalidQuoteSignature);
+	}
+
+	let report = quote.report;
+	let mr_enclave = &report[64..96];
+	let mr_signer = &report[128..160];
+	let isv_prod_id = &report[256..258];
+	let isv_svn = &report[258..260];
+
+	// Validate PRuntime
+	if verify_pruntime {
+		let t_mrenclave = extend_mrenclave(mr_enclave, mr_signer, isv_prod_id, isv_svn);
+		if !pruntime_allowlist.contains(&t_mrenclave) {
+			return Err(Error::PRuntimeRejected);
+		}
+	}
+
+	// Check the PCK certificate and the PCK CA issuing it are not revoked. The PCK CA must be the
+	// second certificate of the chain, so that the PCK CRL is checked against the right key.
+	let (pck_leaf, pck_ca) = match (
+		Certificate::parse(&pck_chain[0]),
+		pck_chain.get(1).and_then(|cert| Certificate::parse(cert)),
+	) {
+		(Some(leaf), Some(ca))
+			if ca.is_issued_by(root.subject, root.spki)
+				&& leaf.is_issued_by(ca.subject, ca.spki) =>
+		{
+			(leaf, ca)
+		}
+		_ => return Err(Error::InvalidPckCertChain),
+	};
+	let root_crl = verify_crl(&collateral.root_ca_crl, root.subject, root.spki, now)?;
+	let pck_crl = verify_crl(&collateral.pck_crl, pck_ca.subject, pck_ca.spki, now)?;
+	if root_crl.revokes(&pck_ca) || pck_crl.revokes(&pck_leaf) {
+		return Err(Error::CertificateRevoked);
+	}
+
+	// Validate the TCB status with the collateral
+	let tcb_info = verify_collateral(
+		&collateral.tcb_info_issuer_chain,
+		&collateral.tcb_info,
+		&collateral.tcb_info_signature,
+		root,
+		&root_crl,
+		now,
+	)?;
+	let qe_identity = verify_collateral(
+		&collateral.qe_identity_issuer_chain,
+		&collateral.qe_identity,
+		&collateral.qe_identity_signature,
+		root,
+		&root_crl,
+		now,
+	)?;
+	let pck_info = parse_pck_extensions(&pck_chain[0])?;
+	let confidence_level = platform_confidence_level(&tcb_info, &pck_info)?
+		.max(qe_confidence_level(&qe_identity, quote.qe_report)?);
+
+	Ok(IasFields {
+		mr_enclave: mr_enclave.try_into().unwrap(),
+		mr_signer: mr_signer.try_into().unwrap(),
+		isv_prod_id: isv_prod_id.try_into().unwrap(),
+		isv_svn: isv_svn.try_into().unwrap(),
+		report_data: report[320..384].try_into().unwrap(),
+		confidence_level,
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// Generated by `sample/gen_dcap_attestation.py` under a test root CA
+	pub const DCAP_SAMPLE: &[u8] = include_bytes!("../../sample/dcap_attestation.json");
+
+	struct Sample {
+		json: serde_json::Value,
+		root_ca: Vec<u8>,
+		quote: Vec<u8>,
+		collateral: DcapCollateral,
+		now: u64,
+	}
+
+	impl Sample {
+		fn load() -> Self {
+			let json: serde_json::Value = serde_json::from_slice(DCAP_SAMPLE).unwrap();
+			let collateral = DcapCollateral {
+				tcb_info_issuer_chain: Self::text(&json, "tcbInfoIssuerChain"),
+				tcb_info: Self::text(&json, "tcbInfo"),
+				tcb_info_signature: Self::hex(&json, "tcbInfoSignature"),
+				qe_identity_issuer_chain: Self::text(&json, "qeIdentityIssuerChain"),
+				qe_identity: Self::text(&json, "qeIdentity"),
+				qe_identity_signature: Self::hex(&json, "qeIdentitySignature"),
+				root_ca_crl: Self::hex(&json, "rootCaCrl"),
+				pck_crl: Self::hex(&json, "pckCrl"),
+			};
+			Sample {
+				root_ca: Self::hex(&json, "rootCa"),
+				quote: Self::hex(&json, "quote"),
+				now: json["timestamp"].as_u64().unwrap(),
+				collateral,
+				json,
+			}
+		}
+
+		fn text(json: &serde_json::Value, key: &str) -> Vec<u8> {
+			json[key].as_str().unwrap().as_bytes().to_vec()
+		}
+
+		fn hex(json: &serde_json::Value, key: &str) -> Vec<u8> {
+			hex::decode(json[key].as_str().unwrap()).unwrap()
+		}
+
+		fn validate(&self, collateral: &DcapCollateral, now: u64) -> Result<IasFields, Error> {
+			let root = webpki::TrustAnchor::try_from_cert_der(&self.root_ca).unwrap();
+			validate_dcap_quote_with_root(&self.quote, collateral, now, false, vec![], &root)
+		}
+	}
+
+	fn pck_info() -> PckInfo {
+		PckInfo {
+			fmspc: [0x00, 0x90, 0x6e, 0xa1, 0x00, 0x00],
+			cpu_svn: [2; 16],
+			pce_svn: 11,
+		}
+	}
+
+	fn tcb_info(levels: serde_json::Value) -> serde_json::Value {
+		serde_json::json!({
+			"version": 3,
+			"fmspc": "00906EA10000",
+			"tcbLevels": levels,
+		})
+	}
+
+	fn tcb_level(svn: u64, pce_svn: u64, status: &str) -> serde_json::Value {
+		let components: Vec<_> = (0..16).map(|_| serde_json::json!({ "svn": svn })).collect();
+		serde_json::json!({
+			"tcb": { "sgxtcbcomponents": components, "pcesvn": pce_svn },
+			"tcbStatus": status,
+		})
+	}
+
+	#[test]
+	fn malformed_quote_is_rejected() {
+		assert!(matches!(parse_quote(&[0u8; 16]), Err(Error::InvalidQuote)));
+		// An IAS (EPID) quote body is not a DCAP quote
+		let mut quote = vec![0u8; HEADER_BYTES + REPORT_BYTES + 4];
+		quote[0] = 2;
+		assert!(matches!(parse_quote(&quote), Err(Error::InvalidQuote)));
+		quote[0] = 3;
+		quote[2] = 2;
+		assert!(matches!(parse_quote(&quote), Err(Error::InvalidQuote)));
+	}
+
+	#[test]
+	fn raw_signature_is_der_encoded() {
+		let mut raw = [0u8; 64];
+		raw[31] = 1;
+		raw[32] = 0x80;
+		let der = encode_der_signature(&raw).unwrap();
+		assert_eq!(&der[..5], &[0x30, 38, 0x02, 1, 1]);
+		assert_eq!(&der[5..8], &[0x02, 33, 0]);
+		assert_eq!(der[8], 0x80);
+		assert!(encode_der_signature(&raw[..63]).is_err());
+	}
+
+	#[test]
+	fn platform_tcb_level_is_graded() {
+		let pck = pck_info();
+		let info = tcb_info(serde_json::json!([
+			tcb_level(3, 11, "UpToDate"),
+			tcb_level(2, 10, "SWHardeningNeeded"),
+			tcb_level(1, 5, "OutOfDate"),
+		]));
+		assert_eq!(platform_confidence_level(&info, &pck), Ok(2));
+
+		let info = tcb_info(serde_json::json!([tcb_level(1, 5, "Revoked")]));
+		assert_eq!(
+			platform_confidence_level(&info, &pck),
+			Err(Error::InvalidQuoteStatus)
+		);
+
+		let mut other = pck_info();
+		other.fmspc = [0; 6];
+		let info = tcb_info(serde_json::json!([tcb_level(1, 5, "UpToDate")]));
+		assert_eq!(
+			platform_confidence_level(&info, &other),
+			Err(Error::InvalidCollateral)
+		);
+	}
+	#[test]
+	fn dcap_quote_is_verified() {
+		let sample = Sample::load();
+		let fields = sample.validate(&sample.collateral, sample.now).unwrap();
+		assert_eq!(fields.mr_enclave.to_vec(), Sample::hex(&sample.json, "mrEnclave"));
+		assert_eq!(fields.report_data.to_vec(), Sample::hex(&sample.json, "reportData"));
+		assert_eq!(fields.confidence_level, 2);
+
+		// Only the Intel SGX Root CA is trusted on chain
+		assert_eq!(
+			validate_dcap_quote(&sample.quote, &sample.collateral, sample.now, false, vec![]),
+			Err(Error::InvalidPckCertChain)
+		);
+		let root = webpki::TrustAnchor::try_from_cert_der(&sample.root_ca).unwrap();
+		assert_eq!(
+			validate_dcap_quote_with_root(
+				&sample.quote,
+				&sample.collateral,
+				sample.now,
+				true,
+				vec![],
+				&root
+			),
+			Err(Error::PRuntimeRejected)
+		);
+	}
+
+	#[test]
+	fn revoked_certs_are_rejected() {
+		let sample = Sample::load();
+		for (pck_crl, root_ca_crl) in [
+			("pckCrlRevokingPck", "rootCaCrl"),
+			("pckCrl", "rootCaCrlRevokingPckCa"),
+			("pckCrl", "rootCaCrlRevokingTcbSigner"),
+		] {
+			let collateral = DcapCollateral {
+				pck_crl: Sample::hex(&sample.json, pck_crl),
+				root_ca_crl: Sample::hex(&sample.json, root_ca_crl),
+				..sample.collateral.clone()
+			};
+			assert_eq!(
+				sample.validate(&collateral, sample.now),
+				Err(Error::CertificateRevoked)
+			);
+		}
+
+		// The CRLs must be signed by their issuers and up to date
+		let collateral = DcapCollateral {
+			pck_crl: sample.collateral.root_ca_crl.clone(),
+			..sample.collateral.clone()
+		};
+		assert_eq!(
+			sample.validate(&collateral, sample.now),
+			Err(Error::InvalidCollateral)
+		);
+		let next_month = sample.now + 30 * 24 * 3600;
+		assert_eq!(
+			sample.validate(&sample.collateral, next_month),
+			Err(Error::OutdatedCollateral)
+		);
+	}
+
+	#[test]
+	fn collateral_must_be_signed_by_tcb_signing_cert() {
+		let sample = Sample::load();
+		// The PCK certificate is chained up to the root CA as well, and so can be any other
+		// certificate issued by the root CA
+		for (chain, signature) in [
+			("pckCertChain", "tcbInfoSignatureByPck"),
+			("otherSignerChain", "tcbInfoSignatureByOtherSigner"),
+		] {
+			let collateral = DcapCollateral {
+				tcb_info_issuer_chain: Sample::text(&sample.json, chain),
+				tcb_info_signature: Sample::hex(&sample.json, signature),
+				..sample.collateral.clone()
+			};
+			assert_eq!(
+				sample.validate(&collateral, sample.now),
+				Err(Error::InvalidCollateral)
+			);
+		}
+	}
+
+	#[test]
+	fn der_time_is_decoded() {
+		let time = |tag, value: &'static [u8]| Der {
+			tag,
+			value,
+			raw: &[],
+		};
+		assert_eq!(
+			decode_der_time(&time(DER_UTC_TIME, b"230701000000Z")),
+			Some(1688169600)
+		);
+		assert_eq!(
+			decode_der_time(&time(DER_GENERALIZED_TIME, b"20230701000000Z")),
+			Some(1688169600)
+		);
+		assert_eq!(decode_der_time(&time(DER_UTC_TIME, b"2307010000Z")), None);
+		assert_eq!(decode_der_time(&time(DER_UTC_TIME, b"231301000000Z")), None);
+	}
+}
@@ -1,5 +1,6 @@
 pub mod accumulator;
 pub(crate) mod attestation;
 pub(crate) mod balance_convert;
+pub(crate) mod dcap;
 pub mod constants;
 pub(crate) mod fixed_point;
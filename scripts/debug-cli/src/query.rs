@@ -1,11 +1,10 @@
 use anyhow::{anyhow, Result};
 use codec::{Decode, Encode};
 use phactory_api::{
-    crypto::{CertificateBody, EncryptedData},
+    crypto::{query, CertificateBody, EncryptedData},
     prpc,
 };
 use phala_crypto::ecdh::EcdhPublicKey;
-use phala_crypto::sr25519::KDF;
 use phala_types::contract;
 use phala_types::contract::ContractId;
 use sp_core::Pair as _;
@@ -16,13 +15,9 @@ pub async fn query<Request: Encode, Response: Decode>(
     id: ContractId,
     data: Request,
 ) -> Result<Response> {
-    // 2. Make ContractQuery
-    let nonce = [1; 32];
-    let head = contract::ContractQueryHead { id, nonce };
-    let query = contract::ContractQuery { head, data };
-
     let pr = phactory_api::pruntime_client::new_pruntime_client(url);
 
+    // 2. Get the ECDH pubkey of the worker.
     let info = pr.get_info(()).await?;
     let remote_pubkey = info
         .ecdh_public_key
@@ -30,16 +25,14 @@ pub async fn query<Request: Encode, Response: Decode>(
     let remote_pubkey = super::try_decode_hex(&remote_pubkey)?;
     let remote_pubkey = EcdhPublicKey::try_from(&remote_pubkey[..])?;
 
-    // 3. Encrypt the ContractQuery.
-
-    let ecdh_key = sp_core::sr25519::Pair::generate()
-        .0
-        .derive_ecdh_key()
-        .or(Err(anyhow!("Derive ecdh key failed")))?;
-
-    let iv = [1; 12];
-    let encrypted_data = EncryptedData::encrypt(&ecdh_key, &remote_pubkey, iv, &query.encode())
-        .or(Err(anyhow!("Encrypt data failed")))?;
+    // 3. Make the ContractQuery and encrypt it, under a fresh key and nonce.
+    let (pending, payload) =
+        query::client::seal_query(&mut rand::thread_rng(), &remote_pubkey, |nonce| {
+            let head = contract::ContractQueryHead { id, nonce: *nonce };
+            contract::ContractQuery { head, data }.encode()
+        })
+        .map_err(|err| anyhow!("Encrypt data failed: {:?}", err))?;
+    let encrypted_data = EncryptedData::from(payload);
 
     // 4. Sign the encrypted data.
     // 4.1 Make the root certificate.
@@ -76,18 +69,12 @@ pub async fn query<Request: Encode, Response: Decode>(
     // 5. Do the RPC call.
     let response = pr.contract_query(request).await?;
 
-    // 6. Decrypt the response.
-    let encrypted_data = response.decode_encrypted_data()?;
-    let data = encrypted_data
-        .decrypt(&ecdh_key)
-        .or(Err(anyhow!("Decrypt data failed")))?;
+    // 6. Decrypt the response, which must echo the nonce of the query.
+    let payload: query::EncryptedPayload = response.decode_encrypted_data()?.into();
+    let result = pending
+        .open_response(&payload)
+        .map_err(|err| anyhow!("Decrypt data failed: {:?}", err))?;
 
     // 7. Decode the response.
-    let response: contract::ContractQueryResponse<Response> = Decode::decode(&mut &data[..])?;
-
-    // 8. check the nonce is match the one we sent.
-    if response.nonce != nonce {
-        return Err(anyhow!("nonce mismatch"));
-    }
-    Ok(response.result)
+    Ok(Decode::decode(&mut &result[..])?)
 }
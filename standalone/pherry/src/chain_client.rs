@@ -66,11 +66,15 @@ pub async fn fetch_storage_changes(api: &ParachainApi, hash: &Hash) -> Result<St
 
 /// Fetch the genesis storage.
 pub async fn fetch_genesis_storage(api: &ParachainApi) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
-    let hash = Some(*api.client.genesis());
+    fetch_storage_at(api, *api.client.genesis()).await
+}
+
+/// Fetch all the storage pairs at the given block
+pub async fn fetch_storage_at(api: &ParachainApi, hash: Hash) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
     let response = api
         .client
         .extra_rpc()
-        .storage_pairs(StorageKey(vec![]), hash)
+        .storage_pairs(StorageKey(vec![]), Some(hash))
         .await?;
     let storage = response.into_iter().map(|(k, v)| (k.0, v.0)).collect();
     Ok(storage)
@@ -6,8 +6,9 @@ use std::cmp;
 use std::str::FromStr;
 use std::time::Duration;
 use tokio::time::sleep;
+use tracing::instrument;
 
-use codec::Decode;
+use codec::{Decode, Encode};
 use phaxt::rpc::ExtraRpcExt as _;
 use phaxt::subxt;
 use sp_core::{crypto::Pair, sr25519, storage::StorageKey};
@@ -148,6 +149,19 @@ struct Args {
     #[clap(long, help = "Don't wait the substrate nodes to sync blocks")]
     no_wait: bool,
 
+    #[clap(
+        long,
+        help = "Bootstrap a new pRuntime from a verified snapshot of the finalized parachain state \
+                instead of replaying all the blocks. Parachain mode only, not for gatekeepers"
+    )]
+    fast_sync: bool,
+
+    #[clap(
+        long,
+        help = "The file containing the admin token of pRuntime, required by --fast-sync"
+    )]
+    pruntime_admin_token_file: Option<String>,
+
     #[clap(
         default_value = "5000",
         long,
@@ -186,6 +200,12 @@ struct Args {
 
     #[clap(long, help = "Restart if number of rpc errors reaches the threshold")]
     restart_on_rpc_error_threshold: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Export the sync spans to the OTLP collector, e.g. http://localhost:4317"
+    )]
+    otlp_endpoint: Option<String>,
 }
 
 struct RunningFlags {
@@ -351,6 +371,7 @@ async fn bisec_setid_change(
     Ok(result)
 }
 
+#[instrument(skip_all, fields(count = headers.len()))]
 async fn req_sync_header(
     pr: &PrClient,
     headers: Vec<HeaderToSync>,
@@ -362,6 +383,7 @@ async fn req_sync_header(
     Ok(resp)
 }
 
+#[instrument(skip_all, fields(count = headers.len()))]
 async fn req_sync_para_header(
     pr: &PrClient,
     headers: blocks::Headers,
@@ -373,6 +395,10 @@ async fn req_sync_para_header(
     Ok(resp)
 }
 
+#[instrument(skip_all, fields(
+    from = blocks.first().map(|b| b.block_header.number),
+    to = blocks.last().map(|b| b.block_header.number),
+))]
 async fn req_dispatch_block(
     pr: &PrClient,
     blocks: Vec<BlockHeaderWithChanges>,
@@ -414,6 +440,7 @@ async fn sync_events_only(
 
 const GRANDPA_ENGINE_ID: sp_runtime::ConsensusEngineId = *b"FRNK";
 
+#[instrument(skip_all)]
 async fn batch_sync_block(
     api: &RelaychainApi,
     paraclient: &ParachainApi,
@@ -422,6 +449,7 @@ async fn batch_sync_block(
     batch_window: usize,
     info: &prpc::PhactoryInfo,
     parachain: bool,
+    fast_sync: Option<&FastSync>,
 ) -> Result<usize> {
     let block_buf = &mut sync_state.blocks;
     if block_buf.is_empty() {
@@ -540,9 +568,31 @@ async fn batch_sync_block(
         next_headernum = r.synced_to + 1;
 
         if parachain {
-            let hdr_synced_to =
-                sync_parachain_header(pr, api, paraclient, last_header_hash, next_para_headernum)
-                    .await?;
+            let hdr_synced_to = match fast_sync {
+                Some(fast_sync) if next_para_headernum == 1 && next_blocknum == 1 => {
+                    // Import the latest state, not the one finalized by the first headers
+                    if reached_relay_tip(api, block_buf, last_header_number).await? {
+                        let synced_to =
+                            import_state_snapshot(fast_sync, api, paraclient, last_header_hash)
+                                .await?;
+                        next_blocknum = synced_to + 1;
+                        synced_to
+                    } else {
+                        info!("Syncing the relaychain headers to the finalized tip for fast sync");
+                        0
+                    }
+                }
+                _ => {
+                    sync_parachain_header(
+                        pr,
+                        api,
+                        paraclient,
+                        last_header_hash,
+                        next_para_headernum,
+                    )
+                    .await?
+                }
+            };
             next_para_headernum = hdr_synced_to + 1;
             let mut para_blocks = Vec::new();
             if next_blocknum <= hdr_synced_to {
@@ -586,6 +636,7 @@ async fn batch_sync_block(
     Ok(synced_blocks)
 }
 
+#[instrument(skip_all, fields(next_headernum = next_headernum))]
 async fn sync_parachain_header(
     pr: &PrClient,
     api: &RelaychainApi,
@@ -653,6 +704,125 @@ async fn sync_parachain_header(
     Ok(r.synced_to)
 }
 
+/// Returns true if no more relaychain headers can be synced up to the finalized tip after
+/// `synced_to`: the buffer reaches the tip, and none of the blocks left in it is justified.
+async fn reached_relay_tip(
+    api: &RelaychainApi,
+    block_buf: &[BlockWithChanges],
+    synced_to: BlockNumber,
+) -> Result<bool> {
+    let justified = block_buf.iter().any(|b| {
+        b.block
+            .justifications
+            .as_ref()
+            .and_then(|v| v.get(GRANDPA_ENGINE_ID))
+            .is_some()
+    });
+    if justified {
+        return Ok(false);
+    }
+    let tip_hash = get_header_hash(&api.client, None).await?;
+    let tip = api
+        .client
+        .rpc()
+        .header(Some(tip_hash))
+        .await?
+        .ok_or(Error::BlockNotFound)?;
+    let buffered_to = block_buf
+        .last()
+        .map_or(synced_to, |b| b.block.block.header.number);
+    Ok(buffered_to >= tip.number)
+}
+
+/// The pRuntime to import the state snapshot to, for `--fast-sync`
+struct FastSync {
+    endpoint: String,
+    admin_token: String,
+}
+
+/// Sends the request to the admin bin_api of pRuntime, which has no pRPC counterpart.
+async fn req_import_state_snapshot(
+    fast_sync: &FastSync,
+    request: blocks::ImportStateSnapshotReq,
+) -> Result<BlockNumber> {
+    let url = format!("{}/admin/bin_api/import_state_snapshot", fast_sync.endpoint);
+    let response: serde_json::Value = reqwest::Client::new()
+        .post(url)
+        .bearer_auth(&fast_sync.admin_token)
+        .body(request.encode())
+        .send()
+        .await?
+        .json()
+        .await?;
+    // The payload is the json output of the action, encoded as a string.
+    let payload: serde_json::Value = response["payload"]
+        .as_str()
+        .map(serde_json::from_str)
+        .transpose()?
+        .unwrap_or_default();
+    if response["status"] != "ok" {
+        return Err(anyhow!("Failed to import the state snapshot: {}", payload));
+    }
+    payload["synced_to"]
+        .as_u64()
+        .map(|n| n as BlockNumber)
+        .ok_or_else(|| anyhow!("Invalid import_state_snapshot response: {}", payload))
+}
+
+/// Bootstraps pRuntime with the state at the parachain head finalized by the relaychain block
+/// `last_header_hash`, which must be the last relaychain header synced to pRuntime, at the
+/// finalized tip.
+///
+/// pRuntime verifies the parachain header against the relaychain state, and the snapshot against
+/// the state root in the header. Returns the parachain block number synced to, or 0 if the
+/// relaychain block has no parachain head yet.
+#[instrument(skip_all)]
+async fn import_state_snapshot(
+    fast_sync: &FastSync,
+    api: &RelaychainApi,
+    para_api: &ParachainApi,
+    last_header_hash: Hash,
+) -> Result<BlockNumber> {
+    let para_id = get_paraid(para_api, None).await?;
+    let para_head_storage_key = chain_client::paras_heads_key(para_id);
+
+    let raw_header = api
+        .client
+        .rpc()
+        .storage(&para_head_storage_key, Some(last_header_hash))
+        .await?;
+    let raw_header = match raw_header {
+        Some(hdr) => hdr.0,
+        None => return Ok(0),
+    };
+    let para_header_data = chain_client::decode_parachain_heads(raw_header)?;
+    let para_header =
+        sp_runtime::generic::Header::<BlockNumber, sp_runtime::traits::BlakeTwo256>::decode(
+            &mut para_header_data.as_slice(),
+        )
+        .or(Err(Error::FailedToDecode))?;
+    let proof =
+        chain_client::read_proof(api, Some(last_header_hash), para_head_storage_key).await?;
+
+    let state = chain_client::fetch_storage_at(para_api, para_header.hash()).await?;
+    info!(
+        "Importing the state snapshot at parachain block {} ({} pairs)",
+        para_header.number,
+        state.len()
+    );
+    let synced_to = req_import_state_snapshot(
+        fast_sync,
+        blocks::ImportStateSnapshotReq {
+            para_header,
+            proof,
+            state,
+        },
+    )
+    .await?;
+    info!("..import_state_snapshot: synced to {}", synced_to);
+    Ok(synced_to)
+}
+
 /// Resolves the starting block header for the genesis block.
 ///
 /// It returns the specified value if `start_header` is Some. Otherwise, it returns 0 for
@@ -930,6 +1100,21 @@ async fn bridge(
         return Ok(());
     }
 
+    let fast_sync = if args.fast_sync && args.parachain {
+        let token_file = args
+            .pruntime_admin_token_file
+            .as_ref()
+            .ok_or_else(|| anyhow!("--fast-sync requires --pruntime-admin-token-file"))?;
+        let admin_token = std::fs::read_to_string(token_file)
+            .context("Failed to read the pRuntime admin token")?;
+        Some(FastSync {
+            endpoint: args.pruntime_endpoint.clone(),
+            admin_token: admin_token.trim().to_string(),
+        })
+    } else {
+        None
+    };
+
     // Don't just sync message if we want to wait for some block
     let mut sync_state = BlockSyncState {
         blocks: Vec::new(),
@@ -1028,6 +1213,7 @@ async fn bridge(
             args.sync_blocks,
             &info,
             args.parachain,
+            fast_sync.as_ref(),
         )
         .await?;
 
@@ -1168,6 +1354,12 @@ pub async fn pherry_main() {
     let mut args = Args::parse();
     preprocess_args(&mut args);
 
+    if let Some(endpoint) = &args.otlp_endpoint {
+        if let Err(err) = phala_tracing::init("pherry", endpoint) {
+            error!("Failed to init the trace exporter: {:?}", err);
+        }
+    }
+
     let mut flags = RunningFlags {
         worker_registered: false,
         restart_failure_count: 0,
@@ -1187,10 +1379,12 @@ pub async fn pherry_main() {
             () = collect_async_errors(threshold, receiver) => ()
         };
         if !args.auto_restart || flags.restart_failure_count > args.max_restart_retries {
+            phala_tracing::shutdown();
             std::process::exit(if flags.worker_registered { 1 } else { 2 });
         }
         flags.restart_failure_count += 1;
         sleep(Duration::from_secs(2)).await;
         info!("Restarting...");
     }
+    phala_tracing::shutdown();
 }
@@ -0,0 +1,284 @@
+//! Walks through a CBOR stream item by item, so that a checkpoint is never decoded as a whole.
+//!
+//! The items the visitor is not interested in are copied to the output, or skipped, without being
+//! decoded. Only the items the visitor asks for are decoded to a [`Value`].
+
+use std::io::{self, Read, Write};
+
+use anyhow::{bail, Result};
+use serde_cbor::Value;
+
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+const MAJOR_SIMPLE: u8 = 7;
+const INDEFINITE: u8 = 31;
+const BREAK: u8 = 0xff;
+
+/// What to do with an item met while walking.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visit {
+    /// Copy the item to the output without decoding it
+    Copy,
+    /// Walk into the items of the array or the map
+    Enter,
+    /// Decode the item and hand it to [`Visitor::decoded`]
+    Decode,
+    /// Leave the item, or the map entry, out of the output
+    Remove,
+}
+
+pub trait Visitor {
+    /// Decides what to do with the item at `path`, the array indices and the map keys leading to
+    /// it.
+    fn visit(&mut self, path: &[Value]) -> Visit;
+
+    /// Inspects or modifies an item decoded for [`Visit::Decode`]. Returns false to leave the item
+    /// out of the output.
+    fn decoded(&mut self, path: &[Value], value: &mut Value) -> Result<bool>;
+}
+
+/// Walks through the CBOR item in `reader`, writing the result to `writer`.
+///
+/// The arrays and maps walked into are written with an indefinite length, since the visitor may
+/// remove some of their items.
+pub fn walk(reader: impl Read, writer: &mut dyn Write, visitor: &mut impl Visitor) -> Result<()> {
+    let mut walker = Walker { reader };
+    let initial = walker.read_u8()?;
+    walker.walk_item(&mut Vec::new(), initial, &[], writer, visitor)?;
+    Ok(())
+}
+
+/// Whether `path` matches `pattern`, in which `*` matches any key, a number matches an array
+/// index and any other text matches a text key.
+pub fn path_matches(path: &[Value], pattern: &[&str]) -> bool {
+    path.len() == pattern.len()
+        && path.iter().zip(pattern).all(|(segment, pattern)| match segment {
+            _ if *pattern == "*" => true,
+            Value::Integer(index) => pattern.parse() == Ok(*index),
+            Value::Text(key) => key == pattern,
+            _ => false,
+        })
+}
+
+struct Walker<R> {
+    reader: R,
+}
+
+impl<R: Read> Walker<R> {
+    fn read_u8(&mut self) -> Result<u8> {
+        let mut byte = [0u8];
+        self.reader.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    /// Reads the argument following the initial byte into `raw`
+    fn read_argument(&mut self, initial: u8, raw: &mut Vec<u8>) -> Result<Option<u64>> {
+        let info = initial & 0x1f;
+        let len = match info {
+            0..=23 => return Ok(Some(info as u64)),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            INDEFINITE => return Ok(None),
+            _ => bail!("Invalid CBOR initial byte {:#x}", initial),
+        };
+        let mut bytes = [0u8; 8];
+        self.reader.read_exact(&mut bytes[8 - len..])?;
+        raw.extend_from_slice(&bytes[8 - len..]);
+        Ok(Some(u64::from_be_bytes(bytes)))
+    }
+
+    /// Copies the item beginning with `initial` to `writer`
+    fn copy_item(&mut self, initial: u8, writer: &mut dyn Write) -> Result<()> {
+        let mut header = vec![initial];
+        let argument = self.read_argument(initial, &mut header)?;
+        writer.write_all(&header)?;
+        match (initial >> 5, argument) {
+            (MAJOR_BYTES | MAJOR_TEXT, Some(len)) => {
+                let copied = io::copy(&mut (&mut self.reader).take(len), writer)?;
+                if copied != len {
+                    bail!("Unexpected end of the checkpoint");
+                }
+            }
+            (MAJOR_ARRAY, Some(len)) => {
+                for _ in 0..len {
+                    let initial = self.read_u8()?;
+                    self.copy_item(initial, writer)?;
+                }
+            }
+            // A tag is followed by the tagged item
+            (MAJOR_TAG, Some(_)) => {
+                let initial = self.read_u8()?;
+                self.copy_item(initial, writer)?;
+            }
+            (MAJOR_MAP, Some(len)) => {
+                for _ in 0..len * 2 {
+                    let initial = self.read_u8()?;
+                    self.copy_item(initial, writer)?;
+                }
+            }
+            (MAJOR_BYTES | MAJOR_TEXT | MAJOR_ARRAY | MAJOR_MAP, None) => loop {
+                let initial = self.read_u8()?;
+                if initial == BREAK {
+                    writer.write_all(&[BREAK])?;
+                    break;
+                }
+                self.copy_item(initial, writer)?;
+            },
+            (MAJOR_SIMPLE, None) => bail!("Unexpected CBOR break"),
+            (_, None) => bail!("Invalid CBOR initial byte {:#x}", initial),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn read_item(&mut self, initial: u8) -> Result<Vec<u8>> {
+        let mut raw = Vec::new();
+        self.copy_item(initial, &mut raw)?;
+        Ok(raw)
+    }
+
+    /// Calls `f` with the initial byte of each item in the container, until the end of it.
+    fn for_each_item(
+        &mut self,
+        count: Option<u64>,
+        mut f: impl FnMut(&mut Self, u8) -> Result<()>,
+    ) -> Result<()> {
+        let mut index = 0;
+        loop {
+            if count == Some(index) {
+                return Ok(());
+            }
+            let initial = self.read_u8()?;
+            if count.is_none() && initial == BREAK {
+                return Ok(());
+            }
+            f(self, initial)?;
+            index += 1;
+        }
+    }
+
+    /// Walks through the item beginning with `initial`, writing `prefix` before it unless it is
+    /// removed. Returns whether the item is kept.
+    fn walk_item(
+        &mut self,
+        path: &mut Vec<Value>,
+        initial: u8,
+        prefix: &[u8],
+        writer: &mut dyn Write,
+        visitor: &mut impl Visitor,
+    ) -> Result<bool> {
+        let major = initial >> 5;
+        match visitor.visit(path) {
+            Visit::Enter if major == MAJOR_ARRAY || major == MAJOR_MAP => {
+                let count = self.read_argument(initial, &mut Vec::new())?;
+                writer.write_all(prefix)?;
+                writer.write_all(&[(major << 5) | INDEFINITE])?;
+                let mut index = 0;
+                self.for_each_item(count, |walker, initial| {
+                    if major == MAJOR_ARRAY {
+                        path.push(Value::Integer(index));
+                        index += 1;
+                        walker.walk_item(path, initial, &[], writer, visitor)?;
+                    } else {
+                        let key = walker.read_item(initial)?;
+                        path.push(serde_cbor::from_slice(&key)?);
+                        let initial = walker.read_u8()?;
+                        walker.walk_item(path, initial, &key, writer, visitor)?;
+                    }
+                    path.pop();
+                    Ok(())
+                })?;
+                writer.write_all(&[BREAK])?;
+                Ok(true)
+            }
+            Visit::Copy | Visit::Enter => {
+                writer.write_all(prefix)?;
+                self.copy_item(initial, writer)?;
+                Ok(true)
+            }
+            Visit::Decode => {
+                let mut value = serde_cbor::from_slice(&self.read_item(initial)?)?;
+                if !visitor.decoded(path, &mut value)? {
+                    return Ok(false);
+                }
+                writer.write_all(prefix)?;
+                serde_cbor::to_writer(writer, &value)?;
+                Ok(true)
+            }
+            Visit::Remove => {
+                self.copy_item(initial, &mut io::sink())?;
+                Ok(false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.into())
+    }
+
+    /// Removes the key `b` and doubles the integers under the key `a`
+    struct Doubler;
+
+    impl Visitor for Doubler {
+        fn visit(&mut self, path: &[Value]) -> Visit {
+            match path.last() {
+                None => Visit::Enter,
+                Some(key) if key == &text("a") => Visit::Enter,
+                Some(key) if key == &text("b") => Visit::Remove,
+                Some(Value::Integer(_)) if path.len() == 2 => Visit::Decode,
+                _ => Visit::Copy,
+            }
+        }
+
+        fn decoded(&mut self, _path: &[Value], value: &mut Value) -> Result<bool> {
+            if let Value::Integer(n) = value {
+                *n *= 2;
+            }
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn items_are_walked_through() {
+        let input = Value::Map(
+            vec![
+                (text("a"), Value::Array(vec![Value::Integer(1), Value::Integer(300)])),
+                (text("b"), Value::Bytes(vec![0; 1000])),
+                (text("c"), Value::Array(vec![Value::Integer(1), text("x")])),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let mut output = Vec::new();
+        walk(&serde_cbor::to_vec(&input).unwrap()[..], &mut output, &mut Doubler).unwrap();
+        let output: Value = serde_cbor::from_slice(&output).unwrap();
+        let expected = Value::Map(
+            vec![
+                (text("a"), Value::Array(vec![Value::Integer(2), Value::Integer(600)])),
+                (text("c"), Value::Array(vec![Value::Integer(1), text("x")])),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn path_is_matched() {
+        let path = [Value::Integer(3), text("contracts"), Value::Bytes(vec![1])];
+        assert!(path_matches(&path, &["3", "contracts", "*"]));
+        assert!(!path_matches(&path, &["2", "contracts", "*"]));
+        assert!(!path_matches(&path, &["3", "contracts"]));
+        assert!(!path_matches(&path, &["3", "clusters", "*"]));
+    }
+}
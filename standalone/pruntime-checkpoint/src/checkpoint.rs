@@ -0,0 +1,599 @@
+//! A schema-less view of a pRuntime checkpoint.
+//!
+//! The items of the checkpoint are decoded to CBOR values instead of the phactory types, so that
+//! it can still be inspected and repaired when some sections no longer decode to the typed state.
+//! The checkpoint is walked through as a stream, decoding only the items each action needs.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use phala_crypto::aead::stream::{new_aes128gcm_reader, new_aes128gcm_writer};
+use phala_mq::MessageOrigin;
+use serde_cbor::Value;
+
+use crate::cbor::{self, path_matches, Visit, Visitor};
+
+/// How the checkpoint file is protected.
+#[derive(Clone, Copy)]
+pub enum Protection {
+    /// Plain CBOR, as written by pRuntime on a platform without file protection
+    Plain,
+    /// An AES-128-GCM stream with the given key, see `phactory::derive_key_for_checkpoint`
+    Encrypted([u8; 16]),
+}
+
+/// The indices of the sections of a checkpoint, in the order of `Phactory::dump_state`.
+const VERSION: &str = "0";
+const PHACTORY: &str = "2";
+const SYSTEM: &str = "3";
+
+pub fn open(path: &Path, protection: Protection) -> Result<Box<dyn Read>> {
+    let file = BufReader::new(File::open(path).context("Failed to open the checkpoint")?);
+    Ok(match protection {
+        Protection::Plain => Box::new(file),
+        Protection::Encrypted(key) => Box::new(new_aes128gcm_reader(key, file)),
+    })
+}
+
+pub fn create(path: &Path, protection: Protection) -> Result<Box<dyn Write>> {
+    let file = BufWriter::new(File::create(path).context("Failed to create the checkpoint")?);
+    Ok(match protection {
+        Protection::Plain => Box::new(file),
+        Protection::Encrypted(key) => Box::new(new_aes128gcm_writer(key, rand::random(), file)),
+    })
+}
+
+fn visit_by_rules(path: &[Value], rules: &[(&[&str], Visit)]) -> Visit {
+    rules
+        .iter()
+        .find(|(pattern, _)| path_matches(path, pattern))
+        .map_or(Visit::Copy, |(_, visit)| *visit)
+}
+
+/// The cluster, the state size and whether a sidevm is running, of a contract.
+pub type ContractSummary = (String, usize, bool);
+
+/// The overview of a checkpoint.
+#[derive(Default, Debug)]
+pub struct Summary {
+    pub version: u64,
+    pub genesis: Vec<u8>,
+    /// The next header number and the next block number of the storage synchronizer
+    pub sync_counters: (u64, u64),
+    /// The send queue channels, as (sender, next sequence, pending messages)
+    pub egress: Vec<(String, u64, usize)>,
+    /// The contracts, as (id, cluster, state size, running a sidevm)
+    pub contracts: Vec<(String, Result<ContractSummary>)>,
+    /// The clusters, as (id, number of contracts)
+    pub clusters: Vec<(String, Result<usize>)>,
+}
+
+/// Collects the [`Summary`] of a checkpoint, decoding only the items it needs.
+pub fn summarize(reader: impl Read) -> Result<Summary> {
+    const RULES: &[(&[&str], Visit)] = &[
+        (&[], Visit::Enter),
+        (&[VERSION], Visit::Decode),
+        (&[PHACTORY], Visit::Enter),
+        (&[PHACTORY, "runtime_state"], Visit::Enter),
+        (&[PHACTORY, "runtime_state", "send_mq"], Visit::Decode),
+        (&[PHACTORY, "runtime_state", "storage_synchronizer"], Visit::Decode),
+        (&[PHACTORY, "runtime_state", "genesis_block_hash"], Visit::Decode),
+        (&[SYSTEM], Visit::Enter),
+        (&[SYSTEM, "contracts"], Visit::Enter),
+        (&[SYSTEM, "contracts", "*"], Visit::Decode),
+        (&[SYSTEM, "contract_clusters"], Visit::Enter),
+        (&[SYSTEM, "contract_clusters", "clusters"], Visit::Enter),
+        (&[SYSTEM, "contract_clusters", "clusters", "*"], Visit::Enter),
+        (&[SYSTEM, "contract_clusters", "clusters", "*", "contracts"], Visit::Decode),
+    ];
+
+    #[derive(Default)]
+    struct Summarizer {
+        version: Option<u64>,
+        genesis: Option<Vec<u8>>,
+        sync_counters: Option<(u64, u64)>,
+        egress: Option<Vec<(String, u64, usize)>>,
+        contracts: Vec<(String, Result<ContractSummary>)>,
+        clusters: Vec<(String, Result<usize>)>,
+    }
+
+    impl Visitor for Summarizer {
+        fn visit(&mut self, path: &[Value]) -> Visit {
+            if path_matches(path, &[SYSTEM, "contract_clusters", "clusters", "*"]) {
+                // Stays corrupted unless its contract list is decoded
+                let id = id_text(&path[3]);
+                self.clusters.push((id, Err(anyhow!("Bad contract list"))));
+            }
+            visit_by_rules(path, RULES)
+        }
+
+        fn decoded(&mut self, path: &[Value], value: &mut Value) -> Result<bool> {
+            let runtime_state = |name| path_matches(path, &[PHACTORY, "runtime_state", name]);
+            if path_matches(path, &[VERSION]) {
+                self.version = Some(integer(value)?);
+            } else if runtime_state("send_mq") {
+                self.egress = Some(egress_channels(value)?);
+            } else if runtime_state("storage_synchronizer") {
+                self.sync_counters = Some(sync_counters(value)?);
+            } else if runtime_state("genesis_block_hash") {
+                self.genesis = Some(bytes(value)?);
+            } else if path_matches(path, &[SYSTEM, "contracts", "*"]) {
+                let id = id_text(&path[2]);
+                self.contracts.push((id, contract_summary(value)));
+            } else if let Some((_, contracts)) = self.clusters.last_mut() {
+                *contracts = match value {
+                    Value::Array(members) => Ok(members.len()),
+                    _ => Err(anyhow!("Bad contract list")),
+                };
+            }
+            Ok(true)
+        }
+    }
+
+    let mut summarizer = Summarizer::default();
+    cbor::walk(reader, &mut io::sink(), &mut summarizer)?;
+    let missing = |name: &str| anyhow!("Missing field: {}", name);
+    Ok(Summary {
+        version: summarizer.version.ok_or_else(|| missing("version"))?,
+        genesis: summarizer.genesis.ok_or_else(|| missing("genesis_block_hash"))?,
+        sync_counters: summarizer
+            .sync_counters
+            .ok_or_else(|| missing("storage_synchronizer"))?,
+        egress: summarizer.egress.ok_or_else(|| missing("send_mq"))?,
+        contracts: summarizer.contracts,
+        clusters: summarizer.clusters,
+    })
+}
+
+fn contract_summary(contract: &Value) -> Result<ContractSummary> {
+    let cluster = id_text(field(contract, "cluster_id")?);
+    let state_size = bytes(field(contract, "contract")?)?.len();
+    let sidevm = !matches!(field(contract, "sidevm_info")?, Value::Null);
+    Ok((cluster, state_size, sidevm))
+}
+
+/// The (next header number, next block number) of the storage synchronizer.
+fn sync_counters(synchronizer: &Value) -> Result<(u64, u64)> {
+    let sync_state = match synchronizer {
+        Value::Map(map) => map
+            .values()
+            .next()
+            .ok_or_else(|| anyhow!("Empty synchronizer"))?,
+        _ => bail!("Bad synchronizer"),
+    };
+    let sync_state = field(sync_state, "sync_state")?;
+    Ok((
+        integer(field(sync_state, "header_number_next")?)?,
+        integer(field(sync_state, "block_number_next")?)?,
+    ))
+}
+
+/// The send queue channels, as (sender, next sequence, pending messages).
+fn egress_channels(send_mq: &Value) -> Result<Vec<(String, u64, usize)>> {
+    map(send_mq)?
+        .iter()
+        .map(|(sender, channel)| {
+            let sequence = integer(field(channel, "sequence")?)?;
+            let pending = match field(channel, "messages")? {
+                Value::Array(messages) => messages.len(),
+                _ => bail!("Bad messages of channel {}", sender_text(sender)),
+            };
+            Ok((sender_text(sender), sequence, pending))
+        })
+        .collect()
+}
+
+/// The SCALE encoded state of the given contract.
+pub fn contract_state(reader: impl Read, id: &str) -> Result<Vec<u8>> {
+    struct Extractor<'a> {
+        id: &'a str,
+        state: Option<Result<Vec<u8>>>,
+    }
+
+    impl Visitor for Extractor<'_> {
+        fn visit(&mut self, path: &[Value]) -> Visit {
+            if path.is_empty()
+                || path_matches(path, &[SYSTEM])
+                || path_matches(path, &[SYSTEM, "contracts"])
+            {
+                Visit::Enter
+            } else if path_matches(path, &[SYSTEM, "contracts", "*"])
+                && id_matches(&path[2], self.id)
+            {
+                Visit::Decode
+            } else {
+                Visit::Copy
+            }
+        }
+
+        fn decoded(&mut self, _path: &[Value], contract: &mut Value) -> Result<bool> {
+            self.state = Some(field(contract, "contract").and_then(bytes));
+            Ok(true)
+        }
+    }
+
+    let mut extractor = Extractor { id, state: None };
+    cbor::walk(reader, &mut io::sink(), &mut extractor)?;
+    extractor
+        .state
+        .ok_or_else(|| anyhow!("Contract {} not found", id))?
+}
+
+/// The sections to remove from a checkpoint.
+#[derive(Default, Debug)]
+pub struct Strip {
+    /// The contracts to remove
+    pub contracts: Vec<String>,
+    /// The clusters to remove, together with all their contracts
+    pub clusters: Vec<String>,
+    /// Whether to drop the pending egress messages
+    pub egress: bool,
+    /// The senders mapped to their next sequence accepted by the chain, see `strip_egress`
+    pub ingress: BTreeMap<String, u64>,
+}
+
+/// What is removed by [`strip`].
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct Stripped {
+    pub contracts: Vec<String>,
+    pub clusters: Vec<String>,
+    pub egress_messages: usize,
+}
+
+/// Writes the checkpoint in `reader` to `writer` without the given sections.
+pub fn strip(reader: impl Read, writer: &mut dyn Write, strip: &Strip) -> Result<Stripped> {
+    struct Stripper<'a> {
+        strip: &'a Strip,
+        stripped: Stripped,
+    }
+
+    impl Stripper<'_> {
+        fn strips(ids: &[String], key: &Value) -> bool {
+            ids.iter().any(|id| id_matches(key, id))
+        }
+    }
+
+    impl Visitor for Stripper<'_> {
+        fn visit(&mut self, path: &[Value]) -> Visit {
+            const RULES: &[(&[&str], Visit)] = &[
+                (&[], Visit::Enter),
+                (&[PHACTORY], Visit::Enter),
+                (&[PHACTORY, "runtime_state"], Visit::Enter),
+                (&[SYSTEM], Visit::Enter),
+                (&[SYSTEM, "contracts"], Visit::Enter),
+                (&[SYSTEM, "contract_clusters"], Visit::Enter),
+                (&[SYSTEM, "contract_clusters", "clusters"], Visit::Enter),
+                (&[SYSTEM, "contract_clusters", "clusters", "*", "contracts"], Visit::Decode),
+            ];
+            let key = match path.last() {
+                Some(key) => key,
+                None => return Visit::Enter,
+            };
+            if path_matches(path, &[PHACTORY, "runtime_state", "send_mq"]) {
+                if self.strip.egress {
+                    Visit::Decode
+                } else {
+                    Visit::Copy
+                }
+            } else if path_matches(path, &[SYSTEM, "contracts", "*"]) {
+                if Self::strips(&self.strip.contracts, key) {
+                    self.stripped.contracts.push(id_text(key));
+                    Visit::Remove
+                } else if !self.strip.clusters.is_empty() {
+                    // To check the cluster of the contract
+                    Visit::Decode
+                } else {
+                    Visit::Copy
+                }
+            } else if path_matches(path, &[SYSTEM, "contract_clusters", "clusters", "*"]) {
+                if Self::strips(&self.strip.clusters, key) {
+                    self.stripped.clusters.push(id_text(key));
+                    Visit::Remove
+                } else if !self.strip.contracts.is_empty() {
+                    Visit::Enter
+                } else {
+                    Visit::Copy
+                }
+            } else {
+                visit_by_rules(path, RULES)
+            }
+        }
+
+        fn decoded(&mut self, path: &[Value], value: &mut Value) -> Result<bool> {
+            if path_matches(path, &[PHACTORY, "runtime_state", "send_mq"]) {
+                self.stripped.egress_messages = strip_egress(value, &self.strip.ingress)?;
+            } else if path_matches(path, &[SYSTEM, "contracts", "*"]) {
+                let stripped = field(value, "cluster_id")
+                    .map_or(false, |cluster| Self::strips(&self.strip.clusters, cluster));
+                if stripped {
+                    self.stripped.contracts.push(id_text(&path[2]));
+                    return Ok(false);
+                }
+            } else if let Value::Array(members) = value {
+                members.retain(|member| !Self::strips(&self.strip.contracts, member));
+            }
+            Ok(true)
+        }
+    }
+
+    let mut stripper = Stripper {
+        strip,
+        stripped: Default::default(),
+    };
+    cbor::walk(reader, writer, &mut stripper)?;
+    Ok(stripper.stripped)
+}
+
+/// Drops the pending egress messages in the send queue, returns the number of them.
+///
+/// `ingress` maps the senders to their next sequence accepted by the chain, as in the
+/// `OffchainIngress` storage. The sequence of these channels is rewound to it, so that the chain
+/// accepts the messages sent later in place of the dropped ones. The other channels keep their
+/// sequence, since some of the dropped messages may already be on chain.
+fn strip_egress(send_mq: &mut Value, ingress: &BTreeMap<String, u64>) -> Result<usize> {
+    let mut dropped = 0;
+    for (sender, channel) in map_mut(send_mq)?.iter_mut() {
+        let messages = match field_mut(channel, "messages")? {
+            Value::Array(messages) => std::mem::take(messages),
+            _ => bail!("Bad messages in the send queue"),
+        };
+        dropped += messages.len();
+        if let Some(&accepted) = ingress.get(&sender_text(sender)) {
+            let sequence = field_mut(channel, "sequence")?;
+            // Never move forward, the replayed blocks would then produce skipped messages
+            if accepted < integer(sequence)? {
+                *sequence = Value::Integer(accepted.into());
+            }
+        }
+    }
+    Ok(dropped)
+}
+
+pub fn field<'a>(value: &'a Value, name: &str) -> Result<&'a Value> {
+    map(value)?
+        .get(&Value::Text(name.into()))
+        .ok_or_else(|| anyhow!("Missing field: {}", name))
+}
+
+fn field_mut<'a>(value: &'a mut Value, name: &str) -> Result<&'a mut Value> {
+    map_mut(value)?
+        .get_mut(&Value::Text(name.into()))
+        .ok_or_else(|| anyhow!("Missing field: {}", name))
+}
+
+fn map(value: &Value) -> Result<&BTreeMap<Value, Value>> {
+    match value {
+        Value::Map(map) => Ok(map),
+        Value::Null => bail!("The section is empty"),
+        _ => bail!("Expect a map"),
+    }
+}
+
+fn map_mut(value: &mut Value) -> Result<&mut BTreeMap<Value, Value>> {
+    match value {
+        Value::Map(map) => Ok(map),
+        Value::Null => bail!("The section is empty"),
+        _ => bail!("Expect a map"),
+    }
+}
+
+pub fn integer(value: &Value) -> Result<u64> {
+    match value {
+        Value::Integer(n) => Ok(*n as u64),
+        _ => bail!("Expect an integer"),
+    }
+}
+
+/// Decodes the bytes serialized either as a CBOR byte string or as a sequence of integers.
+pub fn bytes(value: &Value) -> Result<Vec<u8>> {
+    match value {
+        Value::Bytes(bytes) => Ok(bytes.clone()),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| Ok(integer(item)? as u8))
+            .collect(),
+        _ => bail!("Expect bytes"),
+    }
+}
+
+/// Formats an id serialized as a hex string or as bytes.
+pub fn id_text(value: &Value) -> String {
+    match value {
+        Value::Text(text) => text.to_lowercase(),
+        _ => match bytes(value) {
+            Ok(bytes) => format!("0x{}", hex::encode(bytes)),
+            Err(_) => format!("{:?}", value),
+        },
+    }
+}
+
+fn id_matches(value: &Value, id: &str) -> bool {
+    let id = id.to_lowercase();
+    let id = id.trim_start_matches("0x");
+    id_text(value).trim_start_matches("0x") == id
+}
+
+fn sender_text(value: &Value) -> String {
+    match serde_cbor::value::from_value::<MessageOrigin>(value.clone()) {
+        Ok(origin) => origin.to_string(),
+        Err(_) => format!("{:?}", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.into())
+    }
+
+    fn object(fields: Vec<(&str, Value)>) -> Value {
+        Value::Map(fields.into_iter().map(|(k, v)| (text(k), v)).collect())
+    }
+
+    fn sender() -> Value {
+        serde_cbor::value::to_value(MessageOrigin::Pallet(b"test".to_vec())).unwrap()
+    }
+
+    fn channel(sequence: u64, pending: &[u64]) -> Value {
+        let messages = pending
+            .iter()
+            .map(|&seq| object(vec![("sequence", Value::Integer(seq.into()))]))
+            .collect();
+        object(vec![
+            ("sequence", Value::Integer(sequence.into())),
+            ("messages", Value::Array(messages)),
+        ])
+    }
+
+    fn contract(cluster: u8) -> Value {
+        object(vec![
+            ("cluster_id", Value::Bytes(vec![cluster])),
+            ("contract", Value::Bytes(vec![0; 16])),
+            ("sidevm_info", Value::Null),
+        ])
+    }
+
+    /// Contracts 0x01 and 0x02 in cluster 0x0a, contract 0x03 in cluster 0x0b.
+    fn checkpoint(channel: Value) -> Vec<u8> {
+        let contracts = vec![(1, 0x0a), (2, 0x0a), (3, 0x0b)]
+            .into_iter()
+            .map(|(id, cluster)| (Value::Bytes(vec![id]), contract(cluster)))
+            .collect();
+        let cluster = |members: Vec<u8>| {
+            let members = members.into_iter().map(|id| Value::Bytes(vec![id])).collect();
+            object(vec![
+                ("storage", Value::Bytes(vec![0; 1024])),
+                ("contracts", Value::Array(members)),
+            ])
+        };
+        let clusters = vec![
+            (Value::Bytes(vec![0x0a]), cluster(vec![1, 2])),
+            (Value::Bytes(vec![0x0b]), cluster(vec![3])),
+        ];
+        let send_mq = Value::Map(vec![(sender(), channel)].into_iter().collect());
+        let sync_state = object(vec![
+            ("header_number_next", Value::Integer(11)),
+            ("block_number_next", Value::Integer(9)),
+        ]);
+        let synchronizer = object(vec![("Solo", object(vec![("sync_state", sync_state)]))]);
+        let runtime_state = object(vec![
+            ("send_mq", send_mq),
+            ("storage_synchronizer", synchronizer),
+            ("chain_storage", Value::Bytes(vec![0; 1024])),
+            ("genesis_block_hash", Value::Bytes(vec![0xff; 32])),
+        ]);
+        let checkpoint = Value::Array(vec![
+            Value::Integer(1),
+            Value::Null,
+            object(vec![("runtime_state", runtime_state)]),
+            object(vec![
+                ("contracts", Value::Map(contracts)),
+                (
+                    "contract_clusters",
+                    object(vec![("clusters", Value::Map(clusters.into_iter().collect()))]),
+                ),
+            ]),
+        ]);
+        serde_cbor::to_vec(&checkpoint).unwrap()
+    }
+
+    fn strip_checkpoint(checkpoint: &[u8], options: Strip) -> (Stripped, Summary) {
+        let mut output = Vec::new();
+        let stripped = strip(checkpoint, &mut output, &options).unwrap();
+        (stripped, summarize(&output[..]).unwrap())
+    }
+
+    fn contract_ids(summary: &Summary) -> Vec<&str> {
+        summary.contracts.iter().map(|(id, _)| id.as_str()).collect()
+    }
+
+    #[test]
+    fn checkpoint_is_summarized() {
+        let checkpoint = checkpoint(channel(6, &[3, 4, 5]));
+        let summary = summarize(&checkpoint[..]).unwrap();
+        assert_eq!(summary.version, 1);
+        assert_eq!(summary.genesis, vec![0xff; 32]);
+        assert_eq!(summary.sync_counters, (11, 9));
+        assert_eq!(summary.egress, vec![(sender_text(&sender()), 6, 3)]);
+        assert_eq!(contract_ids(&summary), vec!["0x01", "0x02", "0x03"]);
+        assert_eq!(
+            summary.contracts[2].1.as_ref().unwrap(),
+            &("0x0b".to_string(), 16, false)
+        );
+        let clusters: Vec<_> = summary
+            .clusters
+            .iter()
+            .map(|(id, contracts)| (id.as_str(), *contracts.as_ref().unwrap()))
+            .collect();
+        assert_eq!(clusters, vec![("0x0a", 2), ("0x0b", 1)]);
+        assert_eq!(contract_state(&checkpoint[..], "0x02").unwrap(), vec![0; 16]);
+        assert!(contract_state(&checkpoint[..], "0x04").is_err());
+    }
+
+    #[test]
+    fn strip_egress_rewinds_to_the_chain() {
+        let options = Strip {
+            egress: true,
+            ingress: vec![(sender_text(&sender()), 4)].into_iter().collect(),
+            ..Default::default()
+        };
+        let (stripped, summary) = strip_checkpoint(&checkpoint(channel(6, &[3, 4, 5])), options);
+        assert_eq!(stripped.egress_messages, 3);
+        assert_eq!(summary.egress, vec![(sender_text(&sender()), 4, 0)]);
+    }
+
+    #[test]
+    fn strip_egress_keeps_the_sequence_without_the_chain_state() {
+        let options = Strip {
+            egress: true,
+            ..Default::default()
+        };
+        let (stripped, summary) = strip_checkpoint(&checkpoint(channel(6, &[3, 4, 5])), options);
+        assert_eq!(stripped.egress_messages, 3);
+        assert_eq!(summary.egress, vec![(sender_text(&sender()), 6, 0)]);
+
+        // The chain is ahead of the checkpoint
+        let options = Strip {
+            egress: true,
+            ingress: vec![(sender_text(&sender()), 8)].into_iter().collect(),
+            ..Default::default()
+        };
+        let (stripped, summary) = strip_checkpoint(&checkpoint(channel(6, &[5])), options);
+        assert_eq!(stripped.egress_messages, 1);
+        assert_eq!(summary.egress, vec![(sender_text(&sender()), 6, 0)]);
+    }
+
+    #[test]
+    fn strip_contract_removes_it_from_the_cluster() {
+        let checkpoint = checkpoint(channel(0, &[]));
+        let options = Strip {
+            contracts: vec!["0x01".into(), "0x04".into()],
+            ..Default::default()
+        };
+        let (stripped, summary) = strip_checkpoint(&checkpoint, options);
+        assert_eq!(stripped.contracts, vec!["0x01"]);
+        assert_eq!(contract_ids(&summary), vec!["0x02", "0x03"]);
+        assert_eq!(*summary.clusters[0].1.as_ref().unwrap(), 1);
+        // The untouched sections are copied as is
+        assert_eq!(summary.sync_counters, (11, 9));
+    }
+
+    #[test]
+    fn strip_cluster_removes_its_contracts() {
+        let checkpoint = checkpoint(channel(0, &[]));
+        let options = Strip {
+            clusters: vec!["0x0A".into()],
+            ..Default::default()
+        };
+        let (stripped, summary) = strip_checkpoint(&checkpoint, options);
+        assert_eq!(stripped.clusters, vec!["0x0a"]);
+        assert_eq!(stripped.contracts, vec!["0x01", "0x02"]);
+        assert_eq!(contract_ids(&summary), vec!["0x03"]);
+        assert_eq!(summary.clusters.len(), 1);
+    }
+}
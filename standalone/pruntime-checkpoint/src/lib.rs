@@ -0,0 +1,175 @@
+//! Inspects and repairs pRuntime checkpoints.
+//!
+//! Used by the `pruntime-checkpoint` CLI on the checkpoints readable outside of the enclave, and
+//! by pRuntime itself on the checkpoints protected by SGX.
+
+mod cbor;
+mod checkpoint;
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Subcommand;
+
+pub use checkpoint::Protection;
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Action {
+    /// Print the block height, the egress queues, the contracts and the clusters.
+    Info,
+    /// Write the SCALE encoded state of a contract to a file.
+    ExtractContract {
+        /// The contract id
+        id: String,
+        #[clap(short, long)]
+        output: std::path::PathBuf,
+    },
+    /// Remove the given sections and write the result to a new checkpoint.
+    Strip {
+        #[clap(long, help = "Remove a contract.")]
+        contract: Vec<String>,
+        #[clap(long, help = "Remove a cluster and all its contracts.")]
+        cluster: Vec<String>,
+        #[clap(long, help = "Drop the pending egress messages.")]
+        egress: bool,
+        #[clap(
+            long,
+            parse(try_from_str = parse_ingress),
+            help = "With --egress, rewind the channel of a sender to the next sequence accepted by \
+                    the chain, given as <SENDER>=<SEQ>. The sender is as printed by `info`, the \
+                    sequence is the `OffchainIngress` of the sender on chain. The channels not \
+                    given keep their sequence."
+        )]
+        ingress_sequence: Vec<(String, u64)>,
+        #[clap(
+            short,
+            long,
+            help = "The checkpoint to write, which can be the input checkpoint itself."
+        )]
+        output: std::path::PathBuf,
+    },
+}
+
+fn parse_ingress(arg: &str) -> Result<(String, u64)> {
+    let (sender, sequence) = arg
+        .rsplit_once('=')
+        .ok_or_else(|| anyhow!("Expect <SENDER>=<SEQ>"))?;
+    let sequence = sequence.parse().context("Bad sequence")?;
+    Ok((sender.to_string(), sequence))
+}
+
+fn print_info(summary: &checkpoint::Summary) {
+    println!("version: {}", summary.version);
+    println!("genesis: 0x{}", hex::encode(&summary.genesis));
+    let (next_header, next_block) = summary.sync_counters;
+    println!("headers synced to: {}", next_header.saturating_sub(1));
+    println!("blocks synced to: {}", next_block.saturating_sub(1));
+
+    println!("egress:");
+    for (sender, sequence, pending) in &summary.egress {
+        println!("    {} sequence={} pending={}", sender, sequence, pending);
+    }
+
+    println!("contracts:");
+    for (id, contract) in &summary.contracts {
+        match contract {
+            Ok((cluster, state_size, sidevm)) => println!(
+                "    {} cluster={} state_size={} sidevm={}",
+                id, cluster, state_size, sidevm
+            ),
+            Err(err) => println!("    {} CORRUPTED: {:?}", id, err),
+        }
+    }
+
+    println!("clusters:");
+    for (id, contracts) in &summary.clusters {
+        match contracts {
+            Ok(contracts) => println!("    {} contracts={}", id, contracts),
+            Err(err) => println!("    {} CORRUPTED: {:?}", id, err),
+        }
+    }
+}
+
+/// Runs the action on the checkpoint at `path`.
+pub fn run(path: &Path, protection: Protection, action: Action) -> Result<()> {
+    let reader = checkpoint::open(path, protection)?;
+    match action {
+        Action::Info => print_info(&checkpoint::summarize(reader)?),
+        Action::ExtractContract { id, output } => {
+            let state = checkpoint::contract_state(reader, &id)?;
+            std::fs::write(&output, &state).context("Failed to write the contract state")?;
+            println!("{} bytes written to {}", state.len(), output.display());
+        }
+        Action::Strip {
+            contract,
+            cluster,
+            egress,
+            ingress_sequence,
+            output,
+        } => {
+            let strip = checkpoint::Strip {
+                contracts: contract,
+                clusters: cluster,
+                egress,
+                ingress: ingress_sequence.into_iter().collect(),
+            };
+            // The checkpoint is read while the output is written, so an in place strip goes
+            // through a temporary file, named to not be taken for a checkpoint by pRuntime.
+            let in_place = output.exists()
+                && std::fs::canonicalize(&output)? == std::fs::canonicalize(path)?;
+            let target = if in_place {
+                output.with_file_name(".checkpoint-strip.tmp")
+            } else {
+                output.clone()
+            };
+            let result = checkpoint::create(&target, protection).and_then(|mut writer| {
+                let stripped = checkpoint::strip(reader, &mut writer, &strip)?;
+                writer.flush()?;
+                check_stripped(&strip, &stripped)?;
+                Ok(stripped)
+            });
+            let stripped = match result {
+                Ok(stripped) => stripped,
+                Err(err) => {
+                    let _ = std::fs::remove_file(&target);
+                    return Err(err);
+                }
+            };
+            if in_place {
+                std::fs::rename(&target, &output).context("Failed to replace the checkpoint")?;
+            }
+            for id in &stripped.contracts {
+                println!("Removed contract {}", id);
+            }
+            for id in &stripped.clusters {
+                println!("Removed cluster {}", id);
+            }
+            if strip.egress {
+                println!("Dropped {} egress messages", stripped.egress_messages);
+            }
+            println!("Checkpoint written to {}", output.display());
+        }
+    }
+    Ok(())
+}
+
+fn check_stripped(strip: &checkpoint::Strip, stripped: &checkpoint::Stripped) -> Result<()> {
+    let normalize = |id: &String| id.trim_start_matches("0x").to_lowercase();
+    let found = |requested: &String, removed: &[String]| {
+        removed
+            .iter()
+            .any(|id| normalize(id) == normalize(requested))
+    };
+    for id in &strip.contracts {
+        if !found(id, &stripped.contracts) {
+            bail!("Contract {} not found", id);
+        }
+    }
+    for id in &strip.clusters {
+        if !found(id, &stripped.clusters) {
+            bail!("Cluster {} not found", id);
+        }
+    }
+    Ok(())
+}
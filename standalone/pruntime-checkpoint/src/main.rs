@@ -0,0 +1,68 @@
+//! Inspects and repairs pRuntime checkpoints outside of the enclave.
+//!
+//! Supported are the checkpoint files of a pRuntime without file protection (dev mode), and the
+//! encrypted checkpoint streams given the identity key of the worker. The checkpoint files
+//! protected by SGX can only be opened inside the enclave, with `pruntime checkpoint` running the
+//! same actions except `extract-contract`.
+//!
+//! ```text
+//! pruntime-checkpoint ./data/checkpoint.seal-000001000 info
+//! pruntime-checkpoint --identity-key <hex> state.ckpt extract-contract 0x1234.. -o contract.scale
+//! pruntime-checkpoint ./data/checkpoint.seal-000001000 strip --egress \
+//!     --ingress-sequence 'Worker(1234..)=42' -o fixed.seal
+//! ```
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::{AppSettings, Parser};
+use phactory::Phactory;
+use phala_e2e::DevPlatform;
+use pruntime_checkpoint::{Action, Protection};
+
+#[derive(Parser, Debug)]
+#[clap(about = "Inspect and repair pRuntime checkpoints.", version, author)]
+#[clap(global_setting(AppSettings::DeriveDisplayOrder))]
+struct Args {
+    #[clap(
+        long,
+        help = "The hex encoded identity secret key of the worker, to decrypt a checkpoint stream. \
+                Without a key, the checkpoint is read as a plain file."
+    )]
+    identity_key: Option<String>,
+
+    #[clap(
+        long,
+        help = "The sealing dir of a dev mode pRuntime, to load the identity key from to decrypt \
+                a checkpoint stream."
+    )]
+    sealing_path: Option<String>,
+
+    /// The checkpoint file
+    checkpoint: PathBuf,
+
+    #[clap(subcommand)]
+    action: Action,
+}
+
+fn protection(args: &Args) -> Result<Protection> {
+    if let Some(key) = &args.identity_key {
+        let key = hex::decode(key.trim_start_matches("0x"))
+            .context("Failed to decode the identity key")?;
+        return Ok(Protection::Encrypted(phactory::derive_key_for_checkpoint(&key)));
+    }
+    if let Some(sealing_path) = &args.sealing_path {
+        let key = Phactory::checkpoint_key(&DevPlatform, sealing_path)
+            .context("Failed to load the identity key")?;
+        return Ok(Protection::Encrypted(key));
+    }
+    Ok(Protection::Plain)
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let args = Args::parse();
+    let protection = protection(&args)?;
+    pruntime_checkpoint::run(&args.checkpoint, protection, args.action)
+}
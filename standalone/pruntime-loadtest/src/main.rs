@@ -0,0 +1,253 @@
+//! Fires a mix of encrypted contract queries and info requests at the prpc endpoint of a worker,
+//! and reports the throughput, the error rates and the latency percentiles of each kind.
+//!
+//! ```text
+//! pruntime-loadtest --url http://localhost:8000 --concurrency 32 --duration 60 \
+//!     --info-weight 1 --query-weight 4 --contract 0x1234.. --query 0x0100
+//! ```
+
+mod stats;
+
+use std::convert::TryFrom as _;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{AppSettings, Parser};
+use parity_scale_codec::Encode;
+use phactory_api::{
+    crypto::{CertificateBody, EncryptedData},
+    prpc,
+    pruntime_client::{new_pruntime_client, PRuntimeClient},
+};
+use phala_crypto::{ecdh::EcdhPublicKey, query};
+use phala_types::contract::{ContractId, ContractQueryHead};
+use rand::Rng as _;
+use sp_core::{sr25519, Pair as _};
+
+use stats::Stats;
+
+#[derive(Parser, Debug)]
+#[clap(about = "Load testing of the pRuntime query path.", version, author)]
+#[clap(global_setting(AppSettings::DeriveDisplayOrder))]
+struct Args {
+    #[clap(
+        default_value = "http://localhost:8000",
+        long,
+        help = "pRuntime http endpoint"
+    )]
+    url: String,
+
+    #[clap(default_value = "8", long, help = "The number of concurrent clients.")]
+    concurrency: usize,
+
+    #[clap(default_value = "30", long, help = "The test duration in seconds.")]
+    duration: u64,
+
+    #[clap(default_value = "1", long, help = "The share of get_info requests in the mix.")]
+    info_weight: u32,
+
+    #[clap(default_value = "0", long, help = "The share of contract queries in the mix.")]
+    query_weight: u32,
+
+    #[clap(long, help = "The hex encoded id of the contract to query.")]
+    contract: Option<String>,
+
+    #[clap(
+        default_value = "",
+        long,
+        help = "The hex encoded SCALE query to send to the contract."
+    )]
+    query: String,
+
+    #[clap(
+        long,
+        help = "Sign the queries with a two level certificate chain, to include the signature \
+                verification in the measurement."
+    )]
+    sign: bool,
+}
+
+/// Everything needed to build a contract query.
+struct QueryTarget {
+    contract: ContractId,
+    query: Vec<u8>,
+    worker_pubkey: EcdhPublicKey,
+    signer: Option<Signer>,
+}
+
+/// Signs the queries on behalf of a root key through a certified temporary key.
+struct Signer {
+    root_key: sr25519::Pair,
+    key: sr25519::Pair,
+}
+
+impl Signer {
+    fn generate() -> Self {
+        Self {
+            root_key: sr25519::Pair::generate().0,
+            key: sr25519::Pair::generate().0,
+        }
+    }
+
+    fn sign(&self, data: &[u8]) -> prpc::Signature {
+        let root_cert = prpc::Certificate::new(
+            CertificateBody {
+                pubkey: self.root_key.public().to_vec(),
+                ttl: u32::MAX,
+                config_bits: 0,
+            },
+            None,
+        );
+        let cert_body = CertificateBody {
+            pubkey: self.key.public().to_vec(),
+            ttl: u32::MAX,
+            config_bits: 0,
+        };
+        let cert_signature = prpc::Signature {
+            signed_by: Some(Box::new(root_cert)),
+            signature_type: prpc::SignatureType::Sr25519 as _,
+            signature: self.root_key.sign(&cert_body.encode()).0.to_vec(),
+        };
+        let cert = prpc::Certificate::new(cert_body, Some(Box::new(cert_signature)));
+        prpc::Signature {
+            signed_by: Some(Box::new(cert)),
+            signature_type: prpc::SignatureType::Sr25519 as _,
+            signature: self.key.sign(data).0.to_vec(),
+        }
+    }
+}
+
+impl QueryTarget {
+    async fn query(&self, client: &PRuntimeClient) -> Result<()> {
+        let (pending, payload) =
+            query::client::seal_query(&mut rand::thread_rng(), &self.worker_pubkey, |nonce| {
+                let head = ContractQueryHead {
+                    id: self.contract,
+                    nonce: *nonce,
+                };
+                let mut data = head.encode();
+                data.extend_from_slice(&self.query);
+                data
+            })
+            .map_err(|err| anyhow!("Failed to seal the query: {:?}", err))?;
+        let encrypted_data = EncryptedData::from(payload);
+        let signature = self
+            .signer
+            .as_ref()
+            .map(|signer| signer.sign(&encrypted_data.encode()));
+        let response = client
+            .contract_query(prpc::ContractQueryRequest::new(encrypted_data, signature))
+            .await?;
+        let payload: query::EncryptedPayload = response.decode_encrypted_data()?.into();
+        pending
+            .open_response(&payload)
+            .map_err(|err| anyhow!("Failed to open the response: {:?}", err))?;
+        Ok(())
+    }
+}
+
+fn decode_hex(hex_str: &str) -> Result<Vec<u8>> {
+    hex::decode(hex_str.trim_start_matches("0x")).context("Invalid hex string")
+}
+
+async fn query_target(args: &Args) -> Result<Option<QueryTarget>> {
+    if args.query_weight == 0 {
+        return Ok(None);
+    }
+    let contract = args
+        .contract
+        .as_ref()
+        .ok_or_else(|| anyhow!("--contract is required for contract queries"))?;
+    let contract = decode_hex(contract)?;
+    if contract.len() != 32 {
+        return Err(anyhow!("Bad contract id"));
+    }
+    let contract = ContractId::from_slice(&contract);
+    let info = new_pruntime_client(args.url.clone()).get_info(()).await?;
+    let worker_pubkey = info
+        .ecdh_public_key
+        .ok_or_else(|| anyhow!("Worker not initialized"))?;
+    let worker_pubkey = EcdhPublicKey::try_from(&decode_hex(&worker_pubkey)?[..])
+        .map_err(|_| anyhow!("Bad worker ECDH pubkey"))?;
+    Ok(Some(QueryTarget {
+        contract,
+        query: decode_hex(&args.query)?,
+        worker_pubkey,
+        signer: args.sign.then(Signer::generate),
+    }))
+}
+
+/// Sends requests in a loop until `deadline`, returns the (info, query) stats.
+async fn run_client(
+    url: String,
+    info_weight: u32,
+    query_weight: u32,
+    target: Option<Arc<QueryTarget>>,
+    deadline: Instant,
+) -> (Stats, Stats) {
+    let client = new_pruntime_client(url);
+    let mut info_stats = Stats::default();
+    let mut query_stats = Stats::default();
+    while Instant::now() < deadline {
+        let pick = rand::thread_rng().gen_range(0, info_weight + query_weight);
+        let start = Instant::now();
+        if pick < info_weight {
+            match client.get_info(()).await {
+                Ok(_) => info_stats.record_ok(start.elapsed()),
+                Err(err) => {
+                    log::debug!("get_info failed: {:?}", err);
+                    info_stats.record_error();
+                }
+            }
+        } else if let Some(target) = &target {
+            match target.query(&client).await {
+                Ok(()) => query_stats.record_ok(start.elapsed()),
+                Err(err) => {
+                    log::debug!("contract_query failed: {:?}", err);
+                    query_stats.record_error();
+                }
+            }
+        }
+    }
+    (info_stats, query_stats)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    let args = Args::parse();
+    if args.info_weight + args.query_weight == 0 {
+        return Err(anyhow!("The request mix is empty"));
+    }
+    let target = query_target(&args).await?.map(Arc::new);
+
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(args.duration);
+    let clients: Vec<_> = (0..args.concurrency)
+        .map(|_| {
+            tokio::spawn(run_client(
+                args.url.clone(),
+                args.info_weight,
+                args.query_weight,
+                target.clone(),
+                deadline,
+            ))
+        })
+        .collect();
+
+    let mut info_stats = Stats::default();
+    let mut query_stats = Stats::default();
+    for client in clients {
+        let (info, query) = client.await?;
+        info_stats.merge(info);
+        query_stats.merge(query);
+    }
+    let elapsed = start.elapsed();
+
+    println!("{} clients, {:.1}s", args.concurrency, elapsed.as_secs_f64());
+    info_stats.report("get_info", elapsed);
+    query_stats.report("contract_query", elapsed);
+    Ok(())
+}
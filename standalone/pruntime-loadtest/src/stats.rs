@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+/// The outcome of the requests of one kind.
+#[derive(Default)]
+pub struct Stats {
+    latencies: Vec<Duration>,
+    errors: usize,
+}
+
+impl Stats {
+    pub fn record_ok(&mut self, latency: Duration) {
+        self.latencies.push(latency);
+    }
+
+    pub fn record_error(&mut self) {
+        self.errors += 1;
+    }
+
+    pub fn merge(&mut self, other: Stats) {
+        self.latencies.extend(other.latencies);
+        self.errors += other.errors;
+    }
+
+    pub fn total(&self) -> usize {
+        self.latencies.len() + self.errors
+    }
+
+    /// Prints the throughput, the error rate and the latency percentiles.
+    pub fn report(&mut self, name: &str, elapsed: Duration) {
+        let total = self.total();
+        if total == 0 {
+            println!("{}: no requests", name);
+            return;
+        }
+        self.latencies.sort();
+        println!(
+            "{}: {} requests, {:.1} req/s, {} errors ({:.2}%)",
+            name,
+            total,
+            total as f64 / elapsed.as_secs_f64(),
+            self.errors,
+            self.errors as f64 * 100.0 / total as f64,
+        );
+        if self.latencies.is_empty() {
+            return;
+        }
+        let mean = self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32;
+        println!(
+            "    latency mean={:?} p50={:?} p90={:?} p99={:?} max={:?}",
+            mean,
+            self.percentile(50.0),
+            self.percentile(90.0),
+            self.percentile(99.0),
+            self.latencies[self.latencies.len() - 1],
+        );
+    }
+
+    /// The latency below which `p` percent of the successful requests fall. The latencies must be
+    /// sorted.
+    fn percentile(&self, p: f64) -> Duration {
+        let n = self.latencies.len();
+        let rank = ((p / 100.0) * n as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, n) - 1]
+    }
+}
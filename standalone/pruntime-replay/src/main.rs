@@ -0,0 +1,254 @@
+//! Replays recorded chain blocks through pRuntime outside of SGX.
+//!
+//! The blocks are fed to a non-SGX phactory one by one, and the state roots and contract digests
+//! are printed after each block. Diffing the output of two runs, or of a run against the state
+//! of a worker, narrows a divergence down to the first block where the digests differ.
+//!
+//! ```text
+//! pruntime-replay fetch --node-uri ws://localhost:9944 --from 1000 --to 2000
+//! pruntime-replay run --identity-key <hex seed> > digests.txt
+//! ```
+
+mod record;
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{AppSettings, Parser, Subcommand};
+use phactory::{Phactory, StateDigest};
+use phactory_api::blocks::BlockHeaderWithChanges;
+use phactory_api::ecall_args::{git_revision, InitArgs};
+use phala_e2e::DevPlatform;
+use phaxt::rpc::ExtraRpcExt as _;
+use pherry::types::{phaxt, subxt, BlockNumber, NumberOrHex, ParachainApi, StorageKey};
+use sp_core::{crypto::Pair, sr25519};
+use tempdir::TempDir;
+
+use record::{Genesis, LogReader, LogWriter};
+
+/// The identity key seed used when no key is given
+const DEV_KEY_SEED: [u8; 32] = [1; 32];
+
+#[derive(Parser, Debug)]
+#[clap(about = "Deterministic state replay of pRuntime.", version, author)]
+#[clap(global_setting(AppSettings::DeriveDisplayOrder))]
+struct Args {
+    #[clap(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand, Debug)]
+enum Action {
+    /// Record the genesis state and the blocks from a node.
+    Fetch {
+        #[clap(
+            default_value = "ws://localhost:9944",
+            long,
+            help = "Substrate rpc websocket endpoint."
+        )]
+        node_uri: String,
+
+        #[clap(long, help = "The block number to take the genesis state at.")]
+        from: BlockNumber,
+
+        #[clap(long, help = "The last block number to record.")]
+        to: BlockNumber,
+
+        #[clap(default_value = "genesis.scale", long, help = "The genesis file to write.")]
+        genesis: PathBuf,
+
+        #[clap(default_value = "blocks.scale", long, help = "The block log to write.")]
+        log: PathBuf,
+    },
+    /// Replay the recorded blocks and print the state digest of each block.
+    Run {
+        #[clap(default_value = "genesis.scale", long, help = "The genesis file.")]
+        genesis: PathBuf,
+
+        #[clap(default_value = "blocks.scale", long, help = "The block log.")]
+        log: PathBuf,
+
+        #[clap(
+            long,
+            help = "Restore from the latest checkpoint in the given sealing dir of a non-SGX \
+                    pRuntime instead of starting from the genesis."
+        )]
+        checkpoint: Option<String>,
+
+        #[clap(
+            long,
+            help = "The hex encoded seed of the worker identity key. Ignored when restoring \
+                    from a checkpoint."
+        )]
+        identity_key: Option<String>,
+
+        #[clap(long, help = "Stop after replaying the given block.")]
+        until: Option<BlockNumber>,
+    },
+}
+
+async fn fetch_genesis(api: &ParachainApi, block_number: BlockNumber) -> Result<Genesis> {
+    let pos = subxt::BlockNumber::from(NumberOrHex::Number(block_number.into()));
+    let block_hash = api
+        .client
+        .rpc()
+        .block_hash(Some(pos))
+        .await?
+        .ok_or_else(|| anyhow!("Block {} not found", block_number))?;
+    let state = api
+        .client
+        .extra_rpc()
+        .storage_pairs(StorageKey(vec![]), Some(block_hash))
+        .await?
+        .into_iter()
+        .map(|(k, v)| (k.0, v.0))
+        .collect();
+    Ok(Genesis {
+        block_number,
+        block_hash,
+        state,
+    })
+}
+
+async fn fetch(
+    node_uri: &str,
+    from: BlockNumber,
+    to: BlockNumber,
+    genesis_path: &Path,
+    log_path: &Path,
+) -> Result<()> {
+    let api: ParachainApi = pherry::subxt_connect(node_uri)
+        .await
+        .context("Failed to connect to substrate")?
+        .into();
+    log::info!("Connected to substrate at: {}", node_uri);
+
+    log::info!("Fetching the genesis state at {}", from);
+    fetch_genesis(&api, from).await?.save(genesis_path)?;
+
+    let mut writer = LogWriter::create(log_path)?;
+    for block_number in from + 1..=to {
+        log::info!("Fetching block {}", block_number);
+        let block = pherry::get_block_with_storage_changes(&api, Some(block_number)).await?;
+        writer.write(&BlockHeaderWithChanges {
+            block_header: block.block.block.header,
+            storage_changes: block.storage_changes,
+        })?;
+    }
+    writer.flush()
+}
+
+fn print_digest(block_number: BlockNumber, digest: &StateDigest) {
+    println!("#{} storage_root={:?}", block_number, digest.storage_root);
+    for (id, hash) in digest.contracts.iter() {
+        println!("    contract {:?} {:?}", id, hash);
+    }
+    for (id, root) in digest.clusters.iter() {
+        println!("    cluster {:?} {:?}", id, root);
+    }
+}
+
+fn init_args(sealing_path: String) -> InitArgs {
+    InitArgs {
+        sealing_path,
+        version: env!("CARGO_PKG_VERSION").into(),
+        git_revision: git_revision(),
+        ..Default::default()
+    }
+}
+
+fn run(
+    genesis_path: &Path,
+    log_path: &Path,
+    checkpoint: Option<String>,
+    identity_key: Option<String>,
+    until: Option<BlockNumber>,
+) -> Result<()> {
+    let _sealing_dir;
+    let (mut phactory, mut skip_to_root) = match checkpoint {
+        Some(sealing_path) => {
+            let mut phactory =
+                Phactory::restore_from_checkpoint(&DevPlatform, &sealing_path, false)?
+                    .ok_or_else(|| anyhow!("No checkpoint found in {}", sealing_path))?;
+            phactory.set_args(init_args(sealing_path));
+            phactory.on_restored()?;
+            let root = phactory
+                .state_digest()
+                .ok_or_else(|| anyhow!("The checkpoint has no runtime state"))?
+                .storage_root;
+            log::info!("Restored from checkpoint, storage root: {:?}", root);
+            (phactory, Some(root))
+        }
+        None => {
+            let genesis = Genesis::load(genesis_path)?;
+            let seed = match identity_key {
+                Some(key) => hex::decode(key.trim_start_matches("0x"))
+                    .context("Failed to decode the identity key")?,
+                None => {
+                    log::warn!("No identity key given, using the dev key");
+                    DEV_KEY_SEED.to_vec()
+                }
+            };
+            let identity_key = sr25519::Pair::from_seed_slice(&seed)
+                .map_err(|err| anyhow!("Invalid identity key: {:?}", err))?;
+
+            let sealing_dir = TempDir::new("pruntime-replay")?;
+            let mut phactory = Phactory::new(DevPlatform);
+            phactory.init(init_args(sealing_dir.path().to_string_lossy().into()));
+            _sealing_dir = sealing_dir;
+            phactory.init_replay(genesis.block_hash, genesis.state, identity_key)?;
+            if let Some(digest) = phactory.state_digest() {
+                print_digest(genesis.block_number, &digest);
+            }
+            (phactory, None)
+        }
+    };
+
+    for block in LogReader::open(log_path)? {
+        let block = block?;
+        let block_number = block.block_header.number;
+        if until.map_or(false, |until| block_number > until) {
+            break;
+        }
+        // Skip the blocks up to the one the checkpoint was taken at
+        if let Some(root) = skip_to_root {
+            if block.block_header.state_root == root {
+                skip_to_root = None;
+            }
+            continue;
+        }
+        phactory
+            .replay_block(&block)
+            .with_context(|| format!("Failed to replay block {}", block_number))?;
+        let digest = phactory
+            .state_digest()
+            .ok_or_else(|| anyhow!("The runtime state is gone"))?;
+        print_digest(block_number, &digest);
+    }
+    if skip_to_root.is_some() {
+        anyhow::bail!("The checkpoint state is not found in the block log");
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    match Args::parse().action {
+        Action::Fetch {
+            node_uri,
+            from,
+            to,
+            genesis,
+            log,
+        } => fetch(&node_uri, from, to, &genesis, &log).await,
+        Action::Run {
+            genesis,
+            log,
+            checkpoint,
+            identity_key,
+            until,
+        } => run(&genesis, &log, checkpoint, identity_key, until),
+    }
+}
@@ -0,0 +1,86 @@
+//! The on-disk format of the recorded chain data.
+//!
+//! The genesis file is a SCALE encoded [`Genesis`], and the block log is a sequence of SCALE
+//! encoded [`BlockHeaderWithChanges`] appended one after another.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use parity_scale_codec::{Decode, Encode, IoReader};
+use phactory_api::blocks::{BlockHeaderWithChanges, StorageState};
+use sp_core::H256;
+
+/// The chain state to start the replay with.
+#[derive(Encode, Decode)]
+pub struct Genesis {
+    pub block_number: u32,
+    pub block_hash: H256,
+    pub state: StorageState,
+}
+
+impl Genesis {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let data = std::fs::read(path).context("Failed to read the genesis file")?;
+        Decode::decode(&mut &data[..]).context("Failed to decode the genesis file")
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.encode()).context("Failed to write the genesis file")
+    }
+}
+
+/// Iterates over the blocks in a block log.
+pub struct LogReader {
+    reader: BufReader<File>,
+}
+
+impl LogReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path).context("Failed to open the block log")?;
+        Ok(Self {
+            reader: BufReader::new(file),
+        })
+    }
+}
+
+impl Iterator for LogReader {
+    type Item = Result<BlockHeaderWithChanges>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reader.fill_buf() {
+            Ok(buf) if buf.is_empty() => return None,
+            Ok(_) => (),
+            Err(err) => return Some(Err(err.into())),
+        }
+        Some(
+            BlockHeaderWithChanges::decode(&mut IoReader(&mut self.reader))
+                .context("Failed to decode a block from the log"),
+        )
+    }
+}
+
+/// Writes blocks to a new block log.
+pub struct LogWriter {
+    writer: BufWriter<File>,
+}
+
+impl LogWriter {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::create(path).context("Failed to create the block log")?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn write(&mut self, block: &BlockHeaderWithChanges) -> Result<()> {
+        self.writer.write_all(&block.encode())?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
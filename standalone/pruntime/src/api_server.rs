@@ -1,13 +1,22 @@
+use std::io;
 use std::str;
 
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use rocket::data::Data;
 use rocket::data::ToByteUnit;
 use rocket::http::Method;
 use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
 use rocket::response::status::Custom;
+use rocket::response::stream::ByteStream;
 use rocket::serde::json::{json, Json, Value as JsonValue};
-use rocket::Phase;
-use rocket::{get, post, routes};
+use rocket::tokio::io::AsyncReadExt as _;
+use rocket::tokio::sync::mpsc;
+use rocket::tokio::task;
+use rocket::Build;
+use rocket::{get, post, routes, State};
 use rocket_cors::{AllowedHeaders, AllowedMethods, AllowedOrigins, CorsOptions};
 
 use colored::Colorize as _;
@@ -68,6 +77,16 @@ macro_rules! proxy_get {
     };
 }
 
+macro_rules! admin_proxy_get {
+    ($rpc: literal, $name: ident, $num: expr) => {
+        #[get($rpc)]
+        fn $name(_admin: Admin) -> JsonValue {
+            let input_string = r#"{ "input": {} }"#.to_string();
+            do_ecall_handle!($num, input_string.as_bytes())
+        }
+    };
+}
+
 macro_rules! proxy {
     (post, $rpc: literal, $name: ident, $num: expr) => {
         proxy_post!($rpc, $name, $num)
@@ -77,26 +96,45 @@ macro_rules! proxy {
     };
 }
 
+/// Reads the whole body, fails if it exceeds the limit.
 async fn read_data(data: Data<'_>) -> Option<Vec<u8>> {
-    let stream = data.open(100.mebibytes());
+    let limit = 100.mebibytes();
+    let stream = data.open(limit);
     let data = stream.into_bytes().await.ok()?;
+    if !data.is_complete() {
+        error!("The request body exceeds {}", limit);
+        return None;
+    }
     Some(data.into_inner())
 }
 
+async fn handle_bin(action: u8, data: Data<'_>) -> JsonValue {
+    let data = match read_data(data).await {
+        Some(data) => data,
+        None => {
+            return json!({
+                "status": "error",
+                "payload": "Io error: Read input data failed"
+            })
+        }
+    };
+    do_ecall_handle!(action, &data)
+}
+
 macro_rules! proxy_bin {
     ($rpc: literal, $name: ident, $num: expr) => {
         #[post($rpc, data = "<data>")]
         async fn $name(data: Data<'_>) -> JsonValue {
-            let data = match read_data(data).await {
-                Some(data) => data,
-                None => {
-                    return json!({
-                        "status": "error",
-                        "payload": "Io error: Read input data failed"
-                    })
-                }
-            };
-            do_ecall_handle!($num, &data)
+            handle_bin($num, data).await
+        }
+    };
+}
+
+macro_rules! admin_proxy_bin {
+    ($rpc: literal, $name: ident, $num: expr) => {
+        #[post($rpc, data = "<data>")]
+        async fn $name(_admin: Admin, data: Data<'_>) -> JsonValue {
+            handle_bin($num, data).await
         }
     };
 }
@@ -108,6 +146,13 @@ macro_rules! proxy_routes {
     }};
 }
 
+macro_rules! admin_proxy_get_routes {
+    ($(($rpc: literal, $name: ident, $num: expr),)+) => {{
+        $(admin_proxy_get!($rpc, $name, $num);)+
+        routes![$($name),+]
+    }};
+}
+
 macro_rules! proxy_bin_routes {
     ($(($rpc: literal, $name: ident, $num: expr),)+) => {{
         $(proxy_bin!($rpc, $name, $num);)+
@@ -115,13 +160,238 @@ macro_rules! proxy_bin_routes {
     }};
 }
 
+macro_rules! admin_proxy_bin_routes {
+    ($(($rpc: literal, $name: ident, $num: expr),)+) => {{
+        $(admin_proxy_bin!($rpc, $name, $num);)+
+        routes![$($name),+]
+    }};
+}
+
+/// Reads the chunks sent by the request handler, for decoding a request body as it arrives.
+struct ChunkReader {
+    chunks: mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl io::Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// The snapshot holds the whole parachain state, so it is allowed a larger body than the other
+/// bin APIs, and is decoded while it is received instead of being buffered.
+#[post("/import_state_snapshot", data = "<data>")]
+async fn import_state_snapshot(_admin: Admin, data: Data<'_>) -> JsonValue {
+    let error = |err: anyhow::Error| {
+        error!("Failed to import the state snapshot: {:?}", err);
+        json!({
+            "status": "error",
+            "payload": format!("{:?}!", err)
+        })
+    };
+    // Reject the request before receiving the snapshot, which may take GiBs.
+    if let Err(err) = runtime::ecall_check_state_snapshot_importable() {
+        return error(err);
+    }
+    let (tx, rx) = mpsc::channel(16);
+    let reader = ChunkReader {
+        chunks: rx,
+        chunk: vec![],
+        pos: 0,
+    };
+    let import = task::spawn_blocking(move || runtime::ecall_import_state_snapshot(reader));
+    let mut stream = data.open(4.gibibytes());
+    loop {
+        let mut chunk = vec![0u8; 64 * 1024];
+        match stream.read(&mut chunk).await {
+            Ok(0) => break,
+            Ok(len) => {
+                chunk.truncate(len);
+                // The decoder stops receiving once it fails
+                if tx.send(chunk).await.is_err() {
+                    break;
+                }
+            }
+            Err(err) => return error(err.into()),
+        }
+    }
+    drop(tx);
+    match import.await {
+        Ok(Ok(output)) => {
+            let output_value: serde_json::value::Value = serde_json::from_slice(&output).unwrap();
+            json!(output_value)
+        }
+        Ok(Err(err)) => error(err),
+        Err(err) => error(err.into()),
+    }
+}
+
 #[post("/kick")]
 fn kick() {
     std::process::exit(0);
 }
 
+/// The trace context sent along with a request.
+struct TraceHeaders(HashMap<String, String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TraceHeaders {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        let headers = phala_tracing::TRACE_HEADERS
+            .iter()
+            .filter_map(|name| {
+                let value = request.headers().get_one(name)?;
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect();
+        Outcome::Success(TraceHeaders(headers))
+    }
+}
+
+/// The token read from `--admin-token-file`.
+struct AdminToken(String);
+
+/// Authorizes the admin APIs, which must be called with the admin token in the
+/// `Authorization: Bearer <token>` header.
+struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        let expected = match request.rocket().state::<AdminToken>() {
+            Some(token) => token.0.as_bytes(),
+            None => return Outcome::Failure((Status::Forbidden, ())),
+        };
+        let token = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default()
+            .as_bytes();
+        // Compare in constant time to not leak the token by the timing.
+        let matched = token.len() == expected.len()
+            && token
+                .iter()
+                .zip(expected)
+                .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+                == 0;
+        if matched {
+            Outcome::Success(Admin)
+        } else {
+            Outcome::Failure((Status::Unauthorized, ()))
+        }
+    }
+}
+
+#[get("/egress_status")]
+fn egress_status(_admin: Admin) -> JsonValue {
+    do_ecall_handle!(actions::ACTION_GET_EGRESS_STATUS, b"")
+}
+
+/// The limits for a worker to be ready, configured by the `--ready-*` args.
+struct ReadinessThresholds {
+    max_sync_lag: Duration,
+    max_pending_messages: usize,
+}
+
+/// Responds as long as the server is up, without waiting for the enclave, which may be busy
+/// dispatching blocks for a while.
+#[get("/live")]
+fn health_live() -> Status {
+    Status::Ok
+}
+
+/// The result of each readiness check.
+#[derive(Serialize, Default, Debug, PartialEq, Eq)]
+struct ReadinessChecks {
+    /// The enclave is not held by another request, such as dispatching blocks
+    responsive: bool,
+    registered: bool,
+    master_key: bool,
+    synced: bool,
+    mq_backlog: bool,
+}
+
+impl ReadinessChecks {
+    /// Checks the health info, or reports a busy enclave if there is none.
+    fn new(
+        info: Option<&phactory::HealthInfo>,
+        now_ms: u64,
+        thresholds: &ReadinessThresholds,
+    ) -> Self {
+        let info = match info {
+            Some(info) => info,
+            None => return Self::default(),
+        };
+        let sync_lag = Duration::from_millis(now_ms.saturating_sub(info.last_block_time_ms));
+        Self {
+            responsive: true,
+            registered: info.initialized && info.registered,
+            // Only the gatekeepers need the master key to work.
+            master_key: !info.gatekeeper || info.master_key_available,
+            synced: info.last_block_time_ms > 0 && sync_lag <= thresholds.max_sync_lag,
+            mq_backlog: info.pending_messages <= thresholds.max_pending_messages,
+        }
+    }
+
+    fn ready(&self) -> bool {
+        self.responsive && self.registered && self.master_key && self.synced && self.mq_backlog
+    }
+}
+
+/// Responds 200 if the worker is ready to serve, or 503 otherwise. The body tells the result of
+/// each check.
+///
+/// A busy enclave is reported as not ready rather than waited for, so that the probe does not
+/// time out while a batch of blocks is dispatched.
+#[get("/ready")]
+fn health_ready(thresholds: &State<ReadinessThresholds>) -> Custom<JsonValue> {
+    let info = runtime::ecall_try_health_info();
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let checks = ReadinessChecks::new(info.as_ref(), now_ms, thresholds);
+    let ready = checks.ready();
+    let status = if ready {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    let sync_lag_secs = info
+        .as_ref()
+        .map(|info| now_ms.saturating_sub(info.last_block_time_ms) / 1000);
+    Custom(
+        status,
+        json!({
+            "ready": ready,
+            "checks": checks,
+            "sync_lag_secs": sync_lag_secs,
+            "info": info,
+        }),
+    )
+}
+
 #[post("/<method>", data = "<data>")]
-async fn prpc_proxy(method: String, data: Data<'_>) -> Custom<Vec<u8>> {
+async fn prpc_proxy(method: String, trace: TraceHeaders, data: Data<'_>) -> Custom<Vec<u8>> {
     let path_bytes = method.as_bytes();
     let data = match read_data(data).await {
         Some(data) => data,
@@ -130,7 +400,9 @@ async fn prpc_proxy(method: String, data: Data<'_>) -> Custom<Vec<u8>> {
         }
     };
 
-    let (status_code, output) = runtime::ecall_prpc_request(path_bytes, &data);
+    let span = tracing::info_span!("prpc", method = %method);
+    phala_tracing::set_remote_parent(&span, &trace.0);
+    let (status_code, output) = span.in_scope(|| runtime::ecall_prpc_request(path_bytes, &data));
     if let Some(status) = Status::from_code(status_code) {
         Custom(status, output)
     } else {
@@ -139,6 +411,60 @@ async fn prpc_proxy(method: String, data: Data<'_>) -> Custom<Vec<u8>> {
     }
 }
 
+/// Streams the responses of a subscribed contract query, each one an encoded
+/// `ContractQueryResponse` prefixed with its length as a big endian u32.
+///
+/// The query must be signed, the subscriptions are limited per signer.
+#[post("/contract_query", data = "<data>")]
+async fn subscribe_contract_query(
+    data: Data<'_>,
+) -> Result<ByteStream![Vec<u8>], Custom<Vec<u8>>> {
+    let data = match read_data(data).await {
+        Some(data) => data,
+        None => {
+            return Err(Custom(Status::BadRequest, b"Read body failed".to_vec()));
+        }
+    };
+    let mut responses = runtime::ecall_subscribe_contract_query(&data).map_err(|(code, output)| {
+        Custom(Status::from_code(code).unwrap_or(Status::ServiceUnavailable), output)
+    })?;
+    Ok(ByteStream! {
+        while let Some(response) = responses.recv().await {
+            let mut frame = (response.len() as u32).to_be_bytes().to_vec();
+            frame.extend_from_slice(&response);
+            yield frame;
+        }
+    })
+}
+
+#[post("/challenge")]
+fn handover_challenge(_admin: Admin) -> Custom<Vec<u8>> {
+    match runtime::ecall_handover_create_challenge() {
+        Ok(data) => Custom(Status::Ok, data),
+        Err(err) => {
+            error!("Failed to create handover challenge: {:?}", err);
+            Custom(Status::BadRequest, format!("{:?}", err).into_bytes())
+        }
+    }
+}
+
+#[post("/start", data = "<data>")]
+async fn handover_start(_admin: Admin, data: Data<'_>) -> Custom<Vec<u8>> {
+    let data = match read_data(data).await {
+        Some(data) => data,
+        None => {
+            return Custom(Status::BadRequest, b"Read body failed".to_vec());
+        }
+    };
+    match runtime::ecall_handover_start(&data) {
+        Ok(data) => Custom(Status::Ok, data),
+        Err(err) => {
+            error!("Failed to start handover: {:?}", err);
+            Custom(Status::BadRequest, format!("{:?}", err).into_bytes())
+        }
+    }
+}
+
 fn cors_options() -> CorsOptions {
     let allowed_origins = AllowedOrigins::all();
     let allowed_methods: AllowedMethods = vec![Method::Get, Method::Post]
@@ -163,7 +489,7 @@ fn print_rpc_methods(prefix: &str, methods: &[&str]) {
     }
 }
 
-pub(super) fn rocket(args: &super::Args) -> rocket::Rocket<impl Phase> {
+pub(super) fn rocket(args: &super::Args) -> rocket::Rocket<Build> {
     let mut server = rocket::build()
         .mount(
             "/",
@@ -200,7 +526,51 @@ pub(super) fn rocket(args: &super::Args) -> rocket::Rocket<impl Phase> {
         server = server.mount("/", routes![kick]);
     }
 
+    if let Some(token) = &args.admin_token {
+        info!("ENABLE the admin APIs");
+
+        server = server
+            .manage(AdminToken(token.clone()))
+            .mount("/admin", routes![egress_status])
+            .mount("/admin/bin_api", routes![import_state_snapshot])
+            .mount(
+                "/admin",
+                admin_proxy_get_routes![
+                    ("/heap_profile", heap_profile, actions::ACTION_GET_HEAP_PROFILE),
+                    ("/log_filter", log_filter, actions::ACTION_GET_LOG_FILTER),
+                    (
+                        "/sidevm_instances",
+                        sidevm_instances,
+                        actions::ACTION_GET_SIDEVM_INSTANCES
+                    ),
+                ],
+            )
+            .mount(
+                "/admin/bin_api",
+                admin_proxy_bin_routes![
+                    (
+                        "/sign_endpoints",
+                        sign_endpoints,
+                        actions::BIN_ACTION_SIGN_ENDPOINTS
+                    ),
+                    (
+                        "/set_log_filter",
+                        set_log_filter,
+                        actions::BIN_ACTION_SET_LOG_FILTER
+                    ),
+                ],
+            );
+    }
+
+    server = server
+        .manage(ReadinessThresholds {
+            max_sync_lag: Duration::from_secs(args.ready_max_sync_lag),
+            max_pending_messages: args.ready_max_pending_messages,
+        })
+        .mount("/health", routes![health_live, health_ready]);
     server = server.mount("/prpc", routes![prpc_proxy]);
+    server = server.mount("/subscribe", routes![subscribe_contract_query]);
+    server = server.mount("/handover", routes![handover_challenge, handover_start]);
     print_rpc_methods("/prpc", prpc::phactory_api_server::supported_methods());
 
     if args.allow_cors {
@@ -219,3 +589,87 @@ pub(super) fn rocket(args: &super::Args) -> rocket::Rocket<impl Phase> {
 
     server
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW_MS: u64 = 1_000_000;
+
+    fn thresholds() -> ReadinessThresholds {
+        ReadinessThresholds {
+            max_sync_lag: Duration::from_secs(60),
+            max_pending_messages: 10,
+        }
+    }
+
+    fn healthy() -> phactory::HealthInfo {
+        phactory::HealthInfo {
+            initialized: true,
+            registered: true,
+            gatekeeper: false,
+            master_key_available: false,
+            last_block_time_ms: NOW_MS - 6_000,
+            pending_messages: 0,
+        }
+    }
+
+    fn ready(info: Option<&phactory::HealthInfo>) -> bool {
+        ReadinessChecks::new(info, NOW_MS, &thresholds()).ready()
+    }
+
+    #[test]
+    fn healthy_worker_is_ready() {
+        assert!(ready(Some(&healthy())));
+    }
+
+    #[test]
+    fn busy_enclave_is_not_ready() {
+        assert_eq!(
+            ReadinessChecks::new(None, NOW_MS, &thresholds()),
+            ReadinessChecks::default()
+        );
+        assert!(!ready(None));
+    }
+
+    #[test]
+    fn unregistered_worker_is_not_ready() {
+        let info = phactory::HealthInfo {
+            registered: false,
+            ..healthy()
+        };
+        assert!(!ready(Some(&info)));
+    }
+
+    #[test]
+    fn gatekeeper_needs_the_master_key() {
+        let mut info = phactory::HealthInfo {
+            gatekeeper: true,
+            ..healthy()
+        };
+        assert!(!ready(Some(&info)));
+        info.master_key_available = true;
+        assert!(ready(Some(&info)));
+    }
+
+    #[test]
+    fn lagging_worker_is_not_ready() {
+        let mut info = healthy();
+        info.last_block_time_ms = NOW_MS - 60_000;
+        assert!(ready(Some(&info)));
+        info.last_block_time_ms = NOW_MS - 60_001;
+        assert!(!ready(Some(&info)));
+        // No block dispatched yet
+        info.last_block_time_ms = 0;
+        assert!(!ready(Some(&info)));
+    }
+
+    #[test]
+    fn mq_backlog_is_limited() {
+        let mut info = healthy();
+        info.pending_messages = 10;
+        assert!(ready(Some(&info)));
+        info.pending_messages = 11;
+        assert!(!ready(Some(&info)));
+    }
+}
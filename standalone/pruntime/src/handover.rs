@@ -0,0 +1,50 @@
+//! The client side of the worker key handover. See `phactory::handover` for the protocol.
+
+use anyhow::{anyhow, Context as _, Result};
+use http_req::request::{Method, Request};
+use log::info;
+use std::{convert::TryFrom, time::Duration};
+
+use crate::runtime;
+
+fn post(url: &str, admin_token: &str, body: &[u8]) -> Result<Vec<u8>> {
+    let mut res_body_buffer = Vec::new();
+    let timeout = Some(Duration::from_secs(60));
+    let uri = TryFrom::try_from(url).context("Invalid handover URI")?;
+    let res = Request::new(&uri)
+        .header("Connection", "Close")
+        .header("Authorization", &format!("Bearer {}", admin_token))
+        .header("Content-Length", &body.len())
+        .method(Method::POST)
+        .body(body)
+        .timeout(timeout)
+        .connect_timeout(timeout)
+        .read_timeout(timeout)
+        .send(&mut res_body_buffer)
+        .context("Http request to the old pRuntime failed")?;
+    let status_code = u16::from(res.status_code());
+    if status_code != 200 {
+        return Err(anyhow!(
+            "Bad http status: {}, {}",
+            status_code,
+            String::from_utf8_lossy(&res_body_buffer)
+        ));
+    }
+    Ok(res_body_buffer)
+}
+
+/// Receives the worker key from the pRuntime listening at `from`, authorized by the admin token
+/// the old pRuntime is started with.
+///
+/// The enclave must have been initialized but not yet have any runtime data.
+pub fn request_handover_from(from: &str, admin_token: &str) -> Result<()> {
+    let from = from.trim_end_matches('/');
+    info!("Requesting handover from {}", from);
+    let challenge = post(&format!("{}/handover/challenge", from), admin_token, &[])?;
+    let response = runtime::ecall_handover_accept_challenge(&challenge)
+        .context("Failed to accept the handover challenge")?;
+    let key = post(&format!("{}/handover/start", from), admin_token, &response)?;
+    let block = runtime::ecall_handover_receive(&key).context("Failed to receive worker key")?;
+    info!("Handover done, synced to block {}", block);
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! The app logger, whose filter can be replaced at runtime.
+//!
+//! The logs of phactory are grouped by component, each with its own target:
+//!
+//! - `phactory`: the worker keys and the persistent data
+//! - `checkpoint`: taking, loading and removing the checkpoints
+//! - `chain`: the light client and the chain storage
+//! - `mq`: the message queues
+//! - `system`: the system contract, dispatching the events and the contract commands
+//! - `mining`: the benchmark, the heartbeats and the mining state
+//! - `gk`: the gatekeeper and the master key
+//! - `contract`: the native contracts and the ink contracts
+//! - `sidevm`: the sidevm instances
+//! - `subscription`: the contract query subscriptions
+//! - `side_task`: the side tasks, including the geolocation probe
+//! - `handover`: the worker key handover
+//! - `prpc`: the prpc and the bin_api services
+//!
+//! So `info,sidevm=debug,mq=trace` turns on the sidevm and mq logs without touching the others.
+//! The logs of the other crates keep their module paths as the targets, except the pink runtime
+//! logging under `pink`.
+
+use std::sync::RwLock;
+
+use env_logger::filter::{Builder as FilterBuilder, Filter};
+use log::{LevelFilter, Log, Metadata, Record};
+
+struct DynamicLogger {
+    /// Formats and writes every record passing the filter
+    inner: env_logger::Logger,
+    filter: RwLock<(String, Filter)>,
+}
+
+impl Log for DynamicLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.read().unwrap().1.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.filter.read().unwrap().1.matches(record) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref LOGGER: DynamicLogger = DynamicLogger {
+        inner: env_logger::Builder::new().filter_level(LevelFilter::Trace).build(),
+        filter: RwLock::new((String::new(), FilterBuilder::new().build())),
+    };
+}
+
+fn build_filter(spec: &str) -> Filter {
+    FilterBuilder::new().parse(spec).build()
+}
+
+/// Installs the logger with the filter from `RUST_LOG`, or `default_filter` if it is not set.
+pub(crate) fn init(default_filter: &str) {
+    let spec = std::env::var("RUST_LOG").unwrap_or_else(|_| default_filter.into());
+    set_filter(&spec);
+    log::set_logger(&*LOGGER).expect("Logger already initialized");
+}
+
+/// Replaces the log filter, in the env_logger syntax.
+pub(crate) fn set_filter(spec: &str) {
+    let filter = build_filter(spec);
+    log::set_max_level(filter.filter());
+    *LOGGER.filter.write().unwrap() = (spec.into(), filter);
+}
+
+pub(crate) fn filter() -> String {
+    LOGGER.filter.read().unwrap().0.clone()
+}
@@ -1,14 +1,22 @@
 #![feature(decl_macro)]
 
 mod api_server;
+mod handover;
+mod logger;
 mod pal_gramine;
 mod ra;
 mod runtime;
+mod uds_server;
 
-use std::{env, thread};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    thread,
+};
 
-use clap::{AppSettings, Parser};
+use clap::{AppSettings, Parser, Subcommand};
 use log::{error, info};
+use tokio::signal::unix::{signal, SignalKind};
 
 use phactory_api::ecall_args::{git_revision, InitArgs};
 
@@ -35,7 +43,20 @@ struct Args {
     #[clap(long)]
     enable_kick_api: bool,
 
-    /// Log filter passed to env_logger
+    /// Enable the diagnostic APIs under /admin, authorized by the token in this file in the
+    /// `Authorization: Bearer <token>` header.
+    ///
+    /// The token is read from a file to keep it out of the command line, which any local user can
+    /// see.
+    #[clap(long)]
+    admin_token_file: Option<String>,
+
+    /// The token read from `--admin-token-file`
+    #[clap(skip)]
+    admin_token: Option<String>,
+
+    /// Initial log filter in the env_logger syntax, can be changed via
+    /// /admin/bin_api/set_log_filter
     #[clap(long, default_value = "INFO")]
     log_filter: String,
 
@@ -47,6 +68,23 @@ struct Args {
     #[clap(long)]
     port: Option<String>,
 
+    /// Also serve the prpc requests on the Unix domain socket at the given path, e.g. for a
+    /// co-located pherry.
+    #[clap(long)]
+    prpc_uds: Option<PathBuf>,
+
+    /// The max time in seconds between now and the latest dispatched block for /health/ready to
+    /// report the worker as ready.
+    #[clap(long)]
+    #[clap(default_value_t = 120)]
+    ready_max_sync_lag: u64,
+
+    /// The max number of egress messages not yet accepted on chain for /health/ready to report
+    /// the worker as ready.
+    #[clap(long)]
+    #[clap(default_value_t = 1000)]
+    ready_max_pending_messages: usize,
+
     /// Disable checkpoint
     #[clap(long)]
     disable_checkpoint: bool,
@@ -65,9 +103,52 @@ struct Args {
     #[clap(default_value_t = 5)]
     max_checkpoint_files: u32,
 
+    /// Interval in seconds of the heap usage snapshots, 0 to disable. Only effective when built
+    /// with the `heap-profiling` feature.
+    #[clap(long)]
+    #[clap(default_value_t = 60)]
+    heap_snapshot_interval: u64,
+
+    /// The SS58 network prefix to display the accounts in the logs, which should be the
+    /// `ss58Format` property of the chain spec.
+    #[clap(long)]
+    #[clap(default_value_t = 30)]
+    ss58_prefix: u16,
+
+    /// Export the spans of the prpc requests to the OTLP collector, e.g. http://localhost:4317
+    #[clap(long)]
+    otlp_endpoint: Option<String>,
+
     /// Measuring the time it takes to process each RPC call.
     #[clap(long)]
     measure_rpc_time: bool,
+
+    /// Receive the worker key from the old pRuntime listening at the given URL, then exit.
+    ///
+    /// The old pRuntime must be started with the same admin token in `--admin-token-file`, which
+    /// authorizes the handover.
+    ///
+    /// e.g. --request-handover-from http://localhost:8000
+    #[clap(long)]
+    request_handover_from: Option<String>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Inspect or repair a checkpoint in the sealing dir, then exit.
+    ///
+    /// The checkpoints protected by SGX can only be read inside the enclave. The checkpoints of
+    /// a dev mode pRuntime can also be repaired with the `pruntime-checkpoint` tool.
+    Checkpoint {
+        /// The file name of the checkpoint in the sealing dir
+        file: String,
+
+        #[clap(subcommand)]
+        action: pruntime_checkpoint::Action,
+    },
 }
 
 #[rocket::main]
@@ -90,7 +171,7 @@ async fn main() {
     }
     .into();
 
-    let args = Args::parse();
+    let mut args = Args::parse();
 
     if let Some(address) = &args.address {
         env::set_var("ROCKET_ADDRESS", address);
@@ -100,8 +181,36 @@ async fn main() {
         env::set_var("ROCKET_PORT", port);
     }
 
-    let env = env_logger::Env::default().default_filter_or(&args.log_filter);
-    env_logger::Builder::from_env(env).init();
+    logger::init(&args.log_filter);
+
+    if let Some(path) = &args.admin_token_file {
+        let token = match std::fs::read_to_string(path) {
+            Ok(token) => token.trim().to_string(),
+            Err(err) => {
+                error!("Failed to read the admin token: {:?}", err);
+                std::process::exit(1);
+            }
+        };
+        if token.is_empty() {
+            error!("The admin token in {} is empty", path);
+            std::process::exit(1);
+        }
+        args.admin_token = Some(token);
+    }
+
+    if let Some(Command::Checkpoint { file, action }) = &args.command {
+        if let Err(err) = run_checkpoint_command(&sealing_path, file, action.clone()) {
+            error!("{:?}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(endpoint) = &args.otlp_endpoint {
+        if let Err(err) = phala_tracing::init("pruntime", endpoint) {
+            error!("Failed to init the trace exporter: {:?}", err);
+        }
+    }
 
     let init_args = {
         let args = args.clone();
@@ -116,19 +225,36 @@ async fn main() {
             checkpoint_interval: args.checkpoint_interval,
             remove_corrupted_checkpoint: args.remove_corrupted_checkpoint,
             max_checkpoint_files: args.max_checkpoint_files,
+            heap_snapshot_interval: args.heap_snapshot_interval,
+            ss58_prefix: Some(args.ss58_prefix),
         }
     };
     info!("init_args: {:#?}", init_args);
     if let Err(err) = runtime::ecall_init(init_args) {
         panic!("Initialize Failed: {:?}", err);
     }
+    runtime::ecall_start_subscription_poller();
+
+    if let Some(from) = &args.request_handover_from {
+        let admin_token = match &args.admin_token {
+            Some(token) => token,
+            None => {
+                error!("Handover requires the --admin-token-file of the old pRuntime");
+                std::process::exit(1);
+            }
+        };
+        if let Err(err) = handover::request_handover_from(from, admin_token) {
+            error!("Handover failed: {:?}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
 
     let bench_cores: u32 = args.cores.unwrap_or_else(|| num_cpus::get() as _);
     info!("Bench cores: {}", bench_cores);
 
-    let mut v = vec![];
     for i in 0..bench_cores {
-        let child = thread::Builder::new()
+        thread::Builder::new()
             .name(format!("bench-{}", i))
             .spawn(move || {
                 set_thread_idle_policy();
@@ -138,20 +264,93 @@ async fn main() {
                 }
             })
             .expect("Failed to launch benchmark thread");
-        v.push(child);
     }
 
-    api_server::rocket(&args)
-        .launch()
+    let server = api_server::rocket(&args)
+        .ignite()
         .await
-        .expect("Failed to launch API server");
+        .expect("Failed to ignite API server");
+    let shutdown = server.shutdown();
+
+    // Rocket shuts down gracefully on SIGINT by itself, and on SIGTERM by this.
+    let sigterm_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+                info!("Received SIGTERM, shutting down...");
+                sigterm_shutdown.notify();
+            }
+            Err(err) => error!("Failed to listen to SIGTERM: {:?}", err),
+        }
+    });
 
-    for child in v {
-        let _ = child.join();
+    let uds_server = args.prpc_uds.clone().map(|path| {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            if let Err(err) = uds_server::serve(&path, shutdown).await {
+                error!("Failed to serve prpc on unix socket: {:?}", err);
+            }
+        })
+    });
+
+    // Returns after the in-flight requests are finished.
+    server.launch().await.expect("Failed to launch API server");
+    if let Some(uds_server) = uds_server {
+        let _ = uds_server.await;
+    }
+
+    info!("Stopping sidevm instances and taking the final checkpoint...");
+    if let Err(err) = runtime::ecall_shutdown() {
+        error!("Failed to shut down gracefully: {:?}", err);
     }
     info!("pRuntime quited");
 }
 
+/// Runs a checkpoint action on the files in the sealing dir.
+///
+/// The files are given by name only, so that nothing outside of the sealing dir is read or
+/// written. The contract states are never written out, since they are the secrets the SGX
+/// protection is there for.
+fn run_checkpoint_command(
+    sealing_path: &str,
+    file: &str,
+    action: pruntime_checkpoint::Action,
+) -> anyhow::Result<()> {
+    use pruntime_checkpoint::Action;
+
+    let in_sealing_dir = |name: &Path| -> anyhow::Result<PathBuf> {
+        match name.file_name() {
+            Some(file_name) if name.components().count() == 1 => {
+                Ok(Path::new(sealing_path).join(file_name))
+            }
+            _ => anyhow::bail!("Expect a file name in the sealing dir, got {}", name.display()),
+        }
+    };
+    let path = in_sealing_dir(Path::new(file))?;
+    let action = match action {
+        Action::ExtractContract { .. } => {
+            anyhow::bail!("The contract states can not be extracted from pRuntime")
+        }
+        Action::Strip {
+            contract,
+            cluster,
+            egress,
+            ingress_sequence,
+            output,
+        } => Action::Strip {
+            contract,
+            cluster,
+            egress,
+            ingress_sequence,
+            output: in_sealing_dir(&output)?,
+        },
+        Action::Info => Action::Info,
+    };
+    // The protected files are decrypted by Gramine transparently.
+    pruntime_checkpoint::run(&path, pruntime_checkpoint::Protection::Plain, action)
+}
+
 fn set_thread_idle_policy() {
     let param = libc::sched_param {
         sched_priority: 0,
@@ -1,12 +1,17 @@
 use log::info;
 use std::alloc::System;
 
-use phactory_pal::{Machine, MemoryStats, MemoryUsage, ProtectedFileSystem, Sealing, RA};
+use phactory_pal::{
+    Logging, Machine, MemoryStats, MemoryUsage, ProtectedFileSystem, Sealing, RA,
+};
+#[cfg(not(feature = "heap-profiling"))]
 use phala_allocator::StatSizeAllocator;
+#[cfg(feature = "heap-profiling")]
+use {phactory_pal::TaggedMemoryUsage, phala_allocator::tags::TaggedAllocator};
 use std::fs::File;
 use std::io::ErrorKind;
 
-use crate::ra;
+use crate::{logger, ra};
 
 #[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
 pub(crate) struct GraminePlatform;
@@ -109,9 +114,14 @@ impl Machine for GraminePlatform {
     }
 }
 
+#[cfg(not(feature = "heap-profiling"))]
 #[global_allocator]
 static ALLOCATOR: StatSizeAllocator<System> = StatSizeAllocator::new(System);
 
+#[cfg(feature = "heap-profiling")]
+#[global_allocator]
+static ALLOCATOR: TaggedAllocator<System> = TaggedAllocator::new(System);
+
 impl MemoryStats for GraminePlatform {
     fn memory_usage(&self) -> MemoryUsage {
         let stats = ALLOCATOR.stats();
@@ -121,4 +131,30 @@ impl MemoryStats for GraminePlatform {
             rust_peak_used: stats.peak_used,
         }
     }
+
+    #[cfg(feature = "heap-profiling")]
+    fn memory_usage_by_tag(&self) -> Vec<TaggedMemoryUsage> {
+        ALLOCATOR
+            .tag_stats()
+            .map(|stats| TaggedMemoryUsage {
+                tag: stats.tag,
+                used: stats.current_used,
+                peak_used: stats.peak_used,
+                allocations: stats.allocations,
+            })
+            .collect()
+    }
+}
+
+impl Logging for GraminePlatform {
+    type Error = std::convert::Infallible;
+
+    fn set_log_filter(&self, filter: &str) -> Result<(), Self::Error> {
+        logger::set_filter(filter);
+        Ok(())
+    }
+
+    fn log_filter(&self) -> String {
+        logger::filter()
+    }
 }
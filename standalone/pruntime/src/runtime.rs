@@ -3,8 +3,10 @@ use crate::pal_gramine::GraminePlatform;
 use anyhow::Result;
 use core::sync::atomic::{AtomicU32, Ordering};
 use log::info;
+use parity_scale_codec::{Decode, Encode};
 use phactory::{benchmark, Phactory};
-use std::sync::Mutex;
+use phactory_api::blocks::ImportStateSnapshotReq;
+use std::sync::{Mutex, TryLockError};
 
 lazy_static::lazy_static! {
     static ref APPLICATION: Mutex<Phactory<GraminePlatform>> = Mutex::new(Phactory::new(GraminePlatform));
@@ -50,6 +52,27 @@ pub fn ecall_init(args: phactory_api::ecall_args::InitArgs) -> Result<()> {
     Ok(())
 }
 
+pub fn ecall_check_state_snapshot_importable() -> Result<()> {
+    APPLICATION.lock().unwrap().check_state_snapshot_importable()
+}
+
+/// Decodes the snapshot from `input` before taking the lock, since it is streamed from the request
+/// body.
+pub fn ecall_import_state_snapshot(input: impl std::io::Read) -> Result<Vec<u8>> {
+    let mut input = parity_scale_codec::IoReader(input);
+    let request = ImportStateSnapshotReq::decode(&mut input)?;
+    if std::io::Read::read(&mut input.0, &mut [0u8])? != 0 {
+        anyhow::bail!("Unexpected trailing bytes after the state snapshot");
+    }
+    let mut factory = APPLICATION.lock().unwrap();
+    Ok(factory.handle_import_state_snapshot(request))
+}
+
+/// Starts rerunning the subscribed queries, after `ecall_init`.
+pub fn ecall_start_subscription_poller() {
+    phactory::start_subscription_poller(&APPLICATION);
+}
+
 pub fn ecall_bench_run(index: u32) {
     if !benchmark::paused() {
         info!("[{}] Benchmark thread started", index);
@@ -62,3 +85,45 @@ pub fn ecall_prpc_request(path: &[u8], data: &[u8]) -> (u16, Vec<u8>) {
     info!("pRPC status code: {}, data len: {}", code, data.len());
     (code, data)
 }
+
+pub fn ecall_subscribe_contract_query(
+    data: &[u8],
+) -> Result<phactory::SubscriptionReceiver, (u16, Vec<u8>)> {
+    phactory::subscribe_contract_query(data, &APPLICATION)
+}
+
+/// Returns None if the application is held by another call.
+pub fn ecall_try_health_info() -> Option<phactory::HealthInfo> {
+    match APPLICATION.try_lock() {
+        Ok(factory) => Some(factory.health_info()),
+        Err(TryLockError::WouldBlock) => None,
+        Err(TryLockError::Poisoned(err)) => panic!("{}", err),
+    }
+}
+
+pub fn ecall_shutdown() -> Result<()> {
+    APPLICATION.lock().unwrap().shutdown()
+}
+
+pub fn ecall_handover_create_challenge() -> Result<Vec<u8>> {
+    let mut factory = APPLICATION.lock().unwrap();
+    Ok(factory.handover_create_challenge()?.encode())
+}
+
+pub fn ecall_handover_start(response: &[u8]) -> Result<Vec<u8>> {
+    let response = Decode::decode(&mut &response[..])?;
+    let mut factory = APPLICATION.lock().unwrap();
+    Ok(factory.handover_start(response)?.encode())
+}
+
+pub fn ecall_handover_accept_challenge(challenge: &[u8]) -> Result<Vec<u8>> {
+    let challenge = Decode::decode(&mut &challenge[..])?;
+    let mut factory = APPLICATION.lock().unwrap();
+    Ok(factory.handover_accept_challenge(challenge)?.encode())
+}
+
+pub fn ecall_handover_receive(key: &[u8]) -> Result<u32> {
+    let key = Decode::decode(&mut &key[..])?;
+    let mut factory = APPLICATION.lock().unwrap();
+    factory.handover_receive(key)
+}
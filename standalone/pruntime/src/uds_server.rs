@@ -0,0 +1,101 @@
+//! Serves the prpc requests on a Unix domain socket, alongside the HTTP server.
+//!
+//! The requests are made the same way as to the `/prpc/<method>` routes of the HTTP server, so the
+//! co-located clients can skip the TCP stack, and the access can be limited with the filesystem
+//! permissions of the socket.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::os::unix::fs::FileTypeExt as _;
+use std::path::Path;
+
+use anyhow::{bail, Context as _, Result};
+use hyper::body::HttpBody as _;
+use hyper::server::accept;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{error, info};
+use tokio::net::UnixListener;
+
+use crate::runtime;
+
+/// The max size of a request body, the same as the HTTP server accepts.
+const MAX_BODY_SIZE: usize = 100 * 1024 * 1024;
+
+async fn read_body(mut body: Body) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.ok()?;
+        if data.len() + chunk.len() > MAX_BODY_SIZE {
+            return None;
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Some(data)
+}
+
+fn reply(status: StatusCode, body: Vec<u8>) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+}
+
+async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let method = match request.uri().path().strip_prefix("/prpc/") {
+        Some(method) if request.method() == Method::POST => method.to_string(),
+        _ => return Ok(reply(StatusCode::NOT_FOUND, vec![])),
+    };
+    let trace_headers: HashMap<_, _> = phala_tracing::TRACE_HEADERS
+        .iter()
+        .filter_map(|name| {
+            let value = request.headers().get(*name)?.to_str().ok()?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect();
+    let data = match read_body(request.into_body()).await {
+        Some(data) => data,
+        None => return Ok(reply(StatusCode::BAD_REQUEST, b"Read body failed".to_vec())),
+    };
+
+    let span = tracing::info_span!("prpc", method = %method);
+    phala_tracing::set_remote_parent(&span, &trace_headers);
+    let (status_code, output) =
+        span.in_scope(|| runtime::ecall_prpc_request(method.as_bytes(), &data));
+    match StatusCode::from_u16(status_code) {
+        Ok(status) => Ok(reply(status, output)),
+        Err(_) => {
+            error!("prpc: Invalid status code: {}!", status_code);
+            Ok(reply(StatusCode::SERVICE_UNAVAILABLE, vec![]))
+        }
+    }
+}
+
+/// Serves the prpc requests on the socket at `path` until `shutdown` completes, and then waits for
+/// the in-flight requests to finish.
+///
+/// The socket file left by a previous run is replaced, but any other kind of file at `path` is not
+/// touched.
+pub(super) async fn serve(path: &Path, shutdown: impl Future<Output = ()>) -> Result<()> {
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            bail!("{} exists and is not a socket", path.display());
+        }
+        std::fs::remove_file(path).context("Failed to remove the stale socket")?;
+    }
+    let listener = UnixListener::bind(path).context("Failed to bind the unix socket")?;
+    info!("Serving prpc on unix socket {}", path.display());
+
+    let incoming = accept::poll_fn(move |cx| {
+        listener
+            .poll_accept(cx)
+            .map(|result| Some(result.map(|(stream, _)| stream)))
+    });
+    let make_service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+    Server::builder(incoming)
+        .http1_only(true)
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown)
+        .await
+        .context("The unix socket server failed")
+}
@@ -107,6 +107,10 @@ pub use phala_pallets::{
 	pallet_mining,
 	pallet_stakepool,
 	pallet_fat,
+	pallet_sidevm,
+	pallet_billing,
+	pallet_assets_bridge,
+	pallet_ballot,
 	puppets,
 };
 
@@ -1218,6 +1222,22 @@ parameter_types! {
 	pub const MaxPoolWorkers: u32 = 200;
 	pub const VerifyPRuntime: bool = false;
 	pub const VerifyRelaychainGenesisBlockHash: bool = false;
+	pub const MaxSidevmCodeSize: u32 = 2 * 1024 * 1024;
+	pub const MaxSidevmMemoryPages: u32 = 1024;
+	pub const MaxSidevmGrpcTargets: u32 = 16;
+	pub const MaxSidevmGrpcTargetLen: u32 = 256;
+	pub const ClusterDeposit: Balance = 100 * DOLLARS;
+	pub const MaxCodeAllowListLength: u32 = 64;
+	pub const WorkerEndpointLifetime: u64 = 7 * 24 * 3600;
+	pub const GatekeeperElectionPeriod: BlockNumber = 7 * DAYS;
+	pub const MaxGatekeepers: u32 = 5;
+	pub const GatekeeperConfidenceLevel: u8 = 3;
+	pub const MqIngressRetentionPeriod: BlockNumber = 7 * DAYS;
+	pub const MiningUnresponsiveGracePeriod: BlockNumber = 1 * DAYS;
+	pub const ContractQueryPrice: Balance = 1 * MILLICENTS;
+	pub const SidevmMessagePrice: Balance = 1 * MILLICENTS;
+	pub const MaxUsageFeePerReport: Balance = 10 * DOLLARS;
+	pub const MaxAssetLocationLength: u32 = 256;
 }
 
 impl pallet_registry::Config for Runtime {
@@ -1228,10 +1248,15 @@ impl pallet_registry::Config for Runtime {
 	type VerifyPRuntime = VerifyPRuntime;
 	type VerifyRelaychainGenesisBlockHash = VerifyRelaychainGenesisBlockHash;
 	type GovernanceOrigin = EnsureRootOrHalfCouncil;
+	type EndpointLifetime = WorkerEndpointLifetime;
+	type GatekeeperElectionPeriod = GatekeeperElectionPeriod;
+	type MaxGatekeepers = MaxGatekeepers;
+	type GatekeeperConfidenceLevel = GatekeeperConfidenceLevel;
 }
 impl pallet_mq::Config for Runtime {
 	type QueueNotifyConfig = msg_routing::MessageRouteConfig;
 	type CallMatcher = MqCallMatcher;
+	type IngressRetentionPeriod = MqIngressRetentionPeriod;
 }
 impl pallet_mining::Config for Runtime {
 	type Event = Event;
@@ -1242,6 +1267,8 @@ impl pallet_mining::Config for Runtime {
 	type OnReward = PhalaStakePool;
 	type OnUnbound = PhalaStakePool;
 	type OnStopped = PhalaStakePool;
+	type OnUnresponsive = PhalaStakePool;
+	type UnresponsiveGracePeriod = MiningUnresponsiveGracePeriod;
 	type OnTreasurySettled = Treasury;
 	type UpdateTokenomicOrigin = EnsureRootOrHalfCouncil;
 }
@@ -1258,6 +1285,40 @@ impl pallet_stakepool::Config for Runtime {
 }
 impl pallet_fat::Config for Runtime {
 	type Event = Event;
+	type Currency = Balances;
+	type ClusterDeposit = ClusterDeposit;
+	type MaxCodeAllowListLength = MaxCodeAllowListLength;
+	type OnClusterDestroyed = PhalaBilling;
+}
+
+impl pallet_sidevm::Config for Runtime {
+	type Event = Event;
+	type MaxCodeSize = MaxSidevmCodeSize;
+	type MaxMemoryPages = MaxSidevmMemoryPages;
+	type MaxGrpcTargets = MaxSidevmGrpcTargets;
+	type MaxGrpcTargetLen = MaxSidevmGrpcTargetLen;
+}
+
+impl pallet_billing::Config for Runtime {
+	type Event = Event;
+	type Currency = Balances;
+	type Clusters = PhalaFatContracts;
+	type QueryPrice = ContractQueryPrice;
+	type SidevmMessagePrice = SidevmMessagePrice;
+	type MaxFeePerReport = MaxUsageFeePerReport;
+}
+
+impl pallet_assets_bridge::Config for Runtime {
+	type Event = Event;
+	type Balance = Balance;
+	// No foreign assets are available in the standalone chain yet.
+	type ForeignAssets = ();
+	type MaxLocationLength = MaxAssetLocationLength;
+}
+
+impl pallet_ballot::Config for Runtime {
+	type Event = Event;
+	type Currency = Balances;
 }
 
 impl puppets::parachain_info::Config for Runtime {}
@@ -1313,6 +1374,10 @@ construct_runtime!(
 		PhalaMining: pallet_mining,
 		PhalaStakePool: pallet_stakepool,
 		PhalaFatContracts: pallet_fat,
+		PhalaSidevm: pallet_sidevm,
+		PhalaBilling: pallet_billing,
+		PhalaAssetsBridge: pallet_assets_bridge,
+		PhalaBallot: pallet_ballot,
 
 		// Put them here to make sure pherry could be compiled with phala's metadata.
 		ParachainInfo: puppets::parachain_info,
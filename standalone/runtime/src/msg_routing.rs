@@ -37,6 +37,9 @@ impl pallet_mq::QueueNotifyConfig for MessageRouteConfig {
             PhalaFatContracts::on_worker_contract_message_received,
             PhalaFatContracts::on_cluster_message_received,
             PhalaFatContracts::on_contract_message_received,
+            PhalaFatContracts::on_contract_command_failure_received,
+            PhalaBilling::on_usage_report_received,
+            PhalaAssetsBridge::on_unwrap_message_received,
             // BridgeTransfer::on_message_received,
         };
         Ok(())